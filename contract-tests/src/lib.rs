@@ -0,0 +1,5 @@
+//! Contract tests between `claudiator-hook` and `claudiator-server`.
+//!
+//! This crate has no library code; everything lives in `tests/`. It depends on
+//! both binaries' library targets so the hook's serialized output can be fed
+//! straight into the server's request types and HTTP handlers.
@@ -0,0 +1,279 @@
+#![allow(clippy::unwrap_used)]
+#![allow(missing_docs)]
+
+//! Wire-contract tests: the hook's real output fed to the server's real input.
+//!
+//! Payloads are built with `claudiator_hook::payload::build_payload` from raw
+//! Claude Code hook JSON and either deserialized with the server's request
+//! types or sent over a loopback socket with the hook's own HTTP client to a
+//! server router running in-process. A field rename, type change or status
+//! code change on either side fails here instead of in production.
+
+use std::sync::Arc;
+
+use claudiator_hook::config::Config;
+use claudiator_hook::error::SendError;
+use claudiator_hook::event::{HookEvent, PRIORITY_HIGH};
+use claudiator_hook::payload::build_payload;
+use claudiator_hook::policy::Policy;
+use claudiator_hook::sender;
+use claudiator_server::db::partition::Partition;
+use claudiator_server::db::queries;
+use claudiator_server::models::request::EventPayload as ServerEventPayload;
+use claudiator_server::router::{self, AppState};
+use claudiator_server::storage::{MemoryStorage, SharedStorage};
+use claudiator_server::time_range::TimeRange;
+
+const MASTER_KEY: &str = "contract-master-key";
+
+/// Raw hook JSON as Claude Code writes it to stdin, one per event type the
+/// server handles specially.
+const HOOK_FIXTURES: &[&str] = &[
+    r#"{"session_id":"contract-1","hook_event_name":"SessionStart","cwd":"/home/u/app","source":"startup","transcript_path":"/tmp/t.jsonl"}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"UserPromptSubmit","cwd":"/home/u/app","prompt":"Fix the login bug"}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"PreToolUse","cwd":"/home/u/app","tool_name":"Bash","tool_input":{"command":"ls"}}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"PermissionRequest","cwd":"/home/u/app","tool_name":"Bash","tool_input":{"command":"rm -rf build"}}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"Notification","cwd":"/home/u/app","notification_type":"idle_prompt","message":"Claude is waiting for your input"}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"Stop","cwd":"/home/u/app","stop_hook_active":false}"#,
+    r#"{"session_id":"contract-1","hook_event_name":"SessionEnd","cwd":"/home/u/app","reason":"exit"}"#,
+];
+
+fn make_config(server_url: &str, api_key: &str) -> Config {
+    Config {
+        server_url: server_url.to_string(),
+        api_key: api_key.to_string(),
+        device_name: "contract-machine".to_string(),
+        unique_device_name: false,
+        device_id: "6f1c2f0e-8d5b-4e0c-9a57-3c1e7f4b2a10".to_string(),
+        platform: "linux".to_string(),
+        log_level: "error".to_string(),
+        max_log_size_bytes: 1_048_576,
+        max_log_backups: 2,
+        max_log_age_days: 0,
+        raw_event_log_path: None,
+        circuit_breaker_threshold: 3,
+        circuit_breaker_cooldown_secs: 30,
+        spool_max_bytes: 10_485_760,
+        outside_cwd: None,
+        policy: Policy::default(),
+        e2e_key: None,
+        capture_env: Vec::new(),
+        profile: None,
+    }
+}
+
+fn parse_hook_event(json: &str) -> HookEvent {
+    serde_json::from_str(json).unwrap()
+}
+
+fn make_state(storage: SharedStorage) -> Arc<AppState> {
+    Arc::new(AppState::new(MASTER_KEY, storage))
+}
+
+/// Serve the real router on an ephemeral loopback port. Returns the base URL
+/// and the storage so tests can inspect what was stored.
+async fn spawn_server() -> (String, SharedStorage) {
+    let storage: SharedStorage = Arc::new(MemoryStorage::new());
+    let app = router::build_router(make_state(Arc::clone(&storage)));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    (format!("http://{addr}"), storage)
+}
+
+/// Run a blocking hook call (ureq) off the async runtime.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f).await.unwrap()
+}
+
+// ---------------------------------------------------------------------------
+// Serde: hook output → server request type
+// ---------------------------------------------------------------------------
+
+#[test]
+fn test_hook_payload_deserializes_as_server_request() {
+    let config = make_config("http://unused", MASTER_KEY);
+
+    for fixture in HOOK_FIXTURES {
+        let payload = build_payload(&config, parse_hook_event(fixture));
+        let body = serde_json::to_string(&payload).unwrap();
+
+        let server: ServerEventPayload = serde_json::from_str(&body)
+            .unwrap_or_else(|e| panic!("server rejected hook body {body}: {e}"));
+
+        let raw: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        assert_eq!(server.device.device_id, config.device_id);
+        assert_eq!(server.device.device_name, config.device_name);
+        assert_eq!(server.device.platform, config.platform);
+        assert_eq!(server.event.session_id, raw["session_id"].as_str().unwrap());
+        assert_eq!(
+            server.event.hook_event_name,
+            raw["hook_event_name"].as_str().unwrap()
+        );
+        assert_eq!(server.event.cwd.as_deref(), raw["cwd"].as_str());
+        assert_eq!(server.event.prompt.as_deref(), raw["prompt"].as_str());
+        assert_eq!(server.event.tool_name.as_deref(), raw["tool_name"].as_str());
+        assert_eq!(
+            server.event.notification_type.as_deref(),
+            raw["notification_type"].as_str()
+        );
+        assert_eq!(server.event.message.as_deref(), raw["message"].as_str());
+        assert!(chrono::DateTime::parse_from_rfc3339(&server.timestamp).is_ok());
+    }
+}
+
+#[test]
+fn test_hook_payload_never_carries_sensitive_fields() {
+    let config = make_config("http://unused", MASTER_KEY);
+
+    for fixture in HOOK_FIXTURES {
+        let body =
+            serde_json::to_string(&build_payload(&config, parse_hook_event(fixture))).unwrap();
+        for field in [
+            "tool_input",
+            "transcript_path",
+            "source",
+            "reason",
+            "stop_hook_active",
+        ] {
+            assert!(!body.contains(field), "{field} leaked in {body}");
+        }
+    }
+}
+
+#[test]
+fn test_server_request_reserializes_to_identical_hook_body() {
+    let config = make_config("http://unused", MASTER_KEY);
+
+    for fixture in HOOK_FIXTURES {
+        let payload = build_payload(&config, parse_hook_event(fixture));
+        let hook_body: serde_json::Value = serde_json::to_value(&payload).unwrap();
+
+        let server: ServerEventPayload = serde_json::from_value(hook_body.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&server).unwrap(), hook_body);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// HTTP: hook client → live server router
+// ---------------------------------------------------------------------------
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hook_send_event_is_accepted_and_stored() {
+    let (url, storage) = spawn_server().await;
+    let config = make_config(&url, MASTER_KEY);
+
+    for fixture in HOOK_FIXTURES {
+        let payload = build_payload(&config, parse_hook_event(fixture));
+        let config = make_config(&url, MASTER_KEY);
+        let result = blocking(move || sender::send_event(&config, &payload)).await;
+        assert!(result.is_ok(), "send failed for {fixture}: {result:?}");
+    }
+
+    let devices = storage.list_devices(&Partition::All, "").unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].device_id, config.device_id);
+    assert_eq!(devices[0].device_name, config.device_name);
+    assert_eq!(devices[0].platform, config.platform);
+
+    let events = storage
+        .list_events(
+            &Partition::All,
+            "contract-1",
+            &queries::EventFilter::default(),
+            100,
+        )
+        .unwrap();
+    assert_eq!(events.len(), HOOK_FIXTURES.len());
+    let mut names: Vec<_> = events.iter().map(|e| e.hook_event_name.as_str()).collect();
+    names.sort_unstable();
+    let mut expected = vec![
+        "Notification",
+        "PermissionRequest",
+        "PreToolUse",
+        "SessionEnd",
+        "SessionStart",
+        "Stop",
+        "UserPromptSubmit",
+    ];
+    expected.sort_unstable();
+    assert_eq!(names, expected);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hook_test_connection_reads_server_ping() {
+    let (url, _storage) = spawn_server().await;
+    let config = make_config(&url, MASTER_KEY);
+
+    let body = blocking(move || sender::test_connection(&config))
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(json["status"], "ok");
+    assert!(json["server_version"].is_string());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_bad_api_key_is_client_error_not_outage() {
+    let (url, _storage) = spawn_server().await;
+    let config = make_config(&url, "wrong-key");
+    let payload = build_payload(&config, parse_hook_event(HOOK_FIXTURES[0]));
+
+    let err = blocking(move || sender::send_event(&config, &payload))
+        .await
+        .unwrap_err();
+    let SendError::ServerError(status, body) = &err else {
+        panic!("expected ServerError, got {err:?}");
+    };
+    assert_eq!(*status, 401);
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["error"], "unauthorized");
+    // A misconfigured key must not trip the hook's circuit breaker.
+    assert!(!err.is_server_unavailable());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_invalid_payload_is_client_error_not_outage() {
+    let (url, _storage) = spawn_server().await;
+    let config = make_config(&url, MASTER_KEY);
+    let mut payload = build_payload(&config, parse_hook_event(HOOK_FIXTURES[0]));
+    payload.timestamp = "not-a-timestamp".to_string();
+
+    let err = blocking(move || sender::send_event(&config, &payload))
+        .await
+        .unwrap_err();
+    let SendError::ServerError(status, body) = &err else {
+        panic!("expected ServerError, got {err:?}");
+    };
+    assert_eq!(*status, 422);
+    let json: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(json["error"], "bad_request");
+    assert!(!err.is_server_unavailable());
+}
+
+#[tokio::test]
+async fn test_policy_tagged_event_notifies() {
+    let (url, storage) = spawn_server().await;
+    let config = make_config(&url, MASTER_KEY);
+    let mut event = parse_hook_event(HOOK_FIXTURES[2]);
+    event.priority = Some(PRIORITY_HIGH.to_string());
+    event.message = Some("Matched a policy rule".to_string());
+    let payload = build_payload(&config, event);
+
+    let result = blocking(move || sender::send_event(&config, &payload)).await;
+    assert!(result.is_ok(), "{result:?}");
+
+    let notifications = storage
+        .list_notifications(&Partition::All, None, None, &TimeRange::default(), 10)
+        .unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].notification_type, "policy");
+    assert_eq!(
+        notifications[0].body,
+        "Flagged Bash call: Matched a policy rule"
+    );
+}
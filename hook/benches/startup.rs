@@ -0,0 +1,131 @@
+//! Cold-start latency of the hook binary.
+//!
+//! Claude Code spawns `claudiator-hook` once per event, so process startup
+//! is paid on every tool call. This harness runs the release binary
+//! repeatedly in a throwaway home directory and reports wall time per run:
+//!
+//! - `version`: startup alone; the config is not read.
+//! - `send (suppressed)`: startup, config parse, stdin parse, and a policy
+//!   rule that drops the event before any network I/O.
+//!
+//! Run with `cargo bench --bench startup`. It exits non-zero when a median
+//! exceeds `CLAUDIATOR_STARTUP_BUDGET_MS` (default 50), so CI catches
+//! regressions. `CLAUDIATOR_BENCH_RUNS` sets the runs per scenario
+//! (default 50).
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_claudiator-hook");
+
+const CONFIG: &str = r#"
+server_url = "http://127.0.0.1:9"
+api_key = "bench-key"
+device_name = "bench"
+device_id = "00000000-0000-0000-0000-000000000000"
+platform = "linux"
+
+[[policy.rules]]
+tool = "Bash"
+action = "suppress"
+"#;
+
+const EVENT: &str = r#"{
+    "session_id": "bench-session",
+    "hook_event_name": "PreToolUse",
+    "cwd": "/tmp",
+    "tool_name": "Bash",
+    "tool_input": {"command": "ls"}
+}"#;
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Wall time of one run of the binary with `args`, fed `stdin`.
+fn run_once(home: &Path, args: &[&str], stdin: &str) -> Duration {
+    let start = Instant::now();
+    let mut child = Command::new(BINARY)
+        .args(args)
+        .env("HOME", home)
+        .env_remove("CLAUDIATOR_PROFILE")
+        .env_remove("CLAUDIATOR_LOG_LEVEL")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| panic!("failed to spawn {BINARY}: {e}"));
+    if let Some(mut pipe) = child.stdin.take() {
+        let _ = pipe.write_all(stdin.as_bytes());
+    }
+    let status = child
+        .wait()
+        .unwrap_or_else(|e| panic!("failed to wait for {BINARY}: {e}"));
+    let elapsed = start.elapsed();
+    assert!(status.success(), "{args:?} exited with {status}");
+    elapsed
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Runs one scenario and prints its timings. Returns its median.
+fn scenario(name: &str, home: &Path, args: &[&str], stdin: &str, runs: u64) -> Duration {
+    // One warm-up run pages the binary in, as it would be after the first
+    // event of a session
+    run_once(home, args, stdin);
+    let mut times: Vec<Duration> = (0..runs).map(|_| run_once(home, args, stdin)).collect();
+    times.sort_unstable();
+    let at = |percent: usize| times[(times.len() - 1) * percent / 100];
+    let median = at(50);
+    println!(
+        "{name:<20} min {:>7.2} ms   median {:>7.2} ms   p95 {:>7.2} ms   ({runs} runs)",
+        millis(times[0]),
+        millis(median),
+        millis(at(95)),
+    );
+    median
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; a filter argument is not supported
+    let runs = env_or("CLAUDIATOR_BENCH_RUNS", 50).max(1);
+    let budget = Duration::from_millis(env_or("CLAUDIATOR_STARTUP_BUDGET_MS", 50));
+
+    let home = tempfile::TempDir::new().unwrap_or_else(|e| panic!("temp dir: {e}"));
+    let config_dir = home.path().join(".claude").join("claudiator");
+    std::fs::create_dir_all(&config_dir).unwrap_or_else(|e| panic!("config dir: {e}"));
+    std::fs::write(config_dir.join("config.toml"), CONFIG)
+        .unwrap_or_else(|e| panic!("config file: {e}"));
+
+    let medians = [
+        (
+            "version",
+            scenario("version", home.path(), &["version"], "", runs),
+        ),
+        (
+            "send (suppressed)",
+            scenario("send (suppressed)", home.path(), &["send"], EVENT, runs),
+        ),
+    ];
+
+    let over: Vec<&str> = medians
+        .iter()
+        .filter(|(_, median)| *median > budget)
+        .map(|(name, _)| *name)
+        .collect();
+    if !over.is_empty() {
+        eprintln!(
+            "Median startup over the {} ms budget: {}",
+            budget.as_millis(),
+            over.join(", ")
+        );
+        std::process::exit(1);
+    }
+}
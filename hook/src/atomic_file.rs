@@ -0,0 +1,50 @@
+//! Crash-safe file replacement for small state files.
+//!
+//! Several hook processes may run concurrently (one per Claude Code event),
+//! so state files are written to a per-process temp file and renamed into
+//! place. Readers see either the old or the new contents, never a partial
+//! write.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Atomically replace `path` with `contents`, creating parent directories.
+pub fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_creates_parents_and_replaces() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = dir.path().join("nested").join("state.json");
+
+        assert!(write(&path, "one").is_ok());
+        assert!(write(&path, "two").is_ok());
+        assert_eq!(fs::read_to_string(&path).ok().as_deref(), Some("two"));
+
+        // No temp files left behind.
+        let entries = fs::read_dir(dir.path().join("nested")).map(Iterator::count);
+        assert_eq!(entries.ok(), Some(1));
+    }
+}
@@ -0,0 +1,205 @@
+//! Circuit breaker that skips network attempts while the server is down.
+//!
+//! Each `send` is a fresh process, so breaker state is persisted to
+//! `~/.claude/claudiator/circuit.json` (`circuit-<profile>.json` for a named
+//! profile, so one server's outage doesn't silence another). After `threshold` consecutive
+//! failures the breaker opens and further sends are skipped until
+//! `cooldown_secs` have passed since the last failure. The next attempt after
+//! the cooldown acts as a probe: success closes the breaker, failure re-opens
+//! it for another cooldown.
+//!
+//! Without this, every hook event during an outage would block Claude Code
+//! for the full connect timeout. All I/O here is best-effort: an unreadable or
+//! corrupt state file is treated as a closed breaker.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+
+/// Persisted breaker state.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitState {
+    /// Failures since the last successful send.
+    pub consecutive_failures: u32,
+    /// Unix timestamp (seconds) of the most recent failure.
+    pub last_failure: i64,
+}
+
+/// Default location of the breaker state file for `profile`.
+pub fn default_state_path(profile: Option<&str>) -> Option<PathBuf> {
+    dirs::home_dir().map(|home| {
+        home.join(".claude")
+            .join("claudiator")
+            .join(state_file_name(profile))
+    })
+}
+
+/// `circuit.json`, or `circuit-<profile>.json` with characters unsafe in a
+/// file name replaced by `_`.
+fn state_file_name(profile: Option<&str>) -> String {
+    profile.map_or_else(
+        || "circuit.json".to_string(),
+        |name| format!("circuit-{}.json", file_safe(name)),
+    )
+}
+
+/// `name` with characters unsafe in a file name replaced by `_`.
+pub fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Read the breaker state, falling back to a closed breaker on any error.
+pub fn load(path: &Path) -> CircuitState {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Returns `true` if sends should be skipped at time `now` (Unix seconds).
+///
+/// A `threshold` of 0 disables the breaker entirely.
+pub fn is_open(path: &Path, threshold: u32, cooldown_secs: u64, now: i64) -> bool {
+    if threshold == 0 {
+        return false;
+    }
+
+    let state = load(path);
+    if state.consecutive_failures < threshold {
+        return false;
+    }
+
+    let cooldown = i64::try_from(cooldown_secs).unwrap_or(i64::MAX);
+    now.saturating_sub(state.last_failure) < cooldown
+}
+
+/// Record a failed send at time `now`.
+pub fn record_failure(path: &Path, now: i64) {
+    let mut state = load(path);
+    state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+    state.last_failure = now;
+    save(path, &state);
+}
+
+/// Record a successful send, closing the breaker.
+pub fn record_success(path: &Path) {
+    if path.exists() {
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn save(path: &Path, state: &CircuitState) {
+    if let Ok(json) = serde_json::to_string(state) {
+        let _ = atomic_file::write(path, &json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn state_path(dir: &TempDir) -> PathBuf {
+        dir.path().join("circuit.json")
+    }
+
+    #[test]
+    fn test_state_file_name_per_profile() {
+        assert_eq!(state_file_name(None), "circuit.json");
+        assert_eq!(state_file_name(Some("work")), "circuit-work.json");
+        assert_eq!(state_file_name(Some("../a b")), "circuit-___a_b.json");
+    }
+
+    #[test]
+    fn test_missing_file_is_closed() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        assert!(!is_open(&state_path(&dir), 3, 30, 1_000));
+    }
+
+    #[test]
+    fn test_opens_after_threshold_failures() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        record_failure(&path, 1_000);
+        record_failure(&path, 1_001);
+        assert!(!is_open(&path, 3, 30, 1_002));
+
+        record_failure(&path, 1_002);
+        assert!(is_open(&path, 3, 30, 1_003));
+        assert_eq!(load(&path).consecutive_failures, 3);
+    }
+
+    #[test]
+    fn test_half_opens_after_cooldown() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        for t in 0..3 {
+            record_failure(&path, 1_000 + t);
+        }
+        assert!(is_open(&path, 3, 30, 1_031));
+        assert!(!is_open(&path, 3, 30, 1_032));
+
+        // A failed probe re-opens the breaker for a fresh cooldown.
+        record_failure(&path, 1_032);
+        assert!(is_open(&path, 3, 30, 1_033));
+    }
+
+    #[test]
+    fn test_success_closes_breaker() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        for _ in 0..5 {
+            record_failure(&path, 1_000);
+        }
+        record_success(&path);
+        assert!(!path.exists());
+        assert!(!is_open(&path, 3, 30, 1_001));
+    }
+
+    #[test]
+    fn test_zero_threshold_disables_breaker() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        for _ in 0..10 {
+            record_failure(&path, 1_000);
+        }
+        assert!(!is_open(&path, 0, 30, 1_001));
+    }
+
+    #[test]
+    fn test_corrupt_file_is_closed() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        assert!(fs::write(&path, "not json").is_ok());
+        assert!(!is_open(&path, 1, 30, 1_000));
+        assert_eq!(load(&path), CircuitState::default());
+    }
+}
@@ -3,7 +3,7 @@
 //! Parsed once at startup by [`clap`]. The resolved [`Cli`] is then used by
 //! `main` to choose the subcommand and (optionally) override the log level.
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// Claudiator hook binary — forwards Claude Code events to a remote server
 #[derive(Debug, Parser)]
@@ -16,6 +16,12 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub log_level: Option<String>,
 
+    /// Use the `[profiles.<name>]` section of `config.toml`.
+    ///
+    /// Takes precedence over `CLAUDIATOR_PROFILE`.
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -29,9 +35,68 @@ pub enum Commands {
         raw_event_log: Option<String>,
     },
     /// Test the connection to the configured server
-    Test,
+    Test {
+        /// Also send a synthetic `TestNotification` event and confirm the
+        /// server created a notification for it (needs read and write scope)
+        #[arg(long)]
+        send_test_event: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Print send counters recorded by previous invocations
+    Stats {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
     /// Print the version and exit
-    Version,
+    Version {
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+}
+
+impl Commands {
+    /// Whether the command reads `config.toml` through [`crate::config::Config`].
+    /// The others skip parsing it, so they start faster and still work with
+    /// a broken config.
+    pub const fn needs_config(&self) -> bool {
+        matches!(self, Self::Send { .. } | Self::Test { .. })
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the config file, with API keys masked
+    Show {
+        /// Print the settings this invocation would use instead: the file,
+        /// the selected profile, environment variables, and flags merged over
+        /// the defaults, each labelled with where its value came from
+        #[arg(long)]
+        resolve: bool,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputFormat,
+    },
+}
+
+/// How a reporting subcommand prints its result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// A single JSON object on stdout, also on failure
+    Json,
 }
 
 #[cfg(test)]
@@ -83,7 +148,78 @@ mod tests {
         let cli = Cli::try_parse_from(["claudiator-hook", "test"]);
         assert!(cli.is_ok());
         if let Ok(cli) = cli {
-            assert!(matches!(cli.command, Commands::Test));
+            assert!(matches!(
+                cli.command,
+                Commands::Test {
+                    send_test_event: false,
+                    output: OutputFormat::Text,
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_test_command_with_send_test_event() {
+        let cli = Cli::try_parse_from(["claudiator-hook", "test", "--send-test-event"]);
+        assert!(cli.is_ok());
+        if let Ok(cli) = cli {
+            assert!(matches!(
+                cli.command,
+                Commands::Test {
+                    send_test_event: true,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_stats_command() {
+        let cli = Cli::try_parse_from(["claudiator-hook", "stats"]);
+        assert!(cli.is_ok());
+        if let Ok(cli) = cli {
+            assert!(matches!(cli.command, Commands::Stats { .. }));
+        }
+    }
+
+    #[test]
+    fn test_parse_config_show_command() {
+        let cli = Cli::try_parse_from(["claudiator-hook", "config", "show"]);
+        assert!(cli.is_ok());
+        if let Ok(cli) = cli {
+            assert!(matches!(
+                cli.command,
+                Commands::Config {
+                    command: ConfigCommand::Show {
+                        resolve: false,
+                        output: OutputFormat::Text,
+                    }
+                }
+            ));
+        }
+
+        let cli = Cli::try_parse_from([
+            "claudiator-hook",
+            "--profile",
+            "work",
+            "config",
+            "show",
+            "--resolve",
+            "--output",
+            "json",
+        ]);
+        assert!(cli.is_ok());
+        if let Ok(cli) = cli {
+            assert_eq!(cli.profile.as_deref(), Some("work"));
+            assert!(matches!(
+                cli.command,
+                Commands::Config {
+                    command: ConfigCommand::Show {
+                        resolve: true,
+                        output: OutputFormat::Json,
+                    }
+                }
+            ));
         }
     }
 
@@ -92,10 +228,42 @@ mod tests {
         let cli = Cli::try_parse_from(["claudiator-hook", "version"]);
         assert!(cli.is_ok());
         if let Ok(cli) = cli {
-            assert!(matches!(cli.command, Commands::Version));
+            assert!(matches!(
+                cli.command,
+                Commands::Version {
+                    output: OutputFormat::Text
+                }
+            ));
         }
     }
 
+    #[test]
+    fn test_parse_output_json() {
+        for command in ["test", "stats", "version"] {
+            let cli = Cli::try_parse_from(["claudiator-hook", command, "--output", "json"]);
+            assert!(cli.is_ok(), "{command} should accept --output json");
+            if let Ok(cli) = cli {
+                assert!(matches!(
+                    cli.command,
+                    Commands::Test {
+                        output: OutputFormat::Json,
+                        ..
+                    } | Commands::Stats {
+                        output: OutputFormat::Json
+                    } | Commands::Version {
+                        output: OutputFormat::Json
+                    }
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_output_rejects_unknown_format() {
+        let cli = Cli::try_parse_from(["claudiator-hook", "version", "--output", "yaml"]);
+        assert!(cli.is_err());
+    }
+
     #[test]
     fn test_parse_without_log_level() {
         let cli = Cli::try_parse_from(["claudiator-hook", "send"]);
@@ -114,6 +282,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_with_profile() {
+        let cli = Cli::try_parse_from(["claudiator-hook", "send", "--profile", "work"]);
+        assert!(cli.is_ok());
+        if let Ok(cli) = cli {
+            assert_eq!(cli.profile, Some("work".to_string()));
+        }
+    }
+
     #[test]
     fn test_parse_with_log_level_after_subcommand() {
         let cli = Cli::try_parse_from(["claudiator-hook", "send", "--log-level", "info"]);
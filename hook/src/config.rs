@@ -3,13 +3,19 @@
 //! The config file is written by the Claudiator server installer and contains
 //! the server URL, API key, and device identity. Optional log-related fields
 //! have sane defaults so existing configs don't need to be updated.
+//!
+//! Named `[profiles.<name>]` tables override top-level fields when that
+//! profile is selected, so one machine can report to several servers.
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::error::ConfigError;
+use crate::output::Action;
+use crate::policy::Policy;
 
 fn default_log_level() -> String {
     "error".to_string()
@@ -23,8 +29,52 @@ const fn default_max_log_backups() -> u32 {
     2
 }
 
+const fn default_max_log_age_days() -> u64 {
+    0
+}
+
+const fn default_spool_max_bytes() -> u64 {
+    10_485_760
+}
+
+const fn default_circuit_breaker_threshold() -> u32 {
+    3
+}
+
+const fn default_circuit_breaker_cooldown_secs() -> u64 {
+    30
+}
+
+/// Where a setting's effective value came from, lowest precedence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// Not set anywhere; the built-in default.
+    Default,
+    /// The top level of `config.toml`.
+    File,
+    /// The selected `[profiles.<name>]` table.
+    Profile,
+    /// An environment variable.
+    Env,
+    /// A command-line flag.
+    Flag,
+}
+
+impl Source {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::File => "file",
+            Self::Profile => "profile",
+            Self::Env => "env",
+            Self::Flag => "flag",
+        }
+    }
+}
+
 /// Hook configuration, deserialized from `~/.claude/claudiator/config.toml`.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     /// Base URL of the Claudiator server, e.g. `"https://my-server.example.com"`.
     pub server_url: String,
@@ -32,6 +82,11 @@ pub struct Config {
     pub api_key: String,
     /// Human-readable name for this machine, shown in the server UI.
     pub device_name: String,
+    /// Send `device_name` with the start of `device_id` appended, e.g.
+    /// `ubuntu (3f2a1b9c)`, so machines sharing a hostname stay apart.
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub unique_device_name: bool,
     /// Stable UUID identifying this device across reinstalls.
     pub device_id: String,
     /// Host OS platform string (e.g. `"mac"`, `"linux"`).
@@ -48,29 +103,165 @@ pub struct Config {
     /// Number of rotated log files to retain. Defaults to 2.
     #[serde(default = "default_max_log_backups")]
     pub max_log_backups: u32,
+    /// Rotate the log and delete backups once they are older than this many
+    /// days, regardless of size. Defaults to 0, which disables age limits.
+    #[serde(default = "default_max_log_age_days")]
+    pub max_log_age_days: u64,
     /// Path to append raw hook events (JSONL). If absent, raw logging is disabled.
     #[serde(default)]
     pub raw_event_log_path: Option<String>,
+    /// Consecutive send failures before the circuit breaker opens. Defaults to 3.
+    ///
+    /// Set to 0 to disable the breaker. See [`crate::circuit`].
+    #[serde(default = "default_circuit_breaker_threshold")]
+    pub circuit_breaker_threshold: u32,
+    /// Seconds to skip sends once the breaker is open. Defaults to 30.
+    #[serde(default = "default_circuit_breaker_cooldown_secs")]
+    pub circuit_breaker_cooldown_secs: u64,
+    /// Most bytes events held during a rate limit may take up on disk before
+    /// the oldest are discarded. Defaults to 10 MiB. See [`crate::spool`].
+    #[serde(default = "default_spool_max_bytes")]
+    pub spool_max_bytes: u64,
+    /// Respond to `PreToolUse` events whose file tool targets a path outside
+    /// the session's working directory: `warn`, `ask`, or `block`.
+    ///
+    /// Absent by default, which disables the check. See [`crate::workspace`].
+    #[serde(default)]
+    pub outside_cwd: Option<Action>,
+    /// Local match rules; see [`crate::policy`]. Empty by default.
+    #[serde(default)]
+    pub policy: Policy,
+    /// Key shared with the app, as base64 of 32 bytes. When set, prompts and
+    /// messages are sealed before they leave this machine; see
+    /// [`crate::e2e`]. Absent by default.
+    #[serde(default)]
+    pub e2e_key: Option<String>,
+    /// Names of environment variables to send with every event, e.g.
+    /// `["CI", "GITHUB_RUN_ID"]`, so events from CI runs can be traced back
+    /// to the run. Empty by default.
+    #[serde(default)]
+    pub capture_env: Vec<String>,
+    /// Name of the profile this config was loaded with, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
 }
 
 impl Config {
-    /// Load config from the default path: `~/.claude/claudiator/config.toml`.
-    pub fn load() -> Result<Self, ConfigError> {
+    /// The device name sent with events, with `unique_device_name` applied.
+    pub fn effective_device_name(&self) -> String {
+        if self.unique_device_name {
+            claudiator_types::disambiguated_device_name(&self.device_name, &self.device_id)
+        } else {
+            self.device_name.clone()
+        }
+    }
+
+    /// Load config from the default path: `~/.claude/claudiator/config.toml`,
+    /// applying the named profile if one is given.
+    pub fn load(profile: Option<&str>) -> Result<Self, ConfigError> {
+        Self::load_profile_from(&Self::default_path()?, profile)
+    }
+
+    /// `~/.claude/claudiator/config.toml`.
+    pub fn default_path() -> Result<PathBuf, ConfigError> {
         let home = dirs::home_dir().ok_or(ConfigError::NoHomeDir)?;
-        let path = home.join(".claude").join("claudiator").join("config.toml");
-        Self::load_from(&path)
+        Ok(home.join(".claude").join("claudiator").join("config.toml"))
     }
 
-    /// Load config from an explicit path.
+    /// Load config from an explicit path, without a profile.
     ///
     /// Used by tests to point at a temporary file instead of the real config.
+    #[cfg(test)]
     pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
-        let content = fs::read_to_string(path)
-            .map_err(|err| ConfigError::ReadFailed(path.to_path_buf(), err))?;
-        toml::from_str(&content).map_err(|err| ConfigError::ParseFailed(path.to_path_buf(), err))
+        Self::load_profile_from(path, None)
+    }
+
+    /// Load config from an explicit path. Fields in `[profiles.<profile>]`
+    /// replace the top-level ones; the `profiles` table is otherwise ignored.
+    pub fn load_profile_from(path: &Path, profile: Option<&str>) -> Result<Self, ConfigError> {
+        Self::load_with_sources(path, profile).map(|(config, _)| config)
+    }
+
+    /// Like [`Config::load_profile_from`], also returning whether each key
+    /// set in the file came from the top level or the profile. Keys missing
+    /// from the map took their default.
+    pub fn load_with_sources(
+        path: &Path,
+        profile: Option<&str>,
+    ) -> Result<(Self, BTreeMap<String, Source>), ConfigError> {
+        let mut table = read_table(path)?;
+
+        let profiles = table.remove("profiles");
+        let mut sources: BTreeMap<String, Source> = table
+            .keys()
+            .map(|key| (key.clone(), Source::File))
+            .collect();
+        if let Some(name) = profile {
+            let overrides = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .and_then(toml::Value::as_table)
+                .ok_or_else(|| ConfigError::UnknownProfile(path.to_path_buf(), name.to_string()))?;
+            for (key, value) in overrides {
+                table.insert(key.clone(), value.clone());
+                sources.insert(key.clone(), Source::Profile);
+            }
+        }
+
+        let mut config: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|err| ConfigError::ParseFailed(path.to_path_buf(), err))?;
+        config.profile = profile.map(String::from);
+        Ok((config, sources))
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table, ConfigError> {
+    let content =
+        fs::read_to_string(path).map_err(|err| ConfigError::ReadFailed(path.to_path_buf(), err))?;
+    toml::from_str(&content).map_err(|err| ConfigError::ParseFailed(path.to_path_buf(), err))
+}
+
+/// Keys whose values are secrets, masked wherever the config is shown.
+pub const SECRET_KEYS: &[&str] = &["api_key", "e2e_key"];
+
+/// The config file as written, with every secret (top level and in
+/// profiles) masked.
+pub fn read_masked(path: &Path) -> Result<toml::Table, ConfigError> {
+    let mut table = read_table(path)?;
+    mask_table(&mut table);
+    if let Some(profiles) = table
+        .get_mut("profiles")
+        .and_then(toml::Value::as_table_mut)
+    {
+        for (_, profile) in profiles.iter_mut() {
+            if let Some(profile) = profile.as_table_mut() {
+                mask_table(profile);
+            }
+        }
+    }
+    Ok(table)
+}
+
+fn mask_table(table: &mut toml::Table) {
+    for name in SECRET_KEYS {
+        if let Some(toml::Value::String(key)) = table.get_mut(*name) {
+            *key = mask_api_key(key);
+        }
     }
 }
 
+/// Hides all but the last four characters of an API key, or all of it when
+/// the key is too short for that to leave most of it hidden.
+pub fn mask_api_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 12 {
+        return "****".to_string();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{tail}")
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -155,9 +346,47 @@ platform = "mac"
             assert_eq!(config.log_level, "error");
             assert_eq!(config.max_log_size_bytes, 1_048_576);
             assert_eq!(config.max_log_backups, 2);
+            assert_eq!(config.max_log_age_days, 0);
+            assert_eq!(config.circuit_breaker_threshold, 3);
+            assert_eq!(config.circuit_breaker_cooldown_secs, 30);
+            assert_eq!(config.spool_max_bytes, 10_485_760);
         }
     }
 
+    #[test]
+    fn test_load_from_valid_toml_with_circuit_breaker() {
+        let toml = r#"
+server_url = "https://example.com"
+api_key = "test-key-123"
+device_name = "test-machine"
+device_id = "550e8400-e29b-41d4-a716-446655440000"
+platform = "mac"
+circuit_breaker_threshold = 0
+circuit_breaker_cooldown_secs = 120
+"#;
+        let temp_file = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file.as_file(), toml.as_bytes()).unwrap();
+        let config = Config::load_from(temp_file.path()).unwrap();
+        assert_eq!(config.circuit_breaker_threshold, 0);
+        assert_eq!(config.circuit_breaker_cooldown_secs, 120);
+    }
+
+    #[test]
+    fn test_load_from_valid_toml_with_outside_cwd() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let toml = format!("{VALID_TOML}outside_cwd = \"ask\"\n");
+        std::io::Write::write_all(&mut temp_file.as_file(), toml.as_bytes()).unwrap();
+        let config = Config::load_from(temp_file.path()).unwrap();
+        assert_eq!(config.outside_cwd, Some(Action::Ask));
+
+        let defaults = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut defaults.as_file(), VALID_TOML.as_bytes()).unwrap();
+        assert_eq!(
+            Config::load_from(defaults.path()).unwrap().outside_cwd,
+            None
+        );
+    }
+
     #[test]
     fn test_load_from_valid_toml_with_raw_event_log_path() {
         let toml = r#"
@@ -197,6 +426,7 @@ platform = "mac"
 log_level = "debug"
 max_log_size_bytes = 500
 max_log_backups = 5
+max_log_age_days = 14
 "#;
         let temp_file = NamedTempFile::new();
         assert!(temp_file.is_ok());
@@ -216,6 +446,106 @@ max_log_backups = 5
             assert_eq!(config.log_level, "debug");
             assert_eq!(config.max_log_size_bytes, 500);
             assert_eq!(config.max_log_backups, 5);
+            assert_eq!(config.max_log_age_days, 14);
         }
     }
+
+    const PROFILES_TOML: &str = r#"
+server_url = "https://home.example.com"
+api_key = "home-key"
+device_name = "test-machine"
+device_id = "550e8400-e29b-41d4-a716-446655440000"
+platform = "mac"
+
+[profiles.work]
+server_url = "https://work.example.com"
+api_key = "work-key"
+e2e_key = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc="
+circuit_breaker_threshold = 0
+
+[profiles.broken]
+server_url = 42
+"#;
+
+    #[test]
+    fn test_profile_overrides_top_level_fields() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file.as_file(), PROFILES_TOML.as_bytes()).unwrap();
+
+        let config = Config::load_from(temp_file.path()).unwrap();
+        assert_eq!(config.server_url, "https://home.example.com");
+        assert_eq!(config.api_key, "home-key");
+        assert!(config.profile.is_none());
+
+        let config = Config::load_profile_from(temp_file.path(), Some("work")).unwrap();
+        assert_eq!(config.server_url, "https://work.example.com");
+        assert_eq!(config.api_key, "work-key");
+        assert_eq!(config.circuit_breaker_threshold, 0);
+        assert_eq!(config.device_name, "test-machine");
+        assert_eq!(config.profile.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn test_load_with_sources_labels_file_and_profile_keys() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file.as_file(), PROFILES_TOML.as_bytes()).unwrap();
+
+        let (_, sources) = Config::load_with_sources(temp_file.path(), None).unwrap();
+        assert_eq!(sources.get("api_key"), Some(&Source::File));
+        assert_eq!(sources.get("circuit_breaker_threshold"), None);
+        assert!(!sources.contains_key("profiles"));
+
+        let (config, sources) = Config::load_with_sources(temp_file.path(), Some("work")).unwrap();
+        assert_eq!(config.api_key, "work-key");
+        assert_eq!(sources.get("api_key"), Some(&Source::Profile));
+        assert_eq!(
+            sources.get("circuit_breaker_threshold"),
+            Some(&Source::Profile)
+        );
+        assert_eq!(sources.get("device_name"), Some(&Source::File));
+    }
+
+    #[test]
+    fn test_read_masked_hides_every_secret() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file.as_file(), PROFILES_TOML.as_bytes()).unwrap();
+
+        let table = read_masked(temp_file.path()).unwrap();
+        assert_eq!(table["api_key"].as_str(), Some("****"));
+        assert_eq!(table["profiles"]["work"]["api_key"].as_str(), Some("****"));
+        assert_eq!(
+            table["profiles"]["work"]["e2e_key"].as_str(),
+            Some("****Bwc=")
+        );
+        assert_eq!(
+            table["profiles"]["work"]["server_url"].as_str(),
+            Some("https://work.example.com")
+        );
+    }
+
+    #[test]
+    fn test_mask_api_key() {
+        assert_eq!(mask_api_key("claud_0123456789abcdef"), "****cdef");
+        assert_eq!(mask_api_key("short-key"), "****");
+        assert_eq!(mask_api_key(""), "****");
+    }
+
+    #[test]
+    fn test_profile_errors() {
+        let temp_file = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file.as_file(), PROFILES_TOML.as_bytes()).unwrap();
+
+        let result = Config::load_profile_from(temp_file.path(), Some("missing"));
+        assert!(
+            matches!(result, Err(ConfigError::UnknownProfile(_, ref name)) if name == "missing")
+        );
+
+        let result = Config::load_profile_from(temp_file.path(), Some("broken"));
+        assert!(matches!(result, Err(ConfigError::ParseFailed(..))));
+
+        let without_profiles = NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut without_profiles.as_file(), VALID_TOML.as_bytes()).unwrap();
+        let result = Config::load_profile_from(without_profiles.path(), Some("work"));
+        assert!(matches!(result, Err(ConfigError::UnknownProfile(..))));
+    }
 }
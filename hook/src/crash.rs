@@ -0,0 +1,161 @@
+//! Reporting panics to the server.
+//!
+//! A panicking hook can't tell anyone: Claude Code only sees a failed
+//! command, and the server never hears from the device. [`install`] sets a
+//! panic hook that writes a [`CrashRecord`] to
+//! `~/.claude/claudiator/crash.json`. The next `send` takes the record and
+//! sends it as a `HookCrash` event in that run's session, where it shows on
+//! the session timeline and raises a notification.
+//!
+//! Only the latest crash is kept. Like the other state files, all I/O here
+//! is best-effort.
+
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::event::{HookEvent, HookEventKind};
+
+/// A panic, as written by the panic hook.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashRecord {
+    /// Version of the hook that panicked.
+    pub version: String,
+    /// The panic message.
+    pub message: String,
+    /// `file:line:column` the panic was raised at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// RFC 3339 time of the panic.
+    pub crashed_at: String,
+}
+
+impl CrashRecord {
+    fn from_panic(info: &PanicHookInfo<'_>) -> Self {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| (*s).to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Box<dyn Any>".to_string());
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            message,
+            location: info.location().map(ToString::to_string),
+            crashed_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// The `HookCrash` event reporting this crash in `session_id`.
+    pub fn to_event(&self, session_id: &str) -> HookEvent {
+        let location = self
+            .location
+            .as_deref()
+            .map(|location| format!(" at {location}"))
+            .unwrap_or_default();
+        HookEvent {
+            session_id: session_id.to_string(),
+            hook_event_name: HookEventKind::HookCrash,
+            cwd: None,
+            prompt: None,
+            notification_type: None,
+            tool_name: None,
+            message: Some(format!(
+                "claudiator-hook {} panicked{location} ({}): {}",
+                self.version, self.crashed_at, self.message
+            )),
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
+        }
+    }
+}
+
+/// Where the crash record is kept: `~/.claude/claudiator/crash.json`.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude").join("claudiator").join("crash.json"))
+}
+
+/// Records panics at `path`, then runs the default panic hook.
+pub fn install(path: PathBuf) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(json) = serde_json::to_string(&CrashRecord::from_panic(info)) {
+            let _ = atomic_file::write(&path, &json);
+        }
+        default_hook(info);
+    }));
+}
+
+/// Removes and returns the crash recorded at `path`, if any.
+pub fn take(path: &Path) -> Option<CrashRecord> {
+    let content = fs::read_to_string(path).ok()?;
+    let _ = fs::remove_file(path);
+    serde_json::from_str(&content).ok()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record() -> CrashRecord {
+        CrashRecord {
+            version: "0.3.0".to_string(),
+            message: "index out of bounds".to_string(),
+            location: Some("src/policy.rs:10:5".to_string()),
+            crashed_at: "2024-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_take_removes_the_record() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crash.json");
+        assert!(take(&path).is_none());
+
+        atomic_file::write(&path, serde_json::to_string(&record()).unwrap()).unwrap();
+        assert_eq!(take(&path).unwrap(), record());
+        assert!(!path.exists());
+        assert!(take(&path).is_none());
+    }
+
+    #[test]
+    fn test_corrupt_record_is_discarded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("crash.json");
+        fs::write(&path, "{not json").unwrap();
+        assert!(take(&path).is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_event_carries_version_and_message() {
+        let event = record().to_event("sess-1");
+        assert_eq!(event.hook_event_name, HookEventKind::HookCrash);
+        assert_eq!(event.session_id, "sess-1");
+        assert_eq!(
+            event.message.as_deref(),
+            Some(
+                "claudiator-hook 0.3.0 panicked at src/policy.rs:10:5 \
+                 (2024-01-01T00:00:00+00:00): index out of bounds"
+            )
+        );
+    }
+}
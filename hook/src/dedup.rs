@@ -0,0 +1,223 @@
+//! Drops repeated `Notification` events.
+//!
+//! Claude Code sometimes fires the same `Notification` event several times in
+//! quick succession. Each `send` is a fresh process, so recently seen events
+//! are kept in `~/.claude/claudiator/dedup.json`: a small LRU of event hashes,
+//! each expiring [`TTL_MS`] after it was first seen. A repeat within that
+//! window is dropped before it costs a network round trip or a slice of the
+//! server's rate limit.
+//!
+//! Like the circuit breaker, all I/O is best-effort: an unreadable or corrupt
+//! state file is treated as empty, so at worst a duplicate gets through.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::event::{HookEvent, HookEventKind};
+
+/// How long an event suppresses identical ones, in milliseconds.
+pub const TTL_MS: i64 = 5_000;
+
+/// Most event hashes remembered at once; the oldest are evicted first.
+pub const CAPACITY: usize = 32;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DedupState {
+    /// Oldest first.
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    hash: u64,
+    /// Unix timestamp (milliseconds) the event was first seen.
+    seen_at_ms: i64,
+}
+
+/// Default location of the dedup state file.
+pub fn default_state_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude").join("claudiator").join("dedup.json"))
+}
+
+/// Hash identifying `event`, or `None` for events that are never deduplicated.
+pub fn fingerprint(event: &HookEvent) -> Option<u64> {
+    if event.hook_event_name != HookEventKind::Notification {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(event).ok()?.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Returns `true` if an identical event was seen within [`TTL_MS`] of
+/// `now_ms`. Otherwise records the event and returns `false`.
+///
+/// A duplicate does not extend the window, so a steady stream of identical
+/// notifications still gets one through every [`TTL_MS`].
+pub fn is_duplicate(path: &Path, event: &HookEvent, now_ms: i64) -> bool {
+    let Some(hash) = fingerprint(event) else {
+        return false;
+    };
+
+    let mut state = load(path);
+    state
+        .entries
+        .retain(|entry| now_ms.saturating_sub(entry.seen_at_ms) < TTL_MS);
+
+    if state.entries.iter().any(|entry| entry.hash == hash) {
+        return true;
+    }
+
+    state.entries.push(Entry {
+        hash,
+        seen_at_ms: now_ms,
+    });
+    let excess = state.entries.len().saturating_sub(CAPACITY);
+    state.entries.drain(..excess);
+    save(path, &state);
+    false
+}
+
+fn load(path: &Path) -> DedupState {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, state: &DedupState) {
+    if let Ok(json) = serde_json::to_string(state) {
+        let _ = atomic_file::write(path, &json);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn notification(message: &str) -> HookEvent {
+        HookEvent {
+            session_id: "sess-1".to_string(),
+            hook_event_name: HookEventKind::Notification,
+            cwd: None,
+            prompt: None,
+            notification_type: Some("idle_prompt".to_string()),
+            tool_name: None,
+            message: Some(message.to_string()),
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
+        }
+    }
+
+    fn state_path(dir: &TempDir) -> PathBuf {
+        dir.path().join("dedup.json")
+    }
+
+    #[test]
+    fn test_repeat_within_ttl_is_duplicate() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+        let event = notification("Waiting for input");
+
+        assert!(!is_duplicate(&path, &event, 1_000));
+        assert!(is_duplicate(&path, &event, 1_500));
+        assert!(is_duplicate(&path, &event, 1_000 + TTL_MS - 1));
+    }
+
+    #[test]
+    fn test_repeat_after_ttl_is_sent() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+        let event = notification("Waiting for input");
+
+        assert!(!is_duplicate(&path, &event, 1_000));
+        assert!(is_duplicate(&path, &event, 4_000));
+        // The duplicate at 4_000 did not extend the window
+        assert!(!is_duplicate(&path, &event, 1_000 + TTL_MS));
+    }
+
+    #[test]
+    fn test_different_events_are_not_duplicates() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        assert!(!is_duplicate(&path, &notification("a"), 1_000));
+        assert!(!is_duplicate(&path, &notification("b"), 1_000));
+
+        let mut other_session = notification("a");
+        other_session.session_id = "sess-2".to_string();
+        assert!(!is_duplicate(&path, &other_session, 1_000));
+    }
+
+    #[test]
+    fn test_only_notifications_are_deduplicated() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        let mut stop = notification("a");
+        stop.hook_event_name = HookEventKind::Stop;
+        assert!(!is_duplicate(&path, &stop, 1_000));
+        assert!(!is_duplicate(&path, &stop, 1_001));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        for i in 0..=CAPACITY {
+            assert!(!is_duplicate(&path, &notification(&i.to_string()), 1_000));
+        }
+        assert_eq!(load(&path).entries.len(), CAPACITY);
+        // "0" was evicted, the newest is still remembered
+        assert!(!is_duplicate(&path, &notification("0"), 1_001));
+        assert!(is_duplicate(
+            &path,
+            &notification(&CAPACITY.to_string()),
+            1_001
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_file_is_treated_as_empty() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = state_path(&dir);
+
+        assert!(fs::write(&path, "not json").is_ok());
+        let event = notification("a");
+        assert!(!is_duplicate(&path, &event, 1_000));
+        assert!(is_duplicate(&path, &event, 1_001));
+    }
+}
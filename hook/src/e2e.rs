@@ -0,0 +1,172 @@
+//! End-to-end encryption of what the user typed.
+//!
+//! With `e2e_key` set in `config.toml`, the hook seals an event's `prompt`,
+//! `message`, `task_subject`, `custom_instructions`, and `error` before
+//! sending it, so the server only ever stores and forwards ciphertext. The same key is
+//! entered in the app, which opens the values locally.
+//!
+//! Each value is sealed with ChaCha20-Poly1305 under a random 12-byte nonce
+//! and sent as `e2e:v1:<base64 of nonce, ciphertext, and tag>`. That layout
+//! is `CryptoKit`'s `ChaChaPoly.SealedBox(combined:)`, so the app needs no
+//! parsing of its own.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::ChaCha20Poly1305;
+use claudiator_types::E2E_PREFIX;
+
+use crate::event::HookEvent;
+
+/// A key shared with the app.
+#[derive(Clone)]
+pub struct E2eCipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl std::fmt::Debug for E2eCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("E2eCipher(..)")
+    }
+}
+
+impl E2eCipher {
+    /// Parses a key given as base64 of 32 bytes, e.g. from
+    /// `openssl rand -base64 32`.
+    pub fn from_base64(key: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("e2e_key is not valid base64: {e}"))?;
+        let cipher = ChaCha20Poly1305::new_from_slice(&bytes)
+            .map_err(|_| format!("e2e_key must be 32 bytes, got {}", bytes.len()))?;
+        Ok(Self { cipher })
+    }
+
+    /// Seals `plaintext` into its wire form.
+    pub fn seal(&self, plaintext: &str) -> Result<String, String> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "Failed to seal value".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{E2E_PREFIX}{}", STANDARD.encode(sealed)))
+    }
+
+    /// Seals `event`'s `prompt`, `message`, `task_subject`,
+    /// `custom_instructions`, and `error` in place. Other fields stay readable: the server
+    /// needs them to track sessions and pick notifications.
+    pub fn seal_event(&self, event: &mut HookEvent) -> Result<(), String> {
+        for value in [
+            &mut event.prompt,
+            &mut event.message,
+            &mut event.task_subject,
+            &mut event.custom_instructions,
+            &mut event.error,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *value = self.seal(value)?;
+        }
+        Ok(())
+    }
+
+    /// Opens a value [`E2eCipher::seal`] produced, as the app does.
+    #[cfg(test)]
+    fn open(&self, sealed: &str) -> Option<String> {
+        use chacha20poly1305::Nonce;
+
+        let bytes = STANDARD.decode(sealed.strip_prefix(E2E_PREFIX)?).ok()?;
+        let (nonce, ciphertext) = bytes.split_at_checked(12)?;
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()?;
+        String::from_utf8(plaintext).ok()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::event::HookEventKind;
+
+    fn cipher() -> E2eCipher {
+        E2eCipher::from_base64(&STANDARD.encode([7u8; 32])).unwrap()
+    }
+
+    #[test]
+    fn test_seal_round_trips_with_fresh_nonces() {
+        let cipher = cipher();
+        let first = cipher.seal("deploy to prod").unwrap();
+        let second = cipher.seal("deploy to prod").unwrap();
+        assert!(claudiator_types::is_e2e_sealed(&first));
+        assert!(!first.contains("prod"));
+        assert_ne!(first, second);
+        assert_eq!(cipher.open(&first).unwrap(), "deploy to prod");
+
+        let other = E2eCipher::from_base64(&STANDARD.encode([8u8; 32])).unwrap();
+        assert!(other.open(&first).is_none());
+    }
+
+    #[test]
+    fn test_seal_event_only_touches_typed_text() {
+        let cipher = cipher();
+        let mut event = HookEvent {
+            session_id: "sess-1".to_string(),
+            hook_event_name: HookEventKind::UserPromptSubmit,
+            cwd: Some("/workspace".to_string()),
+            prompt: Some("fix the build".to_string()),
+            notification_type: None,
+            tool_name: Some("Bash".to_string()),
+            message: None,
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: Some("infra".to_string()),
+            task_id: Some("task-1".to_string()),
+            task_subject: Some("Migrate the database".to_string()),
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: Some("manual".to_string()),
+            custom_instructions: Some("keep the test plan".to_string()),
+            error: None,
+            is_interrupt: None,
+            env: None,
+        };
+        cipher.seal_event(&mut event).unwrap();
+
+        assert_eq!(
+            cipher.open(event.prompt.as_deref().unwrap()).unwrap(),
+            "fix the build"
+        );
+        assert_eq!(
+            cipher.open(event.task_subject.as_deref().unwrap()).unwrap(),
+            "Migrate the database"
+        );
+        assert_eq!(
+            cipher
+                .open(event.custom_instructions.as_deref().unwrap())
+                .unwrap(),
+            "keep the test plan"
+        );
+        assert!(event.message.is_none());
+        assert_eq!(event.trigger.as_deref(), Some("manual"));
+        assert_eq!(event.team_name.as_deref(), Some("infra"));
+        assert_eq!(event.task_id.as_deref(), Some("task-1"));
+        assert_eq!(event.cwd.as_deref(), Some("/workspace"));
+        assert_eq!(event.tool_name.as_deref(), Some("Bash"));
+    }
+
+    #[test]
+    fn test_key_must_be_32_bytes_of_base64() {
+        assert!(E2eCipher::from_base64("not base64!").is_err());
+        assert!(E2eCipher::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(E2eCipher::from_base64(&format!("{}\n", STANDARD.encode([1u8; 32]))).is_ok());
+    }
+}
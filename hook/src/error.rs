@@ -16,6 +16,8 @@ pub enum ConfigError {
     ReadFailed(PathBuf, io::Error),
     /// The config file was read but is not valid TOML or is missing required fields.
     ParseFailed(PathBuf, toml::de::Error),
+    /// The selected profile has no `[profiles.<name>]` table in the config file.
+    UnknownProfile(PathBuf, String),
 }
 
 impl std::fmt::Display for ConfigError {
@@ -28,6 +30,9 @@ impl std::fmt::Display for ConfigError {
             Self::ParseFailed(path, err) => {
                 write!(f, "Failed to parse config file {}: {err}", path.display())
             }
+            Self::UnknownProfile(path, name) => {
+                write!(f, "Profile '{name}' not found in {}", path.display())
+            }
         }
     }
 }
@@ -58,6 +63,21 @@ pub enum SendError {
     Network(String),
     /// The server returned a non-200 HTTP status code.
     ServerError(u16, String),
+    /// The server returned `429 Too Many Requests`, with the number of
+    /// seconds its `Retry-After` header asked us to wait, if it sent one.
+    RateLimited(Option<u64>),
+}
+
+impl SendError {
+    /// Whether this failure suggests the server is unreachable or unhealthy,
+    /// as opposed to a problem with this particular request.
+    pub const fn is_server_unavailable(&self) -> bool {
+        match self {
+            Self::Network(_) => true,
+            Self::ServerError(code, _) => *code >= 500,
+            Self::Serialize(_) | Self::RateLimited(_) => false,
+        }
+    }
 }
 
 impl std::fmt::Display for SendError {
@@ -68,6 +88,10 @@ impl std::fmt::Display for SendError {
             Self::ServerError(code, msg) => {
                 write!(f, "Server error {code}: {msg}")
             }
+            Self::RateLimited(Some(secs)) => {
+                write!(f, "Rate limited by server, retry after {secs}s")
+            }
+            Self::RateLimited(None) => write!(f, "Rate limited by server"),
         }
     }
 }
@@ -105,6 +129,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_config_error_unknown_profile() {
+        let err = ConfigError::UnknownProfile(PathBuf::from("/fake/config.toml"), "work".into());
+        assert_eq!(
+            err.to_string(),
+            "Profile 'work' not found in /fake/config.toml"
+        );
+    }
+
     #[test]
     fn test_event_error_parse_failed() {
         let json_result = serde_json::from_str::<serde_json::Value>("invalid json {");
@@ -135,6 +168,27 @@ mod tests {
         assert_eq!(err.to_string(), "Network error: connection timeout");
     }
 
+    #[test]
+    fn test_send_error_is_server_unavailable() {
+        assert!(SendError::Network("refused".to_string()).is_server_unavailable());
+        assert!(SendError::ServerError(503, String::new()).is_server_unavailable());
+        assert!(!SendError::ServerError(401, String::new()).is_server_unavailable());
+        assert!(!SendError::ServerError(422, String::new()).is_server_unavailable());
+        assert!(!SendError::RateLimited(Some(5)).is_server_unavailable());
+    }
+
+    #[test]
+    fn test_send_error_rate_limited() {
+        assert_eq!(
+            SendError::RateLimited(Some(5)).to_string(),
+            "Rate limited by server, retry after 5s"
+        );
+        assert_eq!(
+            SendError::RateLimited(None).to_string(),
+            "Rate limited by server"
+        );
+    }
+
     #[test]
     fn test_send_error_server_error() {
         let err = SendError::ServerError(500, "Internal Server Error".to_string());
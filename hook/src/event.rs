@@ -2,47 +2,30 @@
 //!
 //! [`HookEvent`] is both the inbound DTO (deserialized from Claude Code stdin)
 //! and the outbound DTO (serialized into the network payload). It contains only
-//! the 7 fields the server actually reads. All other fields in the Claude Code
+//! the fields the server actually reads. All other fields in the Claude Code
 //! JSON payload are silently ignored by serde's default behaviour — no explicit
 //! catch-all is needed.
+//!
+//! Both types are defined in the shared `claudiator-types` crate so the hook
+//! and server agree on the wire format; this module re-exports them under the
+//! hook's names.
 
 #[cfg(test)]
 use std::io;
 
-use serde::{Deserialize, Serialize};
+pub use claudiator_types::EventData as HookEvent;
+pub use claudiator_types::HookEventKind;
+pub use claudiator_types::PRIORITY_HIGH;
 
 #[cfg(test)]
 use crate::error::EventError;
 
-/// A hook event received from Claude Code and forwarded to the server.
+/// Parse a [`HookEvent`] from any `Read` source.
 ///
-/// Only the 7 fields the server reads are declared. Unknown fields in the
-/// incoming JSON are silently discarded by serde.
-#[derive(Debug, Deserialize, Serialize)]
-#[allow(clippy::struct_field_names)]
-pub struct HookEvent {
-    pub session_id: String,
-    pub hook_event_name: String,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub cwd: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub prompt: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub notification_type: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub tool_name: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub message: Option<String>,
-}
-
-impl HookEvent {
-    /// Parse a [`HookEvent`] from any `Read` source.
-    ///
-    /// Used by tests to pass a byte slice instead of touching actual stdin.
-    #[cfg(test)]
-    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, EventError> {
-        serde_json::from_reader(reader).map_err(EventError::ParseFailed)
-    }
+/// Used by tests to pass a byte slice instead of touching actual stdin.
+#[cfg(test)]
+pub fn from_reader<R: io::Read>(reader: R) -> Result<HookEvent, EventError> {
+    serde_json::from_reader(reader).map_err(EventError::ParseFailed)
 }
 
 #[cfg(test)]
@@ -53,9 +36,9 @@ mod tests {
     #[test]
     fn test_from_reader_minimal() {
         let json = r#"{"session_id": "sess-1", "hook_event_name": "Stop"}"#;
-        let event = HookEvent::from_reader(json.as_bytes()).unwrap();
+        let event = from_reader(json.as_bytes()).unwrap();
         assert_eq!(event.session_id, "sess-1");
-        assert_eq!(event.hook_event_name, "Stop");
+        assert_eq!(event.hook_event_name, HookEventKind::Stop);
         assert!(event.cwd.is_none());
         assert!(event.tool_name.is_none());
     }
@@ -71,7 +54,7 @@ mod tests {
             "tool_name": "bash",
             "message": "Done"
         }"#;
-        let event = HookEvent::from_reader(json.as_bytes()).unwrap();
+        let event = from_reader(json.as_bytes()).unwrap();
         assert_eq!(event.cwd, Some("/workspace".to_string()));
         assert_eq!(event.prompt, Some("Go".to_string()));
         assert_eq!(event.notification_type, Some("info".to_string()));
@@ -86,28 +69,41 @@ mod tests {
             "session_id": "sess-3",
             "hook_event_name": "PreToolUse",
             "tool_input": {"command": "rm -rf /"},
-            "custom_instructions": "secret",
+            "last_assistant_message": "secret",
             "transcript_path": "/private/t.json",
             "stop_hook_active": false,
             "extra_future_field": 42
         }"#;
-        let event = HookEvent::from_reader(json.as_bytes()).unwrap();
+        let event = from_reader(json.as_bytes()).unwrap();
         assert_eq!(event.session_id, "sess-3");
         // Sensitive / unknown fields are not deserialized
         let serialized = serde_json::to_string(&event).unwrap();
         assert!(!serialized.contains("tool_input"));
-        assert!(!serialized.contains("custom_instructions"));
+        assert!(!serialized.contains("last_assistant_message"));
         assert!(!serialized.contains("transcript_path"));
     }
 
+    #[test]
+    fn test_hook_event_kind_unknown_round_trips() {
+        let json = r#"{"session_id": "s", "hook_event_name": "FutureEvent"}"#;
+        let event = from_reader(json.as_bytes()).unwrap();
+        assert_eq!(
+            event.hook_event_name,
+            HookEventKind::Other("FutureEvent".to_string())
+        );
+
+        let serialized = serde_json::to_value(&event).unwrap();
+        assert_eq!(serialized["hook_event_name"], "FutureEvent");
+    }
+
     #[test]
     fn test_from_reader_missing_required_fields_errors() {
         let json = r#"{"cwd": "/tmp"}"#;
-        assert!(HookEvent::from_reader(json.as_bytes()).is_err());
+        assert!(from_reader(json.as_bytes()).is_err());
     }
 
     #[test]
     fn test_from_reader_invalid_json_errors() {
-        assert!(HookEvent::from_reader(&b"{ not json }"[..]).is_err());
+        assert!(from_reader(&b"{ not json }"[..]).is_err());
     }
 }
@@ -0,0 +1,93 @@
+//! Advisory locks coordinating concurrent hook processes.
+//!
+//! Claude Code starts one hook process per event, so parallel tool calls
+//! can run several at once. Maintenance that moves files around, such as
+//! log rotation, takes an exclusive lock on a `.lock` file beside its target
+//! first. Locks are never waited on: if another process holds one, it is
+//! already doing the same work and the caller skips it.
+//!
+//! The lock is released when the [`FileLock`] is dropped, or by the OS if the
+//! process dies holding it, so a crash can't leave it stuck.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+
+/// An exclusive lock, held until dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    file: fs::File,
+}
+
+impl FileLock {
+    /// Take the lock guarding `target`, without waiting.
+    ///
+    /// Returns `None` if another process holds it, or if the lock file can't
+    /// be created.
+    pub fn try_acquire(target: &Path) -> Option<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path(target))
+            .ok()?;
+        file.try_lock_exclusive().ok()?;
+        Some(Self { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// `<target>.lock`. The file itself is left in place; only the lock on it
+/// matters.
+fn lock_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    target.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_is_exclusive_until_dropped() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let target = dir.path().join("error.log");
+
+        let first = FileLock::try_acquire(&target);
+        assert!(first.is_some());
+        assert!(dir.path().join("error.log.lock").exists());
+        assert!(FileLock::try_acquire(&target).is_none());
+
+        drop(first);
+        assert!(FileLock::try_acquire(&target).is_some());
+    }
+
+    #[test]
+    fn test_locks_on_different_targets_are_independent() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+
+        let log = FileLock::try_acquire(&dir.path().join("error.log"));
+        assert!(log.is_some());
+        assert!(FileLock::try_acquire(&dir.path().join("events.jsonl")).is_some());
+    }
+
+    #[test]
+    fn test_missing_directory_fails_softly() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        assert!(FileLock::try_acquire(&dir.path().join("missing").join("error.log")).is_none());
+    }
+}
@@ -4,11 +4,24 @@
 //! in `tests/`. The binary entry point remains `src/main.rs`; all production
 //! code lives in the modules below.
 
+pub mod atomic_file;
+pub mod circuit;
 pub mod cli;
 pub mod config;
+pub mod crash;
+pub mod dedup;
+pub mod e2e;
 pub mod error;
 pub mod event;
+pub mod file_lock;
 pub mod logger;
+pub mod output;
 pub mod payload;
+pub mod policy;
 pub mod raw_log;
+pub mod report;
+pub mod selftest;
 pub mod sender;
+pub mod spool;
+pub mod stats;
+pub mod workspace;
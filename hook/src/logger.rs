@@ -9,18 +9,45 @@
 //!
 //! Call [`init`] once at startup with the desired level and rotation settings.
 //! If the log functions are called before `init`, a safe default config
-//! (level = Error, 1 MiB, 2 backups) is used automatically.
+//! (level = Error, 1 MiB, 2 backups, no age limit) is used automatically.
+//!
+//! # Buffering
+//!
+//! The log file is opened once per invocation, on the first line that passes
+//! the level filter, and written through a buffer. `ERROR` and `WARN` lines
+//! are flushed immediately; `INFO` and `DEBUG` lines are flushed by [`flush`],
+//! which `main` calls before exiting.
 //!
 //! # Rotation
 //!
 //! When the log file exceeds `max_size_bytes`, it is renamed to `.1`, existing
 //! `.1` becomes `.2`, and so on up to `max_backups`. The oldest backup is
 //! deleted. If `max_backups` is 0 the file is simply truncated in place.
+//!
+//! The size is checked when the file is opened and then tracked in memory.
+//! `DEBUG` lines never trigger a rotation, so a burst of them may overshoot
+//! the cap until the next higher-level line or invocation.
+//!
+//! With a non-zero `max_age_days`, opening the file also rotates it once its
+//! first line is older than that, and deletes backups last written before
+//! then. Quiet machines whose log never reaches the size cap still shed old
+//! entries this way.
+//!
+//! Each rotation parks the log at `<log>.rotating` until the backups have
+//! been shifted, so a process killed mid-rotation leaves a marker behind;
+//! the next open finishes that rotation before doing anything else.
+//!
+//! Parallel tool calls run several hook processes at once, so rotation takes
+//! the `error.log.lock` [`FileLock`] first. A process that finds it held
+//! skips rotating and keeps appending; sizes are re-read under the lock, so
+//! two processes can't both shift the backups for one overflow.
 
 use std::fs;
-use std::io::Write;
-use std::path::Path;
-use std::sync::OnceLock;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use crate::file_lock::FileLock;
 
 /// Log verbosity levels, ordered from least to most verbose.
 ///
@@ -73,6 +100,7 @@ struct LogConfig {
     level: LogLevel,
     max_size_bytes: u64,
     max_backups: u32,
+    max_age_days: u64,
 }
 
 static LOG_CONFIG: OnceLock<LogConfig> = OnceLock::new();
@@ -81,11 +109,13 @@ static LOG_CONFIG: OnceLock<LogConfig> = OnceLock::new();
 ///
 /// Must be called once before any log helpers are used. Subsequent calls are
 /// silently ignored (the `OnceLock` ensures the first write wins).
-pub fn init(level: LogLevel, max_size_bytes: u64, max_backups: u32) {
+/// A `max_age_days` of 0 disables age-based rotation.
+pub fn init(level: LogLevel, max_size_bytes: u64, max_backups: u32, max_age_days: u64) {
     let _ = LOG_CONFIG.set(LogConfig {
         level,
         max_size_bytes,
         max_backups,
+        max_age_days,
     });
 }
 
@@ -94,6 +124,7 @@ fn get_config() -> &'static LogConfig {
         level: LogLevel::Error,
         max_size_bytes: 1_048_576,
         max_backups: 2,
+        max_age_days: 0,
     })
 }
 
@@ -101,7 +132,6 @@ pub fn log_error(message: &str) {
     log(LogLevel::Error, message);
 }
 
-#[allow(dead_code)]
 pub fn log_warn(message: &str) {
     log(LogLevel::Warn, message);
 }
@@ -114,41 +144,124 @@ pub fn log_debug(message: &str) {
     log(LogLevel::Debug, message);
 }
 
+/// Write out any buffered log lines.
+pub fn flush() {
+    let mut file = LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(file) = file.as_mut() {
+        file.flush();
+    }
+}
+
+/// The log file for this invocation; `None` until the first line is logged.
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+
 fn log(level: LogLevel, message: &str) {
     let config = get_config();
     if level > config.level {
         return;
     }
 
-    let Some(home_dir) = dirs::home_dir() else {
-        return;
-    };
+    let mut file = LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    if file.is_none() {
+        let Some(home_dir) = dirs::home_dir() else {
+            return;
+        };
+        *file = Some(LogFile::open(
+            home_dir.join(".claude/claudiator/error.log"),
+            config.max_size_bytes,
+            config.max_backups,
+            config.max_age_days,
+        ));
+    }
+    if let Some(file) = file.as_mut() {
+        file.write(level, message);
+    }
+}
 
-    let log_path = home_dir.join(".claude/claudiator/error.log");
-    log_to_path(
-        &log_path,
-        level,
-        message,
-        config.max_size_bytes,
-        config.max_backups,
-    );
+/// A buffered, append-only handle to the log file that tracks its size so
+/// rotation doesn't need a `stat` per line.
+struct LogFile {
+    path: PathBuf,
+    /// `None` if the file could not be opened; lines are then dropped.
+    writer: Option<BufWriter<fs::File>>,
+    /// Bytes in the file, including buffered ones.
+    len: u64,
+    max_size_bytes: u64,
+    max_backups: u32,
 }
 
-fn log_to_path(path: &Path, level: LogLevel, message: &str, max_size_bytes: u64, max_backups: u32) {
-    if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+impl LogFile {
+    fn open(path: PathBuf, max_size_bytes: u64, max_backups: u32, max_age_days: u64) -> Self {
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        if let Some(_lock) = FileLock::try_acquire(&path) {
+            recover(&path, max_backups);
+            maybe_rotate(&path, max_size_bytes, max_backups);
+            if max_age_days > 0 {
+                let days = i64::try_from(max_age_days).unwrap_or(i64::MAX);
+                let cutoff = chrono::Utc::now() - chrono::Duration::days(days.min(36_500));
+                rotate_by_age(&path, max_backups, cutoff);
+            }
+        }
+
+        let mut file = Self {
+            path,
+            writer: None,
+            len: 0,
+            max_size_bytes,
+            max_backups,
+        };
+        file.reopen();
+        file
     }
 
-    maybe_rotate(path, max_size_bytes, max_backups);
+    fn reopen(&mut self) {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path);
+        self.len = file
+            .as_ref()
+            .ok()
+            .and_then(|file| file.metadata().ok())
+            .map_or(0, |metadata| metadata.len());
+        self.writer = file.ok().map(BufWriter::new);
+    }
 
-    let timestamp = chrono::Utc::now().to_rfc3339();
-    let log_line = format!("[{timestamp}] [{level}] {message}\n");
+    fn write(&mut self, level: LogLevel, message: &str) {
+        if level < LogLevel::Debug && self.len >= self.max_size_bytes {
+            self.flush();
+            self.writer = None;
+            if let Some(_lock) = FileLock::try_acquire(&self.path) {
+                maybe_rotate(&self.path, self.max_size_bytes, self.max_backups);
+            }
+            // Reopen even when another process holds the lock: it may have
+            // just moved the file this handle was appending to.
+            self.reopen();
+        }
 
-    let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) else {
-        return;
-    };
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let log_line = format!("[{timestamp}] [{level}] {message}\n");
+        if writer.write_all(log_line.as_bytes()).is_ok() {
+            self.len = self.len.saturating_add(log_line.len() as u64);
+        }
+
+        if level <= LogLevel::Warn {
+            self.flush();
+        }
+    }
 
-    let _ = file.write_all(log_line.as_bytes());
+    fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = writer.flush();
+        }
+    }
 }
 
 fn maybe_rotate(path: &Path, max_size_bytes: u64, max_backups: u32) {
@@ -160,26 +273,109 @@ fn maybe_rotate(path: &Path, max_size_bytes: u64, max_backups: u32) {
         return; // fast path: file is under size limit
     }
 
+    rotate(path, max_backups);
+}
+
+/// Rotate the log if its first line predates `cutoff`, then delete backups
+/// last modified before `cutoff`.
+fn rotate_by_age(path: &Path, max_backups: u32, cutoff: chrono::DateTime<chrono::Utc>) {
+    if first_line_time(path).is_some_and(|time| time < cutoff) {
+        rotate(path, max_backups);
+    }
+
+    for i in 1..=max_backups {
+        let backup = backup_path(path, i);
+        let stale = fs::metadata(&backup)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| chrono::DateTime::<chrono::Utc>::from(modified) < cutoff);
+        if stale {
+            let _ = fs::remove_file(&backup);
+        }
+    }
+}
+
+/// Timestamp of the first line in the log, if it has one.
+fn first_line_time(path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+    let file = fs::File::open(path).ok()?;
+    let mut line = String::new();
+    std::io::BufRead::read_line(&mut std::io::BufReader::new(file), &mut line).ok()?;
+    let (timestamp, _) = line.strip_prefix('[')?.split_once(']')?;
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&chrono::Utc))
+}
+
+/// Move the log to `.1`, shifting older backups up, or truncate it when no
+/// backups are kept.
+///
+/// The log is first renamed to `<log>.rotating`, which stays until the last
+/// step. A process that dies part way leaves that file behind, and
+/// [`recover`] finishes the job on the next open.
+fn rotate(path: &Path, max_backups: u32) {
     if max_backups == 0 {
         // Truncate the file
         let _ = fs::File::create(path);
         return;
     }
 
+    if fs::rename(path, pending_path(path)).is_ok() {
+        finish_rotation(path, max_backups);
+    }
+}
+
+/// Complete a rotation interrupted by a crash, if one was.
+fn recover(path: &Path, max_backups: u32) {
+    let pending = pending_path(path);
+    if !pending.exists() {
+        return;
+    }
+    if max_backups == 0 {
+        let _ = fs::remove_file(&pending);
+    } else {
+        finish_rotation(path, max_backups);
+    }
+}
+
+/// Shift the backups up and move the pending log into `.1`.
+///
+/// Every step is a single rename or delete. Backups are renumbered first, so
+/// a gap left by an earlier interrupted shift doesn't cost a backup that
+/// should be kept.
+fn finish_rotation(path: &Path, max_backups: u32) {
+    compact_backups(path, max_backups);
+
     // Delete the oldest backup if it exists
-    let oldest = format!("{}.{max_backups}", path.display());
-    let _ = fs::remove_file(&oldest);
+    let _ = fs::remove_file(backup_path(path, max_backups));
 
     // Shift backups: .{i} -> .{i+1}, starting from the oldest
     for i in (1..max_backups).rev() {
-        let from = format!("{}.{i}", path.display());
-        let to = format!("{}.{}", path.display(), i + 1);
-        let _ = fs::rename(&from, &to);
+        let _ = fs::rename(backup_path(path, i), backup_path(path, i + 1));
     }
 
-    // Rename current log to .1
-    let backup_1 = format!("{}.1", path.display());
-    let _ = fs::rename(path, &backup_1);
+    let _ = fs::rename(pending_path(path), backup_path(path, 1));
+}
+
+/// Renumber existing backups to `.1`, `.2`, ... in order, closing gaps.
+fn compact_backups(path: &Path, max_backups: u32) {
+    let mut next = 1;
+    for i in 1..=max_backups {
+        let backup = backup_path(path, i);
+        if !backup.exists() {
+            continue;
+        }
+        if i != next {
+            let _ = fs::rename(&backup, backup_path(path, next));
+        }
+        next += 1;
+    }
+}
+
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    PathBuf::from(format!("{}.{index}", path.display()))
+}
+
+fn pending_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.rotating", path.display()))
 }
 
 #[cfg(test)]
@@ -187,6 +383,82 @@ mod tests {
     use super::*;
     use std::fs;
 
+    /// Log a single line through a fresh handle, as one invocation would.
+    fn log_to_path(
+        path: &Path,
+        level: LogLevel,
+        message: &str,
+        max_size_bytes: u64,
+        max_backups: u32,
+    ) {
+        let mut file = LogFile::open(path.to_path_buf(), max_size_bytes, max_backups, 0);
+        file.write(level, message);
+        file.flush();
+    }
+
+    #[test]
+    fn test_log_file_buffers_info_until_flush() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+
+        let mut file = LogFile::open(log_path.clone(), 1024, 2, 0);
+        file.write(LogLevel::Info, "buffered");
+        assert_eq!(fs::read_to_string(&log_path).unwrap_or_default(), "");
+
+        file.write(LogLevel::Error, "flushed");
+        let content = fs::read_to_string(&log_path).unwrap_or_default();
+        assert!(content.contains("buffered"));
+        assert!(content.contains("flushed"));
+    }
+
+    #[test]
+    fn test_log_file_rotates_within_invocation_but_not_on_debug() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        let backup_path = format!("{}.1", log_path.display());
+
+        let mut file = LogFile::open(log_path.clone(), 10, 2, 0);
+        file.write(LogLevel::Debug, "first line is over the cap");
+        file.write(LogLevel::Debug, "so is the second");
+        file.flush();
+        assert!(!Path::new(&backup_path).exists());
+
+        file.write(LogLevel::Warn, "rotates first");
+        let backup = fs::read_to_string(&backup_path).unwrap_or_default();
+        assert!(backup.contains("so is the second"));
+        let current = fs::read_to_string(&log_path).unwrap_or_default();
+        assert_eq!(current.lines().count(), 1);
+        assert!(current.contains("rotates first"));
+    }
+
+    #[test]
+    fn test_log_file_skips_rotation_while_another_process_rotates() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        let backup_path = format!("{}.1", log_path.display());
+        let _ = fs::write(&log_path, "already over the cap\n");
+
+        let lock = FileLock::try_acquire(&log_path);
+        assert!(lock.is_some());
+        let mut file = LogFile::open(log_path.clone(), 10, 2, 0);
+        file.write(LogLevel::Warn, "appended instead");
+        assert!(!Path::new(&backup_path).exists());
+        let current = fs::read_to_string(&log_path).unwrap_or_default();
+        assert_eq!(current.lines().count(), 2);
+
+        drop(lock);
+        file.write(LogLevel::Warn, "rotates now");
+        assert!(Path::new(&backup_path).exists());
+        let current = fs::read_to_string(&log_path).unwrap_or_default();
+        assert_eq!(current.lines().count(), 1);
+    }
+
     #[test]
     fn test_log_level_ordering() {
         assert!(LogLevel::Error < LogLevel::Warn);
@@ -471,6 +743,55 @@ mod tests {
         assert!(!Path::new(&backup_path).exists());
     }
 
+    #[test]
+    fn test_rotate_by_age_moves_stale_log_aside() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        let stale =
+            "[2026-01-01T00:00:00+00:00] [ERROR] old\n[2026-03-01T00:00:00+00:00] [ERROR] newer\n";
+        assert!(fs::write(&log_path, stale).is_ok());
+
+        let cutoff = chrono::DateTime::parse_from_rfc3339("2026-02-01T00:00:00Z")
+            .map(|time| time.with_timezone(&chrono::Utc));
+        assert!(cutoff.is_ok());
+        let Ok(cutoff) = cutoff else { return };
+        rotate_by_age(&log_path, 2, cutoff);
+
+        assert!(!log_path.exists());
+        let backup = fs::read_to_string(format!("{}.1", log_path.display()));
+        assert_eq!(backup.ok().as_deref(), Some(stale));
+    }
+
+    #[test]
+    fn test_rotate_by_age_keeps_recent_log_and_prunes_old_backups() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        let backup_1 = format!("{}.1", log_path.display());
+        let backup_2 = format!("{}.2", log_path.display());
+
+        let now = chrono::Utc::now();
+        let recent = format!("[{}] [ERROR] recent\n", now.to_rfc3339());
+        assert!(fs::write(&log_path, &recent).is_ok());
+        assert!(fs::write(&backup_1, "recent backup").is_ok());
+        assert!(fs::write(&backup_2, "old backup").is_ok());
+        let old = std::time::SystemTime::from(now - chrono::Duration::days(40));
+        let backdated = fs::File::options()
+            .write(true)
+            .open(&backup_2)
+            .and_then(|file| file.set_modified(old));
+        assert!(backdated.is_ok());
+
+        rotate_by_age(&log_path, 2, now - chrono::Duration::days(30));
+
+        assert_eq!(fs::read_to_string(&log_path).ok(), Some(recent));
+        assert!(Path::new(&backup_1).exists());
+        assert!(!Path::new(&backup_2).exists());
+    }
+
     #[test]
     fn test_maybe_rotate_deletes_oldest() {
         let temp_dir = tempfile::tempdir();
@@ -516,4 +837,113 @@ mod tests {
         let backup_3_path = format!("{}.3", log_path.display());
         assert!(!Path::new(&backup_3_path).exists());
     }
+
+    /// Write each `(suffix, contents)` next to `log_path`; an empty suffix is
+    /// the log itself.
+    fn write_files(log_path: &Path, files: &[(&str, &str)]) {
+        for (suffix, contents) in files {
+            assert!(fs::write(format!("{}{suffix}", log_path.display()), contents).is_ok());
+        }
+    }
+
+    fn read_file(log_path: &Path, suffix: &str) -> Option<String> {
+        fs::read_to_string(format!("{}{suffix}", log_path.display())).ok()
+    }
+
+    #[test]
+    fn test_recover_finishes_rotation_interrupted_after_moving_log() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        // Died right after parking the log; a later line started a new one.
+        write_files(
+            &log_path,
+            &[(".rotating", "parked"), (".1", "backup 1"), ("", "new")],
+        );
+
+        recover(&log_path, 2);
+
+        assert_eq!(read_file(&log_path, ".1").as_deref(), Some("parked"));
+        assert_eq!(read_file(&log_path, ".2").as_deref(), Some("backup 1"));
+        assert_eq!(read_file(&log_path, "").as_deref(), Some("new"));
+        assert!(read_file(&log_path, ".rotating").is_none());
+    }
+
+    #[test]
+    fn test_recover_closes_gap_from_interrupted_shift() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        // Rotating with three backups, died after deleting .3 and moving .2
+        // to .3, before moving .1 to .2.
+        write_files(
+            &log_path,
+            &[
+                (".rotating", "parked"),
+                (".1", "backup 1"),
+                (".3", "backup 2"),
+            ],
+        );
+
+        recover(&log_path, 3);
+
+        assert_eq!(read_file(&log_path, ".1").as_deref(), Some("parked"));
+        assert_eq!(read_file(&log_path, ".2").as_deref(), Some("backup 1"));
+        assert_eq!(read_file(&log_path, ".3").as_deref(), Some("backup 2"));
+        assert!(read_file(&log_path, ".rotating").is_none());
+    }
+
+    #[test]
+    fn test_recover_is_a_no_op_after_complete_rotation() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        write_files(&log_path, &[(".1", "backup 1"), (".3", "backup 3")]);
+
+        recover(&log_path, 3);
+
+        assert_eq!(read_file(&log_path, ".1").as_deref(), Some("backup 1"));
+        assert!(read_file(&log_path, ".2").is_none());
+        assert_eq!(read_file(&log_path, ".3").as_deref(), Some("backup 3"));
+    }
+
+    #[test]
+    fn test_recover_drops_parked_log_without_backups() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        write_files(&log_path, &[(".rotating", "parked")]);
+
+        recover(&log_path, 0);
+
+        assert!(read_file(&log_path, ".rotating").is_none());
+        assert!(read_file(&log_path, ".1").is_none());
+    }
+
+    #[test]
+    fn test_log_file_open_recovers_before_rotating() {
+        let temp_dir = tempfile::tempdir();
+        assert!(temp_dir.is_ok());
+        let Ok(temp_dir) = temp_dir else { return };
+        let log_path = temp_dir.path().join("test.log");
+        write_files(
+            &log_path,
+            &[(".rotating", "parked"), ("", "over the cap again")],
+        );
+
+        let mut file = LogFile::open(log_path.clone(), 10, 2, 0);
+        file.write(LogLevel::Error, "fresh");
+        file.flush();
+
+        assert_eq!(
+            read_file(&log_path, ".1").as_deref(),
+            Some("over the cap again")
+        );
+        assert_eq!(read_file(&log_path, ".2").as_deref(), Some("parked"));
+        assert!(read_file(&log_path, ".rotating").is_none());
+    }
 }
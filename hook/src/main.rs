@@ -9,12 +9,15 @@
 //! The hook binary must always exit 0. Claude Code interprets a non-zero exit
 //! code as a "block" signal and will surface an error to the user. We never
 //! want a backend outage or misconfiguration to disrupt the Claude Code
-//! session, so all errors are logged and the process exits cleanly.
+//! session, so all errors are logged and the process exits cleanly. Local
+//! rules that do want to stop an action say so in JSON on stdout instead;
+//! see [`output`].
 //!
 //! # Entry point
 //!
-//! See [`main`] for the top-level dispatch and [`resolve_log_level`] for the
-//! log-level precedence rules.
+//! See [`main`] for the top-level dispatch, [`resolve_log_level`] for the
+//! log-level precedence rules, and [`resolve_profile`] for config profile
+//! selection. `config show --resolve` prints the outcome of both.
 
 #![warn(clippy::all)]
 #![warn(clippy::pedantic)]
@@ -25,24 +28,44 @@
 #![allow(clippy::cargo_common_metadata)]
 #![allow(clippy::multiple_crate_versions)]
 
+mod atomic_file;
+mod circuit;
 mod cli;
 mod config;
+mod crash;
+mod dedup;
+mod e2e;
 mod error;
 mod event;
+mod file_lock;
 mod logger;
+mod output;
 mod payload;
+mod policy;
 mod raw_log;
+mod report;
+mod selftest;
 mod sender;
+mod spool;
+mod stats;
+mod workspace;
 
 use clap::Parser;
 
-use crate::error::ConfigError;
-use cli::{Cli, Commands};
-use config::Config;
-use event::HookEvent;
-use logger::{log_debug, log_error, log_info, LogLevel};
+use crate::error::{ConfigError, SendError};
+use cli::{Cli, Commands, ConfigCommand, OutputFormat};
+use config::{Config, Source};
+use e2e::E2eCipher;
+use event::{HookEvent, HookEventKind, PRIORITY_HIGH};
+use logger::{log_debug, log_error, log_info, log_warn, LogLevel};
+use output::Response;
 use payload::EventPayload;
-use sender::{send_event, test_connection};
+use policy::Verdict;
+use report::{ConfigReport, Setting, StatsReport, TestEventReport, TestReport, VersionReport};
+use selftest::Scan;
+use sender::{fetch_notifications, send_event, test_connection, NOTIFICATIONS_PAGE_SIZE};
+use spool::Spool;
+use stats::Outcome;
 
 /// Determine the active log level from all sources.
 ///
@@ -55,50 +78,96 @@ use sender::{send_event, test_connection};
 /// Invalid values at any tier are silently skipped so the next source
 /// can take effect. This avoids a misconfigured env var breaking the hook.
 fn resolve_log_level(cli_level: Option<&str>, config_level: &str) -> LogLevel {
+    resolve_log_level_with_source(cli_level, config_level, Source::File).0
+}
+
+/// [`resolve_log_level`], also returning which tier won. `config_source`
+/// is reported when the config value is used.
+fn resolve_log_level_with_source(
+    cli_level: Option<&str>,
+    config_level: &str,
+    config_source: Source,
+) -> (LogLevel, Source) {
     // Precedence: CLI flag > env var > config > default (Error)
     if let Some(level_str) = cli_level {
         if let Ok(level) = level_str.parse::<LogLevel>() {
-            return level;
+            return (level, Source::Flag);
         }
     }
 
     if let Ok(env_level) = std::env::var("CLAUDIATOR_LOG_LEVEL") {
         if let Ok(level) = env_level.parse::<LogLevel>() {
-            return level;
+            return (level, Source::Env);
         }
     }
 
     if let Ok(level) = config_level.parse::<LogLevel>() {
-        return level;
+        return (level, config_source);
     }
 
-    LogLevel::Error
+    (LogLevel::Error, Source::Default)
+}
+
+/// Determine which config profile to use, if any.
+///
+/// `--profile` wins over the `CLAUDIATOR_PROFILE` environment variable. A
+/// blank value at either tier selects the top-level config.
+fn resolve_profile(cli_profile: Option<&str>) -> Option<String> {
+    cli_profile
+        .map(String::from)
+        .or_else(|| std::env::var("CLAUDIATOR_PROFILE").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let config_result = Config::load();
-
-    let (config_log_level, max_size, max_backups) =
-        config_result
-            .as_ref()
-            .map_or(("error", 1_048_576, 2), |config| {
-                (
-                    config.log_level.as_str(),
-                    config.max_log_size_bytes,
-                    config.max_log_backups,
-                )
-            });
+    let profile = resolve_profile(cli.profile.as_deref());
+    let config_result = cli
+        .command
+        .needs_config()
+        .then(|| Config::load(profile.as_deref()));
+
+    let (config_log_level, max_size, max_backups, max_age_days) = config_result
+        .as_ref()
+        .and_then(|result| result.as_ref().ok())
+        .map_or(("error", 1_048_576, 2, 0), |config| {
+            (
+                config.log_level.as_str(),
+                config.max_log_size_bytes,
+                config.max_log_backups,
+                config.max_log_age_days,
+            )
+        });
 
     let log_level = resolve_log_level(cli.log_level.as_deref(), config_log_level);
-    logger::init(log_level, max_size, max_backups);
+    logger::init(log_level, max_size, max_backups, max_age_days);
+    if let Some(path) = crash::default_path() {
+        crash::install(path);
+    }
 
+    // Only commands that need the config loaded it above
+    let config_result = || config_result.unwrap_or_else(|| Config::load(profile.as_deref()));
     match cli.command {
-        Commands::Send { raw_event_log } => cmd_send(config_result, raw_event_log),
-        Commands::Test => cmd_test(),
-        Commands::Version => cmd_version(),
+        Commands::Send { raw_event_log } => cmd_send(config_result(), raw_event_log),
+        Commands::Test {
+            send_test_event,
+            output,
+        } => cmd_test(config_result(), send_test_event, output),
+        Commands::Config {
+            command: ConfigCommand::Show { resolve, output },
+        } => cmd_config_show(
+            resolve,
+            output,
+            cli.profile.as_deref(),
+            cli.log_level.as_deref(),
+        ),
+        Commands::Stats { output } => cmd_stats(output),
+        Commands::Version { output } => cmd_version(output),
     }
+
+    logger::flush();
 }
 
 /// Handle the `send` subcommand.
@@ -141,7 +210,7 @@ fn cmd_send(config_result: Result<Config, ConfigError>, raw_event_log_cli: Optio
         raw_log::append_raw_event(path, &raw_stdin);
     }
 
-    let event = match serde_json::from_str::<HookEvent>(&raw_stdin) {
+    let mut event = match serde_json::from_str::<HookEvent>(&raw_stdin) {
         Ok(e) => e,
         Err(e) => {
             log_error(&format!("Event parse error: {e}"));
@@ -149,46 +218,548 @@ fn cmd_send(config_result: Result<Config, ConfigError>, raw_event_log_cli: Optio
         }
     };
 
-    let payload = EventPayload::new(&config, event);
+    log_debug(&format!(
+        "Parsed {} event for session {}",
+        event.hook_event_name, event.session_id
+    ));
+
+    // Answer Claude Code before touching the network, so local rules hold
+    // even when the server is down.
+    let verdict = local_verdict(&config, &event, &raw_stdin);
+    if let Some(ref response) = verdict.response {
+        log_info(&format!(
+            "Responding to {} with {:?}: {}",
+            event.hook_event_name, response.action, response.reason
+        ));
+        output::print(&event.hook_event_name, response);
+    }
 
-    if let Err(e) = send_event(&config, &payload) {
-        log_error(&format!("Send error: {e}"));
-    } else {
-        log_info("Event sent successfully");
+    let now = chrono::Utc::now();
+    if verdict.suppress {
+        log_info(&format!(
+            "{} event suppressed by policy",
+            event.hook_event_name
+        ));
+        record_stats(Outcome::Suppressed, &now);
+        return;
+    }
+    if let Some(reason) = verdict.priority {
+        event.priority = Some(PRIORITY_HIGH.to_string());
+        event.message.get_or_insert(reason);
+    }
+
+    if let Some(path) = dedup::default_state_path() {
+        if dedup::is_duplicate(&path, &event, now.timestamp_millis()) {
+            log_info("Duplicate Notification dropped");
+            record_stats(Outcome::Deduplicated, &now);
+            return;
+        }
+    }
+
+    if !seal_event(&config, &mut event) {
+        record_stats(Outcome::Failed, &now);
+        return;
+    }
+
+    let payload = payload::build_payload(&config, event);
+
+    let circuit_path = circuit::default_state_path(config.profile.as_deref());
+    if let Some(ref path) = circuit_path {
+        if circuit::is_open(
+            path,
+            config.circuit_breaker_threshold,
+            config.circuit_breaker_cooldown_secs,
+            now.timestamp(),
+        ) {
+            log_warn("Circuit breaker open, skipping send");
+            record_stats(Outcome::Skipped, &now);
+            return;
+        }
     }
+
+    report_crash(
+        &config,
+        &payload.event.session_id,
+        circuit_path.as_deref(),
+        &now,
+    );
+    deliver(&config, &payload, circuit_path.as_deref(), &now);
 }
 
-/// Handle the `test` subcommand.
-///
-/// Hits the server's `/api/v1/ping` endpoint and prints the result. Unlike
-/// `send`, this command exits non-zero on failure — it is only run by the
-/// user interactively to verify connectivity, never by Claude Code directly.
-fn cmd_test() {
-    let config = match Config::load() {
-        Ok(c) => c,
+/// Sends the crash an earlier run recorded, if any, as a `HookCrash` event
+/// in `session_id`; see [`crash`].
+fn report_crash(
+    config: &Config,
+    session_id: &str,
+    circuit_path: Option<&std::path::Path>,
+    now: &chrono::DateTime<chrono::Utc>,
+) {
+    let Some(record) = crash::default_path().and_then(|path| crash::take(&path)) else {
+        return;
+    };
+    log_warn(&format!(
+        "Reporting crash of claudiator-hook {}: {}",
+        record.version, record.message
+    ));
+    let payload = payload::build_payload(config, record.to_event(session_id));
+    deliver(config, &payload, circuit_path, now);
+}
+
+/// Sends `payload`, or spools it while the server is rate limiting, and
+/// updates the circuit breaker and stats with the outcome.
+fn deliver(
+    config: &Config,
+    payload: &EventPayload,
+    circuit_path: Option<&std::path::Path>,
+    now: &chrono::DateTime<chrono::Utc>,
+) {
+    let spool = Spool::default_for(config.profile.as_deref(), config.spool_max_bytes);
+    if let Some(ref spool) = spool {
+        if spool.is_rate_limited(now.timestamp()) {
+            if spool_event(spool, payload, now) {
+                log_warn("Rate limited by server, event spooled without sending");
+            }
+            return;
+        }
+    }
+
+    match (send_event(config, payload), spool) {
+        (Ok(bytes), spool) => {
+            if let Some(path) = circuit_path {
+                circuit::record_success(path);
+            }
+            record_stats(Outcome::Sent(bytes as u64), now);
+            log_info("Event sent successfully");
+            if let Some(ref spool) = spool {
+                spool.clear_rate_limit();
+                drain_spool(config, spool, circuit_path, now);
+            }
+        }
+        (Err(SendError::RateLimited(retry_after)), Some(ref spool)) => {
+            let wait = spool.record_rate_limit(now.timestamp(), retry_after);
+            if spool_event(spool, payload, now) {
+                log_warn(&format!(
+                    "Rate limited by server (429), event spooled; retrying in {wait}s"
+                ));
+            }
+        }
+        (Err(e), _) => {
+            if e.is_server_unavailable() {
+                if let Some(path) = circuit_path {
+                    circuit::record_failure(path, now.timestamp());
+                }
+            }
+            record_stats(Outcome::Failed, now);
+            log_error(&format!("Send error: {e}"));
+        }
+    }
+}
+
+/// Holds `payload` in `spool` for a later send. Returns `false`, having
+/// logged why, if it couldn't be written.
+fn spool_event(spool: &Spool, payload: &EventPayload, now: &chrono::DateTime<chrono::Utc>) -> bool {
+    match spool.push(payload) {
+        Ok(()) => {
+            record_stats(Outcome::Spooled, now);
+            true
+        }
         Err(e) => {
-            eprintln!("Failed to load config: {e}");
-            std::process::exit(1);
+            record_stats(Outcome::Failed, now);
+            log_error(&format!(
+                "Rate limited by server, failed to spool event: {e}"
+            ));
+            false
+        }
+    }
+}
+
+/// Sends a batch of events spooled during an earlier rate limit, now that a
+/// send has gone through.
+fn drain_spool(
+    config: &Config,
+    spool: &Spool,
+    circuit_path: Option<&std::path::Path>,
+    now: &chrono::DateTime<chrono::Utc>,
+) {
+    if spool.is_empty() {
+        return;
+    }
+    let drained = spool.drain(|payload| send_event(config, payload));
+    for bytes in &drained.sent {
+        record_stats(Outcome::Sent(*bytes as u64), now);
+    }
+    if !drained.sent.is_empty() {
+        log_info(&format!("Sent {} spooled events", drained.sent.len()));
+    }
+    if drained.rejected > 0 {
+        log_warn(&format!(
+            "Dropped {} spooled events the server rejected",
+            drained.rejected
+        ));
+    }
+    match drained.error {
+        Some(SendError::RateLimited(retry_after)) => {
+            let wait = spool.record_rate_limit(now.timestamp(), retry_after);
+            log_warn(&format!(
+                "Rate limited by server (429) while sending spooled events; retrying in {wait}s"
+            ));
+        }
+        Some(e) => {
+            if let Some(path) = circuit_path.filter(|_| e.is_server_unavailable()) {
+                circuit::record_failure(path, now.timestamp());
+            }
+            log_warn(&format!("Stopped sending spooled events: {e}"));
         }
+        None => {}
+    }
+}
+
+/// Seals `event`'s prompt and message when `e2e_key` is set. Returns
+/// `false` if the event must not be sent: a bad key never falls back to
+/// sending plaintext.
+fn seal_event(config: &Config, event: &mut HookEvent) -> bool {
+    let Some(ref key) = config.e2e_key else {
+        return true;
     };
+    match E2eCipher::from_base64(key).and_then(|cipher| cipher.seal_event(event)) {
+        Ok(()) => true,
+        Err(e) => {
+            log_error(&format!(
+                "Event not sent, end-to-end encryption failed: {e}"
+            ));
+            false
+        }
+    }
+}
 
-    println!("Testing connection to {}...", config.server_url);
+/// What the `[policy]` rules and `outside_cwd` make of `event`.
+fn local_verdict(config: &Config, event: &HookEvent, raw_stdin: &str) -> Verdict {
+    if config.policy.rules.is_empty() && config.outside_cwd.is_none() {
+        return Verdict::default();
+    }
+    // Rules look at `tool_input`, which `HookEvent` drops
+    let raw: Option<serde_json::Value> = serde_json::from_str(raw_stdin).ok();
+    let mut verdict = config.policy.evaluate(event, raw.as_ref());
+
+    if let (Some(action), Some(raw)) = (config.outside_cwd, &raw) {
+        if event.hook_event_name == HookEventKind::PreToolUse {
+            if let Some(path) = workspace::outside_cwd(raw) {
+                verdict.respond(Response {
+                    action,
+                    reason: format!(
+                        "{} targets {}, outside the working directory",
+                        event.tool_name.as_deref().unwrap_or("Tool"),
+                        path.display()
+                    ),
+                });
+            }
+        }
+    }
+    verdict
+}
+
+fn record_stats(outcome: Outcome, now: &chrono::DateTime<chrono::Utc>) {
+    if let Some(path) = stats::default_stats_path() {
+        stats::record(&path, outcome, &now.to_rfc3339());
+    }
+}
+
+/// Handle the `test` subcommand.
+///
+/// Hits the server's `/api/v1/ping` endpoint and prints the result. With
+/// `--send-test-event` it then runs [`verify_test_event`]. Unlike `send`,
+/// this command exits non-zero on failure — it is only run by the user
+/// interactively to verify connectivity, never by Claude Code directly.
+///
+/// With `--output json` progress lines are suppressed and a single
+/// [`TestReport`] is printed instead, on success and failure alike.
+fn cmd_test(
+    config_result: Result<Config, ConfigError>,
+    send_test_event: bool,
+    output: OutputFormat,
+) {
+    let text = output == OutputFormat::Text;
+    let config = match config_result {
+        Ok(c) => c,
+        Err(e) => fail_test(
+            TestReport::default(),
+            format!("Failed to load config: {e}"),
+            output,
+        ),
+    };
+    let mut report = TestReport::for_config(&config);
+
+    if text {
+        match config.profile {
+            Some(ref profile) => println!(
+                "Testing connection to {} (profile {profile})...",
+                config.server_url
+            ),
+            None => println!("Testing connection to {}...", config.server_url),
+        }
+    }
 
     match test_connection(&config) {
         Ok(body) => {
-            println!("Connection successful!");
-            println!("Server response: {body}");
+            if text {
+                println!("Connection successful!");
+                println!("Server response: {body}");
+            }
+            let ping = report::parse_body(&body);
+            if let Some(warning) = ping["capabilities"]["min_hook_version"]
+                .as_str()
+                .and_then(sender::unsupported_version_warning)
+            {
+                log_warn(&warning);
+                if text {
+                    println!("Warning: {warning}");
+                }
+            }
+            report.ping = Some(ping);
         }
-        Err(e) => {
-            eprintln!("Connection failed: {e}");
-            std::process::exit(1);
+        Err(e) => fail_test(report, format!("Connection failed: {e}"), output),
+    }
+
+    if send_test_event {
+        match verify_test_event(&config, output) {
+            Ok(event) => {
+                if text {
+                    println!("End-to-end test passed.");
+                }
+                report.test_event = Some(event);
+            }
+            Err(e) => fail_test(report, e, output),
         }
     }
+
+    if !text {
+        report.ok = true;
+        report::print(&report);
+    }
+}
+
+/// Report a failed `test` in the requested format and exit non-zero.
+fn fail_test(mut report: TestReport, error: String, output: OutputFormat) -> ! {
+    match output {
+        OutputFormat::Text => eprintln!("{error}"),
+        OutputFormat::Json => {
+            report.error = Some(error);
+            report::print(&report);
+        }
+    }
+    logger::flush();
+    std::process::exit(1);
+}
+
+/// Attempts made to find the test notification before giving up.
+const TEST_POLL_ATTEMPTS: u32 = 5;
+const TEST_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// Pages scanned per attempt, bounding the walk through older notifications.
+const TEST_MAX_PAGES: u32 = 20;
+
+/// Send a synthetic `TestNotification` event and poll
+/// `/api/v1/notifications` until the notification it produced shows up.
+///
+/// This exercises write scope, ingestion, and the notification pipeline in
+/// one pass. Reading notifications back needs read scope. Progress is only
+/// printed for [`OutputFormat::Text`].
+fn verify_test_event(config: &Config, output: OutputFormat) -> Result<TestEventReport, String> {
+    let progress = |line: &str| {
+        if output == OutputFormat::Text {
+            println!("{line}");
+        }
+    };
+
+    let nonce = chrono::Utc::now().timestamp_millis().to_string();
+    let event = selftest::build_event(config, &nonce);
+    let message = event.message.clone().unwrap_or_default();
+    let payload = payload::build_payload(config, event);
+
+    progress("Sending test event...");
+    let bytes = send_event(config, &payload).map_err(|e| format!("Test event rejected: {e}"))?;
+    progress(&format!("Test event accepted ({bytes} bytes)."));
+
+    progress("Waiting for notification...");
+    let page_size = NOTIFICATIONS_PAGE_SIZE as usize;
+    let mut after: Option<String> = None;
+    for attempt in 0..TEST_POLL_ATTEMPTS {
+        if attempt > 0 {
+            std::thread::sleep(TEST_POLL_INTERVAL);
+        }
+        for _ in 0..TEST_MAX_PAGES {
+            let body = fetch_notifications(config, after.as_deref())
+                .map_err(|e| format!("Failed to list notifications: {e}"))?;
+            match selftest::scan_page(&body, &message, page_size)? {
+                Scan::Found(notification) => {
+                    progress(&format!(
+                        "Notification created: {} — {} (id {})",
+                        notification.title, notification.body, notification.id
+                    ));
+                    return Ok(TestEventReport {
+                        bytes_sent: bytes,
+                        notification,
+                    });
+                }
+                Scan::NextPage(cursor) => after = Some(cursor),
+                Scan::Exhausted => break,
+            }
+        }
+    }
+
+    Err("Test event was accepted but no notification appeared".to_string())
+}
+
+/// Handle the `config show` subcommand.
+///
+/// Prints `config.toml` with API keys masked. With `--resolve` it prints
+/// every setting as this invocation sees it instead, labelled with the tier
+/// it came from, so the precedence rules above don't have to be worked out
+/// by hand. Exits non-zero if the config can't be loaded.
+fn cmd_config_show(
+    resolve: bool,
+    output: OutputFormat,
+    cli_profile: Option<&str>,
+    cli_log_level: Option<&str>,
+) {
+    let path = match Config::default_path() {
+        Ok(path) => path,
+        Err(e) => fail_config(ConfigReport::default(), e.to_string(), output),
+    };
+    let mut report = ConfigReport {
+        config_file: Some(path.display().to_string()),
+        ..ConfigReport::default()
+    };
+
+    if resolve {
+        let profile = resolve_profile(cli_profile);
+        let (config, sources) = match Config::load_with_sources(&path, profile.as_deref()) {
+            Ok(loaded) => loaded,
+            Err(e) => fail_config(report, e.to_string(), output),
+        };
+        let mut settings = report::settings(&config, &sources);
+
+        let config_source = sources.get("log_level").copied().unwrap_or(Source::Default);
+        let (level, source) =
+            resolve_log_level_with_source(cli_log_level, &config.log_level, config_source);
+        settings.insert(
+            "log_level".to_string(),
+            Setting {
+                value: level.to_string().to_lowercase().into(),
+                source,
+            },
+        );
+
+        let source = match (&profile, cli_profile) {
+            (None, _) => Source::Default,
+            (Some(_), Some(_)) => Source::Flag,
+            (Some(_), None) => Source::Env,
+        };
+        settings.insert(
+            "profile".to_string(),
+            Setting {
+                value: profile.into(),
+                source,
+            },
+        );
+        report.settings = Some(settings);
+    } else {
+        match config::read_masked(&path) {
+            Ok(file) => report.file = Some(file),
+            Err(e) => fail_config(report, e.to_string(), output),
+        }
+    }
+
+    report.ok = true;
+    match output {
+        OutputFormat::Text => print_config_text(&report),
+        OutputFormat::Json => report::print(&report),
+    }
+}
+
+fn print_config_text(report: &ConfigReport) {
+    let path = report.config_file.as_deref().unwrap_or_default();
+    if let Some(ref file) = report.file {
+        println!("# {path}");
+        match toml::to_string(file) {
+            Ok(toml) => print!("{toml}"),
+            Err(e) => eprintln!("Failed to serialize config: {e}"),
+        }
+    }
+
+    if let Some(ref settings) = report.settings {
+        println!("Config file: {path}");
+        println!();
+        let rows: Vec<(&str, String, Source)> = settings
+            .iter()
+            .map(|(name, setting)| {
+                let value = if setting.value.is_null() {
+                    "(unset)".to_string()
+                } else {
+                    setting.value.to_string()
+                };
+                (name.as_str(), value, setting.source)
+            })
+            .collect();
+        let name_width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+        let value_width = rows
+            .iter()
+            .map(|(_, value, _)| value.len())
+            .max()
+            .unwrap_or(0);
+        for (name, value, source) in rows {
+            println!(
+                "{name:<name_width$} = {value:<value_width$}  # {}",
+                source.as_str()
+            );
+        }
+    }
+}
+
+/// Report a failed `config show` in the requested format and exit non-zero.
+fn fail_config(mut report: ConfigReport, error: String, output: OutputFormat) -> ! {
+    match output {
+        OutputFormat::Text => eprintln!("{error}"),
+        OutputFormat::Json => {
+            report.error = Some(error);
+            report::print(&report);
+        }
+    }
+    logger::flush();
+    std::process::exit(1);
+}
+
+/// Handle the `stats` subcommand.
+///
+/// Prints the counters from `stats.json`. A missing file prints all zeros.
+fn cmd_stats(output: OutputFormat) {
+    let Some(path) = stats::default_stats_path() else {
+        eprintln!("Could not determine home directory");
+        std::process::exit(1);
+    };
+
+    let stats = stats::load(&path);
+    match output {
+        OutputFormat::Text => {
+            println!("{stats}");
+            println!("Stats file:     {}", path.display());
+        }
+        OutputFormat::Json => report::print(&StatsReport {
+            stats,
+            stats_file: path.display().to_string(),
+        }),
+    }
 }
 
 /// Handle the `version` subcommand.
-fn cmd_version() {
-    println!("claudiator-hook {}", env!("CARGO_PKG_VERSION"));
+fn cmd_version(output: OutputFormat) {
+    let report = VersionReport {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+    };
+    match output {
+        OutputFormat::Text => println!("{} {}", report.name, report.version),
+        OutputFormat::Json => report::print(&report),
+    }
 }
 
 #[cfg(test)]
@@ -303,6 +874,56 @@ mod tests {
         });
     }
 
+    // --- resolve_profile precedence tests ---
+
+    #[test]
+    fn test_cli_profile_overrides_env() {
+        with_env_var("CLAUDIATOR_PROFILE", Some("home"), || {
+            assert_eq!(resolve_profile(Some("work")), Some("work".to_string()));
+        });
+    }
+
+    #[test]
+    fn test_env_profile_used_without_cli() {
+        with_env_var("CLAUDIATOR_PROFILE", Some(" home "), || {
+            assert_eq!(resolve_profile(None), Some("home".to_string()));
+        });
+    }
+
+    #[test]
+    fn test_blank_profile_selects_top_level() {
+        with_env_var("CLAUDIATOR_PROFILE", Some(""), || {
+            assert_eq!(resolve_profile(None), None);
+        });
+        with_env_var("CLAUDIATOR_PROFILE", Some("home"), || {
+            assert_eq!(resolve_profile(Some("")), None);
+        });
+    }
+
+    #[test]
+    fn test_log_level_source_reports_winning_tier() {
+        with_env_var("CLAUDIATOR_LOG_LEVEL", Some("info"), || {
+            assert_eq!(
+                resolve_log_level_with_source(Some("debug"), "warn", Source::Profile),
+                (LogLevel::Debug, Source::Flag)
+            );
+            assert_eq!(
+                resolve_log_level_with_source(None, "warn", Source::Profile),
+                (LogLevel::Info, Source::Env)
+            );
+        });
+        with_env_var("CLAUDIATOR_LOG_LEVEL", None, || {
+            assert_eq!(
+                resolve_log_level_with_source(None, "warn", Source::Profile),
+                (LogLevel::Warn, Source::Profile)
+            );
+            assert_eq!(
+                resolve_log_level_with_source(None, "bogus", Source::File),
+                (LogLevel::Error, Source::Default)
+            );
+        });
+    }
+
     #[test]
     fn test_invalid_env_var_falls_through_to_config() {
         with_env_var("CLAUDIATOR_LOG_LEVEL", Some("not-valid"), || {
@@ -0,0 +1,124 @@
+//! JSON responses printed on stdout for Claude Code to act on.
+//!
+//! When a hook exits 0, Claude Code parses its stdout as JSON and can show a
+//! warning, ask the user, or block the action. Local rules produce a
+//! [`Response`]; [`render`] turns it into the shape the event type accepts.
+//! Nothing is printed when no rule fires, which leaves Claude Code's normal
+//! behaviour untouched.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::event::HookEventKind;
+
+/// What a rule asks Claude Code to do, weakest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Show the reason to the user and carry on.
+    Warn,
+    /// Ask the user to confirm the tool call, even if it is pre-approved.
+    Ask,
+    /// Stop the tool call (or the prompt, or the stop) and tell Claude why.
+    Block,
+}
+
+/// A rule's verdict on one event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub action: Action,
+    pub reason: String,
+}
+
+/// The stdout JSON for `response` to an event of type `kind`.
+///
+/// `ask` only exists for `PreToolUse`, and only some events can be blocked.
+/// Where the action isn't supported it falls back to a warning, so the user
+/// still sees the reason.
+pub fn render(kind: &HookEventKind, response: &Response) -> serde_json::Value {
+    let reason = response.reason.as_str();
+    match (response.action, kind) {
+        (Action::Ask | Action::Block, HookEventKind::PreToolUse) => json!({
+            "hookSpecificOutput": {
+                "hookEventName": "PreToolUse",
+                "permissionDecision": if response.action == Action::Block { "deny" } else { "ask" },
+                "permissionDecisionReason": reason,
+            }
+        }),
+        (
+            Action::Block,
+            HookEventKind::PostToolUse
+            | HookEventKind::UserPromptSubmit
+            | HookEventKind::Stop
+            | HookEventKind::SubagentStop,
+        ) => json!({ "decision": "block", "reason": reason }),
+        _ => json!({ "systemMessage": reason }),
+    }
+}
+
+/// Print the response to an event of type `kind` on stdout.
+pub fn print(kind: &HookEventKind, response: &Response) {
+    println!("{}", render(kind, response));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(action: Action) -> Response {
+        Response {
+            action,
+            reason: "outside the repo".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pre_tool_use_permission_decisions() {
+        assert_eq!(
+            render(&HookEventKind::PreToolUse, &response(Action::Block)),
+            json!({"hookSpecificOutput": {
+                "hookEventName": "PreToolUse",
+                "permissionDecision": "deny",
+                "permissionDecisionReason": "outside the repo",
+            }})
+        );
+        assert_eq!(
+            render(&HookEventKind::PreToolUse, &response(Action::Ask))["hookSpecificOutput"]
+                ["permissionDecision"],
+            "ask"
+        );
+    }
+
+    #[test]
+    fn test_block_decision_for_blockable_events() {
+        for kind in [
+            HookEventKind::PostToolUse,
+            HookEventKind::UserPromptSubmit,
+            HookEventKind::Stop,
+            HookEventKind::SubagentStop,
+        ] {
+            assert_eq!(
+                render(&kind, &response(Action::Block)),
+                json!({"decision": "block", "reason": "outside the repo"}),
+                "{kind:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported_actions_fall_back_to_warning() {
+        let warning = json!({"systemMessage": "outside the repo"});
+        assert_eq!(
+            render(&HookEventKind::PreToolUse, &response(Action::Warn)),
+            warning
+        );
+        assert_eq!(
+            render(&HookEventKind::Stop, &response(Action::Ask)),
+            warning
+        );
+        assert_eq!(
+            render(&HookEventKind::Notification, &response(Action::Block)),
+            warning
+        );
+    }
+}
@@ -2,82 +2,121 @@
 //!
 //! [`EventPayload`] wraps a [`HookEvent`] with device metadata and a
 //! timestamp. The server uses the device fields to associate events with a
-//! specific registered device.
+//! specific registered device. The structs live in `claudiator-types`; this
+//! module fills them in from the hook's [`Config`].
 
-use chrono::{SecondsFormat, Utc};
-use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub use claudiator_types::{DeviceInfo, EventPayload};
 
 use crate::config::Config;
 use crate::event::HookEvent;
 
-/// Device identity fields included with every event.
-#[derive(Debug, Serialize)]
-pub struct DeviceInfo {
-    pub device_id: String,
-    pub device_name: String,
-    pub platform: String,
-}
+/// Longest environment variable value sent, in bytes.
+const MAX_ENV_VALUE_LEN: usize = 256;
 
-/// The complete JSON body sent to `POST /api/v1/events`.
-#[derive(Debug, Serialize)]
-pub struct EventPayload {
-    pub device: DeviceInfo,
-    pub event: HookEvent,
-    /// RFC 3339 timestamp (millisecond precision) of when this payload was created.
-    pub timestamp: String,
+/// The variables named in `capture_env` that `lookup` finds set, with
+/// values cut to [`MAX_ENV_VALUE_LEN`]. `None` when none are.
+pub fn capture_env(
+    names: &[String],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<BTreeMap<String, String>> {
+    let captured: BTreeMap<String, String> = names
+        .iter()
+        .filter_map(|name| {
+            let mut value = lookup(name)?;
+            if value.len() > MAX_ENV_VALUE_LEN {
+                let mut end = MAX_ENV_VALUE_LEN;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                value.truncate(end);
+            }
+            Some((name.clone(), value))
+        })
+        .collect();
+    (!captured.is_empty()).then_some(captured)
 }
 
-impl EventPayload {
-    /// Build a payload from the loaded config and a parsed hook event.
-    pub fn new(config: &Config, event: HookEvent) -> Self {
-        let device = DeviceInfo {
-            device_id: config.device_id.clone(),
-            device_name: config.device_name.clone(),
-            platform: config.platform.clone(),
-        };
-        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-        Self {
-            device,
-            event,
-            timestamp,
-        }
-    }
+/// Build a payload from the loaded config and a parsed hook event.
+pub fn build_payload(config: &Config, event: HookEvent) -> EventPayload {
+    let device = DeviceInfo {
+        device_id: config.device_id.clone(),
+        device_name: config.effective_device_name(),
+        platform: config.platform.clone(),
+    };
+    // Claude Code's own events can carry a `title` (notifications do); the
+    // field is only meant for `External` events from other tools
+    let event = HookEvent {
+        title: None,
+        severity: None,
+        env: capture_env(&config.capture_env, |name| std::env::var(name).ok()),
+        ..event
+    };
+    EventPayload::new(device, event)
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
-    use crate::event::HookEvent;
+    use crate::event::{HookEvent, HookEventKind};
+    use crate::policy::Policy;
 
     fn make_config() -> Config {
         Config {
             server_url: "https://example.com".to_string(),
             api_key: "test-key".to_string(),
             device_name: "test-machine".to_string(),
+            unique_device_name: false,
             device_id: "550e8400-e29b-41d4-a716-446655440000".to_string(),
             platform: "mac".to_string(),
             log_level: "error".to_string(),
             max_log_size_bytes: 1_048_576,
             max_log_backups: 2,
+            max_log_age_days: 0,
             raw_event_log_path: None,
+            circuit_breaker_threshold: 3,
+            circuit_breaker_cooldown_secs: 30,
+            spool_max_bytes: 10_485_760,
+            outside_cwd: None,
+            policy: Policy::default(),
+            e2e_key: None,
+            capture_env: Vec::new(),
+            profile: None,
         }
     }
 
     fn make_event() -> HookEvent {
         HookEvent {
             session_id: "sess-123".to_string(),
-            hook_event_name: "test_event".to_string(),
+            hook_event_name: HookEventKind::from("test_event"),
             cwd: None,
             prompt: None,
             notification_type: None,
             tool_name: None,
             message: None,
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
         }
     }
 
     #[test]
     fn test_device_fields() {
-        let payload = EventPayload::new(&make_config(), make_event());
+        let payload = build_payload(&make_config(), make_event());
         assert_eq!(
             payload.device.device_id,
             "550e8400-e29b-41d4-a716-446655440000"
@@ -86,17 +125,48 @@ mod tests {
         assert_eq!(payload.device.platform, "mac");
     }
 
+    #[test]
+    fn test_unique_device_name_appends_short_id() {
+        let config = Config {
+            unique_device_name: true,
+            ..make_config()
+        };
+        let payload = build_payload(&config, make_event());
+        assert_eq!(payload.device.device_name, "test-machine (550e8400)");
+    }
+
     #[test]
     fn test_timestamp_valid_rfc3339_with_millis() {
-        let payload = EventPayload::new(&make_config(), make_event());
+        let payload = build_payload(&make_config(), make_event());
         assert!(chrono::DateTime::parse_from_rfc3339(&payload.timestamp).is_ok());
         assert!(payload.timestamp.contains('.'));
     }
 
+    #[test]
+    fn test_capture_env_keeps_set_variables() {
+        let names = vec![
+            "CI".to_string(),
+            "GITHUB_RUN_ID".to_string(),
+            "UNSET".to_string(),
+        ];
+        let lookup = |name: &str| match name {
+            "CI" => Some("true".to_string()),
+            "GITHUB_RUN_ID" => Some("é".repeat(200)),
+            _ => None,
+        };
+        let captured = capture_env(&names, lookup).unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured["CI"], "true");
+        assert_eq!(captured["GITHUB_RUN_ID"].len(), MAX_ENV_VALUE_LEN);
+
+        assert!(capture_env(&names, |_| None).is_none());
+        assert!(capture_env(&[], lookup).is_none());
+    }
+
     #[test]
     fn test_event_fields_preserved() {
-        let payload = EventPayload::new(&make_config(), make_event());
+        let payload = build_payload(&make_config(), make_event());
         assert_eq!(payload.event.session_id, "sess-123");
-        assert_eq!(payload.event.hook_event_name, "test_event");
+        assert_eq!(payload.event.hook_event_name.as_str(), "test_event");
     }
 }
@@ -0,0 +1,295 @@
+//! Local policy rules from the `[policy]` section of `config.toml`.
+//!
+//! Each rule matches tool events by `tool_name` and/or the `Bash` command
+//! with `*` / `?` wildcard patterns, and says what to do with a match: tag it
+//! high priority so the server notifies about it, drop it instead of sending
+//! it, or answer Claude Code through [`crate::output`]. Rules run before the
+//! event is sent, so they hold even when the server is down.
+//!
+//! ```toml
+//! [[policy.rules]]
+//! tool = "Bash"
+//! command = "*rm -rf*"
+//! action = "block"
+//! reason = "Recursive delete"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::HookEvent;
+use crate::output::{Action, Response};
+
+/// The `[policy]` config section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Policy {
+    /// Every rule is checked; all that match apply.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+/// One `[[policy.rules]]` entry.
+///
+/// A rule needs at least one of `tool` and `command`; one with neither
+/// matches nothing.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rule {
+    /// Pattern for the event's `tool_name`.
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// Pattern for `tool_input.command`. Events without a command never match.
+    #[serde(default)]
+    pub command: Option<String>,
+    pub action: RuleAction,
+    /// Shown to the user or Claude, and sent as the event's `message` when
+    /// it is tagged and has none. Defaults to a description of the match.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// What a matching [`Rule`] does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Send the event tagged high priority.
+    Priority,
+    /// Don't send the event at all.
+    Suppress,
+    /// Answer Claude Code with a warning.
+    Warn,
+    /// Make Claude Code ask the user before running the tool.
+    Ask,
+    /// Make Claude Code refuse the tool call.
+    Block,
+}
+
+/// The combined effect of every rule matching one event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verdict {
+    /// Reason of the first `priority` rule matched, if any.
+    pub priority: Option<String>,
+    pub suppress: bool,
+    /// The strongest `warn` / `ask` / `block` response matched.
+    pub response: Option<Response>,
+}
+
+impl Verdict {
+    /// Keep `response` if it is stronger than the one already chosen.
+    pub fn respond(&mut self, response: Response) {
+        if self
+            .response
+            .as_ref()
+            .is_none_or(|current| response.action > current.action)
+        {
+            self.response = Some(response);
+        }
+    }
+}
+
+impl Policy {
+    /// Check `event` against every rule. `raw` is the event JSON as read
+    /// from stdin, which still has `tool_input`.
+    pub fn evaluate(&self, event: &HookEvent, raw: Option<&serde_json::Value>) -> Verdict {
+        let command = raw
+            .and_then(|raw| raw.get("tool_input")?.get("command")?.as_str())
+            .map(str::to_string);
+        let mut verdict = Verdict::default();
+
+        for rule in &self.rules {
+            if !rule.matches(event.tool_name.as_deref(), command.as_deref()) {
+                continue;
+            }
+            let reason = rule.reason.clone().unwrap_or_else(|| {
+                format!(
+                    "{} matched a policy rule",
+                    event.tool_name.as_deref().unwrap_or("Tool call")
+                )
+            });
+            let action = match rule.action {
+                RuleAction::Priority => {
+                    verdict.priority.get_or_insert(reason);
+                    continue;
+                }
+                RuleAction::Suppress => {
+                    verdict.suppress = true;
+                    continue;
+                }
+                RuleAction::Warn => Action::Warn,
+                RuleAction::Ask => Action::Ask,
+                RuleAction::Block => Action::Block,
+            };
+            verdict.respond(Response { action, reason });
+        }
+        verdict
+    }
+}
+
+impl Rule {
+    fn matches(&self, tool_name: Option<&str>, command: Option<&str>) -> bool {
+        if self.tool.is_none() && self.command.is_none() {
+            return false;
+        }
+        let field_matches = |pattern: Option<&String>, value: Option<&str>| {
+            pattern.is_none_or(|pattern| value.is_some_and(|value| glob_match(pattern, value)))
+        };
+        field_matches(self.tool.as_ref(), tool_name)
+            && field_matches(self.command.as_ref(), command)
+    }
+}
+
+/// Whether `text` matches `pattern` in full, where `*` matches any run of
+/// characters and `?` any single one. Case-sensitive.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and where in `text` it started matching
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::event::HookEventKind;
+    use serde_json::json;
+
+    fn bash(command: &str) -> (HookEvent, serde_json::Value) {
+        let raw = json!({
+            "session_id": "s1",
+            "hook_event_name": "PreToolUse",
+            "tool_name": "Bash",
+            "tool_input": {"command": command},
+        });
+        (serde_json::from_value(raw.clone()).unwrap(), raw)
+    }
+
+    fn rule(tool: Option<&str>, command: Option<&str>, action: RuleAction) -> Rule {
+        Rule {
+            tool: tool.map(String::from),
+            command: command.map(String::from),
+            action,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("Bash", "Bash"));
+        assert!(!glob_match("Bash", "bash"));
+        assert!(glob_match("*rm -rf*", "cd /tmp && rm -rf build"));
+        assert!(!glob_match("rm -rf*", "cd /tmp && rm -rf build"));
+        assert!(glob_match(
+            "git push*--force*",
+            "git push origin main --force"
+        ));
+        assert!(glob_match("mcp__*__delete?", "mcp__github__deletes"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn test_rules_match_tool_and_command() {
+        let (event, raw) = bash("rm -rf target");
+        assert!(rule(Some("Bash"), None, RuleAction::Warn).matches(Some("Bash"), Some("ls")));
+        assert!(rule(Some("Bash"), Some("rm *"), RuleAction::Warn)
+            .matches(event.tool_name.as_deref(), Some("rm -rf target")));
+        assert!(!rule(Some("Edit"), Some("rm *"), RuleAction::Warn)
+            .matches(event.tool_name.as_deref(), Some("rm -rf target")));
+        // A command pattern never matches tools without a command
+        assert!(!rule(None, Some("*"), RuleAction::Warn).matches(Some("Edit"), None));
+        // An empty rule matches nothing
+        assert!(!rule(None, None, RuleAction::Block).matches(Some("Bash"), Some("ls")));
+
+        let policy = Policy {
+            rules: vec![rule(None, Some("rm -rf *"), RuleAction::Suppress)],
+        };
+        assert!(policy.evaluate(&event, Some(&raw)).suppress);
+        assert!(!policy.evaluate(&event, None).suppress);
+    }
+
+    #[test]
+    fn test_verdict_combines_matching_rules() {
+        let (event, raw) = bash("git push --force");
+        let policy = Policy {
+            rules: vec![
+                rule(Some("Bash"), None, RuleAction::Warn),
+                Rule {
+                    reason: Some("Force push".to_string()),
+                    ..rule(None, Some("git push*--force*"), RuleAction::Block)
+                },
+                rule(Some("Bash"), Some("git *"), RuleAction::Ask),
+                rule(Some("Bash"), Some("git *"), RuleAction::Priority),
+                rule(Some("Edit"), None, RuleAction::Suppress),
+            ],
+        };
+
+        let verdict = policy.evaluate(&event, Some(&raw));
+        assert_eq!(
+            verdict.response,
+            Some(Response {
+                action: Action::Block,
+                reason: "Force push".to_string(),
+            })
+        );
+        assert_eq!(
+            verdict.priority.as_deref(),
+            Some("Bash matched a policy rule")
+        );
+        assert!(!verdict.suppress);
+
+        let stop = HookEvent {
+            hook_event_name: HookEventKind::Stop,
+            tool_name: None,
+            ..event
+        };
+        assert_eq!(policy.evaluate(&stop, None), Verdict::default());
+    }
+
+    #[test]
+    fn test_policy_section_parses() {
+        let policy: Policy = toml::from_str(
+            r#"
+[[rules]]
+tool = "Bash"
+command = "*rm -rf*"
+action = "block"
+reason = "Recursive delete"
+
+[[rules]]
+tool = "WebFetch"
+action = "priority"
+"#,
+        )
+        .unwrap();
+        assert_eq!(policy.rules.len(), 2);
+        assert_eq!(policy.rules[0].action, RuleAction::Block);
+        assert_eq!(policy.rules[1].command, None);
+
+        let unknown: Result<Policy, _> =
+            toml::from_str("[[rules]]\ntool = \"Bash\"\naction = \"nuke\"\n");
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Results printed by `--output json`.
+//!
+//! Each reporting subcommand prints exactly one JSON object to stdout, even
+//! when it fails, so scripts and installers can parse stdout unconditionally.
+//! The exit code still signals success or failure.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::{self, Config, Source};
+use crate::selftest::NotificationSummary;
+use crate::stats::Stats;
+
+/// Result of `claudiator-hook test`.
+#[derive(Debug, Default, Serialize)]
+pub struct TestReport {
+    pub ok: bool,
+    /// Absent when the config could not be loaded.
+    pub server_url: Option<String>,
+    pub profile: Option<String>,
+    /// The ping response body; a JSON string when it is not valid JSON.
+    pub ping: Option<serde_json::Value>,
+    /// Present once `--send-test-event` has completed.
+    pub test_event: Option<TestEventReport>,
+    /// What failed, when `ok` is false.
+    pub error: Option<String>,
+}
+
+impl TestReport {
+    /// A report for testing `config`, before anything has run.
+    pub fn for_config(config: &Config) -> Self {
+        Self {
+            server_url: Some(config.server_url.clone()),
+            profile: config.profile.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Result of the `--send-test-event` round trip.
+#[derive(Debug, Serialize)]
+pub struct TestEventReport {
+    /// Request body size of the accepted test event.
+    pub bytes_sent: usize,
+    /// The notification the server created for it.
+    pub notification: NotificationSummary,
+}
+
+/// Result of `claudiator-hook stats`.
+#[derive(Debug, Serialize)]
+pub struct StatsReport {
+    #[serde(flatten)]
+    pub stats: Stats,
+    pub stats_file: String,
+}
+
+/// Result of `claudiator-hook config show`.
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigReport {
+    pub ok: bool,
+    /// Absent when the home directory could not be determined.
+    pub config_file: Option<String>,
+    /// The file as written, with API keys masked. Without `--resolve` only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<toml::Table>,
+    /// Every setting and where it came from. With `--resolve` only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings: Option<BTreeMap<String, Setting>>,
+    /// What failed, when `ok` is false.
+    pub error: Option<String>,
+}
+
+/// One effective setting in a [`ConfigReport`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Setting {
+    /// `null` for optional settings that are unset.
+    pub value: serde_json::Value,
+    pub source: Source,
+}
+
+/// Every field of `config` with its source, per the `sources` returned by
+/// [`Config::load_with_sources`]. Secrets are masked.
+pub fn settings(config: &Config, sources: &BTreeMap<String, Source>) -> BTreeMap<String, Setting> {
+    let serde_json::Value::Object(fields) = serde_json::to_value(config).unwrap_or_default() else {
+        return BTreeMap::new();
+    };
+    fields
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(key) if config::SECRET_KEYS.contains(&name.as_str()) => {
+                    serde_json::Value::String(config::mask_api_key(&key))
+                }
+                value => value,
+            };
+            let source = sources.get(&name).copied().unwrap_or(Source::Default);
+            (name, Setting { value, source })
+        })
+        .collect()
+}
+
+/// Result of `claudiator-hook version`.
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+/// Parse a server response body, keeping it as a string if it isn't JSON.
+pub fn parse_body(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()))
+}
+
+/// Print `report` to stdout as a single line of JSON.
+pub fn print<T: Serialize>(report: &T) {
+    match serde_json::to_string(report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("Failed to serialize output: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_body_keeps_non_json_as_string() {
+        assert_eq!(
+            parse_body(r#"{"status":"ok"}"#),
+            serde_json::json!({"status": "ok"})
+        );
+        assert_eq!(parse_body("pong"), serde_json::json!("pong"));
+    }
+
+    #[test]
+    fn test_failed_test_report_shape() {
+        let report = TestReport {
+            error: Some("Failed to load config: missing".to_string()),
+            ..TestReport::default()
+        };
+        let value = serde_json::to_value(&report);
+        assert!(value.is_ok());
+        let Ok(value) = value else { return };
+        assert_eq!(value["ok"], false);
+        assert_eq!(value["server_url"], serde_json::Value::Null);
+        assert_eq!(value["error"], "Failed to load config: missing");
+    }
+
+    #[test]
+    fn test_settings_mask_secrets_and_label_sources() {
+        let config: Result<Config, _> = toml::from_str(
+            r#"
+server_url = "https://work.example.com"
+api_key = "claud_0123456789abcdef"
+device_name = "test-machine"
+device_id = "550e8400-e29b-41d4-a716-446655440000"
+platform = "mac"
+e2e_key = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc="
+"#,
+        );
+        assert!(config.is_ok());
+        let Ok(config) = config else { return };
+        let sources = BTreeMap::from([
+            ("api_key".to_string(), Source::Profile),
+            ("server_url".to_string(), Source::Profile),
+            ("device_name".to_string(), Source::File),
+        ]);
+
+        let settings = settings(&config, &sources);
+        assert_eq!(
+            settings["api_key"],
+            Setting {
+                value: serde_json::json!("****cdef"),
+                source: Source::Profile,
+            }
+        );
+        assert_eq!(settings["e2e_key"].value, serde_json::json!("****Bwc="));
+        assert_eq!(settings["device_name"].source, Source::File);
+        assert_eq!(settings["max_log_backups"].value, 2);
+        assert_eq!(settings["max_log_backups"].source, Source::Default);
+        assert_eq!(
+            settings["raw_event_log_path"].value,
+            serde_json::Value::Null
+        );
+        assert!(!settings.contains_key("profile"));
+    }
+
+    #[test]
+    fn test_stats_report_flattens_counters() {
+        let report = StatsReport {
+            stats: Stats {
+                events_sent: 3,
+                ..Stats::default()
+            },
+            stats_file: "/home/u/.claude/claudiator/stats.json".to_string(),
+        };
+        let value = serde_json::to_value(&report);
+        assert!(value.is_ok());
+        let Ok(value) = value else { return };
+        assert_eq!(value["events_sent"], 3);
+        assert_eq!(value["last_success"], serde_json::Value::Null);
+        assert_eq!(value["stats_file"], "/home/u/.claude/claudiator/stats.json");
+    }
+}
@@ -0,0 +1,188 @@
+//! End-to-end check behind `claudiator-hook test --send-test-event`.
+//!
+//! A synthetic `TestNotification` event is sent with a unique message, then
+//! `/api/v1/notifications` is searched for a notification carrying that
+//! exact body. Matching on the message rather than on timestamps keeps the
+//! check independent of clock skew between this machine and the server.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::event::{HookEvent, HookEventKind};
+
+/// The fields of a server notification this check reads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationSummary {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub notification_type: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationList {
+    notifications: Vec<NotificationSummary>,
+}
+
+/// Result of scanning one page of notifications.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Scan {
+    /// The test notification was found.
+    Found(NotificationSummary),
+    /// Not on this page; continue after this `created_at`.
+    NextPage(String),
+    /// Not on this page, and there are no more.
+    Exhausted,
+}
+
+/// Session id used for test events: one per device, so repeated runs don't
+/// litter the server with sessions.
+pub fn session_id(config: &Config) -> String {
+    format!("claudiator-test-{}", config.device_id)
+}
+
+/// Build the synthetic event. `nonce` makes the message unique to this run.
+pub fn build_event(config: &Config, nonce: &str) -> HookEvent {
+    HookEvent {
+        session_id: session_id(config),
+        hook_event_name: HookEventKind::TestNotification,
+        cwd: None,
+        prompt: None,
+        notification_type: None,
+        tool_name: None,
+        message: Some(format!(
+            "Test notification from {} ({nonce})",
+            config.effective_device_name()
+        )),
+        priority: None,
+        agent_id: None,
+        agent_type: None,
+        team_name: None,
+        task_id: None,
+        task_subject: None,
+        model: None,
+        permission_suggestions: None,
+        title: None,
+        severity: None,
+        trigger: None,
+        custom_instructions: None,
+        error: None,
+        is_interrupt: None,
+        env: None,
+    }
+}
+
+/// Look for a `test` notification whose body is `message` in one page of
+/// `GET /api/v1/notifications`. `page_size` is the `limit` the page was
+/// requested with; a shorter page is the last one.
+pub fn scan_page(body: &str, message: &str, page_size: usize) -> Result<Scan, String> {
+    let list: NotificationList = serde_json::from_str(body)
+        .map_err(|e| format!("Unexpected notifications response: {e}"))?;
+
+    if let Some(found) = list
+        .notifications
+        .iter()
+        .find(|n| n.notification_type == "test" && n.body == message)
+    {
+        return Ok(Scan::Found(found.clone()));
+    }
+
+    match list.notifications.last() {
+        Some(final_item) if list.notifications.len() >= page_size => {
+            Ok(Scan::NextPage(final_item.created_at.clone()))
+        }
+        _ => Ok(Scan::Exhausted),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Policy;
+
+    fn make_config() -> Config {
+        Config {
+            server_url: "http://localhost:3000".to_string(),
+            api_key: "k".to_string(),
+            device_name: "laptop".to_string(),
+            unique_device_name: false,
+            device_id: "dev-1".to_string(),
+            platform: "mac".to_string(),
+            log_level: "error".to_string(),
+            max_log_size_bytes: 1_048_576,
+            max_log_backups: 2,
+            max_log_age_days: 0,
+            raw_event_log_path: None,
+            circuit_breaker_threshold: 3,
+            circuit_breaker_cooldown_secs: 30,
+            spool_max_bytes: 10_485_760,
+            outside_cwd: None,
+            policy: Policy::default(),
+            e2e_key: None,
+            capture_env: Vec::new(),
+            profile: None,
+        }
+    }
+
+    fn page(bodies: &[(&str, &str)]) -> String {
+        let notifications: Vec<_> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, (notification_type, body))| {
+                serde_json::json!({
+                    "id": format!("n{i}"),
+                    "event_id": i,
+                    "session_id": "s",
+                    "device_id": "d",
+                    "title": "t",
+                    "body": body,
+                    "notification_type": notification_type,
+                    "created_at": format!("2026-01-01T00:00:0{i}.000Z"),
+                    "acknowledged": false,
+                })
+            })
+            .collect();
+        serde_json::json!({ "notifications": notifications }).to_string()
+    }
+
+    #[test]
+    fn test_build_event() {
+        let config = make_config();
+        let event = build_event(&config, "123");
+        assert_eq!(event.session_id, "claudiator-test-dev-1");
+        assert_eq!(event.hook_event_name, "TestNotification");
+        assert_eq!(
+            event.message.as_deref(),
+            Some("Test notification from laptop (123)")
+        );
+    }
+
+    #[test]
+    fn test_scan_finds_matching_test_notification() {
+        let body = page(&[("stop", "msg"), ("test", "other"), ("test", "msg")]);
+        let scan = scan_page(&body, "msg", 200);
+        assert!(matches!(scan, Ok(Scan::Found(ref n)) if n.id == "n2"));
+    }
+
+    #[test]
+    fn test_scan_full_page_continues() {
+        let body = page(&[("stop", "a"), ("stop", "b")]);
+        assert_eq!(
+            scan_page(&body, "msg", 2),
+            Ok(Scan::NextPage("2026-01-01T00:00:01.000Z".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_scan_short_page_is_exhausted() {
+        let body = page(&[("stop", "a")]);
+        assert_eq!(scan_page(&body, "msg", 2), Ok(Scan::Exhausted));
+        assert_eq!(scan_page(&page(&[]), "msg", 2), Ok(Scan::Exhausted));
+    }
+
+    #[test]
+    fn test_scan_rejects_unexpected_body() {
+        assert!(scan_page("{}", "msg", 2).is_err());
+    }
+}
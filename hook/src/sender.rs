@@ -3,13 +3,53 @@
 //! All requests use a hard-coded 3-second timeout. The hook is invoked
 //! synchronously by Claude Code on every hook event, so a slow or unreachable
 //! server must not stall the Claude Code session.
+//!
+//! Requests go through a single process-wide [`ureq::Agent`], so any caller
+//! that sends more than one request per process (e.g. a long-running flusher)
+//! reuses pooled keep-alive connections instead of paying a fresh TCP + TLS
+//! handshake per event. ureq 2 speaks HTTP/1.1 only; connection reuse is
+//! where the latency savings come from.
+//!
+//! The agent identifies as `claudiator-hook/<version> (<os>)`. The server
+//! records the version per device and answers events with the oldest version
+//! it supports; a hook below that logs a warning on each send.
 
+use std::sync::OnceLock;
 use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+
+use claudiator_types::{hook_user_agent, is_older_version, MIN_HOOK_VERSION_HEADER};
+
 use crate::config::Config;
 use crate::error::SendError;
+use crate::logger::log_warn;
 use crate::payload::EventPayload;
 
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Idle connections kept per host. The hook only ever talks to one server.
+const MAX_IDLE_CONNECTIONS_PER_HOST: usize = 2;
+
+/// Process-wide HTTP agent with connection pooling.
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| {
+        ureq::AgentBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .max_idle_connections_per_host(MAX_IDLE_CONNECTIONS_PER_HOST)
+            .user_agent(&hook_user_agent(
+                env!("CARGO_PKG_VERSION"),
+                std::env::consts::OS,
+            ))
+            .build()
+    })
+}
+
+fn build_notifications_url(server_url: &str) -> String {
+    format!("{}/api/v1/notifications", server_url.trim_end_matches('/'))
+}
+
 fn build_events_url(server_url: &str) -> String {
     format!("{}/api/v1/events", server_url.trim_end_matches('/'))
 }
@@ -21,25 +61,31 @@ fn build_ping_url(server_url: &str) -> String {
 /// POST a hook event payload to `POST /api/v1/events`.
 ///
 /// Authenticates with a `Bearer` token from the config and includes a
-/// `User-Agent` header for server-side diagnostics. Returns `Ok(())` only
-/// for HTTP 200; any other status is returned as [`SendError::ServerError`].
-pub fn send_event(config: &Config, payload: &EventPayload) -> Result<(), SendError> {
+/// `User-Agent` header for server-side diagnostics. Returns the request body
+/// size in bytes only for HTTP 200. A `429` is returned as
+/// [`SendError::RateLimited`] with the server's `Retry-After`; any other
+/// status as [`SendError::ServerError`].
+pub fn send_event(config: &Config, payload: &EventPayload) -> Result<usize, SendError> {
     let body = serde_json::to_string(payload).map_err(SendError::Serialize)?;
     let url = build_events_url(&config.server_url);
 
     let api_key = &config.api_key;
-    let version = env!("CARGO_PKG_VERSION");
-    let response = ureq::post(&url)
-        .timeout(Duration::from_secs(3))
+    let response = agent()
+        .post(&url)
         .set("Content-Type", "application/json")
         .set("Authorization", &format!("Bearer {api_key}"))
-        .set("User-Agent", &format!("claudiator-hook/{version}"))
         .send_string(&body);
 
     match response {
         Ok(resp) => {
             if resp.status() == 200 {
-                Ok(())
+                if let Some(warning) = resp
+                    .header(MIN_HOOK_VERSION_HEADER)
+                    .and_then(unsupported_version_warning)
+                {
+                    log_warn(&warning);
+                }
+                Ok(body.len())
             } else {
                 let status = resp.status();
                 let body = resp
@@ -48,6 +94,11 @@ pub fn send_event(config: &Config, payload: &EventPayload) -> Result<(), SendErr
                 Err(SendError::ServerError(status, body))
             }
         }
+        Err(ureq::Error::Status(429, response)) => Err(SendError::RateLimited(
+            response
+                .header("Retry-After")
+                .and_then(|value| parse_retry_after(value, Utc::now())),
+        )),
         Err(ureq::Error::Status(code, response)) => {
             let body = response
                 .into_string()
@@ -58,6 +109,28 @@ pub fn send_event(config: &Config, payload: &EventPayload) -> Result<(), SendErr
     }
 }
 
+/// A warning to show if this hook is older than `min_version`, the oldest
+/// the server supports.
+pub fn unsupported_version_warning(min_version: &str) -> Option<String> {
+    let version = env!("CARGO_PKG_VERSION");
+    is_older_version(version, min_version).then(|| {
+        format!(
+            "claudiator-hook {version} is older than the oldest version the server supports ({min_version}); upgrade the hook"
+        )
+    })
+}
+
+/// Seconds to wait according to a `Retry-After` header, which is either a
+/// number of seconds or an HTTP date. A date in the past means no wait.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(u64::try_from(at.timestamp().saturating_sub(now.timestamp())).unwrap_or(0))
+}
+
 /// GET `/api/v1/ping` and return the response body as a string.
 ///
 /// Used by the `test` subcommand to verify the server is reachable and the
@@ -66,11 +139,9 @@ pub fn test_connection(config: &Config) -> Result<String, SendError> {
     let url = build_ping_url(&config.server_url);
 
     let api_key = &config.api_key;
-    let version = env!("CARGO_PKG_VERSION");
-    let response = ureq::get(&url)
-        .timeout(Duration::from_secs(3))
+    let response = agent()
+        .get(&url)
         .set("Authorization", &format!("Bearer {api_key}"))
-        .set("User-Agent", &format!("claudiator-hook/{version}"))
         .call();
 
     match response {
@@ -98,10 +169,93 @@ pub fn test_connection(config: &Config) -> Result<String, SendError> {
     }
 }
 
+/// Maximum page size accepted by `GET /api/v1/notifications`.
+pub const NOTIFICATIONS_PAGE_SIZE: u32 = 200;
+
+/// GET one page of `/api/v1/notifications` and return the response body.
+///
+/// Notifications are returned oldest first; pass the last `created_at` seen
+/// as `after` to fetch the next page. Requires an API key with read scope.
+pub fn fetch_notifications(config: &Config, after: Option<&str>) -> Result<String, SendError> {
+    let url = build_notifications_url(&config.server_url);
+
+    let api_key = &config.api_key;
+    let mut request = agent()
+        .get(&url)
+        .set("Authorization", &format!("Bearer {api_key}"))
+        .query("limit", &NOTIFICATIONS_PAGE_SIZE.to_string());
+    if let Some(after) = after {
+        request = request.query("after", after);
+    }
+
+    match request.call() {
+        Ok(resp) => {
+            let status = resp.status();
+            let body = resp
+                .into_string()
+                .unwrap_or_else(|_| "Failed to read response body".to_string());
+            if status == 200 {
+                Ok(body)
+            } else {
+                Err(SendError::ServerError(status, body))
+            }
+        }
+        Err(ureq::Error::Status(code, response)) => {
+            let body = response
+                .into_string()
+                .unwrap_or_else(|_| "Failed to read response body".to_string());
+            Err(SendError::ServerError(code, body))
+        }
+        Err(err) => Err(SendError::Network(err.to_string())),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_agent_is_shared() {
+        assert!(std::ptr::eq(agent(), agent()));
+    }
+
+    #[test]
+    fn test_unsupported_version_warning() {
+        assert!(unsupported_version_warning("0.0.1").is_none());
+        assert!(unsupported_version_warning(env!("CARGO_PKG_VERSION")).is_none());
+        let warning = unsupported_version_warning("999.0.0");
+        assert!(warning.is_some_and(|warning| warning.contains("(999.0.0)")));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now =
+            DateTime::parse_from_rfc3339("2015-10-21T07:27:30Z").map(|now| now.with_timezone(&Utc));
+        assert!(now.is_ok());
+        let Ok(now) = now else { return };
+
+        assert_eq!(parse_retry_after("5", now), Some(5));
+        assert_eq!(parse_retry_after(" 120 ", now), Some(120));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(30)
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(0)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+        assert_eq!(parse_retry_after("-1", now), None);
+    }
+
+    #[test]
+    fn test_build_notifications_url() {
+        assert_eq!(
+            build_notifications_url("https://example.com/"),
+            "https://example.com/api/v1/notifications"
+        );
+    }
+
     #[test]
     fn test_build_events_url() {
         assert_eq!(
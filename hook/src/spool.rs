@@ -0,0 +1,443 @@
+//! Events held back while the server is rate limiting this key.
+//!
+//! When `POST /api/v1/events` answers `429 Too Many Requests`, the event is
+//! written to `~/.claude/claudiator/spool/` (`spool-<profile>/` for a named
+//! profile) instead of being dropped, and the server's `Retry-After` is kept
+//! in `spool.json` beside it. Until that time has passed, new events go
+//! straight to the spool without a request. The first successful send after
+//! it drains up to [`DRAIN_BATCH`] spooled events, oldest first, so a backlog
+//! catches up over the next few hook invocations without stalling any one.
+//!
+//! Each event is its own zstd-compressed file, written atomically, so
+//! concurrent hook processes spool without coordinating. Besides
+//! [`MAX_EVENTS`], the files' total size is capped by `spool_max_bytes`, so a
+//! long outage can't fill the disk; either way the oldest events go first. Draining takes a [`FileLock`] so two
+//! processes never send the same event. Like the circuit breaker, all I/O
+//! here is best-effort.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::circuit;
+use crate::error::SendError;
+use crate::file_lock::FileLock;
+use crate::payload::EventPayload;
+
+/// Most events kept; beyond this the oldest are discarded.
+pub const MAX_EVENTS: usize = 1000;
+
+/// zstd level spooled events are compressed at.
+const COMPRESSION_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// Most spooled events sent by one hook invocation.
+pub const DRAIN_BATCH: usize = 20;
+
+/// Wait applied when a `429` carries no usable `Retry-After`.
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 10;
+
+/// Longest wait honoured, so a bogus header can't hold events for days.
+pub const MAX_RETRY_AFTER_SECS: u64 = 3600;
+
+/// Persisted rate limit state.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SpoolState {
+    /// Unix timestamp (seconds) before which no request should be made.
+    retry_at: i64,
+}
+
+/// A spool directory and the rate limit state file beside it.
+#[derive(Debug, Clone)]
+pub struct Spool {
+    dir: PathBuf,
+    state_path: PathBuf,
+    /// Most bytes the event files may take up on disk.
+    max_bytes: u64,
+}
+
+/// What [`Spool::drain`] did.
+#[derive(Debug, Default)]
+pub struct Drained {
+    /// Request body sizes of the events the server accepted.
+    pub sent: Vec<usize>,
+    /// Events the server rejected outright (e.g. `422`). They are deleted:
+    /// sending them again would fail the same way.
+    pub rejected: usize,
+    /// The failure that stopped draining early, if any. The event it hit
+    /// stays spooled.
+    pub error: Option<SendError>,
+}
+
+impl Spool {
+    /// A spool in `dir` holding at most `max_bytes` of events, with its state
+    /// in `<dir>.json`.
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        let mut state_name = dir.file_name().unwrap_or_default().to_os_string();
+        state_name.push(".json");
+        let state_path = dir.with_file_name(state_name);
+        Self {
+            dir,
+            state_path,
+            max_bytes,
+        }
+    }
+
+    /// The spool for `profile` under `~/.claude/claudiator`.
+    pub fn default_for(profile: Option<&str>, max_bytes: u64) -> Option<Self> {
+        let name = profile.map_or_else(
+            || "spool".to_string(),
+            |name| format!("spool-{}", circuit::file_safe(name)),
+        );
+        dirs::home_dir().map(|home| {
+            Self::new(
+                home.join(".claude").join("claudiator").join(name),
+                max_bytes,
+            )
+        })
+    }
+
+    /// Returns `true` if the server asked us to hold off until after `now`
+    /// (Unix seconds).
+    pub fn is_rate_limited(&self, now: i64) -> bool {
+        fs::read_to_string(&self.state_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<SpoolState>(&content).ok())
+            .is_some_and(|state| now < state.retry_at)
+    }
+
+    /// Record a `429` received at `now`, asking to wait `retry_after`
+    /// seconds. Returns the wait actually applied.
+    pub fn record_rate_limit(&self, now: i64, retry_after: Option<u64>) -> u64 {
+        let secs = retry_after
+            .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+            .min(MAX_RETRY_AFTER_SECS);
+        let state = SpoolState {
+            retry_at: now.saturating_add(i64::try_from(secs).unwrap_or(i64::MAX)),
+        };
+        if let Ok(json) = serde_json::to_string(&state) {
+            let _ = atomic_file::write(&self.state_path, &json);
+        }
+        secs
+    }
+
+    /// Forget the rate limit after a send went through.
+    pub fn clear_rate_limit(&self) {
+        if self.state_path.exists() {
+            let _ = fs::remove_file(&self.state_path);
+        }
+    }
+
+    /// Add `payload` to the spool, discarding the oldest events if it is full.
+    pub fn push(&self, payload: &EventPayload) -> io::Result<()> {
+        let json = serde_json::to_vec(payload)?;
+        let compressed = zstd::encode_all(json.as_slice(), COMPRESSION_LEVEL)?;
+        // Millisecond prefix keeps file names in arrival order
+        let name = format!(
+            "{:013}-{}.json.zst",
+            chrono::Utc::now().timestamp_millis(),
+            uuid::Uuid::new_v4().simple()
+        );
+        atomic_file::write(&self.dir.join(name), compressed)?;
+        self.evict();
+        Ok(())
+    }
+
+    /// Delete the oldest events until at most [`MAX_EVENTS`] remain and
+    /// they fit in `max_bytes`.
+    fn evict(&self) {
+        let mut kept = 0;
+        let mut total: u64 = 0;
+        let mut full = false;
+        for path in self.entries().iter().rev() {
+            let size = fs::metadata(path).map_or(0, |meta| meta.len());
+            full = full || kept == MAX_EVENTS || total.saturating_add(size) > self.max_bytes;
+            if full {
+                let _ = fs::remove_file(path);
+            } else {
+                kept += 1;
+                total += size;
+            }
+        }
+    }
+
+    /// Number of events waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Whether no events are waiting.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send up to [`DRAIN_BATCH`] spooled events with `send`, oldest first,
+    /// deleting each one the server takes. Stops at the first failure that
+    /// a later attempt could get past: a rate limit, a network error, or a
+    /// `5xx`.
+    ///
+    /// Does nothing if another process is already draining.
+    pub fn drain(
+        &self,
+        mut send: impl FnMut(&EventPayload) -> Result<usize, SendError>,
+    ) -> Drained {
+        let mut drained = Drained::default();
+        let Some(_lock) = FileLock::try_acquire(&self.dir) else {
+            return drained;
+        };
+
+        for path in self.entries().into_iter().take(DRAIN_BATCH) {
+            let Some(payload) = read_payload(&path) else {
+                let _ = fs::remove_file(&path);
+                continue;
+            };
+            match send(&payload) {
+                Ok(bytes) => {
+                    let _ = fs::remove_file(&path);
+                    drained.sent.push(bytes);
+                }
+                Err(e) if matches!(e, SendError::RateLimited(_)) || e.is_server_unavailable() => {
+                    drained.error = Some(e);
+                    break;
+                }
+                Err(_) => {
+                    let _ = fs::remove_file(&path);
+                    drained.rejected += 1;
+                }
+            }
+        }
+        drained
+    }
+
+    /// Spooled event files, oldest first: compressed `.json.zst` ones and any
+    /// plain `.json` left by older versions. In-flight temp files are skipped.
+    fn entries(&self) -> Vec<PathBuf> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut entries: Vec<PathBuf> = dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "zst" || ext == "json")
+            })
+            .collect();
+        entries.sort();
+        entries
+    }
+}
+
+fn read_payload(path: &Path) -> Option<EventPayload> {
+    let content = fs::read(path).ok()?;
+    let json = if path.extension().is_some_and(|ext| ext == "zst") {
+        zstd::decode_all(content.as_slice()).ok()?
+    } else {
+        content
+    };
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::event::{HookEvent, HookEventKind};
+    use crate::payload::DeviceInfo;
+    use tempfile::TempDir;
+
+    fn spool(dir: &TempDir) -> Spool {
+        Spool::new(dir.path().join("spool"), 1_048_576)
+    }
+
+    fn payload(session_id: &str) -> EventPayload {
+        let device = DeviceInfo {
+            device_id: "dev-1".to_string(),
+            device_name: "laptop".to_string(),
+            platform: "linux".to_string(),
+        };
+        EventPayload::new(
+            device,
+            HookEvent {
+                session_id: session_id.to_string(),
+                hook_event_name: HookEventKind::Stop,
+                cwd: None,
+                prompt: None,
+                notification_type: None,
+                tool_name: None,
+                message: None,
+                priority: None,
+                agent_id: None,
+                agent_type: None,
+                team_name: None,
+                task_id: None,
+                task_subject: None,
+                model: None,
+                permission_suggestions: None,
+                title: None,
+                severity: None,
+                trigger: None,
+                custom_instructions: None,
+                error: None,
+                is_interrupt: None,
+                env: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_state_sits_beside_the_directory() {
+        let spool = Spool::new(PathBuf::from("/tmp/x/spool-work"), 1_048_576);
+        assert_eq!(spool.state_path, PathBuf::from("/tmp/x/spool-work.json"));
+    }
+
+    #[test]
+    fn test_rate_limit_expires() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        assert!(!spool.is_rate_limited(1000));
+
+        assert_eq!(spool.record_rate_limit(1000, Some(5)), 5);
+        assert!(spool.is_rate_limited(1004));
+        assert!(!spool.is_rate_limited(1005));
+
+        assert_eq!(
+            spool.record_rate_limit(1000, None),
+            DEFAULT_RETRY_AFTER_SECS
+        );
+        assert_eq!(
+            spool.record_rate_limit(1000, Some(u64::MAX)),
+            MAX_RETRY_AFTER_SECS
+        );
+
+        spool.clear_rate_limit();
+        assert!(!spool.is_rate_limited(1000));
+    }
+
+    #[test]
+    fn test_drain_sends_oldest_first_and_stops_on_rate_limit() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        for id in ["a", "b", "c"] {
+            spool.push(&payload(id)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert_eq!(spool.len(), 3);
+
+        let mut seen = Vec::new();
+        let drained = spool.drain(|payload| {
+            seen.push(payload.event.session_id.clone());
+            if seen.len() == 2 {
+                Err(SendError::RateLimited(Some(1)))
+            } else {
+                Ok(10)
+            }
+        });
+        assert_eq!(seen, ["a", "b"]);
+        assert_eq!(drained.sent, [10]);
+        assert!(matches!(drained.error, Some(SendError::RateLimited(_))));
+        assert_eq!(spool.len(), 2);
+
+        // Rejected events are dropped, not retried forever
+        let drained = spool.drain(|_| Err(SendError::ServerError(422, String::new())));
+        assert_eq!(drained.rejected, 2);
+        assert!(drained.error.is_none());
+        assert!(spool.is_empty());
+    }
+
+    #[test]
+    fn test_drain_keeps_events_while_server_is_down() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        spool.push(&payload("a")).unwrap();
+
+        let drained = spool.drain(|_| Err(SendError::ServerError(503, String::new())));
+        assert!(drained.sent.is_empty());
+        assert!(drained.error.is_some());
+        assert_eq!(spool.len(), 1);
+    }
+
+    #[test]
+    fn test_drain_is_skipped_while_another_process_drains() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        spool.push(&payload("a")).unwrap();
+
+        let _held = FileLock::try_acquire(&spool.dir).unwrap();
+        let drained = spool.drain(|_| Ok(1));
+        assert!(drained.sent.is_empty());
+        assert_eq!(spool.len(), 1);
+    }
+
+    #[test]
+    fn test_events_are_stored_compressed() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        let mut event = payload("a");
+        event.event.message = Some("x".repeat(4096));
+        spool.push(&event).unwrap();
+
+        let path = &spool.entries()[0];
+        assert!(path.to_string_lossy().ends_with(".json.zst"));
+        assert!(fs::metadata(path).unwrap().len() < 1024);
+        let read = read_payload(path).unwrap();
+        assert_eq!(read.event.message, event.event.message);
+    }
+
+    #[test]
+    fn test_uncompressed_events_from_older_versions_still_drain() {
+        let dir = TempDir::new().unwrap();
+        let spool = spool(&dir);
+        let json = serde_json::to_string(&payload("old")).unwrap();
+        atomic_file::write(&spool.dir.join("0000000000001-legacy.json"), json).unwrap();
+        spool.push(&payload("new")).unwrap();
+
+        let mut seen = Vec::new();
+        spool.drain(|payload| {
+            seen.push(payload.event.session_id.clone());
+            Ok(1)
+        });
+        assert_eq!(seen, ["old", "new"]);
+    }
+
+    #[test]
+    fn test_push_evicts_oldest_past_byte_cap() {
+        let dir = TempDir::new().unwrap();
+        // Random hex barely compresses, so every event takes about as much
+        // room on disk
+        let event = |id: &str| {
+            let mut event = payload(id);
+            event.event.message = Some(
+                (0..64)
+                    .map(|_| uuid::Uuid::new_v4().simple().to_string())
+                    .collect(),
+            );
+            event
+        };
+        let probe = spool(&dir);
+        probe.push(&event("probe")).unwrap();
+        let size = fs::metadata(&probe.entries()[0]).unwrap().len();
+        fs::remove_dir_all(&probe.dir).unwrap();
+
+        let max_bytes = size * 3 + size / 2;
+        let spool = Spool::new(dir.path().join("spool"), max_bytes);
+        for id in ["a", "b", "c", "d", "e"] {
+            spool.push(&event(id)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let total: u64 = spool
+            .entries()
+            .iter()
+            .map(|path| fs::metadata(path).unwrap().len())
+            .sum();
+        assert!(total <= max_bytes);
+        let mut kept = Vec::new();
+        spool.drain(|payload| {
+            kept.push(payload.event.session_id.clone());
+            Ok(1)
+        });
+        assert_eq!(kept, ["c", "d", "e"]);
+    }
+}
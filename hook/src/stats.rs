@@ -0,0 +1,185 @@
+//! Running counters persisted to `~/.claude/claudiator/stats.json`.
+//!
+//! Updated after every `send` so users can confirm the hook is working with
+//! `claudiator-hook stats` instead of digging through the log file. Updates
+//! are read-modify-write with an atomic replace: concurrent hook processes may
+//! occasionally drop an increment, but the file is never left corrupt.
+//!
+//! Like the rest of the `send` path, failures here are silently ignored.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+
+/// Cumulative hook counters.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    /// Events the server accepted.
+    pub events_sent: u64,
+    /// Events that failed to send (network or server error).
+    pub events_failed: u64,
+    /// Events dropped without a network attempt because the circuit breaker was open.
+    pub events_skipped: u64,
+    /// Events dropped as repeats of a `Notification` sent moments earlier.
+    pub events_deduplicated: u64,
+    /// Events dropped by a `suppress` policy rule.
+    pub events_suppressed: u64,
+    /// Events held in the spool because the server was rate limiting.
+    pub events_spooled: u64,
+    /// Total request body bytes of successfully sent events.
+    pub bytes_sent: u64,
+    /// RFC 3339 timestamp of the last successful send.
+    pub last_success: Option<String>,
+    /// RFC 3339 timestamp of the last failed send.
+    pub last_failure: Option<String>,
+}
+
+/// Outcome of a single `send` invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Sent successfully with a body of the given size in bytes.
+    Sent(u64),
+    /// The send was attempted and failed.
+    Failed,
+    /// The send was skipped by the circuit breaker.
+    Skipped,
+    /// The event was dropped as a duplicate.
+    Deduplicated,
+    /// The event was dropped by a policy rule.
+    Suppressed,
+    /// The event was spooled to send once a rate limit lifts.
+    Spooled,
+}
+
+/// Default location of the stats file.
+pub fn default_stats_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".claude").join("claudiator").join("stats.json"))
+}
+
+/// Read stats from `path`. A missing or corrupt file yields zeroed counters.
+pub fn load(path: &Path) -> Stats {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `outcome` to the stats stored at `path`, stamped with `now` (RFC 3339).
+pub fn record(path: &Path, outcome: Outcome, now: &str) {
+    let mut stats = load(path);
+    match outcome {
+        Outcome::Sent(bytes) => {
+            stats.events_sent = stats.events_sent.saturating_add(1);
+            stats.bytes_sent = stats.bytes_sent.saturating_add(bytes);
+            stats.last_success = Some(now.to_string());
+        }
+        Outcome::Failed => {
+            stats.events_failed = stats.events_failed.saturating_add(1);
+            stats.last_failure = Some(now.to_string());
+        }
+        Outcome::Skipped => {
+            stats.events_skipped = stats.events_skipped.saturating_add(1);
+        }
+        Outcome::Deduplicated => {
+            stats.events_deduplicated = stats.events_deduplicated.saturating_add(1);
+        }
+        Outcome::Suppressed => {
+            stats.events_suppressed = stats.events_suppressed.saturating_add(1);
+        }
+        Outcome::Spooled => {
+            stats.events_spooled = stats.events_spooled.saturating_add(1);
+        }
+    }
+
+    if let Ok(json) = serde_json::to_string_pretty(&stats) {
+        let _ = atomic_file::write(path, &json);
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Events sent:    {}", self.events_sent)?;
+        writeln!(f, "Events failed:  {}", self.events_failed)?;
+        writeln!(f, "Events skipped: {}", self.events_skipped)?;
+        writeln!(f, "Events deduped: {}", self.events_deduplicated)?;
+        writeln!(f, "Events muted:   {}", self.events_suppressed)?;
+        writeln!(f, "Events spooled: {}", self.events_spooled)?;
+        writeln!(f, "Bytes sent:     {}", self.bytes_sent)?;
+        writeln!(
+            f,
+            "Last success:   {}",
+            self.last_success.as_deref().unwrap_or("never")
+        )?;
+        write!(
+            f,
+            "Last failure:   {}",
+            self.last_failure.as_deref().unwrap_or("never")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_file_is_zeroed() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        assert_eq!(load(&dir.path().join("stats.json")), Stats::default());
+    }
+
+    #[test]
+    fn test_record_accumulates() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = dir.path().join("stats.json");
+
+        record(&path, Outcome::Sent(100), "2026-01-01T00:00:00Z");
+        record(&path, Outcome::Sent(50), "2026-01-01T00:01:00Z");
+        record(&path, Outcome::Failed, "2026-01-01T00:02:00Z");
+        record(&path, Outcome::Skipped, "2026-01-01T00:03:00Z");
+        record(&path, Outcome::Deduplicated, "2026-01-01T00:04:00Z");
+        record(&path, Outcome::Suppressed, "2026-01-01T00:05:00Z");
+        record(&path, Outcome::Spooled, "2026-01-01T00:06:00Z");
+
+        let stats = load(&path);
+        assert_eq!(stats.events_sent, 2);
+        assert_eq!(stats.bytes_sent, 150);
+        assert_eq!(stats.events_failed, 1);
+        assert_eq!(stats.events_skipped, 1);
+        assert_eq!(stats.events_deduplicated, 1);
+        assert_eq!(stats.events_suppressed, 1);
+        assert_eq!(stats.events_spooled, 1);
+        assert_eq!(stats.last_success.as_deref(), Some("2026-01-01T00:01:00Z"));
+        assert_eq!(stats.last_failure.as_deref(), Some("2026-01-01T00:02:00Z"));
+    }
+
+    #[test]
+    fn test_partial_file_fills_defaults() {
+        let dir = TempDir::new();
+        assert!(dir.is_ok());
+        let Ok(dir) = dir else { return };
+        let path = dir.path().join("stats.json");
+
+        assert!(fs::write(&path, r#"{"events_sent": 7}"#).is_ok());
+        let stats = load(&path);
+        assert_eq!(stats.events_sent, 7);
+        assert_eq!(stats.events_failed, 0);
+        assert!(stats.last_success.is_none());
+    }
+
+    #[test]
+    fn test_display_shows_never_when_unset() {
+        let output = Stats::default().to_string();
+        assert!(output.contains("Events sent:    0"));
+        assert!(output.contains("Last success:   never"));
+    }
+}
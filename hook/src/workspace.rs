@@ -0,0 +1,88 @@
+//! Detects file tools reaching outside the session's working directory.
+//!
+//! Backs the `outside_cwd` config rule. The check is lexical: `..` is
+//! resolved but symlinks are not followed, and tools that take no path
+//! (such as `Bash`) are never flagged.
+
+use std::path::{Component, Path, PathBuf};
+
+/// `tool_input` fields holding the path a file tool acts on.
+const PATH_FIELDS: &[&str] = &["file_path", "notebook_path", "path"];
+
+/// The path a `PreToolUse` event's tool targets, when it lies outside the
+/// event's `cwd`. `raw` is the event JSON as read from stdin.
+pub fn outside_cwd(raw: &serde_json::Value) -> Option<PathBuf> {
+    let cwd = Path::new(raw.get("cwd")?.as_str()?);
+    let input = raw.get("tool_input")?;
+    let target = PATH_FIELDS
+        .iter()
+        .find_map(|field| input.get(field)?.as_str())?;
+
+    let target = normalize(&cwd.join(target));
+    (!target.starts_with(normalize(cwd))).then_some(target)
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(tool_input: &serde_json::Value) -> serde_json::Value {
+        json!({
+            "session_id": "s1",
+            "hook_event_name": "PreToolUse",
+            "cwd": "/home/me/repo",
+            "tool_name": "Edit",
+            "tool_input": tool_input,
+        })
+    }
+
+    #[test]
+    fn test_paths_inside_cwd_are_not_flagged() {
+        for input in [
+            json!({"file_path": "/home/me/repo/src/main.rs"}),
+            json!({"file_path": "src/main.rs"}),
+            json!({"path": "./src/../tests"}),
+            json!({"command": "cat /etc/passwd"}),
+        ] {
+            assert_eq!(outside_cwd(&event(&input)), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn test_paths_outside_cwd_are_flagged() {
+        assert_eq!(
+            outside_cwd(&event(&json!({"file_path": "/etc/hosts"}))),
+            Some(PathBuf::from("/etc/hosts"))
+        );
+        assert_eq!(
+            outside_cwd(&event(&json!({"notebook_path": "../other/nb.ipynb"}))),
+            Some(PathBuf::from("/home/me/other/nb.ipynb"))
+        );
+        // A sibling sharing the prefix is still outside
+        assert_eq!(
+            outside_cwd(&event(&json!({"path": "/home/me/repo-old"}))),
+            Some(PathBuf::from("/home/me/repo-old"))
+        );
+    }
+
+    #[test]
+    fn test_missing_cwd_is_not_flagged() {
+        let raw = json!({"tool_input": {"file_path": "/etc/hosts"}});
+        assert_eq!(outside_cwd(&raw), None);
+    }
+}
@@ -0,0 +1,132 @@
+//! Mock APNs endpoint: `POST /3/device/:token`.
+//!
+//! Answers pushes from a Claudiator server whose APNs host points here, so
+//! the push pipeline can be exercised without Apple credentials. Responses
+//! follow `--apns-script`, a list of status codes used in order and then
+//! repeated, e.g. `200,410,429` answers the first push 200, the second 410,
+//! the third 429, the fourth 200 again. Error bodies carry the same `reason`
+//! strings Apple sends.
+//!
+//! The server's APNs client speaks HTTP/2 with prior knowledge, which axum
+//! accepts on the same port as the regular HTTP/1 routes.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::Utc;
+use colored::Colorize;
+
+use crate::dashboard::Dashboard;
+
+pub struct MockApns {
+    script: Vec<StatusCode>,
+    next: AtomicUsize,
+    dashboard: Option<Arc<Dashboard>>,
+}
+
+impl MockApns {
+    pub fn new(script: Vec<StatusCode>, dashboard: Option<Arc<Dashboard>>) -> Self {
+        let script = if script.is_empty() {
+            vec![StatusCode::OK]
+        } else {
+            script
+        };
+        Self {
+            script,
+            next: AtomicUsize::new(0),
+            dashboard,
+        }
+    }
+
+    pub fn script(&self) -> String {
+        self.script
+            .iter()
+            .map(|status| status.as_u16().to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn next_status(&self) -> StatusCode {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.script.len();
+        self.script[index]
+    }
+}
+
+/// The `reason` APNs returns for a given status.
+const fn reason(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        400 => "BadDeviceToken",
+        403 => "ExpiredProviderToken",
+        410 => "Unregistered",
+        413 => "PayloadTooLarge",
+        429 => "TooManyRequests",
+        503 => "ServiceUnavailable",
+        _ => "InternalServerError",
+    }
+}
+
+pub async fn push_handler(
+    State(mock): State<Arc<MockApns>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Response {
+    let authorized = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("bearer "));
+    let status = if authorized {
+        mock.next_status()
+    } else {
+        StatusCode::FORBIDDEN
+    };
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-")
+            .to_string()
+    };
+    let title = body
+        .pointer("/aps/alert/title")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    let alert_body = body
+        .pointer("/aps/alert/body")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+
+    match &mock.dashboard {
+        Some(dashboard) => dashboard.record_push(&token, title, status),
+        None => {
+            let line = format!("[{}] APNS push -> {}", Utc::now().to_rfc3339(), status);
+            if status.is_success() {
+                println!("{}", line.magenta().bold());
+            } else {
+                println!("{}", line.red().bold());
+            }
+            println!("  {}: {}", "Token".cyan(), token);
+            println!("  {}: {}", "Topic".cyan(), header("apns-topic"));
+            println!("  {}: {}", "Collapse ID".cyan(), header("apns-collapse-id"));
+            println!("  {}: {}", "Title".cyan(), title);
+            println!("  {}: {}", "Body".cyan(), alert_body);
+            println!("{}", "---".dimmed());
+        }
+    }
+
+    let apns_id = [("apns-id", format!("mock-{}", Utc::now().timestamp_micros()))];
+    if status.is_success() {
+        return (status, apns_id).into_response();
+    }
+
+    let mut error = serde_json::json!({ "reason": reason(status) });
+    if status == StatusCode::GONE {
+        error["timestamp"] = Utc::now().timestamp_millis().into();
+    }
+    (status, apns_id, Json(error)).into_response()
+}
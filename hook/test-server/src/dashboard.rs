@@ -0,0 +1,232 @@
+//! In-memory model behind the `--tui` dashboard.
+//!
+//! The events handler records into a [`Dashboard`]; the TUI thread takes a
+//! [`Snapshot`] on each frame. Only the most recent [`MAX_EVENTS`] events are
+//! kept. Devices and sessions are kept for the life of the process.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use claudiator_types::EventPayload;
+
+/// Number of recent events kept for the events table.
+pub const MAX_EVENTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowStatus {
+    Accepted,
+    Rejected,
+    Unrecognised,
+    /// A push delivered to the mock APNs endpoint.
+    Push,
+}
+
+#[derive(Debug, Clone)]
+pub struct EventRow {
+    pub received_at: DateTime<Local>,
+    pub device: String,
+    pub session: String,
+    pub event: String,
+    pub status: RowStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceRow {
+    pub device_id: String,
+    pub device_name: String,
+    pub platform: String,
+    pub last_seen: DateTime<Local>,
+    pub events: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionRow {
+    pub session_id: String,
+    pub device_name: String,
+    pub cwd: String,
+    pub last_event: String,
+    pub last_seen: DateTime<Local>,
+    pub events: u64,
+}
+
+/// A consistent copy of the dashboard for one frame. Every list is sorted
+/// newest first.
+#[derive(Debug, Clone, Default)]
+pub struct Snapshot {
+    pub devices: Vec<DeviceRow>,
+    pub sessions: Vec<SessionRow>,
+    pub events: Vec<EventRow>,
+}
+
+#[derive(Default)]
+struct Inner {
+    devices: HashMap<String, DeviceRow>,
+    sessions: HashMap<String, SessionRow>,
+    events: VecDeque<EventRow>,
+}
+
+#[derive(Default)]
+pub struct Dashboard {
+    inner: Mutex<Inner>,
+}
+
+impl Dashboard {
+    /// Record an accepted event.
+    pub fn record(&self, payload: &EventPayload) {
+        let now = Local::now();
+        let device = &payload.device;
+        let event = &payload.event;
+        let event_name = event.notification_type.as_ref().map_or_else(
+            || event.hook_event_name.to_string(),
+            |notif_type| format!("{} ({notif_type})", event.hook_event_name),
+        );
+        let detail = event
+            .prompt
+            .as_deref()
+            .or(event.message.as_deref())
+            .or(event.tool_name.as_deref())
+            .unwrap_or_default()
+            .to_string();
+
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+
+        let device_row = inner
+            .devices
+            .entry(device.device_id.clone())
+            .or_insert_with(|| DeviceRow {
+                device_id: device.device_id.clone(),
+                device_name: String::new(),
+                platform: String::new(),
+                last_seen: now,
+                events: 0,
+            });
+        device_row.device_name.clone_from(&device.device_name);
+        device_row.platform.clone_from(&device.platform);
+        device_row.last_seen = now;
+        device_row.events += 1;
+
+        let session_row = inner
+            .sessions
+            .entry(event.session_id.clone())
+            .or_insert_with(|| SessionRow {
+                session_id: event.session_id.clone(),
+                device_name: String::new(),
+                cwd: String::new(),
+                last_event: String::new(),
+                last_seen: now,
+                events: 0,
+            });
+        session_row.device_name.clone_from(&device.device_name);
+        if let Some(cwd) = &event.cwd {
+            session_row.cwd.clone_from(cwd);
+        }
+        session_row.last_event.clone_from(&event_name);
+        session_row.last_seen = now;
+        session_row.events += 1;
+
+        push_event(
+            &mut inner,
+            EventRow {
+                received_at: now,
+                device: device.device_name.clone(),
+                session: event.session_id.clone(),
+                event: event_name,
+                status: RowStatus::Accepted,
+                detail,
+            },
+        );
+    }
+
+    /// Record a payload rejected by `--strict`.
+    pub fn record_rejected(&self, body: &serde_json::Value, errors: &[String]) {
+        self.record_raw(body, RowStatus::Rejected, errors.join("; "));
+    }
+
+    /// Record a payload that did not decode as an `EventPayload`.
+    pub fn record_unrecognised(&self, body: &serde_json::Value, error: &str) {
+        self.record_raw(body, RowStatus::Unrecognised, error.to_string());
+    }
+
+    /// Record a push received by the mock APNs endpoint.
+    pub fn record_push(&self, token: &str, title: &str, status: axum::http::StatusCode) {
+        let row = EventRow {
+            received_at: Local::now(),
+            device: "APNs".to_string(),
+            session: token.to_string(),
+            event: format!("Push -> {}", status.as_u16()),
+            status: RowStatus::Push,
+            detail: title.to_string(),
+        };
+        if let Ok(mut inner) = self.inner.lock() {
+            push_event(&mut inner, row);
+        }
+    }
+
+    fn record_raw(&self, body: &serde_json::Value, status: RowStatus, detail: String) {
+        let field = |pointer: &str| {
+            body.pointer(pointer)
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("?")
+                .to_string()
+        };
+        let row = EventRow {
+            received_at: Local::now(),
+            device: field("/device/device_name"),
+            session: field("/event/session_id"),
+            event: field("/event/hook_event_name"),
+            status,
+            detail,
+        };
+        if let Ok(mut inner) = self.inner.lock() {
+            push_event(&mut inner, row);
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let Ok(inner) = self.inner.lock() else {
+            return Snapshot::default();
+        };
+        let mut devices: Vec<_> = inner.devices.values().cloned().collect();
+        devices.sort_by_key(|d| std::cmp::Reverse(d.last_seen));
+        let mut sessions: Vec<_> = inner.sessions.values().cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen));
+        let events = inner.events.iter().rev().cloned().collect();
+        Snapshot {
+            devices,
+            sessions,
+            events,
+        }
+    }
+}
+
+fn push_event(inner: &mut Inner, row: EventRow) {
+    if inner.events.len() == MAX_EVENTS {
+        inner.events.pop_front();
+    }
+    inner.events.push_back(row);
+}
+
+impl Snapshot {
+    /// Keep only sessions and events containing `filter` (case-insensitive)
+    /// in any column. Devices are never filtered.
+    pub fn filtered(mut self, filter: &str) -> Self {
+        if filter.is_empty() {
+            return self;
+        }
+        let needle = filter.to_lowercase();
+        let matches = |fields: &[&str]| {
+            fields
+                .iter()
+                .any(|field| field.to_lowercase().contains(&needle))
+        };
+        self.sessions
+            .retain(|s| matches(&[&s.session_id, &s.device_name, &s.cwd, &s.last_event]));
+        self.events
+            .retain(|e| matches(&[&e.device, &e.session, &e.event, &e.detail]));
+        self
+    }
+}
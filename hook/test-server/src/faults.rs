@@ -0,0 +1,153 @@
+//! Fault injection for exercising hook error handling.
+//!
+//! Applied as middleware to every route. Failures are spread evenly rather
+//! than drawn at random, so `--fail-rate 0.2` fails exactly every fifth
+//! request and a test run is reproducible.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use colored::Colorize;
+
+pub struct Faults {
+    /// Fraction of requests (0.0–1.0) answered with `status`.
+    pub fail_rate: f64,
+    /// Status code returned for injected failures.
+    pub status: StatusCode,
+    /// Delay added before every response.
+    pub latency: Duration,
+    /// Reject every request with 401 regardless of the API key.
+    pub drop_auth: bool,
+    /// Don't print injected faults (the TUI owns the terminal).
+    quiet: bool,
+    requests: AtomicU64,
+}
+
+impl Faults {
+    pub const fn new(
+        fail_rate: f64,
+        status: StatusCode,
+        latency: Duration,
+        drop_auth: bool,
+        quiet: bool,
+    ) -> Self {
+        Self {
+            fail_rate,
+            status,
+            latency,
+            drop_auth,
+            quiet,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.fail_rate > 0.0 || !self.latency.is_zero() || self.drop_auth
+    }
+
+    /// Whether the next request should fail. Request `n` fails when
+    /// `floor((n + 1) * rate)` steps past `floor(n * rate)`.
+    fn next_fails(&self) -> bool {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.fail_rate).floor() > (n * self.fail_rate).floor()
+    }
+}
+
+pub async fn middleware(
+    State(faults): State<Arc<Faults>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !faults.latency.is_zero() {
+        tokio::time::sleep(faults.latency).await;
+    }
+
+    if faults.drop_auth {
+        if !faults.quiet {
+            println!("{}", "[fault] rejecting auth".yellow());
+        }
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "unauthorized",
+                "message": "Invalid or missing API key"
+            })),
+        )
+            .into_response();
+    }
+
+    if faults.next_fails() {
+        if !faults.quiet {
+            println!(
+                "{}",
+                format!(
+                    "[fault] {} {} -> {}",
+                    request.method(),
+                    request.uri(),
+                    faults.status
+                )
+                .yellow()
+            );
+        }
+        let mut response = (
+            faults.status,
+            Json(serde_json::json!({
+                "error": "injected_fault",
+                "message": "Failure injected by test-server"
+            })),
+        )
+            .into_response();
+        if faults.status == StatusCode::TOO_MANY_REQUESTS {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        return response;
+    }
+
+    next.run(request).await
+}
+
+/// Parse a duration such as `500ms`, `2s`, or a bare number of milliseconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit_ms) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 1)
+    } else if let Some(s) = value.strip_suffix('s') {
+        (s, 1000)
+    } else {
+        (value, 1)
+    };
+    let n: u64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid duration '{value}' (expected e.g. 500ms or 2s)"))?;
+    Ok(Duration::from_millis(n * unit_ms))
+}
+
+/// Parse a failure rate in `0.0..=1.0`.
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    let rate: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid rate '{value}'"))?;
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("rate must be between 0 and 1, got {rate}"))
+    }
+}
+
+/// Parse an HTTP status code.
+pub fn parse_status(value: &str) -> Result<StatusCode, String> {
+    value
+        .parse::<u16>()
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| format!("invalid HTTP status '{value}'"))
+}
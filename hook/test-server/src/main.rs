@@ -6,9 +6,24 @@ use axum::{
 };
 use chrono::Utc;
 use clap::Parser;
+use claudiator_types::EventPayload;
 use colored::Colorize;
+use std::path::PathBuf;
 use std::sync::Arc;
 
+mod apns;
+mod dashboard;
+mod faults;
+mod record;
+mod replay;
+mod strict;
+mod tui;
+
+use apns::MockApns;
+use dashboard::Dashboard;
+use faults::Faults;
+use record::Recorder;
+
 #[derive(Parser)]
 #[command(name = "test-server", about = "Claudiator test server")]
 struct Args {
@@ -16,10 +31,49 @@ struct Args {
     port: u16,
     #[arg(long, default_value = "test-key")]
     api_key: String,
+    /// Append every received payload to this NDJSON file
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+    /// POST a recorded NDJSON file to --target instead of serving
+    #[arg(long, value_name = "PATH", conflicts_with = "record")]
+    replay: Option<PathBuf>,
+    /// Server base URL used by --replay
+    #[arg(long, default_value = "http://localhost:3000")]
+    target: String,
+    /// Fraction of requests (0.0-1.0) to fail with --status
+    #[arg(long, value_name = "RATE", value_parser = faults::parse_rate)]
+    fail_rate: Option<f64>,
+    /// Status code for injected failures; fails every request if --fail-rate is unset
+    #[arg(long, value_name = "CODE", value_parser = faults::parse_status)]
+    status: Option<StatusCode>,
+    /// Delay before every response, e.g. 500ms or 2s
+    #[arg(long, value_name = "DURATION", value_parser = faults::parse_duration)]
+    latency: Option<std::time::Duration>,
+    /// Reject every request with 401 regardless of the API key
+    #[arg(long)]
+    drop_auth: bool,
+    /// Reject payloads that fail schema validation with a detailed 422
+    #[arg(long)]
+    strict: bool,
+    /// Status codes for mock APNs pushes, used in order and then repeated
+    #[arg(
+        long,
+        value_name = "CODES",
+        value_delimiter = ',',
+        default_value = "200",
+        value_parser = faults::parse_status
+    )]
+    apns_script: Vec<StatusCode>,
+    /// Show a live dashboard instead of printing each event
+    #[arg(long, conflicts_with = "replay")]
+    tui: bool,
 }
 
 struct AppState {
     api_key: String,
+    recorder: Option<Recorder>,
+    strict: bool,
+    dashboard: Option<Arc<Dashboard>>,
 }
 
 fn check_auth(
@@ -77,91 +131,226 @@ async fn events_handler(
             .into_response();
     }
 
-    // Extract and log event information
+    if let Some(recorder) = &state.recorder {
+        recorder.append(&body);
+    }
+
+    if state.strict {
+        let errors = strict::validate(&body);
+        if !errors.is_empty() {
+            match &state.dashboard {
+                Some(dashboard) => dashboard.record_rejected(&body, &errors),
+                None => print_rejected(&body, &errors),
+            }
+            return (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(serde_json::json!({
+                    "error": "bad_request",
+                    "message": errors.join("; "),
+                    "errors": errors,
+                })),
+            )
+                .into_response();
+        }
+    }
+
+    // Decode with the same wire types the hook and server use. The test
+    // server stays lenient: payloads that don't match are shown raw.
+    match serde_json::from_value::<EventPayload>(body.clone()) {
+        Ok(payload) => match &state.dashboard {
+            Some(dashboard) => dashboard.record(&payload),
+            None => print_event(&payload),
+        },
+        Err(e) => match &state.dashboard {
+            Some(dashboard) => dashboard.record_unrecognised(&body, &e.to_string()),
+            None => print_unrecognised(&body, &e),
+        },
+    }
+
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "status": "ok"
+        })),
+    )
+        .into_response()
+}
+
+fn print_received() {
     let timestamp = Utc::now().to_rfc3339();
     println!(
         "{}",
         format!("[{}] EVENT received", timestamp).green().bold()
     );
+}
+
+fn print_rejected(body: &serde_json::Value, errors: &[String]) {
+    print_received();
+    println!("  {}", "Rejected by --strict:".red().bold());
+    for error in errors {
+        println!("    - {}", error);
+    }
+    println!("  {}", body);
+    println!("{}", "---".dimmed());
+}
+
+fn print_unrecognised(body: &serde_json::Value, error: &serde_json::Error) {
+    print_received();
+    println!("  {}: {}", "Unrecognised payload".yellow(), error);
+    println!("  {}", body);
+    println!("{}", "---".dimmed());
+}
+
+fn print_event(payload: &EventPayload) {
+    print_received();
 
     // Device info
-    let device_name = body["device"]["device_name"].as_str().unwrap_or("unknown");
-    let platform = body["device"]["platform"].as_str().unwrap_or("unknown");
-    let device_id = body["device"]["device_id"].as_str().unwrap_or("unknown");
-    let device_id_short = if device_id.len() > 8 {
-        format!("{}...", &device_id[..8])
+    let device = &payload.device;
+    let device_id_short = if device.device_id.len() > 8 {
+        format!("{}...", &device.device_id[..8])
     } else {
-        device_id.to_string()
+        device.device_id.clone()
     };
 
     println!(
         "  {}: {} ({}) [{}]",
         "Device".cyan(),
-        device_name,
-        platform,
+        device.device_name,
+        device.platform,
         device_id_short
     );
 
     // Session and event info
-    let session_id = body["event"]["session_id"].as_str().unwrap_or("unknown");
-    println!("  {}: {}", "Session".cyan(), session_id);
-
-    let hook_event_name = body["event"]["hook_event_name"]
-        .as_str()
-        .unwrap_or("unknown");
-    let notification_type = body["event"]["notification_type"].as_str();
-    let event_display = if let Some(notif_type) = notification_type {
-        format!("{} ({})", hook_event_name, notif_type)
+    let event = &payload.event;
+    println!("  {}: {}", "Session".cyan(), event.session_id);
+
+    let event_display = if let Some(notif_type) = &event.notification_type {
+        format!("{} ({})", event.hook_event_name, notif_type)
     } else {
-        hook_event_name.to_string()
+        event.hook_event_name.to_string()
     };
     println!("  {}: {}", "Event".cyan(), event_display);
 
     // Optional fields
-    if let Some(cwd) = body["event"]["cwd"].as_str() {
+    if let Some(cwd) = &event.cwd {
         println!("  {}: {}", "CWD".cyan(), cwd);
     }
 
-    if let Some(message) = body["event"]["message"].as_str() {
+    if let Some(message) = &event.message {
         println!("  {}: {}", "Message".cyan(), message);
     }
 
-    if let Some(prompt) = body["event"]["prompt"].as_str() {
+    if let Some(prompt) = &event.prompt {
         println!("  {}: {}", "Prompt".cyan(), prompt);
     }
 
     println!("{}", "---".dimmed());
-
-    (
-        StatusCode::OK,
-        Json(serde_json::json!({
-            "status": "ok"
-        })),
-    )
-        .into_response()
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+
+    if let Some(path) = args.replay {
+        println!("Replaying {} against {}", path.display(), args.target);
+        let api_key = args.api_key.clone();
+        let target = args.target.clone();
+        let result = tokio::task::spawn_blocking(move || replay::run(&path, &target, &api_key))
+            .await
+            .expect("Replay task panicked");
+        match result {
+            Ok(summary) => {
+                println!(
+                    "\nReplayed {} events: {} accepted, {} failed",
+                    summary.accepted + summary.failed,
+                    summary.accepted,
+                    summary.failed
+                );
+                if summary.failed > 0 {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let recorder = args.record.as_deref().map(|path| {
+        println!("Recording events to {}", path.display());
+        Recorder::open(path).expect("Failed to open record file")
+    });
+    let dashboard = args.tui.then(|| Arc::new(Dashboard::default()));
     let state = Arc::new(AppState {
         api_key: args.api_key.clone(),
+        recorder,
+        strict: args.strict,
+        dashboard: dashboard.clone(),
     });
 
+    let faults = Arc::new(Faults::new(
+        args.fail_rate
+            .unwrap_or(if args.status.is_some() { 1.0 } else { 0.0 }),
+        args.status.unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+        args.latency.unwrap_or_default(),
+        args.drop_auth,
+        args.tui,
+    ));
+
+    let mock_apns = Arc::new(MockApns::new(args.apns_script, dashboard.clone()));
+    let apns_script = mock_apns.script();
+    let apns_routes = axum::Router::new()
+        .route("/3/device/:token", axum::routing::post(apns::push_handler))
+        .with_state(mock_apns);
+
     let app = axum::Router::new()
         .route("/api/v1/ping", axum::routing::get(ping_handler))
         .route("/api/v1/events", axum::routing::post(events_handler))
-        .with_state(state);
+        .with_state(state)
+        .merge(apns_routes)
+        .layer(axum::middleware::from_fn_with_state(
+            faults.clone(),
+            faults::middleware,
+        ));
 
     println!(
         "Claudiator test server running on http://0.0.0.0:{}",
         args.port
     );
     println!("API key: {}", args.api_key);
-    println!("Waiting for events...\n");
+    println!("Mock APNs: POST /3/device/:token (script: {})", apns_script);
+    if args.strict {
+        println!("Strict schema validation enabled");
+    }
+    if faults.is_active() {
+        println!(
+            "{}",
+            format!(
+                "Faults: fail-rate {} (status {}), latency {:?}, drop-auth {}",
+                faults.fail_rate, faults.status, faults.latency, faults.drop_auth
+            )
+            .yellow()
+        );
+    }
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port))
         .await
         .expect("Failed to bind port");
-    axum::serve(listener, app).await.expect("Server error");
+
+    let Some(dashboard) = dashboard else {
+        println!("Waiting for events...\n");
+        axum::serve(listener, app).await.expect("Server error");
+        return;
+    };
+
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.expect("Server error");
+    });
+    let title = format!("Claudiator test server · :{}", args.port);
+    tokio::task::spawn_blocking(move || tui::run(dashboard, title))
+        .await
+        .expect("TUI task panicked")
+        .expect("Terminal error");
 }
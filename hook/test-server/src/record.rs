@@ -0,0 +1,34 @@
+//! `--record`: append every received payload to an NDJSON file.
+//!
+//! One JSON document per line, exactly as the hook sent it, so the file can be
+//! fed back to a real server with `--replay`.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    /// Open `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append `body` as a single line. Errors are reported but never fail the request.
+    pub fn append(&self, body: &serde_json::Value) {
+        let line = format!("{body}\n");
+        let Ok(mut file) = self.file.lock() else {
+            return;
+        };
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            eprintln!("Failed to record event: {e}");
+        }
+    }
+}
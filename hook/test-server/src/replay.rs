@@ -0,0 +1,65 @@
+//! `--replay`: POST a recorded NDJSON file to a server, one line at a time.
+//!
+//! Lines are sent verbatim, in order, with the configured API key. Blank lines
+//! are skipped; lines that are not valid JSON are reported and counted as
+//! failures without being sent.
+
+use std::fs;
+use std::path::Path;
+
+use colored::Colorize;
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub accepted: usize,
+    pub failed: usize,
+}
+
+/// Replay every payload in `path` against `target` (a server base URL).
+pub fn run(path: &Path, target: &str, api_key: &str) -> Result<Summary, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let url = format!("{}/api/v1/events", target.trim_end_matches('/'));
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(10))
+        .build();
+
+    let mut summary = Summary::default();
+    for (index, line) in content.lines().enumerate() {
+        let line_no = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Err(e) = serde_json::from_str::<serde_json::Value>(line) {
+            println!("  {} line {line_no}: invalid JSON: {e}", "SKIP".yellow());
+            summary.failed += 1;
+            continue;
+        }
+
+        let response = agent
+            .post(&url)
+            .set("Content-Type", "application/json")
+            .set("Authorization", &format!("Bearer {api_key}"))
+            .send_string(line);
+
+        match response {
+            Ok(resp) => {
+                println!("  {} line {line_no}: {}", "OK".green(), resp.status());
+                summary.accepted += 1;
+            }
+            Err(ureq::Error::Status(code, resp)) => {
+                let body = resp.into_string().unwrap_or_default();
+                println!("  {} line {line_no}: {code} {body}", "FAIL".red());
+                summary.failed += 1;
+            }
+            Err(e) => {
+                println!("  {} line {line_no}: {e}", "FAIL".red());
+                summary.failed += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
@@ -0,0 +1,48 @@
+//! `--strict`: validate payloads the way the real server does, and then some.
+//!
+//! The rules mirror `validate_event_payload` in the server's events handler
+//! (non-empty ids, RFC 3339 timestamp) and additionally reject event names
+//! that `HookEventKind` does not know. The server accepts those, but a hook
+//! sending one usually means a typo or an unmapped new Claude Code event.
+
+use claudiator_types::{EventPayload, HookEventKind};
+
+/// Return every problem with `body`, or an empty list if it is valid.
+pub fn validate(body: &serde_json::Value) -> Vec<String> {
+    let payload = match serde_json::from_value::<EventPayload>(body.clone()) {
+        Ok(payload) => payload,
+        Err(e) => return vec![format!("payload does not match schema: {e}")],
+    };
+
+    let mut errors = Vec::new();
+    if payload.device.device_id.is_empty() {
+        errors.push("device.device_id is required".to_string());
+    }
+    if payload.device.device_name.is_empty() {
+        errors.push("device.device_name is required".to_string());
+    }
+    if payload.device.platform.is_empty() {
+        errors.push("device.platform is required".to_string());
+    }
+    if payload.event.session_id.is_empty() {
+        errors.push("event.session_id is required".to_string());
+    }
+    match &payload.event.hook_event_name {
+        HookEventKind::Other(name) if name.is_empty() => {
+            errors.push("event.hook_event_name is required".to_string());
+        }
+        HookEventKind::Other(name) => {
+            errors.push(format!(
+                "event.hook_event_name '{name}' is not a known event"
+            ));
+        }
+        _ => {}
+    }
+    if chrono::DateTime::parse_from_rfc3339(&payload.timestamp).is_err() {
+        errors.push(format!(
+            "timestamp '{}' is not valid RFC 3339",
+            payload.timestamp
+        ));
+    }
+    errors
+}
@@ -0,0 +1,248 @@
+//! `--tui`: live terminal dashboard of devices, sessions, and recent events.
+//!
+//! Runs on a blocking thread alongside the server. Keys:
+//! `q` quit, `p`/space pause, `↑`/`↓`/`j`/`k` scroll events, `g` jump to
+//! newest, `/` edit the filter (`Enter` keeps it, `Esc` clears it).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::dashboard::{Dashboard, RowStatus, Snapshot};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct App {
+    dashboard: Arc<Dashboard>,
+    title: String,
+    snapshot: Snapshot,
+    paused: bool,
+    filter: String,
+    editing_filter: bool,
+    events: TableState,
+}
+
+impl App {
+    fn refresh(&mut self) {
+        if !self.paused {
+            self.snapshot = self.dashboard.snapshot();
+        }
+    }
+
+    /// Returns `false` when the user asked to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.editing_filter {
+            match code {
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.editing_filter = false;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.events.select(Some(0));
+            return true;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('p' | ' ') => self.paused = !self.paused,
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Char('g') | KeyCode::Home => self.events.select(Some(0)),
+            KeyCode::Down | KeyCode::Char('j') => self.events.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.events.select_previous(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let view = self.snapshot.clone().filtered(&self.filter);
+        let [header, top, events, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(35),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [devices, sessions] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(top);
+
+        self.draw_header(frame, header, view.events.len());
+        draw_devices(frame, devices, &view);
+        draw_sessions(frame, sessions, &view);
+        self.draw_events(frame, events, &view);
+        self.draw_footer(frame, footer);
+    }
+
+    fn draw_header(&self, frame: &mut Frame, area: Rect, shown: usize) {
+        let mut line = Line::from(vec![self.title.clone().bold(), "  ".into()]);
+        line.push_span(format!("{shown} events").dim());
+        if self.paused {
+            line.push_span("  PAUSED".yellow().bold());
+        }
+        frame.render_widget(line, area);
+    }
+
+    fn draw_events(&mut self, frame: &mut Frame, area: Rect, view: &Snapshot) {
+        let rows = view.events.iter().map(|e| {
+            let style = match e.status {
+                RowStatus::Accepted => Style::default(),
+                RowStatus::Rejected => Style::default().fg(Color::Red),
+                RowStatus::Unrecognised => Style::default().fg(Color::Yellow),
+                RowStatus::Push => Style::default().fg(Color::Magenta),
+            };
+            Row::new(vec![
+                e.received_at.format("%H:%M:%S").to_string(),
+                e.device.clone(),
+                short_id(&e.session),
+                e.event.clone(),
+                e.detail.clone(),
+            ])
+            .style(style)
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(16),
+                Constraint::Length(10),
+                Constraint::Length(30),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header_row(["Time", "Device", "Session", "Event", "Detail"]))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(" Events (newest first) "));
+        frame.render_stateful_widget(table, area, &mut self.events);
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let line = if self.editing_filter {
+            Line::from(vec![
+                "Filter: ".bold(),
+                self.filter.clone().into(),
+                "█".into(),
+                "   Enter keep · Esc clear".dim(),
+            ])
+        } else {
+            let mut line = Line::from("q quit · p pause · ↑↓ scroll · g newest · / filter".dim());
+            if !self.filter.is_empty() {
+                line.push_span(format!("   filter: {}", self.filter).cyan());
+            }
+            line
+        };
+        frame.render_widget(line, area);
+    }
+}
+
+fn draw_devices(frame: &mut Frame, area: Rect, view: &Snapshot) {
+    let rows = view.devices.iter().map(|d| {
+        Row::new(vec![
+            d.device_name.clone(),
+            short_id(&d.device_id),
+            d.platform.clone(),
+            d.events.to_string(),
+            d.last_seen.format("%H:%M:%S").to_string(),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(8),
+        ],
+    )
+    .header(header_row(["Device", "ID", "Platform", "Events", "Seen"]))
+    .block(Block::bordered().title(" Devices "));
+    frame.render_widget(table, area);
+}
+
+fn draw_sessions(frame: &mut Frame, area: Rect, view: &Snapshot) {
+    let rows = view.sessions.iter().map(|s| {
+        Row::new(vec![
+            short_id(&s.session_id),
+            s.device_name.clone(),
+            s.last_event.clone(),
+            s.events.to_string(),
+            s.cwd.clone(),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Length(16),
+            Constraint::Length(24),
+            Constraint::Length(6),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(header_row([
+        "Session",
+        "Device",
+        "Last event",
+        "Events",
+        "CWD",
+    ]))
+    .block(Block::bordered().title(" Sessions "));
+    frame.render_widget(table, area);
+}
+
+fn header_row<const N: usize>(titles: [&'static str; N]) -> Row<'static> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+fn short_id(id: &str) -> String {
+    id.chars().take(8).collect()
+}
+
+/// Run the dashboard until the user quits. Restores the terminal on exit.
+pub fn run(dashboard: Arc<Dashboard>, title: String) -> std::io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, dashboard, title);
+    ratatui::restore();
+    result
+}
+
+fn run_loop(
+    terminal: &mut DefaultTerminal,
+    dashboard: Arc<Dashboard>,
+    title: String,
+) -> std::io::Result<()> {
+    let mut app = App {
+        dashboard,
+        title,
+        snapshot: Snapshot::default(),
+        paused: false,
+        filter: String::new(),
+        editing_filter: false,
+        events: TableState::default().with_selected(0),
+    };
+
+    loop {
+        app.refresh();
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if event::poll(POLL_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
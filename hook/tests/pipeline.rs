@@ -7,8 +7,9 @@
 //! are correctly built and that the final JSON body has the expected shape.
 
 use claudiator_hook::config::Config;
-use claudiator_hook::event::HookEvent;
-use claudiator_hook::payload::EventPayload;
+use claudiator_hook::event::{HookEvent, HookEventKind};
+use claudiator_hook::payload::build_payload;
+use claudiator_hook::policy::Policy;
 
 // ---------------------------------------------------------------------------
 // Helpers
@@ -19,12 +20,22 @@ fn make_config(server_url: &str) -> Config {
         server_url: server_url.to_string(),
         api_key: "test-api-key".to_string(),
         device_name: "test-machine".to_string(),
+        unique_device_name: false,
         device_id: "550e8400-e29b-41d4-a716-446655440000".to_string(),
         platform: "mac".to_string(),
         log_level: "error".to_string(),
         max_log_size_bytes: 1_048_576,
         max_log_backups: 2,
+        max_log_age_days: 0,
         raw_event_log_path: None,
+        circuit_breaker_threshold: 3,
+        circuit_breaker_cooldown_secs: 30,
+        spool_max_bytes: 10_485_760,
+        outside_cwd: None,
+        policy: Policy::default(),
+        e2e_key: None,
+        capture_env: Vec::new(),
+        profile: None,
     }
 }
 
@@ -48,12 +59,12 @@ fn test_pipeline_minimal_event_json_to_payload_to_body() {
     // Step 1 – parse from JSON (simulated stdin).
     let event = parse_event(raw);
     assert_eq!(event.session_id, "sess-abc");
-    assert_eq!(event.hook_event_name, "PreToolUse");
+    assert_eq!(event.hook_event_name, HookEventKind::PreToolUse);
     assert_eq!(event.tool_name.as_deref(), Some("Bash"));
 
     // Step 2 – build payload with device metadata.
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     assert_eq!(
         payload.device.device_id,
@@ -62,7 +73,7 @@ fn test_pipeline_minimal_event_json_to_payload_to_body() {
     assert_eq!(payload.device.device_name, "test-machine");
     assert_eq!(payload.device.platform, "mac");
     assert_eq!(payload.event.session_id, "sess-abc");
-    assert_eq!(payload.event.hook_event_name, "PreToolUse");
+    assert_eq!(payload.event.hook_event_name, HookEventKind::PreToolUse);
 
     // Step 3 – serialize to JSON (the HTTP request body).
     let body = serde_json::to_string(&payload).expect("payload must serialize to JSON");
@@ -109,7 +120,7 @@ fn test_pipeline_notification_event() {
 
     let event = parse_event(raw);
     let config = make_config("https://server.example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     let body = serde_json::to_string(&payload).expect("payload must serialize");
     let body_value: serde_json::Value = serde_json::from_str(&body).expect("must be valid JSON");
@@ -138,7 +149,7 @@ fn test_pipeline_unknown_fields_not_forwarded_to_server() {
     let event = parse_event(raw);
 
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
     let body = serde_json::to_string(&payload).expect("payload must serialize");
     let body_value: serde_json::Value = serde_json::from_str(&body).expect("must be valid JSON");
 
@@ -164,7 +175,7 @@ fn test_pipeline_stop_event() {
 
     let event = parse_event(raw);
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
     let body = serde_json::to_string(&payload).expect("payload must serialize");
     let body_value: serde_json::Value = serde_json::from_str(&body).expect("must be valid JSON");
 
@@ -185,7 +196,7 @@ fn test_pipeline_timestamp_is_rfc3339_with_millis() {
     let raw = r#"{"session_id": "sess-ts", "hook_event_name": "PreToolUse"}"#;
     let event = parse_event(raw);
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     // timestamp must be valid RFC 3339 with millisecond precision (contains a dot).
     let ts = &payload.timestamp;
@@ -228,7 +239,7 @@ fn test_payload_serializes_content_type_compatible_json() {
     let raw = r#"{"session_id": "sess-ct", "hook_event_name": "PostToolUse"}"#;
     let event = parse_event(raw);
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     let json_string = serde_json::to_string(&payload);
     assert!(json_string.is_ok(), "payload must serialize without error");
@@ -247,7 +258,7 @@ fn test_payload_omits_none_optional_fields() {
     let raw = r#"{"session_id": "sess-omit", "hook_event_name": "PreToolUse"}"#;
     let event = parse_event(raw);
     let config = make_config("https://example.com");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     let json_string = serde_json::to_string(&payload).expect("must serialize");
     let value: serde_json::Value = serde_json::from_str(&json_string).expect("must parse");
@@ -285,7 +296,7 @@ fn test_payload_events_url_no_trailing_slash() {
     let raw = r#"{"session_id": "sess-url", "hook_event_name": "Stop"}"#;
     let event = parse_event(raw);
     let config = make_config("https://my-server.example.com/");
-    let payload = EventPayload::new(&config, event);
+    let payload = build_payload(&config, event);
 
     // The payload itself carries the server_url indirectly through config;
     // here we simply confirm the pipeline completes without error.
@@ -42,14 +42,22 @@ if [[ -z "$CURRENT_VERSION" ]]; then
 fi
 echo "Current version: $CURRENT_VERSION"
 
-# Detect architecture
+# Detect architecture. Static musl builds are used on musl hosts (e.g.
+# Alpine), on 32-bit ARM, and when CLAUDIATOR_STATIC=1.
 ARCH=$(uname -m)
+LIBC="gnu"
+if [ "${CLAUDIATOR_STATIC:-}" = "1" ] || ldd --version 2>&1 | grep -qi musl; then
+    LIBC="musl"
+fi
 case "$ARCH" in
     x86_64)
-        TARGET="x86_64-unknown-linux-gnu"
+        TARGET="x86_64-unknown-linux-$LIBC"
         ;;
     aarch64)
-        TARGET="aarch64-unknown-linux-gnu"
+        TARGET="aarch64-unknown-linux-$LIBC"
+        ;;
+    armv7l)
+        TARGET="armv7-unknown-linux-musleabihf"
         ;;
     *)
         echo "Error: Unsupported architecture: $ARCH"
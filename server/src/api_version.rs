@@ -0,0 +1,172 @@
+//! API version negotiation.
+//!
+//! Every client route is served under both `/api/v1` and `/api/v2`, by the
+//! same handlers. A request's version comes from its path unless its `Accept`
+//! header names one (`application/vnd.claudiator.v2+json`), which lets
+//! clients that can't change paths opt in. Handlers that change shape between
+//! versions take [`ApiVersion`] as an extractor and branch on it; the rest
+//! serve every version alike.
+//!
+//! The negotiated version is echoed in the `Claudiator-API-Version` response
+//! header.
+
+use axum::extract::{FromRequestParts, Request, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderValue};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::AppError;
+
+/// Response header carrying the version a request was served at.
+pub const VERSION_HEADER: &str = "Claudiator-API-Version";
+
+/// Vendor media type prefix; the version number and `+json` follow.
+const MEDIA_TYPE_PREFIX: &str = "application/vnd.claudiator.v";
+
+/// A version of the client API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    /// Every version served, oldest first.
+    pub const ALL: &'static [Self] = &[Self::V1, Self::V2];
+
+    pub const fn number(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    /// The path prefix routes of this version are nested under.
+    pub const fn prefix(self) -> &'static str {
+        match self {
+            Self::V1 => "/api/v1",
+            Self::V2 => "/api/v2",
+        }
+    }
+
+    fn from_number(number: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|v| v.number() == number)
+    }
+
+    /// The version requested by an `Accept` header, `Ok(None)` when it names
+    /// none, or the unsupported version number it asked for.
+    fn from_accept(accept: &str) -> Result<Option<Self>, String> {
+        for media_type in accept.split(',') {
+            let media_type = media_type.split(';').next().unwrap_or("").trim();
+            let Some(rest) = media_type.strip_prefix(MEDIA_TYPE_PREFIX) else {
+                continue;
+            };
+            let number = rest.strip_suffix("+json").unwrap_or(rest);
+            return number
+                .parse()
+                .ok()
+                .and_then(Self::from_number)
+                .map(Some)
+                .ok_or_else(|| number.to_string());
+        }
+        Ok(None)
+    }
+}
+
+/// Middleware for routes nested under `path_version`'s prefix.
+///
+/// Records the negotiated version for [`ApiVersion`] extractors and echoes it
+/// in the response. An `Accept` header naming an unsupported version gets
+/// `406`.
+pub async fn negotiate(
+    State(path_version): State<ApiVersion>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let requested = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map_or(Ok(None), ApiVersion::from_accept);
+
+    let version = match requested {
+        Ok(version) => version.unwrap_or(path_version),
+        Err(number) => {
+            let supported: Vec<String> = ApiVersion::ALL
+                .iter()
+                .map(|v| v.number().to_string())
+                .collect();
+            return AppError::NotAcceptable(format!(
+                "API version {number} is not supported; supported versions: {}",
+                supported.join(", ")
+            ))
+            .into_response();
+        }
+    };
+
+    request.extensions_mut().insert(version);
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(VERSION_HEADER, HeaderValue::from(version.number()));
+    response
+}
+
+impl<S> FromRequestParts<S> for ApiVersion
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    fn from_request_parts<'life0, 'life1, 'async_trait>(
+        parts: &'life0 mut Parts,
+        _state: &'life1 S,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self, Self::Rejection>> + Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let version = parts.extensions.get::<Self>().copied().unwrap_or_default();
+        Box::pin(async move { Ok(version) })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(ApiVersion::from_accept("application/json"), Ok(None));
+        assert_eq!(ApiVersion::from_accept("*/*"), Ok(None));
+        assert_eq!(
+            ApiVersion::from_accept("application/vnd.claudiator.v2+json"),
+            Ok(Some(ApiVersion::V2))
+        );
+        assert_eq!(
+            ApiVersion::from_accept("text/html, application/vnd.claudiator.v1+json; q=0.9"),
+            Ok(Some(ApiVersion::V1))
+        );
+        assert_eq!(
+            ApiVersion::from_accept("application/vnd.claudiator.v9+json"),
+            Err("9".to_string())
+        );
+    }
+
+    #[test]
+    fn test_prefixes_match_numbers() {
+        for version in ApiVersion::ALL {
+            assert_eq!(
+                version.prefix(),
+                format!("/api/v{}", version.number()),
+                "{version:?}"
+            );
+        }
+    }
+}
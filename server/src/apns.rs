@@ -1,20 +1,111 @@
+//! Push notifications through Apple's APNs.
+//!
+//! The alert types are always built, since notifications are rendered the
+//! same way whether or not they are pushed. The client needs the `apns`
+//! cargo feature (on by default); without it, [`ApnsClient`] can't be
+//! made, so the server runs with push disabled and `jsonwebtoken` and `reqwest`
+//! are left out of the build.
+
+#[cfg(feature = "apns")]
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "apns")]
+use std::path::PathBuf;
+#[cfg(feature = "apns")]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+#[cfg(feature = "apns")]
 use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "apns")]
 use tokio::sync::RwLock;
 
+use crate::ingest::notification;
+use crate::models::response::NotificationResponse;
+
+#[cfg(feature = "apns")]
 #[derive(Debug, Serialize)]
 struct ApnsClaims {
     iss: String,
     iat: u64,
 }
 
+#[cfg(feature = "apns")]
 struct CachedToken {
     token: String,
     issued_at: u64,
 }
 
+/// Text for the app to render from its own localized strings: an APNs
+/// `loc-key` (or `title-loc-key`) and its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LocalizedText {
+    pub key: &'static str,
+    pub args: Vec<String>,
+}
+
+/// Localization keys for a push's title and body. A line without one is
+/// always sent pre-rendered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LocalizedAlert {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<LocalizedText>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<LocalizedText>,
+}
+
+/// Values the hook sealed end to end, which only the app can open. Their
+/// place in the alert text holds a placeholder; the app's notification
+/// service extension opens these and shows them instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedText {
+    /// The session title, shown in place of the generic title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The event's message, shown in place of the body's placeholder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// What a push shows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alert {
+    pub title: String,
+    pub body: String,
+    /// Keys `title` and `body` were rendered from.
+    pub localized: LocalizedAlert,
+    /// Ciphertext kept out of `title` and `body`, sent alongside them.
+    pub sealed: Option<SealedText>,
+}
+
+impl Alert {
+    /// The alert ingestion pushed for a stored notification: its title and
+    /// body, with the localization keys and sealed text set aside in its
+    /// `payload_json`. Resends, retries, and woken snoozes push this, so they
+    /// are localized and the app can still open sealed text.
+    pub fn from_stored(notification: &NotificationResponse) -> Self {
+        let extra = notification
+            .payload_json
+            .as_deref()
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok());
+        let extra = extra.as_ref();
+        let sealed = extra
+            .and_then(|extra| extra.get("e2e"))
+            .and_then(|e2e| SealedText::deserialize(e2e).ok());
+        Self {
+            title: notification.title.clone(),
+            body: notification.body.clone(),
+            localized: notification::stored_localized(
+                extra.and_then(|extra| extra.get("loc")),
+                &notification.title,
+                &notification.body,
+            ),
+            sealed,
+        }
+    }
+}
+
 #[derive(Debug)]
+#[cfg_attr(not(feature = "apns"), allow(dead_code))]
 pub enum ApnsPushResult {
     Success,
     Gone,
@@ -23,6 +114,7 @@ pub enum ApnsPushResult {
     OtherError(String),
 }
 
+#[cfg(feature = "apns")]
 pub struct ApnsClient {
     key_id: String,
     team_id: String,
@@ -30,17 +122,40 @@ pub struct ApnsClient {
     signing_key: EncodingKey,
     http_client: reqwest::Client,
     cached_token: RwLock<Option<CachedToken>>,
+    /// Environment for tokens registered without a sandbox flag.
     default_sandbox: bool,
+    url_override: Option<String>,
+    /// The `.p8` file the signing key was loaded from.
+    key_path: PathBuf,
+    /// Unix time of the last push APNs accepted; 0 until one is.
+    last_success: AtomicU64,
+    /// Send localization keys instead of pre-rendered text where a push has
+    /// them.
+    localized_alerts: bool,
 }
 
+#[cfg(feature = "apns")]
+const PRODUCTION_URL: &str = "https://api.push.apple.com";
+#[cfg(feature = "apns")]
+const SANDBOX_URL: &str = "https://api.sandbox.push.apple.com";
+
+#[cfg(feature = "apns")]
 impl ApnsClient {
-    #[allow(dead_code)]
-    pub(crate) fn new(
+    /// A client signing with the `.p8` key at `key_path`. `url_override`
+    /// replaces both APNs hosts, for testing against a stand-in.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key can't be read or isn't an EC key, or the
+    /// HTTP client can't be built.
+    pub fn new(
         key_path: &str,
         key_id: String,
         team_id: String,
         bundle_id: String,
         default_sandbox: bool,
+        url_override: Option<String>,
+        localized_alerts: bool,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let key_data = std::fs::read(key_path)?;
         let signing_key = EncodingKey::from_ec_pem(&key_data)?;
@@ -55,9 +170,42 @@ impl ApnsClient {
             http_client,
             cached_token: RwLock::new(None),
             default_sandbox,
+            url_override: url_override.map(|url| url.trim_end_matches('/').to_string()),
+            key_path: PathBuf::from(key_path),
+            last_success: AtomicU64::new(0),
+            localized_alerts,
         })
     }
 
+    /// Time since the signing key file was last modified, or `None` if it
+    /// can no longer be read. Old keys are the ones most likely to have been
+    /// revoked.
+    pub fn key_age(&self) -> Option<Duration> {
+        let modified = std::fs::metadata(&self.key_path).ok()?.modified().ok()?;
+        SystemTime::now().duration_since(modified).ok()
+    }
+
+    /// Unix time of the last push APNs accepted since the server started.
+    pub fn last_success(&self) -> Option<u64> {
+        match self.last_success.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(secs),
+        }
+    }
+
+    /// Base URL pushes to a token registered for `sandbox` are sent to. A
+    /// tagged token always gets its own environment; the server default only
+    /// applies to untagged ones. An override replaces both hosts.
+    fn base_url(&self, sandbox: Option<bool>) -> &str {
+        if let Some(url) = &self.url_override {
+            url
+        } else if sandbox.unwrap_or(self.default_sandbox) {
+            SANDBOX_URL
+        } else {
+            PRODUCTION_URL
+        }
+    }
+
     async fn get_or_refresh_token(
         &self,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
@@ -102,40 +250,21 @@ impl ApnsClient {
     pub(crate) async fn send_push(
         &self,
         device_token: &str,
-        title: &str,
-        body: &str,
+        alert: &Alert,
         collapse_id: Option<&str>,
         notification_id: &str,
         session_id: &str,
         device_id: &str,
-        sandbox: bool,
+        sandbox: Option<bool>,
     ) -> ApnsPushResult {
         let token = match self.get_or_refresh_token().await {
             Ok(t) => t,
             Err(e) => return ApnsPushResult::OtherError(format!("Token generation failed: {e}")),
         };
 
-        let host = if sandbox || self.default_sandbox {
-            "https://api.sandbox.push.apple.com"
-        } else {
-            "https://api.push.apple.com"
-        };
+        let url = format!("{}/3/device/{device_token}", self.base_url(sandbox));
 
-        let url = format!("{host}/3/device/{device_token}");
-
-        let payload = serde_json::json!({
-            "aps": {
-                "alert": {
-                    "title": title,
-                    "body": body,
-                },
-                "sound": "default",
-                "content-available": 1,
-            },
-            "notification_id": notification_id,
-            "session_id": session_id,
-            "device_id": device_id,
-        });
+        let payload = self.push_payload(alert, notification_id, session_id, device_id);
 
         let mut request = self
             .http_client
@@ -158,12 +287,54 @@ impl ApnsClient {
                 } else {
                     response.text().await.unwrap_or_default()
                 };
-                Self::status_to_push_result(status, &body_text)
+                let result = Self::status_to_push_result(status, &body_text);
+                if matches!(result, ApnsPushResult::Success) {
+                    self.record_success();
+                }
+                result
             }
             Err(e) => ApnsPushResult::OtherError(format!("Request failed: {e}")),
         }
     }
 
+    /// The JSON body of a push. Sealed text goes in a top-level `e2e`
+    /// object, with `mutable-content` set so the app's notification service
+    /// extension gets to open it before the alert is shown.
+    fn push_payload(
+        &self,
+        alert: &Alert,
+        notification_id: &str,
+        session_id: &str,
+        device_id: &str,
+    ) -> serde_json::Value {
+        let localized = if self.localized_alerts {
+            &alert.localized
+        } else {
+            &LocalizedAlert::default()
+        };
+        let mut payload = serde_json::json!({
+            "aps": {
+                "alert": alert_payload(&alert.title, &alert.body, localized),
+                "sound": "default",
+                "content-available": 1,
+            },
+            "notification_id": notification_id,
+            "session_id": session_id,
+            "device_id": device_id,
+        });
+        if let Some(sealed) = &alert.sealed {
+            payload["aps"]["mutable-content"] = 1.into();
+            payload["e2e"] = serde_json::json!(sealed);
+        }
+        payload
+    }
+
+    fn record_success(&self) {
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            self.last_success.store(now.as_secs(), Ordering::Relaxed);
+        }
+    }
+
     fn status_to_push_result(status: u16, body: &str) -> ApnsPushResult {
         match status {
             200 => ApnsPushResult::Success,
@@ -175,6 +346,7 @@ impl ApnsClient {
     }
 
     #[cfg(test)]
+    #[allow(clippy::expect_used)]
     fn new_for_test(
         signing_key: EncodingKey,
         key_id: String,
@@ -192,11 +364,93 @@ impl ApnsClient {
             http_client,
             cached_token: RwLock::new(None),
             default_sandbox: true,
+            url_override: None,
+            key_path: PathBuf::new(),
+            last_success: AtomicU64::new(0),
+            localized_alerts: false,
+        }
+    }
+}
+
+/// Stands in for the client in builds without the `apns` feature. It
+/// can't be made, so no client is ever configured and the push paths are
+/// never taken.
+#[cfg(not(feature = "apns"))]
+pub struct ApnsClient {
+    _unconstructible: (),
+}
+
+#[cfg(not(feature = "apns"))]
+#[allow(
+    clippy::needless_pass_by_value,
+    clippy::unused_async,
+    clippy::unused_self
+)]
+impl ApnsClient {
+    /// # Errors
+    ///
+    /// Always: there is no client without the `apns` feature.
+    pub fn new(
+        _key_path: &str,
+        _key_id: String,
+        _team_id: String,
+        _bundle_id: String,
+        _default_sandbox: bool,
+        _url_override: Option<String>,
+        _localized_alerts: bool,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Err("the server was built without the `apns` feature".into())
+    }
+
+    pub const fn key_age(&self) -> Option<Duration> {
+        None
+    }
+
+    pub const fn last_success(&self) -> Option<u64> {
+        None
+    }
+
+    pub(crate) async fn send_push(
+        &self,
+        _device_token: &str,
+        _alert: &Alert,
+        _collapse_id: Option<&str>,
+        _notification_id: &str,
+        _session_id: &str,
+        _device_id: &str,
+        _sandbox: Option<bool>,
+    ) -> ApnsPushResult {
+        ApnsPushResult::OtherError("built without the `apns` feature".to_string())
+    }
+}
+
+/// `aps.alert`, with each line as its localization key where `localized`
+/// has one and as `title` / `body` text otherwise.
+#[cfg(feature = "apns")]
+fn alert_payload(title: &str, body: &str, localized: &LocalizedAlert) -> serde_json::Value {
+    let mut alert = serde_json::Map::new();
+    match &localized.title {
+        Some(text) => {
+            alert.insert("title-loc-key".into(), text.key.into());
+            alert.insert("title-loc-args".into(), text.args.clone().into());
+        }
+        None => {
+            alert.insert("title".into(), title.into());
+        }
+    }
+    match &localized.body {
+        Some(text) => {
+            alert.insert("loc-key".into(), text.key.into());
+            alert.insert("loc-args".into(), text.args.clone().into());
+        }
+        None => {
+            alert.insert("body".into(), body.into());
         }
     }
+    alert.into()
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "apns"))]
 #[allow(clippy::unwrap_used)]
 #[allow(clippy::expect_used)]
 mod tests {
@@ -232,9 +486,8 @@ iE0Cu0jnmlsdhPTG/Cur1JBJ2a+hRANCAAR1QTINEESoo+PCsqnLmhFvOCNhbNe5\n\
             .await
             .expect("token generation should succeed");
 
-        let parts: Vec<&str> = token.split('.').collect();
         assert_eq!(
-            parts.len(),
+            token.split('.').count(),
             3,
             "JWT must have exactly three dot-separated parts"
         );
@@ -346,6 +599,156 @@ iE0Cu0jnmlsdhPTG/Cur1JBJ2a+hRANCAAR1QTINEESoo+PCsqnLmhFvOCNhbNe5\n\
         );
     }
 
+    // -------------------------------------------------------------------------
+    // Host selection tests
+    // -------------------------------------------------------------------------
+
+    #[test]
+    fn base_url_follows_sandbox_flag() {
+        let mut client = test_client("KEYID44444", "TEAMID4444");
+        client.default_sandbox = false;
+        assert_eq!(client.base_url(Some(false)), PRODUCTION_URL);
+        assert_eq!(client.base_url(Some(true)), SANDBOX_URL);
+    }
+
+    #[test]
+    fn base_url_for_mixed_fleet() {
+        // App Store and TestFlight builds register production tokens, Xcode
+        // builds sandbox ones, and old clients don't say.
+        let mut client = test_client("KEYID66666", "TEAMID6666");
+        for default_sandbox in [false, true] {
+            client.default_sandbox = default_sandbox;
+            assert_eq!(client.base_url(Some(false)), PRODUCTION_URL);
+            assert_eq!(client.base_url(Some(true)), SANDBOX_URL);
+            let untagged = if default_sandbox {
+                SANDBOX_URL
+            } else {
+                PRODUCTION_URL
+            };
+            assert_eq!(client.base_url(None), untagged);
+        }
+    }
+
+    #[test]
+    fn base_url_override_replaces_both_hosts() {
+        let mut client = test_client("KEYID55555", "TEAMID5555");
+        client.url_override = Some("http://127.0.0.1:3000".to_string());
+        assert_eq!(client.base_url(Some(false)), "http://127.0.0.1:3000");
+        assert_eq!(client.base_url(Some(true)), "http://127.0.0.1:3000");
+    }
+
+    #[tokio::test]
+    async fn send_push_uses_url_override() {
+        let app = axum::Router::new().route(
+            "/3/device/:token",
+            axum::routing::post(|| async { axum::http::StatusCode::GONE }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = test_client("KEYID66666", "TEAMID6666");
+        client.url_override = Some(format!("http://{addr}"));
+        let result = client
+            .send_push("tok", &Alert::default(), None, "n1", "s1", "d1", None)
+            .await;
+        assert!(matches!(result, ApnsPushResult::Gone), "got {result:?}");
+        assert_eq!(client.last_success(), None);
+    }
+
+    #[tokio::test]
+    async fn send_push_records_last_success() {
+        let app = axum::Router::new().route(
+            "/3/device/:token",
+            axum::routing::post(|| async { axum::http::StatusCode::OK }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = test_client("KEYID77777", "TEAMID7777");
+        client.url_override = Some(format!("http://{addr}"));
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let result = client
+            .send_push("tok", &Alert::default(), None, "n1", "s1", "d1", None)
+            .await;
+        assert!(matches!(result, ApnsPushResult::Success), "got {result:?}");
+        assert!(client.last_success().is_some_and(|secs| secs >= before));
+    }
+
+    #[test]
+    fn alert_payload_prefers_localization_keys() {
+        let localized = LocalizedAlert {
+            title: None,
+            body: Some(LocalizedText {
+                key: "NOTIF_PERMISSION_BODY_TOOL",
+                args: vec!["Bash".to_string()],
+            }),
+        };
+        assert_eq!(
+            alert_payload("Fix the build", "Permission required: Bash", &localized),
+            serde_json::json!({
+                "title": "Fix the build",
+                "loc-key": "NOTIF_PERMISSION_BODY_TOOL",
+                "loc-args": ["Bash"],
+            })
+        );
+        assert_eq!(
+            alert_payload(
+                "Session Stopped",
+                "Session stopped: done",
+                &LocalizedAlert::default()
+            ),
+            serde_json::json!({
+                "title": "Session Stopped",
+                "body": "Session stopped: done",
+            })
+        );
+    }
+
+    #[test]
+    fn push_payload_carries_sealed_text() {
+        let client = test_client("KEYID88888", "TEAMID8888");
+        let mut alert = Alert {
+            title: "Session Idle".to_string(),
+            body: "Session idle: [encrypted]".to_string(),
+            ..Alert::default()
+        };
+        let plain = client.push_payload(&alert, "n1", "s1", "d1");
+        assert!(plain["aps"].get("mutable-content").is_none());
+        assert!(plain.get("e2e").is_none());
+
+        alert.sealed = Some(SealedText {
+            title: None,
+            message: Some("e2e:v1:c2VhbGVk".to_string()),
+        });
+        let sealed = client.push_payload(&alert, "n1", "s1", "d1");
+        assert_eq!(sealed["aps"]["mutable-content"], 1);
+        assert_eq!(sealed["aps"]["alert"]["body"], "Session idle: [encrypted]");
+        assert_eq!(
+            sealed["e2e"],
+            serde_json::json!({"message": "e2e:v1:c2VhbGVk"})
+        );
+    }
+
+    #[test]
+    fn key_age_reads_key_file_mtime() {
+        let key_file = tempfile::NamedTempFile::new().unwrap();
+        let mut client = test_client("KEYID88888", "TEAMID8888");
+        assert_eq!(client.key_age(), None);
+
+        client.key_path = key_file.path().to_path_buf();
+        let week = Duration::from_hours(7 * 24);
+        key_file
+            .as_file()
+            .set_modified(SystemTime::now() - week)
+            .unwrap();
+        assert!(client.key_age().is_some_and(|age| age >= week));
+    }
+
     // -------------------------------------------------------------------------
     // Push result parsing tests
     // -------------------------------------------------------------------------
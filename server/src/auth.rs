@@ -3,11 +3,15 @@ use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use axum::extract::FromRequestParts;
+use axum::extract::{FromRequestParts, OriginalUri, Request};
 use axum::http::request::Parts;
-use axum::http::HeaderMap;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
 use chrono::{SecondsFormat, Utc};
 
+use crate::api_version::ApiVersion;
+use crate::db::partition::Partition;
 use crate::db::queries;
 use crate::error::AppError;
 use crate::router::AppState;
@@ -17,19 +21,44 @@ const MAX_FAILURES: u32 = 10;
 
 /// Time window within which failures are counted. After this window the
 /// counter resets automatically.
-const FAILURE_WINDOW: Duration = Duration::from_secs(5 * 60);
+const FAILURE_WINDOW: Duration = Duration::from_mins(5);
 
 /// Default maximum requests per key per minute when no per-key limit is set.
 const DEFAULT_KEY_RATE_LIMIT: u32 = 1000;
 
-/// Time window for per-key rate limiting.
-const KEY_RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Time for an empty per-key bucket to refill completely. A key's limit is
+/// both its bucket capacity and the tokens it regains over this window.
+const KEY_RATE_WINDOW: Duration = Duration::from_mins(1);
+
+/// Token cost of requests to specific routes; everything else costs
+/// [`DEFAULT_ROUTE_WEIGHT`]. Ingestion writes several tables and may fan out
+/// pushes, so it costs more than a read. Paths are relative to the API
+/// version prefix, so every version costs the same.
+const ROUTE_WEIGHTS: &[(&str, &str, u32)] = &[
+    ("POST", "/events", 2),
+    ("POST", "/hooks/http", 2),
+    ("POST", "/push/register", 2),
+];
+
+const DEFAULT_ROUTE_WEIGHT: u32 = 1;
 
 /// Per-IP state: (`failure_count`, `window_start`).
 pub type AuthFailureMap = Mutex<HashMap<IpAddr, (u32, Instant)>>;
 
-/// Per-key state: (`request_count`, `window_start`).
-pub type KeyRateLimitMap = Mutex<HashMap<String, (u32, Instant)>>;
+/// Per-key token bucket: (`tokens`, `last_refill`).
+pub type KeyRateLimitMap = Mutex<HashMap<String, (f64, Instant)>>;
+
+/// A client that authenticated with the previous master key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviousKeyUse {
+    pub first_seen: String,
+    pub last_seen: String,
+    pub requests: u64,
+}
+
+/// Clients still presenting the previous master key, by (`ip`, `User-Agent`).
+/// Kept in memory only, so it covers the time since the server started.
+pub type PreviousKeyUseMap = Mutex<HashMap<(IpAddr, String), PreviousKeyUse>>;
 
 /// Extracts the client IP from request headers.
 ///
@@ -93,34 +122,152 @@ pub fn record_auth_failure(map: &AuthFailureMap, ip: IpAddr) {
     entry.0 = entry.0.saturating_add(1);
 }
 
-/// Checks and increments the request counter for `key_id`.
-/// Returns `Err(AppError::RateLimited)` if the counter exceeds `limit` within `KEY_RATE_WINDOW`.
-#[allow(clippy::significant_drop_tightening)]
+/// Records a request from `ip` with `user_agent` that authenticated with
+/// the previous master key, for `GET /admin/previous-key-clients`. A
+/// client's first use is logged.
+pub fn record_previous_key_use(map: &PreviousKeyUseMap, ip: IpAddr, user_agent: &str, now: &str) {
+    let mut guard = map
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    guard
+        .entry((ip, user_agent.to_string()))
+        .and_modify(|entry| {
+            entry.last_seen = now.to_string();
+            entry.requests = entry.requests.saturating_add(1);
+        })
+        .or_insert_with(|| {
+            tracing::info!(%ip, user_agent, "Client authenticated with the previous master key");
+            PreviousKeyUse {
+                first_seen: now.to_string(),
+                last_seen: now.to_string(),
+                requests: 1,
+            }
+        });
+}
+
+/// Token cost of a request to `path` with `method`.
+pub fn route_weight(method: &str, path: &str) -> u32 {
+    let path = ApiVersion::ALL
+        .iter()
+        .find_map(|version| path.strip_prefix(version.prefix()))
+        .unwrap_or(path);
+    ROUTE_WEIGHTS
+        .iter()
+        .find(|(m, p, _)| *m == method && *p == path)
+        .map_or(DEFAULT_ROUTE_WEIGHT, |(_, _, weight)| *weight)
+}
+
+/// A key's rate-limit state after a request, reported in response headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    /// Whole tokens left in the bucket.
+    pub remaining: u32,
+    /// Time until the bucket holds enough tokens for the rejected request;
+    /// zero when the request was allowed.
+    pub retry_after: Duration,
+    /// Whether this request was over the limit.
+    pub exceeded: bool,
+}
+
+/// Takes `cost` tokens from `key_id`'s bucket. Buckets hold up to `limit`
+/// tokens and refill continuously at `limit` per `KEY_RATE_WINDOW`, so a key
+/// can never burst past its limit the way a fixed window allows at window
+/// boundaries. A rejected request takes no tokens.
+///
+/// Buckets idle for a full window are evicted; they would be full again anyway.
+#[allow(
+    clippy::significant_drop_tightening,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
 pub fn check_key_rate_limit(
     map: &KeyRateLimitMap,
     key_id: &str,
     limit: u32,
-) -> Result<(), AppError> {
+    cost: u32,
+) -> RateLimitStatus {
     let mut guard = map
         .lock()
         .unwrap_or_else(std::sync::PoisonError::into_inner);
     let now = Instant::now();
 
-    guard.retain(|_, (_, start)| now.duration_since(*start) < KEY_RATE_WINDOW);
+    guard.retain(|_, (_, last_refill)| now.duration_since(*last_refill) < KEY_RATE_WINDOW);
 
-    let entry = guard.entry(key_id.to_string()).or_insert((0, now));
+    let capacity = f64::from(limit);
+    let refill_per_sec = capacity / KEY_RATE_WINDOW.as_secs_f64();
+    // A request costlier than the whole bucket could never succeed
+    let cost = f64::from(cost.clamp(1, limit.max(1)));
 
-    if now.duration_since(entry.1) >= KEY_RATE_WINDOW {
-        *entry = (0, now);
+    let entry = guard.entry(key_id.to_string()).or_insert((capacity, now));
+    let elapsed = now.duration_since(entry.1).as_secs_f64();
+    entry.0 = elapsed.mul_add(refill_per_sec, entry.0).min(capacity);
+    entry.1 = now;
+
+    let exceeded = entry.0 < cost;
+    let retry_after = if !exceeded {
+        Duration::ZERO
+    } else if refill_per_sec > 0.0 {
+        Duration::from_secs_f64((cost - entry.0) / refill_per_sec)
+    } else {
+        KEY_RATE_WINDOW
+    };
+    if !exceeded {
+        entry.0 -= cost;
     }
 
-    entry.0 = entry.0.saturating_add(1);
+    RateLimitStatus {
+        limit,
+        remaining: entry.0.floor() as u32,
+        retry_after,
+        exceeded,
+    }
+}
 
-    if entry.0 > limit {
-        return Err(AppError::RateLimited);
+/// Slot the auth extractors fill with the key's [`RateLimitStatus`], so
+/// [`rate_limit_headers`] can report it on the response.
+#[derive(Clone, Default)]
+pub struct RateLimitReport(Arc<Mutex<Option<RateLimitStatus>>>);
+
+impl RateLimitReport {
+    fn set(&self, status: RateLimitStatus) {
+        *self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(status);
     }
 
-    Ok(())
+    fn take(&self) -> Option<RateLimitStatus> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take()
+    }
+}
+
+/// Middleware adding `X-RateLimit-Limit` and `X-RateLimit-Remaining` to
+/// responses for rate-limited keys, plus `Retry-After` when the request was
+/// rejected. The master key is not rate limited and gets no headers.
+pub async fn rate_limit_headers(mut request: Request, next: Next) -> Response {
+    let report = RateLimitReport::default();
+    request.extensions_mut().insert(report.clone());
+
+    let mut response = next.run(request).await;
+
+    if let Some(status) = report.take() {
+        let headers = response.headers_mut();
+        headers.insert("X-RateLimit-Limit", HeaderValue::from(status.limit));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from(status.remaining));
+        if status.exceeded {
+            // Round up so clients never retry before enough tokens are back
+            let secs =
+                status.retry_after.as_secs() + u64::from(status.retry_after.subsec_nanos() > 0);
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+        }
+    }
+
+    response
 }
 
 // ── Scope ─────────────────────────────────────────────────────────────────────
@@ -148,14 +295,31 @@ pub fn parse_scopes(s: &str) -> Vec<Scope> {
 // ── Typed extractors ──────────────────────────────────────────────────────────
 
 /// Extractor that requires a valid key with `read` scope.
-pub struct ReadAuth;
+pub struct ReadAuth(pub Partition);
 
 /// Extractor that requires a valid key with `write` scope.
-pub struct WriteAuth;
+pub struct WriteAuth(pub Partition);
 
 /// Extractor for admin endpoints: requires localhost origin + master key.
 pub struct AdminAuth;
 
+/// The scoped key that authenticated a request, left in the request
+/// extensions by [`ReadAuth`] and [`WriteAuth`] so handlers can attribute
+/// work to it. Absent for master-key requests.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedKey {
+    pub id: String,
+    /// Device the key is bound to, if it was provisioned by enrollment.
+    pub device_id: Option<String>,
+    /// Whether events written with the key drop prompts and messages.
+    pub privacy_mode: bool,
+}
+
+/// Generates a new scoped API key value.
+pub fn generate_api_key() -> String {
+    format!("claud_{}", uuid::Uuid::new_v4().simple())
+}
+
 // ── Core resolution logic ─────────────────────────────────────────────────────
 
 fn extract_bearer_token(headers: &HeaderMap) -> Option<&str> {
@@ -165,33 +329,55 @@ fn extract_bearer_token(headers: &HeaderMap) -> Option<&str> {
         .and_then(|v| v.strip_prefix("Bearer "))
 }
 
+/// Returns `true` if `token` is the master key, or the previous master key
+/// during a rotation. Uses of the previous key are recorded so operators can
+/// see which clients still need updating.
+fn is_master_key(state: &AppState, headers: &HeaderMap, token: &str) -> bool {
+    if token == state.auth.master_key {
+        return true;
+    }
+
+    if state.auth.previous_master_key.as_deref() != Some(token) {
+        return false;
+    }
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    record_previous_key_use(
+        &state.auth.previous_key_uses,
+        extract_client_ip(headers),
+        user_agent,
+        &now,
+    );
+    true
+}
+
 /// Resolves and validates the bearer token, checking the required scope.
 /// Updates `last_used` for DB keys on successful auth.
 fn resolve_auth(
-    headers: &HeaderMap,
+    parts: &mut Parts,
     state: &Arc<AppState>,
     required_scope: &Scope,
-) -> Result<(), AppError> {
+) -> Result<Partition, AppError> {
+    let headers = &parts.headers;
     let ip = extract_client_ip(headers);
-    check_rate_limit(&state.auth_failures, ip)?;
+    check_rate_limit(&state.auth.failures, ip)?;
 
     let Some(token) = extract_bearer_token(headers) else {
-        record_auth_failure(&state.auth_failures, ip);
+        record_auth_failure(&state.auth.failures, ip);
         return Err(AppError::Unauthorized);
     };
 
     // Master key — always read+write
-    if token == state.master_key {
-        return Ok(());
+    if is_master_key(state, headers, token) {
+        return Ok(Partition::All);
     }
 
     // DB key lookup
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("DB pool error: {e}")))?;
-
-    if let Some(row) = queries::find_api_key_by_key(&conn, token)? {
+    if let Some(row) = state.storage.find_api_key_by_key(token)? {
         let scopes = parse_scopes(&row.scopes);
 
         if !scopes.contains(required_scope) {
@@ -201,14 +387,36 @@ fn resolve_auth(
         let effective_limit = row.rate_limit.map_or(DEFAULT_KEY_RATE_LIMIT, |v| {
             u32::try_from(v).unwrap_or(DEFAULT_KEY_RATE_LIMIT)
         });
-        check_key_rate_limit(&state.key_rate_limits, &row.id, effective_limit)?;
+        // Nested routers see the path without its version prefix
+        let path = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map_or_else(|| parts.uri.path(), |uri| uri.path());
+        let cost = route_weight(parts.method.as_str(), path);
+        let status =
+            check_key_rate_limit(&state.auth.key_rate_limits, &row.id, effective_limit, cost);
+        if let Some(report) = parts.extensions.get::<RateLimitReport>() {
+            report.set(status);
+        }
+        if status.exceeded {
+            return Err(AppError::RateLimited);
+        }
 
-        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-        let _ = queries::update_api_key_last_used(&conn, &row.id, &now);
+        let now = Utc::now();
+        let _ = state
+            .storage
+            .update_api_key_last_used(&row.id, &now.to_rfc3339_opts(SecondsFormat::Millis, true));
+        let day = now.format(queries::USAGE_DAY_FORMAT).to_string();
+        let _ = state.storage.increment_key_usage(&row.id, &day, 1, 0);
+        parts.extensions.insert(AuthenticatedKey {
+            id: row.id,
+            device_id: row.device_id,
+            privacy_mode: row.privacy_mode,
+        });
 
-        Ok(())
+        Ok(Partition::Key(row.partition))
     } else {
-        record_auth_failure(&state.auth_failures, ip);
+        record_auth_failure(&state.auth.failures, ip);
         Err(AppError::Unauthorized)
     }
 }
@@ -229,7 +437,7 @@ impl FromRequestParts<Arc<AppState>> for ReadAuth {
         'life1: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(async move { resolve_auth(&parts.headers, state, &Scope::Read).map(|()| Self) })
+        Box::pin(async move { resolve_auth(parts, state, &Scope::Read).map(Self) })
     }
 }
 
@@ -247,7 +455,7 @@ impl FromRequestParts<Arc<AppState>> for WriteAuth {
         'life1: 'async_trait,
         Self: 'async_trait,
     {
-        Box::pin(async move { resolve_auth(&parts.headers, state, &Scope::Write).map(|()| Self) })
+        Box::pin(async move { resolve_auth(parts, state, &Scope::Write).map(Self) })
     }
 }
 
@@ -282,15 +490,15 @@ impl FromRequestParts<Arc<AppState>> for AdminAuth {
 
             // Require master key
             let ip = extract_client_ip(&parts.headers);
-            check_rate_limit(&state.auth_failures, ip)?;
+            check_rate_limit(&state.auth.failures, ip)?;
 
             let Some(token) = extract_bearer_token(&parts.headers) else {
-                record_auth_failure(&state.auth_failures, ip);
+                record_auth_failure(&state.auth.failures, ip);
                 return Err(AppError::Unauthorized);
             };
 
-            if token != state.master_key {
-                record_auth_failure(&state.auth_failures, ip);
+            if !is_master_key(state, &parts.headers, token) {
+                record_auth_failure(&state.auth.failures, ip);
                 return Err(AppError::Unauthorized);
             }
 
@@ -301,6 +509,7 @@ impl FromRequestParts<Arc<AppState>> for AdminAuth {
 
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
+#[allow(clippy::unchecked_time_subtraction)]
 mod tests {
     use super::*;
     use std::net::Ipv4Addr;
@@ -496,7 +705,7 @@ mod tests {
         let limit = 5u32;
 
         for _ in 0..limit {
-            assert!(check_key_rate_limit(&map, key_id, limit).is_ok());
+            assert!(!check_key_rate_limit(&map, key_id, limit, 1).exceeded);
         }
     }
 
@@ -507,13 +716,10 @@ mod tests {
         let limit = 3u32;
 
         for _ in 0..limit {
-            let _ = check_key_rate_limit(&map, key_id, limit);
+            let _ = check_key_rate_limit(&map, key_id, limit, 1);
         }
 
-        assert!(matches!(
-            check_key_rate_limit(&map, key_id, limit),
-            Err(AppError::RateLimited)
-        ));
+        assert!(check_key_rate_limit(&map, key_id, limit, 1).exceeded);
     }
 
     #[test]
@@ -522,10 +728,10 @@ mod tests {
         let limit = 2u32;
 
         for _ in 0..=limit {
-            let _ = check_key_rate_limit(&map, "key-a", limit);
+            let _ = check_key_rate_limit(&map, "key-a", limit, 1);
         }
 
-        assert!(check_key_rate_limit(&map, "key-b", limit).is_ok());
+        assert!(!check_key_rate_limit(&map, "key-b", limit, 1).exceeded);
     }
 
     #[test]
@@ -536,14 +742,11 @@ mod tests {
 
         // Exhaust the limit
         for _ in 0..=limit {
-            let _ = check_key_rate_limit(&map, key_id, limit);
+            let _ = check_key_rate_limit(&map, key_id, limit, 1);
         }
-        assert!(matches!(
-            check_key_rate_limit(&map, key_id, limit),
-            Err(AppError::RateLimited)
-        ));
+        assert!(check_key_rate_limit(&map, key_id, limit, 1).exceeded);
 
-        // Backdate window_start past KEY_RATE_WINDOW to simulate expiry
+        // Backdate last_refill past KEY_RATE_WINDOW to simulate a full refill
         {
             let mut guard = map.lock().unwrap();
             if let Some(entry) = guard.get_mut(key_id) {
@@ -552,6 +755,128 @@ mod tests {
         }
 
         // Counter should reset; request should be allowed again
-        assert!(check_key_rate_limit(&map, key_id, limit).is_ok());
+        assert!(!check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+    }
+
+    #[test]
+    fn test_key_rate_limit_reports_remaining() {
+        let map = make_key_rate_map();
+        let limit = 3u32;
+
+        let first = check_key_rate_limit(&map, "key", limit, 1);
+        assert_eq!(first.limit, limit);
+        assert_eq!(first.remaining, 2);
+        assert_eq!(first.retry_after, Duration::ZERO);
+
+        let _ = check_key_rate_limit(&map, "key", limit, 1);
+        let third = check_key_rate_limit(&map, "key", limit, 1);
+        assert_eq!(third.remaining, 0);
+        assert!(!third.exceeded);
+
+        let fourth = check_key_rate_limit(&map, "key", limit, 1);
+        assert_eq!(fourth.remaining, 0);
+        assert!(fourth.exceeded);
+    }
+
+    #[test]
+    fn test_key_rate_limit_refills_gradually() {
+        let map = make_key_rate_map();
+        let key_id = "gradual";
+        let limit = 4u32;
+
+        for _ in 0..limit {
+            assert!(!check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+        }
+        assert!(check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+
+        // Half a window later only half the bucket is back, so there is no
+        // burst of a whole fresh limit at the window boundary
+        {
+            let mut guard = map.lock().unwrap();
+            if let Some(entry) = guard.get_mut(key_id) {
+                entry.1 = Instant::now() - KEY_RATE_WINDOW / 2;
+            }
+        }
+        assert!(!check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+        assert!(!check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+        assert!(check_key_rate_limit(&map, key_id, limit, 1).exceeded);
+    }
+
+    #[test]
+    fn test_key_rate_limit_weighted_cost() {
+        let map = make_key_rate_map();
+        let limit = 5u32;
+
+        let status = check_key_rate_limit(&map, "weighted", limit, 2);
+        assert_eq!(status.remaining, 3);
+        let status = check_key_rate_limit(&map, "weighted", limit, 2);
+        assert_eq!(status.remaining, 1);
+
+        // Not enough tokens for another costly request, but enough for a cheap one
+        let status = check_key_rate_limit(&map, "weighted", limit, 2);
+        assert!(status.exceeded);
+        assert_eq!(status.remaining, 1);
+        assert!(!check_key_rate_limit(&map, "weighted", limit, 1).exceeded);
+    }
+
+    #[test]
+    fn test_key_rate_limit_cost_clamped_to_capacity() {
+        let map = make_key_rate_map();
+        assert!(!check_key_rate_limit(&map, "tiny", 1, 2).exceeded);
+        assert!(check_key_rate_limit(&map, "zero", 0, 1).exceeded);
+    }
+
+    #[test]
+    fn test_key_rate_limit_evicts_idle_buckets() {
+        let map = make_key_rate_map();
+        let _ = check_key_rate_limit(&map, "idle", 5, 1);
+        {
+            let mut guard = map.lock().unwrap();
+            if let Some(entry) = guard.get_mut("idle") {
+                entry.1 = Instant::now() - KEY_RATE_WINDOW;
+            }
+        }
+        let _ = check_key_rate_limit(&map, "active", 5, 1);
+        let map = map.into_inner().unwrap();
+        assert!(!map.contains_key("idle"));
+        assert!(map.contains_key("active"));
+    }
+
+    #[test]
+    fn test_route_weight() {
+        assert_eq!(route_weight("POST", "/api/v1/events"), 2);
+        assert_eq!(route_weight("POST", "/api/v1/hooks/http"), 2);
+        assert_eq!(route_weight("GET", "/api/v1/ping"), 1);
+        assert_eq!(route_weight("GET", "/api/v1/events/7"), 1);
+        assert_eq!(route_weight("POST", "/api/v2/events"), 2);
+        assert_eq!(route_weight("POST", "/admin/events"), 1);
+    }
+
+    #[test]
+    fn test_record_previous_key_use_counts_per_client() {
+        let map: PreviousKeyUseMap = Mutex::new(HashMap::new());
+        let ip = test_ip();
+
+        record_previous_key_use(
+            &map,
+            ip,
+            "claudiator-hook/0.1.0",
+            "2024-01-01T00:00:00.000Z",
+        );
+        record_previous_key_use(
+            &map,
+            ip,
+            "claudiator-hook/0.1.0",
+            "2024-01-01T00:05:00.000Z",
+        );
+        record_previous_key_use(&map, ip, "curl/8.0", "2024-01-01T00:10:00.000Z");
+
+        let uses = map.into_inner().unwrap();
+        assert_eq!(uses.len(), 2);
+        let hook = &uses[&(ip, "claudiator-hook/0.1.0".to_string())];
+        assert_eq!(hook.requests, 2);
+        assert_eq!(hook.first_seen, "2024-01-01T00:00:00.000Z");
+        assert_eq!(hook.last_seen, "2024-01-01T00:05:00.000Z");
+        assert_eq!(uses[&(ip, "curl/8.0".to_string())].requests, 1);
     }
 }
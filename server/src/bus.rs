@@ -0,0 +1,147 @@
+//! In-process broadcast of ingested events.
+//!
+//! The ingestion pipeline publishes every stored event to the [`EventBus`] on
+//! `AppState`. Delivery mechanisms (SSE, WebSocket, webhooks, digests)
+//! subscribe to it instead of hooking into the HTTP handler, so adding one
+//! never touches ingestion.
+//!
+//! The bus is a `tokio::sync::broadcast` channel: publishing never blocks and
+//! succeeds with no subscribers. A subscriber that falls more than
+//! [`EVENT_BUS_CAPACITY`] events behind gets `RecvError::Lagged` and skips
+//! ahead; it must tolerate gaps.
+
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::db::partition::Partition;
+use crate::models::request::EventPayload;
+
+/// Events buffered per subscriber before the slowest one starts lagging.
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// An event that has been committed to the database.
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // fields are read by subscribers
+pub struct IngestedEvent {
+    /// Row id in the `events` table.
+    pub event_id: i64,
+    /// Partition of the event's device. Subscribers serving a key must
+    /// filter with [`Partition::allows`].
+    pub partition: Partition,
+    pub payload: EventPayload,
+    /// RFC 3339 time the server stored the event.
+    pub received_at: String,
+    /// The status the event moved its session to, if it changed it.
+    pub session_status: Option<String>,
+    /// The notification the event produced, unless none applied or the
+    /// cooldown suppressed it.
+    pub notification: Option<IngestedNotification>,
+}
+
+/// A notification stored for an [`IngestedEvent`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngestedNotification {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    pub notification_type: String,
+}
+
+/// Broadcast channel of [`IngestedEvent`]s.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<IngestedEvent>>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Sends `event` to every current subscriber. Returns how many there were.
+    pub fn publish(&self, event: IngestedEvent) -> usize {
+        self.sender.send(Arc::new(event)).unwrap_or(0)
+    }
+
+    /// Receives every event published from now on.
+    #[allow(dead_code)]
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<IngestedEvent>> {
+        self.sender.subscribe()
+    }
+
+    #[allow(dead_code)]
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUS_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::models::request::{DeviceInfo, EventData};
+
+    fn event(event_id: i64) -> IngestedEvent {
+        IngestedEvent {
+            event_id,
+            partition: Partition::Key(None),
+            payload: EventPayload {
+                device: DeviceInfo {
+                    device_id: "d".to_string(),
+                    device_name: "Device".to_string(),
+                    platform: "mac".to_string(),
+                },
+                event: serde_json::from_value::<EventData>(serde_json::json!({
+                    "session_id": "s",
+                    "hook_event_name": "Stop",
+                }))
+                .unwrap(),
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+            },
+            received_at: "2024-01-01T00:00:00.000Z".to_string(),
+            session_status: None,
+            notification: None,
+        }
+    }
+
+    #[test]
+    fn test_publish_without_subscribers() {
+        let bus = EventBus::default();
+        assert_eq!(bus.subscriber_count(), 0);
+        assert_eq!(bus.publish(event(1)), 0);
+    }
+
+    #[test]
+    fn test_every_subscriber_receives() {
+        let bus = EventBus::default();
+        let mut first = bus.subscribe();
+        let mut second = bus.subscribe();
+
+        assert_eq!(bus.publish(event(1)), 2);
+        assert_eq!(first.try_recv().unwrap().event_id, 1);
+        assert_eq!(second.try_recv().unwrap().event_id, 1);
+    }
+
+    #[test]
+    fn test_slow_subscriber_lags() {
+        let bus = EventBus::new(2);
+        let mut rx = bus.subscribe();
+        for id in 1..=3 {
+            bus.publish(event(id));
+        }
+
+        assert!(matches!(
+            rx.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(1))
+        ));
+        assert_eq!(rx.try_recv().unwrap().event_id, 2);
+    }
+}
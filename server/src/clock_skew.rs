@@ -0,0 +1,84 @@
+//! Correcting event timestamps from machines with wrong clocks.
+//!
+//! Every event stores its skew, `received_at − timestamp` in milliseconds.
+//! That mixes the device's clock offset with delivery delay, which is never
+//! negative: an event held in the hook's spool arrives late but its clock
+//! may be fine. So a device's offset is taken as the smallest skew among
+//! its events over the last [`OFFSET_WINDOW_SECS`]. The hook sends a new
+//! event before draining its spool, so that window usually holds one sent
+//! straight away; keeping it short lets a corrected clock take effect soon.
+//!
+//! When that offset exceeds [`TOLERANCE_MS`], the event's normalized
+//! timestamp is its `timestamp` shifted by the offset, and the device is
+//! flagged as skewed. Otherwise the normalized timestamp is the `timestamp`
+//! as sent, in the server's UTC millisecond form.
+
+use chrono::{DateTime, Duration, SecondsFormat};
+
+/// Largest offset, either way, treated as an accurate clock.
+pub const TOLERANCE_MS: i64 = 30_000;
+
+/// How far back a device's events count toward its offset.
+pub const OFFSET_WINDOW_SECS: i64 = 300;
+
+/// `received_at − timestamp` in milliseconds, or `None` if either is not
+/// RFC 3339.
+pub fn skew_ms(timestamp: &str, received_at: &str) -> Option<i64> {
+    let sent = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    let received = DateTime::parse_from_rfc3339(received_at).ok()?;
+    Some((received - sent).num_milliseconds())
+}
+
+/// Whether a device offset is large enough to correct for.
+pub const fn is_skewed(offset_ms: i64) -> bool {
+    offset_ms.abs() > TOLERANCE_MS
+}
+
+/// `timestamp` corrected by the device offset `offset_ms` when it is
+/// skewed, in UTC millisecond form. `None` if `timestamp` is not RFC 3339.
+pub fn normalize(timestamp: &str, offset_ms: i64) -> Option<String> {
+    let mut time = DateTime::parse_from_rfc3339(timestamp).ok()?.to_utc();
+    if is_skewed(offset_ms) {
+        time += Duration::milliseconds(offset_ms);
+    }
+    Some(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew_is_received_minus_sent() {
+        assert_eq!(
+            skew_ms("2024-01-01T00:00:00Z", "2024-01-01T00:00:01.500Z"),
+            Some(1500)
+        );
+        assert_eq!(
+            skew_ms("2024-01-01T02:00:05+02:00", "2024-01-01T00:00:00Z"),
+            Some(-5000)
+        );
+        assert_eq!(skew_ms("yesterday", "2024-01-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn test_normalize_only_corrects_large_offsets() {
+        assert_eq!(
+            normalize("2024-01-01T01:00:00+01:00", 2000).unwrap(),
+            "2024-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            normalize("2024-01-01T00:00:00Z", -TOLERANCE_MS).unwrap(),
+            "2024-01-01T00:00:00.000Z"
+        );
+        assert_eq!(
+            normalize("2024-01-01T00:00:00Z", 3_600_000).unwrap(),
+            "2024-01-01T01:00:00.000Z"
+        );
+        assert_eq!(
+            normalize("2024-01-01T00:10:00Z", -120_000).unwrap(),
+            "2024-01-01T00:08:00.000Z"
+        );
+    }
+}
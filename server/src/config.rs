@@ -1,24 +1,48 @@
+use std::path::{Path, PathBuf};
+
 use clap::Parser;
 
+use crate::event_storage::{parse_rule, StorageTier};
+use crate::ingest::notification::Severity;
+use crate::notif_routing::{parse_route, Channel};
+use crate::session_title::{TitleStrategy, DEFAULT_TITLE_TEMPLATE};
+use crate::setup::SetupArgs;
+
 #[derive(Debug, Parser)]
 #[command(
     name = "claudiator-server",
     version,
-    about = "Claudiator event ingestion server"
+    about = "Claudiator event ingestion server",
+    subcommand_negates_reqs = true
 )]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ServerConfig {
+    /// Run a one-off command instead of serving.
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[arg(long, default_value = "3000", env = "CLAUDIATOR_PORT")]
     pub port: u16,
     #[arg(long, default_value = "claudiator.db", env = "CLAUDIATOR_DB_PATH")]
     pub db_path: String,
-    #[arg(long, env = "CLAUDIATOR_API_KEY")]
-    pub api_key: String,
+    /// Master key. Required to serve; subcommands don't need it.
+    #[arg(long, env = "CLAUDIATOR_API_KEY", required = true)]
+    pub api_key: Option<String>,
+    /// Old master key, still accepted while clients move to `--api-key`.
+    #[arg(long, env = "CLAUDIATOR_PREVIOUS_API_KEY")]
+    pub previous_api_key: Option<String>,
     #[arg(long, default_value = "0.0.0.0", env = "CLAUDIATOR_BIND")]
     pub bind: String,
+    /// URL clients reach this server at, embedded in enrollment QR payloads.
+    #[arg(long, env = "CLAUDIATOR_PUBLIC_URL")]
+    pub public_url: Option<String>,
     #[arg(long, default_value = "info", env = "CLAUDIATOR_LOG_LEVEL")]
     pub log_level: String,
     #[arg(long, default_value = "logs", env = "CLAUDIATOR_LOG_DIR")]
     pub log_dir: String,
+    /// Directory a relative `--db-path` and `--log-dir` are resolved in,
+    /// instead of the working directory.
+    #[arg(long, env = "CLAUDIATOR_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
     #[arg(long, env = "CLAUDIATOR_APNS_KEY_PATH")]
     pub apns_key_path: Option<String>,
     #[arg(long, env = "CLAUDIATOR_APNS_KEY_ID")]
@@ -27,8 +51,20 @@ pub struct ServerConfig {
     pub apns_team_id: Option<String>,
     #[arg(long, env = "CLAUDIATOR_APNS_BUNDLE_ID")]
     pub apns_bundle_id: Option<String>,
+    /// APNs environment for push tokens registered without a `sandbox` flag.
     #[arg(long, default_value = "false", env = "CLAUDIATOR_APNS_SANDBOX")]
     pub apns_sandbox: bool,
+    /// Send pushes to this base URL instead of Apple's hosts (mock servers, proxies).
+    #[arg(long, env = "CLAUDIATOR_APNS_URL_OVERRIDE")]
+    pub apns_url_override: Option<String>,
+    /// Send built-in notification text as `loc-key` / `loc-args` for the app
+    /// to localize, instead of pre-rendered English.
+    #[arg(
+        long,
+        default_value = "false",
+        env = "CLAUDIATOR_APNS_LOCALIZED_ALERTS"
+    )]
+    pub apns_localized_alerts: bool,
 
     #[arg(long, default_value = "7", env = "CLAUDIATOR_RETENTION_EVENTS_DAYS")]
     pub retention_events_days: u64,
@@ -38,9 +74,230 @@ pub struct ServerConfig {
 
     #[arg(long, default_value = "30", env = "CLAUDIATOR_RETENTION_DEVICES_DAYS")]
     pub retention_devices_days: u64,
+
+    /// Days a push token is kept without an accepted push or re-registration.
+    #[arg(
+        long,
+        default_value = "30",
+        env = "CLAUDIATOR_RETENTION_PUSH_TOKENS_DAYS"
+    )]
+    pub retention_push_tokens_days: u64,
+
+    #[arg(
+        long,
+        default_value = "24",
+        env = "CLAUDIATOR_MAINTENANCE_INTERVAL_HOURS"
+    )]
+    pub maintenance_interval_hours: u64,
+
+    #[arg(long, default_value = "false", env = "CLAUDIATOR_ARCHIVE_EVENTS")]
+    pub archive_events: bool,
+
+    /// Drop prompts and messages from every event at ingest, keeping only
+    /// metadata. Keys can also be created with `privacy_mode` individually.
+    #[arg(long, default_value = "false", env = "CLAUDIATOR_PRIVACY_MODE")]
+    pub privacy_mode: bool,
+
+    /// Scrub prompt text from log output too. Bearer tokens and API keys
+    /// are always scrubbed.
+    #[arg(long, default_value = "false", env = "CLAUDIATOR_REDACT_LOG_PROMPTS")]
+    pub redact_log_prompts: bool,
+
+    /// Record event fields the server doesn't know, per event name, and log
+    /// the first sighting of each. Listed by `GET /admin/unknown-fields`.
+    #[arg(long, default_value = "false", env = "CLAUDIATOR_STRICT_FIELDS")]
+    pub strict_fields: bool,
+
+    /// Oldest `claudiator-hook` version supported. Events from older hooks
+    /// are still accepted, but the device is flagged and a notification
+    /// about it is sent once a day.
+    #[arg(
+        long,
+        default_value = crate::router::DEFAULT_MIN_HOOK_VERSION,
+        value_parser = parse_version,
+        env = "CLAUDIATOR_MIN_HOOK_VERSION"
+    )]
+    pub min_hook_version: String,
+
+    #[arg(
+        long,
+        default_value = "24",
+        value_parser = clap::value_parser!(u64).range(1..),
+        env = "CLAUDIATOR_NOTIFICATION_TTL_HOURS"
+    )]
+    pub notification_ttl_hours: u64,
+
+    /// Per-type TTL overrides as `type=hours`, e.g. `stop=168,permission_prompt=1`.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_type_ttl,
+        env = "CLAUDIATOR_NOTIFICATION_TYPE_TTL"
+    )]
+    pub notification_type_ttl: Vec<(String, u64)>,
+
+    /// Per-type delivery channels as `type=channel+channel` or `type=none`,
+    /// e.g. `idle_prompt=none`. Unrouted types go to every channel.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_route,
+        env = "CLAUDIATOR_NOTIFICATION_ROUTES"
+    )]
+    pub notification_routes: Vec<(String, Vec<Channel>)>,
+
+    /// Least severe failed or interrupted tool call that notifies: `info`,
+    /// `warning`, or `error`. Failures are `error` and interruptions
+    /// `warning`, unless the event names its own severity.
+    #[arg(
+        long,
+        default_value = "warning",
+        value_parser = Severity::parse,
+        env = "CLAUDIATOR_ERROR_NOTIFICATION_MIN_SEVERITY"
+    )]
+    pub error_notification_min_severity: Severity,
+
+    /// Per-event-type storage tiers as `type=headers` or `type=full`, e.g.
+    /// `PreToolUse=headers,PostToolUse=headers`. Unlisted types are stored
+    /// in full.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_rule,
+        env = "CLAUDIATOR_EVENT_STORAGE"
+    )]
+    pub event_storage: Vec<(String, StorageTier)>,
+
+    /// Events admitted for ingestion at once; beyond this, ingest returns 503.
+    #[arg(
+        long,
+        default_value = "64",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        env = "CLAUDIATOR_INGEST_QUEUE_CAPACITY"
+    )]
+    pub ingest_queue_capacity: usize,
+
+    /// Seconds export, search, and overview requests may run before they
+    /// get 408 and their query is interrupted.
+    #[arg(
+        long,
+        default_value = "15",
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..),
+        env = "CLAUDIATOR_HEAVY_ROUTE_TIMEOUT_SECS"
+    )]
+    pub heavy_route_timeout_secs: u64,
+
+    /// Export, search, and overview requests run at once; beyond this, they
+    /// get 503.
+    #[arg(
+        long,
+        default_value = "2",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        env = "CLAUDIATOR_HEAVY_ROUTE_CONCURRENCY"
+    )]
+    pub heavy_route_concurrency: usize,
+
+    /// `SQLite` connections the server may hold open at once.
+    #[arg(
+        long,
+        default_value = "4",
+        value_parser = clap::value_parser!(u32).range(1..),
+        env = "CLAUDIATOR_DB_POOL_SIZE"
+    )]
+    pub db_pool_size: u32,
+
+    /// Seconds a request waits for a free database connection before it
+    /// fails.
+    #[arg(
+        long,
+        default_value = "30",
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..),
+        env = "CLAUDIATOR_DB_POOL_TIMEOUT_SECS"
+    )]
+    pub db_pool_timeout_secs: u64,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "first-prompt",
+        env = "CLAUDIATOR_SESSION_TITLE_STRATEGY"
+    )]
+    pub session_title_strategy: TitleStrategy,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_TITLE_TEMPLATE,
+        env = "CLAUDIATOR_SESSION_TITLE_TEMPLATE"
+    )]
+    pub session_title_template: String,
+
+    /// Key encrypting prompts, event JSON, and session titles at rest, as
+    /// base64 of 32 bytes (`openssl rand -base64 32`).
+    #[arg(long, env = "CLAUDIATOR_ENCRYPTION_KEY", hide_env_values = true)]
+    pub encryption_key: Option<String>,
+
+    /// Read `--encryption-key` from this file instead, e.g. a systemd
+    /// credential.
+    #[arg(
+        long,
+        env = "CLAUDIATOR_ENCRYPTION_KEY_FILE",
+        conflicts_with = "encryption_key"
+    )]
+    pub encryption_key_file: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Moves a relative `db_path` and `log_dir` under `data_dir`, if one is
+    /// set. Absolute paths are left alone.
+    #[allow(dead_code)]
+    pub fn resolve_paths(&mut self) {
+        let Some(data_dir) = &self.data_dir else {
+            return;
+        };
+        for path in [&mut self.db_path, &mut self.log_dir] {
+            if Path::new(path.as_str()).is_relative() {
+                *path = data_dir.join(&*path).display().to_string();
+            }
+        }
+    }
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Generate a master key and env file, migrate the database, and print
+    /// hook config.
+    Setup(SetupArgs),
+}
+
+fn parse_version(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.split('.').all(|part| part.parse::<u64>().is_ok()) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("expected a version like 0.3.1, got '{value}'"))
+    }
+}
+
+fn parse_type_ttl(value: &str) -> Result<(String, u64), String> {
+    let (kind, hours) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `type=hours`, got `{value}`"))?;
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(format!("missing notification type in `{value}`"));
+    }
+    let hours: u64 = hours
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid hours in `{value}`"))?;
+    if hours == 0 {
+        return Err(format!("hours must be at least 1 in `{value}`"));
+    }
+    Ok((kind.to_string(), hours))
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
@@ -58,6 +315,28 @@ mod tests {
         assert_eq!(config.log_level, "debug");
     }
 
+    #[test]
+    fn data_dir_resolves_relative_paths() {
+        let mut config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--data-dir",
+            "/var/lib/claudiator",
+            "--log-dir",
+            "/var/log/claudiator",
+        ])
+        .unwrap();
+        config.resolve_paths();
+        assert_eq!(config.db_path, "/var/lib/claudiator/claudiator.db");
+        assert_eq!(config.log_dir, "/var/log/claudiator");
+
+        let mut config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        config.resolve_paths();
+        assert_eq!(config.db_path, "claudiator.db");
+        assert_eq!(config.log_dir, "logs");
+    }
+
     #[test]
     fn default_log_dir_is_logs() {
         let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
@@ -109,4 +388,411 @@ mod tests {
         .unwrap();
         assert_eq!(config.retention_devices_days, 60);
     }
+
+    #[test]
+    fn default_retention_push_tokens_days_is_30() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.retention_push_tokens_days, 30);
+    }
+
+    #[test]
+    fn default_maintenance_interval_is_24_hours() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.maintenance_interval_hours, 24);
+    }
+
+    #[test]
+    fn custom_maintenance_interval() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--maintenance-interval-hours",
+            "0",
+        ])
+        .unwrap();
+        assert_eq!(config.maintenance_interval_hours, 0);
+    }
+
+    #[test]
+    fn archive_events_disabled_by_default() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(!config.archive_events);
+    }
+
+    #[test]
+    fn privacy_mode_flag() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(!config.privacy_mode);
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--privacy-mode"]).unwrap();
+        assert!(config.privacy_mode);
+    }
+
+    #[test]
+    fn redact_log_prompts_flag() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(!config.redact_log_prompts);
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--redact-log-prompts"])
+                .unwrap();
+        assert!(config.redact_log_prompts);
+    }
+
+    #[test]
+    fn error_notification_min_severity_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.error_notification_min_severity, Severity::Warning);
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--error-notification-min-severity",
+            "error",
+        ])
+        .unwrap();
+        assert_eq!(config.error_notification_min_severity, Severity::Error);
+        assert!(ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--error-notification-min-severity",
+            "fatal",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn min_hook_version_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(
+            config.min_hook_version,
+            crate::router::DEFAULT_MIN_HOOK_VERSION
+        );
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--min-hook-version", "0.4.0"])
+                .unwrap();
+        assert_eq!(config.min_hook_version, "0.4.0");
+        assert!(ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--min-hook-version",
+            "latest",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn archive_events_flag() {
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--archive-events"]).unwrap();
+        assert!(config.archive_events);
+    }
+
+    #[test]
+    fn apns_url_override_unset_by_default() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(config.apns_url_override.is_none());
+    }
+
+    #[test]
+    fn custom_apns_url_override() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--apns-url-override",
+            "http://localhost:3000",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.apns_url_override.as_deref(),
+            Some("http://localhost:3000")
+        );
+    }
+
+    #[test]
+    fn apns_localized_alerts_flag() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(!config.apns_localized_alerts);
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--apns-localized-alerts"])
+                .unwrap();
+        assert!(config.apns_localized_alerts);
+    }
+
+    #[test]
+    fn default_session_title_strategy_is_first_prompt() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.session_title_strategy, TitleStrategy::FirstPrompt);
+        assert_eq!(config.session_title_template, "{dir}: {prompt}");
+    }
+
+    #[test]
+    fn custom_session_title_strategy() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--session-title-strategy",
+            "template",
+            "--session-title-template",
+            "[{dir}] {prompt}",
+        ])
+        .unwrap();
+        assert_eq!(config.session_title_strategy, TitleStrategy::Template);
+        assert_eq!(config.session_title_template, "[{dir}] {prompt}");
+    }
+
+    #[test]
+    fn invalid_session_title_strategy_rejected() {
+        let result = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--session-title-strategy",
+            "random",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn default_notification_ttl_is_24_hours() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.notification_ttl_hours, 24);
+        assert!(config.notification_type_ttl.is_empty());
+    }
+
+    #[test]
+    fn custom_notification_ttl() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--notification-ttl-hours",
+            "168",
+            "--notification-type-ttl",
+            "permission_prompt=1,idle_prompt=2",
+        ])
+        .unwrap();
+        assert_eq!(config.notification_ttl_hours, 168);
+        assert_eq!(
+            config.notification_type_ttl,
+            vec![
+                ("permission_prompt".to_string(), 1),
+                ("idle_prompt".to_string(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_notification_ttl_rejected() {
+        for args in [
+            ["--notification-ttl-hours", "0"],
+            ["--notification-type-ttl", "stop"],
+            ["--notification-type-ttl", "stop=0"],
+            ["--notification-type-ttl", "=5"],
+            ["--notification-type-ttl", "stop=soon"],
+        ] {
+            let result = ServerConfig::try_parse_from(["test", "--api-key", "k", args[0], args[1]]);
+            assert!(result.is_err(), "{args:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn notification_routes_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(config.notification_routes.is_empty());
+
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--notification-routes",
+            "permission_prompt=apns,idle_prompt=none",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.notification_routes,
+            vec![
+                ("permission_prompt".to_string(), vec![Channel::Apns]),
+                ("idle_prompt".to_string(), Vec::new())
+            ]
+        );
+
+        let result = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--notification-routes",
+            "stop=carrier-pigeon",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn event_storage_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(config.event_storage.is_empty());
+
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--event-storage",
+            "PreToolUse=headers,PostToolUse=headers",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.event_storage,
+            vec![
+                ("PreToolUse".to_string(), StorageTier::Headers),
+                ("PostToolUse".to_string(), StorageTier::Headers)
+            ]
+        );
+
+        let result = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--event-storage",
+            "PreToolUse=compressed",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn db_pool_settings_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.db_pool_size, 4);
+        assert_eq!(config.db_pool_timeout_secs, 30);
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--db-pool-size",
+            "16",
+            "--db-pool-timeout-secs",
+            "5",
+        ])
+        .unwrap();
+        assert_eq!(config.db_pool_size, 16);
+        assert_eq!(config.db_pool_timeout_secs, 5);
+        assert!(
+            ServerConfig::try_parse_from(["test", "--api-key", "k", "--db-pool-size", "0"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn heavy_route_limits_parsed() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.heavy_route_timeout_secs, 15);
+        assert_eq!(config.heavy_route_concurrency, 2);
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--heavy-route-timeout-secs",
+            "60",
+            "--heavy-route-concurrency",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(config.heavy_route_timeout_secs, 60);
+        assert_eq!(config.heavy_route_concurrency, 1);
+        assert!(ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--heavy-route-concurrency",
+            "0",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn default_ingest_queue_capacity_is_64() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert_eq!(config.ingest_queue_capacity, 64);
+    }
+
+    #[test]
+    fn custom_ingest_queue_capacity() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--ingest-queue-capacity",
+            "8",
+        ])
+        .unwrap();
+        assert_eq!(config.ingest_queue_capacity, 8);
+
+        let result = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--ingest-queue-capacity",
+            "0",
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn previous_api_key_unset_by_default() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(config.previous_api_key.is_none());
+    }
+
+    #[test]
+    fn custom_previous_api_key() {
+        let config =
+            ServerConfig::try_parse_from(["test", "--api-key", "new", "--previous-api-key", "old"])
+                .unwrap();
+        assert_eq!(config.api_key.as_deref(), Some("new"));
+        assert_eq!(config.previous_api_key.as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn public_url_unset_by_default() {
+        let config = ServerConfig::try_parse_from(["test", "--api-key", "k"]).unwrap();
+        assert!(config.public_url.is_none());
+    }
+
+    #[test]
+    fn custom_public_url() {
+        let config = ServerConfig::try_parse_from([
+            "test",
+            "--api-key",
+            "k",
+            "--public-url",
+            "https://claudiator.example.com",
+        ])
+        .unwrap();
+        assert_eq!(
+            config.public_url.as_deref(),
+            Some("https://claudiator.example.com")
+        );
+    }
+
+    #[test]
+    fn api_key_required_to_serve() {
+        let result = ServerConfig::try_parse_from(["test"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn setup_does_not_need_api_key() {
+        let config =
+            ServerConfig::try_parse_from(["test", "setup", "--hook-key", "--port", "8080"])
+                .unwrap();
+        assert!(config.api_key.is_none());
+        let Some(Command::Setup(args)) = config.command else {
+            panic!("expected setup subcommand");
+        };
+        assert!(args.hook_key);
+        assert_eq!(args.port, 8080);
+    }
 }
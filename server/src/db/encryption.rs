@@ -0,0 +1,300 @@
+//! Optional encryption at rest for the columns that hold what users typed:
+//! `events.event_json` (which carries prompts), `events_archive.event_json`,
+//! and `sessions.title`.
+//!
+//! Values are sealed with XChaCha20-Poly1305 under a random nonce and stored
+//! as `enc:v1:<base64 of nonce and ciphertext>`. The work happens inside
+//! `SQLite`: every pooled connection gets `encrypt_field()` and
+//! `decrypt_field()` functions, and queries wrap those columns in them. So
+//! queries stay the same whether or not a key is configured, and rows written
+//! before a key was set keep reading back as they are.
+//!
+//! Encrypted values can't be searched: the full-text indexes skip them.
+
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rusqlite::functions::{Context, FunctionFlags};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::Connection;
+
+/// Marks a stored value as encrypted. The SQL in `migrations` that keeps
+/// encrypted titles out of the search index matches on it too.
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+const NONCE_LEN: usize = 24;
+
+/// A field encryption key.
+#[derive(Clone)]
+pub struct FieldCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl std::fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FieldCipher(..)")
+    }
+}
+
+impl FieldCipher {
+    /// Parses a key given as base64 of 32 bytes, e.g. from
+    /// `openssl rand -base64 32`. Surrounding whitespace is ignored, so a key
+    /// file may end in a newline.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `key` isn't base64 or doesn't decode to 32 bytes.
+    pub fn from_base64(key: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("Encryption key is not valid base64: {e}"))?;
+        let cipher = XChaCha20Poly1305::new_from_slice(&bytes).map_err(|_| {
+            format!(
+                "Encryption key must be 32 bytes, got {}; generate one with `openssl rand -base64 32`",
+                bytes.len()
+            )
+        })?;
+        Ok(Self { cipher })
+    }
+
+    /// The key from `--encryption-key`, or read from `--encryption-key-file`,
+    /// if either is set.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the key file can't be read or the key is malformed.
+    pub fn from_config(key: Option<&str>, key_file: Option<&Path>) -> Result<Option<Self>, String> {
+        let key = match (key, key_file) {
+            (Some(key), _) => key.to_string(),
+            (None, Some(path)) => fs::read_to_string(path).map_err(|e| {
+                format!("Failed to read encryption key file {}: {e}", path.display())
+            })?,
+            (None, None) => return Ok(None),
+        };
+        Self::from_base64(&key).map(Some)
+    }
+
+    /// A new random key, base64 encoded for [`FieldCipher::from_base64`].
+    pub fn generate_key() -> String {
+        STANDARD.encode(XChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    /// Seals `plaintext` into its stored form.
+    ///
+    /// # Errors
+    ///
+    /// Fails only if the cipher does, which it doesn't for inputs that fit
+    /// in memory.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "Failed to encrypt value".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", STANDARD.encode(sealed)))
+    }
+
+    /// Opens a value [`FieldCipher::encrypt`] produced.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `stored` isn't an encrypted value, or was sealed with another
+    /// key or altered since.
+    pub fn decrypt(&self, stored: &str) -> Result<String, String> {
+        let sealed = stored
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .filter(|sealed| sealed.len() >= NONCE_LEN)
+            .ok_or_else(|| "Stored value is not a valid encrypted value".to_string())?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| {
+                "Failed to decrypt stored value: wrong encryption key, or the value was altered"
+                    .to_string()
+            })?;
+        String::from_utf8(plaintext).map_err(|e| format!("Decrypted value is not UTF-8: {e}"))
+    }
+}
+
+/// Registers `encrypt_field(value)` and `decrypt_field(value)` on `conn`.
+///
+/// Both pass `NULL` through. Without a cipher, `encrypt_field` stores values
+/// as they are, and `decrypt_field` fails on encrypted ones rather than
+/// returning ciphertext as if it were data.
+///
+/// # Errors
+///
+/// Fails if `SQLite` rejects the functions.
+pub fn register_functions(
+    conn: &Connection,
+    cipher: Option<&Arc<FieldCipher>>,
+) -> rusqlite::Result<()> {
+    let encrypt_cipher = cipher.cloned();
+    conn.create_scalar_function(
+        "encrypt_field",
+        1,
+        FunctionFlags::SQLITE_UTF8,
+        move |ctx| match (text_arg(ctx)?, &encrypt_cipher) {
+            (Some(text), Some(cipher)) => {
+                cipher.encrypt(&text).map(Value::Text).map_err(user_error)
+            }
+            (Some(text), None) => Ok(Value::Text(text)),
+            (None, _) => Ok(Value::Null),
+        },
+    )?;
+
+    let decrypt_cipher = cipher.cloned();
+    conn.create_scalar_function(
+        "decrypt_field",
+        1,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| match text_arg(ctx)? {
+            Some(text) if text.starts_with(ENCRYPTED_PREFIX) => decrypt_cipher
+                .as_ref()
+                .ok_or_else(|| {
+                    user_error(
+                        "Found an encrypted value but no encryption key is configured".to_string(),
+                    )
+                })?
+                .decrypt(&text)
+                .map(Value::Text)
+                .map_err(user_error),
+            Some(text) => Ok(Value::Text(text)),
+            None => Ok(Value::Null),
+        },
+    )
+}
+
+fn text_arg(ctx: &Context<'_>) -> rusqlite::Result<Option<String>> {
+    match ctx.get_raw(0) {
+        ValueRef::Null => Ok(None),
+        value => value
+            .as_str()
+            .map(|text| Some(text.to_string()))
+            .map_err(|e| user_error(format!("Expected a text value: {e}"))),
+    }
+}
+
+fn user_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::UserFunctionError(message.into())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> FieldCipher {
+        FieldCipher::from_base64(&FieldCipher::generate_key()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_uses_fresh_nonces() {
+        let cipher = cipher();
+        let first = cipher.encrypt("fix the build").unwrap();
+        let second = cipher.encrypt("fix the build").unwrap();
+        assert!(first.starts_with(ENCRYPTED_PREFIX));
+        assert!(!first.contains("build"));
+        assert_ne!(first, second);
+        assert_eq!(cipher.decrypt(&first).unwrap(), "fix the build");
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering_are_rejected() {
+        let sealed = cipher().encrypt("secret").unwrap();
+        assert!(cipher().decrypt(&sealed).is_err());
+
+        let cipher = cipher();
+        let sealed = cipher.encrypt("secret").unwrap();
+        let mut tampered = sealed.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(cipher
+            .decrypt(&String::from_utf8(tampered).unwrap())
+            .is_err());
+        assert!(cipher.decrypt("plain").is_err());
+    }
+
+    #[test]
+    fn test_key_must_be_32_bytes_of_base64() {
+        assert!(FieldCipher::from_base64("not base64!").is_err());
+        assert!(FieldCipher::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(FieldCipher::from_base64(&format!("{}\n", STANDARD.encode([7u8; 32]))).is_ok());
+    }
+
+    #[test]
+    fn test_key_from_config_value_or_file() {
+        assert!(FieldCipher::from_config(None, None).unwrap().is_none());
+
+        let key = FieldCipher::generate_key();
+        assert!(FieldCipher::from_config(Some(&key), None)
+            .unwrap()
+            .is_some());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("key");
+        assert!(FieldCipher::from_config(None, Some(&path)).is_err());
+        fs::write(&path, format!("{key}\n")).unwrap();
+        let from_file = FieldCipher::from_config(None, Some(&path))
+            .unwrap()
+            .unwrap();
+        let from_value = FieldCipher::from_config(Some(&key), None).unwrap().unwrap();
+        assert_eq!(
+            from_value
+                .decrypt(&from_file.encrypt("same key").unwrap())
+                .unwrap(),
+            "same key"
+        );
+    }
+
+    #[test]
+    fn test_sql_functions() {
+        let conn = Connection::open_in_memory().unwrap();
+        let cipher = Arc::new(cipher());
+        register_functions(&conn, Some(&cipher)).unwrap();
+
+        let sealed: String = conn
+            .query_row("SELECT encrypt_field('hello')", [], |row| row.get(0))
+            .unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        let opened: String = conn
+            .query_row("SELECT decrypt_field(?1)", [&sealed], |row| row.get(0))
+            .unwrap();
+        assert_eq!(opened, "hello");
+        let plain: String = conn
+            .query_row(
+                "SELECT decrypt_field('written before the key')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(plain, "written before the key");
+        let null: Option<String> = conn
+            .query_row("SELECT encrypt_field(NULL)", [], |row| row.get(0))
+            .unwrap();
+        assert!(null.is_none());
+
+        // Without a key, values are stored as they are and encrypted ones
+        // can't be read
+        let keyless = Connection::open_in_memory().unwrap();
+        register_functions(&keyless, None).unwrap();
+        let stored: String = keyless
+            .query_row("SELECT encrypt_field('hello')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, "hello");
+        assert!(keyless
+            .query_row("SELECT decrypt_field(?1)", [&sealed], |row| {
+                row.get::<_, String>(0)
+            })
+            .is_err());
+    }
+}
@@ -1,9 +1,27 @@
 use crate::db::pool::DbPool;
 
-#[allow(clippy::missing_errors_doc)]
+#[allow(clippy::missing_errors_doc, clippy::too_many_lines)]
 pub fn run(pool: &DbPool) -> Result<(), Box<dyn std::error::Error>> {
     let conn = pool.get()?;
 
+    // Enable incremental auto-vacuum so retention deletes can actually shrink the
+    // file. A new database takes the mode as is; an existing one needs a full
+    // VACUUM, which rewrites the whole file, so it is left to an explicit
+    // `POST /admin/maintenance/vacuum` rather than run on every upgrade.
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if auto_vacuum != 2 {
+        let tables: i64 =
+            conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get(0))?;
+        if tables == 0 {
+            conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL;")?;
+        } else {
+            tracing::warn!(
+                "Database is not in incremental auto-vacuum mode, so maintenance can't shrink it; \
+                 run POST /admin/maintenance/vacuum once to convert it"
+            );
+        }
+    }
+
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS devices (
             device_id   TEXT PRIMARY KEY,
@@ -82,6 +100,34 @@ pub fn run(pool: &DbPool) -> Result<(), Box<dyn std::error::Error>> {
         [],
     );
 
+    // Add sandbox_tagged to push_tokens (idempotent). Tokens registered without
+    // a sandbox flag use the server's --apns-sandbox default. Existing sandbox
+    // tokens must have been tagged; existing production rows can't be told
+    // apart from untagged ones, so they keep following the default as before.
+    if conn
+        .execute(
+            "ALTER TABLE push_tokens ADD COLUMN sandbox_tagged INTEGER NOT NULL DEFAULT 0",
+            [],
+        )
+        .is_ok()
+    {
+        conn.execute(
+            "UPDATE push_tokens SET sandbox_tagged = 1 WHERE sandbox = 1",
+            [],
+        )?;
+    }
+
+    // Add last_seen and last_success to push_tokens (idempotent). last_seen is
+    // the last registration, last_success the last push APNs accepted; tokens
+    // with neither inside --retention-push-tokens-days are pruned.
+    if conn
+        .execute("ALTER TABLE push_tokens ADD COLUMN last_seen TEXT", [])
+        .is_ok()
+    {
+        conn.execute("UPDATE push_tokens SET last_seen = updated_at", [])?;
+    }
+    let _ = conn.execute("ALTER TABLE push_tokens ADD COLUMN last_success TEXT", []);
+
     // Add metadata table for version persistence (idempotent)
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS metadata (
@@ -111,6 +157,288 @@ pub fn run(pool: &DbPool) -> Result<(), Box<dyn std::error::Error>> {
     // Add rate_limit column to api_keys (idempotent)
     let _ = conn.execute("ALTER TABLE api_keys ADD COLUMN rate_limit INTEGER", []);
 
+    // Add events_archive table for retention soft-deletes (idempotent).
+    // No foreign keys so sessions/devices can still be cleaned up once archived.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS events_archive (
+            id                INTEGER PRIMARY KEY,
+            device_id         TEXT NOT NULL,
+            session_id        TEXT NOT NULL,
+            hook_event_name   TEXT NOT NULL,
+            timestamp         TEXT NOT NULL,
+            received_at       TEXT NOT NULL,
+            tool_name         TEXT,
+            notification_type TEXT,
+            event_json        TEXT NOT NULL,
+            archived_at       TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_events_archive_session_id ON events_archive(session_id);",
+    )?;
+
+    // Add partition columns for key-scoped data (idempotent). NULL is the
+    // default partition, so existing rows stay visible to existing keys.
+    let _ = conn.execute("ALTER TABLE api_keys ADD COLUMN partition TEXT", []);
+    let _ = conn.execute("ALTER TABLE devices ADD COLUMN partition TEXT", []);
+    let _ = conn.execute("ALTER TABLE push_tokens ADD COLUMN partition TEXT", []);
+    conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_devices_partition ON devices(partition);")?;
+
+    // Add pinned column to sessions (idempotent)
+    let _ = conn.execute(
+        "ALTER TABLE sessions ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Add responses table for remote permission decisions (idempotent).
+    // delivered_at stays NULL until the hook has fetched the response.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS responses (
+            id              TEXT PRIMARY KEY,
+            notification_id TEXT NOT NULL UNIQUE,
+            session_id      TEXT NOT NULL,
+            decision        TEXT NOT NULL,
+            message         TEXT,
+            created_at      TEXT NOT NULL,
+            delivered_at    TEXT
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_responses_session_id ON responses(session_id);",
+    )?;
+
+    // Add key_usage table for per-key daily traffic counts (idempotent).
+    // day is the UTC date as YYYY-MM-DD.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS key_usage (
+            key_id   TEXT NOT NULL,
+            day      TEXT NOT NULL,
+            requests INTEGER NOT NULL DEFAULT 0,
+            events   INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (key_id, day)
+        );",
+    )?;
+
+    // Add enrollment_codes table and device-bound keys for hook onboarding
+    // (idempotent). A code is spent once: used_at and key_id are set when a
+    // hook redeems it.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS enrollment_codes (
+            code       TEXT PRIMARY KEY,
+            partition  TEXT,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            used_at    TEXT,
+            key_id     TEXT
+        );",
+    )?;
+    let _ = conn.execute("ALTER TABLE api_keys ADD COLUMN device_id TEXT", []);
+
+    // Add last_heartbeat to devices (idempotent). NULL until the device's
+    // hook sends its first Heartbeat event.
+    let _ = conn.execute("ALTER TABLE devices ADD COLUMN last_heartbeat TEXT", []);
+
+    // Add hook_version to devices (idempotent). NULL until the device sends
+    // an event from a hook that reports its version in `User-Agent`.
+    let _ = conn.execute("ALTER TABLE devices ADD COLUMN hook_version TEXT", []);
+    // UTC day the device was last notified about running an outdated hook
+    let _ = conn.execute(
+        "ALTER TABLE devices ADD COLUMN outdated_hook_notified_on TEXT",
+        [],
+    );
+
+    // Add delivery_failures table for notifications no channel could deliver
+    // (idempotent). Rows hold everything needed to retry the push, and are
+    // deleted once a retry succeeds.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS delivery_failures (
+            id              TEXT PRIMARY KEY,
+            notification_id TEXT NOT NULL,
+            channel         TEXT NOT NULL,
+            partition       TEXT,
+            session_id      TEXT NOT NULL,
+            device_id       TEXT NOT NULL,
+            title           TEXT NOT NULL,
+            body            TEXT NOT NULL,
+            error           TEXT NOT NULL,
+            attempts        INTEGER NOT NULL DEFAULT 1,
+            created_at      TEXT NOT NULL,
+            last_attempt_at TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_delivery_failures_notification_id
+            ON delivery_failures(notification_id);",
+    )?;
+
+    // Add session_summaries table (idempotent). Retention adds each session's
+    // purged events here before deleting them, so long-term statistics survive.
+    // tool_counts is a JSON object of PreToolUse counts keyed by tool name.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS session_summaries (
+            session_id  TEXT PRIMARY KEY,
+            device_id   TEXT NOT NULL,
+            partition   TEXT,
+            title       TEXT,
+            cwd         TEXT,
+            event_count INTEGER NOT NULL DEFAULT 0,
+            tool_counts TEXT NOT NULL DEFAULT '{}',
+            first_event TEXT NOT NULL,
+            last_event  TEXT NOT NULL,
+            updated_at  TEXT NOT NULL
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_session_summaries_device_id
+            ON session_summaries(device_id);",
+    )?;
+
+    // Full-text search over prompts, notification messages, and session
+    // titles (idempotent). Triggers keep the indexes in step with events and
+    // sessions; rows that existed before the indexes are backfilled once.
+    // events_fts shares rowids with events. sessions have no stable integer
+    // key, so sessions_fts carries the session_id instead. Encrypted values
+    // (see `encryption`) are left out: encrypted event JSON fails
+    // `json_valid`, and titles are matched by their prefix. The title
+    // triggers are recreated on every start so databases indexed before they
+    // skipped encrypted titles pick up the current definitions.
+    let search_indexed: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'events_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute_batch(
+        "DROP TRIGGER IF EXISTS sessions_fts_insert;
+        DROP TRIGGER IF EXISTS sessions_fts_update;
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS events_fts USING fts5(
+            prompt, message, tokenize = 'porter unicode61'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS events_fts_insert AFTER INSERT ON events
+        WHEN json_valid(new.event_json)
+            AND (json_extract(new.event_json, '$.prompt') IS NOT NULL
+                 OR json_extract(new.event_json, '$.message') IS NOT NULL)
+        BEGIN
+            INSERT INTO events_fts (rowid, prompt, message)
+            VALUES (new.id, json_extract(new.event_json, '$.prompt'),
+                    json_extract(new.event_json, '$.message'));
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS events_fts_delete AFTER DELETE ON events
+        BEGIN
+            DELETE FROM events_fts WHERE rowid = old.id;
+        END;
+
+        CREATE VIRTUAL TABLE IF NOT EXISTS sessions_fts USING fts5(
+            session_id UNINDEXED, title, tokenize = 'porter unicode61'
+        );
+
+        CREATE TRIGGER IF NOT EXISTS sessions_fts_insert AFTER INSERT ON sessions
+        WHEN new.title IS NOT NULL AND new.title NOT LIKE 'enc:v1:%'
+        BEGIN
+            INSERT INTO sessions_fts (session_id, title) VALUES (new.session_id, new.title);
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS sessions_fts_update AFTER UPDATE OF title ON sessions
+        BEGIN
+            DELETE FROM sessions_fts WHERE session_id = old.session_id;
+            INSERT INTO sessions_fts (session_id, title)
+            SELECT new.session_id, new.title
+            WHERE new.title IS NOT NULL AND new.title NOT LIKE 'enc:v1:%';
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS sessions_fts_delete AFTER DELETE ON sessions
+        BEGIN
+            DELETE FROM sessions_fts WHERE session_id = old.session_id;
+        END;",
+    )?;
+    if !search_indexed {
+        conn.execute_batch(
+            "INSERT INTO events_fts (rowid, prompt, message)
+             SELECT id, json_extract(event_json, '$.prompt'), json_extract(event_json, '$.message')
+             FROM events
+             WHERE json_valid(event_json)
+               AND (json_extract(event_json, '$.prompt') IS NOT NULL
+                    OR json_extract(event_json, '$.message') IS NOT NULL);
+
+             INSERT INTO sessions_fts (session_id, title)
+             SELECT session_id, title FROM sessions
+             WHERE title IS NOT NULL AND title NOT LIKE 'enc:v1:%';",
+        )?;
+    }
+
+    // Index notifications by device for the device list's unacknowledged
+    // counts (idempotent).
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_notifications_device_id
+            ON notifications(device_id, acknowledged);",
+    )?;
+
+    // Add privacy_mode to api_keys (idempotent). Events written with such a
+    // key keep no prompts or messages.
+    let _ = conn.execute(
+        "ALTER TABLE api_keys ADD COLUMN privacy_mode INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Nest subagent sessions under the session that started them
+    // (idempotent). Top-level sessions have a NULL parent.
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN parent_session_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN agent_type TEXT", []);
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_sessions_parent_session_id
+            ON sessions(parent_session_id);",
+    )?;
+
+    // Promote agent team fields out of event_json (idempotent) so sessions
+    // and events can be filtered by team and task
+    let _ = conn.execute("ALTER TABLE events ADD COLUMN team_name TEXT", []);
+    let _ = conn.execute("ALTER TABLE events ADD COLUMN task_id TEXT", []);
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN team_name TEXT", []);
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_events_team_name ON events(team_name);
+        CREATE INDEX IF NOT EXISTS idx_events_task_id ON events(task_id);
+        CREATE INDEX IF NOT EXISTS idx_sessions_team_name ON sessions(team_name);",
+    )?;
+
+    // Claude model reported by SessionStart (idempotent)
+    let _ = conn.execute("ALTER TABLE sessions ADD COLUMN model TEXT", []);
+
+    // Per-session sequence number assigned at insert, breaking ties between
+    // events with the same timestamp (idempotent). Existing events are
+    // numbered in insertion order.
+    let _ = conn.execute("ALTER TABLE events ADD COLUMN seq INTEGER", []);
+    conn.execute_batch(
+        "UPDATE events SET seq = numbered.seq
+         FROM (SELECT id, ROW_NUMBER() OVER (PARTITION BY session_id ORDER BY id) AS seq
+               FROM events) AS numbered
+         WHERE events.id = numbered.id AND events.seq IS NULL;
+        CREATE INDEX IF NOT EXISTS idx_events_session_seq ON events(session_id, seq);",
+    )?;
+
+    // Clock skew correction (idempotent): each event's received_at minus
+    // timestamp, its timestamp corrected for its device's clock, and each
+    // device's current clock offset. Existing events are left uncorrected.
+    let _ = conn.execute("ALTER TABLE events ADD COLUMN skew_ms INTEGER", []);
+    let _ = conn.execute(
+        "ALTER TABLE events ADD COLUMN normalized_timestamp TEXT",
+        [],
+    );
+    let _ = conn.execute("ALTER TABLE devices ADD COLUMN clock_skew_ms INTEGER", []);
+    conn.execute_batch(
+        "UPDATE events
+         SET normalized_timestamp = COALESCE(strftime('%Y-%m-%dT%H:%M:%fZ', timestamp), timestamp)
+         WHERE normalized_timestamp IS NULL;",
+    )?;
+
+    // Snoozed notifications (idempotent): hidden from lists until
+    // snoozed_until, when the snooze task clears it and pushes them again
+    let _ = conn.execute(
+        "ALTER TABLE notifications ADD COLUMN snoozed_until TEXT",
+        [],
+    );
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_notifications_snoozed_until
+            ON notifications(snoozed_until) WHERE snoozed_until IS NOT NULL;",
+    )?;
+
     tracing::info!("Database migrations complete");
     Ok(())
 }
@@ -1,3 +1,5 @@
+pub mod encryption;
 pub mod migrations;
+pub mod partition;
 pub mod pool;
 pub mod queries;
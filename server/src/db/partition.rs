@@ -0,0 +1,61 @@
+//! Key-scoped data partitions.
+
+/// The slice of data a request may read and write.
+///
+/// DB keys are confined to the partition they were created with; keys created
+/// without one share the default partition. The master key sees every
+/// partition, and devices it creates land in the default partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Partition {
+    /// The master key: every partition.
+    All,
+    /// A DB key: only this partition. `None` is the default partition.
+    Key(Option<String>),
+}
+
+impl Partition {
+    pub const fn is_all(&self) -> bool {
+        matches!(self, Self::All)
+    }
+
+    /// The partition name stored on rows this request creates.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::All => None,
+            Self::Key(name) => name.as_deref(),
+        }
+    }
+
+    /// Whether a row stored in partition `other` is visible to this request.
+    pub fn allows(&self, other: Option<&str>) -> bool {
+        match self {
+            Self::All => true,
+            Self::Key(name) => name.as_deref() == other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_all_allows_everything() {
+        assert!(Partition::All.allows(None));
+        assert!(Partition::All.allows(Some("alice")));
+        assert_eq!(Partition::All.name(), None);
+    }
+
+    #[test]
+    fn test_partition_key_allows_only_its_own() {
+        let alice = Partition::Key(Some("alice".to_string()));
+        assert!(alice.allows(Some("alice")));
+        assert!(!alice.allows(Some("bob")));
+        assert!(!alice.allows(None));
+        assert_eq!(alice.name(), Some("alice"));
+
+        let default = Partition::Key(None);
+        assert!(default.allows(None));
+        assert!(!default.allows(Some("alice")));
+    }
+}
@@ -1,38 +1,178 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
 
+use crate::db::encryption::{self, FieldCipher};
+
 pub type DbPool = Pool<SqliteConnectionManager>;
 
-fn setup_connection(conn: &Connection) -> rusqlite::Result<()> {
+/// Connections kept open when no pool size is configured.
+pub const DEFAULT_MAX_SIZE: u32 = 4;
+
+/// Seconds a checkout waits for a free connection when no timeout is
+/// configured.
+pub const DEFAULT_CONNECTION_TIMEOUT_SECS: u64 = 30;
+
+/// Size of a file-backed pool and how long a checkout waits for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSettings {
+    pub max_size: u32,
+    pub connection_timeout: Duration,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_size: DEFAULT_MAX_SIZE,
+            connection_timeout: Duration::from_secs(DEFAULT_CONNECTION_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// Checkouts since the pool was built, counted through r2d2's event hooks.
+/// The pool's own [`r2d2::State`] gives the open and idle connections.
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    checkouts: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl PoolStats {
+    /// Connections handed out.
+    pub fn checkouts(&self) -> u64 {
+        self.checkouts.load(Ordering::Relaxed)
+    }
+
+    /// Time spent waiting for those connections, summed.
+    pub fn wait_total(&self) -> Duration {
+        Duration::from_micros(self.wait_micros.load(Ordering::Relaxed))
+    }
+
+    /// Longest wait for one connection.
+    pub fn wait_max(&self) -> Duration {
+        Duration::from_micros(self.max_wait_micros.load(Ordering::Relaxed))
+    }
+
+    /// Checkouts that gave up after the connection timeout.
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct StatsHandler(Arc<PoolStats>);
+
+impl HandleEvent for StatsHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        let micros = u64::try_from(event.duration().as_micros()).unwrap_or(u64::MAX);
+        self.0.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.0.wait_micros.fetch_add(micros, Ordering::Relaxed);
+        self.0.max_wait_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn handle_timeout(&self, _event: TimeoutEvent) {
+        self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn setup_connection(conn: &Connection, cipher: Option<&Arc<FieldCipher>>) -> rusqlite::Result<()> {
     conn.execute_batch(
         "PRAGMA journal_mode = WAL;
          PRAGMA synchronous = NORMAL;
          PRAGMA foreign_keys = ON;
          PRAGMA busy_timeout = 5000;",
-    )
+    )?;
+    encryption::register_functions(conn, cipher)
 }
 
 #[allow(clippy::missing_errors_doc)]
 pub fn create_pool(db_path: &str) -> Result<DbPool, Box<dyn std::error::Error>> {
-    let manager = SqliteConnectionManager::file(db_path);
+    create_encrypted_pool(db_path, None)
+}
+
+/// Like [`create_pool`], with `cipher` encrypting the columns listed in
+/// [`encryption`] on write. `None` stores them as plaintext.
+#[allow(clippy::missing_errors_doc)]
+pub fn create_encrypted_pool(
+    db_path: &str,
+    cipher: Option<FieldCipher>,
+) -> Result<DbPool, Box<dyn std::error::Error>> {
+    create_configured_pool(db_path, cipher, PoolSettings::default(), Arc::default())
+}
+
+/// Like [`create_encrypted_pool`], sized by `settings`, with checkouts
+/// counted in `stats`.
+#[allow(clippy::missing_errors_doc)]
+pub fn create_configured_pool(
+    db_path: &str,
+    cipher: Option<FieldCipher>,
+    settings: PoolSettings,
+    stats: Arc<PoolStats>,
+) -> Result<DbPool, Box<dyn std::error::Error>> {
+    build_pool(
+        SqliteConnectionManager::file(db_path),
+        settings,
+        cipher,
+        stats,
+    )
+}
+
+/// A private in-memory database with the same connection setup, for tests.
+/// It has a single connection, since each in-memory connection is a separate
+/// database.
+#[allow(clippy::missing_errors_doc, dead_code)]
+pub fn create_memory_pool(
+    cipher: Option<FieldCipher>,
+) -> Result<DbPool, Box<dyn std::error::Error>> {
+    let settings = PoolSettings {
+        max_size: 1,
+        ..PoolSettings::default()
+    };
+    build_pool(
+        SqliteConnectionManager::memory(),
+        settings,
+        cipher,
+        Arc::default(),
+    )
+}
+
+fn build_pool(
+    manager: SqliteConnectionManager,
+    settings: PoolSettings,
+    cipher: Option<FieldCipher>,
+    stats: Arc<PoolStats>,
+) -> Result<DbPool, Box<dyn std::error::Error>> {
+    let cipher = cipher.map(Arc::new);
     let pool = Pool::builder()
-        .max_size(4)
-        .connection_customizer(Box::new(ConnectionCustomizer))
+        .max_size(settings.max_size)
+        .connection_timeout(settings.connection_timeout)
+        .event_handler(Box::new(StatsHandler(stats)))
+        .connection_customizer(Box::new(ConnectionCustomizer {
+            cipher: cipher.clone(),
+        }))
         .build(manager)?;
 
     // Verify we can get a connection and pragmas work
     let conn = pool.get()?;
-    setup_connection(&conn)?;
+    setup_connection(&conn, cipher.as_ref())?;
 
     Ok(pool)
 }
 
 #[derive(Debug)]
-struct ConnectionCustomizer;
+struct ConnectionCustomizer {
+    cipher: Option<Arc<FieldCipher>>,
+}
 
 impl r2d2::CustomizeConnection<Connection, rusqlite::Error> for ConnectionCustomizer {
     fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
-        setup_connection(conn)
+        setup_connection(conn, self.cipher.as_ref())
     }
 }
@@ -1,32 +1,186 @@
 #![allow(clippy::option_if_let_else)]
 #![allow(clippy::missing_errors_doc)]
 
-use rusqlite::Connection;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
 
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::clock_skew;
+use crate::db::partition::Partition;
 use crate::error::AppError;
 use crate::models::response::{
-    DeviceResponse, EventResponse, NotificationResponse, SessionResponse,
+    ArchivedEventResponse, DeliveryFailure, DeviceResponse, EventDetailResponse, EventResponse,
+    EventSearchHit, NotificationResponse, PermissionResponse, SessionResponse, SessionSearchHit,
+    SessionStatusCounts, TimelineEvent,
 };
+use crate::time_range::TimeRange;
+
+/// Condition limiting rows to a request's partition, where `d` is the joined
+/// `devices` row. Bind it with [`partition_params`].
+const PARTITION_FILTER: &str = "(:all_partitions OR d.partition IS :partition)";
+
+fn partition_params(partition: &Partition) -> [(&'static str, Box<dyn rusqlite::types::ToSql>); 2] {
+    [
+        (":all_partitions", Box::new(partition.is_all())),
+        (":partition", Box::new(partition.name().map(String::from))),
+    ]
+}
 
+/// Inserts or refreshes a device. The partition is only set on insert; a
+/// device never moves between partitions.
 pub fn upsert_device(
     conn: &Connection,
     device_id: &str,
     device_name: &str,
     platform: &str,
+    partition: Option<&str>,
     now: &str,
 ) -> Result<(), AppError> {
     conn.execute(
-        "INSERT INTO devices (device_id, device_name, platform, first_seen, last_seen)
-         VALUES (?1, ?2, ?3, ?4, ?4)
+        "INSERT INTO devices (device_id, device_name, platform, partition, first_seen, last_seen)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
          ON CONFLICT(device_id) DO UPDATE SET
             device_name = excluded.device_name,
             last_seen = excluded.last_seen",
-        rusqlite::params![device_id, device_name, platform, now],
+        rusqlite::params![device_id, device_name, platform, partition, now],
     )
     .map_err(|e| AppError::Internal(format!("Failed to upsert device: {e}")))?;
     Ok(())
 }
 
+/// The device's `last_heartbeat`, or `None` if it is unknown or has never
+/// sent one.
+pub fn device_last_heartbeat(
+    conn: &Connection,
+    device_id: &str,
+) -> Result<Option<String>, AppError> {
+    conn.query_row(
+        "SELECT last_heartbeat FROM devices WHERE device_id = ?1",
+        [device_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(Option::flatten)
+    .map_err(|e| AppError::Internal(format!("Failed to read device heartbeat: {e}")))
+}
+
+pub fn set_device_heartbeat(conn: &Connection, device_id: &str, now: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE devices SET last_heartbeat = ?1 WHERE device_id = ?2",
+        rusqlite::params![now, device_id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to record device heartbeat: {e}")))?;
+    Ok(())
+}
+
+/// Records the hook version `device_id` last reported.
+pub fn set_device_hook_version(
+    conn: &Connection,
+    device_id: &str,
+    hook_version: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE devices SET hook_version = ?1 WHERE device_id = ?2",
+        rusqlite::params![hook_version, device_id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to record device hook version: {e}")))?;
+    Ok(())
+}
+
+/// Records `device_id`'s current clock offset; see [`crate::clock_skew`].
+pub fn set_device_clock_skew(
+    conn: &Connection,
+    device_id: &str,
+    clock_skew_ms: i64,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE devices SET clock_skew_ms = ?1 WHERE device_id = ?2",
+        rusqlite::params![clock_skew_ms, device_id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to record device clock skew: {e}")))?;
+    Ok(())
+}
+
+/// The smallest skew among `device_id`'s events received since `since`, or
+/// `None` if there are none.
+pub fn min_device_skew(
+    conn: &Connection,
+    device_id: &str,
+    since: &str,
+) -> Result<Option<i64>, AppError> {
+    conn.query_row(
+        "SELECT MIN(skew_ms) FROM events WHERE device_id = ?1 AND received_at >= ?2",
+        rusqlite::params![device_id, since],
+        |row| row.get(0),
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to query device clock skew: {e}")))
+}
+
+/// Claims `device_id`'s outdated hook notice for `day` (a
+/// [`USAGE_DAY_FORMAT`] date). Returns `true` only for the first claim of
+/// the day, so concurrent events notify once.
+pub fn claim_outdated_hook_notice(
+    conn: &Connection,
+    device_id: &str,
+    day: &str,
+) -> Result<bool, AppError> {
+    let claimed = conn
+        .execute(
+            "UPDATE devices SET outdated_hook_notified_on = ?2
+             WHERE device_id = ?1 AND outdated_hook_notified_on IS NOT ?2",
+            rusqlite::params![device_id, day],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to claim outdated hook notice: {e}")))?;
+    Ok(claimed > 0)
+}
+
+/// Returns the partition of `device_id`, or `None` if the device is unknown.
+pub fn device_partition(conn: &Connection, device_id: &str) -> Result<Option<Partition>, AppError> {
+    query_partition(
+        conn,
+        "SELECT partition FROM devices WHERE device_id = ?1",
+        device_id,
+    )
+}
+
+/// Returns the partition of the device that owns `session_id`, or `None` if
+/// the session is unknown.
+pub fn session_partition(
+    conn: &Connection,
+    session_id: &str,
+) -> Result<Option<Partition>, AppError> {
+    query_partition(
+        conn,
+        "SELECT d.partition FROM sessions s
+         JOIN devices d ON d.device_id = s.device_id
+         WHERE s.session_id = ?1",
+        session_id,
+    )
+}
+
+fn query_partition(conn: &Connection, sql: &str, id: &str) -> Result<Option<Partition>, AppError> {
+    let mut stmt = conn
+        .prepare(sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare partition query: {e}")))?;
+
+    let mut rows = stmt
+        .query(rusqlite::params![id])
+        .map_err(|e| AppError::Internal(format!("Failed to query partition: {e}")))?;
+
+    if let Some(row) = rows
+        .next()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch partition row: {e}")))?
+    {
+        let partition: Option<String> = row
+            .get(0)
+            .map_err(|e| AppError::Internal(format!("Failed to get partition value: {e}")))?;
+        Ok(Some(Partition::Key(partition)))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn upsert_session(
     conn: &Connection,
     session_id: &str,
@@ -41,7 +195,7 @@ pub fn upsert_session(
 
     conn.execute(
         "INSERT INTO sessions (session_id, device_id, started_at, last_event, status, cwd, title)
-         VALUES (?1, ?2, ?3, ?3, ?4, ?5, ?6)
+         VALUES (?1, ?2, ?3, ?3, ?4, ?5, encrypt_field(?6))
          ON CONFLICT(session_id) DO UPDATE SET
             last_event = excluded.last_event,
             cwd = COALESCE(excluded.cwd, sessions.cwd),
@@ -62,6 +216,86 @@ pub fn upsert_session(
     Ok(())
 }
 
+pub fn set_session_title(conn: &Connection, session_id: &str, title: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE sessions SET title = encrypt_field(?1) WHERE session_id = ?2",
+        rusqlite::params![title, session_id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update session title: {e}")))?;
+    Ok(())
+}
+
+/// Marks `session_id` as a subagent of `parent_session_id`, keeping the
+/// stored `agent_type` when `agent_type` is `None`.
+pub fn set_session_parent(
+    conn: &Connection,
+    session_id: &str,
+    parent_session_id: &str,
+    agent_type: Option<&str>,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE sessions SET parent_session_id = ?2, agent_type = COALESCE(?3, agent_type)
+         WHERE session_id = ?1",
+        rusqlite::params![session_id, parent_session_id, agent_type],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update session parent: {e}")))?;
+    Ok(())
+}
+
+/// Records the agent team `session_id` belongs to.
+pub fn set_session_team(
+    conn: &Connection,
+    session_id: &str,
+    team_name: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE sessions SET team_name = ?2 WHERE session_id = ?1",
+        rusqlite::params![session_id, team_name],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update session team: {e}")))?;
+    Ok(())
+}
+
+/// Records the Claude model `session_id` runs on.
+pub fn set_session_model(conn: &Connection, session_id: &str, model: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE sessions SET model = ?2 WHERE session_id = ?1",
+        rusqlite::params![session_id, model],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update session model: {e}")))?;
+    Ok(())
+}
+
+/// Stores an event's skew and its timestamp corrected for clock skew.
+pub fn set_event_clock(
+    conn: &Connection,
+    event_id: i64,
+    skew_ms: i64,
+    normalized_timestamp: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE events SET skew_ms = ?2, normalized_timestamp = ?3 WHERE id = ?1",
+        rusqlite::params![event_id, skew_ms, normalized_timestamp],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update event clock: {e}")))?;
+    Ok(())
+}
+
+/// Stores an event's team and task ids in their indexed columns.
+pub fn set_event_team_task(
+    conn: &Connection,
+    event_id: i64,
+    team_name: Option<&str>,
+    task_id: Option<&str>,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE events SET team_name = ?2, task_id = ?3 WHERE id = ?1",
+        rusqlite::params![event_id, team_name, task_id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update event team: {e}")))?;
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn insert_event(
     conn: &Connection,
@@ -75,8 +309,9 @@ pub fn insert_event(
     event_json: &str,
 ) -> Result<i64, AppError> {
     conn.execute(
-        "INSERT INTO events (device_id, session_id, hook_event_name, timestamp, received_at, tool_name, notification_type, event_json)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        "INSERT INTO events (device_id, session_id, hook_event_name, timestamp, received_at, tool_name, notification_type, event_json, seq)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, encrypt_field(?8),
+                 (SELECT COALESCE(MAX(seq), 0) + 1 FROM events WHERE session_id = ?2))",
         rusqlite::params![
             device_id,
             session_id,
@@ -92,25 +327,53 @@ pub fn insert_event(
     Ok(conn.last_insert_rowid())
 }
 
-pub fn list_devices(conn: &Connection) -> Result<Vec<DeviceResponse>, AppError> {
+/// Devices in `partition`, most recently seen first, each with its active
+/// session count, unacknowledged notifications, and events received since
+/// `events_since`.
+pub fn list_devices(
+    conn: &Connection,
+    partition: &Partition,
+    events_since: &str,
+) -> Result<Vec<DeviceResponse>, AppError> {
+    let sql = format!(
+        "SELECT d.device_id, d.device_name, d.platform, d.first_seen, d.last_seen, d.last_heartbeat, d.hook_version,
+                (SELECT COUNT(*) FROM sessions s WHERE s.device_id = d.device_id AND s.status != 'ended' AND s.parent_session_id IS NULL) AS active_sessions,
+                (SELECT COUNT(*) FROM notifications n WHERE n.device_id = d.device_id AND n.acknowledged = 0) AS unacked_notifications,
+                (SELECT COUNT(*) FROM events e WHERE e.device_id = d.device_id AND e.received_at >= :events_since) AS recent_events,
+                d.clock_skew_ms
+         FROM devices d
+         WHERE {PARTITION_FILTER}
+         ORDER BY d.last_seen DESC"
+    );
     let mut stmt = conn
-        .prepare(
-            "SELECT d.device_id, d.device_name, d.platform, d.first_seen, d.last_seen,
-                    (SELECT COUNT(*) FROM sessions s WHERE s.device_id = d.device_id AND s.status != 'ended') AS active_sessions
-             FROM devices d
-             ORDER BY d.last_seen DESC",
-        )
+        .prepare(&sql)
         .map_err(|e| AppError::Internal(format!("Failed to prepare devices query: {e}")))?;
 
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> =
+        vec![(":events_since", Box::new(events_since.to_string()))];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
     let devices = stmt
-        .query_map([], |row| {
+        .query_map(params_refs.as_slice(), |row| {
+            let device_name: String = row.get(1)?;
+            let clock_skew_ms: Option<i64> = row.get(10)?;
             Ok(DeviceResponse {
                 device_id: row.get(0)?,
-                device_name: row.get(1)?,
+                display_name: device_name.clone(),
+                device_name,
                 platform: row.get(2)?,
                 first_seen: row.get(3)?,
                 last_seen: row.get(4)?,
-                active_sessions: row.get(5)?,
+                last_heartbeat: row.get(5)?,
+                hook_version: row.get(6)?,
+                hook_outdated: false,
+                active_sessions: row.get(7)?,
+                unacked_notifications: row.get(8)?,
+                events_last_24h: row.get(9)?,
+                clock_skew_ms,
+                clock_skewed: clock_skew_ms.is_some_and(clock_skew::is_skewed),
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query devices: {e}")))?
@@ -122,24 +385,29 @@ pub fn list_devices(conn: &Connection) -> Result<Vec<DeviceResponse>, AppError>
 
 pub fn list_sessions(
     conn: &Connection,
+    partition: &Partition,
     device_id: &str,
     status: Option<&str>,
     limit: i64,
 ) -> Result<Vec<SessionResponse>, AppError> {
-    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, s.title, d.device_name, d.platform
+    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
              FROM sessions s
              LEFT JOIN devices d ON d.device_id = s.device_id
-             WHERE s.device_id = :device_id".to_string();
+             WHERE s.device_id = :device_id AND s.parent_session_id IS NULL".to_string();
 
     let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> =
         vec![(":device_id", Box::new(device_id.to_string()))];
 
+    sql.push_str(" AND ");
+    sql.push_str(PARTITION_FILTER);
+    params.extend(partition_params(partition));
+
     if let Some(s) = status {
         sql.push_str(" AND s.status = :status");
         params.push((":status", Box::new(s.to_string())));
     }
 
-    sql.push_str(" ORDER BY s.last_event DESC LIMIT :limit");
+    sql.push_str(" ORDER BY s.pinned DESC, s.last_event DESC LIMIT :limit");
     params.push((":limit", Box::new(limit)));
 
     let mut stmt = conn
@@ -161,6 +429,12 @@ pub fn list_sessions(
                 title: row.get(6)?,
                 device_name: row.get(7)?,
                 platform: row.get(8)?,
+                pinned: row.get(9)?,
+                parent_session_id: row.get(10)?,
+                agent_type: row.get(11)?,
+                team_name: row.get(12)?,
+                model: row.get(13)?,
+                subagents: Vec::new(),
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query sessions: {e}")))?
@@ -173,22 +447,27 @@ pub fn list_sessions(
 #[allow(dead_code)]
 pub fn list_all_sessions(
     conn: &Connection,
+    partition: &Partition,
     status: Option<&str>,
     limit: i64,
 ) -> Result<Vec<SessionResponse>, AppError> {
-    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, s.title, d.device_name, d.platform
+    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
              FROM sessions s
              LEFT JOIN devices d ON d.device_id = s.device_id
-             WHERE 1=1".to_string();
+             WHERE s.parent_session_id IS NULL".to_string();
 
     let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![];
 
+    sql.push_str(" AND ");
+    sql.push_str(PARTITION_FILTER);
+    params.extend(partition_params(partition));
+
     if let Some(s) = status {
         sql.push_str(" AND s.status = :status");
         params.push((":status", Box::new(s.to_string())));
     }
 
-    sql.push_str(" ORDER BY s.last_event DESC LIMIT :limit");
+    sql.push_str(" ORDER BY s.pinned DESC, s.last_event DESC LIMIT :limit");
     params.push((":limit", Box::new(limit)));
 
     let mut stmt = conn
@@ -210,6 +489,12 @@ pub fn list_all_sessions(
                 title: row.get(6)?,
                 device_name: row.get(7)?,
                 platform: row.get(8)?,
+                pinned: row.get(9)?,
+                parent_session_id: row.get(10)?,
+                agent_type: row.get(11)?,
+                team_name: row.get(12)?,
+                model: row.get(13)?,
+                subagents: Vec::new(),
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query sessions: {e}")))?
@@ -219,6 +504,7 @@ pub fn list_all_sessions(
     Ok(sessions)
 }
 
+#[derive(Debug, Clone)]
 pub struct PaginatedSessions {
     pub sessions: Vec<SessionResponse>,
     pub has_more: bool,
@@ -227,30 +513,59 @@ pub struct PaginatedSessions {
 
 pub fn list_all_sessions_paginated(
     conn: &Connection,
+    partition: &Partition,
     status: Option<&str>,
+    cwd_prefix: Option<&str>,
+    team: Option<&str>,
     exclude_ended: bool,
+    range: &TimeRange,
     limit: i64,
     offset: i64,
 ) -> Result<PaginatedSessions, AppError> {
     let fetch_limit = limit.saturating_add(1);
 
-    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, s.title, d.device_name, d.platform
+    let mut sql = "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
              FROM sessions s
              LEFT JOIN devices d ON d.device_id = s.device_id
-             WHERE 1=1".to_string();
+             WHERE s.parent_session_id IS NULL".to_string();
 
     let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![];
 
+    sql.push_str(" AND ");
+    sql.push_str(PARTITION_FILTER);
+    params.extend(partition_params(partition));
+
     if let Some(s) = status {
         sql.push_str(" AND s.status = :status");
         params.push((":status", Box::new(s.to_string())));
     }
 
+    if let Some(prefix) = cwd_prefix {
+        // substr rather than LIKE so `%` and `_` in paths match literally
+        sql.push_str(" AND substr(s.cwd, 1, length(:cwd_prefix)) = :cwd_prefix");
+        params.push((":cwd_prefix", Box::new(prefix.to_string())));
+    }
+
+    if let Some(team) = team {
+        sql.push_str(" AND s.team_name = :team");
+        params.push((":team", Box::new(team.to_string())));
+    }
+
     if exclude_ended {
         sql.push_str(" AND s.status != 'ended'");
     }
 
-    sql.push_str(" ORDER BY CASE WHEN s.status != 'ended' THEN 0 ELSE 1 END ASC, s.last_event DESC LIMIT :limit OFFSET :offset");
+    // A session is in range when it was active at any point within it
+    if let Some(ref from) = range.from {
+        sql.push_str(" AND s.last_event >= :from");
+        params.push((":from", Box::new(from.clone())));
+    }
+    if let Some(ref to) = range.to {
+        sql.push_str(" AND s.started_at < :to");
+        params.push((":to", Box::new(to.clone())));
+    }
+
+    sql.push_str(" ORDER BY s.pinned DESC, CASE WHEN s.status != 'ended' THEN 0 ELSE 1 END ASC, s.last_event DESC LIMIT :limit OFFSET :offset");
     params.push((":limit", Box::new(fetch_limit)));
     params.push((":offset", Box::new(offset)));
 
@@ -273,6 +588,12 @@ pub fn list_all_sessions_paginated(
                 title: row.get(6)?,
                 device_name: row.get(7)?,
                 platform: row.get(8)?,
+                pinned: row.get(9)?,
+                parent_session_id: row.get(10)?,
+                agent_type: row.get(11)?,
+                team_name: row.get(12)?,
+                model: row.get(13)?,
+                subagents: Vec::new(),
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query sessions: {e}")))?
@@ -295,24 +616,333 @@ pub fn list_all_sessions_paginated(
     })
 }
 
+/// Fetches a single session, or `None` if it doesn't exist in `partition`.
+pub fn get_session(
+    conn: &Connection,
+    partition: &Partition,
+    session_id: &str,
+) -> Result<Option<SessionResponse>, AppError> {
+    let sql = format!(
+        "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
+         FROM sessions s
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE s.session_id = :session_id AND {PARTITION_FILTER}"
+    );
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> =
+        vec![(":session_id", Box::new(session_id.to_string()))];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare session query: {e}")))?;
+
+    let mut sessions = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok(SessionResponse {
+                session_id: row.get(0)?,
+                device_id: row.get(1)?,
+                started_at: row.get(2)?,
+                last_event: row.get(3)?,
+                status: row.get(4)?,
+                cwd: row.get(5)?,
+                title: row.get(6)?,
+                device_name: row.get(7)?,
+                platform: row.get(8)?,
+                pinned: row.get(9)?,
+                parent_session_id: row.get(10)?,
+                agent_type: row.get(11)?,
+                team_name: row.get(12)?,
+                model: row.get(13)?,
+                subagents: Vec::new(),
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query session: {e}")))?;
+
+    sessions
+        .next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch session row: {e}")))
+}
+
+/// Subagent sessions started by `parent_session_id` in `partition`, oldest
+/// first.
+pub fn list_subagent_sessions(
+    conn: &Connection,
+    partition: &Partition,
+    parent_session_id: &str,
+) -> Result<Vec<SessionResponse>, AppError> {
+    let sql = format!(
+        "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
+         FROM sessions s
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE s.parent_session_id = :parent_session_id AND {PARTITION_FILTER}
+         ORDER BY s.started_at ASC"
+    );
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![(
+        ":parent_session_id",
+        Box::new(parent_session_id.to_string()),
+    )];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare subagents query: {e}")))?;
+
+    let sessions = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok(SessionResponse {
+                session_id: row.get(0)?,
+                device_id: row.get(1)?,
+                started_at: row.get(2)?,
+                last_event: row.get(3)?,
+                status: row.get(4)?,
+                cwd: row.get(5)?,
+                title: row.get(6)?,
+                device_name: row.get(7)?,
+                platform: row.get(8)?,
+                pinned: row.get(9)?,
+                parent_session_id: row.get(10)?,
+                agent_type: row.get(11)?,
+                team_name: row.get(12)?,
+                model: row.get(13)?,
+                subagents: Vec::new(),
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query subagents: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect subagents: {e}")))?;
+
+    Ok(sessions)
+}
+
+/// Number of sessions in `partition` in each status except `ended`.
+pub fn count_sessions_by_status(
+    conn: &Connection,
+    partition: &Partition,
+) -> Result<SessionStatusCounts, AppError> {
+    let sql = format!(
+        "SELECT s.status, COUNT(*)
+         FROM sessions s
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE s.status != 'ended' AND s.parent_session_id IS NULL AND {PARTITION_FILTER}
+         GROUP BY s.status"
+    );
+    let params = partition_params(partition);
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare status count query: {e}")))?;
+    let rows = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to count sessions: {e}")))?;
+
+    let mut counts = SessionStatusCounts::default();
+    for row in rows {
+        let (status, count) =
+            row.map_err(|e| AppError::Internal(format!("Failed to read status count: {e}")))?;
+        match status.as_str() {
+            "active" => counts.active = count,
+            "waiting_for_permission" => counts.waiting_for_permission = count,
+            "waiting_for_input" => counts.waiting_for_input = count,
+            "idle" => counts.idle = count,
+            "compacting" => counts.compacting = count,
+            _ => {}
+        }
+    }
+    Ok(counts)
+}
+
+/// Sessions in `partition` by the Claude model they ran on, ended ones
+/// included. Subagents and sessions without a model are not counted.
+pub fn count_sessions_by_model(
+    conn: &Connection,
+    partition: &Partition,
+) -> Result<BTreeMap<String, i64>, AppError> {
+    let sql = format!(
+        "SELECT s.model, COUNT(*)
+         FROM sessions s
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE s.model IS NOT NULL AND s.parent_session_id IS NULL AND {PARTITION_FILTER}
+         GROUP BY s.model"
+    );
+    let params = partition_params(partition);
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare model count query: {e}")))?;
+    let rows = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to count sessions by model: {e}")))?;
+
+    rows.collect::<Result<_, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to read model count: {e}")))
+}
+
+/// The session in `partition` most in need of the user.
+///
+/// A permission prompt comes first, then one waiting for input, then an idle
+/// one, longest-waiting first within each. With nothing waiting, the most
+/// recently active session. `None` if every session has ended.
+pub fn most_urgent_session(
+    conn: &Connection,
+    partition: &Partition,
+) -> Result<Option<SessionResponse>, AppError> {
+    let sql = format!(
+        "SELECT s.session_id, s.device_id, s.started_at, s.last_event, s.status, s.cwd, decrypt_field(s.title), d.device_name, d.platform, s.pinned, s.parent_session_id, s.agent_type, s.team_name, s.model
+         FROM sessions s
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE s.status != 'ended' AND s.parent_session_id IS NULL AND {PARTITION_FILTER}
+         ORDER BY CASE s.status
+                      WHEN 'waiting_for_permission' THEN 0
+                      WHEN 'waiting_for_input' THEN 1
+                      WHEN 'idle' THEN 2
+                      ELSE 3
+                  END,
+                  CASE WHEN s.status = 'active' THEN s.last_event END DESC,
+                  s.last_event ASC
+         LIMIT 1"
+    );
+    let params = partition_params(partition);
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    conn.query_row(&sql, params_refs.as_slice(), |row| {
+        Ok(SessionResponse {
+            session_id: row.get(0)?,
+            device_id: row.get(1)?,
+            started_at: row.get(2)?,
+            last_event: row.get(3)?,
+            status: row.get(4)?,
+            cwd: row.get(5)?,
+            title: row.get(6)?,
+            device_name: row.get(7)?,
+            platform: row.get(8)?,
+            pinned: row.get(9)?,
+            parent_session_id: row.get(10)?,
+            agent_type: row.get(11)?,
+            team_name: row.get(12)?,
+            model: row.get(13)?,
+            subagents: Vec::new(),
+        })
+    })
+    .optional()
+    .map_err(|e| AppError::Internal(format!("Failed to query most urgent session: {e}")))
+}
+
+/// Pins or unpins a session. Sessions outside `partition` are left untouched.
+pub fn set_session_pinned(
+    conn: &Connection,
+    partition: &Partition,
+    session_id: &str,
+    pinned: bool,
+) -> Result<(), AppError> {
+    let sql = format!(
+        "UPDATE sessions SET pinned = :pinned
+         WHERE session_id = :session_id
+           AND device_id IN (SELECT d.device_id FROM devices d WHERE {PARTITION_FILTER})"
+    );
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":pinned", Box::new(pinned)),
+        (":session_id", Box::new(session_id.to_string())),
+    ];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    conn.execute(&sql, params_refs.as_slice())
+        .map_err(|e| AppError::Internal(format!("Failed to update session: {e}")))?;
+
+    Ok(())
+}
+
+/// Narrows [`list_events`] to some event types and/or one tool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// `hook_event_name`s to include; empty includes every type.
+    pub types: Vec<String>,
+    /// Only events whose `tool_name` is this.
+    pub tool: Option<String>,
+    /// Only events whose `task_id` is this.
+    pub task: Option<String>,
+}
+
 pub fn list_events(
     conn: &Connection,
+    partition: &Partition,
     session_id: &str,
+    filter: &EventFilter,
     limit: i64,
 ) -> Result<Vec<EventResponse>, AppError> {
+    let mut params: Vec<(String, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":session_id".to_string(), Box::new(session_id.to_string())),
+        (":limit".to_string(), Box::new(limit)),
+    ];
+    let mut conditions = String::new();
+    if !filter.types.is_empty() {
+        let names: Vec<String> = (0..filter.types.len())
+            .map(|i| format!(":type{i}"))
+            .collect();
+        let _ = write!(
+            conditions,
+            " AND e.hook_event_name IN ({})",
+            names.join(", ")
+        );
+        for (name, kind) in names.into_iter().zip(&filter.types) {
+            params.push((name, Box::new(kind.clone())));
+        }
+    }
+    if let Some(ref tool) = filter.tool {
+        conditions.push_str(" AND e.tool_name = :tool");
+        params.push((":tool".to_string(), Box::new(tool.clone())));
+    }
+    if let Some(ref task) = filter.task {
+        conditions.push_str(" AND e.task_id = :task");
+        params.push((":task".to_string(), Box::new(task.clone())));
+    }
+
+    let sql = format!(
+        "SELECT e.id, e.hook_event_name, e.timestamp, e.tool_name, e.notification_type,
+                json_extract(decrypt_field(e.event_json), '$.message') AS message,
+                e.team_name, e.task_id,
+                json_extract(decrypt_field(e.event_json), '$.task_subject') AS task_subject,
+                e.seq, COALESCE(e.normalized_timestamp, e.timestamp) AS normalized_timestamp
+         FROM events e
+         LEFT JOIN devices d ON d.device_id = e.device_id
+         WHERE e.session_id = :session_id AND {PARTITION_FILTER}{conditions}
+         ORDER BY normalized_timestamp DESC, e.seq DESC
+         LIMIT :limit"
+    );
     let mut stmt = conn
-        .prepare(
-            "SELECT e.id, e.hook_event_name, e.timestamp, e.tool_name, e.notification_type,
-                    json_extract(e.event_json, '$.message') AS message
-             FROM events e
-             WHERE e.session_id = ?1
-             ORDER BY e.timestamp DESC
-             LIMIT ?2",
-        )
+        .prepare(&sql)
         .map_err(|e| AppError::Internal(format!("Failed to prepare events query: {e}")))?;
 
+    params.extend(
+        partition_params(partition)
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v)),
+    );
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> = params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_ref()))
+        .collect();
+
     let events = stmt
-        .query_map(rusqlite::params![session_id, limit], |row| {
+        .query_map(params_refs.as_slice(), |row| {
             Ok(EventResponse {
                 id: row.get(0)?,
                 hook_event_name: row.get(1)?,
@@ -320,6 +950,11 @@ pub fn list_events(
                 tool_name: row.get(3)?,
                 notification_type: row.get(4)?,
                 message: row.get(5)?,
+                team_name: row.get(6)?,
+                task_id: row.get(7)?,
+                task_subject: row.get(8)?,
+                seq: row.get(9)?,
+                normalized_timestamp: row.get(10)?,
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query events: {e}")))?
@@ -329,43 +964,286 @@ pub fn list_events(
     Ok(events)
 }
 
-pub fn get_session_title(conn: &Connection, session_id: &str) -> Result<Option<String>, AppError> {
-    let mut stmt = conn
-        .prepare("SELECT title FROM sessions WHERE session_id = ?1")
-        .map_err(|e| AppError::Internal(format!("Failed to prepare session title query: {e}")))?;
-
-    let mut rows = stmt
-        .query(rusqlite::params![session_id])
-        .map_err(|e| AppError::Internal(format!("Failed to query session title: {e}")))?;
-
-    if let Some(row) = rows
-        .next()
-        .map_err(|e| AppError::Internal(format!("Failed to fetch session title row: {e}")))?
-    {
-        let title: Option<String> = row
-            .get(0)
-            .map_err(|e| AppError::Internal(format!("Failed to get session title value: {e}")))?;
-        Ok(title)
-    } else {
-        Ok(None)
-    }
+/// Turns free text into an FTS5 `MATCH` expression for rows containing every
+/// word, or `None` when there are no words.
+///
+/// Each word is quoted, so FTS5 operators in the input are searched for
+/// literally.
+pub fn search_expression(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
 }
 
-pub fn upsert_push_token(
+/// Sessions whose title matches `expression` (see [`search_expression`]),
+/// best match first.
+pub fn search_sessions(
+    conn: &Connection,
+    partition: &Partition,
+    expression: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SessionSearchHit>, AppError> {
+    let sql = format!(
+        "SELECT s.session_id, s.device_id, d.device_name, decrypt_field(s.title), s.status, s.last_event,
+                snippet(sessions_fts, 1, '**', '**', '…', 16)
+         FROM sessions_fts
+         JOIN sessions s ON s.session_id = sessions_fts.session_id
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE sessions_fts MATCH :expression AND {PARTITION_FILTER}
+         ORDER BY bm25(sessions_fts), s.last_event DESC
+         LIMIT :limit OFFSET :offset"
+    );
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare session search: {e}")))?;
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":expression", Box::new(expression.to_string())),
+        (":limit", Box::new(limit)),
+        (":offset", Box::new(offset)),
+    ];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let hits = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok(SessionSearchHit {
+                session_id: row.get(0)?,
+                device_id: row.get(1)?,
+                device_name: row.get(2)?,
+                title: row.get(3)?,
+                status: row.get(4)?,
+                last_event: row.get(5)?,
+                snippet: row.get(6)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to search sessions: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect session hits: {e}")))?;
+
+    Ok(hits)
+}
+
+/// Events whose prompt or message matches `expression` (see
+/// [`search_expression`]), best match first.
+pub fn search_events(
+    conn: &Connection,
+    partition: &Partition,
+    expression: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<EventSearchHit>, AppError> {
+    let sql = format!(
+        "SELECT e.id, e.session_id, e.device_id, e.hook_event_name, e.timestamp,
+                snippet(events_fts, -1, '**', '**', '…', 16)
+         FROM events_fts
+         JOIN events e ON e.id = events_fts.rowid
+         LEFT JOIN devices d ON d.device_id = e.device_id
+         WHERE events_fts MATCH :expression AND {PARTITION_FILTER}
+         ORDER BY bm25(events_fts), e.timestamp DESC
+         LIMIT :limit OFFSET :offset"
+    );
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare event search: {e}")))?;
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":expression", Box::new(expression.to_string())),
+        (":limit", Box::new(limit)),
+        (":offset", Box::new(offset)),
+    ];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let hits = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok(EventSearchHit {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                device_id: row.get(2)?,
+                hook_event_name: row.get(3)?,
+                timestamp: row.get(4)?,
+                snippet: row.get(5)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to search events: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect event hits: {e}")))?;
+
+    Ok(hits)
+}
+
+/// A session's events in the order they happened, for export.
+pub fn list_session_timeline(
+    conn: &Connection,
+    partition: &Partition,
+    session_id: &str,
+    limit: i64,
+) -> Result<Vec<TimelineEvent>, AppError> {
+    let sql = format!(
+        "SELECT e.hook_event_name, e.timestamp, e.tool_name,
+                json_extract(decrypt_field(e.event_json), '$.message') AS message,
+                json_extract(decrypt_field(e.event_json), '$.prompt') AS prompt,
+                json_extract(decrypt_field(e.event_json), '$.title') AS title,
+                json_extract(decrypt_field(e.event_json), '$.trigger') AS trigger,
+                json_extract(decrypt_field(e.event_json), '$.error') AS error,
+                json_extract(decrypt_field(e.event_json), '$.is_interrupt') AS is_interrupt
+         FROM events e
+         LEFT JOIN devices d ON d.device_id = e.device_id
+         WHERE e.session_id = :session_id AND {PARTITION_FILTER}
+         ORDER BY COALESCE(e.normalized_timestamp, e.timestamp) ASC, e.seq ASC
+         LIMIT :limit"
+    );
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare timeline query: {e}")))?;
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":session_id", Box::new(session_id.to_string())),
+        (":limit", Box::new(limit)),
+    ];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let events = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            Ok(TimelineEvent {
+                hook_event_name: row.get(0)?,
+                timestamp: row.get(1)?,
+                tool_name: row.get(2)?,
+                message: row.get(3)?,
+                prompt: row.get(4)?,
+                title: row.get(5)?,
+                trigger: row.get(6)?,
+                error: row.get(7)?,
+                is_interrupt: row.get(8)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query timeline: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect timeline: {e}")))?;
+
+    Ok(events)
+}
+
+pub fn get_event(
+    conn: &Connection,
+    partition: &Partition,
+    id: i64,
+) -> Result<Option<EventDetailResponse>, AppError> {
+    let sql = format!(
+        "SELECT e.id, e.device_id, e.session_id, e.hook_event_name, e.timestamp, e.received_at,
+                e.tool_name, e.notification_type, decrypt_field(e.event_json),
+                COALESCE(e.normalized_timestamp, e.timestamp), e.skew_ms
+         FROM events e
+         LEFT JOIN devices d ON d.device_id = e.device_id
+         WHERE e.id = :id AND {PARTITION_FILTER}"
+    );
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare event query: {e}")))?;
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![(":id", Box::new(id))];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut rows = stmt
+        .query(params_refs.as_slice())
+        .map_err(|e| AppError::Internal(format!("Failed to query event: {e}")))?;
+
+    let Some(row) = rows
+        .next()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch event row: {e}")))?
+    else {
+        return Ok(None);
+    };
+
+    let get_err = |e: rusqlite::Error| AppError::Internal(format!("Failed to read event row: {e}"));
+    let event_json: String = row.get(8).map_err(get_err)?;
+    let event_json: serde_json::Value = serde_json::from_str(&event_json)
+        .map_err(|e| AppError::Internal(format!("Stored event_json is not valid JSON: {e}")))?;
+    let permission_suggestions = event_json
+        .get("permission_suggestions")
+        .filter(|_| event_json["hook_event_name"] == "PermissionRequest")
+        .cloned();
+
+    Ok(Some(EventDetailResponse {
+        id: row.get(0).map_err(get_err)?,
+        device_id: row.get(1).map_err(get_err)?,
+        session_id: row.get(2).map_err(get_err)?,
+        hook_event_name: row.get(3).map_err(get_err)?,
+        timestamp: row.get(4).map_err(get_err)?,
+        received_at: row.get(5).map_err(get_err)?,
+        tool_name: row.get(6).map_err(get_err)?,
+        notification_type: row.get(7).map_err(get_err)?,
+        normalized_timestamp: row.get(9).map_err(get_err)?,
+        skew_ms: row.get(10).map_err(get_err)?,
+        permission_suggestions,
+        event_json,
+    }))
+}
+
+pub fn get_session_title(conn: &Connection, session_id: &str) -> Result<Option<String>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT decrypt_field(title) FROM sessions WHERE session_id = ?1")
+        .map_err(|e| AppError::Internal(format!("Failed to prepare session title query: {e}")))?;
+
+    let mut rows = stmt
+        .query(rusqlite::params![session_id])
+        .map_err(|e| AppError::Internal(format!("Failed to query session title: {e}")))?;
+
+    if let Some(row) = rows
+        .next()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch session title row: {e}")))?
+    {
+        let title: Option<String> = row
+            .get(0)
+            .map_err(|e| AppError::Internal(format!("Failed to get session title value: {e}")))?;
+        Ok(title)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Registers a push token in `partition`. Re-registering an existing token
+/// moves it to the registering key's partition.
+///
+/// `sandbox` is the token's APNs environment, or `None` when the client didn't
+/// say. An untagged re-registration keeps the environment the token was
+/// already tagged with.
+pub fn upsert_push_token(
     conn: &Connection,
     platform: &str,
     push_token: &str,
     now: &str,
-    sandbox: bool,
+    sandbox: Option<bool>,
+    partition: Option<&str>,
 ) -> Result<(), AppError> {
     conn.execute(
-        "INSERT INTO push_tokens (platform, push_token, created_at, updated_at, sandbox)
-         VALUES (?1, ?2, ?3, ?3, ?4)
+        "INSERT INTO push_tokens (platform, push_token, created_at, updated_at, last_seen, sandbox, sandbox_tagged, partition)
+         VALUES (?1, ?2, ?3, ?3, ?3, ?4, ?5, ?6)
          ON CONFLICT(push_token) DO UPDATE SET
             platform = excluded.platform,
             updated_at = excluded.updated_at,
-            sandbox = excluded.sandbox",
-        rusqlite::params![platform, push_token, now, i32::from(sandbox)],
+            last_seen = excluded.last_seen,
+            sandbox = CASE WHEN excluded.sandbox_tagged THEN excluded.sandbox ELSE sandbox END,
+            sandbox_tagged = MAX(sandbox_tagged, excluded.sandbox_tagged),
+            partition = excluded.partition",
+        rusqlite::params![
+            platform,
+            push_token,
+            now,
+            i32::from(sandbox.unwrap_or(false)),
+            i32::from(sandbox.is_some()),
+            partition
+        ],
     )
     .map_err(|e| AppError::Internal(format!("Failed to upsert push token: {e}")))?;
     Ok(())
@@ -395,21 +1273,44 @@ pub fn insert_notification(
 
 pub fn list_notifications(
     conn: &Connection,
+    partition: &Partition,
+    session_id: Option<&str>,
     after_timestamp: Option<&str>,
+    range: &TimeRange,
     limit: i64,
 ) -> Result<Vec<NotificationResponse>, AppError> {
-    let mut sql = "SELECT id, event_id, session_id, device_id, title, body, notification_type, payload_json, created_at, acknowledged
-             FROM notifications
-             WHERE 1=1".to_string();
+    let mut sql = "SELECT n.id, n.event_id, n.session_id, n.device_id, n.title, n.body, n.notification_type, n.payload_json, n.created_at, n.acknowledged
+             FROM notifications n
+             LEFT JOIN devices d ON d.device_id = n.device_id
+             WHERE ".to_string();
 
     let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![];
 
+    sql.push_str(PARTITION_FILTER);
+    params.extend(partition_params(partition));
+
+    if let Some(session_id) = session_id {
+        sql.push_str(" AND n.session_id = :session_id");
+        params.push((":session_id", Box::new(session_id.to_string())));
+    }
+
+    sql.push_str(" AND n.snoozed_until IS NULL");
+
     if let Some(ts) = after_timestamp {
-        sql.push_str(" AND created_at > :after_timestamp");
+        sql.push_str(" AND n.created_at > :after_timestamp");
         params.push((":after_timestamp", Box::new(ts.to_string())));
     }
 
-    sql.push_str(" ORDER BY created_at ASC LIMIT :limit");
+    if let Some(ref from) = range.from {
+        sql.push_str(" AND n.created_at >= :from");
+        params.push((":from", Box::new(from.clone())));
+    }
+    if let Some(ref to) = range.to {
+        sql.push_str(" AND n.created_at < :to");
+        params.push((":to", Box::new(to.clone())));
+    }
+
+    sql.push_str(" ORDER BY n.created_at ASC LIMIT :limit");
     params.push((":limit", Box::new(limit)));
 
     let mut stmt = conn
@@ -442,18 +1343,147 @@ pub fn list_notifications(
     Ok(notifications)
 }
 
-pub fn delete_expired_notifications(conn: &Connection) -> Result<usize, AppError> {
-    let cutoff = chrono::Utc::now()
-        .checked_sub_signed(chrono::Duration::hours(24))
+/// Fetches a single notification, or `None` if it doesn't exist in `partition`.
+pub fn get_notification(
+    conn: &Connection,
+    partition: &Partition,
+    id: &str,
+) -> Result<Option<NotificationResponse>, AppError> {
+    let sql = format!(
+        "SELECT n.id, n.event_id, n.session_id, n.device_id, n.title, n.body, n.notification_type, n.payload_json, n.created_at, n.acknowledged
+         FROM notifications n
+         LEFT JOIN devices d ON d.device_id = n.device_id
+         WHERE n.id = :id AND {PARTITION_FILTER}"
+    );
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> =
+        vec![(":id", Box::new(id.to_string()))];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut stmt = conn
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare notification query: {e}")))?;
+
+    let mut notifications = stmt
+        .query_map(params_refs.as_slice(), |row| {
+            let acknowledged_int: i32 = row.get(9)?;
+            Ok(NotificationResponse {
+                id: row.get(0)?,
+                event_id: row.get(1)?,
+                session_id: row.get(2)?,
+                device_id: row.get(3)?,
+                title: row.get(4)?,
+                body: row.get(5)?,
+                notification_type: row.get(6)?,
+                payload_json: row.get(7)?,
+                created_at: row.get(8)?,
+                acknowledged: acknowledged_int != 0,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query notification: {e}")))?;
+
+    notifications
+        .next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch notification row: {e}")))
+}
+
+/// How long notifications are kept before retention cleanup deletes them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationTtl {
+    /// Applies to every type without an override.
+    pub default_hours: u64,
+    /// `(notification_type, hours)` overrides.
+    pub per_type: Vec<(String, u64)>,
+}
+
+impl NotificationTtl {
+    pub const DEFAULT_HOURS: u64 = 24;
+
+    /// TTL in hours for notifications of `notification_type`.
+    pub fn hours_for(&self, notification_type: &str) -> u64 {
+        self.per_type
+            .iter()
+            .find(|(kind, _)| kind == notification_type)
+            .map_or(self.default_hours, |(_, hours)| *hours)
+    }
+}
+
+impl Default for NotificationTtl {
+    fn default() -> Self {
+        Self {
+            default_hours: Self::DEFAULT_HOURS,
+            per_type: Vec::new(),
+        }
+    }
+}
+
+fn hours_ago(hours: u64) -> Result<String, AppError> {
+    let hours = i64::try_from(hours)
+        .map_err(|_| AppError::Internal("Time calculation overflow".to_string()))?;
+    Ok(chrono::Utc::now()
+        .checked_sub_signed(chrono::Duration::hours(hours))
         .ok_or_else(|| AppError::Internal("Time calculation overflow".to_string()))?
-        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+}
+
+pub fn delete_expired_notifications(
+    conn: &Connection,
+    ttl: &NotificationTtl,
+) -> Result<usize, AppError> {
+    let mut count = 0;
+
+    for (notification_type, hours) in &ttl.per_type {
+        let cutoff = hours_ago(*hours)?;
+        count += conn
+            .execute(
+                "DELETE FROM notifications WHERE notification_type = ?1 AND created_at < ?2",
+                rusqlite::params![notification_type, cutoff],
+            )
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to delete expired notifications: {e}"))
+            })?;
+    }
+
+    let cutoff = hours_ago(ttl.default_hours)?;
+    let placeholders = ttl
+        .per_type
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(",");
+    let sql = format!(
+        "DELETE FROM notifications
+         WHERE created_at < ? AND notification_type NOT IN ({placeholders})"
+    );
+    let mut params: Vec<&dyn rusqlite::types::ToSql> = vec![&cutoff];
+    params.extend(
+        ttl.per_type
+            .iter()
+            .map(|(kind, _)| kind as &dyn rusqlite::types::ToSql),
+    );
+
+    count += conn
+        .execute(&sql, params.as_slice())
+        .map_err(|e| AppError::Internal(format!("Failed to delete expired notifications: {e}")))?;
+
+    Ok(count)
+}
+
+/// Deletes permission responses older than `ttl_hours`. Callers pass the
+/// `permission_prompt` notification TTL so a response never outlives the
+/// notification it answers.
+pub fn delete_expired_responses(conn: &Connection, ttl_hours: u64) -> Result<usize, AppError> {
+    let cutoff = hours_ago(ttl_hours)?;
 
     let count = conn
         .execute(
-            "DELETE FROM notifications WHERE created_at < ?1",
+            "DELETE FROM responses WHERE created_at < ?1",
             rusqlite::params![cutoff],
         )
-        .map_err(|e| AppError::Internal(format!("Failed to delete expired notifications: {e}")))?;
+        .map_err(|e| AppError::Internal(format!("Failed to delete expired responses: {e}")))?;
 
     Ok(count)
 }
@@ -465,16 +1495,220 @@ pub fn delete_old_events(conn: &Connection, retention_days: u64) -> Result<usize
         .ok_or_else(|| AppError::Internal("Time calculation overflow".to_string()))?
         .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
 
-    let count = conn
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| AppError::Internal(format!("Transaction begin failed: {e}")))?;
+
+    summarize_events_before(&tx, &cutoff, &now)?;
+
+    let count = tx
         .execute(
             "DELETE FROM events WHERE received_at < ?1",
             rusqlite::params![cutoff],
         )
         .map_err(|e| AppError::Internal(format!("Failed to delete old events: {e}")))?;
 
+    tx.commit()
+        .map_err(|e| AppError::Internal(format!("Transaction commit failed: {e}")))?;
+
     Ok(count)
 }
 
+/// Moves events older than the retention window into `events_archive` instead
+/// of deleting them outright. Returns the number of events archived.
+pub fn archive_old_events(conn: &Connection, retention_days: u64) -> Result<usize, AppError> {
+    #[allow(clippy::cast_possible_wrap)]
+    let cutoff = chrono::Utc::now()
+        .checked_sub_signed(chrono::Duration::days(retention_days as i64))
+        .ok_or_else(|| AppError::Internal("Time calculation overflow".to_string()))?
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| AppError::Internal(format!("Transaction begin failed: {e}")))?;
+
+    summarize_events_before(&tx, &cutoff, &now)?;
+
+    tx.execute(
+        "INSERT OR IGNORE INTO events_archive (id, device_id, session_id, hook_event_name, timestamp, received_at, tool_name, notification_type, event_json, archived_at)
+         SELECT id, device_id, session_id, hook_event_name, timestamp, received_at, tool_name, notification_type, event_json, ?2
+         FROM events WHERE received_at < ?1",
+        rusqlite::params![cutoff, now],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to archive old events: {e}")))?;
+
+    let count = tx
+        .execute(
+            "DELETE FROM events WHERE received_at < ?1",
+            rusqlite::params![cutoff],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to delete archived events: {e}")))?;
+
+    tx.commit()
+        .map_err(|e| AppError::Internal(format!("Transaction commit failed: {e}")))?;
+
+    Ok(count)
+}
+
+/// Adds the events received before `cutoff` to their sessions' rows in
+/// `session_summaries`, so event counts, tool usage, and session duration
+/// survive retention. Must run in the same transaction as the delete, or a
+/// failed delete would count the same events twice on the next run.
+fn summarize_events_before(conn: &Connection, cutoff: &str, now: &str) -> Result<(), AppError> {
+    let map_err =
+        |e: rusqlite::Error| AppError::Internal(format!("Failed to summarize events: {e}"));
+
+    let mut tool_counts = tool_counts_before(conn, cutoff).map_err(map_err)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT e.session_id, MIN(e.device_id), MAX(d.partition), MAX(s.title), MAX(s.cwd),
+                    COUNT(*), MIN(e.timestamp), MAX(e.timestamp)
+             FROM events e
+             LEFT JOIN sessions s ON s.session_id = e.session_id
+             LEFT JOIN devices d ON d.device_id = e.device_id
+             WHERE e.received_at < ?1
+             GROUP BY e.session_id",
+        )
+        .map_err(map_err)?;
+    let sessions = stmt
+        .query_map(rusqlite::params![cutoff], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, i64>(5)?,
+                row.get::<_, String>(6)?,
+                row.get::<_, String>(7)?,
+            ))
+        })
+        .map_err(map_err)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(map_err)?;
+
+    for (session_id, device_id, partition, title, cwd, event_count, first_event, last_event) in
+        sessions
+    {
+        let existing: Option<String> = conn
+            .query_row(
+                "SELECT tool_counts FROM session_summaries WHERE session_id = ?1",
+                rusqlite::params![session_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(map_err)?;
+        let mut tools: BTreeMap<String, i64> = existing
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        for (tool, count) in tool_counts.remove(&session_id).unwrap_or_default() {
+            *tools.entry(tool).or_default() += count;
+        }
+        let tools_json = serde_json::to_string(&tools)
+            .map_err(|e| AppError::Internal(format!("Failed to encode tool counts: {e}")))?;
+
+        conn.execute(
+            "INSERT INTO session_summaries (session_id, device_id, partition, title, cwd, event_count, tool_counts, first_event, last_event, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(session_id) DO UPDATE SET
+                partition   = COALESCE(excluded.partition, partition),
+                title       = COALESCE(excluded.title, title),
+                cwd         = COALESCE(excluded.cwd, cwd),
+                event_count = event_count + excluded.event_count,
+                tool_counts = excluded.tool_counts,
+                first_event = MIN(first_event, excluded.first_event),
+                last_event  = MAX(last_event, excluded.last_event),
+                updated_at  = excluded.updated_at",
+            rusqlite::params![
+                session_id,
+                device_id,
+                partition,
+                title,
+                cwd,
+                event_count,
+                tools_json,
+                first_event,
+                last_event,
+                now
+            ],
+        )
+        .map_err(map_err)?;
+    }
+
+    Ok(())
+}
+
+/// `PreToolUse` counts per session and tool for events received before `cutoff`.
+fn tool_counts_before(
+    conn: &Connection,
+    cutoff: &str,
+) -> rusqlite::Result<HashMap<String, BTreeMap<String, i64>>> {
+    let mut stmt = conn.prepare(
+        "SELECT session_id, tool_name, COUNT(*) FROM events
+         WHERE received_at < ?1 AND hook_event_name = 'PreToolUse' AND tool_name IS NOT NULL
+         GROUP BY session_id, tool_name",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![cutoff], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+        ))
+    })?;
+
+    let mut counts: HashMap<String, BTreeMap<String, i64>> = HashMap::new();
+    for row in rows {
+        let (session_id, tool_name, count) = row?;
+        counts
+            .entry(session_id)
+            .or_default()
+            .insert(tool_name, count);
+    }
+    Ok(counts)
+}
+
+pub fn list_archived_events(
+    conn: &Connection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<ArchivedEventResponse>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, device_id, session_id, hook_event_name, timestamp, received_at,
+                    tool_name, notification_type, decrypt_field(event_json), archived_at
+             FROM events_archive
+             WHERE id > ?1
+             ORDER BY id ASC
+             LIMIT ?2",
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to prepare archive query: {e}")))?;
+
+    let events = stmt
+        .query_map(rusqlite::params![after_id, limit], |row| {
+            Ok(ArchivedEventResponse {
+                id: row.get(0)?,
+                device_id: row.get(1)?,
+                session_id: row.get(2)?,
+                hook_event_name: row.get(3)?,
+                timestamp: row.get(4)?,
+                received_at: row.get(5)?,
+                tool_name: row.get(6)?,
+                notification_type: row.get(7)?,
+                event_json: row.get(8)?,
+                archived_at: row.get(9)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query archived events: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect archived events: {e}")))?;
+
+    Ok(events)
+}
+
 pub fn delete_stale_sessions(conn: &Connection, retention_days: u64) -> Result<usize, AppError> {
     #[allow(clippy::cast_possible_wrap)]
     let cutoff = chrono::Utc::now()
@@ -513,25 +1747,105 @@ pub fn delete_stale_devices(conn: &Connection, retention_days: u64) -> Result<us
     Ok(count)
 }
 
+/// Database file statistics captured around a maintenance pass.
+pub struct MaintenanceStats {
+    pub page_count_before: i64,
+    pub page_count_after: i64,
+    pub freelist_count_before: i64,
+}
+
+fn pragma_value(conn: &Connection, pragma: &str) -> Result<i64, AppError> {
+    conn.query_row(&format!("PRAGMA {pragma}"), [], |row| row.get(0))
+        .map_err(|e| AppError::Internal(format!("Failed to read PRAGMA {pragma}: {e}")))
+}
+
+/// Returns free pages to the filesystem and refreshes query planner statistics.
+///
+/// Relies on `auto_vacuum = INCREMENTAL`, which new databases start with and
+/// [`full_vacuum`] converts older ones to, so that pages released by retention
+/// deletes can be truncated from the end of the file.
+pub fn run_maintenance(conn: &Connection) -> Result<MaintenanceStats, AppError> {
+    let page_count_before = pragma_value(conn, "page_count")?;
+    let freelist_count_before = pragma_value(conn, "freelist_count")?;
+
+    // incremental_vacuum frees one page per step, so drain it to completion
+    let mut stmt = conn
+        .prepare("PRAGMA incremental_vacuum")
+        .map_err(|e| AppError::Internal(format!("Failed to prepare incremental vacuum: {e}")))?;
+    let mut rows = stmt
+        .query([])
+        .map_err(|e| AppError::Internal(format!("Failed to run incremental vacuum: {e}")))?;
+    while rows
+        .next()
+        .map_err(|e| AppError::Internal(format!("Failed to run incremental vacuum: {e}")))?
+        .is_some()
+    {}
+    drop(rows);
+    drop(stmt);
+
+    conn.execute_batch("PRAGMA optimize;")
+        .map_err(|e| AppError::Internal(format!("Failed to optimize database: {e}")))?;
+
+    let page_count_after = pragma_value(conn, "page_count")?;
+
+    Ok(MaintenanceStats {
+        page_count_before,
+        page_count_after,
+        freelist_count_before,
+    })
+}
+
+/// Rewrites the database with a full `VACUUM` in `auto_vacuum = INCREMENTAL`.
+///
+/// Other connections wait for the whole rewrite, which needs free disk space
+/// about the size of the file.
+pub fn full_vacuum(conn: &Connection) -> Result<MaintenanceStats, AppError> {
+    let page_count_before = pragma_value(conn, "page_count")?;
+    let freelist_count_before = pragma_value(conn, "freelist_count")?;
+
+    conn.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")
+        .map_err(|e| AppError::Internal(format!("Failed to vacuum database: {e}")))?;
+
+    let page_count_after = pragma_value(conn, "page_count")?;
+
+    Ok(MaintenanceStats {
+        page_count_before,
+        page_count_after,
+        freelist_count_before,
+    })
+}
+
 pub struct PushTokenRow {
     pub push_token: String,
     #[allow(dead_code)]
     pub platform: String,
-    pub sandbox: bool,
+    /// The APNs environment the token was registered for; `None` if untagged.
+    pub sandbox: Option<bool>,
 }
 
-pub fn list_push_tokens(conn: &Connection) -> Result<Vec<PushTokenRow>, AppError> {
+/// Lists the push tokens registered in `partition`.
+pub fn list_push_tokens(
+    conn: &Connection,
+    partition: &Partition,
+) -> Result<Vec<PushTokenRow>, AppError> {
     let mut stmt = conn
-        .prepare("SELECT push_token, platform, sandbox FROM push_tokens")
+        .prepare(
+            "SELECT push_token, platform, CASE WHEN sandbox_tagged THEN sandbox END
+             FROM push_tokens
+             WHERE (:all_partitions OR partition IS :partition)",
+        )
         .map_err(|e| AppError::Internal(format!("Failed to prepare push tokens query: {e}")))?;
 
+    let params = partition_params(partition);
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
     let tokens = stmt
-        .query_map([], |row| {
-            let sandbox_int: i32 = row.get(2)?;
+        .query_map(params_refs.as_slice(), |row| {
             Ok(PushTokenRow {
                 push_token: row.get(0)?,
                 platform: row.get(1)?,
-                sandbox: sandbox_int != 0,
+                sandbox: row.get(2)?,
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query push tokens: {e}")))?
@@ -550,6 +1864,37 @@ pub fn delete_push_token(conn: &Connection, push_token: &str) -> Result<(), AppE
     Ok(())
 }
 
+/// Records that APNs accepted a push to `push_token`.
+pub fn record_push_success(conn: &Connection, push_token: &str, now: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE push_tokens SET last_success = ?2 WHERE push_token = ?1",
+        rusqlite::params![push_token, now],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to record push success: {e}")))?;
+    Ok(())
+}
+
+/// Deletes push tokens that neither received a push nor were re-registered
+/// within the retention window. Returns the number of tokens deleted.
+pub fn delete_stale_push_tokens(conn: &Connection, retention_days: u64) -> Result<usize, AppError> {
+    #[allow(clippy::cast_possible_wrap)]
+    let cutoff = chrono::Utc::now()
+        .checked_sub_signed(chrono::Duration::days(retention_days as i64))
+        .ok_or_else(|| AppError::Internal("Time calculation overflow".to_string()))?
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    let count = conn
+        .execute(
+            "DELETE FROM push_tokens
+             WHERE COALESCE(last_seen, updated_at) < ?1
+               AND (last_success IS NULL OR last_success < ?1)",
+            rusqlite::params![cutoff],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to delete stale push tokens: {e}")))?;
+
+    Ok(count)
+}
+
 pub fn get_metadata(conn: &Connection, key: &str) -> Result<Option<String>, AppError> {
     let mut stmt = conn
         .prepare("SELECT value FROM metadata WHERE key = ?1")
@@ -582,18 +1927,31 @@ pub fn set_metadata(conn: &Connection, key: &str, value: &str) -> Result<(), App
     Ok(())
 }
 
-pub fn acknowledge_notifications(conn: &Connection, ids: &[String]) -> Result<(), AppError> {
+/// Marks notifications as acknowledged. Ids outside `partition` are ignored.
+pub fn acknowledge_notifications(
+    conn: &Connection,
+    partition: &Partition,
+    ids: &[String],
+) -> Result<(), AppError> {
     if ids.is_empty() {
         return Ok(());
     }
 
     let placeholders = ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-    let sql = format!("UPDATE notifications SET acknowledged = 1 WHERE id IN ({placeholders})");
-
-    let params: Vec<&dyn rusqlite::types::ToSql> = ids
+    let sql = format!(
+        "UPDATE notifications SET acknowledged = 1
+         WHERE id IN ({placeholders})
+           AND device_id IN (SELECT d.device_id FROM devices d WHERE (? OR d.partition IS ?))"
+    );
+
+    let all_partitions = partition.is_all();
+    let partition_name = partition.name();
+    let mut params: Vec<&dyn rusqlite::types::ToSql> = ids
         .iter()
         .map(|id| id as &dyn rusqlite::types::ToSql)
         .collect();
+    params.push(&all_partitions);
+    params.push(&partition_name);
 
     conn.execute(&sql, params.as_slice())
         .map_err(|e| AppError::Internal(format!("Failed to acknowledge notifications: {e}")))?;
@@ -601,6 +1959,348 @@ pub fn acknowledge_notifications(conn: &Connection, ids: &[String]) -> Result<()
     Ok(())
 }
 
+/// Acknowledges every notification in `partition` of `session_id` and
+/// created at or before `before`, whichever are given, in one UPDATE.
+/// `before` is a normalized timestamp. Returns how many were updated.
+pub fn acknowledge_notifications_matching(
+    conn: &Connection,
+    partition: &Partition,
+    session_id: Option<&str>,
+    before: Option<&str>,
+) -> Result<usize, AppError> {
+    let mut sql = format!(
+        "UPDATE notifications SET acknowledged = 1
+         WHERE acknowledged = 0
+           AND device_id IN (SELECT d.device_id FROM devices d WHERE {PARTITION_FILTER})"
+    );
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = Vec::new();
+    params.extend(partition_params(partition));
+    if let Some(session_id) = session_id {
+        sql.push_str(" AND session_id = :session_id");
+        params.push((":session_id", Box::new(session_id.to_string())));
+    }
+    if let Some(before) = before {
+        sql.push_str(" AND created_at <= :before");
+        params.push((":before", Box::new(before.to_string())));
+    }
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    conn.execute(&sql, params_refs.as_slice())
+        .map_err(|e| AppError::Internal(format!("Failed to acknowledge notifications: {e}")))
+}
+
+/// Hides notification `id` in `partition` from lists until `until`, a
+/// normalized timestamp. Snoozing again moves the time. Returns whether the
+/// notification was found.
+pub fn snooze_notification(
+    conn: &Connection,
+    partition: &Partition,
+    id: &str,
+    until: &str,
+) -> Result<bool, AppError> {
+    let sql = format!(
+        "UPDATE notifications SET snoozed_until = :until
+         WHERE id = :id
+           AND device_id IN (SELECT d.device_id FROM devices d WHERE {PARTITION_FILTER})"
+    );
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> = vec![
+        (":id", Box::new(id.to_string())),
+        (":until", Box::new(until.to_string())),
+    ];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let changed = conn
+        .execute(&sql, params_refs.as_slice())
+        .map_err(|e| AppError::Internal(format!("Failed to snooze notification: {e}")))?;
+    Ok(changed > 0)
+}
+
+/// Ends every snooze due at or before `now`, in every partition, and
+/// returns the notifications woken.
+pub fn take_due_snoozes(
+    conn: &Connection,
+    now: &str,
+) -> Result<Vec<NotificationResponse>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "UPDATE notifications SET snoozed_until = NULL
+             WHERE snoozed_until IS NOT NULL AND snoozed_until <= ?1
+             RETURNING id, event_id, session_id, device_id, title, body, notification_type, payload_json, created_at, acknowledged",
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to prepare snooze query: {e}")))?;
+
+    let notifications = stmt
+        .query_map([now], |row| {
+            let acknowledged_int: i32 = row.get(9)?;
+            Ok(NotificationResponse {
+                id: row.get(0)?,
+                event_id: row.get(1)?,
+                session_id: row.get(2)?,
+                device_id: row.get(3)?,
+                title: row.get(4)?,
+                body: row.get(5)?,
+                notification_type: row.get(6)?,
+                payload_json: row.get(7)?,
+                created_at: row.get(8)?,
+                acknowledged: acknowledged_int != 0,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to end snoozes: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect woken notifications: {e}")))?;
+
+    Ok(notifications)
+}
+
+/// Records the decision for a permission notification. Responding again
+/// replaces the decision until the hook has fetched it; after that the
+/// response is final and this returns `false`.
+pub fn upsert_response(
+    conn: &Connection,
+    id: &str,
+    notification: &NotificationResponse,
+    decision: &str,
+    message: Option<&str>,
+    now: &str,
+) -> Result<bool, AppError> {
+    let changed = conn
+        .execute(
+            "INSERT INTO responses (id, notification_id, session_id, decision, message, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(notification_id) DO UPDATE SET
+                decision = excluded.decision,
+                message = excluded.message,
+                created_at = excluded.created_at
+             WHERE responses.delivered_at IS NULL",
+            rusqlite::params![
+                id,
+                notification.id,
+                notification.session_id,
+                decision,
+                message,
+                now
+            ],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to upsert response: {e}")))?;
+
+    Ok(changed > 0)
+}
+
+pub fn get_response_for_notification(
+    conn: &Connection,
+    notification_id: &str,
+) -> Result<Option<PermissionResponse>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, notification_id, session_id, decision, message, created_at
+             FROM responses WHERE notification_id = ?1",
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to prepare response query: {e}")))?;
+
+    let mut responses = stmt
+        .query_map(rusqlite::params![notification_id], response_from_row)
+        .map_err(|e| AppError::Internal(format!("Failed to query response: {e}")))?;
+
+    responses
+        .next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch response row: {e}")))
+}
+
+/// Returns the session's responses the hook hasn't fetched yet, oldest first,
+/// and marks them delivered so each is returned only once.
+pub fn take_pending_responses(
+    conn: &Connection,
+    partition: &Partition,
+    session_id: &str,
+    now: &str,
+) -> Result<Vec<PermissionResponse>, AppError> {
+    let sql = format!(
+        "SELECT r.id, r.notification_id, r.session_id, r.decision, r.message, r.created_at
+         FROM responses r
+         JOIN sessions s ON s.session_id = r.session_id
+         LEFT JOIN devices d ON d.device_id = s.device_id
+         WHERE r.session_id = :session_id AND r.delivered_at IS NULL AND {PARTITION_FILTER}
+         ORDER BY r.created_at ASC"
+    );
+
+    let mut params: Vec<(&str, Box<dyn rusqlite::types::ToSql>)> =
+        vec![(":session_id", Box::new(session_id.to_string()))];
+    params.extend(partition_params(partition));
+    let params_refs: Vec<(&str, &dyn rusqlite::types::ToSql)> =
+        params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let tx = conn
+        .unchecked_transaction()
+        .map_err(|e| AppError::Internal(format!("Transaction begin failed: {e}")))?;
+
+    let mut stmt = tx
+        .prepare(&sql)
+        .map_err(|e| AppError::Internal(format!("Failed to prepare responses query: {e}")))?;
+    let responses = stmt
+        .query_map(params_refs.as_slice(), response_from_row)
+        .map_err(|e| AppError::Internal(format!("Failed to query responses: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect responses: {e}")))?;
+    drop(stmt);
+
+    for response in &responses {
+        tx.execute(
+            "UPDATE responses SET delivered_at = ?1 WHERE id = ?2",
+            rusqlite::params![now, response.id],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to mark response delivered: {e}")))?;
+    }
+
+    tx.commit()
+        .map_err(|e| AppError::Internal(format!("Transaction commit failed: {e}")))?;
+
+    Ok(responses)
+}
+
+fn response_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PermissionResponse> {
+    Ok(PermissionResponse {
+        id: row.get(0)?,
+        notification_id: row.get(1)?,
+        session_id: row.get(2)?,
+        decision: row.get(3)?,
+        message: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+pub fn insert_delivery_failure(
+    conn: &Connection,
+    failure: &DeliveryFailure,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO delivery_failures (id, notification_id, channel, partition, session_id,
+             device_id, title, body, error, attempts, created_at, last_attempt_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        rusqlite::params![
+            failure.id,
+            failure.notification_id,
+            failure.channel,
+            failure.partition,
+            failure.session_id,
+            failure.device_id,
+            failure.title,
+            failure.body,
+            failure.error,
+            failure.attempts,
+            failure.created_at,
+            failure.last_attempt_at
+        ],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to insert delivery failure: {e}")))?;
+    Ok(())
+}
+
+const DELIVERY_FAILURE_COLUMNS: &str = "id, notification_id, channel, partition, session_id,
+     device_id, title, body, error, attempts, created_at, last_attempt_at";
+
+/// Most recently attempted failures first.
+pub fn list_delivery_failures(
+    conn: &Connection,
+    limit: i64,
+) -> Result<Vec<DeliveryFailure>, AppError> {
+    let sql = format!(
+        "SELECT {DELIVERY_FAILURE_COLUMNS} FROM delivery_failures
+         ORDER BY last_attempt_at DESC LIMIT ?1"
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| {
+        AppError::Internal(format!("Failed to prepare delivery failures query: {e}"))
+    })?;
+
+    let failures = stmt
+        .query_map(rusqlite::params![limit], delivery_failure_from_row)
+        .map_err(|e| AppError::Internal(format!("Failed to query delivery failures: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect delivery failures: {e}")))?;
+
+    Ok(failures)
+}
+
+pub fn get_delivery_failure(
+    conn: &Connection,
+    id: &str,
+) -> Result<Option<DeliveryFailure>, AppError> {
+    let sql = format!("SELECT {DELIVERY_FAILURE_COLUMNS} FROM delivery_failures WHERE id = ?1");
+    let mut stmt = conn.prepare(&sql).map_err(|e| {
+        AppError::Internal(format!("Failed to prepare delivery failure lookup: {e}"))
+    })?;
+
+    let mut failures = stmt
+        .query_map(rusqlite::params![id], delivery_failure_from_row)
+        .map_err(|e| AppError::Internal(format!("Failed to query delivery failure: {e}")))?;
+
+    failures
+        .next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch delivery failure row: {e}")))
+}
+
+/// Records another failed attempt for a delivery failure.
+pub fn record_delivery_retry_failure(
+    conn: &Connection,
+    id: &str,
+    error: &str,
+    now: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE delivery_failures
+         SET error = ?1, attempts = attempts + 1, last_attempt_at = ?2
+         WHERE id = ?3",
+        rusqlite::params![error, now, id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to update delivery failure: {e}")))?;
+    Ok(())
+}
+
+pub fn delete_delivery_failure(conn: &Connection, id: &str) -> Result<(), AppError> {
+    conn.execute(
+        "DELETE FROM delivery_failures WHERE id = ?1",
+        rusqlite::params![id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to delete delivery failure: {e}")))?;
+    Ok(())
+}
+
+/// Deletes failures whose notification has expired; retrying them would
+/// push a notification the app can no longer list.
+pub fn delete_orphaned_delivery_failures(conn: &Connection) -> Result<usize, AppError> {
+    let count = conn
+        .execute(
+            "DELETE FROM delivery_failures
+             WHERE notification_id NOT IN (SELECT id FROM notifications)",
+            [],
+        )
+        .map_err(|e| {
+            AppError::Internal(format!("Failed to delete orphaned delivery failures: {e}"))
+        })?;
+    Ok(count)
+}
+
+fn delivery_failure_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DeliveryFailure> {
+    Ok(DeliveryFailure {
+        id: row.get(0)?,
+        notification_id: row.get(1)?,
+        channel: row.get(2)?,
+        partition: row.get(3)?,
+        session_id: row.get(4)?,
+        device_id: row.get(5)?,
+        title: row.get(6)?,
+        body: row.get(7)?,
+        error: row.get(8)?,
+        attempts: row.get(9)?,
+        created_at: row.get(10)?,
+        last_attempt_at: row.get(11)?,
+    })
+}
+
 pub struct ApiKeyRow {
     pub id: String,
     pub name: String,
@@ -609,8 +2309,15 @@ pub struct ApiKeyRow {
     pub created_at: String,
     pub last_used: Option<String>,
     pub rate_limit: Option<i64>,
+    pub partition: Option<String>,
+    /// Set for keys provisioned by enrollment; such keys may only ingest
+    /// events for this device.
+    pub device_id: Option<String>,
+    /// Events written with this key keep no prompts or messages.
+    pub privacy_mode: bool,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn insert_api_key(
     conn: &Connection,
     id: &str,
@@ -619,10 +2326,11 @@ pub fn insert_api_key(
     scopes: &str,
     created_at: &str,
     rate_limit: Option<i64>,
+    partition: Option<&str>,
 ) -> Result<(), AppError> {
     conn.execute(
-        "INSERT INTO api_keys (id, name, key, scopes, created_at, rate_limit) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        rusqlite::params![id, name, key, scopes, created_at, rate_limit],
+        "INSERT INTO api_keys (id, name, key, scopes, created_at, rate_limit, partition) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![id, name, key, scopes, created_at, rate_limit, partition],
     )
     .map_err(|e| AppError::Internal(format!("Failed to insert api key: {e}")))?;
     Ok(())
@@ -630,7 +2338,7 @@ pub fn insert_api_key(
 
 pub fn list_api_keys(conn: &Connection) -> Result<Vec<ApiKeyRow>, AppError> {
     let mut stmt = conn
-        .prepare("SELECT id, name, key, scopes, created_at, last_used, rate_limit FROM api_keys ORDER BY created_at ASC")
+        .prepare("SELECT id, name, key, scopes, created_at, last_used, rate_limit, partition, device_id, privacy_mode FROM api_keys ORDER BY created_at ASC")
         .map_err(|e| AppError::Internal(format!("Failed to prepare api_keys query: {e}")))?;
 
     let rows = stmt
@@ -643,6 +2351,9 @@ pub fn list_api_keys(conn: &Connection) -> Result<Vec<ApiKeyRow>, AppError> {
                 created_at: row.get(4)?,
                 last_used: row.get(5)?,
                 rate_limit: row.get(6)?,
+                partition: row.get(7)?,
+                device_id: row.get(8)?,
+                privacy_mode: row.get(9)?,
             })
         })
         .map_err(|e| AppError::Internal(format!("Failed to query api_keys: {e}")))?
@@ -654,7 +2365,7 @@ pub fn list_api_keys(conn: &Connection) -> Result<Vec<ApiKeyRow>, AppError> {
 
 pub fn find_api_key_by_key(conn: &Connection, key: &str) -> Result<Option<ApiKeyRow>, AppError> {
     let mut stmt = conn
-        .prepare("SELECT id, name, key, scopes, created_at, last_used, rate_limit FROM api_keys WHERE key = ?1")
+        .prepare("SELECT id, name, key, scopes, created_at, last_used, rate_limit, partition, device_id, privacy_mode FROM api_keys WHERE key = ?1")
         .map_err(|e| AppError::Internal(format!("Failed to prepare api_key lookup: {e}")))?;
 
     let mut rows = stmt
@@ -687,18 +2398,188 @@ pub fn find_api_key_by_key(conn: &Connection, key: &str) -> Result<Option<ApiKey
             rate_limit: row.get(6).map_err(|e| {
                 AppError::Internal(format!("Failed to get api_key rate_limit: {e}"))
             })?,
+            partition: row
+                .get(7)
+                .map_err(|e| AppError::Internal(format!("Failed to get api_key partition: {e}")))?,
+            device_id: row
+                .get(8)
+                .map_err(|e| AppError::Internal(format!("Failed to get api_key device_id: {e}")))?,
+            privacy_mode: row.get(9).map_err(|e| {
+                AppError::Internal(format!("Failed to get api_key privacy_mode: {e}"))
+            })?,
         }))
     } else {
         Ok(None)
     }
 }
 
+pub fn find_api_key_by_id(conn: &Connection, id: &str) -> Result<Option<ApiKeyRow>, AppError> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, key, scopes, created_at, last_used, rate_limit, partition, device_id, privacy_mode FROM api_keys WHERE id = ?1")
+        .map_err(|e| AppError::Internal(format!("Failed to prepare api_key lookup: {e}")))?;
+
+    let mut rows = stmt
+        .query_map(rusqlite::params![id], |row| {
+            Ok(ApiKeyRow {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                key: row.get(2)?,
+                scopes: row.get(3)?,
+                created_at: row.get(4)?,
+                last_used: row.get(5)?,
+                rate_limit: row.get(6)?,
+                partition: row.get(7)?,
+                device_id: row.get(8)?,
+                privacy_mode: row.get(9)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query api_key: {e}")))?;
+
+    rows.next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch api_key row: {e}")))
+}
+
 pub fn delete_api_key(conn: &Connection, id: &str) -> Result<(), AppError> {
     conn.execute("DELETE FROM api_keys WHERE id = ?1", rusqlite::params![id])
         .map_err(|e| AppError::Internal(format!("Failed to delete api_key: {e}")))?;
+    conn.execute(
+        "DELETE FROM key_usage WHERE key_id = ?1",
+        rusqlite::params![id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to delete key usage: {e}")))?;
+    Ok(())
+}
+
+/// `chrono` format of the `key_usage.day` column.
+pub const USAGE_DAY_FORMAT: &str = "%Y-%m-%d";
+
+pub struct KeyUsageRow {
+    pub day: String,
+    pub requests: i64,
+    pub events: i64,
+}
+
+/// Adds `requests` and `events` to a key's counters for `day`.
+pub fn increment_key_usage(
+    conn: &Connection,
+    key_id: &str,
+    day: &str,
+    requests: i64,
+    events: i64,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO key_usage (key_id, day, requests, events) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(key_id, day) DO UPDATE SET
+             requests = requests + excluded.requests,
+             events = events + excluded.events",
+        rusqlite::params![key_id, day, requests, events],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to record key usage: {e}")))?;
+    Ok(())
+}
+
+/// A key's daily counters from `since_day` onward, most recent first. Days
+/// without traffic have no row.
+pub fn list_key_usage(
+    conn: &Connection,
+    key_id: &str,
+    since_day: &str,
+) -> Result<Vec<KeyUsageRow>, AppError> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT day, requests, events FROM key_usage
+             WHERE key_id = ?1 AND day >= ?2
+             ORDER BY day DESC",
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to prepare key usage query: {e}")))?;
+
+    let rows = stmt
+        .query_map(rusqlite::params![key_id, since_day], |row| {
+            Ok(KeyUsageRow {
+                day: row.get(0)?,
+                requests: row.get(1)?,
+                events: row.get(2)?,
+            })
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query key usage: {e}")))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| AppError::Internal(format!("Failed to collect key usage: {e}")))?;
+
+    Ok(rows)
+}
+
+pub fn set_api_key_device(conn: &Connection, id: &str, device_id: &str) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE api_keys SET device_id = ?1 WHERE id = ?2",
+        rusqlite::params![device_id, id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to bind api_key to device: {e}")))?;
     Ok(())
 }
 
+pub fn set_api_key_privacy_mode(
+    conn: &Connection,
+    id: &str,
+    privacy_mode: bool,
+) -> Result<(), AppError> {
+    conn.execute(
+        "UPDATE api_keys SET privacy_mode = ?1 WHERE id = ?2",
+        rusqlite::params![privacy_mode, id],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to set api_key privacy mode: {e}")))?;
+    Ok(())
+}
+
+pub fn insert_enrollment_code(
+    conn: &Connection,
+    code: &str,
+    partition: Option<&str>,
+    created_at: &str,
+    expires_at: &str,
+) -> Result<(), AppError> {
+    conn.execute(
+        "INSERT INTO enrollment_codes (code, partition, created_at, expires_at) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![code, partition, created_at, expires_at],
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to insert enrollment code: {e}")))?;
+    Ok(())
+}
+
+/// Spends an unused, unexpired enrollment code on `key_id`. Returns the
+/// partition the code enrolls into, or `None` if the code is unknown,
+/// expired, or already used.
+pub fn claim_enrollment_code(
+    conn: &Connection,
+    code: &str,
+    key_id: &str,
+    now: &str,
+) -> Result<Option<Partition>, AppError> {
+    let claimed = conn
+        .execute(
+            "UPDATE enrollment_codes SET used_at = ?1, key_id = ?2
+             WHERE code = ?3 AND used_at IS NULL AND expires_at > ?1",
+            rusqlite::params![now, key_id, code],
+        )
+        .map_err(|e| AppError::Internal(format!("Failed to claim enrollment code: {e}")))?;
+    if claimed == 0 {
+        return Ok(None);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT partition FROM enrollment_codes WHERE code = ?1")
+        .map_err(|e| AppError::Internal(format!("Failed to prepare enrollment code query: {e}")))?;
+    let mut rows = stmt
+        .query_map(rusqlite::params![code], |row| {
+            Ok(Partition::Key(row.get(0)?))
+        })
+        .map_err(|e| AppError::Internal(format!("Failed to query enrollment code: {e}")))?;
+
+    rows.next()
+        .transpose()
+        .map_err(|e| AppError::Internal(format!("Failed to fetch enrollment code row: {e}")))
+}
+
 pub fn update_api_key_last_used(conn: &Connection, id: &str, now: &str) -> Result<(), AppError> {
     conn.execute(
         "UPDATE api_keys SET last_used = ?1 WHERE id = ?2",
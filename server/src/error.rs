@@ -1,18 +1,50 @@
-use axum::http::StatusCode;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use serde::Serialize;
+
+/// One invalid field of a request body, named by its JSON path (e.g.
+/// `device.device_id`).
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            field,
+            message: message.into(),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum AppError {
     Unauthorized,
     Forbidden,
     RateLimited,
+    /// The server is too busy to take the request; retry after this many seconds.
+    Overloaded(u64),
+    NotFound(String),
+    /// The request asked for a representation the server can't produce.
+    NotAcceptable(String),
     BadRequest(String),
+    /// Every invalid field of a request body, reported together as a
+    /// `bad_request` with a `fields` array.
+    Validation(Vec<FieldError>),
     Internal(String),
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            Self::Overloaded(secs) => Some(secs),
+            _ => None,
+        };
+
+        let mut fields = None;
         let (status, error_key, message) = match self {
             Self::Unauthorized => (
                 StatusCode::UNAUTHORIZED,
@@ -29,7 +61,23 @@ impl IntoResponse for AppError {
                 "rate_limited",
                 "Too many failed authentication attempts".to_string(),
             ),
+            Self::Overloaded(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "overloaded",
+                "Server is busy, retry later".to_string(),
+            ),
+            Self::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", msg),
+            Self::NotAcceptable(msg) => (StatusCode::NOT_ACCEPTABLE, "not_acceptable", msg),
             Self::BadRequest(msg) => (StatusCode::UNPROCESSABLE_ENTITY, "bad_request", msg),
+            Self::Validation(errors) => {
+                let message = errors
+                    .iter()
+                    .map(|error| error.message.as_str())
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                fields = Some(errors);
+                (StatusCode::UNPROCESSABLE_ENTITY, "bad_request", message)
+            }
             Self::Internal(msg) => {
                 tracing::error!("Internal error: {}", msg);
                 (
@@ -40,14 +88,22 @@ impl IntoResponse for AppError {
             }
         };
 
-        (
-            status,
-            Json(serde_json::json!({
-                "error": error_key,
-                "message": message,
-            })),
-        )
-            .into_response()
+        let mut body = serde_json::json!({
+            "error": error_key,
+            "message": message,
+        });
+        if let Some(fields) = fields {
+            body["fields"] = serde_json::json!(fields);
+        }
+        let mut response = (status, Json(body)).into_response();
+
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+
+        response
     }
 }
 
@@ -84,6 +140,60 @@ mod tests {
         assert_eq!(json["message"], "Invalid input");
     }
 
+    #[tokio::test]
+    async fn test_validation_error_lists_every_field() {
+        let error = AppError::Validation(vec![
+            FieldError::new("device.device_id", "device_id is required"),
+            FieldError::new("timestamp", "timestamp must be valid RFC 3339"),
+        ]);
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "bad_request");
+        assert_eq!(
+            json["message"],
+            "device_id is required; timestamp must be valid RFC 3339"
+        );
+        assert_eq!(json["fields"][0]["field"], "device.device_id");
+        assert_eq!(json["fields"][1]["field"], "timestamp");
+        assert_eq!(
+            json["fields"][1]["message"],
+            "timestamp must be valid RFC 3339"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_found_error() {
+        let error = AppError::NotFound("Event not found".to_string());
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "not_found");
+        assert_eq!(json["message"], "Event not found");
+    }
+
+    #[tokio::test]
+    async fn test_overloaded_error_sets_retry_after() {
+        let error = AppError::Overloaded(3);
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"], "overloaded");
+    }
+
     #[tokio::test]
     async fn test_internal_error() {
         let error = AppError::Internal("Database error".to_string());
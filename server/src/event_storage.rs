@@ -0,0 +1,181 @@
+//! How much of each event is kept in `events.event_json`.
+//!
+//! Busy agents send far more `PreToolUse`/`PostToolUse` events than anything
+//! else. Rules such as `PreToolUse=headers` store only the indexed header
+//! fields for those types, dropping `cwd`, `prompt`, `message`,
+//! `task_subject`, `permission_suggestions`, `title`, `custom_instructions`,
+//! and `error`. Types without a rule are stored in full, so an empty config
+//! behaves as before.
+//!
+//! Only storage is affected: session titles, status, notifications, and the
+//! event bus all see the full event.
+
+use std::borrow::Cow;
+
+use crate::models::request::EventData;
+
+/// How much of an event's JSON is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageTier {
+    /// The whole event.
+    Full,
+    /// Everything but `cwd`, `prompt`, `message`, `task_subject`,
+    /// `permission_suggestions`, `title`, `custom_instructions`, and `error`:
+    /// names, ids, `priority`, `severity`, `trigger`, `is_interrupt`, and
+    /// `env`.
+    Headers,
+}
+
+impl StorageTier {
+    /// Every tier, in the order they are listed to users.
+    pub const ALL: &'static [Self] = &[Self::Full, Self::Headers];
+
+    /// The name used for this tier in config.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Headers => "headers",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|tier| tier.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Per-event-type storage tiers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventStorage {
+    /// `(hook_event_name, tier)` rules; unlisted types are stored in full.
+    pub per_type: Vec<(String, StorageTier)>,
+}
+
+impl EventStorage {
+    /// The tier events named `hook_event_name` are stored at.
+    pub fn tier_for(&self, hook_event_name: &str) -> StorageTier {
+        self.per_type
+            .iter()
+            .find(|(kind, _)| kind == hook_event_name)
+            .map_or(StorageTier::Full, |(_, tier)| *tier)
+    }
+
+    /// The part of `event` to store, per its type's tier.
+    pub fn stored<'a>(&self, event: &'a EventData) -> Cow<'a, EventData> {
+        match self.tier_for(event.hook_event_name.as_str()) {
+            StorageTier::Full => Cow::Borrowed(event),
+            StorageTier::Headers => Cow::Owned(EventData {
+                cwd: None,
+                prompt: None,
+                message: None,
+                task_subject: None,
+                permission_suggestions: None,
+                title: None,
+                custom_instructions: None,
+                error: None,
+                ..event.clone()
+            }),
+        }
+    }
+}
+
+/// Parses one rule, `hook_event_name=full` or `hook_event_name=headers`.
+///
+/// # Errors
+///
+/// Returns a message for the config error when the event type is missing or
+/// the tier is unknown.
+pub fn parse_rule(value: &str) -> Result<(String, StorageTier), String> {
+    let (kind, tier) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `event_type=tier`, got `{value}`"))?;
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(format!("missing event type in `{value}`"));
+    }
+
+    let tier = StorageTier::parse(tier.trim()).ok_or_else(|| {
+        let known: Vec<&str> = StorageTier::ALL.iter().map(|t| t.as_str()).collect();
+        format!(
+            "unknown storage tier in `{value}`; expected one of: {}",
+            known.join(", ")
+        )
+    })?;
+    Ok((kind.to_string(), tier))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::models::request::HookEventKind;
+
+    fn event(kind: HookEventKind) -> EventData {
+        EventData {
+            session_id: "s1".to_string(),
+            hook_event_name: kind,
+            cwd: Some("/home/u/project".to_string()),
+            prompt: None,
+            notification_type: None,
+            tool_name: Some("Bash".to_string()),
+            message: Some("Running cargo test".to_string()),
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
+        }
+    }
+
+    #[test]
+    fn test_unlisted_types_are_stored_in_full() {
+        let storage = EventStorage::default();
+        let event = event(HookEventKind::PreToolUse);
+        assert_eq!(storage.tier_for("PreToolUse"), StorageTier::Full);
+        assert!(matches!(storage.stored(&event), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_headers_tier_drops_bodies() {
+        let storage = EventStorage {
+            per_type: vec![parse_rule("PreToolUse=headers").unwrap()],
+        };
+        let tool_use = event(HookEventKind::PreToolUse);
+        let stored = storage.stored(&tool_use);
+        assert_eq!(stored.session_id, "s1");
+        assert_eq!(stored.hook_event_name, HookEventKind::PreToolUse);
+        assert_eq!(stored.tool_name.as_deref(), Some("Bash"));
+        assert!(stored.cwd.is_none());
+        assert!(stored.message.is_none());
+
+        let stop = event(HookEventKind::Stop);
+        assert_eq!(storage.stored(&stop).into_owned(), stop);
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        assert_eq!(
+            parse_rule(" PostToolUse = Headers ").unwrap(),
+            ("PostToolUse".to_string(), StorageTier::Headers)
+        );
+        assert_eq!(
+            parse_rule("Stop=full").unwrap(),
+            ("Stop".to_string(), StorageTier::Full)
+        );
+        assert!(parse_rule("Stop").is_err());
+        assert!(parse_rule("=headers").is_err());
+        assert!(parse_rule("Stop=none").is_err());
+    }
+}
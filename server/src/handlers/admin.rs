@@ -1,20 +1,82 @@
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
 use axum::Json;
-use chrono::{SecondsFormat, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::stream;
 use std::sync::Arc;
 
-use crate::auth::AdminAuth;
+use crate::apns::Alert;
+use crate::auth::{generate_api_key, AdminAuth};
+use crate::db::partition::Partition;
 use crate::db::queries;
 use crate::error::AppError;
-use crate::models::request::CreateApiKeyRequest;
+use crate::ingest::push::{self, PushNotification};
+use crate::metrics;
+use crate::models::request::{CreateApiKeyRequest, CreateEnrollmentCodeRequest};
 use crate::models::response::{
-    ApiKeyCreatedResponse, ApiKeyListItem, ApiKeyListResponse, StatusOk,
+    ApiKeyCreatedResponse, ApiKeyListItem, ApiKeyListResponse, CooldownItem, CooldownListResponse,
+    CooldownsClearedResponse, DeliveryFailureListResponse, DeliveryRetryResponse,
+    EnrollmentCodeResponse, EnrollmentQrPayload, EnrollmentQrResponse, KeyUsageDay,
+    KeyUsageResponse, MaintenanceResponse, PreviousKeyClient, PreviousKeyClientsResponse, StatusOk,
+    UnknownFieldItem, UnknownFieldsResponse,
 };
-use crate::router::AppState;
+use crate::notif_dedup::{self, NOTIF_COOLDOWN_WINDOW};
+use crate::route_limits;
+use crate::router::{AppState, AuthState, NotifState};
+use crate::storage::{self, SharedStorage};
+use crate::utils::truncate_at_char_boundary;
 
-fn generate_api_key() -> String {
-    format!("claud_{}", uuid::Uuid::new_v4().simple())
+/// Rows read per connection checkout while streaming the archive export.
+const ARCHIVE_EXPORT_BATCH: i64 = 1000;
+
+/// Days of usage returned when the request does not say.
+const DEFAULT_USAGE_DAYS: u32 = 30;
+
+/// Most days of usage returned by one request.
+const MAX_USAGE_DAYS: u32 = 365;
+
+/// Minutes an enrollment code stays redeemable when the request does not say.
+const DEFAULT_ENROLLMENT_TTL_MINUTES: u32 = 60;
+
+/// Longest an enrollment code can stay redeemable: one week.
+const MAX_ENROLLMENT_TTL_MINUTES: u32 = 7 * 24 * 60;
+
+/// Delivery failures listed when the request does not say.
+const DEFAULT_DELIVERY_FAILURES: i64 = 100;
+
+/// Most delivery failures listed by one request.
+const MAX_DELIVERY_FAILURES: i64 = 1000;
+
+/// Version of the enrollment QR payload format, bumped on breaking changes.
+const ENROLLMENT_QR_VERSION: u32 = 1;
+
+/// Longest accepted partition name.
+const MAX_PARTITION_LEN: usize = 64;
+
+/// Trims `partition` and checks it is a valid partition name. Blank means
+/// the default partition.
+fn validate_partition(partition: Option<&str>) -> Result<Option<&str>, AppError> {
+    let partition = partition.map(str::trim).filter(|p| !p.is_empty());
+    if let Some(p) = partition {
+        if p.len() > MAX_PARTITION_LEN
+            || !p
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(AppError::BadRequest(format!(
+                "invalid partition '{p}': must be at most {MAX_PARTITION_LEN} letters, digits, '-' or '_'"
+            )));
+        }
+    }
+    Ok(partition)
+}
+
+/// Generates a one-time enrollment code, e.g. `3F9A-07C2-B41E`.
+fn generate_enrollment_code() -> String {
+    let hex = uuid::Uuid::new_v4().simple().to_string().to_uppercase();
+    format!("{}-{}-{}", &hex[..4], &hex[4..8], &hex[8..12])
 }
 
 pub async fn create_api_key_handler(
@@ -46,29 +108,38 @@ pub async fn create_api_key_handler(
         }
     }
 
+    let partition = validate_partition(payload.partition.as_deref())?;
+
     let id = uuid::Uuid::new_v4().to_string();
     let key = generate_api_key();
     let scopes_str = validated.join(",");
     let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
 
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
     let rate_limit = payload.rate_limit.map(i64::from);
 
-    queries::insert_api_key(
-        &conn,
-        &id,
-        payload.name.trim(),
-        &key,
-        &scopes_str,
-        &created_at,
-        rate_limit,
-    )?;
+    storage::transaction(&*state.storage, |tx| {
+        tx.insert_api_key(
+            &id,
+            payload.name.trim(),
+            &key,
+            &scopes_str,
+            &created_at,
+            rate_limit,
+            partition,
+        )?;
+        if payload.privacy_mode {
+            tx.set_api_key_privacy_mode(&id, true)?;
+        }
+        Ok(())
+    })?;
 
-    tracing::info!(name = %payload.name.trim(), scopes = %scopes_str, "API key created");
+    tracing::info!(
+        name = %payload.name.trim(),
+        scopes = %scopes_str,
+        partition = partition.unwrap_or("default"),
+        privacy_mode = payload.privacy_mode,
+        "API key created"
+    );
 
     Ok((
         StatusCode::CREATED,
@@ -79,6 +150,8 @@ pub async fn create_api_key_handler(
             scopes: validated,
             created_at,
             rate_limit: payload.rate_limit,
+            partition: partition.map(String::from),
+            privacy_mode: payload.privacy_mode,
         }),
     ))
 }
@@ -87,12 +160,7 @@ pub async fn list_api_keys_handler(
     State(state): State<Arc<AppState>>,
     _auth: AdminAuth,
 ) -> Result<Json<ApiKeyListResponse>, AppError> {
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    let rows = queries::list_api_keys(&conn)?;
+    let rows = state.storage.list_api_keys()?;
 
     let keys = rows
         .into_iter()
@@ -111,6 +179,9 @@ pub async fn list_api_keys_handler(
                 created_at: row.created_at,
                 last_used: row.last_used,
                 rate_limit: row.rate_limit.and_then(|v| u32::try_from(v).ok()),
+                partition: row.partition,
+                device_id: row.device_id,
+                privacy_mode: row.privacy_mode,
             }
         })
         .collect();
@@ -123,14 +194,496 @@ pub async fn delete_api_key_handler(
     _auth: AdminAuth,
     Path(id): Path<String>,
 ) -> Result<Json<StatusOk>, AppError> {
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    queries::delete_api_key(&conn, &id)?;
+    state.storage.delete_api_key(&id)?;
 
     tracing::info!(id = %id, "API key deleted");
 
     Ok(Json(StatusOk::ok()))
 }
+
+/// Mints a one-time code that a new hook exchanges for its own device-bound
+/// write key via `POST /api/v1/devices/register`.
+pub async fn create_enrollment_code_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    payload: Option<Json<CreateEnrollmentCodeRequest>>,
+) -> Result<(StatusCode, Json<EnrollmentCodeResponse>), AppError> {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let enrollment = mint_enrollment_code(&state, &payload)?;
+    Ok((StatusCode::CREATED, Json(enrollment)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EnrollmentQrQuery {
+    pub partition: Option<String>,
+    pub ttl_minutes: Option<u32>,
+    /// Overrides `--public-url` for this code.
+    pub server_url: Option<String>,
+}
+
+/// Mints an enrollment code and bundles it with the server URL into one
+/// string, so a client is configured by scanning or pasting it instead of
+/// typing a URL and key.
+pub async fn enrollment_qr_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    Query(query): Query<EnrollmentQrQuery>,
+) -> Result<Json<EnrollmentQrResponse>, AppError> {
+    let server_url = query
+        .server_url
+        .as_deref()
+        .or(state.public_url.as_deref())
+        .map(|url| url.trim().trim_end_matches('/'))
+        .filter(|url| !url.is_empty())
+        .ok_or_else(|| {
+            AppError::BadRequest(
+                "no server URL: pass server_url or start the server with --public-url".to_string(),
+            )
+        })?
+        .to_string();
+    if !server_url.starts_with("http://") && !server_url.starts_with("https://") {
+        return Err(AppError::BadRequest(format!(
+            "server_url must start with http:// or https://, got '{server_url}'"
+        )));
+    }
+
+    let enrollment = mint_enrollment_code(
+        &state,
+        &CreateEnrollmentCodeRequest {
+            partition: query.partition,
+            ttl_minutes: query.ttl_minutes,
+        },
+    )?;
+
+    let qr_payload = serde_json::to_string(&EnrollmentQrPayload {
+        v: ENROLLMENT_QR_VERSION,
+        url: &server_url,
+        code: &enrollment.code,
+    })
+    .map_err(|e| AppError::Internal(format!("Failed to encode QR payload: {e}")))?;
+
+    Ok(Json(EnrollmentQrResponse {
+        qr_payload,
+        server_url,
+        code: enrollment.code,
+        expires_at: enrollment.expires_at,
+        partition: enrollment.partition,
+    }))
+}
+
+/// Validates an enrollment request and stores a fresh code for it.
+fn mint_enrollment_code(
+    state: &AppState,
+    payload: &CreateEnrollmentCodeRequest,
+) -> Result<EnrollmentCodeResponse, AppError> {
+    let partition = validate_partition(payload.partition.as_deref())?;
+
+    let ttl_minutes = payload
+        .ttl_minutes
+        .unwrap_or(DEFAULT_ENROLLMENT_TTL_MINUTES);
+    if !(1..=MAX_ENROLLMENT_TTL_MINUTES).contains(&ttl_minutes) {
+        return Err(AppError::BadRequest(format!(
+            "ttl_minutes must be between 1 and {MAX_ENROLLMENT_TTL_MINUTES}"
+        )));
+    }
+
+    let now = Utc::now();
+    let created_at = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let expires_at = (now + chrono::Duration::minutes(i64::from(ttl_minutes)))
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    let code = generate_enrollment_code();
+
+    state
+        .storage
+        .insert_enrollment_code(&code, partition, &created_at, &expires_at)?;
+
+    tracing::info!(
+        partition = partition.unwrap_or("default"),
+        expires_at = %expires_at,
+        "Enrollment code created"
+    );
+
+    Ok(EnrollmentCodeResponse {
+        code,
+        created_at,
+        expires_at,
+        partition: partition.map(String::from),
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct KeyUsageQuery {
+    pub days: Option<u32>,
+}
+
+/// Daily request and event counts for one key over the last `days` days
+/// (UTC, today included).
+pub async fn api_key_usage_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    Path(id): Path<String>,
+    Query(query): Query<KeyUsageQuery>,
+) -> Result<Json<KeyUsageResponse>, AppError> {
+    let days = query.days.unwrap_or(DEFAULT_USAGE_DAYS);
+    if !(1..=MAX_USAGE_DAYS).contains(&days) {
+        return Err(AppError::BadRequest(format!(
+            "days must be between 1 and {MAX_USAGE_DAYS}"
+        )));
+    }
+
+    let key = state
+        .storage
+        .find_api_key_by_id(&id)?
+        .ok_or_else(|| AppError::NotFound(format!("API key {id} not found")))?;
+
+    let since_day = (Utc::now() - chrono::Duration::days(i64::from(days - 1)))
+        .format(queries::USAGE_DAY_FORMAT)
+        .to_string();
+    let days = state
+        .storage
+        .list_key_usage(&key.id, &since_day)?
+        .into_iter()
+        .map(|row| KeyUsageDay {
+            date: row.day,
+            requests: row.requests,
+            events: row.events,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(KeyUsageResponse {
+        id: key.id,
+        name: key.name,
+        total_requests: days.iter().map(|day| day.requests).sum(),
+        total_events: days.iter().map(|day| day.events).sum(),
+        days,
+    }))
+}
+
+/// Clients that have authenticated with the previous master key since the
+/// server started, most recently seen first. Once this stays empty the old
+/// key can be dropped from the configuration.
+pub async fn previous_key_clients_handler(
+    State(auth_state): State<AuthState>,
+    _auth: AdminAuth,
+) -> Json<PreviousKeyClientsResponse> {
+    let mut clients = auth_state
+        .previous_key_uses
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .iter()
+        .map(|((ip, user_agent), usage)| PreviousKeyClient {
+            ip: ip.to_string(),
+            user_agent: Some(user_agent.clone()).filter(|ua| !ua.is_empty()),
+            first_seen: usage.first_seen.clone(),
+            last_seen: usage.last_seen.clone(),
+            requests: usage.requests,
+        })
+        .collect::<Vec<_>>();
+    clients.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+
+    Json(PreviousKeyClientsResponse {
+        previous_key_configured: auth_state.previous_master_key.is_some(),
+        clients,
+    })
+}
+
+/// Event fields the server doesn't know, recorded under `--strict-fields`,
+/// most recently seen first.
+pub async fn unknown_fields_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+) -> Json<UnknownFieldsResponse> {
+    let mut fields = state
+        .unknown_fields
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .iter()
+        .map(|((hook_event_name, field), seen)| UnknownFieldItem {
+            hook_event_name: hook_event_name.clone(),
+            field: field.clone(),
+            first_seen: seen.first_seen.clone(),
+            last_seen: seen.last_seen.clone(),
+            events: seen.events,
+        })
+        .collect::<Vec<_>>();
+    fields.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+
+    Json(UnknownFieldsResponse {
+        strict_fields: state.strict_fields,
+        fields,
+    })
+}
+
+/// Running notification cooldowns: why a low-priority notification for a
+/// session was suppressed, and when the next one will go through.
+pub async fn list_cooldowns_handler(
+    State(notif): State<NotifState>,
+    _auth: AdminAuth,
+) -> Json<CooldownListResponse> {
+    let now = Utc::now();
+    let cooldowns = notif_dedup::active_cooldowns(&notif.cooldown)
+        .into_iter()
+        .map(|cooldown| {
+            let format = |time: DateTime<Utc>| time.to_rfc3339_opts(SecondsFormat::Millis, true);
+            CooldownItem {
+                last_sent_at: format(now - cooldown.elapsed),
+                expires_at: format(now + cooldown.remaining),
+                remaining_ms: u64::try_from(cooldown.remaining.as_millis()).unwrap_or(u64::MAX),
+                session_id: cooldown.session_id,
+                notification_type: cooldown.notification_type,
+            }
+        })
+        .collect();
+
+    Json(CooldownListResponse {
+        window_secs: NOTIF_COOLDOWN_WINDOW.as_secs(),
+        cooldowns,
+    })
+}
+
+/// Ends a session's cooldowns so its next notification of each type is sent.
+pub async fn clear_cooldowns_handler(
+    State(notif): State<NotifState>,
+    _auth: AdminAuth,
+    Path(session_id): Path<String>,
+) -> Json<CooldownsClearedResponse> {
+    let cleared = notif_dedup::clear_cooldowns(&notif.cooldown, &session_id);
+    tracing::info!(session_id = %session_id, cleared, "Notification cooldowns cleared");
+    Json(CooldownsClearedResponse {
+        status: "ok",
+        cleared,
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeliveryFailureQuery {
+    pub limit: Option<i64>,
+}
+
+/// Notifications no channel could deliver, most recently attempted first.
+pub async fn list_delivery_failures_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    Query(query): Query<DeliveryFailureQuery>,
+) -> Result<Json<DeliveryFailureListResponse>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_DELIVERY_FAILURES);
+    if !(1..=MAX_DELIVERY_FAILURES).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {MAX_DELIVERY_FAILURES}"
+        )));
+    }
+
+    let failures = state.storage.list_delivery_failures(limit)?;
+    Ok(Json(DeliveryFailureListResponse { failures }))
+}
+
+/// Sends a failed notification again. The record is deleted once delivered;
+/// otherwise it keeps the new error and attempt count.
+pub async fn retry_delivery_failure_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    Path(id): Path<String>,
+) -> Result<Json<DeliveryRetryResponse>, AppError> {
+    let failure = state
+        .storage
+        .get_delivery_failure(&id)?
+        .ok_or_else(|| AppError::NotFound("Delivery failure not found".to_string()))?;
+    let notification = state
+        .storage
+        .get_notification(&Partition::All, &failure.notification_id)?;
+
+    let apns_client = state
+        .notif
+        .apns_client
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest("APNs is not configured".to_string()))?;
+
+    let push = PushNotification {
+        collapse_id: truncate_at_char_boundary(&failure.session_id, 64),
+        // The stored notification has the sealed text; the failure row only
+        // has what was shown
+        alert: notification.as_ref().map_or_else(
+            || Alert {
+                title: failure.title,
+                body: failure.body,
+                ..Alert::default()
+            },
+            Alert::from_stored,
+        ),
+        notification_id: failure.notification_id,
+        session_id: failure.session_id,
+        device_id: failure.device_id,
+    };
+    let result = push::deliver(
+        apns_client,
+        &state.storage,
+        &push,
+        &Partition::Key(failure.partition),
+    )
+    .await;
+
+    let attempts = failure.attempts + 1;
+    match result {
+        Ok(()) => {
+            state.storage.delete_delivery_failure(&id)?;
+            tracing::info!(id = %id, attempts, "Delivery failure retried successfully");
+            Ok(Json(DeliveryRetryResponse {
+                id,
+                delivered: true,
+                attempts,
+                error: None,
+            }))
+        }
+        Err(error) => {
+            let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            state
+                .storage
+                .record_delivery_retry_failure(&id, &error, &now)?;
+            Ok(Json(DeliveryRetryResponse {
+                id,
+                delivered: false,
+                attempts,
+                error: Some(error),
+            }))
+        }
+    }
+}
+
+/// Serves operational gauges in the Prometheus text format.
+pub async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+) -> impl IntoResponse {
+    let pool = metrics::PoolSample::read(state.storage.pool(), &state.pool_stats);
+    (
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        metrics::render(&state.notif, &pool),
+    )
+}
+
+pub async fn run_maintenance_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+) -> Result<Json<MaintenanceResponse>, AppError> {
+    let report = state.storage.run_maintenance()?;
+
+    // Push back the next scheduled pass since we just ran one
+    #[allow(clippy::cast_sign_loss)]
+    let now_secs = Utc::now().timestamp() as u64;
+    state
+        .last_maintenance
+        .store(now_secs, std::sync::atomic::Ordering::Relaxed);
+    let _ = state
+        .storage
+        .set_metadata("last_maintenance", &now_secs.to_string());
+
+    tracing::info!(
+        page_count_before = report.page_count_before,
+        page_count_after = report.page_count_after,
+        "Database maintenance run"
+    );
+
+    Ok(Json(MaintenanceResponse {
+        status: "ok",
+        page_count_before: report.page_count_before,
+        page_count_after: report.page_count_after,
+        freelist_count_before: report.freelist_count_before,
+    }))
+}
+
+/// Converts the database to incremental auto-vacuum with a one-off full
+/// `VACUUM`. Databases created before the mode was the default need this
+/// once for [`run_maintenance_handler`] to shrink them.
+///
+/// The `VACUUM` runs on the blocking pool and is served without the request
+/// timeout, since it takes as long as the file takes to rewrite; a client
+/// that disconnects interrupts it, which leaves the database as it was.
+pub async fn run_full_vacuum_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+) -> Result<Json<MaintenanceResponse>, AppError> {
+    tracing::info!("Running full database VACUUM");
+    let report =
+        route_limits::with_storage(&state.storage, |storage| storage.full_vacuum()).await?;
+    tracing::info!(
+        page_count_before = report.page_count_before,
+        page_count_after = report.page_count_after,
+        "Database vacuumed into incremental auto-vacuum mode"
+    );
+
+    Ok(Json(MaintenanceResponse {
+        status: "ok",
+        page_count_before: report.page_count_before,
+        page_count_after: report.page_count_after,
+        freelist_count_before: report.freelist_count_before,
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveExportQuery {
+    pub after_id: Option<i64>,
+}
+
+/// Streams archived events as NDJSON, one event per line, ordered by id.
+/// Each batch of [`ARCHIVE_EXPORT_BATCH`] is read on its own pooled
+/// connection and sent before the next is read, so the export holds no
+/// connection between batches and buffers one batch at a time. Pass
+/// `after_id` to resume from the last id received.
+pub async fn export_archived_events_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: AdminAuth,
+    Query(query): Query<ArchiveExportQuery>,
+) -> impl IntoResponse {
+    let storage = Arc::clone(&state.storage);
+    let batches = stream::try_unfold(Some(query.after_id.unwrap_or(0)), move |cursor| {
+        let storage = Arc::clone(&storage);
+        async move {
+            match cursor {
+                Some(cursor) => export_batch(&storage, cursor).await,
+                None => Ok(None),
+            }
+        }
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(batches),
+    )
+}
+
+/// The NDJSON for archived events after `cursor`, and the cursor for the
+/// batch after it, or `None` when there are no more.
+async fn export_batch(
+    storage: &SharedStorage,
+    cursor: i64,
+) -> Result<Option<(String, Option<i64>)>, std::io::Error> {
+    let batch = route_limits::with_storage(storage, move |storage| {
+        storage.list_archived_events(cursor, ARCHIVE_EXPORT_BATCH)
+    })
+    .await
+    .map_err(|e| export_error(&e))?;
+    let Some(last) = batch.last() else {
+        return Ok(None);
+    };
+    // A short batch is the last one
+    let next = (i64::try_from(batch.len()) == Ok(ARCHIVE_EXPORT_BATCH)).then_some(last.id);
+
+    let mut chunk = String::new();
+    for event in &batch {
+        let line = serde_json::to_string(event).map_err(|e| {
+            export_error(&AppError::Internal(format!(
+                "Failed to serialize event: {e}"
+            )))
+        })?;
+        chunk.push_str(&line);
+        chunk.push('\n');
+    }
+    Ok(Some((chunk, next)))
+}
+
+/// Ends an archive export that has already started streaming. The status
+/// is sent, so the client only sees the body cut short.
+fn export_error(error: &AppError) -> std::io::Error {
+    tracing::warn!(error = ?error, "Archive export failed");
+    std::io::Error::other(format!("{error:?}"))
+}
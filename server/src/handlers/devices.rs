@@ -1,52 +1,212 @@
 use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::Json;
+use chrono::{SecondsFormat, Utc};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::auth::ReadAuth;
-use crate::db::queries;
+use crate::auth::{self, ReadAuth};
+use crate::db::partition::Partition;
+use crate::db::queries::PaginatedSessions;
 use crate::error::AppError;
-use crate::models::response::{DeviceListResponse, SessionListResponse};
+use crate::models::request::RegisterDeviceRequest;
+use crate::models::response::{
+    DeviceConflict, DeviceConflictsResponse, DeviceListResponse, DeviceRegisteredResponse,
+    DeviceResponse, SessionListResponse, SyncInfo,
+};
+use crate::pagination::Pagination;
 use crate::router::AppState;
+use crate::storage;
 
 pub async fn list_devices_handler(
     State(state): State<Arc<AppState>>,
-    _auth: ReadAuth,
+    ReadAuth(partition): ReadAuth,
 ) -> Result<Json<DeviceListResponse>, AppError> {
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
+    let sync = state.sync_info();
+    let devices = load_devices(&state, &partition, &sync)?;
+    Ok(Json(DeviceListResponse { devices, sync }))
+}
+
+/// Groups of devices that share a name, so the operator can rename or
+/// retire the duplicates. Groups are ordered by their most recently seen
+/// device.
+pub async fn list_device_conflicts_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+) -> Result<Json<DeviceConflictsResponse>, AppError> {
+    let mut conflicts: Vec<DeviceConflict> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for device in load_devices(&state, &partition, &state.sync_info())? {
+        let key = name_key(&device.device_name);
+        if let Some(&i) = index.get(&key) {
+            conflicts[i].devices.push(device);
+        } else {
+            index.insert(key, conflicts.len());
+            conflicts.push(DeviceConflict {
+                device_name: device.device_name.clone(),
+                devices: vec![device],
+            });
+        }
+    }
+    conflicts.retain(|conflict| conflict.devices.len() > 1);
+
+    Ok(Json(DeviceConflictsResponse { conflicts }))
+}
 
-    let devices = queries::list_devices(&conn)?;
+/// Devices in `partition` as the device endpoints report them, from the
+/// list cache when it holds them at `sync`'s versions.
+fn load_devices(
+    state: &AppState,
+    partition: &Partition,
+    sync: &SyncInfo,
+) -> Result<Vec<DeviceResponse>, AppError> {
+    state
+        .list_cache
+        .devices
+        .get_or_load(format!("{partition:?}"), sync, Instant::now(), || {
+            let events_since = (Utc::now() - chrono::Duration::hours(24))
+                .to_rfc3339_opts(SecondsFormat::Millis, true);
+            let mut devices = state.storage.list_devices(partition, &events_since)?;
+            for device in &mut devices {
+                device.hook_outdated = device.hook_version.as_deref().is_some_and(|version| {
+                    claudiator_types::is_older_version(version, &state.min_hook_version)
+                });
+            }
+            disambiguate_names(&mut devices);
+            Ok(devices)
+        })
+}
+
+/// Names compare ignoring case and surrounding whitespace.
+fn name_key(device_name: &str) -> String {
+    device_name.trim().to_lowercase()
+}
 
-    Ok(Json(DeviceListResponse { devices }))
+/// Sets `display_name` of every device whose name another device shares.
+fn disambiguate_names(devices: &mut [DeviceResponse]) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for device in devices.iter() {
+        *counts.entry(name_key(&device.device_name)).or_default() += 1;
+    }
+    for device in devices {
+        if counts[&name_key(&device.device_name)] > 1 {
+            device.display_name =
+                claudiator_types::disambiguated_device_name(&device.device_name, &device.device_id);
+        }
+    }
 }
 
 #[derive(Deserialize)]
 pub struct SessionQueryParams {
     pub status: Option<String>,
-    pub limit: Option<i64>,
 }
 
 pub async fn list_device_sessions_handler(
     State(state): State<Arc<AppState>>,
-    _auth: ReadAuth,
+    ReadAuth(partition): ReadAuth,
     Path(device_id): Path<String>,
     Query(params): Query<SessionQueryParams>,
+    page: Pagination,
 ) -> Result<Json<SessionListResponse>, AppError> {
-    let limit = params.limit.unwrap_or(50).clamp(1, 200);
-
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    let sessions = queries::list_sessions(&conn, &device_id, params.status.as_deref(), limit)?;
+    let sync = state.sync_info();
+    let key = format!(
+        "device {device_id} {partition:?} status={:?} limit={}",
+        params.status, page.limit
+    );
+    let page = state
+        .list_cache
+        .sessions
+        .get_or_load(key, &sync, Instant::now(), || {
+            let sessions = state.storage.list_sessions(
+                &partition,
+                &device_id,
+                params.status.as_deref(),
+                page.limit,
+            )?;
+            Ok(PaginatedSessions {
+                sessions,
+                has_more: false,
+                next_offset: 0,
+            })
+        })?;
 
     Ok(Json(SessionListResponse {
-        sessions,
-        has_more: false,
-        next_offset: 0,
+        sessions: page.sessions,
+        has_more: page.has_more,
+        next_offset: page.next_offset,
+        sync,
     }))
 }
+
+/// Scopes granted to keys provisioned by enrollment.
+const ENROLLED_KEY_SCOPES: &str = "write";
+
+/// Exchanges a one-time enrollment code for a new device id and a write key
+/// bound to that device. Needs no `Authorization`: the code is the
+/// credential, so invalid codes count toward the caller's auth failures.
+pub async fn register_device_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisterDeviceRequest>,
+) -> Result<(StatusCode, Json<DeviceRegisteredResponse>), AppError> {
+    let ip = auth::extract_client_ip(&headers);
+    auth::check_rate_limit(&state.auth.failures, ip)?;
+
+    let device_name = payload.device_name.trim();
+    if device_name.is_empty() {
+        return Err(AppError::BadRequest("device_name is required".into()));
+    }
+    let platform = payload.platform.trim();
+    if platform.is_empty() {
+        return Err(AppError::BadRequest("platform is required".into()));
+    }
+
+    let code = payload.enrollment_code.trim().to_uppercase();
+    let device_id = uuid::Uuid::new_v4().to_string();
+    let key_id = uuid::Uuid::new_v4().to_string();
+    let key = auth::generate_api_key();
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    let claimed = storage::transaction(&*state.storage, |tx| {
+        let Some(partition) = tx.claim_enrollment_code(&code, &key_id, &now)? else {
+            return Ok(None);
+        };
+        tx.insert_api_key(
+            &key_id,
+            device_name,
+            &key,
+            ENROLLED_KEY_SCOPES,
+            &now,
+            None,
+            partition.name(),
+        )?;
+        tx.set_api_key_device(&key_id, &device_id)?;
+        tx.upsert_device(&device_id, device_name, platform, partition.name(), &now)?;
+        Ok(Some(partition))
+    })?;
+    let Some(partition) = claimed else {
+        auth::record_auth_failure(&state.auth.failures, ip);
+        return Err(AppError::Unauthorized);
+    };
+    state.list_cache.invalidate();
+
+    tracing::info!(
+        device_id = %device_id,
+        device_name = %device_name,
+        partition = partition.name().unwrap_or("default"),
+        "Device registered with enrollment code"
+    );
+
+    Ok((
+        StatusCode::CREATED,
+        Json(DeviceRegisteredResponse {
+            device_id,
+            key_id,
+            api_key: key,
+            scopes: vec![ENROLLED_KEY_SCOPES.to_string()],
+            partition: partition.name().map(String::from),
+        }),
+    ))
+}
@@ -1,40 +1,22 @@
 use axum::extract::State;
-use axum::http::HeaderMap;
+use axum::http::{header, HeaderMap};
+use axum::Extension;
 use axum::Json;
 use chrono::{SecondsFormat, Utc};
+use serde::de::DeserializeOwned;
 use std::sync::Arc;
 
-use crate::apns::ApnsClient;
-use crate::auth::WriteAuth;
-use crate::db::pool::DbPool;
-use crate::db::queries;
-use crate::error::AppError;
-use crate::models::request::{DeviceInfo, EventData, EventPayload};
+use crate::auth::{AuthenticatedKey, WriteAuth};
+use crate::db::partition::Partition;
+use crate::error::{AppError, FieldError};
+use crate::ingest::EventIngestService;
+use crate::ingest_queue;
+use crate::models::request::{
+    DeviceInfo, EventData, EventPayload, GenericEventRequest, HookEventKind, SEVERITIES,
+};
 use crate::models::response::StatusOk;
-use crate::notif_dedup;
 use crate::router::AppState;
-use crate::utils::truncate_at_char_boundary;
-
-fn validate_event_payload(payload: &EventPayload) -> Result<(), AppError> {
-    if payload.device.device_id.is_empty() {
-        return Err(AppError::BadRequest("device_id is required".into()));
-    }
-    if payload.event.session_id.is_empty() {
-        return Err(AppError::BadRequest("session_id is required".into()));
-    }
-    if payload.event.hook_event_name.is_empty() {
-        return Err(AppError::BadRequest("hook_event_name is required".into()));
-    }
-
-    // Validate timestamp is valid RFC3339
-    if chrono::DateTime::parse_from_rfc3339(&payload.timestamp).is_err() {
-        return Err(AppError::BadRequest(
-            "timestamp must be valid RFC 3339".into(),
-        ));
-    }
-
-    Ok(())
-}
+use crate::unknown_fields;
 
 fn header_value(headers: &HeaderMap, names: &[&str]) -> Option<String> {
     names.iter().find_map(|name| {
@@ -76,332 +58,105 @@ fn device_info_from_headers(headers: &HeaderMap) -> Result<DeviceInfo, AppError>
     })
 }
 
-fn extract_session_title(payload: &EventPayload) -> Option<String> {
-    if payload.event.hook_event_name == "UserPromptSubmit" {
-        payload
-            .event
-            .prompt
-            .as_deref()
-            .map(|p| truncate_at_char_boundary(p, 200))
-    } else {
-        None
-    }
+/// The `claudiator-hook` version in the request's `User-Agent`, if the hook
+/// sent it.
+fn hook_version(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(claudiator_types::hook_version_from_user_agent)
+        .map(String::from)
 }
 
-fn dispatch_push_notifications(
-    apns_client: Arc<ApnsClient>,
-    db_pool: DbPool,
-    title: String,
-    body: String,
-    collapse_id: String,
-    notification_id: String,
-    session_id: String,
-    device_id: String,
-) {
-    tokio::spawn(async move {
-        let tokens = match db_pool.get() {
-            Ok(c) => match queries::list_push_tokens(&c) {
-                Ok(t) => t,
-                Err(e) => {
-                    tracing::warn!("Failed to list push tokens: {:?}", e);
-                    return;
-                }
-            },
-            Err(e) => {
-                tracing::warn!("Failed to get db connection for push: {}", e);
-                return;
-            }
-        };
-
-        for token_row in &tokens {
-            let result = apns_client
-                .send_push(
-                    &token_row.push_token,
-                    &title,
-                    &body,
-                    Some(&collapse_id),
-                    &notification_id,
-                    &session_id,
-                    &device_id,
-                    token_row.sandbox,
-                )
-                .await;
-
-            match result {
-                crate::apns::ApnsPushResult::Success => {
-                    tracing::debug!(
-                        "Push sent to token {}",
-                        &token_row.push_token[..8.min(token_row.push_token.len())]
-                    );
-                }
-                crate::apns::ApnsPushResult::Gone => {
-                    tracing::info!(
-                        "Push token gone, removing: {}",
-                        &token_row.push_token[..8.min(token_row.push_token.len())]
-                    );
-                    if let Ok(c) = db_pool.get() {
-                        let _ = queries::delete_push_token(&c, &token_row.push_token);
-                    }
-                }
-                crate::apns::ApnsPushResult::AuthError => {
-                    tracing::error!("APNs auth error — check credentials");
-                }
-                crate::apns::ApnsPushResult::Retry => {
-                    tracing::warn!("APNs rate limited, skipping remaining tokens");
-                    break;
-                }
-                crate::apns::ApnsPushResult::OtherError(e) => {
-                    tracing::warn!("APNs push error: {}", e);
-                }
-            }
-        }
-    });
+/// Deserializes a request body taken as raw JSON, so its unknown keys can
+/// be seen first.
+fn parse_body<T: DeserializeOwned>(body: &serde_json::Value) -> Result<T, AppError> {
+    T::deserialize(body).map_err(|e| AppError::BadRequest(format!("Invalid event: {e}")))
 }
 
-fn schedule_retention_cleanup(state: &Arc<AppState>) {
-    #[allow(clippy::cast_sign_loss)]
-    let now_secs = Utc::now().timestamp() as u64;
-    let last_cleanup = state
-        .last_cleanup
-        .load(std::sync::atomic::Ordering::Relaxed);
-    let five_minutes_secs = 5 * 60;
-
-    if now_secs.saturating_sub(last_cleanup) >= five_minutes_secs {
-        state
-            .last_cleanup
-            .store(now_secs, std::sync::atomic::Ordering::Relaxed);
-
-        let cleanup_pool = state.db_pool.clone();
-        let retention_events = state.retention_events_days;
-        let retention_sessions = state.retention_sessions_days;
-        let retention_devices = state.retention_devices_days;
-
-        tokio::spawn(async move {
-            let conn = match cleanup_pool.get() {
-                Ok(c) => c,
-                Err(e) => {
-                    tracing::warn!("Failed to get db connection for cleanup: {}", e);
-                    return;
-                }
-            };
-
-            // FK-safe order: events → notifications → sessions → devices
-            match queries::delete_old_events(&conn, retention_events) {
-                Ok(count) if count > 0 => {
-                    tracing::debug!("Cleaned up {} old events", count);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to clean old events: {:?}", e);
-                }
-                _ => {}
-            }
-
-            match queries::delete_expired_notifications(&conn) {
-                Ok(count) if count > 0 => {
-                    tracing::debug!("Cleaned up {} expired notifications", count);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to clean expired notifications: {:?}", e);
-                }
-                _ => {}
-            }
-
-            match queries::delete_stale_sessions(&conn, retention_sessions) {
-                Ok(count) if count > 0 => {
-                    tracing::debug!("Cleaned up {} stale sessions", count);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to clean stale sessions: {:?}", e);
-                }
-                _ => {}
-            }
-
-            match queries::delete_stale_devices(&conn, retention_devices) {
-                Ok(count) if count > 0 => {
-                    tracing::debug!("Cleaned up {} stale devices", count);
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to clean stale devices: {:?}", e);
-                }
-                _ => {}
-            }
-        });
+/// Under `--strict-fields`, records the keys of the raw event object that
+/// ingest will drop.
+fn record_unknown_fields(state: &AppState, event: &serde_json::Value) {
+    if !state.strict_fields {
+        return;
     }
+    let fields = unknown_fields::unknown_keys(event);
+    if fields.is_empty() {
+        return;
+    }
+    let hook_event_name = event
+        .get("hook_event_name")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    unknown_fields::record(&state.unknown_fields, hook_event_name, &fields, &now);
 }
 
-#[allow(clippy::too_many_lines)]
-fn ingest_event(state: &Arc<AppState>, payload: &EventPayload) -> Result<Json<StatusOk>, AppError> {
-    validate_event_payload(payload)?;
-
-    let received_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-
-    // Extract title from UserPromptSubmit events
-    let title = extract_session_title(payload);
-
-    // Derive session status
-    let session_status = derive_session_status(
-        &payload.event.hook_event_name,
-        payload.event.notification_type.as_deref(),
-    );
-
-    // Serialize the full event as JSON for storage
-    let event_json = serde_json::to_string(&payload.event)
-        .map_err(|e| AppError::Internal(format!("Failed to serialize event: {e}")))?;
-
-    // Get a connection from the pool
-    let mut conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    // Execute all inserts in a transaction.
-    // The Transaction type auto-rolls-back on drop if commit() is not called.
-    let event_id = {
-        let tx = conn
-            .transaction()
-            .map_err(|e| AppError::Internal(format!("Transaction begin failed: {e}")))?;
-
-        queries::upsert_device(
-            &tx,
-            &payload.device.device_id,
-            &payload.device.device_name,
-            &payload.device.platform,
-            &received_at,
-        )?;
-
-        queries::upsert_session(
-            &tx,
-            &payload.event.session_id,
-            &payload.device.device_id,
-            &received_at,
-            session_status.as_deref(),
-            payload.event.cwd.as_deref(),
-            title.as_deref(),
-        )?;
-
-        let event_id = queries::insert_event(
-            &tx,
-            &payload.device.device_id,
-            &payload.event.session_id,
-            &payload.event.hook_event_name,
-            &payload.timestamp,
-            &received_at,
-            payload.event.tool_name.as_deref(),
-            payload.event.notification_type.as_deref(),
-            &event_json,
-        )?;
-
-        // Persist data version bump inside the transaction
-        let new_version = state
-            .version
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-            + 1;
-        queries::set_metadata(&tx, "data_version", &new_version.to_string())?;
-
-        tx.commit()
-            .map_err(|e| AppError::Internal(format!("Transaction commit failed: {e}")))?;
-
-        event_id
+/// Admits the event through the [`IngestQueue`](crate::ingest_queue::IngestQueue)
+/// and ingests it on the blocking pool, so a stalled database holds queue
+/// slots rather than async workers.
+async fn ingest(
+    state: Arc<AppState>,
+    partition: Partition,
+    key: Option<AuthenticatedKey>,
+    hook_version: Option<String>,
+    payload: EventPayload,
+) -> Result<(), AppError> {
+    let Some(_permit) = state.ingest_queue.try_admit() else {
+        tracing::warn!(
+            depth = state.ingest_queue.depth(),
+            "Ingest queue full, rejecting event"
+        );
+        return Err(AppError::Overloaded(ingest_queue::RETRY_AFTER_SECS));
     };
 
-    // Fetch session title for notification content
-    let session_title =
-        queries::get_session_title(&conn, &payload.event.session_id).unwrap_or(None);
-
-    // Notification pipeline — after successful commit
-    if let Some((notif_title, notif_body, notif_type)) = should_notify(
-        &payload.event.hook_event_name,
-        payload.event.notification_type.as_deref(),
-        payload.event.message.as_deref(),
-        session_title.as_deref(),
-        payload.event.tool_name.as_deref(),
-    ) {
-        // Gate low-priority types through the per-(session, type) cooldown.
-        // High-priority types (permission_prompt) always pass through.
-        if notif_dedup::should_send_notification(
-            &state.notif_cooldown,
-            &payload.event.session_id,
-            &notif_type,
-        ) {
-            let notification_id = uuid::Uuid::new_v4().to_string();
-
-            let _ = queries::insert_notification(
-                &conn,
-                &notification_id,
-                event_id,
-                &payload.event.session_id,
-                &payload.device.device_id,
-                &notif_title,
-                &notif_body,
-                &notif_type,
-                None,
-                &received_at,
-            );
-
-            // Persist notification version bump
-            let new_notif_version = state
-                .notification_version
-                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-                + 1;
-            let _ = queries::set_metadata(
-                &conn,
-                "notification_version",
-                &new_notif_version.to_string(),
-            );
-
-            // APNs push dispatch
-            if let Some(ref apns_client) = state.apns_client {
-                // Use session_id as collapse_id with 64-byte truncation guard
-                let collapse_id = truncate_at_char_boundary(&payload.event.session_id, 64);
-
-                dispatch_push_notifications(
-                    apns_client.clone(),
-                    state.db_pool.clone(),
-                    notif_title,
-                    notif_body,
-                    collapse_id,
-                    notification_id,
-                    payload.event.session_id.clone(),
-                    payload.device.device_id.clone(),
-                );
-            }
-        } else {
-            tracing::debug!(
-                session_id = %payload.event.session_id,
-                notif_type = %notif_type,
-                "Notification suppressed by cooldown"
-            );
-        }
-    }
-
-    schedule_retention_cleanup(state);
-
-    tracing::info!(
-        device_id = %payload.device.device_id,
-        session_id = %payload.event.session_id,
-        event = %payload.event.hook_event_name,
-        "Event ingested"
-    );
-
-    Ok(Json(StatusOk::ok()))
+    let ingest_state = Arc::clone(&state);
+    tokio::task::spawn_blocking(move || {
+        EventIngestService::new(&ingest_state)
+            .with_key(key.as_ref())
+            .with_hook_version(hook_version.as_deref())
+            .ingest(&partition, &payload)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Ingest task failed: {e}")))?
 }
 
-#[allow(clippy::too_many_lines)]
 pub async fn events_handler(
     State(state): State<Arc<AppState>>,
-    _auth: WriteAuth,
-    Json(payload): Json<EventPayload>,
-) -> Result<Json<StatusOk>, AppError> {
-    ingest_event(&state, &payload)
+    WriteAuth(partition): WriteAuth,
+    key: Option<Extension<AuthenticatedKey>>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Result<([(&'static str, String); 1], Json<StatusOk>), AppError> {
+    let payload: EventPayload = parse_body(&body)?;
+    if let Some(event) = body.get("event") {
+        record_unknown_fields(&state, event);
+    }
+    let min_hook_version = state.min_hook_version.clone();
+    ingest(
+        state,
+        partition,
+        key.map(|Extension(key)| key),
+        hook_version(&headers),
+        payload,
+    )
+    .await?;
+    Ok((
+        [(claudiator_types::MIN_HOOK_VERSION_HEADER, min_hook_version)],
+        Json(StatusOk::ok()),
+    ))
 }
 
 pub async fn http_hook_handler(
     State(state): State<Arc<AppState>>,
-    _auth: WriteAuth,
+    WriteAuth(partition): WriteAuth,
+    key: Option<Extension<AuthenticatedKey>>,
     headers: HeaderMap,
-    Json(event): Json<EventData>,
+    Json(body): Json<serde_json::Value>,
 ) -> Result<Json<StatusOk>, AppError> {
     let device = device_info_from_headers(&headers)?;
+    let event: EventData = parse_body(&body)?;
+    record_unknown_fields(&state, &body);
     let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
     let payload = EventPayload {
         device,
@@ -409,73 +164,114 @@ pub async fn http_hook_handler(
         timestamp,
     };
 
-    ingest_event(&state, &payload)
+    ingest(
+        state,
+        partition,
+        key.map(|Extension(key)| key),
+        None,
+        payload,
+    )
+    .await?;
+    Ok(Json(StatusOk::ok()))
 }
 
-fn derive_session_status(hook_event_name: &str, notification_type: Option<&str>) -> Option<String> {
-    match hook_event_name {
-        "SessionStart" | "UserPromptSubmit" | "SubagentStart" | "SubagentStop" => {
-            Some("active".to_string())
-        }
-        "Stop" => Some("waiting_for_input".to_string()),
-        "SessionEnd" => Some("ended".to_string()),
-        "PermissionRequest" => Some("waiting_for_permission".to_string()),
-        "Notification" => match notification_type {
-            Some("permission_prompt") => Some("waiting_for_permission".to_string()),
-            Some("idle_prompt") => Some("idle".to_string()),
-            _ => None,
-        },
-        _ => None,
-    }
+/// Platform recorded for the device standing in for a generic source.
+const GENERIC_PLATFORM: &str = "generic";
+
+/// The device or session id standing in for `name`, a generic source or
+/// group. Namespaced so it can't be taken for a hook's, and scoped by the
+/// writing key's partition so two partitions' sources of the same name stay
+/// apart: `generic:{name}` in the default partition, and
+/// `generic@{partition}:{name}` in any other. Partition names have no `:`.
+fn generic_id(partition: &Partition, name: &str) -> String {
+    partition.name().map_or_else(
+        || format!("generic:{name}"),
+        |partition| format!("generic@{partition}:{name}"),
+    )
 }
 
-fn should_notify(
-    hook_event_name: &str,
-    notification_type: Option<&str>,
-    message: Option<&str>,
-    session_title: Option<&str>,
-    tool_name: Option<&str>,
-) -> Option<(String, String, String)> {
-    let title_from_session = |fallback: &str| -> String {
-        session_title
-            .filter(|t| !t.is_empty())
-            .map_or_else(|| fallback.to_string(), String::from)
-    };
+/// Ingests a notification from a tool other than Claude Code as an
+/// `External` event, from a device named after its source.
+pub async fn generic_ingest_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    key: Option<Extension<AuthenticatedKey>>,
+    Json(request): Json<GenericEventRequest>,
+) -> Result<Json<StatusOk>, AppError> {
+    let mut errors = Vec::new();
+    if request.source.trim().is_empty() {
+        errors.push(FieldError::new("source", "source is required"));
+    }
+    if request.title.trim().is_empty() {
+        errors.push(FieldError::new("title", "title is required"));
+    }
+    if request
+        .severity
+        .as_deref()
+        .is_some_and(|severity| !SEVERITIES.contains(&severity))
+    {
+        errors.push(FieldError::new(
+            "severity",
+            format!("severity must be one of {}", SEVERITIES.join(", ")),
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
 
-    match hook_event_name {
-        "Stop" => {
-            let title = title_from_session("Session Stopped");
-            let body = format!("Session stopped: {}", message.unwrap_or("No reason given"));
-            Some((title, body, "stop".to_string()))
-        }
-        "Notification" => match notification_type {
-            Some("permission_prompt") => {
-                let title = title_from_session("Permission Required");
-                let body = match (tool_name, message) {
-                    (Some(tool), Some(msg)) => format!("Permission required: {tool} — {msg}"),
-                    (Some(tool), None) => format!("Permission required: {tool}"),
-                    (None, Some(msg)) => format!("Permission required: {msg}"),
-                    (None, None) => "A session needs permission to continue".to_string(),
-                };
-                Some((title, body, "permission_prompt".to_string()))
-            }
-            Some("idle_prompt") => {
-                let title = title_from_session("Session Idle");
-                let body = format!("Session idle: {}", message.unwrap_or("Waiting for input"));
-                Some((title, body, "idle_prompt".to_string()))
-            }
-            _ => None,
+    let source = request.source.trim().to_string();
+    let session_id = generic_id(
+        &partition,
+        request
+            .group
+            .as_deref()
+            .filter(|group| !group.trim().is_empty())
+            .unwrap_or(&source),
+    );
+    let payload = EventPayload {
+        device: DeviceInfo {
+            device_id: generic_id(&partition, &source),
+            device_name: source,
+            platform: GENERIC_PLATFORM.to_string(),
         },
-        "PermissionRequest" => {
-            let title = title_from_session("Permission Required");
-            let body = match (tool_name, message) {
-                (Some(tool), Some(msg)) => format!("Permission required: {tool} — {msg}"),
-                (Some(tool), None) => format!("Permission required: {tool}"),
-                (None, Some(msg)) => format!("Permission required: {msg}"),
-                (None, None) => "A session needs permission to continue".to_string(),
-            };
-            Some((title, body, "permission_prompt".to_string()))
-        }
-        _ => None,
-    }
+        event: EventData {
+            session_id,
+            hook_event_name: HookEventKind::External,
+            cwd: None,
+            prompt: None,
+            notification_type: None,
+            tool_name: None,
+            message: request.body,
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: Some(request.title),
+            severity: Some(
+                request
+                    .severity
+                    .unwrap_or_else(|| SEVERITIES[0].to_string()),
+            ),
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
+        },
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    };
+
+    ingest(
+        state,
+        partition,
+        key.map(|Extension(key)| key),
+        None,
+        payload,
+    )
+    .await?;
+    Ok(Json(StatusOk::ok()))
 }
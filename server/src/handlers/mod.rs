@@ -4,4 +4,5 @@ pub mod events;
 pub mod notifications;
 pub mod ping;
 pub mod push;
+pub mod search;
 pub mod sessions;
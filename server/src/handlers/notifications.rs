@@ -1,48 +1,267 @@
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
 use axum::Json;
+use chrono::{SecondsFormat, Utc};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
+use crate::apns::Alert;
 use crate::auth::{ReadAuth, WriteAuth};
-use crate::db::queries;
+use crate::db::partition::Partition;
 use crate::error::AppError;
-use crate::models::request::AckRequest;
-use crate::models::response::{NotificationListResponse, StatusOk};
+use crate::ingest::push::{self, PushNotification};
+use crate::models::request::{AckRequest, RespondRequest, SnoozeRequest};
+use crate::models::response::{
+    NotificationListResponse, NotificationResendResponse, NotificationSnoozeResponse,
+    PermissionResponse, StatusOk,
+};
+use crate::notif_routing::Channel;
+use crate::pagination::Pagination;
 use crate::router::AppState;
+use crate::snooze::MAX_SNOOZE_MINUTES;
+use crate::time_range::{self, TimeRange};
+use crate::utils::truncate_at_char_boundary;
+
+/// Maximum length of the message attached to a permission response.
+const MAX_RESPONSE_MESSAGE_LEN: usize = 1000;
 
 #[derive(serde::Deserialize)]
 pub struct NotificationQuery {
     pub after: Option<String>,
-    pub limit: Option<i64>,
 }
 
 pub async fn list_notifications_handler(
     State(state): State<Arc<AppState>>,
-    _auth: ReadAuth,
+    ReadAuth(partition): ReadAuth,
     Query(query): Query<NotificationQuery>,
+    range: TimeRange,
+    page: Pagination,
 ) -> Result<Json<NotificationListResponse>, AppError> {
-    let limit = query.limit.unwrap_or(50).min(200);
+    let sync = state.sync_info();
+    let notifications = state.storage.list_notifications(
+        &partition,
+        None,
+        query.after.as_deref(),
+        &range,
+        page.limit,
+    )?;
 
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
+    Ok(Json(NotificationListResponse {
+        notifications,
+        sync,
+    }))
+}
 
-    let notifications = queries::list_notifications(&conn, query.after.as_deref(), limit)?;
+/// One session's notifications, with the same filters and order as
+/// [`list_notifications_handler`].
+pub async fn list_session_notifications_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+    Path(session_id): Path<String>,
+    Query(query): Query<NotificationQuery>,
+    range: TimeRange,
+    page: Pagination,
+) -> Result<Json<NotificationListResponse>, AppError> {
+    let sync = state.sync_info();
+    let notifications = state.storage.list_notifications(
+        &partition,
+        Some(&session_id),
+        query.after.as_deref(),
+        &range,
+        page.limit,
+    )?;
 
-    Ok(Json(NotificationListResponse { notifications }))
+    Ok(Json(NotificationListResponse {
+        notifications,
+        sync,
+    }))
 }
 
 pub async fn acknowledge_notifications_handler(
     State(state): State<Arc<AppState>>,
-    _auth: WriteAuth,
+    WriteAuth(partition): WriteAuth,
     Json(payload): Json<AckRequest>,
 ) -> Result<Json<StatusOk>, AppError> {
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    queries::acknowledge_notifications(&conn, &payload.ids)?;
+    if let Some(ids) = &payload.ids {
+        if payload.session_id.is_some() || payload.before.is_some() {
+            return Err(AppError::BadRequest(
+                "ids can't be combined with session_id or before".into(),
+            ));
+        }
+        state.storage.acknowledge_notifications(&partition, ids)?;
+    } else {
+        let session_id = payload.session_id.as_deref().filter(|s| !s.is_empty());
+        let before = payload
+            .before
+            .as_deref()
+            .map(|before| time_range::normalize("before", before))
+            .transpose()?;
+        if session_id.is_none() && before.is_none() {
+            return Err(AppError::BadRequest(
+                "one of ids, session_id, or before is required".into(),
+            ));
+        }
+        state.storage.acknowledge_notifications_matching(
+            &partition,
+            session_id,
+            before.as_deref(),
+        )?;
+    }
+    // Devices report their unacknowledged counts
+    state.list_cache.invalidate();
 
     Ok(Json(StatusOk::ok()))
 }
+
+pub async fn respond_to_notification_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    Path(id): Path<String>,
+    Json(payload): Json<RespondRequest>,
+) -> Result<Json<PermissionResponse>, AppError> {
+    if !matches!(payload.decision.as_str(), "allow" | "deny") {
+        return Err(AppError::BadRequest(format!(
+            "invalid decision '{}': must be 'allow' or 'deny'",
+            payload.decision
+        )));
+    }
+    if payload
+        .message
+        .as_ref()
+        .is_some_and(|m| m.len() > MAX_RESPONSE_MESSAGE_LEN)
+    {
+        return Err(AppError::BadRequest(format!(
+            "message must be at most {MAX_RESPONSE_MESSAGE_LEN} bytes"
+        )));
+    }
+
+    let notification = state
+        .storage
+        .get_notification(&partition, &id)?
+        .ok_or_else(|| AppError::NotFound(format!("Notification {id} not found")))?;
+    if notification.notification_type != "permission_prompt" {
+        return Err(AppError::BadRequest(format!(
+            "notification {id} is not a permission prompt"
+        )));
+    }
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let response_id = uuid::Uuid::new_v4().to_string();
+    if !state.storage.upsert_response(
+        &response_id,
+        &notification,
+        &payload.decision,
+        payload.message.as_deref(),
+        &now,
+    )? {
+        return Err(AppError::BadRequest(format!(
+            "notification {id} was already answered and delivered"
+        )));
+    }
+    state
+        .storage
+        .acknowledge_notifications(&partition, std::slice::from_ref(&id))?;
+    state.list_cache.invalidate();
+
+    let response = state
+        .storage
+        .get_response_for_notification(&id)?
+        .ok_or_else(|| AppError::Internal(format!("Response for {id} missing after insert")))?;
+
+    Ok(Json(response))
+}
+
+/// Pushes an existing notification again, for when the original push was
+/// missed. Not subject to the cooldown, and delivered before responding.
+pub async fn resend_notification_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    Path(id): Path<String>,
+) -> Result<Json<NotificationResendResponse>, AppError> {
+    let (notification, device_partition) = {
+        let notification = state
+            .storage
+            .get_notification(&partition, &id)?
+            .ok_or_else(|| AppError::NotFound(format!("Notification {id} not found")))?;
+        let device_partition = state
+            .storage
+            .device_partition(&notification.device_id)?
+            .unwrap_or(Partition::Key(None));
+        (notification, device_partition)
+    };
+
+    let apns_client = state
+        .notif
+        .apns_client
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest("APNs is not configured".to_string()))?;
+    if !state
+        .notif
+        .routes
+        .delivers(&notification.notification_type, Channel::Apns)
+    {
+        return Err(AppError::BadRequest(format!(
+            "{} notifications are not routed to the app",
+            notification.notification_type
+        )));
+    }
+
+    let push = PushNotification {
+        collapse_id: truncate_at_char_boundary(&notification.session_id, 64),
+        alert: Alert::from_stored(&notification),
+        notification_id: notification.id,
+        session_id: notification.session_id,
+        device_id: notification.device_id,
+    };
+    let result = push::deliver(apns_client, &state.storage, &push, &device_partition).await;
+    tracing::info!(
+        notification_id = %id,
+        delivered = result.is_ok(),
+        "Notification resent"
+    );
+
+    Ok(Json(NotificationResendResponse {
+        delivered: result.is_ok(),
+        error: result.err(),
+        id,
+    }))
+}
+
+/// Hides a notification from list responses for `minutes`, after which it
+/// is listed and pushed again (see [`crate::snooze`]).
+pub async fn snooze_notification_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    Path(id): Path<String>,
+    Json(payload): Json<SnoozeRequest>,
+) -> Result<Json<NotificationSnoozeResponse>, AppError> {
+    if !(1..=MAX_SNOOZE_MINUTES).contains(&payload.minutes) {
+        return Err(AppError::BadRequest(format!(
+            "minutes must be between 1 and {MAX_SNOOZE_MINUTES}"
+        )));
+    }
+
+    let notification = state
+        .storage
+        .get_notification(&partition, &id)?
+        .ok_or_else(|| AppError::NotFound(format!("Notification {id} not found")))?;
+    if notification.acknowledged {
+        return Err(AppError::BadRequest(format!(
+            "notification {id} is already acknowledged"
+        )));
+    }
+
+    let snoozed_until = (Utc::now() + chrono::Duration::minutes(i64::from(payload.minutes)))
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    state
+        .storage
+        .snooze_notification(&partition, &id, &snoozed_until)?;
+
+    // Clients refetch to drop the snoozed notification
+    let new_notif_version = state.notification_version.fetch_add(1, Ordering::Relaxed) + 1;
+    let _ = state
+        .storage
+        .set_metadata("notification_version", &new_notif_version.to_string());
+    state.list_cache.invalidate();
+
+    Ok(Json(NotificationSnoozeResponse { id, snoozed_until }))
+}
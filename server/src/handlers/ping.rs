@@ -1,19 +1,88 @@
 use axum::extract::State;
+use axum::response::IntoResponse;
 use axum::Json;
+use chrono::{DateTime, SecondsFormat, Utc};
 use std::sync::Arc;
 
+use crate::api_version::ApiVersion;
 use crate::auth::ReadAuth;
 use crate::error::AppError;
-use crate::models::response::StatusOk;
-use crate::router::AppState;
+use crate::health::WINDOW_SECS;
+use crate::models::response::{
+    Capabilities, IncidentItem, IngestQueueStatus, PingResponse, StatusOk, StatusResponse,
+};
+use crate::router::{AppState, ENDPOINTS, MAX_BODY_BYTES};
 
 pub async fn ping_handler(
     State(state): State<Arc<AppState>>,
     _auth: ReadAuth,
-) -> Result<Json<StatusOk>, AppError> {
+) -> Result<Json<PingResponse>, AppError> {
     let data_v = state.version.load(std::sync::atomic::Ordering::Relaxed);
     let notif_v = state
         .notification_version
         .load(std::sync::atomic::Ordering::Relaxed);
-    Ok(Json(StatusOk::with_versions(data_v, notif_v)))
+
+    let mut push_channels = Vec::new();
+    if state.notif.apns_client.is_some() {
+        push_channels.push("apns");
+    }
+
+    Ok(Json(PingResponse {
+        status: StatusOk::with_versions(data_v, notif_v),
+        capabilities: Capabilities {
+            schema_version: claudiator_types::SCHEMA_VERSION,
+            api_versions: ApiVersion::ALL.iter().map(|v| v.number()).collect(),
+            endpoints: ENDPOINTS,
+            push_channels,
+            batch_events: false,
+            max_body_bytes: MAX_BODY_BYTES,
+            min_hook_version: state.min_hook_version.clone(),
+        },
+        ingest_queue: IngestQueueStatus {
+            depth: state.ingest_queue.depth(),
+            capacity: state.ingest_queue.capacity(),
+        },
+    }))
+}
+
+/// Rolled-up server health and recent incidents; see [`crate::health`].
+pub async fn status_handler(
+    State(state): State<Arc<AppState>>,
+    _auth: ReadAuth,
+) -> Json<StatusResponse> {
+    let now = Utc::now();
+    let snapshot = state.health.snapshot(now.timestamp());
+    let format = |secs: i64| {
+        DateTime::from_timestamp(secs, 0)
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    };
+
+    Json(StatusResponse {
+        state: snapshot.state,
+        server_time: now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        window_secs: WINDOW_SECS,
+        requests: snapshot.requests,
+        server_errors: snapshot.server_errors,
+        pushes: snapshot.pushes,
+        push_failures: snapshot.push_failures,
+        incidents: snapshot
+            .incidents
+            .into_iter()
+            .map(|incident| IncidentItem {
+                id: incident.id,
+                kind: incident.kind,
+                summary: incident.summary,
+                started_at: format(incident.started_at),
+                resolved_at: incident.resolved_at.map(format),
+            })
+            .collect(),
+    })
+}
+
+/// JSON Schema of the `POST /api/v1/events` body, generated from the wire
+/// types, so other senders can check payloads before posting. Needs no
+/// `Authorization`: it says nothing the docs don't.
+pub async fn event_schema_handler() -> impl IntoResponse {
+    Json(claudiator_types::event_payload_schema())
 }
@@ -4,7 +4,6 @@ use chrono::{SecondsFormat, Utc};
 use std::sync::Arc;
 
 use crate::auth::WriteAuth;
-use crate::db::queries;
 use crate::error::AppError;
 use crate::models::request::PushRegisterRequest;
 use crate::models::response::StatusOk;
@@ -12,7 +11,7 @@ use crate::router::AppState;
 
 pub async fn push_register_handler(
     State(state): State<Arc<AppState>>,
-    _auth: WriteAuth,
+    WriteAuth(partition): WriteAuth,
     Json(payload): Json<PushRegisterRequest>,
 ) -> Result<Json<StatusOk>, AppError> {
     if payload.platform.is_empty() {
@@ -23,17 +22,18 @@ pub async fn push_register_handler(
     }
 
     let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
-    let sandbox = payload.sandbox.unwrap_or(false);
 
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    queries::upsert_push_token(&conn, &payload.platform, &payload.push_token, &now, sandbox)?;
+    state.storage.upsert_push_token(
+        &payload.platform,
+        &payload.push_token,
+        &now,
+        payload.sandbox,
+        partition.name(),
+    )?;
 
     tracing::info!(
         platform = %payload.platform,
+        sandbox = ?payload.sandbox,
         "Push token registered"
     );
 
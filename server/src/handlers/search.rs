@@ -0,0 +1,41 @@
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::auth::ReadAuth;
+use crate::db::queries;
+use crate::error::AppError;
+use crate::models::response::SearchResponse;
+use crate::pagination::Pagination;
+use crate::route_limits;
+use crate::router::AppState;
+
+#[derive(Deserialize)]
+pub struct SearchQueryParams {
+    pub q: Option<String>,
+}
+
+/// Sessions whose title, and events whose prompt or message, contain every
+/// word of `q`. `limit` and `offset` apply to each list separately.
+pub async fn search_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+    Query(params): Query<SearchQueryParams>,
+    page: Pagination,
+) -> Result<Json<SearchResponse>, AppError> {
+    let expression = params
+        .q
+        .as_deref()
+        .and_then(queries::search_expression)
+        .ok_or_else(|| AppError::BadRequest("q must contain at least one word".to_string()))?;
+
+    let response = route_limits::with_storage(&state.storage, move |storage| {
+        let sessions = storage.search_sessions(&partition, &expression, page.limit, page.offset)?;
+        let events = storage.search_events(&partition, &expression, page.limit, page.offset)?;
+        Ok(SearchResponse { sessions, events })
+    })
+    .await?;
+
+    Ok(Json(response))
+}
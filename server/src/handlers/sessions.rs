@@ -1,70 +1,268 @@
 use axum::extract::{Path, Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
 use axum::Json;
+use chrono::{SecondsFormat, Utc};
 use serde::Deserialize;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::auth::ReadAuth;
-use crate::db::queries;
+use crate::auth::{ReadAuth, WriteAuth};
+use crate::db::partition::Partition;
+use crate::db::queries::EventFilter;
 use crate::error::AppError;
-use crate::models::response::{EventListResponse, SessionListResponse};
+use crate::models::request::UpdateSessionRequest;
+use crate::models::response::{
+    EventDetailResponse, EventListResponse, OverviewResponse, PendingResponsesResponse,
+    SessionListResponse, SessionResponse,
+};
+use crate::pagination::Pagination;
+use crate::route_limits;
 use crate::router::AppState;
+use crate::session_export::{self, MAX_EXPORT_EVENTS};
+use crate::storage::Storage;
+use crate::time_range::TimeRange;
+
+#[derive(Debug, Deserialize)]
+pub struct AllSessionsQueryParams {
+    pub status: Option<String>,
+    pub cwd_prefix: Option<String>,
+    pub team: Option<String>,
+    pub exclude_ended: Option<bool>,
+}
+
+/// Most event types one events request may filter on.
+const MAX_EVENT_TYPES: usize = 32;
 
 #[derive(Deserialize)]
-pub struct EventQueryParams {
-    pub limit: Option<i64>,
+pub struct EventsQueryParams {
+    /// Comma-separated `hook_event_name`s, e.g. `Stop,Notification`.
+    pub types: Option<String>,
+    pub tool: Option<String>,
+    /// A team `task_id`.
+    pub task: Option<String>,
 }
 
 #[derive(Deserialize)]
-pub struct AllSessionsQueryParams {
-    pub status: Option<String>,
-    pub limit: Option<i64>,
-    pub offset: Option<i64>,
-    pub exclude_ended: Option<bool>,
+pub struct ExportQueryParams {
+    /// Only `markdown` is supported; it is also the default.
+    pub format: Option<String>,
 }
 
 pub async fn list_session_events_handler(
     State(state): State<Arc<AppState>>,
-    _auth: ReadAuth,
+    ReadAuth(partition): ReadAuth,
     Path(session_id): Path<String>,
-    Query(params): Query<EventQueryParams>,
+    Query(params): Query<EventsQueryParams>,
+    page: Pagination<100>,
 ) -> Result<Json<EventListResponse>, AppError> {
-    let limit = params.limit.unwrap_or(100);
+    let types: Vec<String> = params
+        .types
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(String::from)
+        .collect();
+    if types.len() > MAX_EVENT_TYPES {
+        return Err(AppError::BadRequest(format!(
+            "types accepts at most {MAX_EVENT_TYPES} event types"
+        )));
+    }
+    let filter = EventFilter {
+        types,
+        tool: params.tool.filter(|tool| !tool.is_empty()),
+        task: params.task.filter(|task| !task.is_empty()),
+    };
 
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
+    let sync = state.sync_info();
+    let events = state
+        .storage
+        .list_events(&partition, &session_id, &filter, page.limit)?;
 
-    let events = queries::list_events(&conn, &session_id, limit)?;
+    Ok(Json(EventListResponse { events, sync }))
+}
+
+pub async fn get_event_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+    Path(id): Path<i64>,
+) -> Result<Json<EventDetailResponse>, AppError> {
+    let event = state
+        .storage
+        .get_event(&partition, id)?
+        .ok_or_else(|| AppError::NotFound(format!("Event {id} not found")))?;
 
-    Ok(Json(EventListResponse { events }))
+    Ok(Json(event))
 }
 
 pub async fn list_all_sessions_handler(
     State(state): State<Arc<AppState>>,
-    _auth: ReadAuth,
+    ReadAuth(partition): ReadAuth,
     Query(params): Query<AllSessionsQueryParams>,
+    range: TimeRange,
+    page: Pagination,
 ) -> Result<Json<SessionListResponse>, AppError> {
-    let limit = params.limit.unwrap_or(50).clamp(1, 200);
-    let offset = params.offset.unwrap_or(0).max(0);
     let exclude_ended = params.exclude_ended.unwrap_or(false);
 
-    let conn = state
-        .db_pool
-        .get()
-        .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))?;
-
-    let result = queries::list_all_sessions_paginated(
-        &conn,
-        params.status.as_deref(),
-        exclude_ended,
-        limit,
-        offset,
-    )?;
+    let sync = state.sync_info();
+    let key = format!("all {partition:?} {params:?} {exclude_ended} {range:?} {page:?}");
+    let result = state
+        .list_cache
+        .sessions
+        .get_or_load(key, &sync, Instant::now(), || {
+            state.storage.list_all_sessions_paginated(
+                &partition,
+                params.status.as_deref(),
+                params.cwd_prefix.as_deref().filter(|p| !p.is_empty()),
+                params.team.as_deref().filter(|t| !t.is_empty()),
+                exclude_ended,
+                &range,
+                page.limit,
+                page.offset,
+            )
+        })?;
 
     Ok(Json(SessionListResponse {
         sessions: result.sessions,
         has_more: result.has_more,
         next_offset: result.next_offset,
+        sync,
     }))
 }
+
+/// Status counts and the most urgent session, for widgets that poll often.
+pub async fn overview_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+) -> Result<Json<OverviewResponse>, AppError> {
+    // Read before querying, so a change racing the queries bumps the version
+    // past the one returned and the client fetches again
+    let data_version = state.version.load(std::sync::atomic::Ordering::Relaxed);
+
+    let response = route_limits::with_storage(&state.storage, move |storage| {
+        Ok(OverviewResponse {
+            data_version,
+            counts: storage.count_sessions_by_status(&partition)?,
+            models: storage.count_sessions_by_model(&partition)?,
+            most_urgent: storage.most_urgent_session(&partition)?,
+        })
+    })
+    .await?;
+
+    Ok(Json(response))
+}
+
+/// A session with its subagents nested under it. Subagents of subagents
+/// are listed under their own parent's `subagents` in turn.
+pub async fn get_session_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+    Path(session_id): Path<String>,
+) -> Result<Json<SessionResponse>, AppError> {
+    let mut session = state
+        .storage
+        .get_session(&partition, &session_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Session {session_id} not found")))?;
+    nest_subagents(
+        &*state.storage,
+        &partition,
+        &mut session,
+        MAX_SUBAGENT_DEPTH,
+    )?;
+
+    Ok(Json(session))
+}
+
+/// Deepest level of subagents nested by [`get_session_handler`]; guards
+/// against a parent cycle in stored data.
+const MAX_SUBAGENT_DEPTH: usize = 8;
+
+fn nest_subagents(
+    storage: &dyn Storage,
+    partition: &Partition,
+    session: &mut SessionResponse,
+    depth: usize,
+) -> Result<(), AppError> {
+    if depth == 0 {
+        return Ok(());
+    }
+    session.subagents = storage.list_subagent_sessions(partition, &session.session_id)?;
+    for subagent in &mut session.subagents {
+        nest_subagents(storage, partition, subagent, depth - 1)?;
+    }
+    Ok(())
+}
+
+pub async fn update_session_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    Path(session_id): Path<String>,
+    Json(payload): Json<UpdateSessionRequest>,
+) -> Result<Json<SessionResponse>, AppError> {
+    if let Some(pinned) = payload.pinned {
+        state
+            .storage
+            .set_session_pinned(&partition, &session_id, pinned)?;
+        state.list_cache.invalidate();
+    }
+
+    let session = state
+        .storage
+        .get_session(&partition, &session_id)?
+        .ok_or_else(|| AppError::NotFound(format!("Session {session_id} not found")))?;
+
+    Ok(Json(session))
+}
+
+/// Polled by the hook while it blocks on a permission prompt. Each response is
+/// returned once: fetching marks it delivered.
+pub async fn pending_responses_handler(
+    State(state): State<Arc<AppState>>,
+    WriteAuth(partition): WriteAuth,
+    Path(session_id): Path<String>,
+) -> Result<Json<PendingResponsesResponse>, AppError> {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let responses = state
+        .storage
+        .take_pending_responses(&partition, &session_id, &now)?;
+
+    Ok(Json(PendingResponsesResponse { responses }))
+}
+
+/// Renders the session's timeline as a Markdown report.
+pub async fn export_session_handler(
+    State(state): State<Arc<AppState>>,
+    ReadAuth(partition): ReadAuth,
+    Path(session_id): Path<String>,
+    Query(params): Query<ExportQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    match params.format.as_deref() {
+        None | Some("markdown" | "md") => {}
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported export format '{other}'; expected 'markdown'"
+            )));
+        }
+    }
+
+    let (session, events, truncated) = route_limits::with_storage(&state.storage, move |storage| {
+        let session = storage
+            .get_session(&partition, &session_id)?
+            .ok_or_else(|| AppError::NotFound(format!("Session {session_id} not found")))?;
+
+        let limit = i64::try_from(MAX_EXPORT_EVENTS + 1).unwrap_or(i64::MAX);
+        let mut events = storage.list_session_timeline(&partition, &session_id, limit)?;
+        let truncated = events.len() > MAX_EXPORT_EVENTS;
+        events.truncate(MAX_EXPORT_EVENTS);
+        Ok((session, events, truncated))
+    })
+    .await?;
+
+    let body = session_export::render_markdown(&session, &events, truncated);
+    Ok((
+        [(header::CONTENT_TYPE, "text/markdown; charset=utf-8")],
+        body,
+    ))
+}
@@ -0,0 +1,296 @@
+//! Rolled-up server health for `GET /api/v1/status`.
+//!
+//! Two failure rates are tracked over the last [`WINDOW_SECS`]: responses
+//! that were `5xx`, and live push deliveries no token accepted. When either
+//! rate crosses its threshold, over enough samples to mean something, an
+//! incident opens and the server reports itself `degraded`. The incident
+//! resolves once the rate is back under the threshold. The last
+//! [`MAX_INCIDENTS`] incidents are kept, in memory only.
+//!
+//! Every function takes `now` in Unix seconds so the rules can be tested
+//! without waiting.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+
+/// Seconds of history the rates cover.
+pub const WINDOW_SECS: i64 = 300;
+
+/// Incidents kept, open and resolved.
+pub const MAX_INCIDENTS: usize = 20;
+
+/// Percentage of `5xx` responses that opens an incident.
+const SERVER_ERROR_PERCENT: u64 = 5;
+
+/// Responses in the window needed before the `5xx` rate is judged.
+const MIN_REQUESTS: u64 = 20;
+
+/// Percentage of failed push deliveries that opens an incident.
+const PUSH_FAILURE_PERCENT: u64 = 25;
+
+/// Push deliveries in the window needed before their rate is judged.
+const MIN_PUSHES: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthState {
+    Ok,
+    Degraded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncidentKind {
+    ServerErrors,
+    PushFailures,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Incident {
+    pub id: u64,
+    pub kind: IncidentKind,
+    /// What crossed the threshold, as counted when the incident opened.
+    pub summary: String,
+    pub started_at: i64,
+    pub resolved_at: Option<i64>,
+}
+
+/// Counts and incidents as of one moment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthSnapshot {
+    pub state: HealthState,
+    pub requests: u64,
+    pub server_errors: u64,
+    pub pushes: u64,
+    pub push_failures: u64,
+    /// Most recent first.
+    pub incidents: Vec<Incident>,
+}
+
+/// Outcomes per second over the window: `(second, total, failed)`.
+#[derive(Debug, Default)]
+struct Rate {
+    buckets: VecDeque<(i64, u64, u64)>,
+}
+
+impl Rate {
+    fn record(&mut self, now: i64, failed: bool) {
+        match self.buckets.back_mut() {
+            Some((second, total, failures)) if *second == now => {
+                *total += 1;
+                *failures += u64::from(failed);
+            }
+            _ => self.buckets.push_back((now, 1, u64::from(failed))),
+        }
+    }
+
+    /// `(total, failed)` over the window ending at `now`.
+    fn totals(&mut self, now: i64) -> (u64, u64) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(second, _, _)| *second <= now - WINDOW_SECS)
+        {
+            self.buckets.pop_front();
+        }
+        self.buckets
+            .iter()
+            .fold((0, 0), |(total, failed), (_, t, f)| (total + t, failed + f))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    requests: Rate,
+    pushes: Rate,
+    incidents: VecDeque<Incident>,
+    next_id: u64,
+}
+
+impl Inner {
+    /// Opens or resolves the incident of `kind` for `failed` of `total`.
+    fn judge(&mut self, kind: IncidentKind, now: i64, total: u64, failed: u64) {
+        let (percent, min_samples, noun) = match kind {
+            IncidentKind::ServerErrors => (SERVER_ERROR_PERCENT, MIN_REQUESTS, "requests"),
+            IncidentKind::PushFailures => (PUSH_FAILURE_PERCENT, MIN_PUSHES, "push deliveries"),
+        };
+        let over = total >= min_samples && failed * 100 > total * percent;
+        let open = self
+            .incidents
+            .iter_mut()
+            .find(|incident| incident.kind == kind && incident.resolved_at.is_none());
+
+        match (open, over) {
+            (Some(incident), false) => incident.resolved_at = Some(now),
+            (None, true) => {
+                self.next_id += 1;
+                self.incidents.push_front(Incident {
+                    id: self.next_id,
+                    kind,
+                    summary: format!(
+                        "{failed} of {total} {noun} failed in the last {} minutes",
+                        WINDOW_SECS / 60
+                    ),
+                    started_at: now,
+                    resolved_at: None,
+                });
+                self.incidents.truncate(MAX_INCIDENTS);
+                tracing::warn!(?kind, failed, total, "Server health degraded");
+            }
+            _ => {}
+        }
+    }
+
+    fn update(&mut self, now: i64) -> ((u64, u64), (u64, u64)) {
+        let requests = self.requests.totals(now);
+        let pushes = self.pushes.totals(now);
+        self.judge(IncidentKind::ServerErrors, now, requests.0, requests.1);
+        self.judge(IncidentKind::PushFailures, now, pushes.0, pushes.1);
+        (requests, pushes)
+    }
+}
+
+/// Failure rates and incidents since the server started.
+#[derive(Debug, Default)]
+pub struct ServerHealth {
+    inner: Mutex<Inner>,
+}
+
+impl ServerHealth {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Counts a response, failed if it was a `5xx`.
+    pub fn record_request(&self, now: i64, server_error: bool) {
+        let mut inner = self.lock();
+        inner.requests.record(now, server_error);
+        inner.update(now);
+    }
+
+    /// Counts a live push delivery, failed if no token accepted it.
+    pub fn record_push(&self, now: i64, failed: bool) {
+        let mut inner = self.lock();
+        inner.pushes.record(now, failed);
+        inner.update(now);
+    }
+
+    pub fn snapshot(&self, now: i64) -> HealthSnapshot {
+        let mut inner = self.lock();
+        let ((requests, server_errors), (pushes, push_failures)) = inner.update(now);
+        let incidents: Vec<Incident> = inner.incidents.iter().cloned().collect();
+        drop(inner);
+
+        let state = if incidents.iter().any(|i| i.resolved_at.is_none()) {
+            HealthState::Degraded
+        } else {
+            HealthState::Ok
+        };
+        HealthSnapshot {
+            state,
+            requests,
+            server_errors,
+            pushes,
+            push_failures,
+            incidents,
+        }
+    }
+}
+
+/// Middleware counting every response toward the `5xx` rate.
+pub async fn track_responses(
+    State(health): State<Arc<ServerHealth>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    health.record_request(
+        chrono::Utc::now().timestamp(),
+        response.status().is_server_error(),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_few_requests_never_degrade() {
+        let health = ServerHealth::default();
+        for _ in 0..MIN_REQUESTS - 1 {
+            health.record_request(1000, true);
+        }
+        let snapshot = health.snapshot(1000);
+        assert_eq!(snapshot.state, HealthState::Ok);
+        assert_eq!(snapshot.server_errors, MIN_REQUESTS - 1);
+        assert!(snapshot.incidents.is_empty());
+    }
+
+    #[test]
+    fn test_server_errors_open_and_resolve_an_incident() {
+        let health = ServerHealth::default();
+        for i in 0..100 {
+            health.record_request(1000, i >= 90);
+        }
+        let snapshot = health.snapshot(1000);
+        assert_eq!(snapshot.state, HealthState::Degraded);
+        assert_eq!(snapshot.incidents.len(), 1);
+        let incident = &snapshot.incidents[0];
+        assert_eq!(incident.kind, IncidentKind::ServerErrors);
+        assert_eq!(incident.started_at, 1000);
+        assert_eq!(
+            incident.summary,
+            "5 of 95 requests failed in the last 5 minutes"
+        );
+
+        // The failures age out of the window
+        health.record_request(1000 + WINDOW_SECS, false);
+        let snapshot = health.snapshot(1000 + WINDOW_SECS);
+        assert_eq!(snapshot.state, HealthState::Ok);
+        assert_eq!(snapshot.requests, 1);
+        assert_eq!(snapshot.incidents[0].resolved_at, Some(1000 + WINDOW_SECS));
+    }
+
+    #[test]
+    fn test_push_failures_degrade_separately() {
+        let health = ServerHealth::default();
+        for i in 0..4 {
+            health.record_push(1000, i < 2);
+        }
+        for _ in 0..50 {
+            health.record_request(1000, false);
+        }
+        let snapshot = health.snapshot(1001);
+        assert_eq!(snapshot.state, HealthState::Degraded);
+        assert_eq!(snapshot.incidents.len(), 1);
+        assert_eq!(snapshot.incidents[0].kind, IncidentKind::PushFailures);
+        assert_eq!(snapshot.push_failures, 2);
+    }
+
+    #[test]
+    fn test_incidents_are_capped() {
+        let health = ServerHealth::default();
+        let mut now = 0;
+        for _ in 0..MAX_INCIDENTS + 5 {
+            for _ in 0..MIN_PUSHES {
+                health.record_push(now, true);
+            }
+            now += WINDOW_SECS;
+            health.snapshot(now);
+        }
+        let snapshot = health.snapshot(now);
+        assert_eq!(snapshot.incidents.len(), MAX_INCIDENTS);
+        assert_eq!(
+            snapshot.incidents[0].id,
+            u64::try_from(MAX_INCIDENTS + 5).unwrap_or_default()
+        );
+    }
+}
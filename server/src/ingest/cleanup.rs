@@ -0,0 +1,175 @@
+//! Retention cleanup, piggybacked on ingestion.
+//!
+//! Cleanup has no task of its own: each ingested event checks whether five
+//! minutes have passed since the last cleanup and, if so, spawns one. Scheduled
+//! database maintenance rides along when its own interval has elapsed.
+
+use chrono::Utc;
+use std::sync::Arc;
+
+use crate::db::queries::NotificationTtl;
+use crate::router::AppState;
+use crate::storage::Storage;
+
+/// Minimum time between cleanup passes, in seconds.
+const CLEANUP_INTERVAL_SECS: u64 = 5 * 60;
+
+pub fn schedule_retention_cleanup(state: &Arc<AppState>) {
+    #[allow(clippy::cast_sign_loss)]
+    let now_secs = Utc::now().timestamp() as u64;
+    let last_cleanup = state
+        .last_cleanup
+        .load(std::sync::atomic::Ordering::Relaxed);
+
+    if now_secs.saturating_sub(last_cleanup) < CLEANUP_INTERVAL_SECS {
+        return;
+    }
+
+    state
+        .last_cleanup
+        .store(now_secs, std::sync::atomic::Ordering::Relaxed);
+
+    let storage = Arc::clone(&state.storage);
+    let retention = state.retention;
+    let notification_ttl = state.notif.ttl.clone();
+    let list_cache = Arc::clone(&state.list_cache);
+    let maintenance_due = claim_maintenance_slot(state, now_secs);
+
+    tokio::spawn(async move {
+        // FK-safe order: events → notifications → sessions → devices
+        if retention.archive_events {
+            match storage.archive_old_events(retention.events_days) {
+                Ok(count) if count > 0 => {
+                    tracing::debug!("Archived {} old events", count);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to archive old events: {:?}", e);
+                }
+                _ => {}
+            }
+        } else {
+            match storage.delete_old_events(retention.events_days) {
+                Ok(count) if count > 0 => {
+                    tracing::debug!("Cleaned up {} old events", count);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to clean old events: {:?}", e);
+                }
+                _ => {}
+            }
+        }
+
+        expire_notifications(&*storage, &notification_ttl);
+
+        match storage.delete_stale_sessions(retention.sessions_days) {
+            Ok(count) if count > 0 => {
+                tracing::debug!("Cleaned up {} stale sessions", count);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to clean stale sessions: {:?}", e);
+            }
+            _ => {}
+        }
+
+        match storage.delete_stale_devices(retention.devices_days) {
+            Ok(count) if count > 0 => {
+                tracing::debug!("Cleaned up {} stale devices", count);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to clean stale devices: {:?}", e);
+            }
+            _ => {}
+        }
+
+        match storage.delete_stale_push_tokens(retention.push_tokens_days) {
+            Ok(count) if count > 0 => {
+                tracing::info!("Pruned {} stale push tokens", count);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to prune stale push tokens: {:?}", e);
+            }
+            _ => {}
+        }
+
+        list_cache.invalidate();
+
+        // Reclaim pages freed by the deletes above
+        if maintenance_due {
+            match storage.run_maintenance() {
+                Ok(report) => {
+                    let _ = storage.set_metadata("last_maintenance", &now_secs.to_string());
+                    tracing::info!(
+                        "Database maintenance complete: {} -> {} pages",
+                        report.page_count_before,
+                        report.page_count_after
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to run database maintenance: {:?}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Deletes notifications past their TTL, along with permission responses
+/// that have outlived the notification type they answer and delivery
+/// failures for notifications that are gone.
+fn expire_notifications(storage: &dyn Storage, ttl: &NotificationTtl) {
+    match storage.delete_expired_notifications(ttl) {
+        Ok(count) if count > 0 => {
+            tracing::debug!("Cleaned up {} expired notifications", count);
+        }
+        Err(e) => {
+            tracing::warn!("Failed to clean expired notifications: {:?}", e);
+        }
+        _ => {}
+    }
+
+    match storage.delete_orphaned_delivery_failures() {
+        Ok(count) if count > 0 => {
+            tracing::debug!("Cleaned up {} expired delivery failures", count);
+        }
+        Err(e) => {
+            tracing::warn!("Failed to clean expired delivery failures: {:?}", e);
+        }
+        _ => {}
+    }
+
+    match storage.delete_expired_responses(ttl.hours_for("permission_prompt")) {
+        Ok(count) if count > 0 => {
+            tracing::debug!("Cleaned up {} expired responses", count);
+        }
+        Err(e) => {
+            tracing::warn!("Failed to clean expired responses: {:?}", e);
+        }
+        _ => {}
+    }
+}
+
+/// Returns `true` (and records `now_secs` as the last run) when a scheduled
+/// maintenance pass is due. An interval of 0 disables scheduled maintenance.
+/// The pass stores the time in the metadata table once it succeeds, so the
+/// interval carries across restarts.
+fn claim_maintenance_slot(state: &AppState, now_secs: u64) -> bool {
+    if state.retention.maintenance_interval_hours == 0 {
+        return false;
+    }
+
+    let interval_secs = state
+        .retention
+        .maintenance_interval_hours
+        .saturating_mul(60 * 60);
+    let last_maintenance = state
+        .last_maintenance
+        .load(std::sync::atomic::Ordering::Relaxed);
+
+    if now_secs.saturating_sub(last_maintenance) < interval_secs {
+        return false;
+    }
+
+    state
+        .last_maintenance
+        .store(now_secs, std::sync::atomic::Ordering::Relaxed);
+    true
+}
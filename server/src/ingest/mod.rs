@@ -0,0 +1,1019 @@
+//! Event ingestion pipeline shared by `POST /api/v1/events` and
+//! `POST /api/v1/hooks/http`.
+//!
+//! [`EventIngestService::ingest`] runs each event through fixed stages:
+//!
+//! 1. **validate** — reject malformed payloads, listing every invalid field,
+//!    before touching the database
+//! 2. **redact** — in privacy mode (server-wide or for the key), drop the
+//!    prompt, message, and task subject so only metadata goes further
+//! 3. **nest** — move a `SubagentStart` or `SubagentStop` carrying an
+//!    `agent_id` to the subagent's own session, under the one that started it
+//! 4. **prepare** — derive the session title, status, and stored JSON (per
+//!    the type's [`StorageTier`](crate::event_storage::StorageTier))
+//! 5. **authorize** — check the key's device binding, and its partition
+//!    against the device and session
+//! 6. **persist** — write device, session, and event in one transaction
+//! 7. **notify** — store a notification and dispatch pushes, after commit;
+//!    also, once a day, one saying the device's hook is older than
+//!    `--min-hook-version`
+//! 8. **publish** — broadcast the stored event on the [`EventBus`](crate::bus::EventBus)
+//! 9. **cleanup** — schedule retention cleanup
+//!
+//! A `Heartbeat` event skips the pipeline after validation: it authorizes
+//! against the device alone and only refreshes the device's `last_heartbeat`,
+//! storing no session or event. It bumps the data version only when the
+//! device is new or was silent for [`HEARTBEAT_STALE_SECS`].
+//!
+//! The stages that make decisions ([`validate`], [`redact`], [`nest_subagent`],
+//! [`derive_session_status`],
+//! [`check_device_binding`], [`resolve_device_partition`],
+//! [`notification::content_for_event`]) are plain functions of their inputs,
+//! so they are tested without a database.
+
+mod cleanup;
+pub mod notification;
+pub mod push;
+
+use chrono::{Duration, SecondsFormat, Utc};
+use std::sync::Arc;
+
+use crate::apns::{Alert, LocalizedAlert};
+use crate::auth::AuthenticatedKey;
+use crate::bus::{IngestedEvent, IngestedNotification};
+use crate::clock_skew;
+use crate::db::partition::Partition;
+use crate::db::queries;
+use crate::error::{AppError, FieldError};
+use crate::models::request::{EventPayload, HookEventKind, PRIORITY_HIGH, SEVERITIES};
+use crate::notif_dedup;
+use crate::notif_routing::Channel;
+use crate::router::AppState;
+use crate::session_title;
+use crate::storage;
+use crate::utils::truncate_at_char_boundary;
+
+use self::notification::NotificationContent;
+use self::push::PushNotification;
+
+/// Largest `prompt` accepted, in bytes. Generous for anything typed or
+/// pasted, and still well under the request body limit once sealed.
+pub const MAX_PROMPT_BYTES: usize = 256 * 1024;
+
+/// Most environment variables an event may carry in `env`.
+pub const MAX_ENV_VARS: usize = 32;
+
+/// Seconds without a heartbeat after which a device's next one counts as
+/// coming back online.
+pub const HEARTBEAT_STALE_SECS: i64 = 10 * 60;
+
+/// Runs events through the ingestion stages against shared server state.
+pub struct EventIngestService<'a> {
+    state: &'a Arc<AppState>,
+    key: Option<&'a AuthenticatedKey>,
+    hook_version: Option<&'a str>,
+}
+
+/// Values derived from a payload before any database work.
+#[derive(Debug)]
+struct PreparedEvent {
+    received_at: String,
+    usage_day: String,
+    title: Option<String>,
+    session_status: Option<String>,
+    /// Set when the event was moved to a subagent's session.
+    parent_session_id: Option<String>,
+    event_json: String,
+    /// `received_at` minus the event's timestamp; see [`clock_skew`].
+    skew_ms: Option<i64>,
+    /// Start of the window the device's clock offset is taken from.
+    offset_since: String,
+}
+
+impl<'a> EventIngestService<'a> {
+    pub const fn new(state: &'a Arc<AppState>) -> Self {
+        Self {
+            state,
+            key: None,
+            hook_version: None,
+        }
+    }
+
+    /// Ingests on behalf of `key`: events count toward its usage, and a
+    /// device-bound key may only write for its own device.
+    pub const fn with_key(mut self, key: Option<&'a AuthenticatedKey>) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Records `hook_version` as the sending device's hook version.
+    pub const fn with_hook_version(mut self, hook_version: Option<&'a str>) -> Self {
+        self.hook_version = hook_version;
+        self
+    }
+
+    /// Stores `payload` on behalf of a key scoped to `partition`, then
+    /// notifies and schedules cleanup.
+    pub fn ingest(&self, partition: &Partition, payload: &EventPayload) -> Result<(), AppError> {
+        validate(payload)?;
+        if payload.event.hook_event_name == HookEventKind::Heartbeat {
+            return self.record_heartbeat(partition, payload);
+        }
+        let redacted;
+        let payload = if self.privacy_mode() {
+            redacted = redact(payload);
+            &redacted
+        } else {
+            payload
+        };
+        let nested = nest_subagent(payload);
+        let (payload, parent_session_id) = match &nested {
+            Some((nested, parent)) => (nested, Some(parent.as_str())),
+            None => (payload, None),
+        };
+        let prepared = self.prepare(payload, parent_session_id)?;
+
+        check_device_binding(
+            self.key.and_then(|key| key.device_id.as_deref()),
+            &payload.device.device_id,
+        )?;
+
+        let storage = &self.state.storage;
+        let device_partition = resolve_device_partition(
+            partition,
+            storage.device_partition(&payload.device.device_id)?,
+            storage
+                .session_partition(&payload.event.session_id)?
+                .as_ref(),
+        )?;
+
+        let event_id = self.persist(&device_partition, payload, &prepared)?;
+        let notification = self.notify(&device_partition, payload, event_id, &prepared.received_at);
+        self.notify_outdated_hook(&device_partition, payload, event_id, &prepared);
+        self.state.event_bus.publish(IngestedEvent {
+            event_id,
+            partition: device_partition,
+            payload: payload.clone(),
+            received_at: prepared.received_at,
+            session_status: prepared.session_status,
+            notification,
+        });
+        cleanup::schedule_retention_cleanup(self.state);
+
+        tracing::info!(
+            device_id = %payload.device.device_id,
+            session_id = %payload.event.session_id,
+            event = %payload.event.hook_event_name,
+            "Event ingested"
+        );
+
+        Ok(())
+    }
+
+    /// Whether content is dropped at ingest: for every key with
+    /// `--privacy-mode`, or for keys created with `privacy_mode`.
+    fn privacy_mode(&self) -> bool {
+        self.state.privacy_mode || self.key.is_some_and(|key| key.privacy_mode)
+    }
+
+    /// Refreshes the device's `last_seen` and `last_heartbeat`, creating the
+    /// device if this is the first it has sent.
+    fn record_heartbeat(
+        &self,
+        partition: &Partition,
+        payload: &EventPayload,
+    ) -> Result<(), AppError> {
+        check_device_binding(
+            self.key.and_then(|key| key.device_id.as_deref()),
+            &payload.device.device_id,
+        )?;
+
+        let device_partition = resolve_device_partition(
+            partition,
+            self.state
+                .storage
+                .device_partition(&payload.device.device_id)?,
+            None,
+        )?;
+
+        let now = Utc::now();
+        let received_at = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+        storage::transaction(&*self.state.storage, |tx| {
+            let previous = tx.device_last_heartbeat(&payload.device.device_id)?;
+            tx.upsert_device(
+                &payload.device.device_id,
+                &payload.device.device_name,
+                &payload.device.platform,
+                device_partition.name(),
+                &received_at,
+            )?;
+            tx.set_device_heartbeat(&payload.device.device_id, &received_at)?;
+            if let Some(hook_version) = self.hook_version {
+                tx.set_device_hook_version(&payload.device.device_id, hook_version)?;
+            }
+
+            // Bump the data version only when the device shows up or comes back,
+            // so steady heartbeats don't make every client refetch; the cached
+            // device list picks up a plain refresh when it expires
+            if heartbeat_changes_device(previous.as_deref(), now) {
+                let new_version = self
+                    .state
+                    .version
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                    + 1;
+                tx.set_metadata("data_version", &new_version.to_string())?;
+            }
+
+            Ok(())
+        })?;
+
+        tracing::debug!(device_id = %payload.device.device_id, "Heartbeat recorded");
+        Ok(())
+    }
+
+    fn prepare(
+        &self,
+        payload: &EventPayload,
+        parent_session_id: Option<&str>,
+    ) -> Result<PreparedEvent, AppError> {
+        let title = session_title::derive_title(
+            self.state.session_title_strategy,
+            &self.state.session_title_template,
+            payload.event.hook_event_name.as_str(),
+            payload.event.prompt.as_deref(),
+            payload.event.cwd.as_deref(),
+        );
+
+        // A subagent's session ends with it; the parent carries on
+        let session_status = if parent_session_id.is_some()
+            && payload.event.hook_event_name == HookEventKind::SubagentStop
+        {
+            Some("ended".to_string())
+        } else {
+            derive_session_status(
+                payload.event.hook_event_name.as_str(),
+                payload.event.notification_type.as_deref(),
+            )
+        };
+
+        // Serialize the event as JSON for storage, trimmed to its type's tier
+        let event_json = serde_json::to_string(&self.state.event_storage.stored(&payload.event))
+            .map_err(|e| AppError::Internal(format!("Failed to serialize event: {e}")))?;
+
+        let now = Utc::now();
+        let received_at = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+        Ok(PreparedEvent {
+            skew_ms: clock_skew::skew_ms(&payload.timestamp, &received_at),
+            offset_since: (now - Duration::seconds(clock_skew::OFFSET_WINDOW_SECS))
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            received_at,
+            usage_day: now.format(queries::USAGE_DAY_FORMAT).to_string(),
+            title,
+            session_status,
+            parent_session_id: parent_session_id.map(String::from),
+            event_json,
+        })
+    }
+
+    /// Writes device, session, and event in one transaction and returns the
+    /// new event's id. Nothing is written if any write fails.
+    fn persist(
+        &self,
+        device_partition: &Partition,
+        payload: &EventPayload,
+        prepared: &PreparedEvent,
+    ) -> Result<i64, AppError> {
+        storage::transaction(&*self.state.storage, |tx| {
+            tx.upsert_device(
+                &payload.device.device_id,
+                &payload.device.device_name,
+                &payload.device.platform,
+                device_partition.name(),
+                &prepared.received_at,
+            )?;
+            if let Some(hook_version) = self.hook_version {
+                tx.set_device_hook_version(&payload.device.device_id, hook_version)?;
+            }
+
+            tx.upsert_session(
+                &payload.event.session_id,
+                &payload.device.device_id,
+                &prepared.received_at,
+                prepared.session_status.as_deref(),
+                payload.event.cwd.as_deref(),
+                prepared.title.as_deref(),
+            )?;
+            if let Some(parent_session_id) = prepared.parent_session_id.as_deref() {
+                tx.set_session_parent(
+                    &payload.event.session_id,
+                    parent_session_id,
+                    payload.event.agent_type.as_deref(),
+                )?;
+            }
+
+            if let Some(title) = prepared.title.as_deref() {
+                if self.state.session_title_strategy.replaces_existing() {
+                    tx.set_session_title(&payload.event.session_id, title)?;
+                }
+            }
+
+            let event_id = tx.insert_event(
+                &payload.device.device_id,
+                &payload.event.session_id,
+                payload.event.hook_event_name.as_str(),
+                &payload.timestamp,
+                &prepared.received_at,
+                payload.event.tool_name.as_deref(),
+                payload.event.notification_type.as_deref(),
+                &prepared.event_json,
+            )?;
+            if let Some(skew_ms) = prepared.skew_ms {
+                let device_id = &payload.device.device_id;
+                let offset_ms = tx
+                    .min_device_skew(device_id, &prepared.offset_since)?
+                    .map_or(skew_ms, |min| min.min(skew_ms));
+                if let Some(normalized) = clock_skew::normalize(&payload.timestamp, offset_ms) {
+                    tx.set_event_clock(event_id, skew_ms, &normalized)?;
+                }
+                tx.set_device_clock_skew(device_id, offset_ms)?;
+            }
+            if payload.event.team_name.is_some() || payload.event.task_id.is_some() {
+                tx.set_event_team_task(
+                    event_id,
+                    payload.event.team_name.as_deref(),
+                    payload.event.task_id.as_deref(),
+                )?;
+            }
+            if let Some(team_name) = payload.event.team_name.as_deref() {
+                tx.set_session_team(&payload.event.session_id, team_name)?;
+            }
+            if payload.event.hook_event_name == HookEventKind::SessionStart {
+                if let Some(model) = payload.event.model.as_deref() {
+                    tx.set_session_model(&payload.event.session_id, model)?;
+                }
+            }
+
+            if let Some(key) = self.key {
+                tx.increment_key_usage(&key.id, &prepared.usage_day, 0, 1)?;
+            }
+
+            // Persist data version bump inside the transaction
+            let new_version = self
+                .state
+                .version
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                + 1;
+            tx.set_metadata("data_version", &new_version.to_string())?;
+
+            Ok(event_id)
+        })
+    }
+
+    /// Stores the event's notification, if any, and pushes it to the
+    /// partition's devices. Runs after commit; failures are logged, not
+    /// returned, since the event itself is already stored.
+    fn notify(
+        &self,
+        device_partition: &Partition,
+        payload: &EventPayload,
+        event_id: i64,
+        received_at: &str,
+    ) -> Option<IngestedNotification> {
+        let session_title = self
+            .state
+            .storage
+            .get_session_title(&payload.event.session_id)
+            .unwrap_or(None);
+
+        let content = notification::content_for_event(
+            payload.event.hook_event_name.as_str(),
+            payload.event.notification_type.as_deref(),
+            payload.event.message.as_deref(),
+            session_title.as_deref(),
+            payload.event.tool_name.as_deref(),
+        )
+        .or_else(|| {
+            notification::external_content(
+                payload.event.hook_event_name.as_str(),
+                payload.event.title.as_deref(),
+                payload.event.message.as_deref(),
+                &payload.device.device_name,
+            )
+        })
+        .or_else(|| {
+            notification::compacting_content(
+                payload.event.hook_event_name.as_str(),
+                payload.event.trigger.as_deref(),
+                payload.event.custom_instructions.as_deref(),
+                session_title.as_deref(),
+            )
+        })
+        .or_else(|| {
+            notification::error_content(
+                payload.event.severity.as_deref(),
+                payload.event.error.as_deref(),
+                payload.event.is_interrupt,
+                payload.event.tool_name.as_deref(),
+                session_title.as_deref(),
+                self.state.notif.error_min_severity,
+            )
+        })
+        .or_else(|| {
+            notification::task_completed_content(
+                payload.event.hook_event_name.as_str(),
+                payload.event.task_subject.as_deref(),
+                session_title.as_deref(),
+            )
+        })
+        .or_else(|| {
+            notification::policy_content(
+                payload.event.priority.as_deref(),
+                payload.event.message.as_deref(),
+                session_title.as_deref(),
+                payload.event.tool_name.as_deref(),
+            )
+        })?;
+
+        // Types routed away from the app are neither stored nor pushed, and
+        // don't start a cooldown
+        if !self.routed_to_app(&content, payload) {
+            return None;
+        }
+
+        // Gate low-priority types through the per-(session, type) cooldown.
+        // High-priority types (permission_prompt) always pass through.
+        if !notif_dedup::should_send_notification(
+            &self.state.notif.cooldown,
+            &payload.event.session_id,
+            &content.notification_type,
+        ) {
+            tracing::debug!(
+                session_id = %payload.event.session_id,
+                notif_type = %content.notification_type,
+                "Notification suppressed by cooldown"
+            );
+            return None;
+        }
+
+        Some(self.deliver(
+            device_partition,
+            payload,
+            event_id,
+            received_at,
+            content,
+            &payload.event.session_id,
+        ))
+    }
+
+    /// Tells the user, at most once a day per device, that the device's hook
+    /// is older than `--min-hook-version`. The notice rides on the event
+    /// that revealed it. Like [`Self::notify`], failures are only logged.
+    fn notify_outdated_hook(
+        &self,
+        device_partition: &Partition,
+        payload: &EventPayload,
+        event_id: i64,
+        prepared: &PreparedEvent,
+    ) {
+        let Some(hook_version) = self.hook_version else {
+            return;
+        };
+        if !claudiator_types::is_older_version(hook_version, &self.state.min_hook_version) {
+            return;
+        }
+        let content = notification::outdated_hook_content(
+            &payload.device.device_name,
+            hook_version,
+            &self.state.min_hook_version,
+        );
+        if !self.routed_to_app(&content, payload) {
+            return;
+        }
+        match self
+            .state
+            .storage
+            .claim_outdated_hook_notice(&payload.device.device_id, &prepared.usage_day)
+        {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to claim outdated hook notice");
+                return;
+            }
+        }
+
+        tracing::info!(
+            device_id = %payload.device.device_id,
+            hook_version,
+            min_hook_version = %self.state.min_hook_version,
+            "Device is running an outdated hook"
+        );
+        self.deliver(
+            device_partition,
+            payload,
+            event_id,
+            &prepared.received_at,
+            content,
+            &format!("outdated-hook-{}", payload.device.device_id),
+        );
+    }
+
+    /// Whether `content`'s type is delivered to the app at all.
+    fn routed_to_app(&self, content: &NotificationContent, payload: &EventPayload) -> bool {
+        let routed = self
+            .state
+            .notif
+            .routes
+            .delivers(&content.notification_type, Channel::Apns);
+        if !routed {
+            tracing::debug!(
+                session_id = %payload.event.session_id,
+                notif_type = %content.notification_type,
+                "Notification not routed to the app"
+            );
+        }
+        routed
+    }
+
+    /// Stores `content` as a notification for the event and pushes it to
+    /// the partition's devices. Pushes with the same `collapse_id` replace
+    /// each other on the phone.
+    fn deliver(
+        &self,
+        device_partition: &Partition,
+        payload: &EventPayload,
+        event_id: i64,
+        received_at: &str,
+        content: NotificationContent,
+        collapse_id: &str,
+    ) -> IngestedNotification {
+        let notification_id = uuid::Uuid::new_v4().to_string();
+        let payload_json = notification_payload(&content, payload);
+
+        let _ = self.state.storage.insert_notification(
+            &notification_id,
+            event_id,
+            &payload.event.session_id,
+            &payload.device.device_id,
+            &content.title,
+            &content.body,
+            &content.notification_type,
+            payload_json.as_deref(),
+            received_at,
+        );
+
+        // Persist notification version bump
+        let new_notif_version = self
+            .state
+            .notification_version
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            + 1;
+        let _ = self
+            .state
+            .storage
+            .set_metadata("notification_version", &new_notif_version.to_string());
+
+        if let Some(ref apns_client) = self.state.notif.apns_client {
+            push::dispatch(
+                apns_client.clone(),
+                Arc::clone(&self.state.storage),
+                Arc::clone(&self.state.health),
+                PushNotification {
+                    alert: Alert {
+                        title: content.title.clone(),
+                        body: content.body.clone(),
+                        localized: content.localized.clone(),
+                        sealed: content.sealed.clone(),
+                    },
+                    // APNs caps collapse ids at 64 bytes
+                    collapse_id: truncate_at_char_boundary(collapse_id, 64),
+                    notification_id: notification_id.clone(),
+                    session_id: payload.event.session_id.clone(),
+                    device_id: payload.device.device_id.clone(),
+                },
+                device_partition.clone(),
+            );
+        }
+
+        IngestedNotification {
+            id: notification_id,
+            title: content.title,
+            body: content.body,
+            notification_type: content.notification_type,
+        }
+    }
+}
+
+/// `payload` without what the user or Claude wrote: the prompt, message,
+/// task subject, compaction instructions, tool error, permission
+/// suggestions (which quote commands), an external event's title, and the
+/// captured environment variables. Names, the working directory, tool
+/// names, and timestamps are kept, so sessions and their status still track.
+pub fn redact(payload: &EventPayload) -> EventPayload {
+    let mut redacted = payload.clone();
+    redacted.event.prompt = None;
+    redacted.event.message = None;
+    redacted.event.task_subject = None;
+    redacted.event.custom_instructions = None;
+    redacted.event.error = None;
+    redacted.event.permission_suggestions = None;
+    redacted.event.title = None;
+    redacted.event.env = None;
+    redacted
+}
+
+/// Extra data for the app stored with a notification, as JSON: the keys
+/// the text was rendered from, sealed text, which the app opens with its
+/// own key, the options Claude Code suggested with a permission request,
+/// and the severity of an external event or an error. `None` when there is
+/// none of these.
+fn notification_payload(content: &NotificationContent, payload: &EventPayload) -> Option<String> {
+    let mut extra = serde_json::Map::new();
+    if content.localized != LocalizedAlert::default() {
+        extra.insert("loc".to_string(), serde_json::json!(content.localized));
+    }
+    if let Some(sealed) = &content.sealed {
+        extra.insert("e2e".to_string(), serde_json::json!(sealed));
+    }
+    if payload.event.hook_event_name == HookEventKind::PermissionRequest {
+        if let Some(suggestions) = &payload.event.permission_suggestions {
+            extra.insert("permission_suggestions".to_string(), suggestions.clone());
+        }
+    }
+    if payload.event.hook_event_name == HookEventKind::External {
+        if let Some(severity) = &payload.event.severity {
+            extra.insert("severity".to_string(), serde_json::json!(severity));
+        }
+    } else if content.notification_type == "error" {
+        if let Some(severity) = notification::error_severity(
+            payload.event.severity.as_deref(),
+            payload.event.error.as_deref(),
+            payload.event.is_interrupt,
+        ) {
+            extra.insert("severity".to_string(), serde_json::json!(severity.as_str()));
+        }
+    }
+    (!extra.is_empty()).then(|| serde_json::Value::Object(extra).to_string())
+}
+
+/// For a `SubagentStart` or `SubagentStop` carrying an `agent_id`, `payload`
+/// moved to the subagent's session (keyed by `agent_id`) and the id of the
+/// session that started it. `None` for every other event, which stays on
+/// its own session.
+pub fn nest_subagent(payload: &EventPayload) -> Option<(EventPayload, String)> {
+    if !matches!(
+        payload.event.hook_event_name,
+        HookEventKind::SubagentStart | HookEventKind::SubagentStop
+    ) {
+        return None;
+    }
+    let agent_id = payload
+        .event
+        .agent_id
+        .as_deref()
+        .filter(|id| !id.is_empty() && *id != payload.event.session_id)?;
+    let mut nested = payload.clone();
+    nested.event.session_id = agent_id.to_string();
+    Some((nested, payload.event.session_id.clone()))
+}
+
+/// Rejects payloads missing required fields or carrying a bad timestamp.
+pub fn validate(payload: &EventPayload) -> Result<(), AppError> {
+    let mut errors = Vec::new();
+    if payload.device.device_id.is_empty() {
+        errors.push(FieldError::new("device.device_id", "device_id is required"));
+    }
+    if payload.event.hook_event_name.as_str().is_empty() {
+        errors.push(FieldError::new(
+            "event.hook_event_name",
+            "hook_event_name is required",
+        ));
+    }
+    // Heartbeats report on the device, not a session
+    if payload.event.session_id.is_empty()
+        && payload.event.hook_event_name != HookEventKind::Heartbeat
+    {
+        errors.push(FieldError::new(
+            "event.session_id",
+            "session_id is required",
+        ));
+    }
+
+    if payload
+        .event
+        .priority
+        .as_deref()
+        .is_some_and(|priority| priority != PRIORITY_HIGH)
+    {
+        errors.push(FieldError::new(
+            "event.priority",
+            format!("priority must be '{PRIORITY_HIGH}' when present"),
+        ));
+    }
+
+    if payload
+        .event
+        .severity
+        .as_deref()
+        .is_some_and(|severity| !SEVERITIES.contains(&severity))
+    {
+        errors.push(FieldError::new(
+            "event.severity",
+            format!("severity must be one of {}", SEVERITIES.join(", ")),
+        ));
+    }
+    if payload.event.hook_event_name == HookEventKind::External
+        && payload.event.title.as_deref().is_none_or(str::is_empty)
+    {
+        errors.push(FieldError::new(
+            "event.title",
+            "title is required for External events",
+        ));
+    }
+
+    if payload
+        .event
+        .env
+        .as_ref()
+        .is_some_and(|env| env.len() > MAX_ENV_VARS)
+    {
+        errors.push(FieldError::new(
+            "event.env",
+            format!("env must have at most {MAX_ENV_VARS} variables"),
+        ));
+    }
+
+    if payload
+        .event
+        .prompt
+        .as_ref()
+        .is_some_and(|prompt| prompt.len() > MAX_PROMPT_BYTES)
+    {
+        errors.push(FieldError::new(
+            "event.prompt",
+            format!("prompt must be at most {MAX_PROMPT_BYTES} bytes"),
+        ));
+    }
+
+    // Validate timestamp is valid RFC3339
+    if chrono::DateTime::parse_from_rfc3339(&payload.timestamp).is_err() {
+        errors.push(FieldError::new(
+            "timestamp",
+            "timestamp must be valid RFC 3339",
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::Validation(errors))
+    }
+}
+
+/// Rejects events for any device other than the one a device-bound key was
+/// provisioned for. Unbound keys may write for any device.
+pub fn check_device_binding(bound_device: Option<&str>, device_id: &str) -> Result<(), AppError> {
+    match bound_device {
+        Some(bound) if bound != device_id => Err(AppError::Forbidden),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a heartbeat at `now` changes what clients show for the device:
+/// its first heartbeat, or the first after [`HEARTBEAT_STALE_SECS`] without
+/// one. `previous` is the device's `last_heartbeat`.
+pub fn heartbeat_changes_device(previous: Option<&str>, now: chrono::DateTime<Utc>) -> bool {
+    previous
+        .and_then(|previous| chrono::DateTime::parse_from_rfc3339(previous).ok())
+        .is_none_or(|previous| {
+            now.signed_duration_since(previous) >= Duration::seconds(HEARTBEAT_STALE_SECS)
+        })
+}
+
+/// Decides which partition the event's device belongs to.
+///
+/// A key may only write to devices and sessions in its own partition, given
+/// by the existing owners of each. A new device joins the writing key's
+/// partition; the master key's new devices join the default one.
+pub fn resolve_device_partition(
+    partition: &Partition,
+    existing_device: Option<Partition>,
+    existing_session: Option<&Partition>,
+) -> Result<Partition, AppError> {
+    if existing_device
+        .iter()
+        .chain(existing_session)
+        .any(|owner| !partition.allows(owner.name()))
+    {
+        return Err(AppError::Forbidden);
+    }
+    Ok(existing_device.unwrap_or_else(|| Partition::Key(partition.name().map(String::from))))
+}
+
+/// The session status an event moves its session to, or `None` to leave it.
+pub fn derive_session_status(
+    hook_event_name: &str,
+    notification_type: Option<&str>,
+) -> Option<String> {
+    match hook_event_name {
+        "SessionStart" | "UserPromptSubmit" | "SubagentStart" | "SubagentStop" => {
+            Some("active".to_string())
+        }
+        "Stop" => Some("waiting_for_input".to_string()),
+        "PreCompact" => Some("compacting".to_string()),
+        "SessionEnd" => Some("ended".to_string()),
+        "PermissionRequest" => Some("waiting_for_permission".to_string()),
+        "Notification" => match notification_type {
+            Some("permission_prompt") => Some("waiting_for_permission".to_string()),
+            Some("idle_prompt") => Some("idle".to_string()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::models::request::{DeviceInfo, EventData};
+
+    fn payload(device_id: &str, session_id: &str, timestamp: &str) -> EventPayload {
+        EventPayload {
+            device: DeviceInfo {
+                device_id: device_id.to_string(),
+                device_name: "Device".to_string(),
+                platform: "mac".to_string(),
+            },
+            event: serde_json::from_value::<EventData>(serde_json::json!({
+                "session_id": session_id,
+                "hook_event_name": "Stop",
+            }))
+            .unwrap(),
+            timestamp: timestamp.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_redact_keeps_only_metadata() {
+        let mut original = payload("d", "s", "2024-01-01T00:00:00Z");
+        original.event.prompt = Some("Fix the login bug".to_string());
+        original.event.message = Some("Max turns reached".to_string());
+        original.event.tool_name = Some("Bash".to_string());
+        original.event.cwd = Some("/home/u/app".to_string());
+        original.event.env =
+            Some(std::iter::once(("CI".to_string(), "true".to_string())).collect());
+
+        let redacted = redact(&original);
+        assert_eq!(redacted.event.prompt, None);
+        assert_eq!(redacted.event.message, None);
+        assert_eq!(redacted.event.env, None);
+        assert_eq!(redacted.event.tool_name.as_deref(), Some("Bash"));
+        assert_eq!(redacted.event.cwd.as_deref(), Some("/home/u/app"));
+        assert_eq!(redacted.timestamp, original.timestamp);
+        assert_eq!(redacted.device, original.device);
+    }
+
+    #[test]
+    fn test_heartbeat_changes_device() {
+        let now = chrono::DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(heartbeat_changes_device(None, now));
+        assert!(!heartbeat_changes_device(
+            Some("2024-01-01T11:59:00.000Z"),
+            now
+        ));
+        assert!(heartbeat_changes_device(
+            Some("2024-01-01T11:50:00.000Z"),
+            now
+        ));
+        assert!(heartbeat_changes_device(Some("not a time"), now));
+    }
+
+    #[test]
+    fn test_validate() {
+        let ts = "2024-01-01T00:00:00Z";
+        assert!(validate(&payload("d", "s", ts)).is_ok());
+        assert!(matches!(
+            validate(&payload("", "s", ts)),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            validate(&payload("d", "", ts)),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            validate(&payload("d", "s", "yesterday")),
+            Err(AppError::Validation(_))
+        ));
+
+        // Heartbeats need no session, but still need a device
+        let mut heartbeat = payload("d", "", ts);
+        heartbeat.event.hook_event_name = HookEventKind::Heartbeat;
+        assert!(validate(&heartbeat).is_ok());
+        heartbeat.device.device_id = String::new();
+        assert!(matches!(validate(&heartbeat), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn test_validate_reports_every_invalid_field() {
+        let mut bad = payload("", "s", "yesterday");
+        bad.event.prompt = Some("x".repeat(MAX_PROMPT_BYTES + 1));
+        let Err(AppError::Validation(errors)) = validate(&bad) else {
+            panic!("expected a validation error");
+        };
+        let fields: Vec<_> = errors.iter().map(|error| error.field).collect();
+        assert_eq!(fields, ["device.device_id", "event.prompt", "timestamp"]);
+
+        bad.event.prompt = Some("x".repeat(MAX_PROMPT_BYTES));
+        let Err(AppError::Validation(errors)) = validate(&bad) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_device_binding() {
+        assert!(check_device_binding(None, "dev-1").is_ok());
+        assert!(check_device_binding(Some("dev-1"), "dev-1").is_ok());
+        assert!(matches!(
+            check_device_binding(Some("dev-1"), "dev-2"),
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn test_resolve_new_device_joins_key_partition() {
+        let alice = Partition::Key(Some("alice".to_string()));
+        assert_eq!(resolve_device_partition(&alice, None, None).unwrap(), alice);
+        assert_eq!(
+            resolve_device_partition(&Partition::All, None, None).unwrap(),
+            Partition::Key(None)
+        );
+    }
+
+    #[test]
+    fn test_resolve_existing_device_keeps_partition() {
+        let alice = Partition::Key(Some("alice".to_string()));
+        assert_eq!(
+            resolve_device_partition(&Partition::All, Some(alice.clone()), None).unwrap(),
+            alice
+        );
+    }
+
+    #[test]
+    fn test_resolve_rejects_foreign_device_or_session() {
+        let alice = Partition::Key(Some("alice".to_string()));
+        let bob = Partition::Key(Some("bob".to_string()));
+        assert!(matches!(
+            resolve_device_partition(&bob, Some(alice.clone()), None),
+            Err(AppError::Forbidden)
+        ));
+        assert!(matches!(
+            resolve_device_partition(&bob, None, Some(&alice)),
+            Err(AppError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn test_nest_subagent_moves_only_subagent_events() {
+        let mut start = payload("dev-1", "parent", "2024-01-01T00:00:00Z");
+        start.event.hook_event_name = HookEventKind::SubagentStart;
+        start.event.agent_id = Some("agent-1".to_string());
+
+        let (nested, parent) = nest_subagent(&start).unwrap();
+        assert_eq!(nested.event.session_id, "agent-1");
+        assert_eq!(parent, "parent");
+
+        // Older Claude Code versions send no agent_id
+        start.event.agent_id = None;
+        assert!(nest_subagent(&start).is_none());
+
+        let mut stop = payload("dev-1", "parent", "2024-01-01T00:00:00Z");
+        stop.event.agent_id = Some("agent-1".to_string());
+        assert!(nest_subagent(&stop).is_none());
+    }
+
+    #[test]
+    fn test_derive_session_status() {
+        assert_eq!(
+            derive_session_status("SessionStart", None).as_deref(),
+            Some("active")
+        );
+        assert_eq!(
+            derive_session_status("Stop", None).as_deref(),
+            Some("waiting_for_input")
+        );
+        assert_eq!(
+            derive_session_status("Notification", Some("idle_prompt")).as_deref(),
+            Some("idle")
+        );
+        assert_eq!(
+            derive_session_status("PreCompact", None).as_deref(),
+            Some("compacting")
+        );
+        assert_eq!(derive_session_status("Notification", None), None);
+        assert_eq!(derive_session_status("PreToolUse", None), None);
+    }
+}
@@ -0,0 +1,898 @@
+//! Which events produce a notification, and what it says.
+//!
+//! Built-in text is written once, in [`STRINGS`], under the localization key
+//! the app looks it up by. Each notification keeps both the English render,
+//! which is stored and pushed by default, and the key with its arguments,
+//! pushed instead when APNs localized alerts are enabled. The keys are
+//! stored with the notification, so pushing it again is localized too.
+//!
+//! Messages and session titles the hook sealed end to end never appear in
+//! the text: a message shows as [`SEALED_PLACEHOLDER`], a title falls back
+//! to the generic one, and the ciphertext travels beside them in
+//! [`NotificationContent::sealed`] for the app to open.
+
+use claudiator_types::is_e2e_sealed;
+
+use crate::apns::{LocalizedAlert, LocalizedText, SealedText};
+use crate::models::request::{PRIORITY_HIGH, SEVERITIES};
+
+/// English for every localization key, with `%@` marking each argument in
+/// order (the iOS format). The app's `Localizable.strings` must define the
+/// same keys.
+pub const STRINGS: &[(&str, &str)] = &[
+    ("NOTIF_STOP_TITLE", "Session Stopped"),
+    ("NOTIF_STOP_BODY", "Session stopped: %@"),
+    (
+        "NOTIF_STOP_BODY_NO_REASON",
+        "Session stopped: No reason given",
+    ),
+    ("NOTIF_PERMISSION_TITLE", "Permission Required"),
+    (
+        "NOTIF_PERMISSION_BODY_TOOL_MESSAGE",
+        "Permission required: %@ — %@",
+    ),
+    ("NOTIF_PERMISSION_BODY_TOOL", "Permission required: %@"),
+    ("NOTIF_PERMISSION_BODY_MESSAGE", "Permission required: %@"),
+    (
+        "NOTIF_PERMISSION_BODY",
+        "A session needs permission to continue",
+    ),
+    ("NOTIF_IDLE_TITLE", "Session Idle"),
+    ("NOTIF_IDLE_BODY", "Session idle: %@"),
+    (
+        "NOTIF_IDLE_BODY_NO_MESSAGE",
+        "Session idle: Waiting for input",
+    ),
+    ("NOTIF_TEST_TITLE", "Claudiator Test"),
+    ("NOTIF_TEST_BODY", "Test notification from claudiator-hook"),
+    ("NOTIF_POLICY_TITLE", "Policy Alert"),
+    ("NOTIF_POLICY_BODY_TOOL_MESSAGE", "Flagged %@ call: %@"),
+    ("NOTIF_POLICY_BODY_TOOL", "Flagged %@ call"),
+    ("NOTIF_POLICY_BODY_MESSAGE", "Flagged event: %@"),
+    ("NOTIF_POLICY_BODY", "A session event was flagged by policy"),
+    ("NOTIF_TASK_COMPLETED_TITLE", "Task Completed"),
+    ("NOTIF_TASK_COMPLETED_BODY", "Task completed: %@"),
+    (
+        "NOTIF_TASK_COMPLETED_BODY_NO_SUBJECT",
+        "A team task was completed",
+    ),
+    ("NOTIF_HOOK_CRASH_TITLE", "Hook Crashed"),
+    (
+        "NOTIF_HOOK_CRASH_BODY",
+        "claudiator-hook crashed on an earlier run",
+    ),
+    ("NOTIF_OUTDATED_HOOK_TITLE", "Outdated Hook"),
+    (
+        "NOTIF_OUTDATED_HOOK_BODY",
+        "Device %@ is running an outdated hook (%@, need %@ or later)",
+    ),
+    ("NOTIF_EXTERNAL_BODY", "From %@"),
+    ("NOTIF_COMPACTING_TITLE", "Compacting"),
+    ("NOTIF_COMPACTING_BODY", "Compacting the conversation"),
+    (
+        "NOTIF_COMPACTING_BODY_AUTO",
+        "Context window full, compacting the conversation",
+    ),
+    (
+        "NOTIF_COMPACTING_BODY_INSTRUCTIONS",
+        "Compacting the conversation: %@",
+    ),
+    ("NOTIF_TOOL_FAILED_TITLE", "Tool Failed"),
+    ("NOTIF_TOOL_FAILED_BODY_TOOL_ERROR", "%@ failed: %@"),
+    ("NOTIF_TOOL_FAILED_BODY_TOOL", "%@ failed"),
+    ("NOTIF_TOOL_FAILED_BODY_ERROR", "Error: %@"),
+    ("NOTIF_TOOL_FAILED_BODY", "A tool call failed"),
+    ("NOTIF_INTERRUPTED_TITLE", "Interrupted"),
+    (
+        "NOTIF_INTERRUPTED_BODY_TOOL",
+        "Interrupted while running %@",
+    ),
+    ("NOTIF_INTERRUPTED_BODY", "Session interrupted"),
+];
+
+/// Stands in for a sealed message in a notification's body. The app replaces
+/// it with the opened message.
+pub const SEALED_PLACEHOLDER: &str = "[encrypted]";
+
+/// Title, body, and type of the notification an event produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationContent {
+    pub title: String,
+    pub body: String,
+    /// `stop`, `permission_prompt`, `idle_prompt`, `test`, `policy`,
+    /// `task_completed`, `hook_crash`, `outdated_hook`, `external`,
+    /// `compacting`, or `error`.
+    pub notification_type: String,
+    /// Keys `title` and `body` were rendered from. Text taken verbatim from
+    /// the session, such as its title, has none.
+    pub localized: LocalizedAlert,
+    /// Sealed values kept out of `title` and `body`, if the event had any.
+    pub sealed: Option<SealedText>,
+}
+
+/// An event's message and session title as notifications may show them,
+/// with sealed values set aside.
+struct Visible<'a> {
+    message: Option<&'a str>,
+    session_title: Option<&'a str>,
+    sealed: Option<SealedText>,
+}
+
+impl<'a> Visible<'a> {
+    fn new(message: Option<&'a str>, session_title: Option<&'a str>) -> Self {
+        let sealed_message = message.filter(|m| is_e2e_sealed(m));
+        let sealed_title = session_title.filter(|t| is_e2e_sealed(t));
+        let sealed = (sealed_message.is_some() || sealed_title.is_some()).then(|| SealedText {
+            title: sealed_title.map(String::from),
+            message: sealed_message.map(String::from),
+        });
+        Self {
+            message: sealed_message.map_or(message, |_| Some(SEALED_PLACEHOLDER)),
+            session_title: session_title.filter(|t| !is_e2e_sealed(t)),
+            sealed,
+        }
+    }
+
+    fn attach(self, content: NotificationContent) -> NotificationContent {
+        NotificationContent {
+            sealed: self.sealed,
+            ..content
+        }
+    }
+}
+
+/// One line of a notification: built-in text under a key, or text from the
+/// session shown as is.
+enum Text {
+    Localized(LocalizedText),
+    Verbatim(String),
+}
+
+impl Text {
+    fn localized(key: &'static str, args: &[&str]) -> Self {
+        Self::Localized(LocalizedText {
+            key,
+            args: args.iter().map(ToString::to_string).collect(),
+        })
+    }
+
+    /// The session title if there is a non-empty one, `key` otherwise.
+    fn session_title(session_title: Option<&str>, key: &'static str) -> Self {
+        session_title.filter(|t| !t.is_empty()).map_or_else(
+            || Self::localized(key, &[]),
+            |t| Self::Verbatim(t.to_string()),
+        )
+    }
+
+    fn split(self) -> (String, Option<LocalizedText>) {
+        match self {
+            Self::Localized(text) => (render(&text), Some(text)),
+            Self::Verbatim(text) => (text, None),
+        }
+    }
+}
+
+fn content(title: Text, body: Text, notification_type: &str) -> NotificationContent {
+    let (title, title_loc) = title.split();
+    let (body, body_loc) = body.split();
+    NotificationContent {
+        title,
+        body,
+        notification_type: notification_type.to_string(),
+        localized: LocalizedAlert {
+            title: title_loc,
+            body: body_loc,
+        },
+        sealed: None,
+    }
+}
+
+/// The English for `text`: its [`STRINGS`] entry with the arguments filled
+/// in.
+pub fn render(text: &LocalizedText) -> String {
+    let template = STRINGS
+        .iter()
+        .find(|(key, _)| *key == text.key)
+        .map_or(text.key, |(_, english)| english);
+    let mut parts = template.split("%@");
+    let mut rendered = parts.next().unwrap_or_default().to_string();
+    for (part, arg) in parts.zip(
+        text.args
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::repeat("")),
+    ) {
+        rendered.push_str(arg);
+        rendered.push_str(part);
+    }
+    rendered
+}
+
+/// The keys a stored notification was rendered from, as kept under `loc`
+/// in its `payload_json`. Notifications stored without them get a key for
+/// each line that is a built-in string without arguments.
+pub fn stored_localized(
+    loc: Option<&serde_json::Value>,
+    title: &str,
+    body: &str,
+) -> LocalizedAlert {
+    loc.map_or_else(
+        || LocalizedAlert {
+            title: plain_key(title),
+            body: plain_key(body),
+        },
+        |loc| LocalizedAlert {
+            title: stored_text(loc.get("title")),
+            body: stored_text(loc.get("body")),
+        },
+    )
+}
+
+fn stored_text(value: Option<&serde_json::Value>) -> Option<LocalizedText> {
+    let value = value?;
+    let key = value.get("key")?.as_str()?;
+    let key = STRINGS.iter().map(|(k, _)| *k).find(|k| *k == key)?;
+    let args = value
+        .get("args")?
+        .as_array()?
+        .iter()
+        .map(|arg| arg.as_str().map(String::from))
+        .collect::<Option<Vec<_>>>()?;
+    Some(LocalizedText { key, args })
+}
+
+fn plain_key(text: &str) -> Option<LocalizedText> {
+    STRINGS
+        .iter()
+        .find(|(_, english)| *english == text && !english.contains("%@"))
+        .map(|(key, _)| LocalizedText {
+            key,
+            args: Vec::new(),
+        })
+}
+
+/// Builds the notification for an event, or `None` if the event doesn't
+/// notify. The session title, when known, replaces the generic title.
+pub fn content_for_event(
+    hook_event_name: &str,
+    notification_type: Option<&str>,
+    message: Option<&str>,
+    session_title: Option<&str>,
+    tool_name: Option<&str>,
+) -> Option<NotificationContent> {
+    let visible = Visible::new(message, session_title);
+    event_content(
+        hook_event_name,
+        notification_type,
+        visible.message,
+        visible.session_title,
+        tool_name,
+    )
+    .map(|content| visible.attach(content))
+}
+
+fn event_content(
+    hook_event_name: &str,
+    notification_type: Option<&str>,
+    message: Option<&str>,
+    session_title: Option<&str>,
+    tool_name: Option<&str>,
+) -> Option<NotificationContent> {
+    match hook_event_name {
+        "Stop" => Some(content(
+            Text::session_title(session_title, "NOTIF_STOP_TITLE"),
+            message.map_or_else(
+                || Text::localized("NOTIF_STOP_BODY_NO_REASON", &[]),
+                |msg| Text::localized("NOTIF_STOP_BODY", &[msg]),
+            ),
+            "stop",
+        )),
+        "Notification" => match notification_type {
+            Some("permission_prompt") => {
+                Some(permission_content(session_title, tool_name, message))
+            }
+            Some("idle_prompt") => Some(content(
+                Text::session_title(session_title, "NOTIF_IDLE_TITLE"),
+                message.map_or_else(
+                    || Text::localized("NOTIF_IDLE_BODY_NO_MESSAGE", &[]),
+                    |msg| Text::localized("NOTIF_IDLE_BODY", &[msg]),
+                ),
+                "idle_prompt",
+            )),
+            _ => None,
+        },
+        "TestNotification" => Some(content(
+            Text::localized("NOTIF_TEST_TITLE", &[]),
+            message.map_or_else(
+                || Text::localized("NOTIF_TEST_BODY", &[]),
+                |msg| Text::Verbatim(msg.to_string()),
+            ),
+            "test",
+        )),
+        "PermissionRequest" => Some(permission_content(session_title, tool_name, message)),
+        "HookCrash" => Some(content(
+            Text::localized("NOTIF_HOOK_CRASH_TITLE", &[]),
+            message.map_or_else(
+                || Text::localized("NOTIF_HOOK_CRASH_BODY", &[]),
+                |msg| Text::Verbatim(msg.to_string()),
+            ),
+            "hook_crash",
+        )),
+        _ => None,
+    }
+}
+
+/// The notification for an event a hook policy rule flagged as high
+/// priority, used when its type doesn't notify on its own.
+pub fn policy_content(
+    priority: Option<&str>,
+    message: Option<&str>,
+    session_title: Option<&str>,
+    tool_name: Option<&str>,
+) -> Option<NotificationContent> {
+    if priority != Some(PRIORITY_HIGH) {
+        return None;
+    }
+    let visible = Visible::new(message, session_title);
+    let (message, session_title) = (visible.message, visible.session_title);
+    let body = match (tool_name, message) {
+        (Some(tool), Some(msg)) => Text::localized("NOTIF_POLICY_BODY_TOOL_MESSAGE", &[tool, msg]),
+        (Some(tool), None) => Text::localized("NOTIF_POLICY_BODY_TOOL", &[tool]),
+        (None, Some(msg)) => Text::localized("NOTIF_POLICY_BODY_MESSAGE", &[msg]),
+        (None, None) => Text::localized("NOTIF_POLICY_BODY", &[]),
+    };
+    let content = content(
+        Text::session_title(session_title, "NOTIF_POLICY_TITLE"),
+        body,
+        "policy",
+    );
+    Some(visible.attach(content))
+}
+
+/// The notification for a `TaskCompleted` event from an agent team, naming
+/// the task by its subject. A sealed subject is left out, like a sealed
+/// session title.
+pub fn task_completed_content(
+    hook_event_name: &str,
+    task_subject: Option<&str>,
+    session_title: Option<&str>,
+) -> Option<NotificationContent> {
+    if hook_event_name != "TaskCompleted" {
+        return None;
+    }
+    let visible = Visible::new(None, session_title);
+    let body = task_subject
+        .filter(|subject| !subject.is_empty() && !is_e2e_sealed(subject))
+        .map_or_else(
+            || Text::localized("NOTIF_TASK_COMPLETED_BODY_NO_SUBJECT", &[]),
+            |subject| Text::localized("NOTIF_TASK_COMPLETED_BODY", &[subject]),
+        );
+    let content = content(
+        Text::session_title(visible.session_title, "NOTIF_TASK_COMPLETED_TITLE"),
+        body,
+        "task_completed",
+    );
+    Some(visible.attach(content))
+}
+
+/// The notification for an `External` event from `source`: its title as is,
+/// and its message, or the source when there is none.
+pub fn external_content(
+    hook_event_name: &str,
+    title: Option<&str>,
+    message: Option<&str>,
+    source: &str,
+) -> Option<NotificationContent> {
+    if hook_event_name != "External" {
+        return None;
+    }
+    let visible = Visible::new(message, None);
+    let body = visible.message.filter(|m| !m.is_empty()).map_or_else(
+        || Text::localized("NOTIF_EXTERNAL_BODY", &[source]),
+        |msg| Text::Verbatim(msg.to_string()),
+    );
+    let content = content(
+        Text::Verbatim(title.unwrap_or_default().to_string()),
+        body,
+        "external",
+    );
+    Some(visible.attach(content))
+}
+
+/// The notification for a `PreCompact` event, so the pause while Claude
+/// Code compacts the conversation doesn't look like a stall. Says whether
+/// the compaction was automatic, and quotes the user's instructions for a
+/// manual one.
+pub fn compacting_content(
+    hook_event_name: &str,
+    trigger: Option<&str>,
+    custom_instructions: Option<&str>,
+    session_title: Option<&str>,
+) -> Option<NotificationContent> {
+    if hook_event_name != "PreCompact" {
+        return None;
+    }
+    let visible = Visible::new(custom_instructions, session_title);
+    let body = match (visible.message.filter(|i| !i.is_empty()), trigger) {
+        (Some(instructions), _) => {
+            Text::localized("NOTIF_COMPACTING_BODY_INSTRUCTIONS", &[instructions])
+        }
+        (None, Some("auto")) => Text::localized("NOTIF_COMPACTING_BODY_AUTO", &[]),
+        (None, _) => Text::localized("NOTIF_COMPACTING_BODY", &[]),
+    };
+    let content = content(
+        Text::session_title(visible.session_title, "NOTIF_COMPACTING_TITLE"),
+        body,
+        "compacting",
+    );
+    Some(visible.attach(content))
+}
+
+/// How serious an event is, least serious first. Named by [`SEVERITIES`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => SEVERITIES[0],
+            Self::Warning => SEVERITIES[1],
+            Self::Error => SEVERITIES[2],
+        }
+    }
+
+    /// Parses one of [`SEVERITIES`], ignoring case.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message listing the accepted names.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        [Self::Info, Self::Warning, Self::Error]
+            .into_iter()
+            .find(|severity| severity.as_str().eq_ignore_ascii_case(value.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "unknown severity `{value}`, expected one of {}",
+                    SEVERITIES.join(", ")
+                )
+            })
+    }
+}
+
+/// The severity of an event reporting an error: its own `severity` if it
+/// has a valid one, `error` for a failure, `warning` for an interruption.
+/// `None` when the event reports neither.
+pub fn error_severity(
+    severity: Option<&str>,
+    error: Option<&str>,
+    is_interrupt: Option<bool>,
+) -> Option<Severity> {
+    let interrupted = is_interrupt == Some(true);
+    if error.is_none() && !interrupted {
+        return None;
+    }
+    let derived = if interrupted {
+        Severity::Warning
+    } else {
+        Severity::Error
+    };
+    Some(
+        severity
+            .and_then(|s| Severity::parse(s).ok())
+            .unwrap_or(derived),
+    )
+}
+
+/// The notification for an event carrying `error` or `is_interrupt`, such
+/// as a failed or interrupted tool call, if its severity reaches
+/// `min_severity`. A sealed error shows as [`SEALED_PLACEHOLDER`].
+pub fn error_content(
+    severity: Option<&str>,
+    error: Option<&str>,
+    is_interrupt: Option<bool>,
+    tool_name: Option<&str>,
+    session_title: Option<&str>,
+    min_severity: Severity,
+) -> Option<NotificationContent> {
+    if error_severity(severity, error, is_interrupt)? < min_severity {
+        return None;
+    }
+    let visible = Visible::new(error, session_title);
+    let error = visible.message.filter(|e| !e.is_empty());
+    let (title_key, body) = if is_interrupt == Some(true) {
+        let body = tool_name.map_or_else(
+            || Text::localized("NOTIF_INTERRUPTED_BODY", &[]),
+            |tool| Text::localized("NOTIF_INTERRUPTED_BODY_TOOL", &[tool]),
+        );
+        ("NOTIF_INTERRUPTED_TITLE", body)
+    } else {
+        let body = match (tool_name, error) {
+            (Some(tool), Some(err)) => {
+                Text::localized("NOTIF_TOOL_FAILED_BODY_TOOL_ERROR", &[tool, err])
+            }
+            (Some(tool), None) => Text::localized("NOTIF_TOOL_FAILED_BODY_TOOL", &[tool]),
+            (None, Some(err)) => Text::localized("NOTIF_TOOL_FAILED_BODY_ERROR", &[err]),
+            (None, None) => Text::localized("NOTIF_TOOL_FAILED_BODY", &[]),
+        };
+        ("NOTIF_TOOL_FAILED_TITLE", body)
+    };
+    let content = content(
+        Text::session_title(visible.session_title, title_key),
+        body,
+        "error",
+    );
+    Some(visible.attach(content))
+}
+
+/// The notice that `device_name`'s hook, at `hook_version`, is older than
+/// `min_hook_version`.
+pub fn outdated_hook_content(
+    device_name: &str,
+    hook_version: &str,
+    min_hook_version: &str,
+) -> NotificationContent {
+    content(
+        Text::localized("NOTIF_OUTDATED_HOOK_TITLE", &[]),
+        Text::localized(
+            "NOTIF_OUTDATED_HOOK_BODY",
+            &[device_name, hook_version, min_hook_version],
+        ),
+        "outdated_hook",
+    )
+}
+
+fn permission_content(
+    session_title: Option<&str>,
+    tool_name: Option<&str>,
+    message: Option<&str>,
+) -> NotificationContent {
+    let body = match (tool_name, message) {
+        (Some(tool), Some(msg)) => {
+            Text::localized("NOTIF_PERMISSION_BODY_TOOL_MESSAGE", &[tool, msg])
+        }
+        (Some(tool), None) => Text::localized("NOTIF_PERMISSION_BODY_TOOL", &[tool]),
+        (None, Some(msg)) => Text::localized("NOTIF_PERMISSION_BODY_MESSAGE", &[msg]),
+        (None, None) => Text::localized("NOTIF_PERMISSION_BODY", &[]),
+    };
+    content(
+        Text::session_title(session_title, "NOTIF_PERMISSION_TITLE"),
+        body,
+        "permission_prompt",
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_uses_session_title() {
+        let content = content_for_event("Stop", None, None, Some("Fix the build"), None).unwrap();
+        assert_eq!(content.title, "Fix the build");
+        assert_eq!(content.body, "Session stopped: No reason given");
+        assert_eq!(content.notification_type, "stop");
+    }
+
+    #[test]
+    fn test_empty_session_title_falls_back() {
+        let content = content_for_event("Stop", None, None, Some(""), None).unwrap();
+        assert_eq!(content.title, "Session Stopped");
+    }
+
+    #[test]
+    fn test_permission_request_and_prompt_match() {
+        let request = content_for_event(
+            "PermissionRequest",
+            None,
+            Some("rm -rf"),
+            None,
+            Some("Bash"),
+        );
+        let prompt = content_for_event(
+            "Notification",
+            Some("permission_prompt"),
+            Some("rm -rf"),
+            None,
+            Some("Bash"),
+        );
+        assert_eq!(request, prompt);
+        assert_eq!(request.unwrap().body, "Permission required: Bash — rm -rf");
+    }
+
+    #[test]
+    fn test_test_notification_ignores_session_title() {
+        let content =
+            content_for_event("TestNotification", None, Some("hi"), Some("Title"), None).unwrap();
+        assert_eq!(content.title, "Claudiator Test");
+        assert_eq!(content.body, "hi");
+        assert_eq!(content.notification_type, "test");
+    }
+
+    #[test]
+    fn test_hook_crash_shows_the_panic() {
+        let content = content_for_event(
+            "HookCrash",
+            None,
+            Some("claudiator-hook 0.3.0 panicked: oops"),
+            Some("Title"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(content.title, "Hook Crashed");
+        assert_eq!(content.body, "claudiator-hook 0.3.0 panicked: oops");
+        assert_eq!(content.notification_type, "hook_crash");
+
+        let content = content_for_event("HookCrash", None, None, None, None).unwrap();
+        assert_eq!(content.body, "claudiator-hook crashed on an earlier run");
+    }
+
+    #[test]
+    fn test_policy_content_only_for_high_priority() {
+        let content = policy_content(Some("high"), Some("rm -rf /"), None, Some("Bash")).unwrap();
+        assert_eq!(content.title, "Policy Alert");
+        assert_eq!(content.body, "Flagged Bash call: rm -rf /");
+        assert_eq!(content.notification_type, "policy");
+        assert_eq!(
+            policy_content(Some("high"), None, Some("Deploy"), None)
+                .unwrap()
+                .title,
+            "Deploy"
+        );
+        assert!(policy_content(None, Some("rm -rf /"), None, Some("Bash")).is_none());
+        assert!(policy_content(Some("low"), None, None, None).is_none());
+    }
+
+    #[test]
+    fn test_outdated_hook_content_names_device_and_versions() {
+        let content = outdated_hook_content("build-box", "0.2.0", "0.3.0");
+        assert_eq!(content.title, "Outdated Hook");
+        assert_eq!(
+            content.body,
+            "Device build-box is running an outdated hook (0.2.0, need 0.3.0 or later)"
+        );
+        assert_eq!(content.notification_type, "outdated_hook");
+    }
+
+    #[test]
+    fn test_external_content_shows_title_and_body_as_sent() {
+        let content = external_content(
+            "External",
+            Some("Deploy failed"),
+            Some("prod-eu rolled back"),
+            "ci",
+        )
+        .unwrap();
+        assert_eq!(content.title, "Deploy failed");
+        assert_eq!(content.body, "prod-eu rolled back");
+        assert_eq!(content.notification_type, "external");
+        assert!(content.localized.title.is_none());
+
+        let no_body = external_content("External", Some("Backup done"), None, "cron").unwrap();
+        assert_eq!(no_body.body, "From cron");
+
+        assert!(external_content("Stop", Some("x"), None, "ci").is_none());
+    }
+
+    #[test]
+    fn test_compacting_content_by_trigger() {
+        let auto = compacting_content("PreCompact", Some("auto"), None, None).unwrap();
+        assert_eq!(auto.title, "Compacting");
+        assert_eq!(
+            auto.body,
+            "Context window full, compacting the conversation"
+        );
+        assert_eq!(auto.notification_type, "compacting");
+
+        let manual = compacting_content(
+            "PreCompact",
+            Some("manual"),
+            Some("keep the test plan"),
+            Some("Refactor auth"),
+        )
+        .unwrap();
+        assert_eq!(manual.title, "Refactor auth");
+        assert_eq!(
+            manual.body,
+            "Compacting the conversation: keep the test plan"
+        );
+
+        let sealed =
+            compacting_content("PreCompact", Some("manual"), Some("e2e:v1:AAAA"), None).unwrap();
+        assert_eq!(sealed.body, "Compacting the conversation: [encrypted]");
+        assert!(sealed.sealed.is_some());
+
+        assert!(compacting_content("Stop", Some("auto"), None, None).is_none());
+    }
+
+    #[test]
+    fn test_error_content_for_failures_and_interrupts() {
+        let failed = error_content(
+            None,
+            Some("exit code 1"),
+            None,
+            Some("Bash"),
+            None,
+            Severity::Warning,
+        )
+        .unwrap();
+        assert_eq!(failed.title, "Tool Failed");
+        assert_eq!(failed.body, "Bash failed: exit code 1");
+        assert_eq!(failed.notification_type, "error");
+
+        let interrupted = error_content(
+            None,
+            Some("Interrupted by user"),
+            Some(true),
+            Some("Bash"),
+            Some("Refactor auth"),
+            Severity::Warning,
+        )
+        .unwrap();
+        assert_eq!(interrupted.title, "Refactor auth");
+        assert_eq!(interrupted.body, "Interrupted while running Bash");
+
+        let sealed =
+            error_content(None, Some("e2e:v1:AAAA"), None, None, None, Severity::Info).unwrap();
+        assert_eq!(sealed.body, "Error: [encrypted]");
+
+        assert!(error_content(None, None, Some(false), None, None, Severity::Info).is_none());
+    }
+
+    #[test]
+    fn test_error_content_respects_min_severity() {
+        let interrupt = |min| error_content(None, None, Some(true), None, None, min);
+        assert!(interrupt(Severity::Warning).is_some());
+        assert!(interrupt(Severity::Error).is_none());
+
+        // An event's own severity replaces the derived one
+        assert!(
+            error_content(Some("info"), Some("x"), None, None, None, Severity::Warning).is_none()
+        );
+        assert_eq!(
+            error_severity(Some("bogus"), Some("x"), None),
+            Some(Severity::Error)
+        );
+        assert_eq!(Severity::parse(" WARNING"), Ok(Severity::Warning));
+        assert!(Severity::parse("fatal").is_err());
+    }
+
+    #[test]
+    fn test_task_completed_names_the_task() {
+        let content =
+            task_completed_content("TaskCompleted", Some("Migrate the database"), None).unwrap();
+        assert_eq!(content.title, "Task Completed");
+        assert_eq!(content.body, "Task completed: Migrate the database");
+        assert_eq!(content.notification_type, "task_completed");
+
+        let sealed =
+            task_completed_content("TaskCompleted", Some("e2e:v1:AAAA"), Some("Refactor auth"))
+                .unwrap();
+        assert_eq!(sealed.title, "Refactor auth");
+        assert_eq!(sealed.body, "A team task was completed");
+
+        assert!(task_completed_content("Stop", Some("x"), None).is_none());
+    }
+
+    #[test]
+    fn test_content_keeps_localization_keys() {
+        let content = content_for_event(
+            "Notification",
+            Some("permission_prompt"),
+            Some("rm -rf"),
+            None,
+            Some("Bash"),
+        )
+        .unwrap();
+        assert_eq!(
+            content.localized.title,
+            Some(LocalizedText {
+                key: "NOTIF_PERMISSION_TITLE",
+                args: vec![],
+            })
+        );
+        assert_eq!(
+            content.localized.body,
+            Some(LocalizedText {
+                key: "NOTIF_PERMISSION_BODY_TOOL_MESSAGE",
+                args: vec!["Bash".to_string(), "rm -rf".to_string()],
+            })
+        );
+
+        // Session titles and custom test messages are shown as written
+        let content = content_for_event("Stop", None, None, Some("Fix the build"), None).unwrap();
+        assert_eq!(content.localized.title, None);
+        let content = content_for_event("TestNotification", None, Some("hi"), None, None).unwrap();
+        assert_eq!(content.localized.body, None);
+    }
+
+    #[test]
+    fn test_render_fills_arguments_in_order() {
+        let text = |key, args: &[&str]| LocalizedText {
+            key,
+            args: args.iter().map(ToString::to_string).collect(),
+        };
+        assert_eq!(
+            render(&text(
+                "NOTIF_POLICY_BODY_TOOL_MESSAGE",
+                &["Bash", "50%@ off"]
+            )),
+            "Flagged Bash call: 50%@ off"
+        );
+        assert_eq!(render(&text("NOTIF_STOP_BODY", &[])), "Session stopped: ");
+        assert_eq!(render(&text("UNKNOWN_KEY", &[])), "UNKNOWN_KEY");
+        for (key, english) in STRINGS {
+            assert!(!english.is_empty(), "{key} has no English");
+        }
+    }
+
+    #[test]
+    fn test_sealed_text_stays_out_of_title_and_body() {
+        let content = content_for_event(
+            "Notification",
+            Some("permission_prompt"),
+            Some("e2e:v1:bWVzc2FnZQ=="),
+            Some("e2e:v1:dGl0bGU="),
+            Some("Bash"),
+        )
+        .unwrap();
+        assert_eq!(content.title, "Permission Required");
+        assert_eq!(content.body, "Permission required: Bash — [encrypted]");
+        assert_eq!(
+            content.sealed,
+            Some(SealedText {
+                title: Some("e2e:v1:dGl0bGU=".to_string()),
+                message: Some("e2e:v1:bWVzc2FnZQ==".to_string()),
+            })
+        );
+        assert_eq!(
+            content.localized.body.unwrap().args,
+            vec!["Bash".to_string(), SEALED_PLACEHOLDER.to_string()]
+        );
+
+        let policy =
+            policy_content(Some("high"), Some("e2e:v1:eA=="), Some("Deploy"), None).unwrap();
+        assert_eq!(policy.title, "Deploy");
+        assert_eq!(policy.body, "Flagged event: [encrypted]");
+        assert_eq!(policy.sealed.unwrap().title, None);
+
+        let plain = content_for_event("Stop", None, Some("done"), Some("Deploy"), None).unwrap();
+        assert!(plain.sealed.is_none());
+    }
+
+    #[test]
+    fn test_silent_events() {
+        assert!(content_for_event("SessionStart", None, None, None, None).is_none());
+        assert!(
+            content_for_event("Notification", Some("auth_success"), None, None, None).is_none()
+        );
+    }
+
+    #[test]
+    fn test_stored_localized_reads_loc() {
+        let content = content_for_event("Stop", None, Some("done"), Some("Fix it"), None).unwrap();
+        let loc = serde_json::json!(content.localized);
+        assert_eq!(
+            stored_localized(Some(&loc), &content.title, &content.body),
+            content.localized
+        );
+
+        let unknown = serde_json::json!({"body": {"key": "NOT_A_KEY", "args": []}});
+        assert_eq!(
+            stored_localized(Some(&unknown), "Fix it", "x"),
+            LocalizedAlert::default()
+        );
+    }
+
+    #[test]
+    fn test_stored_localized_without_loc_matches_plain_strings() {
+        let localized = stored_localized(None, "Session Stopped", "Session stopped: done");
+        assert_eq!(localized.title.unwrap().key, "NOTIF_STOP_TITLE");
+        assert!(localized.body.is_none());
+    }
+}
@@ -0,0 +1,148 @@
+//! APNs fan-out for a stored notification.
+//!
+//! Pushes are sent from a spawned task so ingestion never waits on Apple.
+//! Tokens APNs reports as gone are deleted; a rate-limit response stops the
+//! fan-out for this notification. A push no token accepted is kept as a
+//! delivery failure so an operator can retry it.
+
+use chrono::{SecondsFormat, Utc};
+use std::sync::Arc;
+
+use crate::apns::{Alert, ApnsClient, ApnsPushResult};
+use crate::db::partition::Partition;
+use crate::health::ServerHealth;
+use crate::models::response::DeliveryFailure;
+use crate::notif_routing::Channel;
+use crate::storage::SharedStorage;
+
+/// Everything a push needs about the notification being delivered.
+#[derive(Debug, Clone)]
+pub struct PushNotification {
+    /// Resends, retries, and woken snoozes rebuild it with
+    /// [`Alert::from_stored`].
+    pub alert: Alert,
+    pub collapse_id: String,
+    pub notification_id: String,
+    pub session_id: String,
+    pub device_id: String,
+}
+
+/// Sends `push` to every token registered in `partition` from a spawned
+/// task. If no token accepts it, the push is recorded in
+/// `delivery_failures` for `POST /admin/delivery-failures/:id/retry`. The
+/// outcome counts toward `health`'s push failure rate.
+pub fn dispatch(
+    apns_client: Arc<ApnsClient>,
+    storage: SharedStorage,
+    health: Arc<ServerHealth>,
+    push: PushNotification,
+    partition: Partition,
+) {
+    tokio::spawn(async move {
+        let result = deliver(&apns_client, &storage, &push, &partition).await;
+        health.record_push(Utc::now().timestamp(), result.is_err());
+        if let Err(error) = result {
+            tracing::warn!(
+                notification_id = %push.notification_id,
+                error = %error,
+                "Push delivery failed on every token; recorded for retry"
+            );
+            record_failure(&storage, &push, &partition, &error);
+        }
+    });
+}
+
+/// Sends `push` to every token registered in `partition`.
+///
+/// Succeeds if any token accepted the push, or if there was no token to
+/// send to. Otherwise returns the last error seen.
+pub async fn deliver(
+    apns_client: &ApnsClient,
+    storage: &SharedStorage,
+    push: &PushNotification,
+    partition: &Partition,
+) -> Result<(), String> {
+    let tokens = storage
+        .list_push_tokens(partition)
+        .map_err(|e| format!("Failed to list push tokens: {e:?}"))?;
+
+    let mut delivered = false;
+    let mut last_error = None;
+    for token_row in &tokens {
+        let result = apns_client
+            .send_push(
+                &token_row.push_token,
+                &push.alert,
+                Some(&push.collapse_id),
+                &push.notification_id,
+                &push.session_id,
+                &push.device_id,
+                token_row.sandbox,
+            )
+            .await;
+
+        match result {
+            ApnsPushResult::Success => {
+                tracing::debug!(
+                    "Push sent to token {}",
+                    &token_row.push_token[..8.min(token_row.push_token.len())]
+                );
+                delivered = true;
+                let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+                let _ = storage.record_push_success(&token_row.push_token, &now);
+            }
+            ApnsPushResult::Gone => {
+                tracing::info!(
+                    "Push token gone, removing: {}",
+                    &token_row.push_token[..8.min(token_row.push_token.len())]
+                );
+                let _ = storage.delete_push_token(&token_row.push_token);
+            }
+            ApnsPushResult::AuthError => {
+                tracing::error!("APNs auth error — check credentials");
+                last_error = Some("APNs auth error".to_string());
+            }
+            ApnsPushResult::Retry => {
+                tracing::warn!("APNs rate limited, skipping remaining tokens");
+                last_error = Some("APNs rate limited".to_string());
+                break;
+            }
+            ApnsPushResult::OtherError(e) => {
+                tracing::warn!("APNs push error: {}", e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    match last_error {
+        Some(error) if !delivered => Err(error),
+        _ => Ok(()),
+    }
+}
+
+fn record_failure(
+    storage: &SharedStorage,
+    push: &PushNotification,
+    partition: &Partition,
+    error: &str,
+) {
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let failure = DeliveryFailure {
+        id: uuid::Uuid::new_v4().to_string(),
+        notification_id: push.notification_id.clone(),
+        channel: Channel::Apns.as_str().to_string(),
+        partition: partition.name().map(String::from),
+        session_id: push.session_id.clone(),
+        device_id: push.device_id.clone(),
+        title: push.alert.title.clone(),
+        body: push.alert.body.clone(),
+        error: error.to_string(),
+        attempts: 1,
+        created_at: now.clone(),
+        last_attempt_at: now,
+    };
+
+    if let Err(e) = storage.insert_delivery_failure(&failure) {
+        tracing::warn!("Failed to record delivery failure: {:?}", e);
+    }
+}
@@ -0,0 +1,105 @@
+//! Bounded admission for event ingestion.
+//!
+//! Every ingest holds an [`IngestPermit`] from the moment it is admitted until
+//! its database work finishes, so [`IngestQueue::depth`] counts requests that
+//! are waiting on or writing to the database. When the write path stalls (a
+//! `VACUUM`, a slow disk) the depth climbs to the capacity and further events
+//! are turned away with `503 Service Unavailable` and a `Retry-After`, rather
+//! than piling up until they time out.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Ingests admitted at once when no capacity is configured.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Seconds a rejected client is asked to wait before retrying.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+#[derive(Debug)]
+pub struct IngestQueue {
+    depth: AtomicUsize,
+    capacity: usize,
+}
+
+impl IngestQueue {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            depth: AtomicUsize::new(0),
+            capacity,
+        }
+    }
+
+    /// Ingests currently admitted and not yet finished.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Acquire)
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Admits one ingest, or returns `None` when the queue is full. The slot
+    /// is released when the permit is dropped.
+    pub fn try_admit(&self) -> Option<IngestPermit<'_>> {
+        self.depth
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |depth| {
+                (depth < self.capacity).then_some(depth + 1)
+            })
+            .ok()
+            .map(|_| IngestPermit { queue: self })
+    }
+}
+
+impl Default for IngestQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// One admitted ingest; frees its slot on drop.
+#[derive(Debug)]
+pub struct IngestPermit<'a> {
+    queue: &'a IngestQueue,
+}
+
+impl Drop for IngestPermit<'_> {
+    fn drop(&mut self) {
+        self.queue.depth.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admits_up_to_capacity() {
+        let queue = IngestQueue::new(2);
+        let first = queue.try_admit();
+        let second = queue.try_admit();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert_eq!(queue.depth(), 2);
+        assert!(queue.try_admit().is_none());
+        assert_eq!(queue.depth(), 2);
+    }
+
+    #[test]
+    fn test_dropping_permit_frees_slot() {
+        let queue = IngestQueue::new(1);
+        let permit = queue.try_admit();
+        assert!(permit.is_some());
+        assert!(queue.try_admit().is_none());
+
+        drop(permit);
+        assert_eq!(queue.depth(), 0);
+        assert!(queue.try_admit().is_some());
+    }
+
+    #[test]
+    fn test_zero_capacity_rejects_everything() {
+        let queue = IngestQueue::new(0);
+        assert!(queue.try_admit().is_none());
+        assert_eq!(queue.depth(), 0);
+    }
+}
@@ -1,13 +1,34 @@
 #![allow(missing_docs)]
 
+pub mod api_version;
 pub mod apns;
 pub(crate) mod auth;
+pub mod bus;
+pub mod clock_skew;
 pub(crate) mod config;
 pub(crate) mod handlers;
+pub(crate) mod ingest;
+pub mod ingest_queue;
 pub(crate) mod notif_dedup;
+pub(crate) mod pagination;
+pub mod setup;
 pub(crate) mod utils;
 
 pub mod db;
 pub mod error;
+pub mod event_storage;
+pub mod health;
+pub mod list_cache;
+pub mod log_redaction;
+pub mod metrics;
 pub mod models;
+pub mod notif_routing;
+pub mod paths;
+pub mod route_limits;
 pub mod router;
+pub mod session_export;
+pub mod session_title;
+pub mod snooze;
+pub mod storage;
+pub mod time_range;
+pub mod unknown_fields;
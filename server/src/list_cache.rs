@@ -0,0 +1,217 @@
+//! In-memory cache of the device and session list responses.
+//!
+//! Every client polls these lists, and most polls find nothing changed. A
+//! cached list is keyed by its endpoint and parameters (partition included)
+//! and tagged with the data and notification versions it was read at, so
+//! ingesting an event or a notification retires it. Writes that change the
+//! lists without bumping a version (pinning a session, acknowledging
+//! notifications, registering a device, retention cleanup) call
+//! [`ReadCache::invalidate`], which also bumps the cache's own generation:
+//! a list loaded across an invalidation is returned but not cached, since
+//! it may predate the write. Entries also expire after [`TTL`], which
+//! bounds the drift of time-based counts like `events_last_24h`.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::db::queries::PaginatedSessions;
+use crate::error::AppError;
+use crate::models::response::{DeviceResponse, SyncInfo};
+
+/// How long a cached list may be served.
+pub const TTL: Duration = Duration::from_secs(10);
+
+/// Lists kept per cache. Past this, the cache starts over.
+const MAX_ENTRIES: usize = 256;
+
+#[derive(Debug)]
+struct Entry<T> {
+    versions: (u64, u64),
+    loaded_at: Instant,
+    value: T,
+}
+
+#[derive(Debug)]
+struct Entries<T> {
+    /// Bumped by every [`ListCache::clear`].
+    generation: u64,
+    map: HashMap<String, Entry<T>>,
+}
+
+/// One kind of list, by endpoint and parameters.
+#[derive(Debug)]
+pub struct ListCache<T> {
+    entries: Mutex<Entries<T>>,
+}
+
+impl<T> Default for ListCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(Entries {
+                generation: 0,
+                map: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<T: Clone> ListCache<T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries<T>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// The list cached under `key` at `sync`'s versions, or the one `load`
+    /// reads, which is cached. Take `sync` before loading, so a write racing
+    /// the load tags the entry with versions already stale. A load that
+    /// overlaps a [`ReadCache::invalidate`] is not cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns `load`'s error, which is not cached.
+    pub fn get_or_load(
+        &self,
+        key: String,
+        sync: &SyncInfo,
+        now: Instant,
+        load: impl FnOnce() -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let versions = (sync.data_version, sync.notification_version);
+        let (cached, generation) = {
+            let entries = self.lock();
+            let cached = entries
+                .map
+                .get(&key)
+                .filter(|entry| {
+                    entry.versions == versions && now.duration_since(entry.loaded_at) < TTL
+                })
+                .map(|entry| entry.value.clone());
+            (cached, entries.generation)
+        };
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+
+        let value = load()?;
+        let mut entries = self.lock();
+        if entries.generation != generation {
+            return Ok(value);
+        }
+        // Versions only grow, so entries from older ones can't be served again
+        entries.map.retain(|_, entry| entry.versions == versions);
+        if entries.map.len() >= MAX_ENTRIES {
+            entries.map.clear();
+        }
+        entries.map.insert(
+            key,
+            Entry {
+                versions,
+                loaded_at: now,
+                value: value.clone(),
+            },
+        );
+        drop(entries);
+        Ok(value)
+    }
+
+    fn clear(&self) {
+        let mut entries = self.lock();
+        entries.generation += 1;
+        entries.map.clear();
+    }
+}
+
+/// The cached lists.
+#[derive(Debug, Default)]
+pub struct ReadCache {
+    /// `GET /api/v1/devices` and `GET /api/v1/devices/conflicts`.
+    pub devices: ListCache<Vec<DeviceResponse>>,
+    /// `GET /api/v1/sessions` and `GET /api/v1/devices/:id/sessions`.
+    pub sessions: ListCache<PaginatedSessions>,
+}
+
+impl ReadCache {
+    /// Drops every cached list, after a write the versions don't track.
+    pub fn invalidate(&self) {
+        self.devices.clear();
+        self.sessions.clear();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn sync(data_version: u64) -> SyncInfo {
+        SyncInfo {
+            server_time: String::new(),
+            data_version,
+            notification_version: 0,
+        }
+    }
+
+    #[test]
+    fn test_cached_until_version_changes() {
+        let cache = ListCache::<u32>::default();
+        let now = Instant::now();
+        let get = |key: &str, version, value| {
+            cache
+                .get_or_load(key.to_string(), &sync(version), now, || Ok(value))
+                .unwrap()
+        };
+
+        assert_eq!(get("a", 1, 1), 1);
+        assert_eq!(get("a", 1, 2), 1);
+        assert_eq!(get("b", 1, 3), 3);
+        assert_eq!(get("a", 2, 4), 4);
+        assert_eq!(cache.lock().map.len(), 1);
+    }
+
+    #[test]
+    fn test_load_racing_a_clear_is_not_cached() {
+        let cache = ListCache::<u32>::default();
+        let now = Instant::now();
+        let value = cache
+            .get_or_load("a".into(), &sync(1), now, || {
+                cache.clear();
+                Ok(1)
+            })
+            .unwrap();
+        assert_eq!(value, 1);
+        let value = cache
+            .get_or_load("a".into(), &sync(1), now, || Ok(2))
+            .unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = ListCache::<u32>::default();
+        let now = Instant::now();
+        cache
+            .get_or_load("a".into(), &sync(1), now, || Ok(1))
+            .unwrap();
+        let value = cache
+            .get_or_load("a".into(), &sync(1), now + TTL, || Ok(2))
+            .unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_failed_load_is_not_cached() {
+        let cache = ListCache::<u32>::default();
+        let now = Instant::now();
+        assert!(cache
+            .get_or_load("a".into(), &sync(1), now, || Err(AppError::Internal(
+                "down".into()
+            )))
+            .is_err());
+        let value = cache
+            .get_or_load("a".into(), &sync(1), now, || Ok(1))
+            .unwrap();
+        assert_eq!(value, 1);
+    }
+}
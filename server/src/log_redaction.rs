@@ -0,0 +1,425 @@
+//! Scrubbing secrets out of log output.
+//!
+//! Handlers don't log credentials or prompts on purpose, but error strings
+//! and request traces can still carry them: a header echoed in an error, a
+//! rejected body, a database error quoting a value. [`MakeRedacting`] wraps
+//! the writer of each `fmt` layer, so every formatted line passes through
+//! [`Redactor::redact`] before it reaches stdout or the log file. It
+//! removes:
+//!
+//! - the token after `Bearer` (any case), as in an `Authorization` header
+//! - generated API keys (`claud_…`)
+//! - the configured master, previous, and encryption keys
+//! - with `--redact-log-prompts`, the values of [`PROMPT_FIELDS`], whether
+//!   logged as JSON (`"prompt":"…"`), a tracing field (`prompt="…"`), or
+//!   `Debug` output (`prompt: Some("…")`)
+//!
+//! The `fmt` layers write each event in one call, so a line is never split
+//! across writes.
+
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use tracing_subscriber::fmt::MakeWriter;
+
+/// What a removed value is replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Fields holding prompt text, scrubbed with `--redact-log-prompts`.
+pub const PROMPT_FIELDS: [&str; 2] = ["prompt", "custom_instructions"];
+
+/// Configured secrets shorter than this are not scrubbed: replacing every
+/// occurrence of a short string would mangle unrelated log text.
+const MIN_SECRET_LEN: usize = 8;
+
+/// Prefix of keys made by `auth::generate_api_key`.
+const GENERATED_KEY_PREFIX: &str = "claud_";
+
+/// The rules applied to each log line.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    prompts: bool,
+}
+
+impl Redactor {
+    /// Scrubs `secrets` (empty and short ones are skipped) and bearer
+    /// tokens, and prompt fields if `prompts` is set.
+    pub fn new<I: IntoIterator<Item = String>>(secrets: I, prompts: bool) -> Self {
+        let mut secrets: Vec<String> = secrets
+            .into_iter()
+            .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+            .collect();
+        // Longest first, so a secret containing another is removed whole
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        secrets.dedup();
+        Self { secrets, prompts }
+    }
+
+    /// `text` with every secret the rules match replaced by [`REDACTED`].
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for secret in &self.secrets {
+            if out.contains(secret.as_str()) {
+                out = out.replace(secret.as_str(), REDACTED);
+            }
+        }
+        out = redact_after(&out, "bearer ", true, is_token_char);
+        out = redact_after(&out, GENERATED_KEY_PREFIX, false, |c| {
+            c.is_ascii_alphanumeric()
+        });
+        if self.prompts {
+            for field in PROMPT_FIELDS {
+                out = redact_field(&out, field);
+            }
+        }
+        out
+    }
+
+    /// Wraps `inner` so everything written through it is redacted.
+    pub fn wrap<M>(self, inner: M) -> MakeRedacting<M> {
+        MakeRedacting {
+            inner,
+            redactor: Arc::new(self),
+        }
+    }
+}
+
+/// A [`MakeWriter`] whose writers redact before writing to `inner`'s.
+#[derive(Debug, Clone)]
+pub struct MakeRedacting<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for MakeRedacting<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            redactor: Arc::clone(&self.redactor),
+        }
+    }
+}
+
+/// Writer redacting each write before passing it on.
+#[derive(Debug)]
+pub struct RedactingWriter<W> {
+    inner: W,
+    redactor: Arc<Redactor>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let redacted = self.redactor.redact(&String::from_utf8_lossy(buf));
+        self.inner.write_all(redacted.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Characters of a bearer token: anything up to whitespace, a quote, or
+/// punctuation closing the value.
+const fn is_token_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '"' | '\'' | ',' | ';' | ')' | '}' | ']' | '\\')
+}
+
+/// Replaces the run of `is_value` characters after each `marker` with
+/// [`REDACTED`], keeping the marker. `marker` is matched ignoring ASCII
+/// case if `ignore_case` is set.
+fn redact_after(
+    text: &str,
+    marker: &str,
+    ignore_case: bool,
+    is_value: impl Fn(char) -> bool,
+) -> String {
+    let haystack = if ignore_case {
+        text.to_ascii_lowercase()
+    } else {
+        text.to_string()
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some(found) = haystack[from..].find(marker) {
+        let value_start = from + found + marker.len();
+        let value_len: usize = text[value_start..]
+            .chars()
+            .take_while(|&c| is_value(c))
+            .map(char::len_utf8)
+            .sum();
+        if value_len > 0 {
+            out.push_str(&text[copied..value_start]);
+            out.push_str(REDACTED);
+            copied = value_start + value_len;
+        }
+        from = value_start + value_len;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// Length of the ANSI escape sequences (`ESC [ … m`) at the start of `s`.
+fn ansi_len(s: &str) -> usize {
+    let mut len = 0;
+    while let Some(rest) = s[len..].strip_prefix("\x1b[") {
+        match rest.find('m') {
+            Some(end) => len += 2 + end + 1,
+            None => break,
+        }
+    }
+    len
+}
+
+/// Length of whitespace and ANSI escapes at the start of `s`.
+fn filler_len(s: &str) -> usize {
+    let mut len = 0;
+    loop {
+        let skipped = ansi_len(&s[len..])
+            + s[len..]
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .map(char::len_utf8)
+                .sum::<usize>();
+        if skipped == 0 {
+            return len;
+        }
+        len += skipped;
+    }
+}
+
+/// Whether `field` at the end of `before` starts a name, rather than
+/// ending a longer one like `user_prompt`.
+fn starts_name(before: &str) -> bool {
+    // A name right after an ANSI style, e.g. `ESC[3mprompt`
+    if let Some(style) = before.strip_suffix('m') {
+        let params = style.trim_end_matches(|c: char| c.is_ascii_digit() || c == ';');
+        if params.ends_with("\x1b[") {
+            return true;
+        }
+    }
+    !before
+        .chars()
+        .next_back()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Replaces the string values of `field` with [`REDACTED`], in JSON
+/// (`"field": "…"`, also inside escaped JSON), tracing (`field="…"` or
+/// `field=…`), and `Debug` (`field: "…"`, `field: Some("…")`) forms.
+fn redact_field(text: &str, field: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut from = 0;
+    while let Some(found) = text[from..].find(field) {
+        let name_start = from + found;
+        let mut at = name_start + field.len();
+        from = at;
+        if !starts_name(&text[..name_start]) {
+            continue;
+        }
+        // Closing quote of a JSON key, escaped or not
+        at += ["\\\"", "\""]
+            .iter()
+            .find(|quote| text[at..].starts_with(*quote))
+            .map_or(0, |quote| quote.len());
+        at += filler_len(&text[at..]);
+        let separator = text[at..].chars().next();
+        if !matches!(separator, Some(':' | '=')) {
+            continue;
+        }
+        at += 1;
+        at += filler_len(&text[at..]);
+        if text[at..].starts_with("Some(") {
+            at += "Some(".len();
+        }
+        let Some(value_len) = value_len(&text[at..], separator == Some('=')) else {
+            continue;
+        };
+        out.push_str(&text[copied..at]);
+        let value = &text[at..at + value_len];
+        let quote = ["\\\"", "\""]
+            .into_iter()
+            .find(|quote| value.starts_with(quote));
+        match quote {
+            Some(quote) => {
+                out.push_str(quote);
+                out.push_str(REDACTED);
+                out.push_str(quote);
+            }
+            None => out.push_str(REDACTED),
+        }
+        copied = at + value_len;
+        from = copied;
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// Length of the value at the start of `s`: a string quoted with `"` or
+/// `\"`, or, if `bare` is allowed, a run of non-whitespace. `None` if
+/// there is no value to redact (e.g. `null`, `None`, or nothing).
+fn value_len(s: &str, bare: bool) -> Option<usize> {
+    if let Some(rest) = s.strip_prefix("\\\"") {
+        // Escaped JSON, e.g. in `Debug` output: `\\` is a backslash and `\"`
+        // a quote. The value ends at a quote the JSON doesn't escape.
+        let bytes = rest.as_bytes();
+        let mut escaped = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            let token = &bytes[i..(i + 2).min(bytes.len())];
+            if escaped {
+                escaped = false;
+            } else if token == b"\\\\" {
+                escaped = true;
+            } else if token == b"\\\"" {
+                return Some(2 + i + 2);
+            }
+            i += if token[0] == b'\\' { token.len() } else { 1 };
+        }
+        return Some(s.len());
+    }
+    if let Some(rest) = s.strip_prefix('"') {
+        let bytes = rest.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return Some(1 + i + 1),
+                _ => i += 1,
+            }
+        }
+        return Some(s.len());
+    }
+    if !bare {
+        return None;
+    }
+    let len: usize = s
+        .chars()
+        .take_while(|c| !c.is_whitespace())
+        .map(char::len_utf8)
+        .sum();
+    (len > 0).then_some(len)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn redactor(prompts: bool) -> Redactor {
+        Redactor::new(
+            ["master-secret-key".to_string(), "short".to_string()],
+            prompts,
+        )
+    }
+
+    #[test]
+    fn test_bearer_tokens_are_removed() {
+        let r = redactor(false);
+        assert_eq!(
+            r.redact(r#"headers: {"authorization": "Bearer abc.def-123"}"#),
+            r#"headers: {"authorization": "Bearer [REDACTED]"}"#
+        );
+        assert_eq!(
+            r.redact("authorization: bearer tok, next"),
+            "authorization: bearer [REDACTED], next"
+        );
+        assert_eq!(r.redact("Bearer  "), "Bearer  ");
+    }
+
+    #[test]
+    fn test_keys_are_removed() {
+        let r = redactor(false);
+        assert_eq!(
+            r.redact("key claud_0123456789abcdef0123456789abcdef rejected"),
+            "key claud_[REDACTED] rejected"
+        );
+        assert_eq!(
+            r.redact("auth with master-secret-key failed"),
+            "auth with [REDACTED] failed"
+        );
+        // Short configured secrets and metric names are left alone
+        assert_eq!(
+            r.redact("short claudiator_apns_configured 1"),
+            "short claudiator_apns_configured 1"
+        );
+    }
+
+    #[test]
+    fn test_prompts_kept_unless_enabled() {
+        let line = r#"body {"prompt":"my secret plan"}"#;
+        assert_eq!(redactor(false).redact(line), line);
+        assert_eq!(
+            redactor(true).redact(line),
+            r#"body {"prompt":"[REDACTED]"}"#
+        );
+    }
+
+    #[test]
+    fn test_prompt_forms() {
+        let r = redactor(true);
+        assert_eq!(
+            r.redact(r#"{"prompt": "say \"hi\"", "cwd": "/tmp"}"#),
+            r#"{"prompt": "[REDACTED]", "cwd": "/tmp"}"#
+        );
+        assert_eq!(
+            r.redact(r#"Internal error: "{\"prompt\":\"a \\\"b\\\"\",\"x\":1}""#),
+            r#"Internal error: "{\"prompt\":\"[REDACTED]\",\"x\":1}""#
+        );
+        assert_eq!(
+            r.redact(r#"event prompt="fix it" session_id=s1"#),
+            r#"event prompt="[REDACTED]" session_id=s1"#
+        );
+        assert_eq!(
+            r.redact("event prompt=plain session_id=s1"),
+            "event prompt=[REDACTED] session_id=s1"
+        );
+        assert_eq!(
+            r.redact(r#"EventData { prompt: Some("fix it"), custom_instructions: None }"#),
+            r#"EventData { prompt: Some("[REDACTED]"), custom_instructions: None }"#
+        );
+        assert_eq!(
+            r.redact("\x1b[3mprompt\x1b[0m\x1b[2m=\x1b[0m\"fix it\""),
+            "\x1b[3mprompt\x1b[0m\x1b[2m=\x1b[0m\"[REDACTED]\""
+        );
+    }
+
+    #[test]
+    fn test_other_fields_are_kept() {
+        let r = redactor(true);
+        let line = r#"user_prompt="x" prompt_count=3 {"prompts": 2} prompt: null"#;
+        assert_eq!(r.redact(line), line);
+    }
+
+    #[test]
+    fn test_writer_redacts_each_write() {
+        let out = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = std::sync::Arc::clone(&out);
+        let make = redactor(false).wrap(move || SharedBuf(std::sync::Arc::clone(&sink)));
+        let mut writer = make.make_writer();
+        let line = b"Authorization: Bearer abc\n";
+        assert_eq!(writer.write(line).unwrap(), line.len());
+        assert_eq!(
+            String::from_utf8(out.lock().unwrap().clone()).unwrap(),
+            "Authorization: Bearer [REDACTED]\n"
+        );
+    }
+
+    struct SharedBuf(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,67 +1,81 @@
 #![allow(missing_docs)]
 
+mod api_version;
 mod apns;
 mod auth;
+mod bus;
+mod clock_skew;
 mod config;
 mod db;
 mod error;
+mod event_storage;
 mod handlers;
+mod health;
+mod ingest;
+mod ingest_queue;
+mod list_cache;
+mod log_redaction;
+mod metrics;
 mod models;
 mod notif_dedup;
+mod notif_routing;
+mod pagination;
+mod paths;
+mod route_limits;
 mod router;
+mod session_export;
+mod session_title;
+mod setup;
+mod snooze;
+mod storage;
+mod time_range;
+mod unknown_fields;
 mod utils;
 
-use std::collections::HashMap;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::time::Duration;
 
 use clap::Parser;
 
-use config::ServerConfig;
+use config::{Command, ServerConfig};
+use db::encryption::FieldCipher;
 use db::pool;
-use router::AppState;
+use db::queries::NotificationTtl;
+use event_storage::EventStorage;
+use ingest_queue::IngestQueue;
+use log_redaction::Redactor;
+use notif_routing::NotificationRoutes;
+use route_limits::RouteLimits;
+use router::{AppState, NotifState, RetentionConfig};
+use storage::{SharedStorage, SqliteStorage, Storage};
+use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 #[tokio::main]
 #[allow(clippy::expect_used)]
 async fn main() {
-    let config = ServerConfig::parse();
+    let mut config = ServerConfig::parse();
 
-    // Build env filter: RUST_LOG takes precedence, then config.log_level
-    let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+    if let Some(command) = &config.command {
+        run_command(command);
+        return;
+    }
+    let api_key = config.api_key.clone().expect("clap requires --api-key");
 
-    // File appender with daily rotation
-    let file_appender = tracing_appender::rolling::daily(&config.log_dir, "server.log");
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    config.resolve_paths();
+    if let Err(e) = paths::check_writable(&config.db_path, &config.log_dir) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(fmt::layer().with_target(true))
-        .with(fmt::layer().with_ansi(false).with_writer(non_blocking))
-        .init();
+    let _log_guard = init_logging(&config, &api_key);
 
     // Initialize database
-    let db_pool = pool::create_pool(&config.db_path).expect("Failed to create database pool");
-
-    db::migrations::run(&db_pool).expect("Failed to run database migrations");
+    let (storage, pool_stats) = open_database(&config);
 
-    // Load version counters from metadata table
-    let (data_version, notification_version) = {
-        let conn = db_pool.get().expect("Failed to get db connection");
-        let data_v = db::queries::get_metadata(&conn, "data_version")
-            .ok()
-            .flatten()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(0);
-        let notif_v = db::queries::get_metadata(&conn, "notification_version")
-            .ok()
-            .flatten()
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or(0);
-        (data_v, notif_v)
-    };
+    let (data_version, notification_version) = load_versions(&*storage);
+    let last_maintenance = load_last_maintenance(&*storage);
 
     tracing::info!(
         "Loaded data_version: {}, notification_version: {}",
@@ -69,51 +83,66 @@ async fn main() {
         notification_version
     );
 
-    // Build APNs client if configured
-    let apns_client = if let (Some(key_path), Some(key_id), Some(team_id), Some(bundle_id)) = (
-        &config.apns_key_path,
-        &config.apns_key_id,
-        &config.apns_team_id,
-        &config.apns_bundle_id,
-    ) {
-        match apns::ApnsClient::new(
-            key_path,
-            key_id.clone(),
-            team_id.clone(),
-            bundle_id.clone(),
-            config.apns_sandbox,
-        ) {
-            Ok(client) => {
-                tracing::info!("APNs client initialized (sandbox: {})", config.apns_sandbox);
-                Some(Arc::new(client))
-            }
-            Err(e) => {
-                tracing::warn!(
-                    "Failed to initialize APNs client: {}. Push notifications disabled.",
-                    e
-                );
-                None
-            }
-        }
-    } else {
-        tracing::info!("APNs not configured, push notifications disabled");
-        None
-    };
+    let apns_client = build_apns_client(&config);
+
+    let previous_master_key = config
+        .previous_api_key
+        .clone()
+        .filter(|key| !key.is_empty() && *key != api_key);
+    if previous_master_key.is_some() {
+        tracing::warn!(
+            "Previous master key accepted during rotation; see GET /admin/previous-key-clients"
+        );
+    }
 
-    let state = Arc::new(AppState {
-        master_key: config.api_key.clone(),
-        db_pool,
+    let mut state = AppState {
+        notif: NotifState {
+            apns_client,
+            ttl: NotificationTtl {
+                default_hours: config.notification_ttl_hours,
+                per_type: config.notification_type_ttl.clone(),
+            },
+            routes: NotificationRoutes {
+                per_type: config.notification_routes.clone(),
+            },
+            error_min_severity: config.error_notification_min_severity,
+            ..NotifState::default()
+        },
+        retention: RetentionConfig {
+            events_days: config.retention_events_days,
+            sessions_days: config.retention_sessions_days,
+            devices_days: config.retention_devices_days,
+            push_tokens_days: config.retention_push_tokens_days,
+            maintenance_interval_hours: config.maintenance_interval_hours,
+            archive_events: config.archive_events,
+        },
+        public_url: config
+            .public_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty()),
         version: AtomicU64::new(data_version),
         notification_version: AtomicU64::new(notification_version),
-        last_cleanup: AtomicU64::new(0),
-        apns_client,
-        retention_events_days: config.retention_events_days,
-        retention_sessions_days: config.retention_sessions_days,
-        retention_devices_days: config.retention_devices_days,
-        auth_failures: Arc::new(Mutex::new(HashMap::new())),
-        key_rate_limits: Arc::new(Mutex::new(HashMap::new())),
-        notif_cooldown: Arc::new(Mutex::new(HashMap::new())),
-    });
+        event_storage: EventStorage {
+            per_type: config.event_storage.clone(),
+        },
+        session_title_strategy: config.session_title_strategy,
+        session_title_template: config.session_title_template.clone(),
+        privacy_mode: config.privacy_mode,
+        min_hook_version: config.min_hook_version.clone(),
+        ingest_queue: IngestQueue::new(config.ingest_queue_capacity),
+        route_limits: RouteLimits::new(
+            Duration::from_secs(config.heavy_route_timeout_secs),
+            config.heavy_route_concurrency,
+        ),
+        strict_fields: config.strict_fields,
+        pool_stats,
+        last_maintenance: AtomicU64::new(last_maintenance),
+        ..AppState::new(api_key, storage)
+    };
+    state.auth.previous_master_key = previous_master_key;
+    let state = Arc::new(state);
+    snooze::spawn(Arc::clone(&state));
 
     let app = router::build_router(state);
 
@@ -136,6 +165,87 @@ async fn main() {
     .expect("Server error");
 }
 
+/// Logs to stdout and a daily file in `--log-dir`, both scrubbed of keys
+/// (and prompts with `--redact-log-prompts`). Logs are flushed to the file
+/// until the returned guard is dropped.
+fn init_logging(config: &ServerConfig, api_key: &str) -> WorkerGuard {
+    // Build env filter: RUST_LOG takes precedence, then config.log_level
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+
+    // File appender with daily rotation
+    let file_appender = tracing_appender::rolling::daily(&config.log_dir, "server.log");
+    let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
+
+    // Both outputs scrub keys, and prompts if asked, before writing
+    let redactor = Redactor::new(
+        [
+            Some(api_key.to_string()),
+            config.previous_api_key.clone(),
+            config.encryption_key.clone(),
+        ]
+        .into_iter()
+        .flatten(),
+        config.redact_log_prompts,
+    );
+
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(
+            fmt::layer()
+                .with_target(true)
+                .with_writer(redactor.clone().wrap(std::io::stdout)),
+        )
+        .with(
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(redactor.wrap(non_blocking)),
+        )
+        .init();
+    guard
+}
+
+/// Loads the data and notification version counters from the metadata
+/// table, starting at 0 when they've never been stored.
+fn load_versions(storage: &dyn Storage) -> (u64, u64) {
+    let load = |key| {
+        storage
+            .get_metadata(key)
+            .ok()
+            .flatten()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    (load("data_version"), load("notification_version"))
+}
+
+/// Loads when database maintenance last ran from the metadata table. With
+/// none stored, the current time, so upgrading or restarting a server
+/// doesn't start a pass before its interval.
+#[allow(clippy::cast_sign_loss)]
+fn load_last_maintenance(storage: &dyn Storage) -> u64 {
+    storage
+        .get_metadata("last_maintenance")
+        .ok()
+        .flatten()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() as u64)
+}
+
+/// Runs a one-off subcommand instead of the server, exiting non-zero if it
+/// fails.
+fn run_command(command: &Command) {
+    match command {
+        Command::Setup(args) => match setup::run(args) {
+            Ok(setup) => setup.print(),
+            Err(e) => {
+                eprintln!("Setup failed: {e}");
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
 #[allow(clippy::expect_used)]
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -162,3 +272,80 @@ async fn shutdown_signal() {
 
     tracing::info!("Shutdown signal received, finishing in-flight requests...");
 }
+
+/// Opens and migrates the database, with at-rest encryption if a key is
+/// configured. Exits if the key is unusable.
+#[allow(clippy::expect_used)]
+fn open_database(config: &ServerConfig) -> (SharedStorage, Arc<pool::PoolStats>) {
+    let field_cipher = FieldCipher::from_config(
+        config.encryption_key.as_deref(),
+        config.encryption_key_file.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        tracing::error!("{e}");
+        std::process::exit(1);
+    });
+    if field_cipher.is_some() {
+        tracing::info!("Encrypting prompts, event JSON, and session titles at rest");
+    }
+    if config.heavy_route_concurrency >= config.db_pool_size as usize {
+        tracing::warn!(
+            "--heavy-route-concurrency ({}) leaves no database connections for ingestion; \
+             raise --db-pool-size ({})",
+            config.heavy_route_concurrency,
+            config.db_pool_size
+        );
+    }
+    let settings = pool::PoolSettings {
+        max_size: config.db_pool_size,
+        connection_timeout: Duration::from_secs(config.db_pool_timeout_secs),
+    };
+    let stats = Arc::new(pool::PoolStats::default());
+    let db_pool =
+        pool::create_configured_pool(&config.db_path, field_cipher, settings, Arc::clone(&stats))
+            .expect("Failed to create database pool");
+
+    db::migrations::run(&db_pool).expect("Failed to run database migrations");
+    (Arc::new(SqliteStorage::new(db_pool)), stats)
+}
+
+/// Builds the APNs client if all of its settings are present.
+fn build_apns_client(config: &ServerConfig) -> Option<Arc<apns::ApnsClient>> {
+    if let (Some(key_path), Some(key_id), Some(team_id), Some(bundle_id)) = (
+        &config.apns_key_path,
+        &config.apns_key_id,
+        &config.apns_team_id,
+        &config.apns_bundle_id,
+    ) {
+        match apns::ApnsClient::new(
+            key_path,
+            key_id.clone(),
+            team_id.clone(),
+            bundle_id.clone(),
+            config.apns_sandbox,
+            config.apns_url_override.clone(),
+            config.apns_localized_alerts,
+        ) {
+            Ok(client) => {
+                tracing::info!(
+                    "APNs client initialized (untagged tokens use sandbox: {})",
+                    config.apns_sandbox
+                );
+                if let Some(url) = &config.apns_url_override {
+                    tracing::warn!("APNs pushes will be sent to {url} instead of Apple");
+                }
+                Some(Arc::new(client))
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to initialize APNs client: {}. Push notifications disabled.",
+                    e
+                );
+                None
+            }
+        }
+    } else {
+        tracing::info!("APNs not configured, push notifications disabled");
+        None
+    }
+}
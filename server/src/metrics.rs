@@ -0,0 +1,210 @@
+//! Prometheus text exposition served by `GET /admin/metrics`.
+//!
+//! Metrics are read at scrape time. They cover what an operator needs to
+//! alert on before pushes silently stop (how old the APNs signing key is and
+//! when APNs last accepted a push) and to size the database connection
+//! pool (how many connections are busy and how long requests wait for one).
+
+use std::fmt::{Display, Write};
+use std::time::Duration;
+
+use crate::db::pool::{DbPool, PoolStats};
+use crate::router::NotifState;
+
+/// `Content-Type` of the exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The connection pool as of one scrape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolSample {
+    pub max_size: u32,
+    pub connections: u32,
+    pub idle: u32,
+    pub checkouts: u64,
+    pub wait_total: Duration,
+    pub wait_max: Duration,
+    pub timeouts: u64,
+}
+
+impl PoolSample {
+    /// Reads `pool` and the checkouts `stats` has recorded. The pool
+    /// gauges are zero for storage without a pool.
+    pub fn read(pool: Option<&DbPool>, stats: &PoolStats) -> Self {
+        let (max_size, connections, idle) = pool.map_or((0, 0, 0), |pool| {
+            let open = pool.state();
+            (pool.max_size(), open.connections, open.idle_connections)
+        });
+        Self {
+            max_size,
+            connections,
+            idle,
+            checkouts: stats.checkouts(),
+            wait_total: stats.wait_total(),
+            wait_max: stats.wait_max(),
+            timeouts: stats.timeouts(),
+        }
+    }
+}
+
+/// Renders every metric for `notif` and the pool.
+pub fn render(notif: &NotifState, pool: &PoolSample) -> String {
+    let mut out = String::new();
+    match notif.apns_client {
+        Some(ref client) => write_apns(&mut out, true, client.key_age(), client.last_success()),
+        None => write_apns(&mut out, false, None, None),
+    }
+    write_pool(&mut out, pool);
+    out
+}
+
+fn write_apns(
+    out: &mut String,
+    configured: bool,
+    key_age: Option<Duration>,
+    last_success: Option<u64>,
+) {
+    gauge(
+        out,
+        "claudiator_apns_configured",
+        "Whether APNs push delivery is configured (1) or disabled (0).",
+        u64::from(configured),
+    );
+    if !configured {
+        return;
+    }
+    if let Some(age) = key_age {
+        gauge(
+            out,
+            "claudiator_apns_key_age_seconds",
+            "Seconds since the APNs signing key file was last modified.",
+            age.as_secs(),
+        );
+    }
+    gauge(
+        out,
+        "claudiator_apns_last_success_timestamp_seconds",
+        "Unix time of the last push APNs accepted; 0 if none since the server started.",
+        last_success.unwrap_or(0),
+    );
+}
+
+fn write_pool(out: &mut String, pool: &PoolSample) {
+    gauge(
+        out,
+        "claudiator_db_pool_max_size",
+        "Connections the database pool may open (--db-pool-size).",
+        pool.max_size,
+    );
+    gauge(
+        out,
+        "claudiator_db_pool_connections",
+        "Database connections currently open.",
+        pool.connections,
+    );
+    gauge(
+        out,
+        "claudiator_db_pool_in_use",
+        "Database connections currently checked out.",
+        pool.connections.saturating_sub(pool.idle),
+    );
+    counter(
+        out,
+        "claudiator_db_pool_checkouts_total",
+        "Database connections handed out since the server started.",
+        pool.checkouts,
+    );
+    counter(
+        out,
+        "claudiator_db_pool_wait_seconds_total",
+        "Seconds spent waiting for a database connection, summed over checkouts.",
+        pool.wait_total.as_secs_f64(),
+    );
+    gauge(
+        out,
+        "claudiator_db_pool_wait_seconds_max",
+        "Longest wait for one database connection since the server started.",
+        pool.wait_max.as_secs_f64(),
+    );
+    counter(
+        out,
+        "claudiator_db_pool_timeouts_total",
+        "Checkouts that gave up after --db-pool-timeout-secs without a connection.",
+        pool.timeouts,
+    );
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl Display) {
+    metric(out, name, help, "gauge", value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: impl Display) {
+    metric(out, name, help, "counter", value);
+}
+
+fn metric(out: &mut String, name: &str, help: &str, kind: &str, value: impl Display) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+    let _ = writeln!(out, "{name} {value}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unconfigured_apns_reports_only_configured_gauge() {
+        let mut out = String::new();
+        write_apns(&mut out, false, None, None);
+        assert!(out.contains("claudiator_apns_configured 0\n"));
+        assert!(!out.contains("claudiator_apns_key_age_seconds"));
+        assert!(!out.contains("claudiator_apns_last_success_timestamp_seconds"));
+    }
+
+    #[test]
+    fn test_configured_apns_gauges() {
+        let mut out = String::new();
+        write_apns(
+            &mut out,
+            true,
+            Some(Duration::from_hours(24)),
+            Some(1_700_000_000),
+        );
+        assert!(out.contains("# TYPE claudiator_apns_key_age_seconds gauge\n"));
+        assert!(out.contains("claudiator_apns_configured 1\n"));
+        assert!(out.contains("claudiator_apns_key_age_seconds 86400\n"));
+        assert!(out.contains("claudiator_apns_last_success_timestamp_seconds 1700000000\n"));
+    }
+
+    #[test]
+    fn test_pool_metrics() {
+        let mut out = String::new();
+        write_pool(
+            &mut out,
+            &PoolSample {
+                max_size: 4,
+                connections: 3,
+                idle: 1,
+                checkouts: 10,
+                wait_total: Duration::from_millis(1500),
+                wait_max: Duration::from_millis(250),
+                timeouts: 1,
+            },
+        );
+        assert!(out.contains("claudiator_db_pool_max_size 4\n"));
+        assert!(out.contains("claudiator_db_pool_connections 3\n"));
+        assert!(out.contains("claudiator_db_pool_in_use 2\n"));
+        assert!(out.contains("# TYPE claudiator_db_pool_checkouts_total counter\n"));
+        assert!(out.contains("claudiator_db_pool_checkouts_total 10\n"));
+        assert!(out.contains("claudiator_db_pool_wait_seconds_total 1.5\n"));
+        assert!(out.contains("claudiator_db_pool_wait_seconds_max 0.25\n"));
+        assert!(out.contains("claudiator_db_pool_timeouts_total 1\n"));
+    }
+
+    #[test]
+    fn test_missing_key_file_omits_key_age() {
+        let mut out = String::new();
+        write_apns(&mut out, true, None, None);
+        assert!(!out.contains("claudiator_apns_key_age_seconds"));
+        assert!(out.contains("claudiator_apns_last_success_timestamp_seconds 0\n"));
+    }
+}
@@ -1,40 +1,26 @@
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
-pub struct EventPayload {
-    pub device: DeviceInfo,
-    pub event: EventData,
-    pub timestamp: String,
-}
+// Wire-format types shared with the hook binary.
+pub use claudiator_types::{
+    DeviceInfo, EventData, EventPayload, HookEventKind, PRIORITY_HIGH, SEVERITIES,
+};
 
+/// A notification from a tool other than Claude Code, for
+/// `POST /api/v1/ingest/generic`.
 #[derive(Debug, Deserialize)]
-pub struct DeviceInfo {
-    pub device_id: String,
-    pub device_name: String,
-    pub platform: String,
-}
-
-/// Inbound event data from the hook binary.
-///
-/// Contains only the 7 fields the server actually reads. Unknown fields in the
-/// incoming JSON are silently ignored by serde — no `extra` catch-all needed.
-/// This also means `event_json` stored in the database will only contain these
-/// 7 fields and never any sensitive data.
-#[derive(Debug, Deserialize, Serialize)]
-pub struct EventData {
-    pub session_id: String,
-    pub hook_event_name: String,
-
-    #[serde(default)]
-    pub cwd: Option<String>,
-    #[serde(default)]
-    pub prompt: Option<String>,
+pub struct GenericEventRequest {
+    /// What sent the event, such as `ci` or `backup`. Stands in for the
+    /// device.
+    pub source: String,
+    pub title: String,
     #[serde(default)]
-    pub notification_type: Option<String>,
+    pub body: Option<String>,
+    /// One of [`SEVERITIES`]; `info` when absent.
     #[serde(default)]
-    pub tool_name: Option<String>,
+    pub severity: Option<String>,
+    /// Groups the source's events into one session. The source when absent.
     #[serde(default)]
-    pub message: Option<String>,
+    pub group: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,7 +33,32 @@ pub struct PushRegisterRequest {
 
 #[derive(Debug, Deserialize)]
 pub struct AckRequest {
-    pub ids: Vec<String>,
+    /// Notifications to acknowledge by id.
+    pub ids: Option<Vec<String>>,
+    /// Instead of `ids`: every notification of this session.
+    pub session_id: Option<String>,
+    /// Instead of `ids`: only notifications created at or before this RFC
+    /// 3339 timestamp. Combines with `session_id`.
+    pub before: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnoozeRequest {
+    /// How long to hide the notification for.
+    pub minutes: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RespondRequest {
+    pub decision: String,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSessionRequest {
+    #[serde(default)]
+    pub pinned: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -56,6 +67,25 @@ pub struct CreateApiKeyRequest {
     pub scopes: Vec<String>,
     #[serde(default)]
     pub rate_limit: Option<u32>,
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub privacy_mode: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateEnrollmentCodeRequest {
+    #[serde(default)]
+    pub partition: Option<String>,
+    #[serde(default)]
+    pub ttl_minutes: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterDeviceRequest {
+    pub enrollment_code: String,
+    pub device_name: String,
+    pub platform: String,
 }
 
 #[cfg(test)]
@@ -145,12 +175,27 @@ mod tests {
     fn test_event_data_serializes_only_known_fields() {
         let data = EventData {
             session_id: "s1".to_string(),
-            hook_event_name: "Stop".to_string(),
+            hook_event_name: "Stop".into(),
             cwd: Some("/workspace".to_string()),
             prompt: None,
             notification_type: None,
             tool_name: Some("bash".to_string()),
             message: None,
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
         };
 
         let json = serde_json::to_string(&data).unwrap();
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use serde::Serialize;
 
+use crate::health::{HealthState, IncidentKind};
+
 #[derive(Debug, Serialize)]
 pub struct StatusOk {
     pub status: &'static str,
@@ -51,22 +55,99 @@ impl StatusOk {
     }
 }
 
+/// Optional features and limits of this server, reported by ping so clients
+/// can adapt instead of hard-coding assumptions.
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    pub schema_version: u32,
+    /// API versions served; client routes exist under each `/api/vN`.
+    pub api_versions: Vec<u32>,
+    pub endpoints: &'static [&'static str],
+    pub push_channels: Vec<&'static str>,
+    pub batch_events: bool,
+    pub max_body_bytes: usize,
+    /// Oldest `claudiator-hook` version this server supports.
+    pub min_hook_version: String,
+}
+
+/// Current load on the ingest path. Clients can back off before `depth`
+/// reaches `capacity` and events start being rejected with 503.
+#[derive(Debug, Serialize)]
+pub struct IngestQueueStatus {
+    pub depth: usize,
+    pub capacity: usize,
+}
+
 #[derive(Debug, Serialize)]
+pub struct PingResponse {
+    #[serde(flatten)]
+    pub status: StatusOk,
+    pub capabilities: Capabilities,
+    pub ingest_queue: IngestQueueStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct DeviceResponse {
     pub device_id: String,
     pub device_name: String,
+    /// `device_name`, with the start of `device_id` appended when another
+    /// device in the partition has the same name.
+    pub display_name: String,
     pub platform: String,
     pub first_seen: String,
     pub last_seen: String,
+    /// When the device's hook last sent a `Heartbeat`; absent if it never has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_heartbeat: Option<String>,
+    /// Version of the device's hook, from the `User-Agent` of its latest
+    /// event; absent if its hook never reported one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook_version: Option<String>,
+    /// Whether `hook_version` is older than the server's `--min-hook-version`.
+    pub hook_outdated: bool,
     pub active_sessions: i64,
+    pub unacked_notifications: i64,
+    /// Events received from the device in the 24 hours before the request.
+    pub events_last_24h: i64,
+    /// How far the device's clock is behind the server's (negative when
+    /// ahead), from its recent events; absent until it sends one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
+    /// Whether `clock_skew_ms` is large enough that the device's event
+    /// timestamps are being corrected.
+    pub clock_skewed: bool,
+}
+
+/// Server clock and change counters, flattened into client list responses
+/// so clients can spot clock skew and tell whether a sync is due without
+/// calling ping.
+#[derive(Debug, Serialize)]
+pub struct SyncInfo {
+    pub server_time: String,
+    pub data_version: u64,
+    pub notification_version: u64,
 }
 
 #[derive(Debug, Serialize)]
 pub struct DeviceListResponse {
     pub devices: Vec<DeviceResponse>,
+    #[serde(flatten)]
+    pub sync: SyncInfo,
+}
+
+/// Devices sharing one name.
+#[derive(Debug, Serialize)]
+pub struct DeviceConflict {
+    pub device_name: String,
+    pub devices: Vec<DeviceResponse>,
 }
 
 #[derive(Debug, Serialize)]
+pub struct DeviceConflictsResponse {
+    pub conflicts: Vec<DeviceConflict>,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct SessionResponse {
     pub session_id: String,
     pub device_id: String,
@@ -80,6 +161,23 @@ pub struct SessionResponse {
     pub device_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub platform: Option<String>,
+    pub pinned: bool,
+    /// For a subagent, the session that started it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_session_id: Option<String>,
+    /// For a subagent, its type, e.g. `"Explore"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_type: Option<String>,
+    /// Agent team the session belongs to, from its latest event naming one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    /// Claude model the session runs on, as reported by `SessionStart`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Subagents this session started, oldest first. Only filled in by
+    /// `GET /api/v1/sessions/:session_id`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subagents: Vec<Self>,
 }
 
 #[derive(Debug, Serialize)]
@@ -87,6 +185,64 @@ pub struct SessionListResponse {
     pub sessions: Vec<SessionResponse>,
     pub has_more: bool,
     pub next_offset: i64,
+    #[serde(flatten)]
+    pub sync: SyncInfo,
+}
+
+/// Sessions that haven't ended, by status.
+#[derive(Debug, Default, Serialize)]
+pub struct SessionStatusCounts {
+    pub active: i64,
+    pub waiting_for_permission: i64,
+    pub waiting_for_input: i64,
+    pub idle: i64,
+    pub compacting: i64,
+}
+
+/// Everything a widget or watch complication shows, in one small response.
+#[derive(Debug, Serialize)]
+pub struct OverviewResponse {
+    /// Changes whenever session data does; clients can skip redrawing while
+    /// it stays the same.
+    pub data_version: u64,
+    pub counts: SessionStatusCounts,
+    /// Sessions by the Claude model they ran on, ended ones included.
+    /// Sessions that never reported a model are left out.
+    pub models: BTreeMap<String, i64>,
+    /// See [`crate::db::queries::most_urgent_session`]. `null` when no
+    /// session is running.
+    pub most_urgent: Option<SessionResponse>,
+}
+
+/// A session whose title matched a search. `snippet` is the title with
+/// matched terms wrapped in `**`.
+#[derive(Debug, Serialize)]
+pub struct SessionSearchHit {
+    pub session_id: String,
+    pub device_id: String,
+    pub device_name: Option<String>,
+    pub title: String,
+    pub status: String,
+    pub last_event: String,
+    pub snippet: String,
+}
+
+/// An event whose prompt or message matched a search. `snippet` is an
+/// excerpt with matched terms wrapped in `**`.
+#[derive(Debug, Serialize)]
+pub struct EventSearchHit {
+    pub id: i64,
+    pub session_id: String,
+    pub device_id: String,
+    pub hook_event_name: String,
+    pub timestamp: String,
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub sessions: Vec<SessionSearchHit>,
+    pub events: Vec<EventSearchHit>,
 }
 
 #[derive(Debug, Serialize)]
@@ -94,14 +250,81 @@ pub struct EventResponse {
     pub id: i64,
     pub hook_event_name: String,
     pub timestamp: String,
+    /// Position within the session, assigned at insert; orders events that
+    /// share a `timestamp`.
+    pub seq: i64,
+    /// `timestamp` corrected for the device's clock skew.
+    pub normalized_timestamp: String,
     pub tool_name: Option<String>,
     pub notification_type: Option<String>,
     pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_subject: Option<String>,
+}
+
+/// An event as rendered in a session export, oldest first.
+#[derive(Debug, Clone)]
+pub struct TimelineEvent {
+    pub hook_event_name: String,
+    pub timestamp: String,
+    pub tool_name: Option<String>,
+    pub message: Option<String>,
+    pub prompt: Option<String>,
+    /// Headline of an `External` event.
+    pub title: Option<String>,
+    /// What started a `PreCompact` compaction.
+    pub trigger: Option<String>,
+    /// Why a `PostToolUseFailure` tool call failed.
+    pub error: Option<String>,
+    pub is_interrupt: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventDetailResponse {
+    pub id: i64,
+    pub device_id: String,
+    pub session_id: String,
+    pub hook_event_name: String,
+    pub timestamp: String,
+    pub received_at: String,
+    pub tool_name: Option<String>,
+    pub notification_type: Option<String>,
+    /// `timestamp` corrected for the device's clock skew.
+    pub normalized_timestamp: String,
+    /// `received_at` minus `timestamp`, in milliseconds. Absent for events
+    /// stored before skew was recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skew_ms: Option<i64>,
+    /// Options Claude Code suggested with a `PermissionRequest`, copied
+    /// out of `event_json` for remote approval.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission_suggestions: Option<serde_json::Value>,
+    pub event_json: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchivedEventResponse {
+    pub id: i64,
+    pub device_id: String,
+    pub session_id: String,
+    pub hook_event_name: String,
+    pub timestamp: String,
+    pub received_at: String,
+    pub tool_name: Option<String>,
+    pub notification_type: Option<String>,
+    pub event_json: String,
+    pub archived_at: String,
 }
 
 #[derive(Debug, Serialize)]
 pub struct EventListResponse {
     pub events: Vec<EventResponse>,
+    #[serde(flatten)]
+    pub sync: SyncInfo,
 }
 
 #[derive(Debug, Serialize)]
@@ -122,6 +345,77 @@ pub struct NotificationResponse {
 #[derive(Debug, Serialize)]
 pub struct NotificationListResponse {
     pub notifications: Vec<NotificationResponse>,
+    #[serde(flatten)]
+    pub sync: SyncInfo,
+}
+
+/// A decision on a permission notification, made remotely.
+#[derive(Debug, Serialize)]
+pub struct PermissionResponse {
+    pub id: String,
+    pub notification_id: String,
+    pub session_id: String,
+    /// `allow` or `deny`.
+    pub decision: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub created_at: String,
+}
+
+/// A notification every channel failed to deliver, kept for retry.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryFailure {
+    pub id: String,
+    pub notification_id: String,
+    /// Channel the delivery was attempted on, e.g. `apns`.
+    pub channel: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    pub session_id: String,
+    pub device_id: String,
+    pub title: String,
+    pub body: String,
+    /// Error from the most recent attempt.
+    pub error: String,
+    pub attempts: i64,
+    pub created_at: String,
+    pub last_attempt_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryFailureListResponse {
+    pub failures: Vec<DeliveryFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationResendResponse {
+    pub id: String,
+    pub delivered: bool,
+    /// Why the push failed; absent when it was delivered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationSnoozeResponse {
+    pub id: String,
+    /// When the notification is listed and pushed again.
+    pub snoozed_until: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliveryRetryResponse {
+    pub id: String,
+    pub delivered: bool,
+    pub attempts: i64,
+    /// Why the retry failed; absent when it was delivered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingResponsesResponse {
+    pub responses: Vec<PermissionResponse>,
 }
 
 #[derive(Debug, Serialize)]
@@ -133,6 +427,9 @@ pub struct ApiKeyCreatedResponse {
     pub created_at: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rate_limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    pub privacy_mode: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -145,6 +442,11 @@ pub struct ApiKeyListItem {
     pub last_used: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rate_limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub privacy_mode: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -152,6 +454,141 @@ pub struct ApiKeyListResponse {
     pub keys: Vec<ApiKeyListItem>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct EnrollmentCodeResponse {
+    pub code: String,
+    pub created_at: String,
+    pub expires_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+/// The string a client scans or pastes to enroll: `{"v":1,"url":..,"code":..}`.
+#[derive(Debug, Serialize)]
+pub struct EnrollmentQrPayload<'a> {
+    pub v: u32,
+    pub url: &'a str,
+    pub code: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EnrollmentQrResponse {
+    /// Compact JSON of [`EnrollmentQrPayload`], ready to render as a QR code.
+    pub qr_payload: String,
+    pub server_url: String,
+    pub code: String,
+    pub expires_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceRegisteredResponse {
+    pub device_id: String,
+    pub key_id: String,
+    pub api_key: String,
+    pub scopes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partition: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyUsageDay {
+    pub date: String,
+    pub requests: i64,
+    pub events: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyUsageResponse {
+    pub id: String,
+    pub name: String,
+    pub total_requests: i64,
+    pub total_events: i64,
+    pub days: Vec<KeyUsageDay>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviousKeyClient {
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub requests: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviousKeyClientsResponse {
+    pub previous_key_configured: bool,
+    pub clients: Vec<PreviousKeyClient>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncidentItem {
+    pub id: u64,
+    pub kind: IncidentKind,
+    pub summary: String,
+    pub started_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    pub state: HealthState,
+    pub server_time: String,
+    pub window_secs: i64,
+    pub requests: u64,
+    pub server_errors: u64,
+    pub pushes: u64,
+    pub push_failures: u64,
+    pub incidents: Vec<IncidentItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnknownFieldItem {
+    pub hook_event_name: String,
+    pub field: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub events: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnknownFieldsResponse {
+    pub strict_fields: bool,
+    pub fields: Vec<UnknownFieldItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CooldownItem {
+    pub session_id: String,
+    pub notification_type: String,
+    pub last_sent_at: String,
+    pub expires_at: String,
+    pub remaining_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CooldownListResponse {
+    pub window_secs: u64,
+    pub cooldowns: Vec<CooldownItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CooldownsClearedResponse {
+    pub status: &'static str,
+    pub cleared: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceResponse {
+    pub status: &'static str,
+    pub page_count_before: i64,
+    pub page_count_after: i64,
+    pub freelist_count_before: i64,
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -6,7 +6,19 @@ use std::time::{Duration, Instant};
 pub const NOTIF_COOLDOWN_WINDOW: Duration = Duration::from_secs(30);
 
 /// Notification types that always fire immediately, bypassing the cooldown.
-const HIGH_PRIORITY_TYPES: &[&str] = &["permission_prompt"];
+///
+/// `test` is user-initiated by `claudiator-hook test --send-test-event`, which
+/// must see a fresh notification on every run. `policy` notifications are
+/// each for an event a hook policy rule asked to hear about, each
+/// `hook_crash` for a separate crash, and each `external` for a separate
+/// message from another tool.
+const HIGH_PRIORITY_TYPES: &[&str] = &[
+    "permission_prompt",
+    "test",
+    "policy",
+    "hook_crash",
+    "external",
+];
 
 /// Per-session, per-type cooldown state.
 ///
@@ -16,9 +28,11 @@ pub type NotifCooldownMap = Mutex<HashMap<(String, String), Instant>>;
 
 /// Returns `true` if the notification should be sent, `false` if it should be suppressed.
 ///
-/// - **High-priority** types (`permission_prompt`) always return `true`.
-/// - **Low-priority** types (`stop`, `idle_prompt`) return `true` only when no notification
-///   of the same type was sent for this session within [`NOTIF_COOLDOWN_WINDOW`].
+/// - **High-priority** types (`permission_prompt`, `test`, `policy`, `hook_crash`) always
+///   return `true`.
+/// - **Low-priority** types (`stop`, `idle_prompt`, `task_completed`) return `true` only when
+///   no notification of the same type was sent for this session within
+///   [`NOTIF_COOLDOWN_WINDOW`].
 ///
 /// On a `true` return, the map entry is updated to the current time.
 pub fn should_send_notification(
@@ -51,9 +65,57 @@ pub fn should_send_notification(
     true
 }
 
+/// A running cooldown, as listed by `GET /admin/cooldowns`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveCooldown {
+    pub session_id: String,
+    pub notification_type: String,
+    /// Time since the notification that started the cooldown was sent.
+    pub elapsed: Duration,
+    /// Time until the next notification of this type goes through.
+    pub remaining: Duration,
+}
+
+/// Cooldowns still running, ordered by session then type. Expired entries
+/// are skipped but left for [`should_send_notification`] to evict.
+pub fn active_cooldowns(map: &NotifCooldownMap) -> Vec<ActiveCooldown> {
+    let now = Instant::now();
+    let mut cooldowns: Vec<ActiveCooldown> = map
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .iter()
+        .filter_map(|((session_id, notification_type), last_sent)| {
+            let elapsed = now.duration_since(*last_sent);
+            Some(ActiveCooldown {
+                session_id: session_id.clone(),
+                notification_type: notification_type.clone(),
+                elapsed,
+                remaining: NOTIF_COOLDOWN_WINDOW
+                    .checked_sub(elapsed)
+                    .filter(|remaining| !remaining.is_zero())?,
+            })
+        })
+        .collect();
+    cooldowns.sort_by(|a, b| {
+        (&a.session_id, &a.notification_type).cmp(&(&b.session_id, &b.notification_type))
+    });
+    cooldowns
+}
+
+/// Ends every cooldown for `session_id`, so its next notification of each
+/// type is sent. Returns how many were cleared.
+pub fn clear_cooldowns(map: &NotifCooldownMap, session_id: &str) -> usize {
+    let mut guard = map
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let before = guard.len();
+    guard.retain(|(session, _), _| session != session_id);
+    before - guard.len()
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
-#[allow(clippy::unchecked_duration_subtraction)]
+#[allow(clippy::unchecked_time_subtraction)]
 mod tests {
     use super::*;
 
@@ -61,6 +123,13 @@ mod tests {
         Mutex::new(HashMap::new())
     }
 
+    #[test]
+    fn test_test_type_always_fires() {
+        let map = make_map();
+        assert!(should_send_notification(&map, "sess-1", "test"));
+        assert!(should_send_notification(&map, "sess-1", "test"));
+    }
+
     #[test]
     fn test_permission_prompt_always_fires() {
         let map = make_map();
@@ -233,4 +302,53 @@ mod tests {
 
         assert_eq!(t0, t1);
     }
+
+    #[test]
+    fn test_active_cooldowns_lists_running_cooldowns_in_order() {
+        let map = make_map();
+        assert!(should_send_notification(&map, "sess-2", "stop"));
+        assert!(should_send_notification(&map, "sess-1", "stop"));
+        assert!(should_send_notification(&map, "sess-1", "idle_prompt"));
+        assert!(should_send_notification(
+            &map,
+            "sess-1",
+            "permission_prompt"
+        ));
+        map.lock().unwrap().insert(
+            ("sess-3".to_string(), "stop".to_string()),
+            Instant::now() - NOTIF_COOLDOWN_WINDOW,
+        );
+
+        let cooldowns = active_cooldowns(&map);
+        let keys: Vec<_> = cooldowns
+            .iter()
+            .map(|c| (c.session_id.as_str(), c.notification_type.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("sess-1", "idle_prompt"),
+                ("sess-1", "stop"),
+                ("sess-2", "stop")
+            ]
+        );
+        for cooldown in &cooldowns {
+            assert!(cooldown.remaining <= NOTIF_COOLDOWN_WINDOW);
+            assert!(cooldown.elapsed + cooldown.remaining <= NOTIF_COOLDOWN_WINDOW);
+        }
+    }
+
+    #[test]
+    fn test_clear_cooldowns_lets_next_notification_through() {
+        let map = make_map();
+        assert!(should_send_notification(&map, "sess-1", "stop"));
+        assert!(should_send_notification(&map, "sess-1", "idle_prompt"));
+        assert!(should_send_notification(&map, "sess-2", "stop"));
+
+        assert_eq!(clear_cooldowns(&map, "sess-1"), 2);
+        assert_eq!(clear_cooldowns(&map, "sess-1"), 0);
+
+        assert!(should_send_notification(&map, "sess-1", "stop"));
+        assert!(!should_send_notification(&map, "sess-2", "stop"));
+    }
 }
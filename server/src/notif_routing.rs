@@ -0,0 +1,136 @@
+//! Which delivery channels each notification type is sent through.
+//!
+//! Routes are configured per notification type, e.g.
+//! `permission_prompt=apns,idle_prompt=none`. Types without a route go to
+//! every channel, so an empty config behaves as before routing existed.
+
+/// A way of delivering a notification to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// The mobile app: the stored notification it polls for, plus an APNs
+    /// push when APNs is configured.
+    Apns,
+}
+
+impl Channel {
+    /// Every channel, in the order they are listed to users.
+    pub const ALL: &'static [Self] = &[Self::Apns];
+
+    /// The name used for this channel in route config.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Apns => "apns",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|channel| channel.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Route value that sends a notification type nowhere.
+const NO_CHANNELS: &str = "none";
+
+/// Per-type notification channel routes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotificationRoutes {
+    /// `(notification_type, channels)` routes; an empty list drops the type.
+    pub per_type: Vec<(String, Vec<Channel>)>,
+}
+
+impl NotificationRoutes {
+    /// Channels that deliver notifications of `notification_type`.
+    pub fn channels_for(&self, notification_type: &str) -> &[Channel] {
+        self.per_type
+            .iter()
+            .find(|(kind, _)| kind == notification_type)
+            .map_or(Channel::ALL, |(_, channels)| channels.as_slice())
+    }
+
+    /// Whether notifications of `notification_type` go to `channel`.
+    pub fn delivers(&self, notification_type: &str, channel: Channel) -> bool {
+        self.channels_for(notification_type).contains(&channel)
+    }
+}
+
+/// Parses one route, `type=channel+channel` or `type=none`.
+///
+/// # Errors
+///
+/// Returns a message for the config error when the type is missing or a
+/// channel is unknown.
+pub fn parse_route(value: &str) -> Result<(String, Vec<Channel>), String> {
+    let (kind, channels) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected `type=channels`, got `{value}`"))?;
+    let kind = kind.trim();
+    if kind.is_empty() {
+        return Err(format!("missing notification type in `{value}`"));
+    }
+
+    let channels = channels.trim();
+    if channels.eq_ignore_ascii_case(NO_CHANNELS) {
+        return Ok((kind.to_string(), Vec::new()));
+    }
+
+    let mut parsed = Vec::new();
+    for name in channels.split('+').map(str::trim) {
+        let channel = Channel::parse(name).ok_or_else(|| {
+            let known: Vec<&str> = Channel::ALL.iter().map(|c| c.as_str()).collect();
+            format!(
+                "unknown channel `{name}` in `{value}`; expected `{NO_CHANNELS}` or one of: {}",
+                known.join(", ")
+            )
+        })?;
+        if !parsed.contains(&channel) {
+            parsed.push(channel);
+        }
+    }
+    Ok((kind.to_string(), parsed))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unrouted_types_go_everywhere() {
+        let routes = NotificationRoutes::default();
+        assert_eq!(routes.channels_for("stop"), Channel::ALL);
+        assert!(routes.delivers("permission_prompt", Channel::Apns));
+    }
+
+    #[test]
+    fn test_routes_override_per_type() {
+        let routes = NotificationRoutes {
+            per_type: vec![
+                parse_route("idle_prompt=none").unwrap(),
+                parse_route("stop=apns").unwrap(),
+            ],
+        };
+        assert!(routes.channels_for("idle_prompt").is_empty());
+        assert!(!routes.delivers("idle_prompt", Channel::Apns));
+        assert_eq!(routes.channels_for("stop"), &[Channel::Apns]);
+        assert!(routes.delivers("permission_prompt", Channel::Apns));
+    }
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(
+            parse_route(" permission_prompt = APNS + apns ").unwrap(),
+            ("permission_prompt".to_string(), vec![Channel::Apns])
+        );
+        assert_eq!(
+            parse_route("stop=None").unwrap(),
+            ("stop".to_string(), Vec::new())
+        );
+        assert!(parse_route("stop").is_err());
+        assert!(parse_route("=apns").is_err());
+        assert!(parse_route("stop=").is_err());
+        assert!(parse_route("stop=apns+pager").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use serde::Deserialize;
+
+use crate::api_version::ApiVersion;
+use crate::error::AppError;
+
+/// Default page size when `limit` is omitted.
+pub const DEFAULT_LIMIT: i64 = 50;
+
+/// Largest page size any list endpoint will return.
+pub const MAX_LIMIT: i64 = 200;
+
+#[derive(Deserialize)]
+struct RawPagination {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// Extractor for the `limit` / `offset` query parameters shared by list endpoints.
+///
+/// `DEFAULT` is used when `limit` is omitted. Non-numeric input is rejected
+/// with `422 bad_request`. From `/api/v2` on, so are values outside `1..=MAX`
+/// and negative offsets, so every list endpoint fails the same way; `/api/v1`
+/// clamps them as it always has, since released clients send larger limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination<const DEFAULT: i64 = DEFAULT_LIMIT, const MAX: i64 = MAX_LIMIT> {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl<const DEFAULT: i64, const MAX: i64> Pagination<DEFAULT, MAX> {
+    fn from_raw(
+        limit: Option<i64>,
+        offset: Option<i64>,
+        version: ApiVersion,
+    ) -> Result<Self, AppError> {
+        let limit = limit.unwrap_or(DEFAULT);
+        let offset = offset.unwrap_or(0);
+        if version == ApiVersion::V1 {
+            return Ok(Self {
+                limit: limit.clamp(1, MAX),
+                offset: offset.max(0),
+            });
+        }
+
+        if !(1..=MAX).contains(&limit) {
+            return Err(AppError::BadRequest(format!(
+                "limit must be between 1 and {MAX}"
+            )));
+        }
+        if offset < 0 {
+            return Err(AppError::BadRequest("offset must not be negative".into()));
+        }
+
+        Ok(Self { limit, offset })
+    }
+}
+
+impl<S, const DEFAULT: i64, const MAX: i64> FromRequestParts<S> for Pagination<DEFAULT, MAX>
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    fn from_request_parts<'life0, 'life1, 'async_trait>(
+        parts: &'life0 mut Parts,
+        _state: &'life1 S,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self, AppError>> + Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        let version = parts
+            .extensions
+            .get::<ApiVersion>()
+            .copied()
+            .unwrap_or_default();
+        Box::pin(async move {
+            let Query(raw) = Query::<RawPagination>::try_from_uri(&parts.uri)
+                .map_err(|e| AppError::BadRequest(format!("invalid pagination parameters: {e}")))?;
+            Self::from_raw(raw.limit, raw.offset, version)
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    type Standard = Pagination;
+    type Events = Pagination<100>;
+
+    fn strict(limit: Option<i64>, offset: Option<i64>) -> Result<Standard, AppError> {
+        Standard::from_raw(limit, offset, ApiVersion::V2)
+    }
+
+    #[test]
+    fn test_defaults_when_omitted() {
+        for version in ApiVersion::ALL {
+            let p = Standard::from_raw(None, None, *version).unwrap();
+            assert_eq!(p.limit, DEFAULT_LIMIT);
+            assert_eq!(p.offset, 0);
+        }
+    }
+
+    #[test]
+    fn test_custom_default() {
+        assert_eq!(
+            Events::from_raw(None, None, ApiVersion::V2).unwrap().limit,
+            100
+        );
+    }
+
+    #[test]
+    fn test_accepts_bounds() {
+        assert_eq!(strict(Some(1), None).unwrap().limit, 1);
+        assert_eq!(strict(Some(MAX_LIMIT), None).unwrap().limit, MAX_LIMIT);
+    }
+
+    #[test]
+    fn test_rejects_zero_and_negative_limit() {
+        assert!(matches!(
+            strict(Some(0), None),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            strict(Some(-5), None),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_limit_above_max() {
+        assert!(matches!(
+            strict(Some(MAX_LIMIT + 1), None),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_negative_offset() {
+        assert!(matches!(
+            strict(None, Some(-1)),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_v1_clamps_out_of_range_values() {
+        let p = Standard::from_raw(Some(MAX_LIMIT + 100), Some(-5), ApiVersion::V1).unwrap();
+        assert_eq!(p.limit, MAX_LIMIT);
+        assert_eq!(p.offset, 0);
+        let p = Standard::from_raw(Some(0), None, ApiVersion::V1).unwrap();
+        assert_eq!(p.limit, 1);
+    }
+}
@@ -0,0 +1,103 @@
+//! Startup checks for the directories the server writes to.
+//!
+//! `SQLite` and the log appender only report an unwritable directory on
+//! first use, as a panic or an opaque I/O error. A server started from a
+//! read-only image or as a service user without access to its data
+//! directory should instead say which path is the problem and stop before
+//! serving. [`check_writable`] runs before logging is set up, so its errors
+//! go to stderr.
+
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+/// File created and removed to test that a directory is writable.
+const PROBE_FILE: &str = ".claudiator-write-test";
+
+/// Creates `dir` if it is missing and checks a file can be written in it.
+///
+/// # Errors
+///
+/// Names `what` (e.g. "Log") and the directory, with the OS error.
+pub fn ensure_writable_dir(what: &str, dir: &Path) -> Result<(), String> {
+    let shown = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    fs::create_dir_all(shown)
+        .map_err(|e| format!("{what} directory {} can't be created: {e}", shown.display()))?;
+    let probe = shown.join(PROBE_FILE);
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .map_err(|e| format!("{what} directory {} is not writable: {e}", shown.display()))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Checks the database file's directory and `log_dir` are writable, and
+/// the database file itself if it exists.
+///
+/// # Errors
+///
+/// Describes the first path that can't be written, and how to move it.
+pub fn check_writable(db_path: &str, log_dir: &str) -> Result<(), String> {
+    let hint =
+        "; set --data-dir (CLAUDIATOR_DATA_DIR) or the path's own flag to a writable location";
+    let db_path = Path::new(db_path);
+    ensure_writable_dir(
+        "Database",
+        db_path.parent().unwrap_or_else(|| Path::new("")),
+    )
+    .map_err(|e| format!("{e}{hint}"))?;
+    if db_path.exists() {
+        OpenOptions::new().append(true).open(db_path).map_err(|e| {
+            format!(
+                "Database file {} is not writable: {e}{hint}",
+                db_path.display()
+            )
+        })?;
+    }
+    ensure_writable_dir("Log", Path::new(log_dir)).map_err(|e| format!("{e}{hint}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_directories_are_created() {
+        let dir = TempDir::new().unwrap();
+        let db = dir.path().join("data/claudiator.db");
+        let logs = dir.path().join("logs");
+        check_writable(&db.display().to_string(), &logs.display().to_string()).unwrap();
+        assert!(db.parent().unwrap().is_dir());
+        assert!(logs.is_dir());
+        assert!(!logs.join(PROBE_FILE).exists());
+    }
+
+    #[test]
+    fn test_directory_in_place_of_a_file_is_reported() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("not-a-dir");
+        fs::write(&file, "").unwrap();
+        let logs = file.join("logs");
+        let error = check_writable(
+            &dir.path().join("claudiator.db").display().to_string(),
+            &logs.display().to_string(),
+        )
+        .unwrap_err();
+        assert!(
+            error.starts_with(&format!(
+                "Log directory {} can't be created",
+                logs.display()
+            )),
+            "{error}"
+        );
+        assert!(error.contains("--data-dir"), "{error}");
+    }
+}
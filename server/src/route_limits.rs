@@ -0,0 +1,240 @@
+//! Timeouts and a concurrency limit for the heavy read routes.
+//!
+//! Session export, search, the overview counts, and the archive export run
+//! queries whose cost grows with the database, on the same small connection
+//! pool event ingestion writes through. [`RouteLimits::limit`] wraps those
+//! routes so a pathological query can't hold connections indefinitely: at
+//! most `--heavy-route-concurrency` of them run at once, and further
+//! requests get `503` with a `Retry-After` instead of waiting for a
+//! connection; one running past `--heavy-route-timeout-secs` gets `408`.
+//!
+//! A timeout only drops the handler's future, and `SQLite` calls don't
+//! yield to it. The heavy handlers therefore query through
+//! [`with_storage`], which runs the query on the blocking pool and
+//! interrupts it if its future is dropped, so the connection goes back to
+//! the pool promptly.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::error_handling::HandleErrorLayer;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::MethodRouter;
+use tokio::sync::Semaphore;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::timeout::TimeoutLayer;
+use tower::ServiceBuilder;
+
+use crate::error::AppError;
+use crate::ingest_queue::RETRY_AFTER_SECS;
+use crate::storage::{self, Interrupt, SharedStorage, Storage};
+
+/// Seconds a heavy request may run when no timeout is configured.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+/// Heavy requests run at once when no limit is configured. The default pool
+/// has four connections; this leaves two for ingestion and the light reads.
+pub const DEFAULT_CONCURRENCY: usize = 2;
+
+/// The limits shared by every heavy route.
+#[derive(Debug, Clone)]
+pub struct RouteLimits {
+    timeout: Duration,
+    /// Shared by every route [`Self::limit`] wraps, so the limit covers
+    /// them together.
+    permits: Arc<Semaphore>,
+}
+
+impl RouteLimits {
+    pub fn new(timeout: Duration, concurrency: usize) -> Self {
+        Self {
+            timeout,
+            permits: Arc::new(Semaphore::new(concurrency)),
+        }
+    }
+
+    /// Heavy requests that could start now.
+    #[cfg(test)]
+    fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// `route` with the timeout and the shared concurrency limit applied.
+    pub fn limit<S>(&self, route: MethodRouter<S>) -> MethodRouter<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        route.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_limit_error))
+                .layer(TimeoutLayer::new(self.timeout))
+                .layer(LoadShedLayer::new())
+                .layer(GlobalConcurrencyLimitLayer::with_semaphore(Arc::clone(
+                    &self.permits,
+                ))),
+        )
+    }
+}
+
+impl Default for RouteLimits {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            DEFAULT_CONCURRENCY,
+        )
+    }
+}
+
+/// `408` for a heavy request that ran too long, `503` for one turned away
+/// because the others are still running.
+async fn handle_limit_error(err: tower::BoxError) -> Response {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        (StatusCode::REQUEST_TIMEOUT, "Request timed out").into_response()
+    } else if err.is::<tower::load_shed::error::Overloaded>() {
+        AppError::Overloaded(RETRY_AFTER_SECS).into_response()
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+    }
+}
+
+/// Runs `query` on the blocking pool, against storage pinned to one
+/// connection. If the returned future is dropped first, e.g. by a timeout,
+/// the query is interrupted.
+///
+/// # Errors
+///
+/// Returns `query`'s error, or [`AppError::Internal`] if no connection is
+/// available or the query panics.
+pub async fn with_storage<T, F>(storage: &SharedStorage, query: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn Storage) -> Result<T, AppError> + Send + 'static,
+{
+    let storage = Arc::clone(storage);
+    let interrupt = Arc::new(Interrupt::default());
+    let guard = InterruptOnDrop(Arc::clone(&interrupt));
+
+    let task = tokio::task::spawn_blocking(move || {
+        let mut query = Some(query);
+        let mut value = None;
+        storage.pinned(&interrupt, &mut |pinned| {
+            if let Some(query) = query.take() {
+                value = Some(query(pinned)?);
+            }
+            Ok(())
+        })?;
+        value.ok_or_else(|| AppError::Internal("Query did not run".to_string()))
+    });
+    let result = task.await;
+    guard.disarm();
+    result.map_err(|e| AppError::Internal(format!("Query task failed: {e}")))?
+}
+
+/// Interrupts the query still holding the callback when dropped.
+struct InterruptOnDrop(Arc<Interrupt>);
+
+impl InterruptOnDrop {
+    fn disarm(self) {
+        storage::lock_interrupt(&self.0).take();
+    }
+}
+
+impl Drop for InterruptOnDrop {
+    // The lock is held while interrupting, so the query can't finish and
+    // hand its connection to another request in between
+    #[allow(clippy::significant_drop_in_scrutinee)]
+    fn drop(&mut self) {
+        if let Some(interrupt) = storage::lock_interrupt(&self.0).take() {
+            interrupt();
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    /// An interrupt slot armed with a callback that sets the flag.
+    fn armed(flag: &Arc<AtomicBool>) -> Arc<Interrupt> {
+        let flag = Arc::clone(flag);
+        Arc::new(Interrupt::new(Some(Box::new(move || {
+            flag.store(true, Ordering::Relaxed);
+        }))))
+    }
+
+    #[test]
+    fn test_dropped_guard_interrupts() {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let interrupt = armed(&interrupted);
+        drop(InterruptOnDrop(Arc::clone(&interrupt)));
+        assert!(interrupted.load(Ordering::Relaxed));
+        assert!(storage::lock_interrupt(&interrupt).is_none());
+    }
+
+    #[test]
+    fn test_disarmed_guard_does_not_interrupt() {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let interrupt = armed(&interrupted);
+        InterruptOnDrop(Arc::clone(&interrupt)).disarm();
+        assert!(!interrupted.load(Ordering::Relaxed));
+        assert!(storage::lock_interrupt(&interrupt).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_busy_routes_are_shed() {
+        let limits = RouteLimits::new(Duration::from_secs(5), 1);
+        let release = Arc::new(tokio::sync::Notify::new());
+        let wait = Arc::clone(&release);
+        let app: Router = Router::new().route(
+            "/slow",
+            limits.limit(get(move || {
+                let wait = Arc::clone(&wait);
+                async move {
+                    wait.notified().await;
+                    "done"
+                }
+            })),
+        );
+        let request = || Request::get("/slow").body(Body::empty()).unwrap();
+
+        let first = tokio::spawn(app.clone().oneshot(request()));
+        while limits.available() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let shed = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(shed.headers()["retry-after"], "1");
+
+        release.notify_one();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        assert_eq!(limits.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_slow_route_times_out() {
+        let limits = RouteLimits::new(Duration::from_millis(20), 1);
+        let app: Router = Router::new().route(
+            "/slow",
+            limits.limit(get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "done"
+            })),
+        );
+        let response = app
+            .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(limits.available(), 1);
+    }
+}
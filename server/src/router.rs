@@ -1,35 +1,262 @@
 use axum::error_handling::HandleErrorLayer;
+use axum::extract::{DefaultBodyLimit, FromRef};
 use axum::http::StatusCode;
 use axum::routing::{delete, get, post};
 use axum::Router;
-use std::sync::atomic::AtomicU64;
+use chrono::{SecondsFormat, Utc};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 
+use crate::api_version::{self, ApiVersion};
 use crate::apns::ApnsClient;
-use crate::auth::{AuthFailureMap, KeyRateLimitMap};
-use crate::db::pool::DbPool;
+use crate::auth::{self, AuthFailureMap, KeyRateLimitMap, PreviousKeyUseMap};
+use crate::bus::EventBus;
+use crate::db::pool::PoolStats;
+use crate::db::queries::NotificationTtl;
+use crate::event_storage::EventStorage;
 use crate::handlers;
+use crate::health::{self, ServerHealth};
+use crate::ingest::notification::Severity;
+use crate::ingest_queue::IngestQueue;
+use crate::list_cache::ReadCache;
+use crate::models::response::SyncInfo;
 use crate::notif_dedup::NotifCooldownMap;
+use crate::notif_routing::NotificationRoutes;
+use crate::route_limits::RouteLimits;
+use crate::session_title::{TitleStrategy, DEFAULT_TITLE_TEMPLATE};
+use crate::storage::SharedStorage;
+use crate::unknown_fields::UnknownFieldMap;
 
 pub struct AppState {
-    pub master_key: String,
-    pub db_pool: DbPool,
+    pub auth: AuthState,
+    pub notif: NotifState,
+    pub retention: RetentionConfig,
+    /// URL clients reach this server at; embedded in enrollment QR payloads.
+    pub public_url: Option<String>,
+    pub storage: SharedStorage,
+    /// Checkouts from the storage's connection pool, for
+    /// `GET /admin/metrics`.
+    pub pool_stats: Arc<PoolStats>,
     pub version: AtomicU64,
     pub notification_version: AtomicU64,
     pub last_cleanup: AtomicU64,
-    pub apns_client: Option<Arc<ApnsClient>>,
-    pub retention_events_days: u64,
-    pub retention_sessions_days: u64,
-    pub retention_devices_days: u64,
-    pub auth_failures: Arc<AuthFailureMap>,
+    /// Unix time of the last maintenance pass, also stored in the metadata
+    /// table so a restart doesn't trigger one. Starts at the current time
+    /// when none was stored.
+    pub last_maintenance: AtomicU64,
+    /// How much of each event type's JSON is stored.
+    pub event_storage: EventStorage,
+    pub session_title_strategy: TitleStrategy,
+    pub session_title_template: String,
+    /// Drop prompts and messages from every event at ingest.
+    pub privacy_mode: bool,
+    /// Oldest hook version supported. Reported by ping and on every
+    /// `POST /api/v1/events` response; devices running older hooks are
+    /// flagged and notified about.
+    pub min_hook_version: String,
+    /// Every ingested event, for delivery subsystems to subscribe to.
+    pub event_bus: EventBus,
+    /// Bounds concurrent event ingestion; see [`IngestQueue`].
+    pub ingest_queue: IngestQueue,
+    /// Record event fields the server doesn't know; see
+    /// [`unknown_fields`](crate::unknown_fields).
+    pub strict_fields: bool,
+    pub unknown_fields: UnknownFieldMap,
+    /// `5xx` and push failure rates behind `GET /api/v1/status`.
+    pub health: Arc<ServerHealth>,
+    /// Device and session lists served without querying; see
+    /// [`list_cache`](crate::list_cache).
+    pub list_cache: Arc<ReadCache>,
+    /// Timeout and concurrency limit of the heavy read routes; see
+    /// [`route_limits`](crate::route_limits).
+    pub route_limits: RouteLimits,
+}
+
+impl AppState {
+    /// State with every setting at its config default and nothing configured
+    /// beyond the master key. Callers override fields as needed.
+    pub fn new(master_key: impl Into<String>, storage: SharedStorage) -> Self {
+        Self {
+            auth: AuthState::new(master_key),
+            notif: NotifState::default(),
+            retention: RetentionConfig::default(),
+            public_url: None,
+            storage,
+            pool_stats: Arc::default(),
+            version: AtomicU64::new(0),
+            notification_version: AtomicU64::new(0),
+            last_cleanup: AtomicU64::new(0),
+            #[allow(clippy::cast_sign_loss)]
+            last_maintenance: AtomicU64::new(Utc::now().timestamp() as u64),
+            event_storage: EventStorage::default(),
+            session_title_strategy: TitleStrategy::FirstPrompt,
+            session_title_template: DEFAULT_TITLE_TEMPLATE.to_string(),
+            privacy_mode: false,
+            min_hook_version: DEFAULT_MIN_HOOK_VERSION.to_string(),
+            event_bus: EventBus::default(),
+            ingest_queue: IngestQueue::default(),
+            strict_fields: false,
+            unknown_fields: UnknownFieldMap::default(),
+            health: Arc::default(),
+            list_cache: Arc::default(),
+            route_limits: RouteLimits::default(),
+        }
+    }
+
+    /// The current [`SyncInfo`]. Take it before querying, so a change racing
+    /// the queries bumps the versions past the ones returned and the client
+    /// fetches again.
+    pub fn sync_info(&self) -> SyncInfo {
+        SyncInfo {
+            server_time: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            data_version: self.version.load(Ordering::Relaxed),
+            notification_version: self.notification_version.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Keys accepted by the server and per-client auth bookkeeping.
+#[derive(Clone)]
+pub struct AuthState {
+    pub master_key: String,
+    /// Accepted alongside `master_key` while clients move to a new key.
+    pub previous_master_key: Option<String>,
+    pub failures: Arc<AuthFailureMap>,
     pub key_rate_limits: Arc<KeyRateLimitMap>,
-    pub notif_cooldown: Arc<NotifCooldownMap>,
+    pub previous_key_uses: Arc<PreviousKeyUseMap>,
+}
+
+impl AuthState {
+    pub fn new(master_key: impl Into<String>) -> Self {
+        Self {
+            master_key: master_key.into(),
+            previous_master_key: None,
+            failures: Arc::default(),
+            key_rate_limits: Arc::default(),
+            previous_key_uses: Arc::default(),
+        }
+    }
+}
+
+impl FromRef<Arc<AppState>> for AuthState {
+    fn from_ref(state: &Arc<AppState>) -> Self {
+        state.auth.clone()
+    }
+}
+
+/// How notifications are kept and delivered.
+#[derive(Clone, Default)]
+pub struct NotifState {
+    pub apns_client: Option<Arc<ApnsClient>>,
+    pub ttl: NotificationTtl,
+    pub routes: NotificationRoutes,
+    pub cooldown: Arc<NotifCooldownMap>,
+    /// Least severe failure or interruption that notifies.
+    pub error_min_severity: Severity,
+}
+
+impl FromRef<Arc<AppState>> for NotifState {
+    fn from_ref(state: &Arc<AppState>) -> Self {
+        state.notif.clone()
+    }
 }
 
+/// Retention windows and maintenance cadence for the periodic cleanup pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionConfig {
+    pub events_days: u64,
+    pub sessions_days: u64,
+    pub devices_days: u64,
+    /// Push tokens with no accepted push or registration for this long are
+    /// deleted.
+    pub push_tokens_days: u64,
+    pub maintenance_interval_hours: u64,
+    /// Move expired events into `events_archive` instead of deleting them.
+    pub archive_events: bool,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            events_days: 7,
+            sessions_days: 7,
+            devices_days: 30,
+            push_tokens_days: 30,
+            maintenance_interval_hours: 24,
+            archive_events: false,
+        }
+    }
+}
+
+impl FromRef<Arc<AppState>> for RetentionConfig {
+    fn from_ref(state: &Arc<AppState>) -> Self {
+        state.retention
+    }
+}
+
+/// Largest request body accepted, in bytes (axum's default, made explicit so
+/// ping can report it).
+pub const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Default for `--min-hook-version`, the oldest `claudiator-hook` version
+/// this server supports. Raise it when the server starts relying on
+/// something older hooks don't send.
+pub const DEFAULT_MIN_HOOK_VERSION: &str = "0.1.0";
+
+/// Every route served by [`build_router`], as `"METHOD /path"`. Reported by
+/// ping; keep in sync when adding routes.
+///
+/// Client routes are listed under `/api/v1` and also served under every other
+/// prefix in [`ApiVersion::ALL`].
+pub const ENDPOINTS: &[&str] = &[
+    "GET /api/v1/ping",
+    "GET /api/v1/status",
+    "GET /api/v1/schema/event",
+    "POST /api/v1/events",
+    "POST /api/v1/hooks/http",
+    "POST /api/v1/ingest/generic",
+    "GET /api/v1/devices",
+    "GET /api/v1/devices/conflicts",
+    "POST /api/v1/devices/register",
+    "GET /api/v1/devices/:device_id/sessions",
+    "GET /api/v1/overview",
+    "GET /api/v1/sessions",
+    "GET /api/v1/sessions/:session_id",
+    "PATCH /api/v1/sessions/:session_id",
+    "GET /api/v1/sessions/:session_id/events",
+    "GET /api/v1/sessions/:session_id/export",
+    "GET /api/v1/sessions/:session_id/notifications",
+    "GET /api/v1/sessions/:session_id/responses/pending",
+    "GET /api/v1/events/:id",
+    "GET /api/v1/search",
+    "POST /api/v1/push/register",
+    "GET /api/v1/notifications",
+    "POST /api/v1/notifications/ack",
+    "POST /api/v1/notifications/:id/respond",
+    "POST /api/v1/notifications/:id/resend",
+    "POST /api/v1/notifications/:id/snooze",
+    "POST /admin/api-keys",
+    "POST /admin/enrollment-codes",
+    "GET /admin/enrollment-qr",
+    "GET /admin/delivery-failures",
+    "POST /admin/delivery-failures/:id/retry",
+    "GET /admin/api-keys",
+    "DELETE /admin/api-keys/:id",
+    "GET /admin/api-keys/:id/usage",
+    "GET /admin/previous-key-clients",
+    "GET /admin/cooldowns",
+    "DELETE /admin/cooldowns/:session_id",
+    "GET /admin/unknown-fields",
+    "GET /admin/metrics",
+    "POST /admin/maintenance",
+    "POST /admin/maintenance/vacuum",
+    "GET /admin/events-archive/export",
+];
+
 /// Converts a tower timeout error into an HTTP 408 Request Timeout response.
 async fn handle_timeout_error(err: tower::BoxError) -> (StatusCode, &'static str) {
     if err.is::<tower::timeout::error::Elapsed>() {
@@ -39,8 +266,9 @@ async fn handle_timeout_error(err: tower::BoxError) -> (StatusCode, &'static str
     }
 }
 
-pub fn build_router(state: Arc<AppState>) -> Router {
-    let admin_router = Router::new()
+/// Routes nested under `/admin`; every handler takes [`auth::AdminAuth`].
+fn admin_router(limits: &RouteLimits) -> Router<Arc<AppState>> {
+    Router::new()
         .route(
             "/api-keys",
             post(handlers::admin::create_api_key_handler)
@@ -49,49 +277,175 @@ pub fn build_router(state: Arc<AppState>) -> Router {
         .route(
             "/api-keys/:id",
             delete(handlers::admin::delete_api_key_handler),
-        );
+        )
+        .route(
+            "/api-keys/:id/usage",
+            get(handlers::admin::api_key_usage_handler),
+        )
+        .route(
+            "/enrollment-codes",
+            post(handlers::admin::create_enrollment_code_handler),
+        )
+        .route(
+            "/enrollment-qr",
+            get(handlers::admin::enrollment_qr_handler),
+        )
+        .route(
+            "/delivery-failures",
+            get(handlers::admin::list_delivery_failures_handler),
+        )
+        .route(
+            "/delivery-failures/:id/retry",
+            post(handlers::admin::retry_delivery_failure_handler),
+        )
+        .route(
+            "/previous-key-clients",
+            get(handlers::admin::previous_key_clients_handler),
+        )
+        .route("/cooldowns", get(handlers::admin::list_cooldowns_handler))
+        .route(
+            "/cooldowns/:session_id",
+            delete(handlers::admin::clear_cooldowns_handler),
+        )
+        .route("/metrics", get(handlers::admin::metrics_handler))
+        .route(
+            "/unknown-fields",
+            get(handlers::admin::unknown_fields_handler),
+        )
+        .route(
+            "/maintenance",
+            post(handlers::admin::run_maintenance_handler),
+        )
+        .route(
+            "/events-archive/export",
+            limits.limit(get(handlers::admin::export_archived_events_handler)),
+        )
+}
 
+/// Routes exempt from the 30-second request timeout: admin work that takes
+/// as long as the database is large and can't be cut short midway.
+fn untimed_router() -> Router<Arc<AppState>> {
+    Router::new().route(
+        "/admin/maintenance/vacuum",
+        post(handlers::admin::run_full_vacuum_handler),
+    )
+}
+
+/// Client routes, nested under each version's prefix by [`build_router`].
+/// Handlers branch on the [`ApiVersion`] extractor where versions differ.
+/// Export, search, and the overview counts are limited by `limits`.
+fn api_router(limits: &RouteLimits) -> Router<Arc<AppState>> {
     Router::new()
-        .route("/api/v1/ping", get(handlers::ping::ping_handler))
-        .route("/api/v1/events", post(handlers::events::events_handler))
+        .route("/ping", get(handlers::ping::ping_handler))
+        .route("/status", get(handlers::ping::status_handler))
+        .route("/schema/event", get(handlers::ping::event_schema_handler))
+        .route("/events", post(handlers::events::events_handler))
+        .route("/hooks/http", post(handlers::events::http_hook_handler))
         .route(
-            "/api/v1/hooks/http",
-            post(handlers::events::http_hook_handler),
+            "/ingest/generic",
+            post(handlers::events::generic_ingest_handler),
         )
+        .route("/devices", get(handlers::devices::list_devices_handler))
         .route(
-            "/api/v1/devices",
-            get(handlers::devices::list_devices_handler),
+            "/devices/conflicts",
+            get(handlers::devices::list_device_conflicts_handler),
         )
         .route(
-            "/api/v1/devices/:device_id/sessions",
+            "/devices/register",
+            post(handlers::devices::register_device_handler),
+        )
+        .route(
+            "/devices/:device_id/sessions",
             get(handlers::devices::list_device_sessions_handler),
         )
         .route(
-            "/api/v1/sessions",
+            "/overview",
+            limits.limit(get(handlers::sessions::overview_handler)),
+        )
+        .route(
+            "/sessions",
             get(handlers::sessions::list_all_sessions_handler),
         )
         .route(
-            "/api/v1/sessions/:session_id/events",
+            "/sessions/:session_id",
+            get(handlers::sessions::get_session_handler)
+                .patch(handlers::sessions::update_session_handler),
+        )
+        .route(
+            "/sessions/:session_id/events",
             get(handlers::sessions::list_session_events_handler),
         )
         .route(
-            "/api/v1/push/register",
+            "/sessions/:session_id/export",
+            limits.limit(get(handlers::sessions::export_session_handler)),
+        )
+        .route(
+            "/sessions/:session_id/notifications",
+            get(handlers::notifications::list_session_notifications_handler),
+        )
+        .route(
+            "/sessions/:session_id/responses/pending",
+            get(handlers::sessions::pending_responses_handler),
+        )
+        .route("/events/:id", get(handlers::sessions::get_event_handler))
+        .route(
+            "/search",
+            limits.limit(get(handlers::search::search_handler)),
+        )
+        .route(
+            "/push/register",
             post(handlers::push::push_register_handler),
         )
         .route(
-            "/api/v1/notifications",
+            "/notifications",
             get(handlers::notifications::list_notifications_handler),
         )
         .route(
-            "/api/v1/notifications/ack",
+            "/notifications/ack",
             post(handlers::notifications::acknowledge_notifications_handler),
         )
-        .nest("/admin", admin_router)
-        .layer(
-            ServiceBuilder::new()
-                .layer(HandleErrorLayer::new(handle_timeout_error))
-                .layer(tower::timeout::TimeoutLayer::new(Duration::from_secs(30)))
-                .layer(TraceLayer::new_for_http()),
+        .route(
+            "/notifications/:id/respond",
+            post(handlers::notifications::respond_to_notification_handler),
+        )
+        .route(
+            "/notifications/:id/resend",
+            post(handlers::notifications::resend_notification_handler),
         )
+        .route(
+            "/notifications/:id/snooze",
+            post(handlers::notifications::snooze_notification_handler),
+        )
+}
+
+pub fn build_router(state: Arc<AppState>) -> Router {
+    let limits = &state.route_limits;
+    let mut router = Router::new();
+    for &version in ApiVersion::ALL {
+        router = router.nest(
+            version.prefix(),
+            api_router(limits).layer(axum::middleware::from_fn_with_state(
+                version,
+                api_version::negotiate,
+            )),
+        );
+    }
+
+    let timed = router.nest("/admin", admin_router(limits)).layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_timeout_error))
+            .layer(tower::timeout::TimeoutLayer::new(Duration::from_secs(30))),
+    );
+
+    Router::new()
+        .merge(timed)
+        .merge(untimed_router())
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .layer(axum::middleware::from_fn(auth::rate_limit_headers))
+        .layer(TraceLayer::new_for_http())
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::clone(&state.health),
+            health::track_responses,
+        ))
         .with_state(state)
 }
@@ -0,0 +1,327 @@
+//! Markdown report of a session's timeline, served by
+//! `GET /api/v1/sessions/:session_id/export`.
+//!
+//! Prompts start a new section; tool uses, permission requests,
+//! notifications, and stops are listed under the prompt they followed.
+//! `PostToolUse` is left out because it repeats the `PreToolUse` before it.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::DateTime;
+
+use crate::models::request::HookEventKind;
+use crate::models::response::{SessionResponse, TimelineEvent};
+use crate::utils::truncate_at_char_boundary;
+
+/// Most events included in one export; later events are omitted.
+pub const MAX_EXPORT_EVENTS: usize = 5000;
+
+/// Longest notification message shown on a timeline line, in bytes.
+const MAX_MESSAGE_LEN: usize = 300;
+
+/// Renders `events` (oldest first) as a Markdown report. `truncated` notes
+/// that the session had more events than were passed in.
+pub fn render_markdown(
+    session: &SessionResponse,
+    events: &[TimelineEvent],
+    truncated: bool,
+) -> String {
+    let mut out = String::new();
+    let heading = session
+        .title
+        .as_deref()
+        .map_or_else(|| format!("Session {}", session.session_id), single_line);
+    let _ = writeln!(out, "# {heading}\n");
+
+    let _ = writeln!(out, "- **Session:** {}", code(&session.session_id));
+    match session.device_name {
+        Some(ref name) => {
+            let _ = writeln!(
+                out,
+                "- **Device:** {} ({})",
+                single_line(name),
+                code(&session.device_id)
+            );
+        }
+        None => {
+            let _ = writeln!(out, "- **Device:** {}", code(&session.device_id));
+        }
+    }
+    if let Some(ref cwd) = session.cwd {
+        let _ = writeln!(out, "- **Directory:** {}", code(cwd));
+    }
+    if let Some(ref model) = session.model {
+        let _ = writeln!(out, "- **Model:** {}", code(model));
+    }
+    let _ = writeln!(out, "- **Started:** {}", full_time(&session.started_at));
+    let _ = writeln!(out, "- **Last event:** {}", full_time(&session.last_event));
+    let _ = writeln!(out, "- **Status:** {}", session.status);
+
+    write_summary(&mut out, events);
+
+    out.push_str("\n## Timeline\n");
+    if events.is_empty() {
+        out.push_str("\nNo events recorded.\n");
+    }
+    let mut in_list = false;
+    for event in events {
+        let kind = HookEventKind::from(event.hook_event_name.clone());
+        if kind == HookEventKind::UserPromptSubmit {
+            let _ = writeln!(out, "\n### Prompt · {}\n", full_time(&event.timestamp));
+            let prompt = event
+                .prompt
+                .as_deref()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .unwrap_or("(empty prompt)");
+            for line in prompt.lines() {
+                let _ = writeln!(out, "> {line}");
+            }
+            in_list = false;
+            continue;
+        }
+
+        let Some(line) = describe(&kind, event) else {
+            continue;
+        };
+        if !in_list {
+            out.push('\n');
+            in_list = true;
+        }
+        let _ = writeln!(out, "- {} {line}", code(&clock_time(&event.timestamp)));
+    }
+
+    if truncated {
+        let _ = writeln!(
+            out,
+            "\n_Only the first {MAX_EXPORT_EVENTS} events are included._"
+        );
+    }
+    out
+}
+
+fn write_summary(out: &mut String, events: &[TimelineEvent]) {
+    let mut prompts = 0;
+    let mut stops = 0;
+    let mut tools: BTreeMap<&str, usize> = BTreeMap::new();
+    for event in events {
+        match event.hook_event_name.as_str() {
+            "UserPromptSubmit" => prompts += 1,
+            "Stop" => stops += 1,
+            "PreToolUse" => {
+                *tools
+                    .entry(event.tool_name.as_deref().unwrap_or("unknown"))
+                    .or_default() += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let tool_uses: usize = tools.values().sum();
+    let mut by_count: Vec<(&str, usize)> = tools.into_iter().collect();
+    by_count.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    out.push_str("\n## Summary\n\n");
+    let _ = writeln!(out, "- Prompts: {prompts}");
+    if by_count.is_empty() {
+        let _ = writeln!(out, "- Tool uses: {tool_uses}");
+    } else {
+        let breakdown: Vec<String> = by_count
+            .iter()
+            .map(|(tool, count)| format!("{} ×{count}", code(tool)))
+            .collect();
+        let _ = writeln!(out, "- Tool uses: {tool_uses} ({})", breakdown.join(", "));
+    }
+    let _ = writeln!(out, "- Stops: {stops}");
+}
+
+/// One timeline line for a non-prompt event, or `None` to leave it out.
+fn describe(kind: &HookEventKind, event: &TimelineEvent) -> Option<String> {
+    let tool = || code(event.tool_name.as_deref().unwrap_or("unknown"));
+    let message = || {
+        event
+            .message
+            .as_deref()
+            .map(|m| truncate_at_char_boundary(&single_line(m), MAX_MESSAGE_LEN))
+    };
+
+    let line = match kind {
+        HookEventKind::PreToolUse => format!("Used {}", tool()),
+        HookEventKind::PostToolUseFailure if event.is_interrupt == Some(true) => {
+            format!("Interrupted {}", tool())
+        }
+        HookEventKind::PostToolUseFailure => labelled(
+            &format!("{} failed", tool()),
+            event
+                .error
+                .as_deref()
+                .map(|e| truncate_at_char_boundary(&single_line(e), MAX_MESSAGE_LEN)),
+        ),
+        HookEventKind::PermissionRequest => format!("Asked permission to use {}", tool()),
+        HookEventKind::Notification => labelled("Notification", message()),
+        HookEventKind::Stop => "Stopped, waiting for input".to_string(),
+        HookEventKind::SessionStart => "Session started".to_string(),
+        HookEventKind::SessionEnd => "Session ended".to_string(),
+        HookEventKind::SubagentStart => "Subagent started".to_string(),
+        HookEventKind::SubagentStop => "Subagent finished".to_string(),
+        HookEventKind::PreCompact => match event.trigger.as_deref() {
+            Some("auto") => "Conversation compacted automatically".to_string(),
+            _ => "Conversation compacted".to_string(),
+        },
+        HookEventKind::TeammateIdle => "Teammate went idle".to_string(),
+        HookEventKind::TaskCompleted => labelled("Task completed", message()),
+        HookEventKind::HookCrash => labelled("Hook crashed", message()),
+        HookEventKind::External => {
+            let title = event
+                .title
+                .as_deref()
+                .map_or_else(|| "External event".to_string(), single_line);
+            labelled(&title, message())
+        }
+        HookEventKind::PostToolUse
+        | HookEventKind::UserPromptSubmit
+        | HookEventKind::TestNotification
+        | HookEventKind::Heartbeat => return None,
+        HookEventKind::Other(name) => code(name),
+    };
+    Some(line)
+}
+
+/// `label`, followed by `detail` when there is one.
+fn labelled(label: &str, detail: Option<String>) -> String {
+    detail.map_or_else(|| label.to_string(), |detail| format!("{label}: {detail}"))
+}
+
+/// `value` as inline code. Backticks inside would end the span early, so
+/// they are replaced.
+fn code(value: &str) -> String {
+    format!("`{}`", single_line(value).replace('`', "'"))
+}
+
+fn single_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `2024-01-01 10:00:00 UTC`, or the stored value if it isn't RFC 3339.
+fn full_time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp).map_or_else(
+        |_| timestamp.to_string(),
+        |time| time.to_utc().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+    )
+}
+
+/// `10:00:00` (UTC), or the stored value if it isn't RFC 3339.
+fn clock_time(timestamp: &str) -> String {
+    DateTime::parse_from_rfc3339(timestamp).map_or_else(
+        |_| timestamp.to_string(),
+        |time| time.to_utc().format("%H:%M:%S").to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> SessionResponse {
+        SessionResponse {
+            session_id: "s1".to_string(),
+            device_id: "dev-1".to_string(),
+            started_at: "2024-01-01T10:00:00Z".to_string(),
+            last_event: "2024-01-01T10:05:00Z".to_string(),
+            status: "waiting_for_input".to_string(),
+            cwd: Some("/home/u/project".to_string()),
+            title: Some("Fix the\nlogin bug".to_string()),
+            device_name: Some("Laptop".to_string()),
+            platform: Some("mac".to_string()),
+            pinned: false,
+            parent_session_id: None,
+            agent_type: None,
+            team_name: None,
+            model: Some("claude-sonnet-4-5".to_string()),
+            subagents: Vec::new(),
+        }
+    }
+
+    fn event(name: &str, seconds: u32) -> TimelineEvent {
+        TimelineEvent {
+            hook_event_name: name.to_string(),
+            timestamp: format!("2024-01-01T10:00:{seconds:02}Z"),
+            tool_name: None,
+            message: None,
+            prompt: None,
+            title: None,
+            trigger: None,
+            error: None,
+            is_interrupt: None,
+        }
+    }
+
+    fn tool(name: &str, seconds: u32, tool: &str) -> TimelineEvent {
+        TimelineEvent {
+            tool_name: Some(tool.to_string()),
+            ..event(name, seconds)
+        }
+    }
+
+    #[test]
+    fn test_render_markdown_groups_events_under_prompts() {
+        let events = vec![
+            event("SessionStart", 0),
+            TimelineEvent {
+                prompt: Some("Fix the login bug\nin auth.rs".to_string()),
+                ..event("UserPromptSubmit", 1)
+            },
+            tool("PreToolUse", 2, "Read"),
+            tool("PostToolUse", 3, "Read"),
+            tool("PreToolUse", 4, "Edit"),
+            tool("PreToolUse", 5, "Edit"),
+            TimelineEvent {
+                message: Some("Claude needs your permission".to_string()),
+                ..event("Notification", 6)
+            },
+            event("Stop", 7),
+        ];
+
+        let markdown = render_markdown(&session(), &events, false);
+
+        assert!(markdown.starts_with("# Fix the login bug\n"));
+        assert!(markdown.contains("- **Device:** Laptop (`dev-1`)"));
+        assert!(markdown.contains("- **Directory:** `/home/u/project`"));
+        assert!(markdown.contains("- **Model:** `claude-sonnet-4-5`"));
+        assert!(markdown.contains("- **Started:** 2024-01-01 10:00:00 UTC"));
+        assert!(markdown.contains("- Prompts: 1"));
+        assert!(markdown.contains("- Tool uses: 3 (`Edit` ×2, `Read` ×1)"));
+        assert!(markdown.contains("- Stops: 1"));
+        assert!(markdown.contains(
+            "- `10:00:00` Session started\n\n### Prompt · 2024-01-01 10:00:01 UTC\n\n> Fix the login bug\n> in auth.rs\n\n- `10:00:02` Used `Read`\n- `10:00:04` Used `Edit`"
+        ));
+        assert!(markdown.contains("- `10:00:06` Notification: Claude needs your permission"));
+        assert!(markdown.contains("- `10:00:07` Stopped, waiting for input"));
+        assert!(!markdown.contains("10:00:03"));
+        assert!(!markdown.contains("Only the first"));
+    }
+
+    #[test]
+    fn test_render_markdown_without_events() {
+        let session = SessionResponse {
+            title: None,
+            device_name: None,
+            cwd: None,
+            ..session()
+        };
+        let markdown = render_markdown(&session, &[], true);
+
+        assert!(markdown.starts_with("# Session s1\n"));
+        assert!(markdown.contains("- **Device:** `dev-1`\n"));
+        assert!(!markdown.contains("**Directory:**"));
+        assert!(markdown.contains("- Tool uses: 0\n"));
+        assert!(markdown.contains("No events recorded."));
+        assert!(markdown.contains("_Only the first 5000 events are included._"));
+    }
+
+    #[test]
+    fn test_code_replaces_backticks() {
+        assert_eq!(code("a`b"), "`a'b`");
+    }
+}
@@ -0,0 +1,262 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use claudiator_types::is_e2e_sealed;
+
+use crate::utils::truncate_at_char_boundary;
+
+/// Maximum stored title length in bytes (before the ellipsis).
+const MAX_TITLE_LEN: usize = 200;
+
+#[allow(clippy::literal_string_with_formatting_args)]
+const DIR_PLACEHOLDER: &str = "{dir}";
+#[allow(clippy::literal_string_with_formatting_args)]
+const PROMPT_PLACEHOLDER: &str = "{prompt}";
+
+/// Template used when `--session-title-template` is not set.
+#[allow(clippy::literal_string_with_formatting_args)]
+pub const DEFAULT_TITLE_TEMPLATE: &str = "{dir}: {prompt}";
+
+/// How a session's title is derived from incoming events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TitleStrategy {
+    /// First `UserPromptSubmit` prompt; never changes afterwards.
+    FirstPrompt,
+    /// Most recent `UserPromptSubmit` prompt.
+    LatestPrompt,
+    /// Last path component of the session's working directory.
+    Directory,
+    /// The configured template, re-rendered on every prompt.
+    Template,
+}
+
+impl TitleStrategy {
+    /// Whether a newly derived title should overwrite one already stored.
+    pub const fn replaces_existing(self) -> bool {
+        matches!(self, Self::LatestPrompt | Self::Template)
+    }
+}
+
+/// Derives a session title for a single event, or `None` if this event
+/// should not set one.
+///
+/// Templates support `{dir}` and `{prompt}` placeholders. When the event has
+/// no `cwd`, the template falls back to the prompt alone.
+///
+/// A prompt the hook sealed end to end is used whole for every strategy but
+/// [`TitleStrategy::Directory`]: it can't be cut short or placed in a
+/// template and still be opened by the app.
+pub fn derive_title(
+    strategy: TitleStrategy,
+    template: &str,
+    hook_event_name: &str,
+    prompt: Option<&str>,
+    cwd: Option<&str>,
+) -> Option<String> {
+    let prompt = prompt
+        .filter(|_| hook_event_name == "UserPromptSubmit")
+        .map(str::trim)
+        .filter(|p| !p.is_empty());
+    let dir = cwd.and_then(directory_name);
+    if let Some(sealed) = prompt.filter(|p| is_e2e_sealed(p)) {
+        if strategy != TitleStrategy::Directory {
+            return Some(sealed.to_string());
+        }
+    }
+
+    let title = match strategy {
+        TitleStrategy::FirstPrompt | TitleStrategy::LatestPrompt => prompt?.to_string(),
+        TitleStrategy::Directory => dir?.to_string(),
+        TitleStrategy::Template => {
+            let prompt = prompt?;
+            dir.map_or_else(
+                || prompt.to_string(),
+                |dir| render_template(template, dir, prompt),
+            )
+        }
+    };
+
+    Some(truncate_at_char_boundary(&title, MAX_TITLE_LEN))
+}
+
+/// `template` with its placeholders filled in one pass, so a directory or
+/// prompt that itself contains a placeholder is kept as written.
+fn render_template(template: &str, dir: &str, prompt: &str) -> String {
+    let mut rendered = String::with_capacity(template.len() + dir.len() + prompt.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix(DIR_PLACEHOLDER) {
+            rendered.push_str(dir);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(PROMPT_PLACEHOLDER) {
+            rendered.push_str(prompt);
+            rest = after;
+        } else {
+            rendered.push('{');
+            rest = &rest[1..];
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn directory_name(cwd: &str) -> Option<&str> {
+    Path::new(cwd.trim_end_matches(['/', '\\']))
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "{dir}: {prompt}";
+
+    #[test]
+    fn test_first_prompt_uses_prompt_only_on_user_prompt_submit() {
+        let title = derive_title(
+            TitleStrategy::FirstPrompt,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some("Fix the login bug"),
+            Some("/home/u/app"),
+        );
+        assert_eq!(title.as_deref(), Some("Fix the login bug"));
+
+        let title = derive_title(
+            TitleStrategy::FirstPrompt,
+            TEMPLATE,
+            "Stop",
+            Some("Fix the login bug"),
+            Some("/home/u/app"),
+        );
+        assert_eq!(title, None);
+    }
+
+    #[test]
+    fn test_sealed_prompt_is_kept_whole() {
+        let sealed = format!("e2e:v1:{}", "A".repeat(300));
+        for strategy in [
+            TitleStrategy::FirstPrompt,
+            TitleStrategy::LatestPrompt,
+            TitleStrategy::Template,
+        ] {
+            let title = derive_title(
+                strategy,
+                TEMPLATE,
+                "UserPromptSubmit",
+                Some(&sealed),
+                Some("/home/u/app"),
+            );
+            assert_eq!(title.as_deref(), Some(sealed.as_str()));
+        }
+        let title = derive_title(
+            TitleStrategy::Directory,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some(&sealed),
+            Some("/home/u/app"),
+        );
+        assert_eq!(title.as_deref(), Some("app"));
+    }
+
+    #[test]
+    fn test_directory_uses_last_path_component() {
+        let title = derive_title(
+            TitleStrategy::Directory,
+            TEMPLATE,
+            "SessionStart",
+            None,
+            Some("/home/u/claudiator/"),
+        );
+        assert_eq!(title.as_deref(), Some("claudiator"));
+    }
+
+    #[test]
+    fn test_directory_without_cwd_is_none() {
+        let title = derive_title(
+            TitleStrategy::Directory,
+            TEMPLATE,
+            "SessionStart",
+            None,
+            None,
+        );
+        assert_eq!(title, None);
+        let title = derive_title(TitleStrategy::Directory, TEMPLATE, "Stop", None, Some("/"));
+        assert_eq!(title, None);
+    }
+
+    #[test]
+    fn test_template_renders_placeholders() {
+        let title = derive_title(
+            TitleStrategy::Template,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some("Add tests"),
+            Some("/home/u/claudiator"),
+        );
+        assert_eq!(title.as_deref(), Some("claudiator: Add tests"));
+    }
+
+    #[test]
+    fn test_template_keeps_placeholders_in_substituted_values() {
+        let title = derive_title(
+            TitleStrategy::Template,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some("Rename {dir}"),
+            Some("/home/u/{prompt}"),
+        );
+        assert_eq!(title.as_deref(), Some("{prompt}: Rename {dir}"));
+    }
+
+    #[test]
+    fn test_template_without_cwd_falls_back_to_prompt() {
+        let title = derive_title(
+            TitleStrategy::Template,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some("Add tests"),
+            None,
+        );
+        assert_eq!(title.as_deref(), Some("Add tests"));
+    }
+
+    #[test]
+    fn test_blank_prompt_is_ignored() {
+        let title = derive_title(
+            TitleStrategy::LatestPrompt,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some("   "),
+            None,
+        );
+        assert_eq!(title, None);
+    }
+
+    #[test]
+    fn test_title_is_truncated() {
+        let long = "x".repeat(500);
+        let title = derive_title(
+            TitleStrategy::LatestPrompt,
+            TEMPLATE,
+            "UserPromptSubmit",
+            Some(&long),
+            None,
+        )
+        .unwrap_or_default();
+        assert!(title.ends_with('…'));
+        assert_eq!(title.len(), MAX_TITLE_LEN + '…'.len_utf8());
+    }
+
+    #[test]
+    fn test_replaces_existing() {
+        assert!(!TitleStrategy::FirstPrompt.replaces_existing());
+        assert!(!TitleStrategy::Directory.replaces_existing());
+        assert!(TitleStrategy::LatestPrompt.replaces_existing());
+        assert!(TitleStrategy::Template.replaces_existing());
+    }
+}
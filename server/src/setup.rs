@@ -0,0 +1,318 @@
+//! `claudiator-server setup`: one-step bootstrap for a new server.
+//!
+//! Generates a master key, writes it with the listen settings to an
+//! environment file (the format `start-server.sh` and systemd's
+//! `EnvironmentFile` read), creates the database and runs migrations, and
+//! optionally creates scoped keys for the hook and the app. The printed
+//! summary ends with a snippet for the hook's `config.toml`.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{SecondsFormat, Utc};
+
+use crate::auth::generate_api_key;
+use crate::db::encryption::FieldCipher;
+use crate::db::{migrations, pool};
+use crate::storage::{SqliteStorage, Storage};
+
+/// Arguments of the `setup` subcommand.
+#[derive(Debug, clap::Args)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct SetupArgs {
+    /// Environment file to write the server settings to.
+    #[arg(long, default_value = ".env")]
+    pub env_file: PathBuf,
+    /// Replace `--env-file` if it already exists.
+    #[arg(long)]
+    pub force: bool,
+    /// Database file to create or migrate.
+    #[arg(long, default_value = "claudiator.db")]
+    pub db_path: String,
+    #[arg(long, default_value = "3000")]
+    pub port: u16,
+    #[arg(long, default_value = "0.0.0.0")]
+    pub bind: String,
+    /// URL clients reach this server at. Written as `CLAUDIATOR_PUBLIC_URL`
+    /// and used in the hook snippet; defaults to `http://localhost:<port>`.
+    #[arg(long)]
+    pub public_url: Option<String>,
+    /// Also create a write-scoped key for the hook.
+    #[arg(long)]
+    pub hook_key: bool,
+    /// Also create a read-scoped key for the app.
+    #[arg(long)]
+    pub app_key: bool,
+    /// Also generate a key encrypting prompts, event JSON, and session titles
+    /// at rest.
+    #[arg(long)]
+    pub encrypt: bool,
+}
+
+/// What [`run`] created.
+#[derive(Debug)]
+pub struct Setup {
+    pub env_file: PathBuf,
+    pub db_path: String,
+    pub server_url: String,
+    pub master_key: String,
+    pub hook_key: Option<String>,
+    pub app_key: Option<String>,
+    pub encryption_key: Option<String>,
+}
+
+/// Bootstrap a server as described by `args`.
+///
+/// # Errors
+///
+/// Fails without writing anything if the environment file exists and
+/// `--force` was not given, since replacing it discards the master key in
+/// it. Also fails if the database can't be opened or migrated, or a file
+/// can't be written.
+pub fn run(args: &SetupArgs) -> Result<Setup, Box<dyn Error>> {
+    if args.env_file.exists() && !args.force {
+        return Err(format!(
+            "{} already exists; pass --force to replace it",
+            args.env_file.display()
+        )
+        .into());
+    }
+
+    let db_pool = pool::create_pool(&args.db_path)?;
+    migrations::run(&db_pool)?;
+    let storage = SqliteStorage::new(db_pool);
+    let create_key = |name: &str, scopes: &str| -> Result<String, Box<dyn Error>> {
+        let key = generate_api_key();
+        let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let id = uuid::Uuid::new_v4().to_string();
+        storage
+            .insert_api_key(&id, name, &key, scopes, &created_at, None, None)
+            .map_err(|e| format!("Failed to create {name} key: {e:?}"))?;
+        Ok(key)
+    };
+    let hook_key = args
+        .hook_key
+        .then(|| create_key("hook", "write"))
+        .transpose()?;
+    let app_key = args
+        .app_key
+        .then(|| create_key("app", "read"))
+        .transpose()?;
+
+    let master_key = generate_api_key();
+    let encryption_key = args.encrypt.then(FieldCipher::generate_key);
+    write_env_file(
+        args,
+        &env_file_contents(args, &master_key, encryption_key.as_deref()),
+    )?;
+
+    let server_url = args.public_url.as_deref().map_or_else(
+        || format!("http://localhost:{}", args.port),
+        |url| url.trim_end_matches('/').to_string(),
+    );
+    Ok(Setup {
+        env_file: args.env_file.clone(),
+        db_path: args.db_path.clone(),
+        server_url,
+        master_key,
+        hook_key,
+        app_key,
+        encryption_key,
+    })
+}
+
+fn env_file_contents(args: &SetupArgs, master_key: &str, encryption_key: Option<&str>) -> String {
+    let mut contents = format!(
+        "# Written by `claudiator-server setup`. Holds the master key: keep it private.\n\
+         CLAUDIATOR_API_KEY={master_key}\n\
+         CLAUDIATOR_PORT={}\n\
+         CLAUDIATOR_BIND={}\n\
+         CLAUDIATOR_DB_PATH={}\n",
+        args.port, args.bind, args.db_path
+    );
+    if let Some(url) = &args.public_url {
+        let _ = writeln!(contents, "CLAUDIATOR_PUBLIC_URL={url}");
+    }
+    if let Some(key) = encryption_key {
+        let _ = writeln!(contents, "CLAUDIATOR_ENCRYPTION_KEY={key}");
+    }
+    contents
+}
+
+/// Write the environment file, readable only by its owner where the
+/// platform supports it.
+fn write_env_file(args: &SetupArgs, contents: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&args.env_file)?;
+    // `mode` only applies to new files; a replaced one keeps its old bits
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+impl Setup {
+    /// The lines to paste into `~/.claude/claudiator/config.toml`. Uses the
+    /// hook key when one was created, the master key otherwise.
+    pub fn hook_snippet(&self) -> String {
+        let key = self.hook_key.as_deref().unwrap_or(&self.master_key);
+        format!(
+            "server_url = \"{}\"\napi_key = \"{key}\"\n",
+            self.server_url
+        )
+    }
+
+    /// Print a summary for the operator on stdout.
+    pub fn print(&self) {
+        println!("Wrote {}", self.env_file.display());
+        println!("Database ready at {}", self.db_path);
+        println!();
+        println!("Master key:       {}", self.master_key);
+        if let Some(key) = &self.hook_key {
+            println!("Hook key (write): {key}");
+        }
+        if let Some(key) = &self.app_key {
+            println!("App key (read):   {key}");
+        }
+        if self.encryption_key.is_some() {
+            println!();
+            println!(
+                "Encryption key written to {}. Back it up: data encrypted with it can't be read without it.",
+                self.env_file.display()
+            );
+        }
+        println!();
+        println!("Start the server with these settings:");
+        // `.` searches PATH for a bare file name
+        let env_file = if self.env_file.is_relative() && !self.env_file.starts_with(".") {
+            Path::new(".").join(&self.env_file)
+        } else {
+            self.env_file.clone()
+        };
+        println!(
+            "  set -a; . {}; set +a; claudiator-server",
+            env_file.display()
+        );
+        println!();
+        println!("Add to ~/.claude/claudiator/config.toml on each machine running the hook:");
+        println!();
+        print!("{}", self.hook_snippet());
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        setup: SetupArgs,
+    }
+
+    fn args(dir: &tempfile::TempDir, extra: &[&str]) -> SetupArgs {
+        let env_file = dir.path().join(".env");
+        let db_path = dir.path().join("claudiator.db");
+        let mut argv = vec![
+            "setup".to_string(),
+            "--env-file".to_string(),
+            env_file.display().to_string(),
+            "--db-path".to_string(),
+            db_path.display().to_string(),
+        ];
+        argv.extend(extra.iter().map(ToString::to_string));
+        Cli::try_parse_from(argv).unwrap().setup
+    }
+
+    #[test]
+    fn test_setup_writes_env_file_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = args(
+            &dir,
+            &[
+                "--hook-key",
+                "--app-key",
+                "--encrypt",
+                "--public-url",
+                "https://claudiator.example.com/",
+            ],
+        );
+
+        let setup = run(&args).unwrap();
+
+        let env = fs::read_to_string(&args.env_file).unwrap();
+        assert!(env.contains(&format!("CLAUDIATOR_API_KEY={}\n", setup.master_key)));
+        assert!(env.contains("CLAUDIATOR_PORT=3000\n"));
+        assert!(env.contains(&format!("CLAUDIATOR_DB_PATH={}\n", args.db_path)));
+        assert!(env.contains("CLAUDIATOR_PUBLIC_URL=https://claudiator.example.com/\n"));
+        let encryption_key = setup.encryption_key.as_deref().unwrap();
+        assert!(env.contains(&format!("CLAUDIATOR_ENCRYPTION_KEY={encryption_key}\n")));
+        assert!(FieldCipher::from_base64(encryption_key).is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&args.env_file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let storage = SqliteStorage::new(pool::create_pool(&args.db_path).unwrap());
+        let hook_key = setup.hook_key.as_deref().unwrap();
+        let hook = storage.find_api_key_by_key(hook_key).unwrap().unwrap();
+        assert_eq!(hook.scopes, "write");
+        let app_key = setup.app_key.as_deref().unwrap();
+        let app = storage.find_api_key_by_key(app_key).unwrap().unwrap();
+        assert_eq!(app.scopes, "read");
+
+        assert_eq!(
+            setup.hook_snippet(),
+            format!("server_url = \"https://claudiator.example.com\"\napi_key = \"{hook_key}\"\n")
+        );
+    }
+
+    #[test]
+    fn test_setup_without_scoped_keys_uses_master_key_for_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let setup = run(&args(&dir, &["--port", "8080"])).unwrap();
+
+        assert!(setup.hook_key.is_none());
+        assert!(setup.app_key.is_none());
+        assert!(setup.encryption_key.is_none());
+        assert_eq!(
+            setup.hook_snippet(),
+            format!(
+                "server_url = \"http://localhost:8080\"\napi_key = \"{}\"\n",
+                setup.master_key
+            )
+        );
+    }
+
+    #[test]
+    fn test_setup_refuses_to_replace_env_file_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = args(&dir, &[]);
+        fs::write(&args.env_file, "CLAUDIATOR_API_KEY=keep-me\n").unwrap();
+
+        assert!(run(&args).is_err());
+        assert_eq!(
+            fs::read_to_string(&args.env_file).unwrap(),
+            "CLAUDIATOR_API_KEY=keep-me\n"
+        );
+        assert!(!std::path::Path::new(&args.db_path).exists());
+
+        let forced = SetupArgs {
+            force: true,
+            ..args
+        };
+        let setup = run(&forced).unwrap();
+        assert!(fs::read_to_string(&forced.env_file)
+            .unwrap()
+            .contains(&setup.master_key));
+    }
+}
@@ -0,0 +1,100 @@
+//! Waking snoozed notifications.
+//!
+//! `POST /api/v1/notifications/:id/snooze` hides a notification from list
+//! responses until its `snoozed_until`. [`spawn`] starts the background
+//! task that, every [`POLL_SECS`], ends the snoozes that are due: the
+//! notifications are listed again, the notification version is bumped so
+//! clients refetch, and each one not acknowledged in the meantime is pushed
+//! again the way `POST /api/v1/notifications/:id/resend` would.
+//!
+//! A woken notification keeps its `created_at`, so clients polling with
+//! `?after=` only see it again through the push or a full refetch.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+
+use crate::apns::Alert;
+use crate::db::partition::Partition;
+use crate::error::AppError;
+use crate::ingest::push::{self, PushNotification};
+use crate::notif_routing::Channel;
+use crate::router::AppState;
+use crate::utils::truncate_at_char_boundary;
+
+/// Longest snooze accepted, in minutes: one day.
+pub const MAX_SNOOZE_MINUTES: u32 = 24 * 60;
+
+/// Seconds between checks for snoozes that are due.
+pub const POLL_SECS: u64 = 30;
+
+/// Starts the task waking snoozed notifications. It runs for the life of
+/// the server.
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(POLL_SECS));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+            if let Err(e) = wake_due(&state, &now) {
+                tracing::warn!("Failed to wake snoozed notifications: {e:?}");
+            }
+        }
+    });
+}
+
+/// Ends every snooze due at or before `now`, a normalized timestamp, and
+/// pushes the woken notifications again. Returns how many were woken.
+///
+/// # Errors
+///
+/// Returns [`AppError::Internal`] if the database can't be reached.
+pub fn wake_due(state: &AppState, now: &str) -> Result<usize, AppError> {
+    let woken = state.storage.take_due_snoozes(now)?;
+    if woken.is_empty() {
+        return Ok(0);
+    }
+
+    let new_notif_version = state.notification_version.fetch_add(1, Ordering::Relaxed) + 1;
+    let _ = state
+        .storage
+        .set_metadata("notification_version", &new_notif_version.to_string());
+    state.list_cache.invalidate();
+    tracing::info!(count = woken.len(), "Snoozed notifications woken");
+
+    let Some(ref apns_client) = state.notif.apns_client else {
+        return Ok(woken.len());
+    };
+    let count = woken.len();
+    for notification in woken {
+        if notification.acknowledged
+            || !state
+                .notif
+                .routes
+                .delivers(&notification.notification_type, Channel::Apns)
+        {
+            continue;
+        }
+        let device_partition = state
+            .storage
+            .device_partition(&notification.device_id)?
+            .unwrap_or(Partition::Key(None));
+        push::dispatch(
+            apns_client.clone(),
+            Arc::clone(&state.storage),
+            Arc::clone(&state.health),
+            PushNotification {
+                collapse_id: truncate_at_char_boundary(&notification.session_id, 64),
+                alert: Alert::from_stored(&notification),
+                notification_id: notification.id,
+                session_id: notification.session_id,
+                device_id: notification.device_id,
+            },
+            device_partition,
+        );
+    }
+    Ok(count)
+}
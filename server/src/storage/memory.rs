@@ -0,0 +1,1825 @@
+//! [`Storage`] in process memory.
+//!
+//! Rows live in maps behind one lock and go away with the storage. Queries
+//! return what the `SQLite` backend would for the same writes, except:
+//!
+//! - search matches each word as a case-insensitive substring of the title,
+//!   prompt, or message, newest first, and the snippet is the whole text
+//!   with matches marked, rather than `FTS5`'s ranked, stemmed matching
+//! - retention keeps no session summaries
+//! - maintenance and vacuum do nothing and report zero pages
+//! - nothing is encrypted, since nothing is written out
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use chrono::{SecondsFormat, Utc};
+
+use super::{Interrupt, Storage};
+use crate::clock_skew;
+use crate::db::partition::Partition;
+use crate::db::queries::{
+    ApiKeyRow, EventFilter, KeyUsageRow, MaintenanceStats, NotificationTtl, PaginatedSessions,
+    PushTokenRow,
+};
+use crate::error::AppError;
+use crate::models::response::{
+    ArchivedEventResponse, DeliveryFailure, DeviceResponse, EventDetailResponse, EventResponse,
+    EventSearchHit, NotificationResponse, PermissionResponse, SessionResponse, SessionSearchHit,
+    SessionStatusCounts, TimelineEvent,
+};
+use crate::time_range::TimeRange;
+
+// Rows keep their columns' names
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone)]
+struct Device {
+    device_name: String,
+    platform: String,
+    partition: Option<String>,
+    first_seen: String,
+    last_seen: String,
+    last_heartbeat: Option<String>,
+    hook_version: Option<String>,
+    clock_skew_ms: Option<i64>,
+    outdated_hook_notified_on: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    device_id: String,
+    started_at: String,
+    last_event: String,
+    status: String,
+    cwd: Option<String>,
+    title: Option<String>,
+    pinned: bool,
+    parent_session_id: Option<String>,
+    agent_type: Option<String>,
+    team_name: Option<String>,
+    model: Option<String>,
+}
+
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone)]
+struct Event {
+    device_id: String,
+    session_id: String,
+    hook_event_name: String,
+    timestamp: String,
+    received_at: String,
+    tool_name: Option<String>,
+    notification_type: Option<String>,
+    event_json: String,
+    seq: i64,
+    skew_ms: Option<i64>,
+    normalized_timestamp: Option<String>,
+    team_name: Option<String>,
+    task_id: Option<String>,
+}
+
+impl Event {
+    /// `timestamp` corrected for clock skew, when it has been.
+    fn normalized(&self) -> &str {
+        self.normalized_timestamp
+            .as_deref()
+            .unwrap_or(&self.timestamp)
+    }
+
+    fn json(&self) -> Option<serde_json::Value> {
+        serde_json::from_str(&self.event_json).ok()
+    }
+
+    /// The text search looks through: the prompt and the message.
+    fn searchable(&self) -> Option<String> {
+        let json = self.json()?;
+        let text: Vec<&str> = ["prompt", "message"]
+            .iter()
+            .filter_map(|field| json.get(field).and_then(serde_json::Value::as_str))
+            .collect();
+        (!text.is_empty()).then(|| text.join(" "))
+    }
+}
+
+#[allow(clippy::struct_field_names)]
+#[derive(Debug, Clone)]
+struct Notification {
+    event_id: i64,
+    session_id: String,
+    device_id: String,
+    title: String,
+    body: String,
+    notification_type: String,
+    payload_json: Option<String>,
+    created_at: String,
+    acknowledged: bool,
+    snoozed_until: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Response {
+    id: String,
+    session_id: String,
+    decision: String,
+    message: Option<String>,
+    created_at: String,
+    delivered_at: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct PushToken {
+    platform: String,
+    last_seen: String,
+    last_success: Option<String>,
+    sandbox: bool,
+    sandbox_tagged: bool,
+    partition: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct ApiKey {
+    name: String,
+    key: String,
+    scopes: String,
+    created_at: String,
+    last_used: Option<String>,
+    rate_limit: Option<i64>,
+    partition: Option<String>,
+    device_id: Option<String>,
+    privacy_mode: bool,
+}
+
+#[derive(Debug, Clone)]
+struct EnrollmentCode {
+    partition: Option<String>,
+    expires_at: String,
+    used_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tables {
+    devices: BTreeMap<String, Device>,
+    sessions: BTreeMap<String, Session>,
+    events: BTreeMap<i64, Event>,
+    /// The last event id handed out; ids aren't reused.
+    last_event_id: i64,
+    archive: BTreeMap<i64, (Event, String)>,
+    notifications: BTreeMap<String, Notification>,
+    /// By notification id.
+    responses: BTreeMap<String, Response>,
+    push_tokens: BTreeMap<String, PushToken>,
+    delivery_failures: BTreeMap<String, DeliveryFailure>,
+    api_keys: BTreeMap<String, ApiKey>,
+    /// `(requests, events)` by key id and day.
+    key_usage: BTreeMap<(String, String), (i64, i64)>,
+    enrollment_codes: BTreeMap<String, EnrollmentCode>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl Tables {
+    /// Whether a row of `device_id` is visible in `partition`. Rows of an
+    /// unknown device are in the default partition.
+    fn visible(&self, partition: &Partition, device_id: &str) -> bool {
+        partition.allows(
+            self.devices
+                .get(device_id)
+                .and_then(|device| device.partition.as_deref()),
+        )
+    }
+
+    /// Whether `device_id` is a known device in `partition`.
+    fn in_partition(&self, partition: &Partition, device_id: &str) -> bool {
+        self.devices
+            .get(device_id)
+            .is_some_and(|device| partition.allows(device.partition.as_deref()))
+    }
+
+    fn session_response(&self, session_id: &str, session: &Session) -> SessionResponse {
+        let device = self.devices.get(&session.device_id);
+        SessionResponse {
+            session_id: session_id.to_string(),
+            device_id: session.device_id.clone(),
+            started_at: session.started_at.clone(),
+            last_event: session.last_event.clone(),
+            status: session.status.clone(),
+            cwd: session.cwd.clone(),
+            title: session.title.clone(),
+            device_name: device.map(|device| device.device_name.clone()),
+            platform: device.map(|device| device.platform.clone()),
+            pinned: session.pinned,
+            parent_session_id: session.parent_session_id.clone(),
+            agent_type: session.agent_type.clone(),
+            team_name: session.team_name.clone(),
+            model: session.model.clone(),
+            subagents: Vec::new(),
+        }
+    }
+
+    /// Top-level sessions in `partition`.
+    fn sessions_in<'a>(
+        &'a self,
+        partition: &'a Partition,
+    ) -> impl Iterator<Item = (&'a String, &'a Session)> + 'a {
+        self.sessions.iter().filter(move |(_, session)| {
+            session.parent_session_id.is_none() && self.visible(partition, &session.device_id)
+        })
+    }
+
+    /// Removes and returns the events received before `cutoff`.
+    fn remove_events_before(&mut self, cutoff: &str) -> BTreeMap<i64, Event> {
+        let (old, kept) = std::mem::take(&mut self.events)
+            .into_iter()
+            .partition(|(_, event)| event.received_at.as_str() < cutoff);
+        self.events = kept;
+        old
+    }
+}
+
+fn notification_response(id: &str, notification: &Notification) -> NotificationResponse {
+    NotificationResponse {
+        id: id.to_string(),
+        event_id: notification.event_id,
+        session_id: notification.session_id.clone(),
+        device_id: notification.device_id.clone(),
+        title: notification.title.clone(),
+        body: notification.body.clone(),
+        notification_type: notification.notification_type.clone(),
+        payload_json: notification.payload_json.clone(),
+        created_at: notification.created_at.clone(),
+        acknowledged: notification.acknowledged,
+    }
+}
+
+fn permission_response(notification_id: &str, response: &Response) -> PermissionResponse {
+    PermissionResponse {
+        id: response.id.clone(),
+        notification_id: notification_id.to_string(),
+        session_id: response.session_id.clone(),
+        decision: response.decision.clone(),
+        message: response.message.clone(),
+        created_at: response.created_at.clone(),
+    }
+}
+
+fn api_key_row(id: &str, key: &ApiKey) -> ApiKeyRow {
+    ApiKeyRow {
+        id: id.to_string(),
+        name: key.name.clone(),
+        key: key.key.clone(),
+        scopes: key.scopes.clone(),
+        created_at: key.created_at.clone(),
+        last_used: key.last_used.clone(),
+        rate_limit: key.rate_limit,
+        partition: key.partition.clone(),
+        device_id: key.device_id.clone(),
+        privacy_mode: key.privacy_mode,
+    }
+}
+
+/// `SQLite`'s `LIMIT`: negative means no limit.
+fn limit(limit: i64) -> usize {
+    usize::try_from(limit).unwrap_or(usize::MAX)
+}
+
+/// `SQLite`'s `OFFSET`: negative means none.
+fn offset(offset: i64) -> usize {
+    usize::try_from(offset).unwrap_or(0)
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// The stored form of the time `age` ago.
+fn ago(age: Option<chrono::Duration>) -> Result<String, AppError> {
+    Ok(age
+        .and_then(|age| Utc::now().checked_sub_signed(age))
+        .ok_or_else(|| AppError::Internal("Time calculation overflow".to_string()))?
+        .to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn hours_ago(hours: u64) -> Result<String, AppError> {
+    ago(i64::try_from(hours)
+        .ok()
+        .and_then(chrono::Duration::try_hours))
+}
+
+fn days_ago(days: u64) -> Result<String, AppError> {
+    ago(i64::try_from(days)
+        .ok()
+        .and_then(chrono::Duration::try_days))
+}
+
+fn constraint_failed(what: &str, constraint: &str) -> AppError {
+    AppError::Internal(format!("Failed to {what}: {constraint} constraint failed"))
+}
+
+/// The words of a [`search_expression`](crate::db::queries::search_expression),
+/// lowercased.
+fn search_terms(expression: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        let mut term = String::new();
+        while let Some(c) = chars.next() {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            term.push(c);
+        }
+        terms.push(term.to_lowercase());
+    }
+    terms
+}
+
+/// `text` with every occurrence of `terms` wrapped in `**`, or `None` if
+/// some term doesn't occur.
+fn search_snippet(text: &str, terms: &[String]) -> Option<String> {
+    let lower = text.to_lowercase();
+    if terms.is_empty() || !terms.iter().all(|term| lower.contains(term.as_str())) {
+        return None;
+    }
+    // Lowercasing can change byte lengths, so match char by char
+    let chars: Vec<char> = text.chars().collect();
+    let mut snippet = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let matched = terms.iter().find_map(|term| {
+            let len = term.chars().count();
+            let candidate: String = chars.get(i..i + len)?.iter().collect();
+            (len > 0 && candidate.to_lowercase() == *term).then_some((candidate, len))
+        });
+        if let Some((candidate, len)) = matched {
+            snippet.push_str("**");
+            snippet.push_str(&candidate);
+            snippet.push_str("**");
+            i += len;
+        } else {
+            snippet.push(chars[i]);
+            i += 1;
+        }
+    }
+    Some(snippet)
+}
+
+/// Storage that keeps everything in memory. Cheap to create, so each test
+/// can have its own.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn tables(&self) -> MutexGuard<'_, Tables> {
+        self.tables.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Each method holds the lock throughout, so it sees and leaves the tables
+// consistent, as one statement would
+#[allow(clippy::significant_drop_tightening)]
+impl Storage for MemoryStorage {
+    fn upsert_device(
+        &self,
+        device_id: &str,
+        device_name: &str,
+        platform: &str,
+        partition: Option<&str>,
+        now: &str,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if let Some(device) = tables.devices.get_mut(device_id) {
+            device.device_name = device_name.to_string();
+            device.last_seen = now.to_string();
+        } else {
+            tables.devices.insert(
+                device_id.to_string(),
+                Device {
+                    device_name: device_name.to_string(),
+                    platform: platform.to_string(),
+                    partition: partition.map(String::from),
+                    first_seen: now.to_string(),
+                    last_seen: now.to_string(),
+                    last_heartbeat: None,
+                    hook_version: None,
+                    clock_skew_ms: None,
+                    outdated_hook_notified_on: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn device_last_heartbeat(&self, device_id: &str) -> Result<Option<String>, AppError> {
+        Ok(self
+            .tables()
+            .devices
+            .get(device_id)
+            .and_then(|device| device.last_heartbeat.clone()))
+    }
+
+    fn set_device_heartbeat(&self, device_id: &str, now: &str) -> Result<(), AppError> {
+        if let Some(device) = self.tables().devices.get_mut(device_id) {
+            device.last_heartbeat = Some(now.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_device_hook_version(&self, device_id: &str, hook_version: &str) -> Result<(), AppError> {
+        if let Some(device) = self.tables().devices.get_mut(device_id) {
+            device.hook_version = Some(hook_version.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_device_clock_skew(&self, device_id: &str, clock_skew_ms: i64) -> Result<(), AppError> {
+        if let Some(device) = self.tables().devices.get_mut(device_id) {
+            device.clock_skew_ms = Some(clock_skew_ms);
+        }
+        Ok(())
+    }
+
+    fn min_device_skew(&self, device_id: &str, since: &str) -> Result<Option<i64>, AppError> {
+        Ok(self
+            .tables()
+            .events
+            .values()
+            .filter(|event| event.device_id == device_id && event.received_at.as_str() >= since)
+            .filter_map(|event| event.skew_ms)
+            .min())
+    }
+
+    fn claim_outdated_hook_notice(&self, device_id: &str, day: &str) -> Result<bool, AppError> {
+        let mut tables = self.tables();
+        let Some(device) = tables.devices.get_mut(device_id) else {
+            return Ok(false);
+        };
+        if device.outdated_hook_notified_on.as_deref() == Some(day) {
+            return Ok(false);
+        }
+        device.outdated_hook_notified_on = Some(day.to_string());
+        Ok(true)
+    }
+
+    fn device_partition(&self, device_id: &str) -> Result<Option<Partition>, AppError> {
+        Ok(self
+            .tables()
+            .devices
+            .get(device_id)
+            .map(|device| Partition::Key(device.partition.clone())))
+    }
+
+    fn list_devices(
+        &self,
+        partition: &Partition,
+        events_since: &str,
+    ) -> Result<Vec<DeviceResponse>, AppError> {
+        let tables = self.tables();
+        let mut devices: Vec<DeviceResponse> = tables
+            .devices
+            .iter()
+            .filter(|(_, device)| partition.allows(device.partition.as_deref()))
+            .map(|(device_id, device)| {
+                let count = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+                DeviceResponse {
+                    device_id: device_id.clone(),
+                    device_name: device.device_name.clone(),
+                    display_name: device.device_name.clone(),
+                    platform: device.platform.clone(),
+                    first_seen: device.first_seen.clone(),
+                    last_seen: device.last_seen.clone(),
+                    last_heartbeat: device.last_heartbeat.clone(),
+                    hook_version: device.hook_version.clone(),
+                    hook_outdated: false,
+                    active_sessions: count(
+                        tables
+                            .sessions
+                            .values()
+                            .filter(|s| {
+                                s.device_id == *device_id
+                                    && s.status != "ended"
+                                    && s.parent_session_id.is_none()
+                            })
+                            .count(),
+                    ),
+                    unacked_notifications: count(
+                        tables
+                            .notifications
+                            .values()
+                            .filter(|n| n.device_id == *device_id && !n.acknowledged)
+                            .count(),
+                    ),
+                    events_last_24h: count(
+                        tables
+                            .events
+                            .values()
+                            .filter(|e| {
+                                e.device_id == *device_id && e.received_at.as_str() >= events_since
+                            })
+                            .count(),
+                    ),
+                    clock_skew_ms: device.clock_skew_ms,
+                    clock_skewed: device.clock_skew_ms.is_some_and(clock_skew::is_skewed),
+                }
+            })
+            .collect();
+        devices.sort_by(|a, b| b.last_seen.cmp(&a.last_seen));
+        Ok(devices)
+    }
+
+    fn delete_stale_devices(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = days_ago(retention_days)?;
+        let mut tables = self.tables();
+        let stale: Vec<String> = tables
+            .devices
+            .iter()
+            .filter(|(device_id, device)| {
+                device.last_seen < cutoff
+                    && !tables.sessions.values().any(|s| s.device_id == **device_id)
+                    && !tables.events.values().any(|e| e.device_id == **device_id)
+            })
+            .map(|(device_id, _)| device_id.clone())
+            .collect();
+        for device_id in &stale {
+            tables.devices.remove(device_id);
+        }
+        Ok(stale.len())
+    }
+
+    fn session_partition(&self, session_id: &str) -> Result<Option<Partition>, AppError> {
+        let tables = self.tables();
+        Ok(tables
+            .sessions
+            .get(session_id)
+            .and_then(|session| tables.devices.get(&session.device_id))
+            .map(|device| Partition::Key(device.partition.clone())))
+    }
+
+    fn upsert_session(
+        &self,
+        session_id: &str,
+        device_id: &str,
+        now: &str,
+        status: Option<&str>,
+        cwd: Option<&str>,
+        title: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if let Some(session) = tables.sessions.get_mut(session_id) {
+            session.last_event = now.to_string();
+            if let Some(cwd) = cwd {
+                session.cwd = Some(cwd.to_string());
+            }
+            if session.title.is_none() {
+                session.title = title.map(String::from);
+            }
+            if let Some(status) = status {
+                session.status = status.to_string();
+            }
+            return Ok(());
+        }
+        if !tables.devices.contains_key(device_id) {
+            return Err(constraint_failed("upsert session", "FOREIGN KEY"));
+        }
+        tables.sessions.insert(
+            session_id.to_string(),
+            Session {
+                device_id: device_id.to_string(),
+                started_at: now.to_string(),
+                last_event: now.to_string(),
+                status: status.unwrap_or("active").to_string(),
+                cwd: cwd.map(String::from),
+                title: title.map(String::from),
+                pinned: false,
+                parent_session_id: None,
+                agent_type: None,
+                team_name: None,
+                model: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn set_session_title(&self, session_id: &str, title: &str) -> Result<(), AppError> {
+        if let Some(session) = self.tables().sessions.get_mut(session_id) {
+            session.title = Some(title.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_session_parent(
+        &self,
+        session_id: &str,
+        parent_session_id: &str,
+        agent_type: Option<&str>,
+    ) -> Result<(), AppError> {
+        if let Some(session) = self.tables().sessions.get_mut(session_id) {
+            session.parent_session_id = Some(parent_session_id.to_string());
+            if let Some(agent_type) = agent_type {
+                session.agent_type = Some(agent_type.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn set_session_team(&self, session_id: &str, team_name: &str) -> Result<(), AppError> {
+        if let Some(session) = self.tables().sessions.get_mut(session_id) {
+            session.team_name = Some(team_name.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_session_model(&self, session_id: &str, model: &str) -> Result<(), AppError> {
+        if let Some(session) = self.tables().sessions.get_mut(session_id) {
+            session.model = Some(model.to_string());
+        }
+        Ok(())
+    }
+
+    fn list_sessions(
+        &self,
+        partition: &Partition,
+        device_id: &str,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<SessionResponse>, AppError> {
+        let tables = self.tables();
+        let mut sessions: Vec<(&String, &Session)> = tables
+            .sessions_in(partition)
+            .filter(|(_, s)| s.device_id == device_id)
+            .filter(|(_, s)| status.is_none_or(|status| s.status == status))
+            .collect();
+        sessions
+            .sort_by(|(_, a), (_, b)| (b.pinned, &b.last_event).cmp(&(a.pinned, &a.last_event)));
+        Ok(sessions
+            .into_iter()
+            .take(self::limit(limit))
+            .map(|(id, session)| tables.session_response(id, session))
+            .collect())
+    }
+
+    fn list_all_sessions_paginated(
+        &self,
+        partition: &Partition,
+        status: Option<&str>,
+        cwd_prefix: Option<&str>,
+        team: Option<&str>,
+        exclude_ended: bool,
+        range: &TimeRange,
+        limit: i64,
+        offset: i64,
+    ) -> Result<PaginatedSessions, AppError> {
+        let tables = self.tables();
+        let mut sessions: Vec<(&String, &Session)> = tables
+            .sessions_in(partition)
+            .filter(|(_, s)| {
+                status.is_none_or(|status| s.status == status)
+                    && cwd_prefix.is_none_or(|prefix| {
+                        s.cwd.as_deref().is_some_and(|cwd| cwd.starts_with(prefix))
+                    })
+                    && team.is_none_or(|team| s.team_name.as_deref() == Some(team))
+                    && !(exclude_ended && s.status == "ended")
+                    && range.from.as_ref().is_none_or(|from| s.last_event >= *from)
+                    && range.to.as_ref().is_none_or(|to| s.started_at < *to)
+            })
+            .collect();
+        sessions.sort_by(|(_, a), (_, b)| {
+            (
+                Reverse(a.pinned),
+                a.status == "ended",
+                Reverse(&a.last_event),
+            )
+                .cmp(&(
+                    Reverse(b.pinned),
+                    b.status == "ended",
+                    Reverse(&b.last_event),
+                ))
+        });
+
+        let limit = self::limit(limit);
+        let mut page: Vec<SessionResponse> = sessions
+            .into_iter()
+            .skip(self::offset(offset))
+            .take(limit.saturating_add(1))
+            .map(|(id, session)| tables.session_response(id, session))
+            .collect();
+        let has_more = page.len() > limit;
+        page.truncate(limit);
+        let next_offset = offset + i64::try_from(page.len()).unwrap_or(i64::MAX);
+        Ok(PaginatedSessions {
+            sessions: page,
+            has_more,
+            next_offset,
+        })
+    }
+
+    fn get_session(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+    ) -> Result<Option<SessionResponse>, AppError> {
+        let tables = self.tables();
+        Ok(tables
+            .sessions
+            .get(session_id)
+            .filter(|session| tables.visible(partition, &session.device_id))
+            .map(|session| tables.session_response(session_id, session)))
+    }
+
+    fn list_subagent_sessions(
+        &self,
+        partition: &Partition,
+        parent_session_id: &str,
+    ) -> Result<Vec<SessionResponse>, AppError> {
+        let tables = self.tables();
+        let mut sessions: Vec<(&String, &Session)> = tables
+            .sessions
+            .iter()
+            .filter(|(_, s)| {
+                s.parent_session_id.as_deref() == Some(parent_session_id)
+                    && tables.visible(partition, &s.device_id)
+            })
+            .collect();
+        sessions.sort_by(|(_, a), (_, b)| a.started_at.cmp(&b.started_at));
+        Ok(sessions
+            .into_iter()
+            .map(|(id, session)| tables.session_response(id, session))
+            .collect())
+    }
+
+    fn count_sessions_by_status(
+        &self,
+        partition: &Partition,
+    ) -> Result<SessionStatusCounts, AppError> {
+        let mut counts = SessionStatusCounts::default();
+        for (_, session) in self.tables().sessions_in(partition) {
+            match session.status.as_str() {
+                "active" => counts.active += 1,
+                "waiting_for_permission" => counts.waiting_for_permission += 1,
+                "waiting_for_input" => counts.waiting_for_input += 1,
+                "idle" => counts.idle += 1,
+                "compacting" => counts.compacting += 1,
+                _ => {}
+            }
+        }
+        Ok(counts)
+    }
+
+    fn count_sessions_by_model(
+        &self,
+        partition: &Partition,
+    ) -> Result<BTreeMap<String, i64>, AppError> {
+        let mut counts = BTreeMap::new();
+        for (_, session) in self.tables().sessions_in(partition) {
+            if let Some(ref model) = session.model {
+                *counts.entry(model.clone()).or_default() += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    fn most_urgent_session(
+        &self,
+        partition: &Partition,
+    ) -> Result<Option<SessionResponse>, AppError> {
+        let tables = self.tables();
+        Ok(tables
+            .sessions_in(partition)
+            .filter(|(_, s)| s.status != "ended")
+            .min_by_key(|(_, s)| {
+                let rank = match s.status.as_str() {
+                    "waiting_for_permission" => 0,
+                    "waiting_for_input" => 1,
+                    "idle" => 2,
+                    _ => 3,
+                };
+                let active = (s.status == "active").then_some(s.last_event.as_str());
+                (rank, Reverse(active), s.last_event.as_str())
+            })
+            .map(|(id, session)| tables.session_response(id, session)))
+    }
+
+    fn set_session_pinned(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        pinned: bool,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        let allowed = tables
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| tables.in_partition(partition, &session.device_id));
+        if allowed {
+            if let Some(session) = tables.sessions.get_mut(session_id) {
+                session.pinned = pinned;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_session_title(&self, session_id: &str) -> Result<Option<String>, AppError> {
+        Ok(self
+            .tables()
+            .sessions
+            .get(session_id)
+            .and_then(|session| session.title.clone()))
+    }
+
+    fn delete_stale_sessions(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = days_ago(retention_days)?;
+        let mut tables = self.tables();
+        let stale: Vec<String> = tables
+            .sessions
+            .iter()
+            .filter(|(session_id, session)| {
+                session.last_event < cutoff
+                    && !tables.events.values().any(|e| e.session_id == **session_id)
+                    && !tables
+                        .notifications
+                        .values()
+                        .any(|n| n.session_id == **session_id)
+            })
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        for session_id in &stale {
+            tables.sessions.remove(session_id);
+        }
+        Ok(stale.len())
+    }
+
+    fn insert_event(
+        &self,
+        device_id: &str,
+        session_id: &str,
+        hook_event_name: &str,
+        timestamp: &str,
+        received_at: &str,
+        tool_name: Option<&str>,
+        notification_type: Option<&str>,
+        event_json: &str,
+    ) -> Result<i64, AppError> {
+        let mut tables = self.tables();
+        if !tables.devices.contains_key(device_id) || !tables.sessions.contains_key(session_id) {
+            return Err(constraint_failed("insert event", "FOREIGN KEY"));
+        }
+        let seq = tables
+            .events
+            .values()
+            .filter(|event| event.session_id == session_id)
+            .map(|event| event.seq)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        tables.last_event_id += 1;
+        let id = tables.last_event_id;
+        tables.events.insert(
+            id,
+            Event {
+                device_id: device_id.to_string(),
+                session_id: session_id.to_string(),
+                hook_event_name: hook_event_name.to_string(),
+                timestamp: timestamp.to_string(),
+                received_at: received_at.to_string(),
+                tool_name: tool_name.map(String::from),
+                notification_type: notification_type.map(String::from),
+                event_json: event_json.to_string(),
+                seq,
+                skew_ms: None,
+                normalized_timestamp: None,
+                team_name: None,
+                task_id: None,
+            },
+        );
+        Ok(id)
+    }
+
+    fn set_event_clock(
+        &self,
+        event_id: i64,
+        skew_ms: i64,
+        normalized_timestamp: &str,
+    ) -> Result<(), AppError> {
+        if let Some(event) = self.tables().events.get_mut(&event_id) {
+            event.skew_ms = Some(skew_ms);
+            event.normalized_timestamp = Some(normalized_timestamp.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_event_team_task(
+        &self,
+        event_id: i64,
+        team_name: Option<&str>,
+        task_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        if let Some(event) = self.tables().events.get_mut(&event_id) {
+            event.team_name = team_name.map(String::from);
+            event.task_id = task_id.map(String::from);
+        }
+        Ok(())
+    }
+
+    fn list_events(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        filter: &EventFilter,
+        limit: i64,
+    ) -> Result<Vec<EventResponse>, AppError> {
+        let tables = self.tables();
+        let mut events: Vec<(&i64, &Event)> = tables
+            .events
+            .iter()
+            .filter(|(_, e)| {
+                e.session_id == session_id
+                    && tables.visible(partition, &e.device_id)
+                    && (filter.types.is_empty() || filter.types.contains(&e.hook_event_name))
+                    && filter
+                        .tool
+                        .as_ref()
+                        .is_none_or(|tool| e.tool_name.as_ref() == Some(tool))
+                    && filter
+                        .task
+                        .as_ref()
+                        .is_none_or(|task| e.task_id.as_ref() == Some(task))
+            })
+            .collect();
+        events.sort_by(|(_, a), (_, b)| (b.normalized(), b.seq).cmp(&(a.normalized(), a.seq)));
+        Ok(events
+            .into_iter()
+            .take(self::limit(limit))
+            .map(|(id, e)| {
+                let json = e.json();
+                let text = |field: &str| {
+                    json.as_ref()
+                        .and_then(|json| json.get(field))
+                        .and_then(serde_json::Value::as_str)
+                        .map(String::from)
+                };
+                EventResponse {
+                    id: *id,
+                    hook_event_name: e.hook_event_name.clone(),
+                    timestamp: e.timestamp.clone(),
+                    seq: e.seq,
+                    normalized_timestamp: e.normalized().to_string(),
+                    tool_name: e.tool_name.clone(),
+                    notification_type: e.notification_type.clone(),
+                    message: text("message"),
+                    team_name: e.team_name.clone(),
+                    task_id: e.task_id.clone(),
+                    task_subject: text("task_subject"),
+                }
+            })
+            .collect())
+    }
+
+    fn search_sessions(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SessionSearchHit>, AppError> {
+        let terms = search_terms(expression);
+        let tables = self.tables();
+        let mut hits: Vec<SessionSearchHit> = tables
+            .sessions
+            .iter()
+            .filter(|(_, s)| tables.visible(partition, &s.device_id))
+            .filter_map(|(session_id, s)| {
+                let title = s.title.as_ref()?;
+                let snippet = search_snippet(title, &terms)?;
+                Some(SessionSearchHit {
+                    session_id: session_id.clone(),
+                    device_id: s.device_id.clone(),
+                    device_name: tables
+                        .devices
+                        .get(&s.device_id)
+                        .map(|device| device.device_name.clone()),
+                    title: title.clone(),
+                    status: s.status.clone(),
+                    last_event: s.last_event.clone(),
+                    snippet,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.last_event.cmp(&a.last_event));
+        Ok(hits
+            .into_iter()
+            .skip(self::offset(offset))
+            .take(self::limit(limit))
+            .collect())
+    }
+
+    fn search_events(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EventSearchHit>, AppError> {
+        let terms = search_terms(expression);
+        let tables = self.tables();
+        let mut hits: Vec<EventSearchHit> = tables
+            .events
+            .iter()
+            .filter(|(_, e)| tables.visible(partition, &e.device_id))
+            .filter_map(|(id, e)| {
+                let snippet = search_snippet(&e.searchable()?, &terms)?;
+                Some(EventSearchHit {
+                    id: *id,
+                    session_id: e.session_id.clone(),
+                    device_id: e.device_id.clone(),
+                    hook_event_name: e.hook_event_name.clone(),
+                    timestamp: e.timestamp.clone(),
+                    snippet,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        Ok(hits
+            .into_iter()
+            .skip(self::offset(offset))
+            .take(self::limit(limit))
+            .collect())
+    }
+
+    fn list_session_timeline(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        limit: i64,
+    ) -> Result<Vec<TimelineEvent>, AppError> {
+        let tables = self.tables();
+        let mut events: Vec<&Event> = tables
+            .events
+            .values()
+            .filter(|e| e.session_id == session_id && tables.visible(partition, &e.device_id))
+            .collect();
+        events.sort_by(|a, b| (a.normalized(), a.seq).cmp(&(b.normalized(), b.seq)));
+        Ok(events
+            .into_iter()
+            .take(self::limit(limit))
+            .map(|e| {
+                let json = e.json();
+                let field = |name: &str| json.as_ref().and_then(|json| json.get(name));
+                let text = |name: &str| {
+                    field(name)
+                        .and_then(serde_json::Value::as_str)
+                        .map(String::from)
+                };
+                TimelineEvent {
+                    hook_event_name: e.hook_event_name.clone(),
+                    timestamp: e.timestamp.clone(),
+                    tool_name: e.tool_name.clone(),
+                    message: text("message"),
+                    prompt: text("prompt"),
+                    title: text("title"),
+                    trigger: text("trigger"),
+                    error: text("error"),
+                    is_interrupt: field("is_interrupt").and_then(serde_json::Value::as_bool),
+                }
+            })
+            .collect())
+    }
+
+    fn get_event(
+        &self,
+        partition: &Partition,
+        id: i64,
+    ) -> Result<Option<EventDetailResponse>, AppError> {
+        let tables = self.tables();
+        let Some(e) = tables
+            .events
+            .get(&id)
+            .filter(|e| tables.visible(partition, &e.device_id))
+        else {
+            return Ok(None);
+        };
+        let event_json: serde_json::Value = serde_json::from_str(&e.event_json)
+            .map_err(|e| AppError::Internal(format!("Stored event_json is not valid JSON: {e}")))?;
+        let permission_suggestions = event_json
+            .get("permission_suggestions")
+            .filter(|_| event_json["hook_event_name"] == "PermissionRequest")
+            .cloned();
+        Ok(Some(EventDetailResponse {
+            id,
+            device_id: e.device_id.clone(),
+            session_id: e.session_id.clone(),
+            hook_event_name: e.hook_event_name.clone(),
+            timestamp: e.timestamp.clone(),
+            received_at: e.received_at.clone(),
+            tool_name: e.tool_name.clone(),
+            notification_type: e.notification_type.clone(),
+            normalized_timestamp: e.normalized().to_string(),
+            skew_ms: e.skew_ms,
+            permission_suggestions,
+            event_json,
+        }))
+    }
+
+    fn delete_old_events(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = days_ago(retention_days)?;
+        Ok(self.tables().remove_events_before(&cutoff).len())
+    }
+
+    fn archive_old_events(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = days_ago(retention_days)?;
+        let archived_at = now();
+        let mut tables = self.tables();
+        let events = tables.remove_events_before(&cutoff);
+        let count = events.len();
+        for (id, event) in events {
+            tables
+                .archive
+                .entry(id)
+                .or_insert_with(|| (event, archived_at.clone()));
+        }
+        Ok(count)
+    }
+
+    fn list_archived_events(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<ArchivedEventResponse>, AppError> {
+        Ok(self
+            .tables()
+            .archive
+            .range(after_id.saturating_add(1)..)
+            .take(self::limit(limit))
+            .map(|(id, (e, archived_at))| ArchivedEventResponse {
+                id: *id,
+                device_id: e.device_id.clone(),
+                session_id: e.session_id.clone(),
+                hook_event_name: e.hook_event_name.clone(),
+                timestamp: e.timestamp.clone(),
+                received_at: e.received_at.clone(),
+                tool_name: e.tool_name.clone(),
+                notification_type: e.notification_type.clone(),
+                event_json: e.event_json.clone(),
+                archived_at: archived_at.clone(),
+            })
+            .collect())
+    }
+
+    fn insert_notification(
+        &self,
+        id: &str,
+        event_id: i64,
+        session_id: &str,
+        device_id: &str,
+        title: &str,
+        body: &str,
+        notification_type: &str,
+        payload_json: Option<&str>,
+        created_at: &str,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if tables.notifications.contains_key(id) {
+            return Err(constraint_failed("insert notification", "UNIQUE"));
+        }
+        tables.notifications.insert(
+            id.to_string(),
+            Notification {
+                event_id,
+                session_id: session_id.to_string(),
+                device_id: device_id.to_string(),
+                title: title.to_string(),
+                body: body.to_string(),
+                notification_type: notification_type.to_string(),
+                payload_json: payload_json.map(String::from),
+                created_at: created_at.to_string(),
+                acknowledged: false,
+                snoozed_until: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn list_notifications(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        after_timestamp: Option<&str>,
+        range: &TimeRange,
+        limit: i64,
+    ) -> Result<Vec<NotificationResponse>, AppError> {
+        let tables = self.tables();
+        let mut notifications: Vec<(&String, &Notification)> = tables
+            .notifications
+            .iter()
+            .filter(|(_, n)| {
+                tables.visible(partition, &n.device_id)
+                    && session_id.is_none_or(|session_id| n.session_id == session_id)
+                    && n.snoozed_until.is_none()
+                    && after_timestamp.is_none_or(|after| n.created_at.as_str() > after)
+                    && range.from.as_ref().is_none_or(|from| n.created_at >= *from)
+                    && range.to.as_ref().is_none_or(|to| n.created_at < *to)
+            })
+            .collect();
+        notifications.sort_by(|(_, a), (_, b)| a.created_at.cmp(&b.created_at));
+        Ok(notifications
+            .into_iter()
+            .take(self::limit(limit))
+            .map(|(id, n)| notification_response(id, n))
+            .collect())
+    }
+
+    fn get_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+    ) -> Result<Option<NotificationResponse>, AppError> {
+        let tables = self.tables();
+        Ok(tables
+            .notifications
+            .get(id)
+            .filter(|n| tables.visible(partition, &n.device_id))
+            .map(|n| notification_response(id, n)))
+    }
+
+    fn delete_expired_notifications(&self, ttl: &NotificationTtl) -> Result<usize, AppError> {
+        let mut cutoffs = BTreeMap::new();
+        for (notification_type, hours) in &ttl.per_type {
+            cutoffs
+                .entry(notification_type.as_str())
+                .or_insert(hours_ago(*hours)?);
+        }
+        let default_cutoff = hours_ago(ttl.default_hours)?;
+
+        let mut tables = self.tables();
+        let before = tables.notifications.len();
+        tables.notifications.retain(|_, n| {
+            let cutoff = cutoffs
+                .get(n.notification_type.as_str())
+                .unwrap_or(&default_cutoff);
+            n.created_at >= *cutoff
+        });
+        Ok(before - tables.notifications.len())
+    }
+
+    fn acknowledge_notifications(
+        &self,
+        partition: &Partition,
+        ids: &[String],
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        let allowed: Vec<String> = ids
+            .iter()
+            .filter(|id| {
+                tables
+                    .notifications
+                    .get(*id)
+                    .is_some_and(|n| tables.in_partition(partition, &n.device_id))
+            })
+            .cloned()
+            .collect();
+        for id in allowed {
+            if let Some(notification) = tables.notifications.get_mut(&id) {
+                notification.acknowledged = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn acknowledge_notifications_matching(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        before: Option<&str>,
+    ) -> Result<usize, AppError> {
+        let mut tables = self.tables();
+        let matching: Vec<String> = tables
+            .notifications
+            .iter()
+            .filter(|(_, n)| {
+                !n.acknowledged
+                    && tables.in_partition(partition, &n.device_id)
+                    && session_id.is_none_or(|session_id| n.session_id == session_id)
+                    && before.is_none_or(|before| n.created_at.as_str() <= before)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &matching {
+            if let Some(notification) = tables.notifications.get_mut(id) {
+                notification.acknowledged = true;
+            }
+        }
+        Ok(matching.len())
+    }
+
+    fn snooze_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+        until: &str,
+    ) -> Result<bool, AppError> {
+        let mut tables = self.tables();
+        let allowed = tables
+            .notifications
+            .get(id)
+            .is_some_and(|n| tables.in_partition(partition, &n.device_id));
+        if !allowed {
+            return Ok(false);
+        }
+        if let Some(notification) = tables.notifications.get_mut(id) {
+            notification.snoozed_until = Some(until.to_string());
+        }
+        Ok(true)
+    }
+
+    fn take_due_snoozes(&self, now: &str) -> Result<Vec<NotificationResponse>, AppError> {
+        let mut tables = self.tables();
+        let mut woken = Vec::new();
+        for (id, notification) in &mut tables.notifications {
+            if notification
+                .snoozed_until
+                .as_deref()
+                .is_some_and(|until| until <= now)
+            {
+                notification.snoozed_until = None;
+                woken.push(notification_response(id, notification));
+            }
+        }
+        Ok(woken)
+    }
+
+    fn upsert_response(
+        &self,
+        id: &str,
+        notification: &NotificationResponse,
+        decision: &str,
+        message: Option<&str>,
+        now: &str,
+    ) -> Result<bool, AppError> {
+        let mut tables = self.tables();
+        if let Some(response) = tables.responses.get_mut(&notification.id) {
+            if response.delivered_at.is_some() {
+                return Ok(false);
+            }
+            response.decision = decision.to_string();
+            response.message = message.map(String::from);
+            response.created_at = now.to_string();
+            return Ok(true);
+        }
+        tables.responses.insert(
+            notification.id.clone(),
+            Response {
+                id: id.to_string(),
+                session_id: notification.session_id.clone(),
+                decision: decision.to_string(),
+                message: message.map(String::from),
+                created_at: now.to_string(),
+                delivered_at: None,
+            },
+        );
+        Ok(true)
+    }
+
+    fn get_response_for_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<Option<PermissionResponse>, AppError> {
+        Ok(self
+            .tables()
+            .responses
+            .get(notification_id)
+            .map(|response| permission_response(notification_id, response)))
+    }
+
+    fn take_pending_responses(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        now: &str,
+    ) -> Result<Vec<PermissionResponse>, AppError> {
+        let mut tables = self.tables();
+        let visible = tables
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| tables.visible(partition, &session.device_id));
+        if !visible {
+            return Ok(Vec::new());
+        }
+        let mut pending: Vec<PermissionResponse> = tables
+            .responses
+            .iter()
+            .filter(|(_, r)| r.session_id == session_id && r.delivered_at.is_none())
+            .map(|(notification_id, r)| permission_response(notification_id, r))
+            .collect();
+        pending.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        for response in &pending {
+            if let Some(stored) = tables.responses.get_mut(&response.notification_id) {
+                stored.delivered_at = Some(now.to_string());
+            }
+        }
+        Ok(pending)
+    }
+
+    fn delete_expired_responses(&self, ttl_hours: u64) -> Result<usize, AppError> {
+        let cutoff = hours_ago(ttl_hours)?;
+        let mut tables = self.tables();
+        let before = tables.responses.len();
+        tables.responses.retain(|_, r| r.created_at >= cutoff);
+        Ok(before - tables.responses.len())
+    }
+
+    fn upsert_push_token(
+        &self,
+        platform: &str,
+        push_token: &str,
+        now: &str,
+        sandbox: Option<bool>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        let token = tables
+            .push_tokens
+            .entry(push_token.to_string())
+            .or_insert_with(|| PushToken {
+                platform: platform.to_string(),
+                last_seen: now.to_string(),
+                last_success: None,
+                sandbox: false,
+                sandbox_tagged: false,
+                partition: None,
+            });
+        token.platform = platform.to_string();
+        token.last_seen = now.to_string();
+        if let Some(sandbox) = sandbox {
+            token.sandbox = sandbox;
+            token.sandbox_tagged = true;
+        }
+        token.partition = partition.map(String::from);
+        Ok(())
+    }
+
+    fn list_push_tokens(&self, partition: &Partition) -> Result<Vec<PushTokenRow>, AppError> {
+        Ok(self
+            .tables()
+            .push_tokens
+            .iter()
+            .filter(|(_, token)| partition.allows(token.partition.as_deref()))
+            .map(|(push_token, token)| PushTokenRow {
+                push_token: push_token.clone(),
+                platform: token.platform.clone(),
+                sandbox: token.sandbox_tagged.then_some(token.sandbox),
+            })
+            .collect())
+    }
+
+    fn delete_push_token(&self, push_token: &str) -> Result<(), AppError> {
+        self.tables().push_tokens.remove(push_token);
+        Ok(())
+    }
+
+    fn record_push_success(&self, push_token: &str, now: &str) -> Result<(), AppError> {
+        if let Some(token) = self.tables().push_tokens.get_mut(push_token) {
+            token.last_success = Some(now.to_string());
+        }
+        Ok(())
+    }
+
+    fn delete_stale_push_tokens(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = days_ago(retention_days)?;
+        let mut tables = self.tables();
+        let before = tables.push_tokens.len();
+        tables.push_tokens.retain(|_, token| {
+            token.last_seen >= cutoff
+                || token
+                    .last_success
+                    .as_ref()
+                    .is_some_and(|success| *success >= cutoff)
+        });
+        Ok(before - tables.push_tokens.len())
+    }
+
+    fn insert_delivery_failure(&self, failure: &DeliveryFailure) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if tables.delivery_failures.contains_key(&failure.id) {
+            return Err(constraint_failed("insert delivery failure", "UNIQUE"));
+        }
+        tables
+            .delivery_failures
+            .insert(failure.id.clone(), failure.clone());
+        Ok(())
+    }
+
+    fn list_delivery_failures(&self, limit: i64) -> Result<Vec<DeliveryFailure>, AppError> {
+        let mut failures: Vec<DeliveryFailure> =
+            self.tables().delivery_failures.values().cloned().collect();
+        failures.sort_by(|a, b| b.last_attempt_at.cmp(&a.last_attempt_at));
+        failures.truncate(self::limit(limit));
+        Ok(failures)
+    }
+
+    fn get_delivery_failure(&self, id: &str) -> Result<Option<DeliveryFailure>, AppError> {
+        Ok(self.tables().delivery_failures.get(id).cloned())
+    }
+
+    fn record_delivery_retry_failure(
+        &self,
+        id: &str,
+        error: &str,
+        now: &str,
+    ) -> Result<(), AppError> {
+        if let Some(failure) = self.tables().delivery_failures.get_mut(id) {
+            failure.error = error.to_string();
+            failure.attempts += 1;
+            failure.last_attempt_at = now.to_string();
+        }
+        Ok(())
+    }
+
+    fn delete_delivery_failure(&self, id: &str) -> Result<(), AppError> {
+        self.tables().delivery_failures.remove(id);
+        Ok(())
+    }
+
+    fn delete_orphaned_delivery_failures(&self) -> Result<usize, AppError> {
+        let mut tables = self.tables();
+        let Tables {
+            ref notifications,
+            ref mut delivery_failures,
+            ..
+        } = *tables;
+        let before = delivery_failures.len();
+        delivery_failures.retain(|_, failure| notifications.contains_key(&failure.notification_id));
+        Ok(before - delivery_failures.len())
+    }
+
+    fn insert_api_key(
+        &self,
+        id: &str,
+        name: &str,
+        key: &str,
+        scopes: &str,
+        created_at: &str,
+        rate_limit: Option<i64>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if tables.api_keys.contains_key(id) || tables.api_keys.values().any(|k| k.key == key) {
+            return Err(constraint_failed("insert api key", "UNIQUE"));
+        }
+        tables.api_keys.insert(
+            id.to_string(),
+            ApiKey {
+                name: name.to_string(),
+                key: key.to_string(),
+                scopes: scopes.to_string(),
+                created_at: created_at.to_string(),
+                last_used: None,
+                rate_limit,
+                partition: partition.map(String::from),
+                device_id: None,
+                privacy_mode: false,
+            },
+        );
+        Ok(())
+    }
+
+    fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, AppError> {
+        let mut keys: Vec<ApiKeyRow> = self
+            .tables()
+            .api_keys
+            .iter()
+            .map(|(id, key)| api_key_row(id, key))
+            .collect();
+        keys.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Ok(keys)
+    }
+
+    fn find_api_key_by_key(&self, key: &str) -> Result<Option<ApiKeyRow>, AppError> {
+        Ok(self
+            .tables()
+            .api_keys
+            .iter()
+            .find(|(_, row)| row.key == key)
+            .map(|(id, row)| api_key_row(id, row)))
+    }
+
+    fn find_api_key_by_id(&self, id: &str) -> Result<Option<ApiKeyRow>, AppError> {
+        Ok(self
+            .tables()
+            .api_keys
+            .get(id)
+            .map(|row| api_key_row(id, row)))
+    }
+
+    fn delete_api_key(&self, id: &str) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        tables.api_keys.remove(id);
+        tables.key_usage.retain(|(key_id, _), _| key_id != id);
+        Ok(())
+    }
+
+    fn increment_key_usage(
+        &self,
+        key_id: &str,
+        day: &str,
+        requests: i64,
+        events: i64,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        let usage = tables
+            .key_usage
+            .entry((key_id.to_string(), day.to_string()))
+            .or_default();
+        usage.0 += requests;
+        usage.1 += events;
+        Ok(())
+    }
+
+    fn list_key_usage(&self, key_id: &str, since_day: &str) -> Result<Vec<KeyUsageRow>, AppError> {
+        Ok(self
+            .tables()
+            .key_usage
+            .iter()
+            .rev()
+            .filter(|((id, day), _)| id == key_id && day.as_str() >= since_day)
+            .map(|((_, day), (requests, events))| KeyUsageRow {
+                day: day.clone(),
+                requests: *requests,
+                events: *events,
+            })
+            .collect())
+    }
+
+    fn set_api_key_device(&self, id: &str, device_id: &str) -> Result<(), AppError> {
+        if let Some(key) = self.tables().api_keys.get_mut(id) {
+            key.device_id = Some(device_id.to_string());
+        }
+        Ok(())
+    }
+
+    fn set_api_key_privacy_mode(&self, id: &str, privacy_mode: bool) -> Result<(), AppError> {
+        if let Some(key) = self.tables().api_keys.get_mut(id) {
+            key.privacy_mode = privacy_mode;
+        }
+        Ok(())
+    }
+
+    fn update_api_key_last_used(&self, id: &str, now: &str) -> Result<(), AppError> {
+        if let Some(key) = self.tables().api_keys.get_mut(id) {
+            key.last_used = Some(now.to_string());
+        }
+        Ok(())
+    }
+
+    fn insert_enrollment_code(
+        &self,
+        code: &str,
+        partition: Option<&str>,
+        _created_at: &str,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        let mut tables = self.tables();
+        if tables.enrollment_codes.contains_key(code) {
+            return Err(constraint_failed("insert enrollment code", "UNIQUE"));
+        }
+        tables.enrollment_codes.insert(
+            code.to_string(),
+            EnrollmentCode {
+                partition: partition.map(String::from),
+                expires_at: expires_at.to_string(),
+                used_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    fn claim_enrollment_code(
+        &self,
+        code: &str,
+        _key_id: &str,
+        now: &str,
+    ) -> Result<Option<Partition>, AppError> {
+        let mut tables = self.tables();
+        let Some(enrollment) = tables.enrollment_codes.get_mut(code) else {
+            return Ok(None);
+        };
+        if enrollment.used_at.is_some() || enrollment.expires_at.as_str() <= now {
+            return Ok(None);
+        }
+        enrollment.used_at = Some(now.to_string());
+        Ok(Some(Partition::Key(enrollment.partition.clone())))
+    }
+
+    fn get_metadata(&self, key: &str) -> Result<Option<String>, AppError> {
+        Ok(self.tables().metadata.get(key).cloned())
+    }
+
+    fn set_metadata(&self, key: &str, value: &str) -> Result<(), AppError> {
+        self.tables()
+            .metadata
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn run_maintenance(&self) -> Result<MaintenanceStats, AppError> {
+        Ok(MaintenanceStats {
+            page_count_before: 0,
+            page_count_after: 0,
+            freelist_count_before: 0,
+        })
+    }
+
+    fn full_vacuum(&self) -> Result<MaintenanceStats, AppError> {
+        self.run_maintenance()
+    }
+
+    fn atomically(
+        &self,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        // `f` writes to a copy, swapped in only if it succeeds. The lock is
+        // held throughout so transactions don't interleave.
+        let mut tables = self.tables();
+        let scratch = Self {
+            tables: Mutex::new(tables.clone()),
+        };
+        f(&scratch)?;
+        *tables = scratch
+            .tables
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(())
+    }
+
+    fn pinned(
+        &self,
+        _interrupt: &Interrupt,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        f(self)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::db::queries::search_expression;
+    use crate::storage::transaction;
+
+    #[test]
+    fn test_atomically_discards_writes_on_error() {
+        let storage = MemoryStorage::new();
+        let result = transaction(&storage, |tx| {
+            tx.set_metadata("kept", "no")?;
+            Err::<(), _>(AppError::Internal("fail".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(storage.get_metadata("kept").unwrap(), None);
+
+        transaction(&storage, |tx| tx.set_metadata("kept", "yes")).unwrap();
+        assert_eq!(
+            storage.get_metadata("kept").unwrap().as_deref(),
+            Some("yes")
+        );
+    }
+
+    #[test]
+    fn test_search_matches_every_word() {
+        let storage = MemoryStorage::new();
+        let now = "2024-01-01T00:00:00.000Z";
+        storage
+            .upsert_device("dev-1", "Laptop", "mac", None, now)
+            .unwrap();
+        storage
+            .upsert_session("sess-1", "dev-1", now, None, None, Some("Fix the Parser"))
+            .unwrap();
+        storage
+            .upsert_session("sess-2", "dev-1", now, None, None, Some("Fix the lexer"))
+            .unwrap();
+
+        let expression = search_expression("parser fix").unwrap();
+        let hits = storage
+            .search_sessions(&Partition::All, &expression, 10, 0)
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].session_id, "sess-1");
+        assert_eq!(hits[0].snippet, "**Fix** the **Parser**");
+    }
+
+    #[test]
+    fn test_search_terms_unquote_expression() {
+        let expression = search_expression("say \"hi\" NEAR").unwrap();
+        assert_eq!(search_terms(&expression), ["say", "\"hi\"", "near"]);
+    }
+}
@@ -0,0 +1,387 @@
+//! Where handlers, ingestion, and the background tasks read and write data.
+//!
+//! [`Storage`] has one method per function in [`db::queries`](crate::db::queries),
+//! taking the same arguments minus the connection and behaving the same
+//! way. The server runs on [`SqliteStorage`]; [`MemoryStorage`] keeps
+//! everything in process, so tests can run without a database file, and
+//! shows what another backend has to provide.
+
+#![allow(clippy::missing_errors_doc)]
+
+#[allow(dead_code)] // the server runs on SQLite; tests use this
+pub mod memory;
+pub mod sqlite;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::db::partition::Partition;
+use crate::db::pool::DbPool;
+use crate::db::queries::{
+    ApiKeyRow, EventFilter, KeyUsageRow, MaintenanceStats, NotificationTtl, PaginatedSessions,
+    PushTokenRow,
+};
+use crate::error::AppError;
+use crate::models::response::{
+    ArchivedEventResponse, DeliveryFailure, DeviceResponse, EventDetailResponse, EventResponse,
+    EventSearchHit, NotificationResponse, PermissionResponse, SessionResponse, SessionSearchHit,
+    SessionStatusCounts, TimelineEvent,
+};
+use crate::time_range::TimeRange;
+
+#[allow(unused_imports)]
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+/// The storage shared through [`AppState`](crate::router::AppState).
+pub type SharedStorage = Arc<dyn Storage + Send + Sync>;
+
+/// Stops whatever a [`Storage::pinned`] call is running. Empty while
+/// nothing is running, or when the backend can't be interrupted.
+pub type Interrupt = Mutex<Option<Box<dyn Fn() + Send>>>;
+
+/// Locks `interrupt`, ignoring poisoning: the slot holds no invariant a
+/// panic could break.
+pub fn lock_interrupt(interrupt: &Interrupt) -> MutexGuard<'_, Option<Box<dyn Fn() + Send>>> {
+    interrupt.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub trait Storage {
+    // Devices
+    fn upsert_device(
+        &self,
+        device_id: &str,
+        device_name: &str,
+        platform: &str,
+        partition: Option<&str>,
+        now: &str,
+    ) -> Result<(), AppError>;
+    fn device_last_heartbeat(&self, device_id: &str) -> Result<Option<String>, AppError>;
+    fn set_device_heartbeat(&self, device_id: &str, now: &str) -> Result<(), AppError>;
+    fn set_device_hook_version(&self, device_id: &str, hook_version: &str) -> Result<(), AppError>;
+    fn set_device_clock_skew(&self, device_id: &str, clock_skew_ms: i64) -> Result<(), AppError>;
+    fn min_device_skew(&self, device_id: &str, since: &str) -> Result<Option<i64>, AppError>;
+    fn claim_outdated_hook_notice(&self, device_id: &str, day: &str) -> Result<bool, AppError>;
+    fn device_partition(&self, device_id: &str) -> Result<Option<Partition>, AppError>;
+    fn list_devices(
+        &self,
+        partition: &Partition,
+        events_since: &str,
+    ) -> Result<Vec<DeviceResponse>, AppError>;
+    fn delete_stale_devices(&self, retention_days: u64) -> Result<usize, AppError>;
+
+    // Sessions
+    fn session_partition(&self, session_id: &str) -> Result<Option<Partition>, AppError>;
+    fn upsert_session(
+        &self,
+        session_id: &str,
+        device_id: &str,
+        now: &str,
+        status: Option<&str>,
+        cwd: Option<&str>,
+        title: Option<&str>,
+    ) -> Result<(), AppError>;
+    fn set_session_title(&self, session_id: &str, title: &str) -> Result<(), AppError>;
+    fn set_session_parent(
+        &self,
+        session_id: &str,
+        parent_session_id: &str,
+        agent_type: Option<&str>,
+    ) -> Result<(), AppError>;
+    fn set_session_team(&self, session_id: &str, team_name: &str) -> Result<(), AppError>;
+    fn set_session_model(&self, session_id: &str, model: &str) -> Result<(), AppError>;
+    fn list_sessions(
+        &self,
+        partition: &Partition,
+        device_id: &str,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<SessionResponse>, AppError>;
+    #[allow(clippy::too_many_arguments)]
+    fn list_all_sessions_paginated(
+        &self,
+        partition: &Partition,
+        status: Option<&str>,
+        cwd_prefix: Option<&str>,
+        team: Option<&str>,
+        exclude_ended: bool,
+        range: &TimeRange,
+        limit: i64,
+        offset: i64,
+    ) -> Result<PaginatedSessions, AppError>;
+    fn get_session(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+    ) -> Result<Option<SessionResponse>, AppError>;
+    fn list_subagent_sessions(
+        &self,
+        partition: &Partition,
+        parent_session_id: &str,
+    ) -> Result<Vec<SessionResponse>, AppError>;
+    fn count_sessions_by_status(
+        &self,
+        partition: &Partition,
+    ) -> Result<SessionStatusCounts, AppError>;
+    fn count_sessions_by_model(
+        &self,
+        partition: &Partition,
+    ) -> Result<BTreeMap<String, i64>, AppError>;
+    fn most_urgent_session(
+        &self,
+        partition: &Partition,
+    ) -> Result<Option<SessionResponse>, AppError>;
+    fn set_session_pinned(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        pinned: bool,
+    ) -> Result<(), AppError>;
+    fn get_session_title(&self, session_id: &str) -> Result<Option<String>, AppError>;
+    fn delete_stale_sessions(&self, retention_days: u64) -> Result<usize, AppError>;
+
+    // Events
+    #[allow(clippy::too_many_arguments)]
+    fn insert_event(
+        &self,
+        device_id: &str,
+        session_id: &str,
+        hook_event_name: &str,
+        timestamp: &str,
+        received_at: &str,
+        tool_name: Option<&str>,
+        notification_type: Option<&str>,
+        event_json: &str,
+    ) -> Result<i64, AppError>;
+    fn set_event_clock(
+        &self,
+        event_id: i64,
+        skew_ms: i64,
+        normalized_timestamp: &str,
+    ) -> Result<(), AppError>;
+    fn set_event_team_task(
+        &self,
+        event_id: i64,
+        team_name: Option<&str>,
+        task_id: Option<&str>,
+    ) -> Result<(), AppError>;
+    fn list_events(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        filter: &EventFilter,
+        limit: i64,
+    ) -> Result<Vec<EventResponse>, AppError>;
+    /// `expression` comes from [`search_expression`](crate::db::queries::search_expression).
+    fn search_sessions(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SessionSearchHit>, AppError>;
+    /// `expression` comes from [`search_expression`](crate::db::queries::search_expression).
+    fn search_events(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EventSearchHit>, AppError>;
+    fn list_session_timeline(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        limit: i64,
+    ) -> Result<Vec<TimelineEvent>, AppError>;
+    fn get_event(
+        &self,
+        partition: &Partition,
+        id: i64,
+    ) -> Result<Option<EventDetailResponse>, AppError>;
+    fn delete_old_events(&self, retention_days: u64) -> Result<usize, AppError>;
+    fn archive_old_events(&self, retention_days: u64) -> Result<usize, AppError>;
+    fn list_archived_events(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<ArchivedEventResponse>, AppError>;
+
+    // Notifications and permission responses
+    #[allow(clippy::too_many_arguments)]
+    fn insert_notification(
+        &self,
+        id: &str,
+        event_id: i64,
+        session_id: &str,
+        device_id: &str,
+        title: &str,
+        body: &str,
+        notification_type: &str,
+        payload_json: Option<&str>,
+        created_at: &str,
+    ) -> Result<(), AppError>;
+    fn list_notifications(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        after_timestamp: Option<&str>,
+        range: &TimeRange,
+        limit: i64,
+    ) -> Result<Vec<NotificationResponse>, AppError>;
+    fn get_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+    ) -> Result<Option<NotificationResponse>, AppError>;
+    fn delete_expired_notifications(&self, ttl: &NotificationTtl) -> Result<usize, AppError>;
+    fn acknowledge_notifications(
+        &self,
+        partition: &Partition,
+        ids: &[String],
+    ) -> Result<(), AppError>;
+    fn acknowledge_notifications_matching(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        before: Option<&str>,
+    ) -> Result<usize, AppError>;
+    fn snooze_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+        until: &str,
+    ) -> Result<bool, AppError>;
+    fn take_due_snoozes(&self, now: &str) -> Result<Vec<NotificationResponse>, AppError>;
+    fn upsert_response(
+        &self,
+        id: &str,
+        notification: &NotificationResponse,
+        decision: &str,
+        message: Option<&str>,
+        now: &str,
+    ) -> Result<bool, AppError>;
+    fn get_response_for_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<Option<PermissionResponse>, AppError>;
+    fn take_pending_responses(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        now: &str,
+    ) -> Result<Vec<PermissionResponse>, AppError>;
+    fn delete_expired_responses(&self, ttl_hours: u64) -> Result<usize, AppError>;
+
+    // Push tokens and delivery failures
+    fn upsert_push_token(
+        &self,
+        platform: &str,
+        push_token: &str,
+        now: &str,
+        sandbox: Option<bool>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError>;
+    fn list_push_tokens(&self, partition: &Partition) -> Result<Vec<PushTokenRow>, AppError>;
+    fn delete_push_token(&self, push_token: &str) -> Result<(), AppError>;
+    fn record_push_success(&self, push_token: &str, now: &str) -> Result<(), AppError>;
+    fn delete_stale_push_tokens(&self, retention_days: u64) -> Result<usize, AppError>;
+    fn insert_delivery_failure(&self, failure: &DeliveryFailure) -> Result<(), AppError>;
+    fn list_delivery_failures(&self, limit: i64) -> Result<Vec<DeliveryFailure>, AppError>;
+    fn get_delivery_failure(&self, id: &str) -> Result<Option<DeliveryFailure>, AppError>;
+    fn record_delivery_retry_failure(
+        &self,
+        id: &str,
+        error: &str,
+        now: &str,
+    ) -> Result<(), AppError>;
+    fn delete_delivery_failure(&self, id: &str) -> Result<(), AppError>;
+    fn delete_orphaned_delivery_failures(&self) -> Result<usize, AppError>;
+
+    // API keys and enrollment
+    #[allow(clippy::too_many_arguments)]
+    fn insert_api_key(
+        &self,
+        id: &str,
+        name: &str,
+        key: &str,
+        scopes: &str,
+        created_at: &str,
+        rate_limit: Option<i64>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError>;
+    fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, AppError>;
+    fn find_api_key_by_key(&self, key: &str) -> Result<Option<ApiKeyRow>, AppError>;
+    fn find_api_key_by_id(&self, id: &str) -> Result<Option<ApiKeyRow>, AppError>;
+    fn delete_api_key(&self, id: &str) -> Result<(), AppError>;
+    fn increment_key_usage(
+        &self,
+        key_id: &str,
+        day: &str,
+        requests: i64,
+        events: i64,
+    ) -> Result<(), AppError>;
+    fn list_key_usage(&self, key_id: &str, since_day: &str) -> Result<Vec<KeyUsageRow>, AppError>;
+    fn set_api_key_device(&self, id: &str, device_id: &str) -> Result<(), AppError>;
+    fn set_api_key_privacy_mode(&self, id: &str, privacy_mode: bool) -> Result<(), AppError>;
+    fn update_api_key_last_used(&self, id: &str, now: &str) -> Result<(), AppError>;
+    fn insert_enrollment_code(
+        &self,
+        code: &str,
+        partition: Option<&str>,
+        created_at: &str,
+        expires_at: &str,
+    ) -> Result<(), AppError>;
+    fn claim_enrollment_code(
+        &self,
+        code: &str,
+        key_id: &str,
+        now: &str,
+    ) -> Result<Option<Partition>, AppError>;
+
+    // Metadata and maintenance
+    fn get_metadata(&self, key: &str) -> Result<Option<String>, AppError>;
+    fn set_metadata(&self, key: &str, value: &str) -> Result<(), AppError>;
+    fn run_maintenance(&self) -> Result<MaintenanceStats, AppError>;
+    fn full_vacuum(&self) -> Result<MaintenanceStats, AppError>;
+
+    /// Runs `f` in one transaction: every write it makes through the
+    /// storage it is given is kept if it returns `Ok`, and none otherwise.
+    /// Prefer [`transaction`], which returns `f`'s value.
+    fn atomically(
+        &self,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError>;
+
+    /// Runs `f` against storage that can be stopped from another thread:
+    /// while `f` runs, `interrupt` holds a callback that makes its current
+    /// and later queries fail. The callback is removed before this returns.
+    fn pinned(
+        &self,
+        interrupt: &Interrupt,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError>;
+
+    /// The connection pool behind this storage, for `GET /admin/metrics`;
+    /// `None` for backends without one.
+    fn pool(&self) -> Option<&DbPool> {
+        None
+    }
+}
+
+/// Runs `f` in one transaction of `storage` and returns its value; see
+/// [`Storage::atomically`].
+pub fn transaction<T>(
+    storage: &dyn Storage,
+    f: impl FnOnce(&dyn Storage) -> Result<T, AppError>,
+) -> Result<T, AppError> {
+    let mut f = Some(f);
+    let mut value = None;
+    storage.atomically(&mut |tx| {
+        if let Some(f) = f.take() {
+            value = Some(f(tx)?);
+        }
+        Ok(())
+    })?;
+    value.ok_or_else(|| AppError::Internal("Transaction did not run".to_string()))
+}
@@ -0,0 +1,771 @@
+//! [`Storage`] on the `SQLite` database, through [`db::queries`](crate::db::queries).
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+
+use super::{lock_interrupt, Interrupt, Storage};
+use crate::db::partition::Partition;
+use crate::db::pool::DbPool;
+use crate::db::queries::{
+    self, ApiKeyRow, EventFilter, KeyUsageRow, MaintenanceStats, NotificationTtl,
+    PaginatedSessions, PushTokenRow,
+};
+use crate::error::AppError;
+use crate::models::response::{
+    ArchivedEventResponse, DeliveryFailure, DeviceResponse, EventDetailResponse, EventResponse,
+    EventSearchHit, NotificationResponse, PermissionResponse, SessionResponse, SessionSearchHit,
+    SessionStatusCounts, TimelineEvent,
+};
+use crate::time_range::TimeRange;
+
+type PooledSqlite = PooledConnection<SqliteConnectionManager>;
+
+/// Storage on a connection pool. Each call checks out its own connection,
+/// except within [`Storage::atomically`] and [`Storage::pinned`], which
+/// hold one for the whole closure.
+pub struct SqliteStorage {
+    pool: DbPool,
+    /// The connection every call uses, when one is held.
+    held: Option<Mutex<PooledSqlite>>,
+}
+
+impl SqliteStorage {
+    pub const fn new(pool: DbPool) -> Self {
+        Self { pool, held: None }
+    }
+
+    fn holding(&self, conn: PooledSqlite) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            held: Some(Mutex::new(conn)),
+        }
+    }
+
+    fn checkout(&self) -> Result<PooledSqlite, AppError> {
+        self.pool
+            .get()
+            .map_err(|e| AppError::Internal(format!("Database pool error: {e}")))
+    }
+
+    /// Runs `f` on the held connection, or on one checked out for it.
+    fn with<T>(&self, f: impl FnOnce(&Connection) -> Result<T, AppError>) -> Result<T, AppError> {
+        match self.held {
+            Some(ref conn) => f(&lock(conn)),
+            None => f(&*self.checkout()?),
+        }
+    }
+
+    /// Runs `f` on a held connection in a transaction, joining the one
+    /// already open on it if there is one.
+    fn in_transaction(
+        &self,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        if !self.with(|conn| Ok(conn.is_autocommit()))? {
+            return f(self);
+        }
+        self.with(|conn| {
+            conn.execute_batch("BEGIN")
+                .map_err(|e| AppError::Internal(format!("Transaction begin failed: {e}")))
+        })?;
+        let result = f(self).and_then(|()| {
+            self.with(|conn| {
+                conn.execute_batch("COMMIT")
+                    .map_err(|e| AppError::Internal(format!("Transaction commit failed: {e}")))
+            })
+        });
+        if result.is_err() {
+            // The connection goes back to the pool, so it mustn't stay in
+            // the transaction
+            let _ = self.with(|conn| {
+                if !conn.is_autocommit() {
+                    let _ = conn.execute_batch("ROLLBACK");
+                }
+                Ok(())
+            });
+        }
+        result
+    }
+
+    /// [`Storage::pinned`], with `f` given the pinned storage itself.
+    fn pin<T>(
+        &self,
+        interrupt: &Interrupt,
+        f: impl FnOnce(&Self) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let conn = self.checkout()?;
+        let handle = conn.get_interrupt_handle();
+        *lock_interrupt(interrupt) = Some(Box::new(move || handle.interrupt()));
+        let pinned = self.holding(conn);
+        let result = f(&pinned);
+        // Disarmed before the connection goes back to the pool, so a late
+        // interrupt can't stop the next query run on it
+        lock_interrupt(interrupt).take();
+        drop(pinned);
+        result
+    }
+}
+
+fn lock(conn: &Mutex<PooledSqlite>) -> MutexGuard<'_, PooledSqlite> {
+    conn.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Storage for SqliteStorage {
+    fn atomically(
+        &self,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        if self.held.is_some() {
+            self.in_transaction(f)
+        } else {
+            self.holding(self.checkout()?).in_transaction(f)
+        }
+    }
+
+    fn pinned(
+        &self,
+        interrupt: &Interrupt,
+        f: &mut dyn FnMut(&dyn Storage) -> Result<(), AppError>,
+    ) -> Result<(), AppError> {
+        self.pin(interrupt, |pinned| f(pinned))
+    }
+
+    fn pool(&self) -> Option<&DbPool> {
+        Some(&self.pool)
+    }
+
+    fn upsert_device(
+        &self,
+        device_id: &str,
+        device_name: &str,
+        platform: &str,
+        partition: Option<&str>,
+        now: &str,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::upsert_device(conn, device_id, device_name, platform, partition, now)
+        })
+    }
+
+    fn device_last_heartbeat(&self, device_id: &str) -> Result<Option<String>, AppError> {
+        self.with(|conn| queries::device_last_heartbeat(conn, device_id))
+    }
+
+    fn set_device_heartbeat(&self, device_id: &str, now: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_device_heartbeat(conn, device_id, now))
+    }
+
+    fn set_device_hook_version(&self, device_id: &str, hook_version: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_device_hook_version(conn, device_id, hook_version))
+    }
+
+    fn set_device_clock_skew(&self, device_id: &str, clock_skew_ms: i64) -> Result<(), AppError> {
+        self.with(|conn| queries::set_device_clock_skew(conn, device_id, clock_skew_ms))
+    }
+
+    fn min_device_skew(&self, device_id: &str, since: &str) -> Result<Option<i64>, AppError> {
+        self.with(|conn| queries::min_device_skew(conn, device_id, since))
+    }
+
+    fn claim_outdated_hook_notice(&self, device_id: &str, day: &str) -> Result<bool, AppError> {
+        self.with(|conn| queries::claim_outdated_hook_notice(conn, device_id, day))
+    }
+
+    fn device_partition(&self, device_id: &str) -> Result<Option<Partition>, AppError> {
+        self.with(|conn| queries::device_partition(conn, device_id))
+    }
+
+    fn list_devices(
+        &self,
+        partition: &Partition,
+        events_since: &str,
+    ) -> Result<Vec<DeviceResponse>, AppError> {
+        self.with(|conn| queries::list_devices(conn, partition, events_since))
+    }
+
+    fn delete_stale_devices(&self, retention_days: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_stale_devices(conn, retention_days))
+    }
+
+    fn session_partition(&self, session_id: &str) -> Result<Option<Partition>, AppError> {
+        self.with(|conn| queries::session_partition(conn, session_id))
+    }
+
+    fn upsert_session(
+        &self,
+        session_id: &str,
+        device_id: &str,
+        now: &str,
+        status: Option<&str>,
+        cwd: Option<&str>,
+        title: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::upsert_session(conn, session_id, device_id, now, status, cwd, title)
+        })
+    }
+
+    fn set_session_title(&self, session_id: &str, title: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_session_title(conn, session_id, title))
+    }
+
+    fn set_session_parent(
+        &self,
+        session_id: &str,
+        parent_session_id: &str,
+        agent_type: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::set_session_parent(conn, session_id, parent_session_id, agent_type)
+        })
+    }
+
+    fn set_session_team(&self, session_id: &str, team_name: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_session_team(conn, session_id, team_name))
+    }
+
+    fn set_session_model(&self, session_id: &str, model: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_session_model(conn, session_id, model))
+    }
+
+    fn list_sessions(
+        &self,
+        partition: &Partition,
+        device_id: &str,
+        status: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<SessionResponse>, AppError> {
+        self.with(|conn| queries::list_sessions(conn, partition, device_id, status, limit))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn list_all_sessions_paginated(
+        &self,
+        partition: &Partition,
+        status: Option<&str>,
+        cwd_prefix: Option<&str>,
+        team: Option<&str>,
+        exclude_ended: bool,
+        range: &TimeRange,
+        limit: i64,
+        offset: i64,
+    ) -> Result<PaginatedSessions, AppError> {
+        self.with(|conn| {
+            queries::list_all_sessions_paginated(
+                conn,
+                partition,
+                status,
+                cwd_prefix,
+                team,
+                exclude_ended,
+                range,
+                limit,
+                offset,
+            )
+        })
+    }
+
+    fn get_session(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+    ) -> Result<Option<SessionResponse>, AppError> {
+        self.with(|conn| queries::get_session(conn, partition, session_id))
+    }
+
+    fn list_subagent_sessions(
+        &self,
+        partition: &Partition,
+        parent_session_id: &str,
+    ) -> Result<Vec<SessionResponse>, AppError> {
+        self.with(|conn| queries::list_subagent_sessions(conn, partition, parent_session_id))
+    }
+
+    fn count_sessions_by_status(
+        &self,
+        partition: &Partition,
+    ) -> Result<SessionStatusCounts, AppError> {
+        self.with(|conn| queries::count_sessions_by_status(conn, partition))
+    }
+
+    fn count_sessions_by_model(
+        &self,
+        partition: &Partition,
+    ) -> Result<BTreeMap<String, i64>, AppError> {
+        self.with(|conn| queries::count_sessions_by_model(conn, partition))
+    }
+
+    fn most_urgent_session(
+        &self,
+        partition: &Partition,
+    ) -> Result<Option<SessionResponse>, AppError> {
+        self.with(|conn| queries::most_urgent_session(conn, partition))
+    }
+
+    fn set_session_pinned(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        pinned: bool,
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::set_session_pinned(conn, partition, session_id, pinned))
+    }
+
+    fn get_session_title(&self, session_id: &str) -> Result<Option<String>, AppError> {
+        self.with(|conn| queries::get_session_title(conn, session_id))
+    }
+
+    fn delete_stale_sessions(&self, retention_days: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_stale_sessions(conn, retention_days))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_event(
+        &self,
+        device_id: &str,
+        session_id: &str,
+        hook_event_name: &str,
+        timestamp: &str,
+        received_at: &str,
+        tool_name: Option<&str>,
+        notification_type: Option<&str>,
+        event_json: &str,
+    ) -> Result<i64, AppError> {
+        self.with(|conn| {
+            queries::insert_event(
+                conn,
+                device_id,
+                session_id,
+                hook_event_name,
+                timestamp,
+                received_at,
+                tool_name,
+                notification_type,
+                event_json,
+            )
+        })
+    }
+
+    fn set_event_clock(
+        &self,
+        event_id: i64,
+        skew_ms: i64,
+        normalized_timestamp: &str,
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::set_event_clock(conn, event_id, skew_ms, normalized_timestamp))
+    }
+
+    fn set_event_team_task(
+        &self,
+        event_id: i64,
+        team_name: Option<&str>,
+        task_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::set_event_team_task(conn, event_id, team_name, task_id))
+    }
+
+    fn list_events(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        filter: &EventFilter,
+        limit: i64,
+    ) -> Result<Vec<EventResponse>, AppError> {
+        self.with(|conn| queries::list_events(conn, partition, session_id, filter, limit))
+    }
+
+    fn search_sessions(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SessionSearchHit>, AppError> {
+        self.with(|conn| queries::search_sessions(conn, partition, expression, limit, offset))
+    }
+
+    fn search_events(
+        &self,
+        partition: &Partition,
+        expression: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<EventSearchHit>, AppError> {
+        self.with(|conn| queries::search_events(conn, partition, expression, limit, offset))
+    }
+
+    fn list_session_timeline(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        limit: i64,
+    ) -> Result<Vec<TimelineEvent>, AppError> {
+        self.with(|conn| queries::list_session_timeline(conn, partition, session_id, limit))
+    }
+
+    fn get_event(
+        &self,
+        partition: &Partition,
+        id: i64,
+    ) -> Result<Option<EventDetailResponse>, AppError> {
+        self.with(|conn| queries::get_event(conn, partition, id))
+    }
+
+    fn delete_old_events(&self, retention_days: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_old_events(conn, retention_days))
+    }
+
+    fn archive_old_events(&self, retention_days: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::archive_old_events(conn, retention_days))
+    }
+
+    fn list_archived_events(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<ArchivedEventResponse>, AppError> {
+        self.with(|conn| queries::list_archived_events(conn, after_id, limit))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_notification(
+        &self,
+        id: &str,
+        event_id: i64,
+        session_id: &str,
+        device_id: &str,
+        title: &str,
+        body: &str,
+        notification_type: &str,
+        payload_json: Option<&str>,
+        created_at: &str,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::insert_notification(
+                conn,
+                id,
+                event_id,
+                session_id,
+                device_id,
+                title,
+                body,
+                notification_type,
+                payload_json,
+                created_at,
+            )
+        })
+    }
+
+    fn list_notifications(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        after_timestamp: Option<&str>,
+        range: &TimeRange,
+        limit: i64,
+    ) -> Result<Vec<NotificationResponse>, AppError> {
+        self.with(|conn| {
+            queries::list_notifications(conn, partition, session_id, after_timestamp, range, limit)
+        })
+    }
+
+    fn get_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+    ) -> Result<Option<NotificationResponse>, AppError> {
+        self.with(|conn| queries::get_notification(conn, partition, id))
+    }
+
+    fn delete_expired_notifications(&self, ttl: &NotificationTtl) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_expired_notifications(conn, ttl))
+    }
+
+    fn acknowledge_notifications(
+        &self,
+        partition: &Partition,
+        ids: &[String],
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::acknowledge_notifications(conn, partition, ids))
+    }
+
+    fn acknowledge_notifications_matching(
+        &self,
+        partition: &Partition,
+        session_id: Option<&str>,
+        before: Option<&str>,
+    ) -> Result<usize, AppError> {
+        self.with(|conn| {
+            queries::acknowledge_notifications_matching(conn, partition, session_id, before)
+        })
+    }
+
+    fn snooze_notification(
+        &self,
+        partition: &Partition,
+        id: &str,
+        until: &str,
+    ) -> Result<bool, AppError> {
+        self.with(|conn| queries::snooze_notification(conn, partition, id, until))
+    }
+
+    fn take_due_snoozes(&self, now: &str) -> Result<Vec<NotificationResponse>, AppError> {
+        self.with(|conn| queries::take_due_snoozes(conn, now))
+    }
+
+    fn upsert_response(
+        &self,
+        id: &str,
+        notification: &NotificationResponse,
+        decision: &str,
+        message: Option<&str>,
+        now: &str,
+    ) -> Result<bool, AppError> {
+        self.with(|conn| queries::upsert_response(conn, id, notification, decision, message, now))
+    }
+
+    fn get_response_for_notification(
+        &self,
+        notification_id: &str,
+    ) -> Result<Option<PermissionResponse>, AppError> {
+        self.with(|conn| queries::get_response_for_notification(conn, notification_id))
+    }
+
+    fn take_pending_responses(
+        &self,
+        partition: &Partition,
+        session_id: &str,
+        now: &str,
+    ) -> Result<Vec<PermissionResponse>, AppError> {
+        self.with(|conn| queries::take_pending_responses(conn, partition, session_id, now))
+    }
+
+    fn delete_expired_responses(&self, ttl_hours: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_expired_responses(conn, ttl_hours))
+    }
+
+    fn upsert_push_token(
+        &self,
+        platform: &str,
+        push_token: &str,
+        now: &str,
+        sandbox: Option<bool>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::upsert_push_token(conn, platform, push_token, now, sandbox, partition)
+        })
+    }
+
+    fn list_push_tokens(&self, partition: &Partition) -> Result<Vec<PushTokenRow>, AppError> {
+        self.with(|conn| queries::list_push_tokens(conn, partition))
+    }
+
+    fn delete_push_token(&self, push_token: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::delete_push_token(conn, push_token))
+    }
+
+    fn record_push_success(&self, push_token: &str, now: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::record_push_success(conn, push_token, now))
+    }
+
+    fn delete_stale_push_tokens(&self, retention_days: u64) -> Result<usize, AppError> {
+        self.with(|conn| queries::delete_stale_push_tokens(conn, retention_days))
+    }
+
+    fn insert_delivery_failure(&self, failure: &DeliveryFailure) -> Result<(), AppError> {
+        self.with(|conn| queries::insert_delivery_failure(conn, failure))
+    }
+
+    fn list_delivery_failures(&self, limit: i64) -> Result<Vec<DeliveryFailure>, AppError> {
+        self.with(|conn| queries::list_delivery_failures(conn, limit))
+    }
+
+    fn get_delivery_failure(&self, id: &str) -> Result<Option<DeliveryFailure>, AppError> {
+        self.with(|conn| queries::get_delivery_failure(conn, id))
+    }
+
+    fn record_delivery_retry_failure(
+        &self,
+        id: &str,
+        error: &str,
+        now: &str,
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::record_delivery_retry_failure(conn, id, error, now))
+    }
+
+    fn delete_delivery_failure(&self, id: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::delete_delivery_failure(conn, id))
+    }
+
+    fn delete_orphaned_delivery_failures(&self) -> Result<usize, AppError> {
+        self.with(queries::delete_orphaned_delivery_failures)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn insert_api_key(
+        &self,
+        id: &str,
+        name: &str,
+        key: &str,
+        scopes: &str,
+        created_at: &str,
+        rate_limit: Option<i64>,
+        partition: Option<&str>,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::insert_api_key(
+                conn, id, name, key, scopes, created_at, rate_limit, partition,
+            )
+        })
+    }
+
+    fn list_api_keys(&self) -> Result<Vec<ApiKeyRow>, AppError> {
+        self.with(queries::list_api_keys)
+    }
+
+    fn find_api_key_by_key(&self, key: &str) -> Result<Option<ApiKeyRow>, AppError> {
+        self.with(|conn| queries::find_api_key_by_key(conn, key))
+    }
+
+    fn find_api_key_by_id(&self, id: &str) -> Result<Option<ApiKeyRow>, AppError> {
+        self.with(|conn| queries::find_api_key_by_id(conn, id))
+    }
+
+    fn delete_api_key(&self, id: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::delete_api_key(conn, id))
+    }
+
+    fn increment_key_usage(
+        &self,
+        key_id: &str,
+        day: &str,
+        requests: i64,
+        events: i64,
+    ) -> Result<(), AppError> {
+        self.with(|conn| queries::increment_key_usage(conn, key_id, day, requests, events))
+    }
+
+    fn list_key_usage(&self, key_id: &str, since_day: &str) -> Result<Vec<KeyUsageRow>, AppError> {
+        self.with(|conn| queries::list_key_usage(conn, key_id, since_day))
+    }
+
+    fn set_api_key_device(&self, id: &str, device_id: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_api_key_device(conn, id, device_id))
+    }
+
+    fn set_api_key_privacy_mode(&self, id: &str, privacy_mode: bool) -> Result<(), AppError> {
+        self.with(|conn| queries::set_api_key_privacy_mode(conn, id, privacy_mode))
+    }
+
+    fn update_api_key_last_used(&self, id: &str, now: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::update_api_key_last_used(conn, id, now))
+    }
+
+    fn insert_enrollment_code(
+        &self,
+        code: &str,
+        partition: Option<&str>,
+        created_at: &str,
+        expires_at: &str,
+    ) -> Result<(), AppError> {
+        self.with(|conn| {
+            queries::insert_enrollment_code(conn, code, partition, created_at, expires_at)
+        })
+    }
+
+    fn claim_enrollment_code(
+        &self,
+        code: &str,
+        key_id: &str,
+        now: &str,
+    ) -> Result<Option<Partition>, AppError> {
+        self.with(|conn| queries::claim_enrollment_code(conn, code, key_id, now))
+    }
+
+    fn get_metadata(&self, key: &str) -> Result<Option<String>, AppError> {
+        self.with(|conn| queries::get_metadata(conn, key))
+    }
+
+    fn set_metadata(&self, key: &str, value: &str) -> Result<(), AppError> {
+        self.with(|conn| queries::set_metadata(conn, key, value))
+    }
+
+    fn run_maintenance(&self) -> Result<MaintenanceStats, AppError> {
+        self.with(queries::run_maintenance)
+    }
+
+    fn full_vacuum(&self) -> Result<MaintenanceStats, AppError> {
+        self.with(queries::full_vacuum)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::db::{migrations, pool};
+
+    const ENDLESS_QUERY: &str = "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n) \
+                                 SELECT count(*) FROM n";
+
+    fn storage() -> SqliteStorage {
+        let pool = pool::create_memory_pool(None).unwrap();
+        migrations::run(&pool).unwrap();
+        SqliteStorage::new(pool)
+    }
+
+    #[test]
+    fn test_atomically_rolls_back_on_error() {
+        let storage = storage();
+        let result = super::super::transaction(&storage, |tx| {
+            tx.set_metadata("kept", "no")?;
+            Err::<(), _>(AppError::Internal("fail".to_string()))
+        });
+        assert!(result.is_err());
+        assert_eq!(storage.get_metadata("kept").unwrap(), None);
+
+        super::super::transaction(&storage, |tx| tx.set_metadata("kept", "yes")).unwrap();
+        assert_eq!(
+            storage.get_metadata("kept").unwrap().as_deref(),
+            Some("yes")
+        );
+    }
+
+    #[test]
+    fn test_pinned_query_can_be_interrupted() {
+        let storage = Arc::new(storage());
+        let interrupt = Arc::new(Interrupt::default());
+
+        let running = {
+            let storage = Arc::clone(&storage);
+            let interrupt = Arc::clone(&interrupt);
+            std::thread::spawn(move || {
+                storage.pin(&interrupt, |pinned| {
+                    pinned.with(|conn| {
+                        conn.query_row(ENDLESS_QUERY, [], |row| row.get::<_, i64>(0))
+                            .map_err(|e| AppError::Internal(e.to_string()))
+                    })
+                })
+            })
+        };
+        // An interrupt before the query starts is lost, so keep at it
+        while !running.is_finished() {
+            if let Some(ref stop) = *lock_interrupt(&interrupt) {
+                stop();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(running.join().unwrap().is_err());
+        assert!(lock_interrupt(&interrupt).is_none());
+
+        // The pool's only connection came back
+        assert_eq!(storage.get_metadata("missing").unwrap(), None);
+    }
+}
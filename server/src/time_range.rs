@@ -0,0 +1,119 @@
+//! The `from` / `to` query parameters shared by time-filtered list endpoints.
+
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use chrono::{DateTime, SecondsFormat};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+#[derive(Deserialize)]
+struct RawTimeRange {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+/// Extractor for an optional half-open `[from, to)` time range.
+///
+/// Both bounds are RFC 3339 and may carry any offset; they are normalized to
+/// the UTC millisecond form the server stores, so queries can compare them
+/// against timestamp columns as strings. Unparseable bounds, or a `from`
+/// after `to`, are rejected with `422 bad_request`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+impl TimeRange {
+    fn from_raw(from: Option<&str>, to: Option<&str>) -> Result<Self, AppError> {
+        let from = from.map(|value| normalize("from", value)).transpose()?;
+        let to = to.map(|value| normalize("to", value)).transpose()?;
+        if let (Some(from), Some(to)) = (&from, &to) {
+            if from > to {
+                return Err(AppError::BadRequest("from must not be after to".into()));
+            }
+        }
+        Ok(Self { from, to })
+    }
+}
+
+/// Parses `value`, the `name` parameter, into the stored UTC millisecond form.
+pub(crate) fn normalize(name: &str, value: &str) -> Result<String, AppError> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.to_utc().to_rfc3339_opts(SecondsFormat::Millis, true))
+        .map_err(|e| AppError::BadRequest(format!("{name} must be an RFC 3339 timestamp: {e}")))
+}
+
+impl<S> FromRequestParts<S> for TimeRange
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    fn from_request_parts<'life0, 'life1, 'async_trait>(
+        parts: &'life0 mut Parts,
+        _state: &'life1 S,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self, AppError>> + Send + 'async_trait>,
+    >
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+    {
+        Box::pin(async move {
+            let Query(raw) = Query::<RawTimeRange>::try_from_uri(&parts.uri)
+                .map_err(|e| AppError::BadRequest(format!("invalid time range: {e}")))?;
+            Self::from_raw(
+                raw.from.as_deref().filter(|v| !v.is_empty()),
+                raw.to.as_deref().filter(|v| !v.is_empty()),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_when_omitted() {
+        assert_eq!(
+            TimeRange::from_raw(None, None).unwrap(),
+            TimeRange::default()
+        );
+    }
+
+    #[test]
+    fn test_normalizes_to_utc_millis() {
+        let range = TimeRange::from_raw(
+            Some("2024-01-01T02:00:00+02:00"),
+            Some("2024-01-02T00:00:00Z"),
+        )
+        .unwrap();
+        assert_eq!(range.from.as_deref(), Some("2024-01-01T00:00:00.000Z"));
+        assert_eq!(range.to.as_deref(), Some("2024-01-02T00:00:00.000Z"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_timestamps() {
+        assert!(matches!(
+            TimeRange::from_raw(Some("yesterday"), None),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            TimeRange::from_raw(None, Some("2024-01-01")),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_inverted_range() {
+        assert!(matches!(
+            TimeRange::from_raw(Some("2024-01-02T00:00:00Z"), Some("2024-01-01T00:00:00Z")),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+//! Event fields the server doesn't know about, seen under `--strict-fields`.
+//!
+//! Ingest keeps only the fields in [`EVENT_FIELDS`] and drops the rest
+//! without a trace. With `--strict-fields`, the keys it drops are also
+//! recorded per event name, and a warning is logged the first time each one
+//! shows up, so a field Claude Code starts sending is noticed rather than
+//! lost. `GET /admin/unknown-fields` lists them. Events are still accepted.
+//!
+//! The `claudiator-hook` binary trims events before sending, so unknown
+//! keys normally arrive through `POST /api/v1/hooks/http`, which receives
+//! Claude Code's JSON as is.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use claudiator_types::EVENT_FIELDS;
+
+/// Sightings of one unknown field under one event name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownFieldUse {
+    pub first_seen: String,
+    pub last_seen: String,
+    pub events: u64,
+}
+
+/// Unknown fields by (`hook_event_name`, field). Kept in memory only, so it
+/// covers the time since the server started.
+pub type UnknownFieldMap = Mutex<HashMap<(String, String), UnknownFieldUse>>;
+
+/// Keys of the raw event object `event` that [`EVENT_FIELDS`] doesn't list.
+pub fn unknown_keys(event: &serde_json::Value) -> Vec<&str> {
+    event
+        .as_object()
+        .map(|object| {
+            object
+                .keys()
+                .map(String::as_str)
+                .filter(|key| !EVENT_FIELDS.contains(key))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Records that an event named `hook_event_name` arrived at `now` carrying
+/// `fields`.
+pub fn record(map: &UnknownFieldMap, hook_event_name: &str, fields: &[&str], now: &str) {
+    let mut guard = map
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    for field in fields {
+        guard
+            .entry((hook_event_name.to_string(), (*field).to_string()))
+            .and_modify(|seen| {
+                seen.last_seen = now.to_string();
+                seen.events += 1;
+            })
+            .or_insert_with(|| {
+                tracing::warn!(
+                    hook_event_name,
+                    field,
+                    "Event carries a field the server does not know"
+                );
+                UnknownFieldUse {
+                    first_seen: now.to_string(),
+                    last_seen: now.to_string(),
+                    events: 1,
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_keys_skips_declared_fields() {
+        let event = serde_json::json!({
+            "session_id": "s",
+            "hook_event_name": "PreToolUse",
+            "tool_name": "Bash",
+            "tool_input": {"command": "ls"},
+            "transcript_path": "/tmp/t.jsonl"
+        });
+        let mut keys = unknown_keys(&event);
+        keys.sort_unstable();
+        assert_eq!(keys, ["tool_input", "transcript_path"]);
+        assert!(unknown_keys(&serde_json::json!("not an object")).is_empty());
+    }
+
+    #[test]
+    fn test_record_counts_per_event_name() {
+        let map = UnknownFieldMap::default();
+        record(&map, "Stop", &["reason"], "2024-01-01T00:00:00.000Z");
+        record(&map, "Stop", &["reason"], "2024-01-01T00:01:00.000Z");
+        record(&map, "SessionEnd", &["reason"], "2024-01-01T00:02:00.000Z");
+
+        let map = map.into_inner().unwrap();
+        let stop = &map[&("Stop".to_string(), "reason".to_string())];
+        assert_eq!(stop.events, 2);
+        assert_eq!(stop.first_seen, "2024-01-01T00:00:00.000Z");
+        assert_eq!(stop.last_seen, "2024-01-01T00:01:00.000Z");
+        assert_eq!(map.len(), 2);
+    }
+}
@@ -1,28 +1,24 @@
 #![allow(clippy::unwrap_used)]
 #![allow(unused_variables)]
 #![allow(missing_docs)]
+#![allow(clippy::similar_names)]
 
+use claudiator_server::db::encryption::FieldCipher;
+use claudiator_server::db::partition::Partition;
+use claudiator_server::db::pool::DbPool;
+use claudiator_server::db::queries::NotificationTtl;
 use claudiator_server::db::{migrations, pool, queries};
-use r2d2::Pool;
-use r2d2_sqlite::SqliteConnectionManager;
-
-type DbPool = Pool<SqliteConnectionManager>;
-
+use claudiator_server::models;
+use claudiator_server::time_range::TimeRange;
 fn test_pool() -> DbPool {
-    let pool = pool::create_pool(":memory:").unwrap();
-    // For :memory: databases with r2d2, use max_size(1) since each connection
-    // creates a separate database
-    let manager = SqliteConnectionManager::memory();
-    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    let pool = pool::create_memory_pool(None).unwrap();
     migrations::run(&pool).unwrap();
     pool
 }
 
 #[test]
 fn test_migration_idempotency() {
-    let pool = pool::create_pool(":memory:").unwrap();
-    let manager = SqliteConnectionManager::memory();
-    let pool = Pool::builder().max_size(1).build(manager).unwrap();
+    let pool = pool::create_memory_pool(None).unwrap();
 
     // Run migrations twice
     migrations::run(&pool).unwrap();
@@ -40,6 +36,34 @@ fn test_migration_idempotency() {
     assert!(result > 0);
 }
 
+#[test]
+fn test_pool_settings_and_stats() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db_path = dir.path().join("claudiator.db");
+    let stats = std::sync::Arc::new(pool::PoolStats::default());
+    let settings = pool::PoolSettings {
+        max_size: 1,
+        connection_timeout: std::time::Duration::from_millis(50),
+    };
+    let pool = pool::create_configured_pool(
+        db_path.to_str().unwrap(),
+        None,
+        settings,
+        std::sync::Arc::clone(&stats),
+    )
+    .unwrap();
+    assert_eq!(pool.max_size(), 1);
+
+    let conn = pool.get().unwrap();
+    assert!(pool.get().is_err());
+    drop(conn);
+
+    // One checkout while building the pool, one above
+    assert_eq!(stats.checkouts(), 2);
+    assert_eq!(stats.timeouts(), 1);
+    assert!(stats.wait_max() <= stats.wait_total());
+}
+
 #[test]
 fn test_upsert_device() {
     let pool = test_pool();
@@ -47,10 +71,10 @@ fn test_upsert_device() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Insert
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
 
     // Verify insert
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 1);
     assert_eq!(devices[0].device_id, "device-1");
     assert_eq!(devices[0].device_name, "My Device");
@@ -58,10 +82,10 @@ fn test_upsert_device() {
 
     // Update
     let later = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "device-1", "Updated Device", "macos", &later).unwrap();
+    queries::upsert_device(&conn, "device-1", "Updated Device", "macos", None, &later).unwrap();
 
     // Verify update
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 1);
     assert_eq!(devices[0].device_name, "Updated Device");
 }
@@ -73,7 +97,7 @@ fn test_upsert_session() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup device
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
 
     // Insert session
     queries::upsert_session(
@@ -88,7 +112,7 @@ fn test_upsert_session() {
     .unwrap();
 
     // Verify insert
-    let sessions = queries::list_sessions(&conn, "device-1", None, 10).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "device-1", None, 10).unwrap();
     assert_eq!(sessions.len(), 1);
     assert_eq!(sessions[0].session_id, "session-1");
     assert_eq!(sessions[0].status, "active");
@@ -107,7 +131,7 @@ fn test_upsert_session() {
     .unwrap();
 
     // Verify title was not overwritten
-    let sessions = queries::list_sessions(&conn, "device-1", None, 10).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "device-1", None, 10).unwrap();
     assert_eq!(sessions[0].status, "ended");
     assert_eq!(sessions[0].title, Some("Initial Title".to_string()));
 }
@@ -119,7 +143,7 @@ fn test_insert_and_list_events() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
 
     // Insert event
@@ -139,13 +163,198 @@ fn test_insert_and_list_events() {
     assert!(event_id > 0);
 
     // List events
-    let events = queries::list_events(&conn, "session-1", 10).unwrap();
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "session-1",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(events.len(), 1);
     assert_eq!(events[0].hook_event_name, "tool-use");
     assert_eq!(events[0].tool_name, Some("bash".to_string()));
     assert_eq!(events[0].notification_type, Some("info".to_string()));
 }
 
+#[test]
+fn test_events_sharing_a_timestamp_keep_insertion_order() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = "2024-01-01T00:00:00.000Z";
+
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, now).unwrap();
+    for session_id in ["session-1", "session-2"] {
+        queries::upsert_session(&conn, session_id, "device-1", now, None, None, None).unwrap();
+    }
+    for (session_id, name) in [
+        ("session-1", "first"),
+        ("session-2", "other"),
+        ("session-1", "second"),
+        ("session-1", "third"),
+    ] {
+        queries::insert_event(
+            &conn, "device-1", session_id, name, now, now, None, None, "{}",
+        )
+        .unwrap();
+    }
+
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "session-1",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
+    let order: Vec<(&str, i64)> = events
+        .iter()
+        .map(|e| (e.hook_event_name.as_str(), e.seq))
+        .collect();
+    assert_eq!(order, [("third", 3), ("second", 2), ("first", 1)]);
+
+    // Events from before the column existed are numbered by the migration
+    conn.execute("UPDATE events SET seq = NULL", []).unwrap();
+    drop(conn);
+    migrations::run(&pool).unwrap();
+    let conn = pool.get().unwrap();
+    let seqs: Vec<(String, i64)> = conn
+        .prepare("SELECT hook_event_name, seq FROM events ORDER BY id")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(
+        seqs,
+        [
+            ("first".to_string(), 1),
+            ("other".to_string(), 1),
+            ("second".to_string(), 2),
+            ("third".to_string(), 3)
+        ]
+    );
+}
+
+#[test]
+fn test_get_event_returns_full_payload() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "PreToolUse",
+        &now,
+        &now,
+        Some("Bash"),
+        None,
+        r#"{"tool_input":{"command":"ls -la"}}"#,
+    )
+    .unwrap();
+
+    let event = queries::get_event(&conn, &Partition::All, event_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.id, event_id);
+    assert_eq!(event.session_id, "session-1");
+    assert_eq!(event.device_id, "device-1");
+    assert_eq!(event.tool_name.as_deref(), Some("Bash"));
+    assert_eq!(event.event_json["tool_input"]["command"], "ls -la");
+
+    assert!(queries::get_event(&conn, &Partition::All, event_id + 1)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_encrypted_columns_read_back_transparently() {
+    let cipher = FieldCipher::from_base64(&FieldCipher::generate_key()).unwrap();
+    let pool = pool::create_memory_pool(Some(cipher)).unwrap();
+    migrations::run(&pool).unwrap();
+    let conn = pool.get().unwrap();
+    let now = "2024-01-01T00:00:00.000Z";
+
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, now).unwrap();
+    // Written before a key was configured
+    conn.execute(
+        "INSERT INTO sessions (session_id, device_id, started_at, last_event, status, title)
+         VALUES ('old', 'device-1', ?1, ?1, 'active', 'Plain title')",
+        [now],
+    )
+    .unwrap();
+    queries::upsert_session(
+        &conn,
+        "session-1",
+        "device-1",
+        now,
+        None,
+        None,
+        Some("Secret title"),
+    )
+    .unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "UserPromptSubmit",
+        now,
+        now,
+        None,
+        None,
+        r#"{"prompt":"rotate the secret token"}"#,
+    )
+    .unwrap();
+
+    let (title, event_json): (String, String) = conn
+        .query_row(
+            "SELECT s.title, e.event_json FROM sessions s JOIN events e USING (session_id)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert!(title.starts_with("enc:v1:"));
+    assert!(!event_json.contains("secret"));
+
+    let session = queries::get_session(&conn, &Partition::All, "session-1")
+        .unwrap()
+        .unwrap();
+    assert_eq!(session.title.as_deref(), Some("Secret title"));
+    assert_eq!(
+        queries::get_session_title(&conn, "old").unwrap().as_deref(),
+        Some("Plain title")
+    );
+    let event = queries::get_event(&conn, &Partition::All, event_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(event.event_json["prompt"], "rotate the secret token");
+    let timeline = queries::list_session_timeline(&conn, &Partition::All, "session-1", 10).unwrap();
+    assert_eq!(
+        timeline[0].prompt.as_deref(),
+        Some("rotate the secret token")
+    );
+
+    // Encrypted values stay out of the search indexes
+    let expression = queries::search_expression("secret").unwrap();
+    assert!(
+        queries::search_sessions(&conn, &Partition::All, &expression, 10, 0)
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        queries::search_events(&conn, &Partition::All, &expression, 10, 0)
+            .unwrap()
+            .is_empty()
+    );
+    let expression = queries::search_expression("plain").unwrap();
+    let hits = queries::search_sessions(&conn, &Partition::All, &expression, 10, 0).unwrap();
+    assert_eq!(hits.len(), 1);
+}
+
 #[test]
 fn test_list_devices_with_active_sessions() {
     let pool = test_pool();
@@ -153,8 +362,8 @@ fn test_list_devices_with_active_sessions() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup devices
-    queries::upsert_device(&conn, "device-1", "Device 1", "macos", &now).unwrap();
-    queries::upsert_device(&conn, "device-2", "Device 2", "linux", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device 1", "macos", None, &now).unwrap();
+    queries::upsert_device(&conn, "device-2", "Device 2", "linux", None, &now).unwrap();
 
     // Device 1: 2 active, 1 ended
     queries::upsert_session(&conn, "s1", "device-1", &now, Some("active"), None, None).unwrap();
@@ -164,7 +373,7 @@ fn test_list_devices_with_active_sessions() {
     // Device 2: 1 active
     queries::upsert_session(&conn, "s4", "device-2", &now, Some("active"), None, None).unwrap();
 
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 2);
 
     let dev1 = devices.iter().find(|d| d.device_id == "device-1").unwrap();
@@ -174,27 +383,129 @@ fn test_list_devices_with_active_sessions() {
     assert_eq!(dev2.active_sessions, 1);
 }
 
+#[test]
+fn test_list_devices_attention_counts() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = "2024-01-02T00:00:00.000Z";
+
+    queries::upsert_device(&conn, "device-1", "Device 1", "macos", None, now).unwrap();
+    queries::upsert_device(&conn, "device-2", "Device 2", "linux", None, now).unwrap();
+    queries::upsert_session(&conn, "s1", "device-1", now, None, None, None).unwrap();
+
+    // Two events received in the window, one before it
+    let mut event_ids = Vec::new();
+    for received_at in [
+        "2024-01-01T00:00:00.000Z",
+        "2024-01-01T12:00:00.000Z",
+        "2024-01-02T00:00:00.000Z",
+    ] {
+        let id = queries::insert_event(
+            &conn,
+            "device-1",
+            "s1",
+            "Stop",
+            received_at,
+            received_at,
+            None,
+            None,
+            "{}",
+        )
+        .unwrap();
+        event_ids.push(id);
+    }
+    for (id, event_id) in [("n1", event_ids[0]), ("n2", event_ids[1])] {
+        queries::insert_notification(
+            &conn, id, event_id, "s1", "device-1", "Title", "Body", "stop", None, now,
+        )
+        .unwrap();
+    }
+    queries::acknowledge_notifications(&conn, &Partition::All, &["n1".to_string()]).unwrap();
+
+    let devices =
+        queries::list_devices(&conn, &Partition::All, "2024-01-01T12:00:00.000Z").unwrap();
+    let dev1 = devices.iter().find(|d| d.device_id == "device-1").unwrap();
+    assert_eq!(dev1.unacked_notifications, 1);
+    assert_eq!(dev1.events_last_24h, 2);
+    let dev2 = devices.iter().find(|d| d.device_id == "device-2").unwrap();
+    assert_eq!(dev2.unacked_notifications, 0);
+    assert_eq!(dev2.events_last_24h, 0);
+}
+
+#[test]
+fn test_session_overview_counts_and_urgency() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let session = |id: &str, last_event: &str, status: &str| {
+        queries::upsert_session(&conn, id, "device-1", last_event, Some(status), None, None)
+            .unwrap();
+    };
+    queries::upsert_device(
+        &conn,
+        "device-1",
+        "Device 1",
+        "macos",
+        None,
+        "2024-01-01T00:00:00Z",
+    )
+    .unwrap();
+
+    assert!(queries::most_urgent_session(&conn, &Partition::All)
+        .unwrap()
+        .is_none());
+
+    session("old-active", "2024-01-01T01:00:00Z", "active");
+    session("new-active", "2024-01-01T02:00:00Z", "active");
+    session("ended", "2024-01-01T03:00:00Z", "ended");
+    // Nothing waiting: the most recently active session
+    let urgent = queries::most_urgent_session(&conn, &Partition::All).unwrap();
+    assert_eq!(urgent.unwrap().session_id, "new-active");
+
+    session("idle", "2024-01-01T01:00:00Z", "idle");
+    session("input-new", "2024-01-01T02:00:00Z", "waiting_for_input");
+    session("input-old", "2024-01-01T01:00:00Z", "waiting_for_input");
+    // Waiting for input beats idle; the longest wait comes first
+    let urgent = queries::most_urgent_session(&conn, &Partition::All).unwrap();
+    assert_eq!(urgent.unwrap().session_id, "input-old");
+
+    session(
+        "permission",
+        "2024-01-01T04:00:00Z",
+        "waiting_for_permission",
+    );
+    let urgent = queries::most_urgent_session(&conn, &Partition::All).unwrap();
+    assert_eq!(urgent.unwrap().session_id, "permission");
+
+    let counts = queries::count_sessions_by_status(&conn, &Partition::All).unwrap();
+    assert_eq!(counts.active, 2);
+    assert_eq!(counts.waiting_for_permission, 1);
+    assert_eq!(counts.waiting_for_input, 2);
+    assert_eq!(counts.idle, 1);
+}
+
 #[test]
 fn test_list_sessions_with_status_filter() {
     let pool = test_pool();
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "Device 1", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device 1", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "s1", "device-1", &now, Some("active"), None, None).unwrap();
     queries::upsert_session(&conn, "s2", "device-1", &now, Some("ended"), None, None).unwrap();
     queries::upsert_session(&conn, "s3", "device-1", &now, Some("active"), None, None).unwrap();
 
     // Filter by active
-    let active = queries::list_sessions(&conn, "device-1", Some("active"), 10).unwrap();
+    let active =
+        queries::list_sessions(&conn, &Partition::All, "device-1", Some("active"), 10).unwrap();
     assert_eq!(active.len(), 2);
 
     // Filter by ended
-    let ended = queries::list_sessions(&conn, "device-1", Some("ended"), 10).unwrap();
+    let ended =
+        queries::list_sessions(&conn, &Partition::All, "device-1", Some("ended"), 10).unwrap();
     assert_eq!(ended.len(), 1);
 
     // No filter
-    let all = queries::list_sessions(&conn, "device-1", None, 10).unwrap();
+    let all = queries::list_sessions(&conn, &Partition::All, "device-1", None, 10).unwrap();
     assert_eq!(all.len(), 3);
 }
 
@@ -204,7 +515,7 @@ fn test_list_sessions_with_limit() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "Device 1", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device 1", "macos", None, &now).unwrap();
     for i in 1..=5 {
         queries::upsert_session(
             &conn,
@@ -218,7 +529,7 @@ fn test_list_sessions_with_limit() {
         .unwrap();
     }
 
-    let sessions = queries::list_sessions(&conn, "device-1", None, 3).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "device-1", None, 3).unwrap();
     assert_eq!(sessions.len(), 3);
 }
 
@@ -228,19 +539,78 @@ fn test_list_all_sessions() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "Device 1", "macos", &now).unwrap();
-    queries::upsert_device(&conn, "device-2", "Device 2", "linux", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device 1", "macos", None, &now).unwrap();
+    queries::upsert_device(&conn, "device-2", "Device 2", "linux", None, &now).unwrap();
 
     queries::upsert_session(&conn, "s1", "device-1", &now, Some("active"), None, None).unwrap();
     queries::upsert_session(&conn, "s2", "device-2", &now, Some("active"), None, None).unwrap();
 
-    let sessions = queries::list_all_sessions(&conn, None, 10).unwrap();
+    let sessions = queries::list_all_sessions(&conn, &Partition::All, None, 10).unwrap();
     assert_eq!(sessions.len(), 2);
 
-    let sessions_filtered = queries::list_all_sessions(&conn, Some("active"), 10).unwrap();
+    let sessions_filtered =
+        queries::list_all_sessions(&conn, &Partition::All, Some("active"), 10).unwrap();
     assert_eq!(sessions_filtered.len(), 2);
 }
 
+#[test]
+fn test_push_token_sandbox_tags_in_mixed_fleet() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::upsert_push_token(&conn, "ios", "app-store", &now, Some(false), None).unwrap();
+    queries::upsert_push_token(&conn, "ios", "xcode", &now, Some(true), None).unwrap();
+    queries::upsert_push_token(&conn, "ios", "old-client", &now, None, None).unwrap();
+    // An untagged re-registration keeps the environment already recorded
+    queries::upsert_push_token(&conn, "ios", "xcode", &now, None, None).unwrap();
+
+    let mut tokens: Vec<(String, Option<bool>)> = queries::list_push_tokens(&conn, &Partition::All)
+        .unwrap()
+        .into_iter()
+        .map(|t| (t.push_token, t.sandbox))
+        .collect();
+    tokens.sort();
+    assert_eq!(
+        tokens,
+        vec![
+            ("app-store".to_string(), Some(false)),
+            ("old-client".to_string(), None),
+            ("xcode".to_string(), Some(true)),
+        ]
+    );
+}
+
+#[test]
+fn test_delete_stale_push_tokens() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let old = (chrono::Utc::now() - chrono::Duration::days(31))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+
+    // Registered long ago: one never delivered to, one delivered to recently
+    queries::upsert_push_token(&conn, "ios", "departed", &old, Some(false), None).unwrap();
+    queries::upsert_push_token(&conn, "ios", "quiet", &old, Some(false), None).unwrap();
+    queries::record_push_success(&conn, "quiet", &now).unwrap();
+    // Registered recently, never delivered to
+    queries::upsert_push_token(&conn, "ios", "new", &now, Some(false), None).unwrap();
+    // Delivered to long ago, but re-registered since
+    queries::upsert_push_token(&conn, "ios", "relaunched", &old, Some(false), None).unwrap();
+    queries::record_push_success(&conn, "relaunched", &old).unwrap();
+    queries::upsert_push_token(&conn, "ios", "relaunched", &now, None, None).unwrap();
+
+    assert_eq!(queries::delete_stale_push_tokens(&conn, 30).unwrap(), 1);
+
+    let mut tokens: Vec<String> = queries::list_push_tokens(&conn, &Partition::All)
+        .unwrap()
+        .into_iter()
+        .map(|t| t.push_token)
+        .collect();
+    tokens.sort();
+    assert_eq!(tokens, vec!["new", "quiet", "relaunched"]);
+}
+
 #[test]
 fn test_push_token_lifecycle() {
     let pool = test_pool();
@@ -248,24 +618,24 @@ fn test_push_token_lifecycle() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Insert
-    queries::upsert_push_token(&conn, "ios", "token-123", &now, false).unwrap();
+    queries::upsert_push_token(&conn, "ios", "token-123", &now, Some(false), None).unwrap();
 
     // List
-    let tokens = queries::list_push_tokens(&conn).unwrap();
+    let tokens = queries::list_push_tokens(&conn, &Partition::All).unwrap();
     assert_eq!(tokens.len(), 1);
     assert_eq!(tokens[0].platform, "ios");
     assert_eq!(tokens[0].push_token, "token-123");
-    assert!(!tokens[0].sandbox);
+    assert_eq!(tokens[0].sandbox, Some(false));
 
     // Update
-    queries::upsert_push_token(&conn, "ios", "token-123", &now, true).unwrap();
-    let tokens = queries::list_push_tokens(&conn).unwrap();
+    queries::upsert_push_token(&conn, "ios", "token-123", &now, Some(true), None).unwrap();
+    let tokens = queries::list_push_tokens(&conn, &Partition::All).unwrap();
     assert_eq!(tokens.len(), 1);
-    assert!(tokens[0].sandbox);
+    assert_eq!(tokens[0].sandbox, Some(true));
 
     // Delete
     queries::delete_push_token(&conn, "token-123").unwrap();
-    let tokens = queries::list_push_tokens(&conn).unwrap();
+    let tokens = queries::list_push_tokens(&conn, &Partition::All).unwrap();
     assert_eq!(tokens.len(), 0);
 }
 
@@ -276,7 +646,7 @@ fn test_notification_lifecycle() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
@@ -307,7 +677,15 @@ fn test_notification_lifecycle() {
     .unwrap();
 
     // List all
-    let notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(notifs.len(), 1);
     assert_eq!(notifs[0].title, "Test Title");
 
@@ -328,7 +706,15 @@ fn test_notification_lifecycle() {
     .unwrap();
 
     // List notifications after the first notification's timestamp
-    let notifs = queries::list_notifications(&conn, Some(&now), 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        Some(&now),
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(notifs.len(), 1);
     assert_eq!(notifs[0].id, "notif-2");
 }
@@ -340,7 +726,7 @@ fn test_delete_expired_notifications() {
 
     // Setup
     let now = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
@@ -388,78 +774,123 @@ fn test_delete_expired_notifications() {
     .unwrap();
 
     // Delete expired
-    let deleted = queries::delete_expired_notifications(&conn).unwrap();
+    let deleted =
+        queries::delete_expired_notifications(&conn, &NotificationTtl::default()).unwrap();
     assert_eq!(deleted, 1);
 
     // Verify only recent remains
-    let notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(notifs.len(), 1);
     assert_eq!(notifs[0].id, "new-notif");
 }
 
 #[test]
-fn test_metadata_operations() {
+fn test_delete_expired_notifications_per_type_ttl() {
     let pool = test_pool();
     let conn = pool.get().unwrap();
 
-    // Get non-existent key
-    let value = queries::get_metadata(&conn, "test-key").unwrap();
-    assert!(value.is_none());
-
-    // Set a value
-    queries::set_metadata(&conn, "test-key", "test-value").unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "Stop",
+        &now,
+        &now,
+        None,
+        None,
+        "{}",
+    )
+    .unwrap();
 
-    // Get the value
-    let value = queries::get_metadata(&conn, "test-key").unwrap();
-    assert_eq!(value, Some("test-value".to_string()));
+    // All three are two days old
+    let created_at = (chrono::Utc::now() - chrono::Duration::hours(48)).to_rfc3339();
+    for (id, notification_type) in [
+        ("stop-notif", "stop"),
+        ("idle-notif", "idle_prompt"),
+        ("test-notif", "test"),
+    ] {
+        queries::insert_notification(
+            &conn,
+            id,
+            event_id,
+            "session-1",
+            "device-1",
+            "Title",
+            "Body",
+            notification_type,
+            None,
+            &created_at,
+        )
+        .unwrap();
+    }
 
-    // Update the value
-    queries::set_metadata(&conn, "test-key", "updated-value").unwrap();
+    // A week by default, but `idle_prompt` keeps only a day
+    let ttl = NotificationTtl {
+        default_hours: 168,
+        per_type: vec![("idle_prompt".to_string(), 24)],
+    };
+    let deleted = queries::delete_expired_notifications(&conn, &ttl).unwrap();
+    assert_eq!(deleted, 1);
 
-    // Verify update
-    let value = queries::get_metadata(&conn, "test-key").unwrap();
-    assert_eq!(value, Some("updated-value".to_string()));
+    let mut remaining: Vec<String> = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap()
+    .into_iter()
+    .map(|n| n.id)
+    .collect();
+    remaining.sort();
+    assert_eq!(remaining, vec!["stop-notif", "test-notif"]);
+
+    // Overrides also work the other way round
+    let ttl = NotificationTtl {
+        default_hours: 24,
+        per_type: vec![("stop".to_string(), 168)],
+    };
+    let deleted = queries::delete_expired_notifications(&conn, &ttl).unwrap();
+    assert_eq!(deleted, 1);
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
+    assert_eq!(notifs.len(), 1);
+    assert_eq!(notifs[0].id, "stop-notif");
 }
 
 #[test]
-fn test_metadata_multiple_keys() {
+fn test_delete_expired_responses() {
     let pool = test_pool();
     let conn = pool.get().unwrap();
 
-    // Set multiple keys
-    queries::set_metadata(&conn, "key1", "value1").unwrap();
-    queries::set_metadata(&conn, "key2", "value2").unwrap();
-    queries::set_metadata(&conn, "key3", "value3").unwrap();
-
-    // Verify all keys
-    assert_eq!(
-        queries::get_metadata(&conn, "key1").unwrap(),
-        Some("value1".to_string())
-    );
-    assert_eq!(
-        queries::get_metadata(&conn, "key2").unwrap(),
-        Some("value2".to_string())
-    );
-    assert_eq!(
-        queries::get_metadata(&conn, "key3").unwrap(),
-        Some("value3".to_string())
-    );
-}
-
-#[test]
-fn test_acknowledge_notifications_basic() {
-    let pool = test_pool();
-    let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
-
-    // Setup
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
         "device-1",
         "session-1",
-        "tool-use",
+        "PermissionRequest",
         &now,
         &now,
         None,
@@ -468,14 +899,216 @@ fn test_acknowledge_notifications_basic() {
     )
     .unwrap();
 
-    // Insert notifications
-    queries::insert_notification(
-        &conn,
-        "notif-1",
-        event_id,
-        "session-1",
-        "device-1",
-        "Title 1",
+    let old_time = (chrono::Utc::now() - chrono::Duration::hours(25)).to_rfc3339();
+    for (id, created_at) in [
+        ("old-notif", old_time.as_str()),
+        ("new-notif", now.as_str()),
+    ] {
+        queries::insert_notification(
+            &conn,
+            id,
+            event_id,
+            "session-1",
+            "device-1",
+            "Permission Required",
+            "Body",
+            "permission_prompt",
+            None,
+            created_at,
+        )
+        .unwrap();
+        let notification = queries::get_notification(&conn, &Partition::All, id)
+            .unwrap()
+            .unwrap();
+        assert!(queries::upsert_response(
+            &conn,
+            &format!("resp-{id}"),
+            &notification,
+            "allow",
+            None,
+            created_at
+        )
+        .unwrap());
+    }
+
+    let deleted = queries::delete_expired_responses(&conn, 24).unwrap();
+    assert_eq!(deleted, 1);
+
+    assert!(queries::get_response_for_notification(&conn, "old-notif")
+        .unwrap()
+        .is_none());
+    let pending =
+        queries::take_pending_responses(&conn, &Partition::All, "session-1", &now).unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].notification_id, "new-notif");
+}
+
+#[test]
+fn test_delivery_failure_lifecycle() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "Stop",
+        &now,
+        &now,
+        None,
+        None,
+        "{}",
+    )
+    .unwrap();
+    queries::insert_notification(
+        &conn,
+        "notif-1",
+        event_id,
+        "session-1",
+        "device-1",
+        "Session Stopped",
+        "Body",
+        "stop",
+        None,
+        &now,
+    )
+    .unwrap();
+
+    for (id, notification_id, attempted_at) in [
+        ("fail-1", "notif-1", "2024-01-01T00:00:00.000Z"),
+        ("fail-2", "notif-gone", "2024-01-02T00:00:00.000Z"),
+    ] {
+        queries::insert_delivery_failure(
+            &conn,
+            &models::response::DeliveryFailure {
+                id: id.to_string(),
+                notification_id: notification_id.to_string(),
+                channel: "apns".to_string(),
+                partition: Some("alice".to_string()),
+                session_id: "session-1".to_string(),
+                device_id: "device-1".to_string(),
+                title: "Session Stopped".to_string(),
+                body: "Body".to_string(),
+                error: "APNs auth error".to_string(),
+                attempts: 1,
+                created_at: attempted_at.to_string(),
+                last_attempt_at: attempted_at.to_string(),
+            },
+        )
+        .unwrap();
+    }
+
+    let failures = queries::list_delivery_failures(&conn, 10).unwrap();
+    assert_eq!(
+        failures.iter().map(|f| f.id.as_str()).collect::<Vec<_>>(),
+        ["fail-2", "fail-1"]
+    );
+    assert_eq!(queries::list_delivery_failures(&conn, 1).unwrap().len(), 1);
+
+    queries::record_delivery_retry_failure(&conn, "fail-1", "Request failed", &now).unwrap();
+    let failure = queries::get_delivery_failure(&conn, "fail-1")
+        .unwrap()
+        .unwrap();
+    assert_eq!(failure.attempts, 2);
+    assert_eq!(failure.error, "Request failed");
+    assert_eq!(failure.partition.as_deref(), Some("alice"));
+
+    // Failures outlive neither their notification nor a successful retry
+    assert_eq!(
+        queries::delete_orphaned_delivery_failures(&conn).unwrap(),
+        1
+    );
+    assert!(queries::get_delivery_failure(&conn, "fail-2")
+        .unwrap()
+        .is_none());
+    queries::delete_delivery_failure(&conn, "fail-1").unwrap();
+    assert!(queries::list_delivery_failures(&conn, 10)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_metadata_operations() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    // Get non-existent key
+    let value = queries::get_metadata(&conn, "test-key").unwrap();
+    assert!(value.is_none());
+
+    // Set a value
+    queries::set_metadata(&conn, "test-key", "test-value").unwrap();
+
+    // Get the value
+    let value = queries::get_metadata(&conn, "test-key").unwrap();
+    assert_eq!(value, Some("test-value".to_string()));
+
+    // Update the value
+    queries::set_metadata(&conn, "test-key", "updated-value").unwrap();
+
+    // Verify update
+    let value = queries::get_metadata(&conn, "test-key").unwrap();
+    assert_eq!(value, Some("updated-value".to_string()));
+}
+
+#[test]
+fn test_metadata_multiple_keys() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    // Set multiple keys
+    queries::set_metadata(&conn, "key1", "value1").unwrap();
+    queries::set_metadata(&conn, "key2", "value2").unwrap();
+    queries::set_metadata(&conn, "key3", "value3").unwrap();
+
+    // Verify all keys
+    assert_eq!(
+        queries::get_metadata(&conn, "key1").unwrap(),
+        Some("value1".to_string())
+    );
+    assert_eq!(
+        queries::get_metadata(&conn, "key2").unwrap(),
+        Some("value2".to_string())
+    );
+    assert_eq!(
+        queries::get_metadata(&conn, "key3").unwrap(),
+        Some("value3".to_string())
+    );
+}
+
+#[test]
+fn test_acknowledge_notifications_basic() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    // Setup
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "tool-use",
+        &now,
+        &now,
+        None,
+        None,
+        "{}",
+    )
+    .unwrap();
+
+    // Insert notifications
+    queries::insert_notification(
+        &conn,
+        "notif-1",
+        event_id,
+        "session-1",
+        "device-1",
+        "Title 1",
         "Body",
         "info",
         None,
@@ -498,10 +1131,18 @@ fn test_acknowledge_notifications_basic() {
     .unwrap();
 
     // Acknowledge one notification
-    queries::acknowledge_notifications(&conn, &["notif-1".to_string()]).unwrap();
+    queries::acknowledge_notifications(&conn, &Partition::All, &["notif-1".to_string()]).unwrap();
 
     // Verify acknowledged status
-    let notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     let notif1 = notifs.iter().find(|n| n.id == "notif-1").unwrap();
     let notif2 = notifs.iter().find(|n| n.id == "notif-2").unwrap();
 
@@ -516,7 +1157,7 @@ fn test_acknowledge_notifications_multiple() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
@@ -551,6 +1192,7 @@ fn test_acknowledge_notifications_multiple() {
     // Acknowledge multiple notifications
     queries::acknowledge_notifications(
         &conn,
+        &Partition::All,
         &[
             "notif-1".to_string(),
             "notif-2".to_string(),
@@ -560,7 +1202,15 @@ fn test_acknowledge_notifications_multiple() {
     .unwrap();
 
     // Verify
-    let notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     let acked_count = notifs.iter().filter(|n| n.acknowledged).count();
     assert_eq!(acked_count, 3);
 }
@@ -571,7 +1221,7 @@ fn test_acknowledge_notifications_empty() {
     let conn = pool.get().unwrap();
 
     // Should not error on empty array
-    queries::acknowledge_notifications(&conn, &[]).unwrap();
+    queries::acknowledge_notifications(&conn, &Partition::All, &[]).unwrap();
 }
 
 #[test]
@@ -580,7 +1230,8 @@ fn test_acknowledge_notifications_nonexistent() {
     let conn = pool.get().unwrap();
 
     // Should not error on non-existent IDs
-    queries::acknowledge_notifications(&conn, &["nonexistent".to_string()]).unwrap();
+    queries::acknowledge_notifications(&conn, &Partition::All, &["nonexistent".to_string()])
+        .unwrap();
 }
 
 #[test]
@@ -590,7 +1241,7 @@ fn test_list_notifications_with_after_timestamp() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
@@ -638,11 +1289,27 @@ fn test_list_notifications_with_after_timestamp() {
     .unwrap();
 
     // List all notifications
-    let all_notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let all_notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(all_notifs.len(), 2);
 
     // List notifications after first timestamp
-    let notifs = queries::list_notifications(&conn, Some(timestamp1), 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        Some(timestamp1),
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(notifs.len(), 1);
     assert_eq!(notifs[0].id, "notif-2");
 }
@@ -654,7 +1321,7 @@ fn test_timestamp_pagination_ordering() {
     let now = chrono::Utc::now().to_rfc3339();
 
     // Setup
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
     let event_id = queries::insert_event(
         &conn,
@@ -713,7 +1380,15 @@ fn test_timestamp_pagination_ordering() {
     .unwrap();
 
     // List all - should be in ascending order by timestamp
-    let notifs = queries::list_notifications(&conn, None, 10).unwrap();
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(notifs.len(), 3);
     assert_eq!(notifs[0].id, "notif-1");
     assert_eq!(notifs[1].id, "notif-2");
@@ -726,7 +1401,7 @@ fn test_get_session_title_with_title() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
     queries::upsert_session(
         &conn,
         "session-1",
@@ -748,7 +1423,7 @@ fn test_get_session_title_without_title() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
     queries::upsert_session(
         &conn,
         "session-1",
@@ -780,7 +1455,7 @@ fn test_delete_old_events() {
 
     // Setup device and session
     let now = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
     queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
 
     // Insert old event (8 days ago)
@@ -819,17 +1494,172 @@ fn test_delete_old_events() {
     assert_eq!(deleted, 1);
 
     // Verify only recent event remains
-    let events = queries::list_events(&conn, "session-1", 10).unwrap();
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "session-1",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
+    assert_eq!(events.len(), 1);
+}
+
+#[test]
+fn test_retention_rolls_events_into_session_summaries() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(
+        &conn,
+        "session-1",
+        "device-1",
+        &now,
+        None,
+        Some("/home/u/project"),
+        Some("Fix the login bug"),
+    )
+    .unwrap();
+
+    let insert = |days_ago: i64, kind: &str, tool: Option<&str>| {
+        let time = (chrono::Utc::now() - chrono::Duration::days(days_ago))
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        queries::insert_event(
+            &conn,
+            "device-1",
+            "session-1",
+            kind,
+            &time,
+            &time,
+            tool,
+            None,
+            "{}",
+        )
+        .unwrap();
+    };
+    insert(10, "PreToolUse", Some("Bash"));
+    insert(10, "PostToolUse", Some("Bash"));
+    insert(9, "PreToolUse", Some("Read"));
+    insert(1, "PreToolUse", Some("Bash"));
+
+    assert_eq!(queries::delete_old_events(&conn, 7).unwrap(), 3);
+
+    // A later pass adds to the existing summary instead of replacing it
+    insert(8, "PreToolUse", Some("Bash"));
+    assert_eq!(queries::archive_old_events(&conn, 7).unwrap(), 1);
+
+    let (device_id, title, event_count, tool_counts, first_event, last_event): (
+        String,
+        Option<String>,
+        i64,
+        String,
+        String,
+        String,
+    ) = conn
+        .query_row(
+            "SELECT device_id, title, event_count, tool_counts, first_event, last_event
+             FROM session_summaries WHERE session_id = 'session-1'",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(device_id, "device-1");
+    assert_eq!(title.as_deref(), Some("Fix the login bug"));
+    assert_eq!(event_count, 4);
+    assert_eq!(tool_counts, r#"{"Bash":2,"Read":1}"#);
+    assert!(first_event < last_event);
+
+    // The recent event is still live and not yet summarized
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "session-1",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(events.len(), 1);
 }
 
+#[test]
+fn test_search_expression_quotes_terms() {
+    assert_eq!(
+        queries::search_expression("  payment  webhook ").as_deref(),
+        Some(r#""payment" "webhook""#)
+    );
+    assert_eq!(
+        queries::search_expression(r#"say "hi" OR"#).as_deref(),
+        Some(r#""say" """hi""" "OR""#)
+    );
+    assert_eq!(queries::search_expression(" \t"), None);
+}
+
+#[test]
+fn test_search_index_follows_titles_and_retention() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+    queries::set_session_title(&conn, "session-1", "Fix the login bug").unwrap();
+
+    let old_time = (chrono::Utc::now() - chrono::Duration::days(8))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "UserPromptSubmit",
+        &old_time,
+        &old_time,
+        None,
+        None,
+        r#"{"prompt": "Fix the login bug"}"#,
+    )
+    .unwrap();
+
+    let search = |text: &str| {
+        let expression = queries::search_expression(text).unwrap();
+        (
+            queries::search_sessions(&conn, &Partition::All, &expression, 10, 0)
+                .unwrap()
+                .len(),
+            queries::search_events(&conn, &Partition::All, &expression, 10, 0)
+                .unwrap()
+                .len(),
+        )
+    };
+    assert_eq!(search("login"), (1, 1));
+
+    // Retitling replaces the indexed title
+    queries::set_session_title(&conn, "session-1", "Payment webhook retries").unwrap();
+    assert_eq!(search("login"), (0, 1));
+    assert_eq!(search("webhook"), (1, 0));
+
+    // Events removed by retention leave the index too
+    queries::delete_old_events(&conn, 7).unwrap();
+    assert_eq!(search("login"), (0, 0));
+}
+
 #[test]
 fn test_delete_stale_sessions() {
     let pool = test_pool();
     let conn = pool.get().unwrap();
 
     let now = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
 
     // Insert old orphaned session (8 days ago)
     let old_time = (chrono::Utc::now() - chrono::Duration::days(8)).to_rfc3339();
@@ -861,7 +1691,7 @@ fn test_delete_stale_sessions() {
     assert_eq!(deleted, 1);
 
     // Verify only recent session remains
-    let sessions = queries::list_sessions(&conn, "device-1", None, 10).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "device-1", None, 10).unwrap();
     assert_eq!(sessions.len(), 1);
     assert_eq!(sessions[0].session_id, "recent-session");
 }
@@ -872,7 +1702,7 @@ fn test_delete_stale_sessions_keeps_session_with_events() {
     let conn = pool.get().unwrap();
 
     let now = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "device-1", "Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
 
     // Insert old session (8 days ago)
     let old_time = (chrono::Utc::now() - chrono::Duration::days(8)).to_rfc3339();
@@ -906,7 +1736,7 @@ fn test_delete_stale_sessions_keeps_session_with_events() {
     let deleted = queries::delete_stale_sessions(&conn, 7).unwrap();
     assert_eq!(deleted, 0);
 
-    let sessions = queries::list_sessions(&conn, "device-1", None, 10).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "device-1", None, 10).unwrap();
     assert_eq!(sessions.len(), 1);
 }
 
@@ -917,18 +1747,18 @@ fn test_delete_stale_devices() {
 
     // Insert old orphaned device (31 days ago)
     let old_time = (chrono::Utc::now() - chrono::Duration::days(31)).to_rfc3339();
-    queries::upsert_device(&conn, "old-device", "Old Device", "macos", &old_time).unwrap();
+    queries::upsert_device(&conn, "old-device", "Old Device", "macos", None, &old_time).unwrap();
 
     // Insert recent device
     let now = chrono::Utc::now().to_rfc3339();
-    queries::upsert_device(&conn, "recent-device", "Recent Device", "linux", &now).unwrap();
+    queries::upsert_device(&conn, "recent-device", "Recent Device", "linux", None, &now).unwrap();
 
     // Delete stale devices older than 30 days
     let deleted = queries::delete_stale_devices(&conn, 30).unwrap();
     assert_eq!(deleted, 1);
 
     // Verify only recent device remains
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 1);
     assert_eq!(devices[0].device_id, "recent-device");
 }
@@ -940,7 +1770,7 @@ fn test_delete_stale_devices_keeps_device_with_sessions() {
 
     // Insert old device (31 days ago)
     let old_time = (chrono::Utc::now() - chrono::Duration::days(31)).to_rfc3339();
-    queries::upsert_device(&conn, "old-device", "Old Device", "macos", &old_time).unwrap();
+    queries::upsert_device(&conn, "old-device", "Old Device", "macos", None, &old_time).unwrap();
 
     // Add a session referencing this device
     let now = chrono::Utc::now().to_rfc3339();
@@ -959,7 +1789,7 @@ fn test_delete_stale_devices_keeps_device_with_sessions() {
     let deleted = queries::delete_stale_devices(&conn, 30).unwrap();
     assert_eq!(deleted, 0);
 
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 1);
 }
 
@@ -972,7 +1802,7 @@ fn test_full_retention_cascade() {
     let old_time = (chrono::Utc::now() - chrono::Duration::days(31)).to_rfc3339();
     let old_time_millis = (chrono::Utc::now() - chrono::Duration::days(31))
         .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
-    queries::upsert_device(&conn, "old-device", "Old", "macos", &old_time).unwrap();
+    queries::upsert_device(&conn, "old-device", "Old", "macos", None, &old_time).unwrap();
     queries::upsert_session(
         &conn,
         "old-session",
@@ -999,7 +1829,7 @@ fn test_full_retention_cascade() {
     // --- Recent chain (should be untouched) ---
     let now = chrono::Utc::now().to_rfc3339();
     let now_millis = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
-    queries::upsert_device(&conn, "new-device", "New", "linux", &now).unwrap();
+    queries::upsert_device(&conn, "new-device", "New", "linux", None, &now).unwrap();
     queries::upsert_session(
         &conn,
         "new-session",
@@ -1034,15 +1864,22 @@ fn test_full_retention_cascade() {
     assert_eq!(devices_deleted, 1);
 
     // Verify recent chain is untouched
-    let devices = queries::list_devices(&conn).unwrap();
+    let devices = queries::list_devices(&conn, &Partition::All, "").unwrap();
     assert_eq!(devices.len(), 1);
     assert_eq!(devices[0].device_id, "new-device");
 
-    let sessions = queries::list_sessions(&conn, "new-device", None, 10).unwrap();
+    let sessions = queries::list_sessions(&conn, &Partition::All, "new-device", None, 10).unwrap();
     assert_eq!(sessions.len(), 1);
     assert_eq!(sessions[0].session_id, "new-session");
 
-    let events = queries::list_events(&conn, "new-session", 10).unwrap();
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "new-session",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
     assert_eq!(events.len(), 1);
 }
 
@@ -1060,6 +1897,7 @@ fn test_api_key_insert_and_list() {
         "write",
         &now,
         None,
+        None,
     )
     .unwrap();
 
@@ -1092,26 +1930,66 @@ fn test_api_key_list_multiple_ordered_by_created_at() {
     let t3 = "2024-01-01T12:00:00Z";
 
     // Insert in non-sequential order
-    queries::insert_api_key(&conn, "id-2", "second", "claud_key2", "read", t2, None).unwrap();
-    queries::insert_api_key(&conn, "id-1", "first", "claud_key1", "write", t1, None).unwrap();
-    queries::insert_api_key(&conn, "id-3", "third", "claud_key3", "read,write", t3, None).unwrap();
-
-    let keys = queries::list_api_keys(&conn).unwrap();
-    assert_eq!(keys.len(), 3);
-    assert_eq!(keys[0].id, "id-1");
-    assert_eq!(keys[1].id, "id-2");
-    assert_eq!(keys[2].id, "id-3");
-}
-
-#[test]
-fn test_api_key_find_by_key_existing() {
-    let pool = test_pool();
-    let conn = pool.get().unwrap();
-    let now = chrono::Utc::now().to_rfc3339();
-
-    queries::insert_api_key(&conn, "id-1", "ios-app", "claud_findme", "read", &now, None).unwrap();
-
-    let result = queries::find_api_key_by_key(&conn, "claud_findme").unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-2",
+        "second",
+        "claud_key2",
+        "read",
+        t2,
+        None,
+        None,
+    )
+    .unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-1",
+        "first",
+        "claud_key1",
+        "write",
+        t1,
+        None,
+        None,
+    )
+    .unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-3",
+        "third",
+        "claud_key3",
+        "read,write",
+        t3,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let keys = queries::list_api_keys(&conn).unwrap();
+    assert_eq!(keys.len(), 3);
+    assert_eq!(keys[0].id, "id-1");
+    assert_eq!(keys[1].id, "id-2");
+    assert_eq!(keys[2].id, "id-3");
+}
+
+#[test]
+fn test_api_key_find_by_key_existing() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::insert_api_key(
+        &conn,
+        "id-1",
+        "ios-app",
+        "claud_findme",
+        "read",
+        &now,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let result = queries::find_api_key_by_key(&conn, "claud_findme").unwrap();
     assert!(result.is_some());
     let row = result.unwrap();
     assert_eq!(row.id, "id-1");
@@ -1142,6 +2020,7 @@ fn test_api_key_delete() {
         "read",
         &now,
         None,
+        None,
     )
     .unwrap();
 
@@ -1169,7 +2048,17 @@ fn test_api_key_update_last_used() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::insert_api_key(&conn, "id-1", "test", "claud_key", "read,write", &now, None).unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-1",
+        "test",
+        "claud_key",
+        "read,write",
+        &now,
+        None,
+        None,
+    )
+    .unwrap();
 
     // Initially null
     let row = queries::find_api_key_by_key(&conn, "claud_key")
@@ -1193,7 +2082,17 @@ fn test_api_key_unique_key_constraint() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::insert_api_key(&conn, "id-1", "first", "claud_sameval", "read", &now, None).unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-1",
+        "first",
+        "claud_sameval",
+        "read",
+        &now,
+        None,
+        None,
+    )
+    .unwrap();
 
     // Inserting a second key with the same `key` value should fail
     let result = queries::insert_api_key(
@@ -1204,6 +2103,7 @@ fn test_api_key_unique_key_constraint() {
         "write",
         &now,
         None,
+        None,
     );
     assert!(result.is_err());
 }
@@ -1214,7 +2114,17 @@ fn test_api_key_scopes_comma_separated() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::insert_api_key(&conn, "id-1", "rw", "claud_rw", "read,write", &now, None).unwrap();
+    queries::insert_api_key(
+        &conn,
+        "id-1",
+        "rw",
+        "claud_rw",
+        "read,write",
+        &now,
+        None,
+        None,
+    )
+    .unwrap();
 
     let row = queries::find_api_key_by_key(&conn, "claud_rw")
         .unwrap()
@@ -1236,6 +2146,7 @@ fn test_api_key_rate_limit_persists() {
         "read",
         &now,
         Some(500),
+        None,
     )
     .unwrap();
 
@@ -1259,6 +2170,7 @@ fn test_api_key_rate_limit_null_when_not_set() {
         "read",
         &now,
         None,
+        None,
     )
     .unwrap();
 
@@ -1274,7 +2186,7 @@ fn test_list_all_sessions_paginated_basic() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
 
     // Create 3 sessions
     for i in 1..=3 {
@@ -1291,13 +2203,35 @@ fn test_list_all_sessions_paginated_basic() {
     }
 
     // Page 1: limit=2
-    let page1 = queries::list_all_sessions_paginated(&conn, None, false, 2, 0).unwrap();
+    let page1 = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        None,
+        false,
+        &TimeRange::default(),
+        2,
+        0,
+    )
+    .unwrap();
     assert_eq!(page1.sessions.len(), 2);
     assert!(page1.has_more);
     assert_eq!(page1.next_offset, 2);
 
     // Page 2
-    let page2 = queries::list_all_sessions_paginated(&conn, None, false, 2, 2).unwrap();
+    let page2 = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        None,
+        false,
+        &TimeRange::default(),
+        2,
+        2,
+    )
+    .unwrap();
     assert_eq!(page2.sessions.len(), 1);
     assert!(!page2.has_more);
     assert_eq!(page2.next_offset, 3);
@@ -1309,7 +2243,7 @@ fn test_list_all_sessions_paginated_exclude_ended() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
 
     queries::upsert_session(
         &conn,
@@ -1332,12 +2266,292 @@ fn test_list_all_sessions_paginated_exclude_ended() {
     )
     .unwrap();
 
-    let result = queries::list_all_sessions_paginated(&conn, None, true, 50, 0).unwrap();
+    let result = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        None,
+        true,
+        &TimeRange::default(),
+        50,
+        0,
+    )
+    .unwrap();
     assert_eq!(result.sessions.len(), 1);
     assert_eq!(result.sessions[0].session_id, "session-active");
     assert!(!result.has_more);
 }
 
+#[test]
+fn test_list_all_sessions_paginated_cwd_prefix() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
+
+    for (id, cwd) in [
+        ("session-foo", Some("/home/me/projects/foo")),
+        ("session-foo-sub", Some("/home/me/projects/foo/crates/bar")),
+        ("session-other", Some("/home/me/projects/other")),
+        ("session-percent", Some("/home/me/100%/foo")),
+        ("session-no-cwd", None),
+    ] {
+        queries::upsert_session(&conn, id, "device-1", &now, Some("active"), cwd, None).unwrap();
+    }
+
+    let result = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        Some("/home/me/projects/foo"),
+        None,
+        false,
+        &TimeRange::default(),
+        50,
+        0,
+    )
+    .unwrap();
+    let mut ids: Vec<_> = result
+        .sessions
+        .iter()
+        .map(|s| s.session_id.as_str())
+        .collect();
+    ids.sort_unstable();
+    assert_eq!(ids, ["session-foo", "session-foo-sub"]);
+
+    // `%` is matched literally, not as a wildcard
+    let result = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        Some("/home/me/1%"),
+        None,
+        false,
+        &TimeRange::default(),
+        50,
+        0,
+    )
+    .unwrap();
+    assert!(result.sessions.is_empty());
+}
+
+#[test]
+fn test_list_all_sessions_paginated_time_range() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    queries::upsert_device(
+        &conn,
+        "device-1",
+        "My Device",
+        "macos",
+        None,
+        "2024-01-01T00:00:00.000Z",
+    )
+    .unwrap();
+    for (id, times) in [
+        ("session-old", &["2024-01-01T10:00:00.000Z"][..]),
+        (
+            "session-overnight",
+            &["2024-01-01T23:00:00.000Z", "2024-01-02T01:00:00.000Z"][..],
+        ),
+        ("session-new", &["2024-01-02T12:00:00.000Z"][..]),
+    ] {
+        for time in times {
+            queries::upsert_session(&conn, id, "device-1", time, None, None, None).unwrap();
+        }
+    }
+
+    let ids = |range: &TimeRange| {
+        let mut ids: Vec<String> = queries::list_all_sessions_paginated(
+            &conn,
+            &Partition::All,
+            None,
+            None,
+            None,
+            false,
+            range,
+            50,
+            0,
+        )
+        .unwrap()
+        .sessions
+        .into_iter()
+        .map(|s| s.session_id)
+        .collect();
+        ids.sort_unstable();
+        ids
+    };
+
+    // Sessions active at any point in the range are included
+    let jan_2 = TimeRange {
+        from: Some("2024-01-02T00:00:00.000Z".to_string()),
+        to: Some("2024-01-03T00:00:00.000Z".to_string()),
+    };
+    assert_eq!(ids(&jan_2), ["session-new", "session-overnight"]);
+
+    let before_jan_2 = TimeRange {
+        from: None,
+        to: Some("2024-01-02T00:00:00.000Z".to_string()),
+    };
+    assert_eq!(ids(&before_jan_2), ["session-old", "session-overnight"]);
+}
+
+#[test]
+fn test_list_notifications_time_range() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = "2024-01-01T00:00:00.000Z";
+
+    queries::upsert_device(&conn, "device-1", "Device", "mac", None, now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", now, None, None, None).unwrap();
+    let event_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "Stop",
+        now,
+        now,
+        None,
+        None,
+        "{}",
+    )
+    .unwrap();
+    for (id, created_at) in [
+        ("notif-1", "2024-01-01T12:00:00.000Z"),
+        ("notif-2", "2024-01-02T00:00:00.000Z"),
+        ("notif-3", "2024-01-02T12:00:00.000Z"),
+    ] {
+        queries::insert_notification(
+            &conn,
+            id,
+            event_id,
+            "session-1",
+            "device-1",
+            "Title",
+            "Body",
+            "stop",
+            None,
+            created_at,
+        )
+        .unwrap();
+    }
+
+    // `from` is inclusive, `to` exclusive
+    let range = TimeRange {
+        from: Some("2024-01-02T00:00:00.000Z".to_string()),
+        to: Some("2024-01-02T12:00:00.000Z".to_string()),
+    };
+    let notifs =
+        queries::list_notifications(&conn, &Partition::All, None, None, &range, 10).unwrap();
+    let ids: Vec<&str> = notifs.iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(ids, ["notif-2"]);
+}
+
+#[test]
+fn test_list_notifications_for_session() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = "2024-01-01T00:00:00.000Z";
+
+    queries::upsert_device(&conn, "device-1", "Device", "mac", None, now).unwrap();
+    for (id, session_id) in [
+        ("notif-1", "session-1"),
+        ("notif-2", "session-2"),
+        ("notif-3", "session-1"),
+    ] {
+        queries::upsert_session(&conn, session_id, "device-1", now, None, None, None).unwrap();
+        let event_id = queries::insert_event(
+            &conn, "device-1", session_id, "Stop", now, now, None, None, "{}",
+        )
+        .unwrap();
+        queries::insert_notification(
+            &conn, id, event_id, session_id, "device-1", "Title", "Body", "stop", None, now,
+        )
+        .unwrap();
+    }
+
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        Some("session-1"),
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
+    let mut ids: Vec<&str> = notifs.iter().map(|n| n.id.as_str()).collect();
+    ids.sort_unstable();
+    assert_eq!(ids, ["notif-1", "notif-3"]);
+
+    let notifs = queries::list_notifications(
+        &conn,
+        &Partition::All,
+        Some("session-9"),
+        None,
+        &TimeRange::default(),
+        10,
+    )
+    .unwrap();
+    assert!(notifs.is_empty());
+}
+
+#[test]
+fn test_set_session_pinned() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+
+    let session = queries::get_session(&conn, &Partition::All, "session-1")
+        .unwrap()
+        .unwrap();
+    assert!(!session.pinned);
+
+    // Another partition can't pin it
+    queries::set_session_pinned(
+        &conn,
+        &Partition::Key(Some("other".into())),
+        "session-1",
+        true,
+    )
+    .unwrap();
+    let session = queries::get_session(&conn, &Partition::All, "session-1")
+        .unwrap()
+        .unwrap();
+    assert!(!session.pinned);
+
+    queries::set_session_pinned(&conn, &Partition::Key(None), "session-1", true).unwrap();
+    let session = queries::get_session(&conn, &Partition::All, "session-1")
+        .unwrap()
+        .unwrap();
+    assert!(session.pinned);
+
+    // Later events don't reset the flag
+    queries::upsert_session(
+        &conn,
+        "session-1",
+        "device-1",
+        &now,
+        Some("ended"),
+        None,
+        None,
+    )
+    .unwrap();
+    let session = queries::get_session(&conn, &Partition::All, "session-1")
+        .unwrap()
+        .unwrap();
+    assert!(session.pinned);
+
+    assert!(queries::get_session(&conn, &Partition::All, "missing")
+        .unwrap()
+        .is_none());
+}
+
 #[test]
 fn test_list_all_sessions_paginated_priority_ordering() {
     let pool = test_pool();
@@ -1348,6 +2562,7 @@ fn test_list_all_sessions_paginated_priority_ordering() {
         "device-1",
         "My Device",
         "macos",
+        None,
         "2024-01-01T00:00:00Z",
     )
     .unwrap();
@@ -1375,7 +2590,18 @@ fn test_list_all_sessions_paginated_priority_ordering() {
     )
     .unwrap();
 
-    let result = queries::list_all_sessions_paginated(&conn, None, false, 50, 0).unwrap();
+    let result = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        None,
+        false,
+        &TimeRange::default(),
+        50,
+        0,
+    )
+    .unwrap();
     assert_eq!(result.sessions.len(), 2);
     // Active should come first despite older last_event
     assert_eq!(result.sessions[0].session_id, "session-active-old");
@@ -1388,7 +2614,7 @@ fn test_list_all_sessions_paginated_has_more_boundary() {
     let conn = pool.get().unwrap();
     let now = chrono::Utc::now().to_rfc3339();
 
-    queries::upsert_device(&conn, "device-1", "My Device", "macos", &now).unwrap();
+    queries::upsert_device(&conn, "device-1", "My Device", "macos", None, &now).unwrap();
 
     // Exactly 2 sessions, limit=2
     for i in 1..=2 {
@@ -1404,7 +2630,284 @@ fn test_list_all_sessions_paginated_has_more_boundary() {
         .unwrap();
     }
 
-    let result = queries::list_all_sessions_paginated(&conn, None, false, 2, 0).unwrap();
+    let result = queries::list_all_sessions_paginated(
+        &conn,
+        &Partition::All,
+        None,
+        None,
+        None,
+        false,
+        &TimeRange::default(),
+        2,
+        0,
+    )
+    .unwrap();
     assert_eq!(result.sessions.len(), 2);
     assert!(!result.has_more); // Exactly limit, no more
 }
+
+#[test]
+fn test_migrations_enable_incremental_auto_vacuum() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let mode: i64 = conn
+        .query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mode, 2, "auto_vacuum should be INCREMENTAL");
+}
+
+#[test]
+fn test_existing_database_is_vacuumed_into_incremental_only_on_request() {
+    let pool = pool::create_memory_pool(None).unwrap();
+    pool.get()
+        .unwrap()
+        .execute_batch("CREATE TABLE legacy (id INTEGER PRIMARY KEY);")
+        .unwrap();
+
+    // Startup leaves an existing database's mode alone
+    migrations::run(&pool).unwrap();
+    let conn = pool.get().unwrap();
+    let auto_vacuum = || -> i64 {
+        conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(auto_vacuum(), 0);
+
+    queries::full_vacuum(&conn).unwrap();
+    assert_eq!(auto_vacuum(), 2);
+}
+
+#[test]
+fn test_run_maintenance_reclaims_deleted_pages() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+
+    let big_json = format!(r#"{{"message":"{}"}}"#, "x".repeat(2000));
+    for _ in 0..200 {
+        queries::insert_event(
+            &conn,
+            "device-1",
+            "session-1",
+            "Stop",
+            &now,
+            &now,
+            None,
+            None,
+            &big_json,
+        )
+        .unwrap();
+    }
+
+    conn.execute("DELETE FROM events", []).unwrap();
+
+    let stats = queries::run_maintenance(&conn).unwrap();
+    assert!(stats.freelist_count_before > 0);
+    assert!(stats.page_count_after < stats.page_count_before);
+
+    let freelist: i64 = conn
+        .query_row("PRAGMA freelist_count", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(freelist, 0);
+}
+
+#[test]
+fn test_archive_old_events_moves_rows() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+
+    // Insert old event (8 days ago)
+    let old_time = (chrono::Utc::now() - chrono::Duration::days(8))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let old_id = queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "Stop",
+        &old_time,
+        &old_time,
+        None,
+        None,
+        r#"{"message":"old"}"#,
+    )
+    .unwrap();
+
+    // Insert recent event
+    let recent = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    queries::insert_event(
+        &conn,
+        "device-1",
+        "session-1",
+        "Stop",
+        &recent,
+        &recent,
+        None,
+        None,
+        "{}",
+    )
+    .unwrap();
+
+    let archived = queries::archive_old_events(&conn, 7).unwrap();
+    assert_eq!(archived, 1);
+
+    // Hot table keeps only the recent event
+    let events = queries::list_events(
+        &conn,
+        &Partition::All,
+        "session-1",
+        &queries::EventFilter::default(),
+        10,
+    )
+    .unwrap();
+    assert_eq!(events.len(), 1);
+
+    // Archive holds the old event with its original id and payload
+    let rows = queries::list_archived_events(&conn, 0, 10).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id, old_id);
+    assert_eq!(rows[0].event_json, r#"{"message":"old"}"#);
+    assert_eq!(rows[0].received_at, old_time);
+
+    // Running again is a no-op
+    assert_eq!(queries::archive_old_events(&conn, 7).unwrap(), 0);
+}
+
+#[test]
+fn test_list_archived_events_paginates_by_id() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    let now = chrono::Utc::now().to_rfc3339();
+    queries::upsert_device(&conn, "device-1", "Device", "macos", None, &now).unwrap();
+    queries::upsert_session(&conn, "session-1", "device-1", &now, None, None, None).unwrap();
+
+    let old_time = (chrono::Utc::now() - chrono::Duration::days(8))
+        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    for _ in 0..3 {
+        queries::insert_event(
+            &conn,
+            "device-1",
+            "session-1",
+            "Stop",
+            &old_time,
+            &old_time,
+            None,
+            None,
+            "{}",
+        )
+        .unwrap();
+    }
+    queries::archive_old_events(&conn, 7).unwrap();
+
+    let first = queries::list_archived_events(&conn, 0, 2).unwrap();
+    assert_eq!(first.len(), 2);
+    let rest = queries::list_archived_events(&conn, first[1].id, 2).unwrap();
+    assert_eq!(rest.len(), 1);
+    assert!(rest[0].id > first[1].id);
+}
+
+#[test]
+fn test_key_usage_accumulates_per_day() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    queries::increment_key_usage(&conn, "key-1", "2024-01-01", 1, 0).unwrap();
+    queries::increment_key_usage(&conn, "key-1", "2024-01-01", 1, 1).unwrap();
+    queries::increment_key_usage(&conn, "key-1", "2024-01-02", 5, 3).unwrap();
+    queries::increment_key_usage(&conn, "key-1", "2023-12-31", 9, 9).unwrap();
+    queries::increment_key_usage(&conn, "key-2", "2024-01-02", 7, 7).unwrap();
+
+    let usage = queries::list_key_usage(&conn, "key-1", "2024-01-01").unwrap();
+    let usage: Vec<_> = usage
+        .iter()
+        .map(|row| (row.day.as_str(), row.requests, row.events))
+        .collect();
+    assert_eq!(usage, vec![("2024-01-02", 5, 3), ("2024-01-01", 2, 1)]);
+}
+
+#[test]
+fn test_delete_api_key_removes_usage() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    queries::insert_api_key(
+        &conn,
+        "key-1",
+        "hook",
+        "claud_abc",
+        "write",
+        "2024-01-01T00:00:00Z",
+        None,
+        None,
+    )
+    .unwrap();
+    queries::increment_key_usage(&conn, "key-1", "2024-01-01", 1, 1).unwrap();
+
+    queries::delete_api_key(&conn, "key-1").unwrap();
+
+    assert!(queries::find_api_key_by_id(&conn, "key-1")
+        .unwrap()
+        .is_none());
+    assert!(queries::list_key_usage(&conn, "key-1", "2000-01-01")
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_claim_enrollment_code_once_before_expiry() {
+    let pool = test_pool();
+    let conn = pool.get().unwrap();
+
+    queries::insert_enrollment_code(
+        &conn,
+        "CODE-1",
+        Some("alice"),
+        "2024-01-01T00:00:00.000Z",
+        "2024-01-01T01:00:00.000Z",
+    )
+    .unwrap();
+    queries::insert_enrollment_code(
+        &conn,
+        "CODE-2",
+        None,
+        "2024-01-01T00:00:00.000Z",
+        "2024-01-01T01:00:00.000Z",
+    )
+    .unwrap();
+
+    // Expired
+    assert!(
+        queries::claim_enrollment_code(&conn, "CODE-1", "key-1", "2024-01-01T01:00:00.000Z")
+            .unwrap()
+            .is_none()
+    );
+
+    let claimed =
+        queries::claim_enrollment_code(&conn, "CODE-1", "key-1", "2024-01-01T00:30:00.000Z")
+            .unwrap();
+    assert_eq!(claimed, Some(Partition::Key(Some("alice".to_string()))));
+    let claimed =
+        queries::claim_enrollment_code(&conn, "CODE-2", "key-2", "2024-01-01T00:30:00.000Z")
+            .unwrap();
+    assert_eq!(claimed, Some(Partition::Key(None)));
+
+    // Already used
+    assert!(
+        queries::claim_enrollment_code(&conn, "CODE-1", "key-3", "2024-01-01T00:31:00.000Z")
+            .unwrap()
+            .is_none()
+    );
+    assert!(
+        queries::claim_enrollment_code(&conn, "UNKNOWN", "key-3", "2024-01-01T00:31:00.000Z")
+            .unwrap()
+            .is_none()
+    );
+}
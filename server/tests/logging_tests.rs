@@ -2,28 +2,15 @@
 #![allow(missing_docs)]
 
 use axum_test::TestServer;
-use claudiator_server::{db, router};
-use std::sync::atomic::AtomicU64;
+use claudiator_server::router;
+use claudiator_server::storage::MemoryStorage;
 use std::sync::Arc;
 
 fn test_server() -> TestServer {
-    let db_pool = db::pool::create_pool(":memory:").unwrap();
-    db::migrations::run(&db_pool).unwrap();
-
-    let state = Arc::new(router::AppState {
-        master_key: "test-key".to_string(),
-        db_pool,
-        version: AtomicU64::new(0),
-        notification_version: AtomicU64::new(0),
-        last_cleanup: AtomicU64::new(0),
-        apns_client: None,
-        retention_events_days: 7,
-        retention_sessions_days: 7,
-        retention_devices_days: 30,
-        auth_failures: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
-        key_rate_limits: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
-        notif_cooldown: Arc::new(std::sync::Mutex::new(std::collections::HashMap::new())),
-    });
+    let state = Arc::new(router::AppState::new(
+        "test-key",
+        Arc::new(MemoryStorage::new()),
+    ));
 
     let app = router::build_router(state);
     TestServer::new(app).unwrap()
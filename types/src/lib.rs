@@ -0,0 +1,647 @@
+//! Wire-format types shared by `claudiator-hook` and `claudiator-server`.
+//!
+//! The hook serializes an [`EventPayload`] and POSTs it to
+//! `POST /api/v1/events`; the server deserializes the same struct. Keeping a
+//! single definition here means the two sides cannot drift apart silently.
+//!
+//! [`EventData`] is the trimmed event DTO: it declares only the fields the
+//! server reads. Everything else in the Claude Code hook JSON (tool inputs,
+//! transcripts, assistant messages) is dropped by serde on deserialization
+//! and therefore never leaves the user's machine.
+
+use std::collections::BTreeMap;
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Version of the wire format defined in this crate.
+///
+/// Reported by the server in `GET /api/v1/ping`. Bump it when a change would
+/// break an older peer (a required field added, a field renamed or retyped);
+/// adding an optional field does not need a bump.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The type of a Claude Code hook event (`hook_event_name`).
+///
+/// Serialized as the original string, so the wire format is a plain string.
+/// Unrecognised names are kept verbatim in [`HookEventKind::Other`] and
+/// round-trip unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum HookEventKind {
+    /// A new session started (or resumed).
+    SessionStart,
+    /// The session ended.
+    SessionEnd,
+    /// The user submitted a prompt.
+    UserPromptSubmit,
+    /// A tool is about to run.
+    PreToolUse,
+    /// A tool finished running.
+    PostToolUse,
+    /// A tool call failed; `error` says why and `is_interrupt` whether the
+    /// user stopped it.
+    PostToolUseFailure,
+    /// Claude is asking the user for permission.
+    PermissionRequest,
+    /// A Claude Code notification (see `notification_type`).
+    Notification,
+    /// Claude finished responding and is waiting for input.
+    Stop,
+    /// A subagent started.
+    SubagentStart,
+    /// A subagent finished.
+    SubagentStop,
+    /// The conversation is about to be compacted.
+    PreCompact,
+    /// An agent-team teammate went idle.
+    TeammateIdle,
+    /// An agent-team task was marked complete.
+    TaskCompleted,
+    /// Synthetic event sent by `claudiator-hook test --send-test-event`.
+    TestNotification,
+    /// Synthetic liveness ping from `claudiator-hook`; carries no session.
+    Heartbeat,
+    /// Synthetic report from `claudiator-hook` that an earlier run panicked;
+    /// `message` holds the hook version and panic message.
+    HookCrash,
+    /// Event from a source other than Claude Code, such as a cron job or CI
+    /// run, posted to `POST /api/v1/ingest/generic`. Carries `title`,
+    /// `message`, and `severity`.
+    External,
+    /// Any event name this version does not know about.
+    Other(String),
+}
+
+impl HookEventKind {
+    /// Every event name this version knows, in declaration order.
+    pub const KNOWN: [Self; 18] = [
+        Self::SessionStart,
+        Self::SessionEnd,
+        Self::UserPromptSubmit,
+        Self::PreToolUse,
+        Self::PostToolUse,
+        Self::PostToolUseFailure,
+        Self::PermissionRequest,
+        Self::Notification,
+        Self::Stop,
+        Self::SubagentStart,
+        Self::SubagentStop,
+        Self::PreCompact,
+        Self::TeammateIdle,
+        Self::TaskCompleted,
+        Self::TestNotification,
+        Self::Heartbeat,
+        Self::HookCrash,
+        Self::External,
+    ];
+
+    /// The event name exactly as Claude Code sends it.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::SessionStart => "SessionStart",
+            Self::SessionEnd => "SessionEnd",
+            Self::UserPromptSubmit => "UserPromptSubmit",
+            Self::PreToolUse => "PreToolUse",
+            Self::PostToolUse => "PostToolUse",
+            Self::PostToolUseFailure => "PostToolUseFailure",
+            Self::PermissionRequest => "PermissionRequest",
+            Self::Notification => "Notification",
+            Self::Stop => "Stop",
+            Self::SubagentStart => "SubagentStart",
+            Self::SubagentStop => "SubagentStop",
+            Self::PreCompact => "PreCompact",
+            Self::TeammateIdle => "TeammateIdle",
+            Self::TaskCompleted => "TaskCompleted",
+            Self::TestNotification => "TestNotification",
+            Self::Heartbeat => "Heartbeat",
+            Self::HookCrash => "HookCrash",
+            Self::External => "External",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for HookEventKind {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "SessionStart" => Self::SessionStart,
+            "SessionEnd" => Self::SessionEnd,
+            "UserPromptSubmit" => Self::UserPromptSubmit,
+            "PreToolUse" => Self::PreToolUse,
+            "PostToolUse" => Self::PostToolUse,
+            "PostToolUseFailure" => Self::PostToolUseFailure,
+            "PermissionRequest" => Self::PermissionRequest,
+            "Notification" => Self::Notification,
+            "Stop" => Self::Stop,
+            "SubagentStart" => Self::SubagentStart,
+            "SubagentStop" => Self::SubagentStop,
+            "PreCompact" => Self::PreCompact,
+            "TeammateIdle" => Self::TeammateIdle,
+            "TaskCompleted" => Self::TaskCompleted,
+            "TestNotification" => Self::TestNotification,
+            "Heartbeat" => Self::Heartbeat,
+            "HookCrash" => Self::HookCrash,
+            "External" => Self::External,
+            _ => Self::Other(name),
+        }
+    }
+}
+
+impl From<&str> for HookEventKind {
+    fn from(name: &str) -> Self {
+        Self::from(name.to_string())
+    }
+}
+
+impl From<HookEventKind> for String {
+    fn from(kind: HookEventKind) -> Self {
+        match kind {
+            HookEventKind::Other(name) => name,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl PartialEq<str> for HookEventKind {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for HookEventKind {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Any non-empty string: [`HookEventKind::KNOWN`] names are listed as
+/// examples, and other names are accepted as [`HookEventKind::Other`].
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for HookEventKind {
+    fn schema_name() -> String {
+        "HookEventKind".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{InstanceType, Metadata, SchemaObject, StringValidation};
+
+        SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                min_length: Some(1),
+                ..StringValidation::default()
+            })),
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Claude Code hook event name. Names not listed in examples are accepted \
+                     and stored as sent."
+                        .to_string(),
+                ),
+                examples: Self::KNOWN
+                    .iter()
+                    .map(|kind| serde_json::Value::from(kind.as_str()))
+                    .collect(),
+                ..Metadata::default()
+            })),
+            ..SchemaObject::default()
+        }
+        .into()
+    }
+}
+
+/// JSON Schema (draft 7) of [`EventPayload`], the body of
+/// `POST /api/v1/events`.
+///
+/// It describes the shape serde accepts. The server also enforces the rules
+/// listed for its `422` response, such as a non-empty `session_id`.
+#[cfg(feature = "schema")]
+pub fn event_payload_schema() -> schemars::schema::RootSchema {
+    schemars::schema_for!(EventPayload)
+}
+
+impl std::fmt::Display for HookEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Device identity fields included with every event.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceInfo {
+    /// Stable UUID identifying the device.
+    pub device_id: String,
+    /// Human-readable machine name.
+    pub device_name: String,
+    /// Host OS platform string (e.g. `"mac"`, `"linux"`).
+    pub platform: String,
+}
+
+/// The trimmed hook event forwarded from the hook to the server.
+///
+/// Only the fields the server reads are declared (see [`EVENT_FIELDS`]).
+/// Unknown fields in the incoming JSON are silently discarded by serde, and
+/// `None` fields are omitted when serializing.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[allow(clippy::struct_field_names)]
+pub struct EventData {
+    /// Claude Code session identifier.
+    pub session_id: String,
+    /// Hook event type.
+    pub hook_event_name: HookEventKind,
+    /// Working directory of the session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// User prompt text (`UserPromptSubmit` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Notification subtype (`Notification` only), e.g. `"permission_prompt"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notification_type: Option<String>,
+    /// Tool name for tool and permission events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// Human-readable message attached to the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// `"high"` ([`PRIORITY_HIGH`]) when a hook policy rule flagged the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    /// Subagent identifier (`SubagentStart` and `SubagentStop`). The server
+    /// files these events under a session of this id, nested under
+    /// `session_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_id: Option<String>,
+    /// Subagent type, e.g. `"Explore"` (`SubagentStart` and `SubagentStop`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_type: Option<String>,
+    /// Agent team the session belongs to, in team mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_name: Option<String>,
+    /// Team task identifier (`TaskCompleted`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    /// One-line summary of the team task (`TaskCompleted`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_subject: Option<String>,
+    /// Claude model the session runs on (`SessionStart`), e.g.
+    /// `"claude-sonnet-4-5"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Options Claude Code offers alongside a permission prompt, such as
+    /// rules to always allow the tool (`PermissionRequest`). Kept as sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permission_suggestions: Option<serde_json::Value>,
+    /// Headline of an `External` event's notification.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// How serious an `External` event is: one of [`SEVERITIES`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    /// What started a compaction (`PreCompact`): `"manual"` for `/compact`,
+    /// `"auto"` when the context window filled up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    /// Instructions the user gave with `/compact` (`PreCompact`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom_instructions: Option<String>,
+    /// Why a tool call failed (`PostToolUseFailure`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Whether the user interrupted the tool call (`PostToolUseFailure`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_interrupt: Option<bool>,
+    /// Environment variables the hook was configured to capture, such as
+    /// `GITHUB_RUN_ID`, by name. Unset variables are left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// JSON names of the fields [`EventData`] declares. Any other key in an
+/// incoming event is one serde discards.
+pub const EVENT_FIELDS: [&str; 22] = [
+    "session_id",
+    "hook_event_name",
+    "cwd",
+    "prompt",
+    "notification_type",
+    "tool_name",
+    "message",
+    "priority",
+    "agent_id",
+    "agent_type",
+    "team_name",
+    "task_id",
+    "task_subject",
+    "model",
+    "permission_suggestions",
+    "title",
+    "severity",
+    "trigger",
+    "custom_instructions",
+    "error",
+    "is_interrupt",
+    "env",
+];
+
+/// Accepted [`EventData::severity`] values, least serious first.
+pub const SEVERITIES: [&str; 3] = ["info", "warning", "error"];
+
+/// [`EventData::priority`] of events a hook policy rule flagged. The server
+/// notifies for them even when their type otherwise wouldn't.
+pub const PRIORITY_HIGH: &str = "high";
+
+/// Prefix of a [`EventData::prompt`] or [`EventData::message`] the hook
+/// sealed end to end.
+///
+/// The rest is base64 of a ChaCha20-Poly1305 nonce, ciphertext, and tag.
+/// Only the app holds the key, so the server stores and forwards these
+/// values without reading them.
+pub const E2E_PREFIX: &str = "e2e:v1:";
+
+/// Whether `value` is one the hook sealed end to end.
+pub fn is_e2e_sealed(value: &str) -> bool {
+    value.starts_with(E2E_PREFIX)
+}
+
+/// The complete JSON body of `POST /api/v1/events`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct EventPayload {
+    /// The device that produced the event.
+    pub device: DeviceInfo,
+    /// The trimmed hook event.
+    pub event: EventData,
+    /// RFC 3339 timestamp of when the payload was created.
+    pub timestamp: String,
+}
+
+impl EventPayload {
+    /// Build a payload stamped with the current time (millisecond precision).
+    pub fn new(device: DeviceInfo, event: EventData) -> Self {
+        Self {
+            device,
+            event,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+}
+
+/// Product token the hook sends in its `User-Agent`.
+pub const HOOK_PRODUCT: &str = "claudiator-hook";
+
+/// Response header on `POST /api/v1/events` naming the oldest hook version
+/// the server supports.
+pub const MIN_HOOK_VERSION_HEADER: &str = "X-Claudiator-Min-Hook-Version";
+
+/// The hook's `User-Agent`: `claudiator-hook/<version> (<platform>)`.
+pub fn hook_user_agent(version: &str, platform: &str) -> String {
+    format!("{HOOK_PRODUCT}/{version} ({platform})")
+}
+
+/// The hook version in a `User-Agent` [`hook_user_agent`] built, or `None`
+/// if another client sent it.
+pub fn hook_version_from_user_agent(user_agent: &str) -> Option<&str> {
+    let version = user_agent
+        .strip_prefix(HOOK_PRODUCT)?
+        .strip_prefix('/')?
+        .split_whitespace()
+        .next()?;
+    (!version.is_empty()).then_some(version)
+}
+
+/// `device_name` followed by the start of `device_id`, e.g.
+/// `laptop (3f2a1b9c)`.
+///
+/// Tells apart machines that share a name, such as VMs cloned from one
+/// image. The server shows it for colliding names, and the hook sends it
+/// when `unique_device_name` is set.
+pub fn disambiguated_device_name(device_name: &str, device_id: &str) -> String {
+    let short_id: String = device_id.chars().take(8).collect();
+    format!("{} ({short_id})", device_name.trim())
+}
+
+/// Whether dotted version `version` is older than `floor`.
+///
+/// Components compare as numbers (`0.10.0` is newer than `0.9.1`), and a
+/// pre-release or build suffix is ignored. Unparseable versions are never
+/// reported as older.
+pub fn is_older_version(version: &str, floor: &str) -> bool {
+    fn parts(version: &str) -> Option<Vec<u64>> {
+        version
+            .split(['-', '+'])
+            .next()?
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect()
+    }
+    match (parts(version), parts(floor)) {
+        (Some(version), Some(floor)) => version < floor,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn make_event(name: &str) -> EventData {
+        EventData {
+            session_id: "sess-1".to_string(),
+            hook_event_name: name.into(),
+            cwd: None,
+            prompt: None,
+            notification_type: None,
+            tool_name: None,
+            message: None,
+            priority: None,
+            agent_id: None,
+            agent_type: None,
+            team_name: None,
+            task_id: None,
+            task_subject: None,
+            model: None,
+            permission_suggestions: None,
+            title: None,
+            severity: None,
+            trigger: None,
+            custom_instructions: None,
+            error: None,
+            is_interrupt: None,
+            env: None,
+        }
+    }
+
+    #[test]
+    fn test_hook_event_kind_known_names_round_trip() {
+        for name in [
+            "SessionStart",
+            "SessionEnd",
+            "UserPromptSubmit",
+            "PreToolUse",
+            "PostToolUse",
+            "PostToolUseFailure",
+            "PermissionRequest",
+            "Notification",
+            "Stop",
+            "SubagentStart",
+            "SubagentStop",
+            "PreCompact",
+            "TeammateIdle",
+            "TaskCompleted",
+            "TestNotification",
+            "Heartbeat",
+            "HookCrash",
+            "External",
+        ] {
+            let kind = HookEventKind::from(name);
+            assert!(!matches!(kind, HookEventKind::Other(_)), "{name}");
+            assert!(HookEventKind::KNOWN.contains(&kind), "{name}");
+            assert_eq!(kind.as_str(), name);
+            assert_eq!(String::from(kind), name);
+        }
+    }
+
+    #[test]
+    fn test_hook_event_kind_unknown_preserved() {
+        let kind: HookEventKind = serde_json::from_str(r#""FutureEvent""#).unwrap();
+        assert_eq!(kind, HookEventKind::Other("FutureEvent".to_string()));
+        assert_eq!(serde_json::to_string(&kind).unwrap(), r#""FutureEvent""#);
+    }
+
+    #[test]
+    fn test_hook_event_kind_compares_with_str() {
+        assert_eq!(HookEventKind::Stop, "Stop");
+        assert!(HookEventKind::Other("x".to_string()) == *"x");
+        assert_eq!(HookEventKind::Notification.to_string(), "Notification");
+    }
+
+    #[test]
+    fn test_event_data_omits_none_fields() {
+        let json = serde_json::to_value(make_event("Stop")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"session_id": "sess-1", "hook_event_name": "Stop"})
+        );
+    }
+
+    #[test]
+    fn test_event_data_carries_priority() {
+        let event = EventData {
+            priority: Some(PRIORITY_HIGH.to_string()),
+            ..make_event("PreToolUse")
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["priority"], "high");
+        assert_eq!(serde_json::from_value::<EventData>(json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_event_data_drops_unknown_fields() {
+        let event: EventData = serde_json::from_str(
+            r#"{"session_id": "s", "hook_event_name": "PreToolUse", "tool_input": {"command": "ls"}}"#,
+        )
+        .unwrap();
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(!json.contains("tool_input"));
+    }
+
+    #[test]
+    fn test_event_fields_lists_every_declared_field() {
+        let mut event = make_event("Stop");
+        let text = Some("x".to_string());
+        event.cwd.clone_from(&text);
+        event.prompt.clone_from(&text);
+        event.notification_type.clone_from(&text);
+        event.tool_name.clone_from(&text);
+        event.message.clone_from(&text);
+        event.priority.clone_from(&text);
+        event.agent_id.clone_from(&text);
+        event.agent_type.clone_from(&text);
+        event.team_name.clone_from(&text);
+        event.task_id.clone_from(&text);
+        event.task_subject.clone_from(&text);
+        event.model.clone_from(&text);
+        event.title.clone_from(&text);
+        event.severity.clone_from(&text);
+        event.trigger.clone_from(&text);
+        event.custom_instructions.clone_from(&text);
+        event.error = text;
+        event.is_interrupt = Some(true);
+        event.env = Some(BTreeMap::from([("CI".to_string(), "true".to_string())]));
+        event.permission_suggestions = Some(serde_json::json!([]));
+
+        let json = serde_json::to_value(&event).unwrap();
+        let mut keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut fields = EVENT_FIELDS.to_vec();
+        keys.sort_unstable();
+        fields.sort_unstable();
+        assert_eq!(keys, fields);
+    }
+
+    #[test]
+    fn test_event_payload_new_timestamp() {
+        let device = DeviceInfo {
+            device_id: "d".to_string(),
+            device_name: "n".to_string(),
+            platform: "mac".to_string(),
+        };
+        let payload = EventPayload::new(device, make_event("Stop"));
+        let parsed = chrono::DateTime::parse_from_rfc3339(&payload.timestamp);
+        assert!(parsed.is_ok());
+        assert!(payload.timestamp.contains('.'));
+    }
+
+    #[test]
+    fn test_event_payload_round_trip() {
+        let device = DeviceInfo {
+            device_id: "d".to_string(),
+            device_name: "n".to_string(),
+            platform: "mac".to_string(),
+        };
+        let payload = EventPayload::new(device, make_event("UserPromptSubmit"));
+        let json = serde_json::to_string(&payload).unwrap();
+        let back: EventPayload = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, payload);
+    }
+
+    #[test]
+    fn test_hook_user_agent_round_trips_version() {
+        let user_agent = hook_user_agent("0.3.1", "macos");
+        assert_eq!(user_agent, "claudiator-hook/0.3.1 (macos)");
+        assert_eq!(hook_version_from_user_agent(&user_agent), Some("0.3.1"));
+        assert_eq!(
+            hook_version_from_user_agent("claudiator-hook/0.2.0"),
+            Some("0.2.0")
+        );
+        assert_eq!(hook_version_from_user_agent("curl/8.4.0"), None);
+        assert_eq!(hook_version_from_user_agent("claudiator-hooks/1.0"), None);
+    }
+
+    #[test]
+    fn test_disambiguated_device_name_appends_short_id() {
+        assert_eq!(
+            disambiguated_device_name("laptop", "3f2a1b9c-0d4e-4f5a-8b6c-7d8e9f0a1b2c"),
+            "laptop (3f2a1b9c)"
+        );
+        assert_eq!(disambiguated_device_name("vm", "dev-1"), "vm (dev-1)");
+    }
+
+    #[test]
+    fn test_is_older_version_compares_numerically() {
+        assert!(is_older_version("0.9.1", "0.10.0"));
+        assert!(is_older_version("0.3", "0.3.1"));
+        assert!(!is_older_version("0.10.0", "0.9.1"));
+        assert!(!is_older_version("0.3.1", "0.3.1"));
+        assert!(!is_older_version("0.3.1-beta.1", "0.3.1"));
+        assert!(!is_older_version("dev", "0.3.1"));
+    }
+}